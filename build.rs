@@ -11,5 +11,10 @@ fn main() -> Result<()> {
         _ => None,
     };
 
-    vergen(config)
+    vergen(config)?;
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/butido.proto")?;
+
+    Ok(())
 }
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE
+    submits
+ADD COLUMN
+    manifest TEXT
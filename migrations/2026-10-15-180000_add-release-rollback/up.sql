@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE
+    releases
+ADD COLUMN
+    rollback_of INTEGER REFERENCES releases(id),
+ADD COLUMN
+    reason TEXT
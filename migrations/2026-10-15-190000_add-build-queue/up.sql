@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE build_queue (
+    id SERIAL PRIMARY KEY NOT NULL,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    package_name VARCHAR NOT NULL,
+    package_version VARCHAR,
+    args TEXT[] NOT NULL,
+    repo_hash VARCHAR NOT NULL,
+    state VARCHAR NOT NULL DEFAULT 'queued',
+    claimed_by VARCHAR,
+    heartbeat TIMESTAMP WITH TIME ZONE,
+    finished TIMESTAMP WITH TIME ZONE,
+    error TEXT
+);
+
+CREATE INDEX build_queue_state ON build_queue (state);
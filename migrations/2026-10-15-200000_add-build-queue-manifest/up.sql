@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE
+    build_queue
+ADD COLUMN
+    manifest TEXT
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE
+    artifacts
+ADD COLUMN
+    checksum VARCHAR,
+ADD COLUMN
+    verified BOOLEAN
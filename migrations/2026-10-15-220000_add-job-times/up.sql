@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE
+    jobs
+ADD COLUMN
+    started TIMESTAMP WITH TIME ZONE,
+ADD COLUMN
+    finished TIMESTAMP WITH TIME ZONE
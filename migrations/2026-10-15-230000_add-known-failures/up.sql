@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE known_failures (
+    id SERIAL PRIMARY KEY NOT NULL,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    job_id INTEGER REFERENCES jobs(id),
+    package_name VARCHAR,
+    package_version VARCHAR,
+    image_name VARCHAR,
+    reason TEXT NOT NULL,
+
+    CHECK (job_id IS NOT NULL OR package_name IS NOT NULL)
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE
+    artifacts
+ADD COLUMN
+    size BIGINT
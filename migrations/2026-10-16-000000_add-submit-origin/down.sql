@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE
+    submits
+DROP COLUMN
+    butido_version,
+DROP COLUMN
+    butido_git_hash,
+DROP COLUMN
+    config_fingerprint
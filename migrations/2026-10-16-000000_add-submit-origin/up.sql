@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE
+    submits
+ADD COLUMN
+    butido_version VARCHAR,
+ADD COLUMN
+    butido_git_hash VARCHAR,
+ADD COLUMN
+    config_fingerprint VARCHAR
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE job_phases (
+    id SERIAL PRIMARY KEY NOT NULL,
+    job_id INTEGER REFERENCES jobs(id) NOT NULL,
+    name VARCHAR NOT NULL,
+    started TIMESTAMP WITH TIME ZONE NOT NULL,
+    finished TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX job_phases_job_id ON job_phases (job_id);
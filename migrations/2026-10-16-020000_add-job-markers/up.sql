@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE job_markers (
+    id SERIAL PRIMARY KEY NOT NULL,
+    job_id INTEGER REFERENCES jobs(id) NOT NULL,
+    kind VARCHAR NOT NULL,
+    name VARCHAR,
+    value TEXT NOT NULL,
+
+    CHECK (kind IN ('metric', 'warning')),
+    CHECK (kind <> 'metric' OR name IS NOT NULL)
+);
+
+CREATE INDEX job_markers_job_id ON job_markers (job_id);
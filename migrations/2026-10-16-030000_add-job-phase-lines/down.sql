@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE
+    job_phases
+DROP COLUMN
+    first_line,
+DROP COLUMN
+    line_count
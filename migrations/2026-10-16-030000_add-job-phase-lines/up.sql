@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE
+    job_phases
+ADD COLUMN
+    first_line INTEGER,
+ADD COLUMN
+    line_count INTEGER
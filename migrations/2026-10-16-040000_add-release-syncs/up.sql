@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE release_syncs (
+    id SERIAL PRIMARY KEY NOT NULL,
+    release_id INTEGER REFERENCES releases(id) ON DELETE CASCADE NOT NULL,
+    target VARCHAR NOT NULL,
+    synced TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT UC_release_target UNIQUE (release_id, target)
+);
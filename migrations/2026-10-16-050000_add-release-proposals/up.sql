@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE release_proposals (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    release_store_name VARCHAR NOT NULL,
+    package_name VARCHAR,
+    package_version VARCHAR,
+    proposed_by VARCHAR NOT NULL,
+    proposed TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    approved_by VARCHAR,
+    approved TIMESTAMP WITH TIME ZONE,
+
+    CHECK (approved_by IS NULL OR approved_by <> proposed_by)
+);
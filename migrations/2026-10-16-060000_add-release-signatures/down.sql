@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE
+    releases
+DROP COLUMN
+    signature_path,
+DROP COLUMN
+    signature_fingerprint;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE
+    releases
+ADD COLUMN
+    signature_path VARCHAR,
+ADD COLUMN
+    signature_fingerprint VARCHAR;
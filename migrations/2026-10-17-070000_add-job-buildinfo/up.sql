@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE
+    jobs
+ADD COLUMN
+    buildinfo TEXT;
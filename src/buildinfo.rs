@@ -0,0 +1,205 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Buildinfo files of jobs
+//!
+//! Like the .buildinfo files of Debian, a buildinfo records what went into the build of a job:
+//! the image and its digest, the sources, the script, the environment and the artifacts of the
+//! dependencies. It is written in the deb822 format to "<artifact>.buildinfo" next to each
+//! artifact of the job and recorded with the job in the database.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::endpoint::ExportedArtifact;
+use crate::job::RunnableJob;
+
+/// The inputs of a job, collected before the job is run
+#[derive(Debug)]
+pub struct BuildInfo {
+    submit: Uuid,
+    job: Uuid,
+    package_name: String,
+    package_version: String,
+    image: String,
+
+    /// The name, URL and hash ("<type>:<value>") of each source, sorted by name
+    sources: Vec<(String, String, String)>,
+
+    /// The artifacts of the dependencies
+    dependencies: Vec<String>,
+    environment: Vec<(String, String)>,
+}
+
+/// The results of a job that are recorded in its buildinfo
+#[derive(Debug)]
+pub struct BuildResult<'a> {
+    pub endpoint: &'a str,
+    pub image_digest: Option<&'a str>,
+    pub container_hash: &'a str,
+    pub script: &'a str,
+    pub finished: Option<NaiveDateTime>,
+    pub artifacts: &'a [ExportedArtifact],
+}
+
+impl BuildInfo {
+    pub fn new(submit: &Uuid, job: &RunnableJob) -> Self {
+        let mut sources = job.package()
+            .sources()
+            .iter()
+            .map(|(name, source)| {
+                let hash = format!("{}:{}", source.hash().hashtype(), source.hash().value());
+                (name.clone(), source.url().to_string(), hash)
+            })
+            .collect::<Vec<_>>();
+        sources.sort();
+
+        let mut dependencies = job.resources()
+            .iter()
+            .filter_map(|resource| resource.artifact())
+            .map(|artifact| artifact.display().to_string())
+            .collect::<Vec<_>>();
+        dependencies.sort();
+
+        let mut environment = job.environment()
+            .map(|(name, value)| (name.as_ref().to_string(), value.clone()))
+            .collect::<Vec<_>>();
+        environment.sort();
+
+        BuildInfo {
+            submit: *submit,
+            job: *job.uuid(),
+            package_name: job.package().name().to_string(),
+            package_version: job.package().version().to_string(),
+            image: job.image().to_string(),
+            sources,
+            dependencies,
+            environment,
+        }
+    }
+
+    /// The buildinfo of the job with the results of its build, in the deb822 format
+    pub fn render(&self, result: &BuildResult<'_>) -> String {
+        use sha2::Digest;
+
+        let mut out = String::new();
+        // writing to a String cannot fail
+        let _ = writeln!(out, "Format: 1.0");
+        let _ = writeln!(out, "Source: {}", self.package_name);
+        let _ = writeln!(out, "Version: {}", self.package_version);
+        let _ = writeln!(out, "Build-Origin: butido {}", crate::cli::VERSION);
+        if let Some(finished) = result.finished {
+            let _ = writeln!(out, "Build-Date: {}", finished.format("%a, %d %b %Y %H:%M:%S"));
+        }
+        let _ = writeln!(out, "Butido-Git-Hash: {}", env!("VERGEN_GIT_SHA"));
+        let _ = writeln!(out, "Submit: {}", self.submit);
+        let _ = writeln!(out, "Job: {}", self.job);
+        let _ = writeln!(out, "Endpoint: {}", result.endpoint);
+        let _ = writeln!(out, "Image: {}", self.image);
+        if let Some(digest) = result.image_digest {
+            let _ = writeln!(out, "Image-Digest: {digest}");
+        }
+        let _ = writeln!(out, "Container: {}", result.container_hash);
+        let _ = writeln!(out, "Script-Sha256: {:x}", sha2::Sha256::digest(result.script.as_bytes()));
+
+        let _ = writeln!(out, "Checksums-Sha256:");
+        for artifact in result.artifacts {
+            let _ = writeln!(out, " {} {} {}", artifact.checksum, artifact.size, artifact.path.display());
+        }
+
+        if !self.sources.is_empty() {
+            let _ = writeln!(out, "Sources:");
+            for (name, url, hash) in self.sources.iter() {
+                let _ = writeln!(out, " {hash} {name} {url}");
+            }
+        }
+
+        if !self.dependencies.is_empty() {
+            let _ = writeln!(out, "Build-Dependencies:");
+            for dependency in self.dependencies.iter() {
+                let _ = writeln!(out, " {dependency}");
+            }
+        }
+
+        if !self.environment.is_empty() {
+            let _ = writeln!(out, "Environment:");
+            for (name, value) in self.environment.iter() {
+                let _ = writeln!(out, " {}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\""));
+            }
+        }
+
+        out
+    }
+}
+
+/// The path of the buildinfo file of an artifact
+pub fn buildinfo_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".buildinfo");
+    PathBuf::from(path)
+}
+
+/// Write the buildinfo next to each of the `files`
+pub async fn write(buildinfo: &str, files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let path = buildinfo_path(file);
+        tokio::fs::write(&path, buildinfo)
+            .await
+            .with_context(|| anyhow!("Writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buildinfo_path() {
+        assert_eq!(buildinfo_path(Path::new("/rel/foo-1.0.tar")), PathBuf::from("/rel/foo-1.0.tar.buildinfo"));
+    }
+
+    #[test]
+    fn test_render() {
+        let buildinfo = BuildInfo {
+            submit: Uuid::nil(),
+            job: Uuid::nil(),
+            package_name: String::from("foo"),
+            package_version: String::from("1.0"),
+            image: String::from("debian:bullseye"),
+            sources: vec![(String::from("src"), String::from("https://example.com/foo-1.0.tar.gz"), String::from("sha256:abc"))],
+            dependencies: vec![String::from("bar-2.0.tar")],
+            environment: vec![(String::from("FOO"), String::from("a \"b\""))],
+        };
+
+        let rendered = buildinfo.render(&BuildResult {
+            endpoint: "ep",
+            image_digest: Some("sha256:def"),
+            container_hash: "c0ffee",
+            script: "",
+            finished: None,
+            artifacts: &[],
+        });
+
+        assert!(rendered.starts_with("Format: 1.0\nSource: foo\nVersion: 1.0\n"));
+        assert!(rendered.contains("\nImage: debian:bullseye\nImage-Digest: sha256:def\n"));
+        assert!(rendered.contains("\nScript-Sha256: e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n"));
+        assert!(rendered.contains("\nSources:\n sha256:abc src https://example.com/foo-1.0.tar.gz\n"));
+        assert!(rendered.contains("\nBuild-Dependencies:\n bar-2.0.tar\n"));
+        assert!(rendered.ends_with("\nEnvironment:\n FOO=\"a \\\"b\\\"\"\n"));
+    }
+}
@@ -8,6 +8,7 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -25,16 +26,23 @@ pub const IDENT_DEPENDENCY_TYPE_RUNTIME: &str = "runtime";
 
 pub const VERSION: &str = env!("VERGEN_GIT_SEMVER");
 
+#[allow(clippy::let_and_return)] // without the "grpc" feature
 pub fn cli<'a>() -> Command<'a> {
-    Command::new("butido")
+    let cli = Command::new("butido")
         .author(crate_authors!())
         .version(VERSION)
         .disable_version_flag(true)
         .about("Generic Build Orchestration System for building linux packages with docker")
+        .allow_external_subcommands(true)
+        .allow_invalid_utf8_for_external_subcommands(true)
         .after_help(indoc::indoc!(r#"
             The following environment variables can be passed to butido:
 
                 RUST_LOG - to enable logging, for exact usage see the rust cookbook
+                BUTIDO_CONFIG - the configuration file to use, see --config
+                BUTIDO_PROFILE - the configuration profile to use, see --profile
+
+            Executables named "butido-<name>" on the PATH are available as subcommands ("butido <name>").
         "#))
 
         .arg(Arg::new("version")
@@ -49,7 +57,51 @@ pub fn cli<'a>() -> Command<'a> {
             .action(ArgAction::SetTrue)
             .required(false)
             .long("hide-bars")
+            .visible_alias("no-progress")
             .help("Hide all progress bars")
+            .long_help(indoc::indoc!(r#"
+                Hide all progress bars.
+                The progress bars are also hidden if stdout or stderr is not a terminal.
+            "#))
+        )
+
+        .arg(Arg::new("quiet")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .short('q')
+            .long("quiet")
+            .help("Non-interactive mode: hide all progress bars and do not ask questions")
+            .long_help(indoc::indoc!(r#"
+                Non-interactive mode, e.g. for CI: hide all progress bars and do not ask questions.
+                Commands that would ask for a confirmation fail instead, unless the confirmation is
+                not needed (e.g. with 'release --noninteractive').
+            "#))
+        )
+
+        .arg(Arg::new("config")
+            .required(false)
+            .long("config")
+            .value_name("PATH")
+            .help("Use this configuration file instead of the config.toml of the repository")
+            .long_help(indoc::indoc!(r#"
+                Use this configuration file instead of the config.toml in the top-level of the repository.
+                The configuration in the XDG configuration directory is still merged into it.
+                Can also be set via environment 'BUTIDO_CONFIG', but this setting has precedence.
+            "#))
+        )
+
+        .arg(Arg::new("profile")
+            .required(false)
+            .long("profile")
+            .value_name("NAME")
+            .help("Use a profile of the configuration")
+            .long_help(indoc::indoc!(r#"
+                Use a profile of the configuration.
+                The settings in the table "profiles.<NAME>" of the configuration override the other
+                settings, tables are merged. Environment variables ('BUTIDO_*') still override the
+                settings of the profile.
+                Can also be set via environment 'BUTIDO_PROFILE', but this setting has precedence.
+            "#))
         )
 
         .arg(Arg::new("database_host")
@@ -147,6 +199,21 @@ pub fn cli<'a>() -> Command<'a> {
                     .value_parser(["psql", "pgcli"])
                     .help("Use a specific tool")
                 )
+
+                .arg(Arg::new("read_only")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("read-only")
+                    .help("Make the transactions of the session read-only")
+                    .long_help(indoc::indoc!(r#"
+                        Make the transactions of the session read-only by default (default_transaction_read_only),
+                        so that data cannot be changed by accident.
+
+                        This is a safety net, not a security measure: it can be turned off within the session. To
+                        prevent changes for sure, connect with a database role that only has read privileges
+                        (see --db-user).
+                    "#))
+                )
             )
 
             .subcommand(Command::new("setup")
@@ -157,6 +224,20 @@ pub fn cli<'a>() -> Command<'a> {
                 "#))
             )
 
+            .subcommand(Command::new("test")
+                .version(VERSION)
+                .about("Check the database connection and health")
+                .long_about(indoc::indoc!(r#"
+                    Check the database connection and print the server version, the size of the database, the
+                    migrations that are not applied yet and the number of rows in the main tables.
+
+                    The pending migrations are found by comparing the migrations built into butido with the
+                    migrations recorded as run in the database. The database is not modified.
+
+                    Exits with an error if the database cannot be reached or there are pending migrations.
+                "#))
+            )
+
             .subcommand(Command::new("artifacts")
                 .version(VERSION)
                 .about("List artifacts from the DB")
@@ -167,6 +248,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
                 .arg(Arg::new("job_uuid")
                     .required(false)
                     .long("job")
@@ -175,6 +257,11 @@ pub fn cli<'a>() -> Command<'a> {
                     .value_name("JOB UUID")
                     .help("Print only artifacts for a certain job")
                 )
+                .arg(arg_list_limit("Only list the LIMIT newest artifacts"))
+                .arg(arg_list_offset("Skip the OFFSET newest artifacts"))
+                .arg(arg_list_all("List all artifacts instead of only the newest ones"))
+                .arg(arg_older_than_date("List only artifacts of submits older than DATE"))
+                .arg(arg_newer_than_date("List only artifacts of submits newer than DATE"))
             )
 
             .subcommand(Command::new("envvars")
@@ -187,6 +274,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
             )
 
             .subcommand(Command::new("images")
@@ -199,6 +287,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
             )
 
             .subcommand(Command::new("submit")
@@ -211,6 +300,59 @@ pub fn cli<'a>() -> Command<'a> {
                     .value_name("SUBMIT")
                     .help("The Submit to show details about")
                 )
+                .arg(Arg::new("show_manifest")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("show-manifest")
+                    .help("Show the manifest the submit was started with, if any")
+                )
+            )
+
+            .subcommand(Command::new("submit-diff")
+                .version(VERSION)
+                .about("Compare the jobs of two submits")
+                .long_about(indoc::indoc!(r#"
+                    Compare the jobs of two submits, e.g. of two releases.
+
+                    The jobs are matched by the name of their package. For each package, the versions, the results
+                    and the durations of the jobs in both submits are shown, together with a status:
+
+                        added       The package was only built in the second submit
+                        removed     The package was only built in the first submit
+                        fixed       The package failed in the first submit and succeeded in the second one
+                        broken      The package succeeded in the first submit and failed in the second one
+                        unchanged   The result of the package did not change
+
+                    If a package was built several times in a submit (e.g. in different versions), it counts as
+                    successful if all of its jobs succeeded and its duration is the sum of their durations.
+                "#))
+                .arg(Arg::new("submit_a")
+                    .required(true)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("SUBMIT A")
+                    .help("The UUID of the first submit")
+                )
+                .arg(Arg::new("submit_b")
+                    .required(true)
+                    .index(2)
+                    .takes_value(true)
+                    .value_name("SUBMIT B")
+                    .help("The UUID of the second submit")
+                )
+                .arg(Arg::new("changed_only")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("changed-only")
+                    .help("Only show packages that were added, removed, fixed or broken")
+                )
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
             )
 
             .subcommand(Command::new("submits")
@@ -223,6 +365,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
                 .arg(Arg::new("with_pkg")
                     .required(false)
                     .long("with-pkg")
@@ -239,13 +382,11 @@ pub fn cli<'a>() -> Command<'a> {
                     .help("Only list submits that had the root package PKG")
                     .conflicts_with("with_pkg")
                 )
-                .arg(Arg::new("limit")
-                    .required(false)
-                    .long("limit")
-                    .takes_value(true)
-                    .value_name("LIMIT")
-                    .help("Only list LIMIT submits")
-                )
+                .arg(arg_list_limit("Only list the LIMIT newest submits"))
+                .arg(arg_list_offset("Skip the OFFSET newest submits"))
+                .arg(arg_list_all("List all submits instead of only the newest ones"))
+                .arg(arg_older_than_date("List only submits older than DATE"))
+                .arg(arg_newer_than_date("List only submits newer than DATE"))
                 .arg(Arg::new("for-commit")
                     .required(false)
                     .long("commit")
@@ -272,6 +413,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
 
                 .arg(Arg::new("submit_uuid")
                     .required(false)
@@ -291,15 +433,25 @@ pub fn cli<'a>() -> Command<'a> {
                     .help("Filter for this \"key=value\" environment variable")
                 )
 
-                .arg(Arg::new("limit")
+                .arg(Arg::new("metric")
                     .required(false)
-                    .long("limit")
-                    .short('L')
+                    .long("metric")
                     .takes_value(true)
-                    .value_name("LIMIT")
-                    .help("Only list newest LIMIT jobs instead of all")
+                    .value_name("KEY[=VALUE]")
+                    .help("Only list jobs that reported this metric (with this value)")
+                )
+
+                .arg(Arg::new("with_warnings")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("with-warnings")
+                    .help("Only list jobs that reported warnings")
                 )
 
+                .arg(arg_list_limit("Only list the LIMIT newest jobs").short('L'))
+                .arg(arg_list_offset("Skip the OFFSET newest jobs"))
+                .arg(arg_list_all("List all jobs instead of only the newest ones"))
+
                 .arg(arg_older_than_date("List only jobs older than DATE"))
                 .arg(arg_newer_than_date("List only jobs newer than DATE"))
 
@@ -333,6 +485,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
 
                 .arg(Arg::new("job_uuid")
                     .required(true)
@@ -370,6 +523,107 @@ pub fn cli<'a>() -> Command<'a> {
                 .arg(script_arg_no_line_numbers())
                 .arg(script_arg_highlight())
                 .arg(script_arg_no_highlight())
+                .arg(arg_pager())
+                .arg(arg_raw_log().requires("show_log"))
+                .arg(Arg::new("show_log_times")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("timestamps")
+                    .requires("show_log")
+                    .conflicts_with("raw")
+                    .help("Show the time each log line was received at")
+                    .long_help(indoc::indoc!(r#"
+                        Show the time each log line was received at, in UTC.
+                        Logs of jobs that were run with older versions of butido have no times.
+                    "#))
+                )
+                .arg(Arg::new("show_phase")
+                    .required(false)
+                    .long("phase")
+                    .takes_value(true)
+                    .value_name("PHASE")
+                    .requires("show_log")
+                    .help("Only show the log of PHASE")
+                )
+            )
+            .subcommand(Command::new("diff-artifacts")
+                .version(VERSION)
+                .about("Compare the artifacts of two jobs")
+                .long_about(indoc::indoc!(r#"
+                    Compare the artifacts of two jobs by name, size and checksum.
+
+                    The artifacts are looked up in the staging directory of the submit of the job or, if they were
+                    released, in the release store.
+                "#))
+                .arg(Arg::new("job_a")
+                    .required(true)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("JOB A")
+                    .help("The UUID of the first job")
+                )
+                .arg(Arg::new("job_b")
+                    .required(true)
+                    .index(2)
+                    .takes_value(true)
+                    .value_name("JOB B")
+                    .help("The UUID of the second job")
+                )
+                .arg(Arg::new("contents")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("contents")
+                    .short('c')
+                    .help("Also compare the file lists of changed tar artifacts (.tar, .tar.gz, .tgz)")
+                )
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
+            )
+            .subcommand(Command::new("job-diff")
+                .version(VERSION)
+                .about("Show the differences between two jobs")
+                .long_about(indoc::indoc!(r#"
+                    Show the differences between two jobs as unified diffs of their images, endpoints, packages,
+                    environment variables and scripts and, optionally, of their logs.
+
+                    This helps to find out why a package that was built successfully before fails now.
+                "#))
+                .arg(Arg::new("job_a")
+                    .required(true)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("JOB A")
+                    .help("The UUID of the first job")
+                )
+                .arg(Arg::new("job_b")
+                    .required(true)
+                    .index(2)
+                    .takes_value(true)
+                    .value_name("JOB B")
+                    .help("The UUID of the second job")
+                )
+                .arg(Arg::new("log")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("log")
+                    .short('l')
+                    .help("Also show the differences of the logs")
+                )
+                .arg(Arg::new("context")
+                    .required(false)
+                    .long("context")
+                    .short('U')
+                    .takes_value(true)
+                    .value_name("LINES")
+                    .default_value("3")
+                    .value_parser(parse_usize)
+                    .help("Number of unchanged lines to show around a change")
+                )
             )
             .subcommand(Command::new("log-of")
                 .version(VERSION)
@@ -381,6 +635,27 @@ pub fn cli<'a>() -> Command<'a> {
                     .value_name("UUID")
                     .help("The id of the Job")
                 )
+                .arg(arg_pager())
+                .arg(arg_raw_log())
+                .args(args_log_filter())
+            )
+            .subcommand(Command::new("buildinfo-of")
+                .version(VERSION)
+                .about("Print the buildinfo of a job")
+                .long_about(indoc::indoc!(r#"
+                    Print the buildinfo of a job.
+
+                    The buildinfo records the image digest, the sources, the hash of the script, the
+                    environment and the artifacts of a job, it is also written to "<artifact>.buildinfo" next to
+                    each artifact. It is only recorded for jobs that succeeded.
+                "#))
+                .arg(Arg::new("job_uuid")
+                    .required(true)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("UUID")
+                    .help("The id of the Job")
+                )
             )
             .subcommand(Command::new("releases")
                 .version(VERSION)
@@ -392,6 +667,7 @@ pub fn cli<'a>() -> Command<'a> {
                     .takes_value(false)
                     .help("Format output as CSV")
                 )
+                .arg(arg_output_format())
 
                 .arg(arg_older_than_date("List only releases older than DATE"))
                 .arg(arg_newer_than_date("List only releases newer than DATE"))
@@ -399,6 +675,7 @@ pub fn cli<'a>() -> Command<'a> {
                 .arg(Arg::new("store")
                     .required(false)
                     .long("to")
+                    .visible_alias("store")
                     .takes_value(true)
                     .value_name("STORE")
                     .help("List only releases to STORE")
@@ -412,6 +689,179 @@ pub fn cli<'a>() -> Command<'a> {
                     .value_name("PKG")
                     .help("Only list releases for package PKG")
                 )
+
+                .arg(Arg::new("include_missing")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("include-missing")
+                    .help("Also list releases whose file is missing in the release store")
+                )
+            )
+            .subcommand(Command::new("export")
+                .version(VERSION)
+                .about("Export the build history (submits, jobs, artifacts, releases)")
+                .long_about(indoc::indoc!(r#"
+                    Export the submits, jobs, artifacts and releases from the database, for example to migrate to
+                    another database server or to archive the history.
+
+                    Rows are referenced by UUIDs and names instead of database IDs, so that the export can be
+                    imported into another database with `butido db import`.
+                "#))
+                .arg(Arg::new("format")
+                    .required(false)
+                    .long("format")
+                    .takes_value(true)
+                    .value_name("FORMAT")
+                    .value_parser(["json"])
+                    .default_value("json")
+                    .help("The format of the export")
+                )
+                .arg(Arg::new("output")
+                    .required(false)
+                    .long("output")
+                    .short('o')
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help("Write the export to FILE instead of stdout")
+                )
+            )
+            .subcommand(Command::new("import")
+                .version(VERSION)
+                .about("Import the build history from an export")
+                .long_about(indoc::indoc!(r#"
+                    Import an export that was created with `butido db export`.
+
+                    Submits that exist in the database already are skipped, together with their jobs, artifacts and
+                    releases. The import is done in one transaction, so nothing is imported if it fails.
+                "#))
+                .arg(Arg::new("file")
+                    .required(true)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .value_parser(file_exists_validator)
+                    .help("The export to import")
+                )
+            )
+            .subcommand(Command::new("known-failures")
+                .version(VERSION)
+                .about("Manage known failures")
+                .long_about(indoc::indoc!(r#"
+                    Manage known failures, i.e. jobs that are expected to fail.
+
+                    A known failure flags either one specific job, or all jobs of a package, optionally only of a
+                    specific version and/or image. Failed jobs that are flagged as known failures are shown as
+                    "known failure" in 'db submit' and 'db jobs' and are counted separately in 'db stats', so that
+                    expected breakage can be told apart from regressions.
+                "#))
+                .subcommand(Command::new("list")
+                    .version(VERSION)
+                    .about("List the known failures")
+                    .arg(Arg::new("csv")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("csv")
+                        .takes_value(false)
+                        .help("Format output as CSV")
+                    )
+                    .arg(arg_output_format())
+                )
+                .subcommand(Command::new("add")
+                    .version(VERSION)
+                    .about("Flag a job or a package as known failure")
+                    .arg(Arg::new("job")
+                        .required(false)
+                        .long("job")
+                        .takes_value(true)
+                        .value_name("UUID")
+                        .help("Flag this job")
+                    )
+                    .arg(Arg::new("package")
+                        .required(false)
+                        .long("package")
+                        .short('p')
+                        .takes_value(true)
+                        .value_name("PKG")
+                        .help("Flag the jobs of this package")
+                    )
+                    .arg(Arg::new("package_version")
+                        .required(false)
+                        .long("package-version")
+                        .takes_value(true)
+                        .value_name("VERSION")
+                        .requires("package")
+                        .help("Flag only the jobs of this version of the package")
+                    )
+                    .arg(Arg::new("image")
+                        .required(false)
+                        .long("image")
+                        .short('I')
+                        .takes_value(true)
+                        .value_name("IMAGE")
+                        .requires("package")
+                        .help("Flag only the jobs of the package on this image")
+                    )
+                    .arg(Arg::new("reason")
+                        .required(true)
+                        .long("reason")
+                        .short('r')
+                        .takes_value(true)
+                        .value_name("REASON")
+                        .help("Why the failure is expected")
+                    )
+                    .group(ArgGroup::new("job-or-package")
+                        .args(&["job", "package"])
+                        .required(true)
+                    )
+                )
+                .subcommand(Command::new("remove")
+                    .version(VERSION)
+                    .about("Remove a known failure")
+                    .arg(Arg::new("id")
+                        .required(true)
+                        .index(1)
+                        .takes_value(true)
+                        .value_name("ID")
+                        .value_parser(parse_usize)
+                        .help("The ID of the known failure (see 'db known-failures list')")
+                    )
+                )
+            )
+            .subcommand(Command::new("stats")
+                .version(VERSION)
+                .about("Print statistics about the builds")
+                .long_about(indoc::indoc!(r#"
+                    Print statistics about the builds in the database:
+
+                        - the number of submits, jobs, artifacts and releases
+                        - the number of submits per week
+                        - the success rate of the jobs per package and per image, with and without known failures
+                        - the average duration of the jobs and the slowest packages
+                        - the packages with the largest artifacts
+                        - the size of the database tables
+
+                    The durations of jobs and the sizes of artifacts are only known if they were recorded by a butido
+                    version that records them.
+                "#))
+                .arg(arg_newer_than_date("Only use submits newer than DATE"))
+                .arg(Arg::new("weeks")
+                    .required(false)
+                    .long("weeks")
+                    .takes_value(true)
+                    .value_name("N")
+                    .value_parser(parse_usize)
+                    .default_value("12")
+                    .help("Show the submits of the last N weeks")
+                )
+                .arg(Arg::new("top")
+                    .required(false)
+                    .long("top")
+                    .takes_value(true)
+                    .value_name("N")
+                    .value_parser(parse_usize)
+                    .default_value("10")
+                    .help("Show the N slowest and the N largest packages")
+                )
             )
         )
 
@@ -420,7 +870,7 @@ pub fn cli<'a>() -> Command<'a> {
             .about("Build packages in containers")
 
             .arg(Arg::new("package_name")
-                .required(true)
+                .required_unless_present("manifest")
                 .index(1)
                 .value_name("NAME")
             )
@@ -451,46 +901,227 @@ pub fn cli<'a>() -> Command<'a> {
                     Do not perform script linting before starting the build.
                 "#))
             )
-
-            .arg(Arg::new("staging_dir")
+            .arg(Arg::new("deny_deprecated")
+                .action(ArgAction::SetTrue)
                 .required(false)
-                .long("staging-dir")
-                .takes_value(true)
-                .value_name("PATH")
-                .value_parser(dir_exists_validator)
-                .help("Do not throw dice on staging directory name, but hardcode for this run.")
+                .takes_value(false)
+                .long("deny-deprecated")
+                .help("Fail if deprecated packages are selected as dependencies")
+                .long_help(indoc::indoc!(r#"
+                    Fail if deprecated packages (packages with a "deprecated" setting) are selected as
+                    dependencies, instead of printing a warning.
+                "#))
             )
 
-            .arg(Arg::new("shebang")
+            .arg(Arg::new("dry_run")
+                .action(ArgAction::SetTrue)
                 .required(false)
-                .long("shebang")
-                .takes_value(true)
-                .value_name("BANG")
-                .help("Overwrite the configured shebang line")
+                .long("dry-run")
+                .help("Only print the jobs of the build")
+                .long_help(indoc::indoc!(r#"
+                    Only print the jobs of the build (package, version, image, endpoint candidates and environment).
+
+                    The tree is built and checked like for a real build, including the checks of the environment
+                    variables of the jobs, but nothing is written to the database and no container is started.
+                    Fails if a check fails.
+                "#))
             )
 
-            .arg(Arg::new("env")
+            .arg(Arg::new("priority")
                 .required(false)
                 .action(ArgAction::Append)
                 .takes_value(true)
-                .short('E')
-                .long("env")
-                .value_parser(env_pass_validator)
-                .help("Pass environment variable to all build jobs")
+                .long("priority")
+                .value_name("NAME=PRIORITY")
+                .value_parser(package_priority_validator)
+                .help("Set the scheduling priority of the jobs for a package")
                 .long_help(indoc::indoc!(r#"
-                    Pass these variables to each build job.
-                    This argument expects \"key=value\" or name of variable available in ENV
+                    Set the scheduling priority of the jobs for a package, overriding the 'priority' from its pkg.toml.
+
+                    Jobs with a higher priority (and the jobs they depend on) get a free endpoint first. Can be passed
+                    multiple times.
                 "#))
             )
 
-            .arg(Arg::new("image")
-                .required(true)
+            .arg(Arg::new("jobs")
+                .required(false)
+                .long("jobs")
+                .short('j')
                 .takes_value(true)
-                .value_name("IMAGE NAME")
-                .short('I')
-                .long("image")
-                .help("Name of the docker image to use")
-            )
+                .value_name("N")
+                .value_parser(parse_usize)
+                .help("Run at most N jobs in parallel (overrides 'max_parallel_jobs' from the configuration)")
+                .long_help(indoc::indoc!(r#"
+                    Run at most N jobs in parallel, on all endpoints together.
+
+                    Overrides the 'max_parallel_jobs' setting from the configuration. The limits of the individual
+                    endpoints still apply.
+                "#))
+            )
+
+            .arg(Arg::new("dashboard")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("dashboard")
+                .help("Show a dashboard of all jobs instead of a progress bar per job")
+                .long_help(indoc::indoc!(r#"
+                    Show a dashboard of all jobs instead of a progress bar per job.
+
+                    The dashboard shows the status and current phase of each job, the last log lines of the running
+                    jobs and the utilization of the endpoints. It is redrawn periodically while the jobs run.
+                    Ignored if the output is not a terminal.
+                "#))
+            )
+
+            .arg(Arg::new("output")
+                .required(false)
+                .long("output")
+                .value_name("FORMAT")
+                .value_parser(["text", "json-lines"])
+                .conflicts_with("dashboard")
+                .help("The output format (default: text)")
+                .long_help(indoc::indoc!(r#"
+                    The output format.
+
+                    "text" (the default) shows progress bars and prints a summary for humans.
+                    "json-lines" prints one JSON object per line for each event of the jobs instead
+                    ("job_scheduled", "job_started", "phase_changed", "artifact_produced",
+                    "job_retrying", "job_finished"), and a final "submit_finished" event with the
+                    produced artifacts and the failed jobs.
+                "#))
+            )
+
+            .arg(Arg::new("status_address")
+                .required(false)
+                .long("status-address")
+                .value_name("ADDR")
+                .value_parser(socket_address_validator)
+                .help("Serve the status of the submit over HTTP on this address (e.g. 127.0.0.1:8080)")
+                .long_help(indoc::indoc!(r#"
+                    Serve the status of the submit over HTTP on this address (e.g. 127.0.0.1:8080).
+
+                    The endpoint is read-only and only available while the jobs run:
+
+                        GET /status         A summary of the submit
+                        GET /jobs           The status, endpoint and current phase of each job
+                        GET /logs/<uuid>    The last lines of the log of the current run of a job
+                                            (?from=<n> skips the first n lines, the number of the
+                                            first returned line is in the X-First-Line header)
+
+                    "butido logs --follow" uses this endpoint to follow the log of a job.
+                "#))
+            )
+
+            .arg(Arg::new("staging_dir")
+                .required(false)
+                .long("staging-dir")
+                .takes_value(true)
+                .value_name("PATH")
+                .value_parser(dir_exists_validator)
+                .help("Do not throw dice on staging directory name, but hardcode for this run.")
+            )
+
+            .arg(Arg::new("shebang")
+                .required(false)
+                .long("shebang")
+                .takes_value(true)
+                .value_name("BANG")
+                .help("Overwrite the configured shebang line")
+            )
+
+            .arg(Arg::new("env")
+                .required(false)
+                .action(ArgAction::Append)
+                .takes_value(true)
+                .short('E')
+                .long("env")
+                .value_parser(env_pass_validator)
+                .help("Pass environment variable to all build jobs")
+                .long_help(indoc::indoc!(r#"
+                    Pass these variables to each build job.
+                    This argument expects \"key=value\" or name of variable available in ENV
+                "#))
+            )
+
+            .arg(Arg::new("image")
+                .required(false)
+                .takes_value(true)
+                .value_name("IMAGE NAME")
+                .short('I')
+                .long("image")
+                .help("Name of the docker image to use")
+                .long_help(indoc::indoc!(r#"
+                    Name of the docker image to use.
+
+                    If not passed, the first matching entry of the `docker.default_images` setting from the
+                    configuration is used.
+                "#))
+            )
+
+            .arg(Arg::new("flavor")
+                .required(false)
+                .takes_value(true)
+                .value_name("FLAVOR")
+                .long("flavor")
+                .help("Build the FLAVOR of the package")
+                .long_help(indoc::indoc!(r#"
+                    Build the FLAVOR of the package.
+
+                    The flavor is applied to all packages in the tree that define it in their `flavors` table.
+                    The package that is built must define the flavor.
+                "#))
+            )
+
+            .arg(Arg::new("with")
+                .required(false)
+                .action(ArgAction::Append)
+                .takes_value(true)
+                .value_name("FEATURE")
+                .long("with")
+                .help("Enable the optional FEATURE of the packages")
+                .long_help(indoc::indoc!(r#"
+                    Enable the optional FEATURE of the packages. Can be passed multiple times.
+
+                    The feature is enabled for all packages in the tree that define it in their `features` table,
+                    which adds the dependencies and environment variables of the feature to the package.
+                    At least one package in the tree must define the feature.
+                "#))
+            )
+
+            .arg(Arg::new("manifest")
+                .required(false)
+                .takes_value(true)
+                .value_name("PATH")
+                .long("manifest")
+                .value_parser(file_exists_validator)
+                .help("Read the submit from a manifest file")
+                .long_help(indoc::indoc!(r#"
+                    Read the submit from a manifest file (TOML).
+
+                    The manifest can declare the `package` (table with `name` and optional `version`), the
+                    `image`, the `flavor`, the `features`, the `shebang`, the `env` and `labels` tables as well as the flags
+                    `no_verification`, `no_lint` and `write_log`.
+                    Arguments passed on the commandline override the settings from the manifest.
+
+                    The content of the manifest is stored with the submit.
+                "#))
+            )
+
+            .arg(Arg::new("continuous_release")
+                .required(false)
+                .takes_value(true)
+                .value_name("STORE")
+                .long("continuous-release")
+                .help("Release the artifacts of each job to STORE as soon as the job succeeded")
+                .long_help(indoc::indoc!(r#"
+                    Release the artifacts of each job to the release store STORE as soon as the job succeeded, instead
+                    of waiting for the whole submit to finish.
+
+                    Existing files in the release store are replaced.
+                    The releases are recorded as partial in the database and are marked as complete when the submit
+                    finished successfully.
+                "#))
+            )
 
             .arg(Arg::new("write-log-file")
                 .action(ArgAction::SetTrue)
@@ -502,9 +1133,152 @@ pub fn cli<'a>() -> Command<'a> {
                     With this flag set, butido does not only write the build logs to database, but also to the configured
                     log directory.
 
-                    The log of a build is written to `<log_dir>/<build id>.log`.
+                    The log of each job is written to `<log_dir>/<log_files.path>`, by default
+                    `<log_dir>/<submit>/<package>-<version>-<job>.log`. See the `log_files` section of the
+                    configuration for the naming, rotation and compression of the log files.
+                "#))
+            )
+        )
+
+        .subcommand(Command::new("queue")
+            .version(VERSION)
+            .about("Work on a build queue that is shared by several butido instances")
+            .long_about(indoc::indoc!(r#"
+                Work on a build queue that is shared by several butido instances.
+
+                Builds are enqueued in the database and run by the workers ("butido queue work"), possibly on
+                different hosts. Each build is claimed by exactly one worker. If a worker stops sending heartbeats
+                for a build (e.g. because its host died), the build is claimed by another worker.
+
+                Workers only run the builds that were enqueued at the commit their repository is at, builds for
+                other commits are left for other workers.
+            "#))
+            .subcommand(Command::new("add")
+                .version(VERSION)
+                .about("Enqueue a build (like 'butido submit enqueue')")
+                .arg(arg_build_args())
+            )
+            .subcommand(Command::new("list")
+                .version(VERSION)
+                .about("List the builds in the queue")
+            )
+            .subcommand(Command::new("work")
+                .version(VERSION)
+                .about("Run builds from the queue")
+                .arg(Arg::new("once")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("once")
+                    .help("Exit as soon as the queue is empty")
+                )
+                .arg(arg_poll_interval())
+                .arg(arg_stale_after())
+            )
+        )
+
+        .subcommand(Command::new("submit")
+            .version(VERSION)
+            .about("Manage submits without running them in the foreground")
+            .subcommand(Command::new("enqueue")
+                .version(VERSION)
+                .about("Enqueue a submit, which is run by a 'butido daemon' (or 'butido queue work')")
+                .arg(arg_build_args())
+            )
+        )
+
+        .subcommand(Command::new("daemon")
+            .version(VERSION)
+            .about("Run submits from the build queue continuously")
+            .long_about(indoc::indoc!(r#"
+                Run submits from the build queue continuously, until interrupted (Ctrl-C).
+
+                Submits are added with "butido submit enqueue", or by putting submit requests (build manifests
+                with the extension ".toml") into the requests directory. Several daemons (on different hosts)
+                can work on the same queue. An interrupted submit is put back into the queue.
+
+                Only submits that were enqueued at the commit the repository is at are run. The package
+                repository is loaded again for each submit, so that the daemon picks up new commits.
+            "#))
+            .arg(Arg::new("requests_dir")
+                .required(false)
+                .long("requests")
+                .value_name("DIR")
+                .value_parser(dir_exists_validator)
+                .help("Enqueue the submit requests (build manifests) from this directory")
+                .long_help(indoc::indoc!(r#"
+                    Enqueue the submit requests (build manifests with the extension ".toml") from this directory.
+
+                    Enqueued requests are removed from the directory, requests that cannot be enqueued are renamed
+                    to "*.toml.failed".
                 "#))
             )
+            .arg(arg_poll_interval())
+            .arg(arg_stale_after())
+        )
+
+        .subcommand(Command::new("rebuild-dependents")
+            .version(VERSION)
+            .about("Rebuild all packages that (transitively) depend on a package")
+            .long_about(indoc::indoc!(r#"
+                Rebuild all packages that (transitively) depend on a package, e.g. after a security fix in a base library.
+
+                Only the dependents that no other dependent depends on are submitted, one submit each. The other
+                dependents are rebuilt as part of their trees.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .index(1)
+                .value_name("NAME")
+                .help("The name of the package whose dependents are rebuilt")
+            )
+            .arg(Arg::new("package_version")
+                .required(false)
+                .index(2)
+                .value_name("VERSION")
+                .help("Only rebuild packages with a dependency that matches this version of the package")
+            )
+            .arg(Arg::new("dry_run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("dry-run")
+                .help("Only print the packages that would be rebuilt")
+            )
+            .arg(Arg::new("no_verification")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-verify")
+                .help("Skip hashsum check")
+            )
+            .arg(Arg::new("no_lint")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-lint")
+                .help("Skip linting")
+            )
+            .arg(Arg::new("env")
+                .required(false)
+                .action(ArgAction::Append)
+                .takes_value(true)
+                .short('E')
+                .long("env")
+                .value_parser(env_pass_validator)
+                .help("Pass environment variable to all build jobs")
+            )
+            .arg(Arg::new("image")
+                .required(false)
+                .takes_value(true)
+                .value_name("IMAGE NAME")
+                .short('I')
+                .long("image")
+                .help("Name of the docker image to use (default: the configured default image of each package)")
+            )
+            .arg(Arg::new("write-log-file")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("write-log")
+                .short('L')
+                .help("Write log to disk as well")
+            )
         )
 
         .subcommand(Command::new("what-depends")
@@ -532,6 +1306,25 @@ pub fn cli<'a>() -> Command<'a> {
                 ])
                 .help("Specify which dependency types are to be checked. By default, all are checked")
             )
+            .arg(Arg::new("transitive")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("transitive")
+                .help("Also list the packages that depend on the package indirectly")
+                .long_help(indoc::indoc!(r#"
+                    Also list the packages that depend on the package indirectly, i.e. all
+                    packages that would be affected by a change of the package.
+                "#))
+            )
+            .arg(Arg::new("depth")
+                .required(false)
+                .long("depth")
+                .takes_value(true)
+                .value_name("N")
+                .value_parser(parse_usize)
+                .requires("transitive")
+                .help("Only follow N levels of dependencies with --transitive (default: unlimited)")
+            )
         )
         .subcommand(Command::new("dependencies-of")
             .version(VERSION)
@@ -571,12 +1364,58 @@ pub fn cli<'a>() -> Command<'a> {
             .version(VERSION)
             .alias("versions")
             .about("List the versions of a package")
+            .subcommand_negates_reqs(true)
             .arg(Arg::new("package_name")
                 .required(true)
                 .index(1)
                 .value_name("PACKAGE_NAME")
                 .help("The name of the package")
             )
+            .subcommand(Command::new("check-upstream")
+                .version(VERSION)
+                .about("Check whether there are newer upstream versions of packages")
+                .long_about(indoc::indoc!(r#"
+                    Query the configured upstream version URL (release-monitoring.org by default, or the
+                    `upstream_version_url` of the package) and report packages where the upstream version is
+                    newer than the newest packaged version.
+                "#))
+                .arg(Arg::new("package_name")
+                    .required(false)
+                    .index(1)
+                    .value_name("PKG")
+                    .help("Check only this package (optional, if left out, all packages are checked)")
+                )
+                .arg(Arg::new("matching")
+                    .required(false)
+                    .long("matching")
+                    .takes_value(true)
+                    .value_name("REGEX")
+                    .help("Check all packages where the package name matches REGEX")
+                    .conflicts_with("package_name")
+                )
+                .arg(Arg::new("all")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("all")
+                    .short('a')
+                    .help("Also list packages that are up to date")
+                )
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
+                .arg(Arg::new("timeout")
+                    .required(false)
+                    .long("timeout")
+                    .takes_value(true)
+                    .value_name("TIMEOUT")
+                    .value_parser(parse_u64)
+                    .help("Set timeout for each request in seconds")
+                )
+            )
         )
         .subcommand(Command::new("env-of")
             .version(VERSION)
@@ -772,6 +1611,18 @@ pub fn cli<'a>() -> Command<'a> {
             .arg(script_arg_highlight())
             .arg(script_arg_no_highlight())
 
+            .arg(Arg::new("show_effective")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("show-effective")
+                .conflicts_with("terse")
+                .help("Print the effective package definition as TOML")
+                .long_help(indoc::indoc!(r#"
+                    Print the effective package definition as TOML, i.e. the pkg.toml after merging it with the
+                    pkg.toml files of the parent directories and the repository overlays.
+                    Use this to find out where a value of a package comes from.
+                "#))
+            )
         )
         .subcommand(Command::new("source")
             .version(VERSION)
@@ -800,6 +1651,28 @@ pub fn cli<'a>() -> Command<'a> {
                     .help("Verify all packages where the package name matches REGEX")
                 )
 
+                .arg(Arg::new("missing_only")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("missing-only")
+                    .help("Only verify sources that were not successfully verified before")
+                    .long_help(indoc::indoc!(r#"
+                        Only verify sources that were not successfully verified before.
+
+                        Successful verifications are recorded in the source cache. A source is verified again if
+                        its file or its expected hash changed since then.
+                    "#))
+                )
+                .arg(Arg::new("jobs")
+                    .required(false)
+                    .long("jobs")
+                    .short('j')
+                    .takes_value(true)
+                    .value_name("N")
+                    .value_parser(parse_usize)
+                    .help("Number of sources to hash concurrently (default: number of CPUs)")
+                )
+
                 .group(ArgGroup::new("verify-one-or-many")
                     .args(&["package_name", "matching"])
                     .required(true)
@@ -874,51 +1747,294 @@ pub fn cli<'a>() -> Command<'a> {
                 .arg(Arg::new("package_name")
                     .required(false)
                     .index(1)
-                    .value_name("PKG")
-                    .help("Get the source file pathes for this package")
+                    .value_name("PKG")
+                    .help("Get the source file pathes for this package")
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .index(2)
+                    .value_name("VERSION")
+                    .help("Get the source file pathes for the package in this version")
+                )
+            )
+        )
+
+        .subcommand(Command::new("release")
+            .version(VERSION)
+            .about("Manage artifact releases")
+            .subcommand(Command::new("rm")
+                .version(VERSION)
+                .about("Remove release artifacts")
+                .long_about(indoc::indoc!(r#"
+                    Removes a released artifact from the release store and deletes the according database entry.
+
+                    This command asks interactively whether you want to delete data.
+                    This can't be turned off.
+                "#))
+                .arg(Arg::new("release_store_name")
+                    .required(true)
+                    .long("from")
+                    .value_name("RELEASE_STORE_NAME")
+                    .help("Release store name to remove release from")
+                )
+
+                .arg(Arg::new("package_name")
+                    .required(false)
+                    .index(1)
+                    .value_name("PKG")
+                    .help("The name of the package")
+                    .requires("package_version")
+                )
+
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .index(2)
+                    .value_name("VERSION")
+                    .help("The exact version of the package (string match)")
+                    .requires("package_name")
+                )
+            )
+
+            .subcommand(Command::new("rollback")
+                .version(VERSION)
+                .about("Roll back the release of a package to a previously released artifact")
+                .long_about(indoc::indoc!(r#"
+                    Rolls back the latest release of a package in a release store to a previously released artifact of the
+                    package.
+
+                    The released file of the package is removed from the release store and the file of the previous release
+                    is put in place again. If that file was removed from the release store in the meantime, it is restored
+                    from the staging directory of its submit.
+                    The rollback is recorded as a new release of the previous artifact, together with the reason.
+
+                    This command asks interactively whether you want to continue, unless --non-interactive is passed.
+                "#))
+                .arg(Arg::new("release_store_name")
+                    .required(true)
+                    .long("store")
+                    .value_name("RELEASE_STORE_NAME")
+                    .help("Release store name to roll back the release in")
+                )
+
+                .arg(Arg::new("package_name")
+                    .required(true)
+                    .index(1)
+                    .value_name("PKG")
+                    .help("The name of the package")
+                )
+
+                .arg(Arg::new("package_version")
+                    .required(true)
+                    .index(2)
+                    .value_name("VERSION")
+                    .help("The exact version of the package (string match)")
+                )
+
+                .arg(Arg::new("to_release_id")
+                    .required(false)
+                    .long("to")
+                    .value_name("RELEASE_ID")
+                    .value_parser(clap::value_parser!(i32))
+                    .help("The ID of the release to roll back to (default: the previous release of the package)")
+                )
+
+                .arg(Arg::new("reason")
+                    .required(true)
+                    .long("reason")
+                    .value_name("REASON")
+                    .help("The reason for the rollback, recorded with the release")
+                )
+
+                .arg(Arg::new("noninteractive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("non-interactive")
+                    .help("Dont ask before rolling back")
+                )
+            )
+
+            .subcommand(Command::new("propose")
+                .version(VERSION)
+                .about("Propose a release, which is done when another user approves it")
+                .long_about(indoc::indoc!(r#"
+                    Records the intent to release artifacts of a submit to a release store in the database, without releasing
+                    them. The release is done when another user approves the proposal with `butido release approve`.
+
+                    This is the only way to release to the release stores in the "release_approval_required" setting.
+                "#))
+                .arg(Arg::new("submit_uuid")
+                    .required(true)
+                    .index(1)
+                    .value_name("SUBMIT")
+                    .help("The submit uuid from which to release a package")
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(true)
+                    .long("to")
+                    .value_name("RELEASE_STORE_NAME")
+                    .help("Release store name to release to")
+                )
+                .arg(Arg::new("package_name")
+                    .required(false)
+                    .index(2)
+                    .value_name("PKG")
+                    .help("The name of the package")
+                    .conflicts_with("all-packages")
+                )
+                .arg(Arg::new("all-packages")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("all")
+                    .help("Release all packages")
+                    .conflicts_with("package_name")
+                )
+                .group(ArgGroup::new("package")
+                    .args(&["package_name", "all-packages"])
+                    .required(true) // one of these is required
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .index(3)
+                    .value_name("VERSION")
+                    .help("The exact version of the package (string match)")
+                )
+            )
+
+            .subcommand(Command::new("approve")
+                .version(VERSION)
+                .about("Approve a proposed release and release the artifacts")
+                .long_about(indoc::indoc!(r#"
+                    Approves a release that was proposed with `butido release propose` and releases the artifacts, like
+                    `butido release new` would.
+
+                    A release can not be approved by the user who proposed it. The users are identified by the
+                    database user butido connects as (not the login name), so each user needs an own database user.
+                    If all users share one database user, no proposal can be approved.
+                    If releasing the artifacts fails, the approval is undone and the proposal can be approved again.
+                    The artifacts that were released by the failed approval are not released again.
+                "#))
+                .arg(Arg::new("proposal_id")
+                    .required(true)
+                    .index(1)
+                    .value_name("PROPOSAL_ID")
+                    .value_parser(clap::value_parser!(i32))
+                    .help("The ID of the proposed release")
+                )
+                .arg(Arg::new("package_do_update")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("update")
+                    .help("Do update a package if it already exists in the release store")
+                )
+                .arg(Arg::new("noninteractive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("non-interactive")
+                    .help("Dont be interactive (only with --update at the moment)")
+                    .requires("package_do_update")
+                )
+                .arg(Arg::new("quiet")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("quiet")
+                    .short('q')
+                    .help("Don't print pathes to released files after releases are complete")
+                )
+            )
+
+            .subcommand(Command::new("proposals")
+                .version(VERSION)
+                .about("List the proposed releases that are not approved yet")
+                .arg(Arg::new("all")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("all")
+                    .help("Also list the approved proposals")
+                )
+                .arg(arg_output_format())
+            )
+
+            .subcommand(Command::new("notes")
+                .version(VERSION)
+                .about("Generate a Markdown changelog of the releases between two points in time")
+                .long_about(indoc::indoc!(r#"
+                    Prints a Markdown changelog of the packages that were released between SINCE and UNTIL, grouped by
+                    release store and package, with the version changes, the dates of the releases and submits and the git
+                    hashes the packages were built from.
+
+                    SINCE and UNTIL are either submit UUIDs (the time of the submit is used), dates (e.g. "2023-01-01" or
+                    "2023-01-01 12:00:00") or durations before now (e.g. "2weeks").
+                "#))
+                .arg(Arg::new("since")
+                    .required(true)
+                    .index(1)
+                    .value_name("SINCE")
+                    .help("Only list releases after this submit UUID or date")
                 )
-                .arg(Arg::new("package_version")
+                .arg(Arg::new("until")
                     .required(false)
                     .index(2)
-                    .value_name("VERSION")
-                    .help("Get the source file pathes for the package in this version")
+                    .value_name("UNTIL")
+                    .help("Only list releases until this submit UUID or date (default: now)")
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(false)
+                    .long("store")
+                    .value_name("RELEASE_STORE_NAME")
+                    .help("Only list the releases in this release store")
                 )
             )
-        )
 
-        .subcommand(Command::new("release")
-            .version(VERSION)
-            .about("Manage artifact releases")
-            .subcommand(Command::new("rm")
+            .subcommand(Command::new("sync")
                 .version(VERSION)
-                .about("Remove release artifacts")
+                .about("Sync a release store to a remote storage")
                 .long_about(indoc::indoc!(r#"
-                    Removes a released artifact from the release store and deletes the according database entry.
+                    Copies the released files of a release store to a remote storage (a "release_sync_targets" entry of the
+                    configuration), via rsync over SSH or to an S3 compatible object storage.
 
-                    This command asks interactively whether you want to delete data.
-                    This can't be turned off.
+                    The synced releases are recorded in the database, so only the releases that were not synced to the
+                    target yet are copied. Files that were removed from the release store are not removed remotely.
                 "#))
                 .arg(Arg::new("release_store_name")
                     .required(true)
-                    .long("from")
+                    .long("store")
                     .value_name("RELEASE_STORE_NAME")
-                    .help("Release store name to remove release from")
+                    .help("Release store name to sync")
+                )
+
+                .arg(Arg::new("sync_target")
+                    .required(true)
+                    .long("to")
+                    .value_name("TARGET")
+                    .help("The name of the sync target in the configuration")
                 )
 
                 .arg(Arg::new("package_name")
                     .required(false)
                     .index(1)
                     .value_name("PKG")
-                    .help("The name of the package")
-                    .requires("package_version")
+                    .help("Only sync the releases of this package")
                 )
 
                 .arg(Arg::new("package_version")
                     .required(false)
                     .index(2)
                     .value_name("VERSION")
-                    .help("The exact version of the package (string match)")
-                    .requires("package_name")
+                    .help("Only sync the releases of this version (SQL LIKE pattern)")
+                )
+
+                .arg(Arg::new("force")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("force")
+                    .help("Also sync the releases that were synced to the target already")
+                )
+
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only print the files that would be synced")
                 )
             )
 
@@ -939,6 +2055,9 @@ pub fn cli<'a>() -> Command<'a> {
                     .long_help(indoc::indoc!(r#"
                         Butido can release to different release stores, based on this CLI flag.
                         The release stores that are available must be listed in the configuration.
+
+                        If the name refers to an OCI release target in the configuration, the artifacts
+                        are pushed to the registry instead, one OCI artifact per package.
                     "#))
                 )
                 .arg(Arg::new("package_name")
@@ -1005,11 +2124,218 @@ pub fn cli<'a>() -> Command<'a> {
             )
         )
 
+        .subcommand(Command::new("init")
+            .version(VERSION)
+            .about("Interactively create a starter configuration")
+            .long_about(indoc::indoc!(r#"
+                Interactively create a starter config.toml in the current directory.
+
+                Asks for the database credentials, the store directories, the container images and
+                the docker endpoints, and optionally creates the store directories and a skeleton
+                package repository with a top-level pkg.toml and an example package.
+                If the current directory is not a git repository, it can be initialized as one.
+            "#))
+            .arg(Arg::new("force")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("force")
+                .help("Overwrite an existing config.toml")
+            )
+        )
+
+        .subcommand(Command::new("config")
+            .version(VERSION)
+            .about("Commands for the butido configuration")
+            .subcommand(Command::new("check")
+                .version(VERSION)
+                .about("Load and validate the configuration")
+                .long_about(indoc::indoc!(r#"
+                    Load and validate the configuration.
+
+                    In contrast to loading the configuration for other commands, this does not stop at
+                    the first error but reports all problems that were found:
+
+                        * settings that are not known to butido (e.g. because of a typo)
+                        * settings that cannot be parsed or are not valid
+                        * missing staging, releases, release store, source cache and log directories
+                        * databases that cannot be connected to
+                        * endpoints that cannot be connected to
+                        * images that are missing on an endpoint
+
+                    Every finding is printed with the columns Severity, Setting, Check, Message.
+                    The command fails if at least one finding has the severity "error".
+                "#))
+                .arg(Arg::new("offline")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("offline")
+                    .help("Do not check the database and the endpoints")
+                )
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
+                .arg(arg_output_format())
+            )
+        )
+
+        .subcommand(Command::new("repo")
+            .version(VERSION)
+            .about("Commands for the package repository")
+            .subcommand(Command::new("check")
+                .version(VERSION)
+                .about("Validate all package definitions of the repository")
+                .long_about(indoc::indoc!(r#"
+                    Validate all package definitions of the repository.
+
+                    In contrast to loading the repository for other commands, this does not stop at
+                    the first error but reports all problems that were found:
+
+                        * package definition files that cannot be parsed
+                        * packages that are defined multiple times (same name and version)
+                        * dependencies that cannot be satisfied by any package in the repository
+                        * sources without a hash
+                        * phases that are not in the "available_phases" setting
+                        * inconsistent "allowed_images" and "denied_images" settings
+
+                    Every finding is printed with the columns
+                    Severity, Package, Version, Path, Check, Message.
+                    The command fails if at least one finding has the severity "error".
+                "#))
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
+            )
+            .subcommand(Command::new("migrate")
+                .version(VERSION)
+                .about("Migrate all pkg.toml files to the current schema version")
+                .long_about(indoc::indoc!(r#"
+                    Migrate all pkg.toml files of the repository to the current schema version.
+
+                    The version of the pkg.toml format is declared with "schema_version" in each file,
+                    files without it use schema version 1. Outdated files are still loaded, but a
+                    warning is printed for them. This command rewrites them in the current format,
+                    files that are already up to date are not touched.
+                "#))
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only print the files that would be migrated")
+                )
+            )
+            .subcommand(Command::new("licenses")
+                .version(VERSION)
+                .about("Report the licenses in the dependency tree of a package")
+                .long_about(indoc::indoc!(r#"
+                    Report the licenses in the dependency tree of a package.
+
+                    The license of a package is set as SPDX license expression with "license" in the
+                    pkg.toml. The number of packages per license in the tree is printed, followed by
+                    the packages without a license and the packages with a license from the
+                    "denied_licenses" setting.
+                    The command fails if the tree contains a package with a denied license.
+                "#))
+                .arg(Arg::new("package_name")
+                    .required(true)
+                    .index(1)
+                    .value_name("NAME")
+                    .help("The name of the package")
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .index(2)
+                    .value_name("VERSION_CONSTRAINT")
+                    .help("A version constraint to search for (optional), E.G. '=1.0.0'")
+                )
+                .arg(Arg::new("image")
+                    .required(false)
+                    .takes_value(true)
+                    .value_name("IMAGE NAME")
+                    .short('I')
+                    .long("image")
+                    .help("Name of the docker image to use, for conditional dependencies")
+                )
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .help("Format output as CSV")
+                )
+            )
+        )
+
+        .subcommand(Command::new("audit")
+            .version(VERSION)
+            .about("Report known vulnerabilities in the dependency tree of a package")
+            .long_about(indoc::indoc!(r#"
+                Report known vulnerabilities in the dependency tree of a package.
+
+                The packages of the tree are looked up in the OSV database (https://osv.dev), which
+                includes the CVEs of the NVD. The ecosystem and name of the packages in the OSV
+                database are configured in the "audit" section of the configuration, packages
+                without an ecosystem are not checked.
+
+                The command fails if a known vulnerability is found, so it can be used to gate CI
+                pipelines.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .index(1)
+                .value_name("NAME")
+                .help("The name of the package")
+            )
+            .arg(Arg::new("package_version")
+                .required(false)
+                .index(2)
+                .value_name("VERSION_CONSTRAINT")
+                .help("A version constraint to search for (optional), E.G. '=1.0.0'")
+            )
+            .arg(Arg::new("image")
+                .required(false)
+                .takes_value(true)
+                .value_name("IMAGE NAME")
+                .short('I')
+                .long("image")
+                .help("Name of the docker image to use, for conditional dependencies")
+            )
+            .arg(Arg::new("timeout")
+                .required(false)
+                .takes_value(true)
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("Set timeout for each request in seconds")
+            )
+            .arg(Arg::new("csv")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("csv")
+                .takes_value(false)
+                .conflicts_with("json")
+                .help("Format output as CSV")
+            )
+            .arg(Arg::new("json")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("json")
+                .takes_value(false)
+                .help("Print the audited packages with their vulnerabilities as JSON")
+            )
+        )
+
         .subcommand(Command::new("tree-of")
             .version(VERSION)
             .about("Print the dependency tree of one or multiple packages")
             .arg(Arg::new("package_name")
-                .required(true)
+                .required_unless_present("check_cycles")
                 .index(1)
                 .value_name("NAME")
                 .help("Package name to lint (if not present, every package will be linted")
@@ -1049,6 +2375,105 @@ pub fn cli<'a>() -> Command<'a> {
                     conditions on dependencies.
                 "#))
             )
+            .arg(Arg::new("check_cycles")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("check-cycles")
+                .help("Check the trees for dependency cycles instead of printing them")
+                .long_help(indoc::indoc!(r#"
+                    Check the trees for dependency cycles instead of printing them.
+
+                    If no package name is given, the trees of all packages in the repository are
+                    checked. Every cycle that is found is printed once, the command fails if
+                    there is at least one cycle.
+                "#))
+            )
+            .arg(Arg::new("format")
+                .required(false)
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["tree", "dot", "json"])
+                .conflicts_with("check_cycles")
+                .help("The output format (default: tree)")
+                .long_help(indoc::indoc!(r#"
+                    The output format.
+
+                    "tree" (the default) prints the trees for humans, "dot" prints a Graphviz
+                    digraph per tree and "json" prints a JSON array with the packages and
+                    dependencies of each tree.
+                "#))
+            )
+            .arg(Arg::new("diff")
+                .required(false)
+                .long("diff")
+                .value_name("GIT_REF")
+                .conflicts_with_all(&["check_cycles", "format"])
+                .help("Print how the trees changed compared to a git ref")
+                .long_help(indoc::indoc!(r#"
+                    Print how the trees changed compared to a git ref.
+
+                    The trees are resolved both with the repository in the working directory and
+                    with the repository at GIT_REF (a branch, tag or commit). The packages that
+                    were added to or removed from a tree and the packages whose version changed
+                    are printed.
+                "#))
+            )
+        )
+
+        .subcommand(Command::new("browse")
+            .version(VERSION)
+            .about("Browse packages, their dependency trees and builds in the terminal")
+            .long_about(indoc::indoc!(r#"
+                Interactive browser for the packages of the repository
+
+                Shows a search for package names (regex), the matching packages and the merged
+                definition, the dependency tree or the recent builds (from the database) of the
+                selected package on one screen. Select a package with the arrow keys, switch
+                between the views with Tab and scroll them with PgUp/PgDn. Esc quits.
+
+                The database is only connected to when builds are shown.
+            "#))
+        )
+
+        .subcommand(Command::new("logs")
+            .version(VERSION)
+            .about("Show the log of a job, or follow the log of a running job")
+            .long_about(indoc::indoc!(r#"
+                Show the log of a job
+
+                Without --follow, the log of the finished job is loaded from the database
+                (like "db log-of").
+
+                With --follow, the output of a running job is streamed live until the job is
+                finished. This needs the status endpoint of the build that runs the job, i.e. the
+                build has to be started with "--status-address" and the same address has to be
+                passed here. Only the output of phases with full log is streamed.
+            "#))
+            .arg(Arg::new("job_uuid")
+                .required(true)
+                .index(1)
+                .takes_value(true)
+                .value_name("UUID")
+                .help("The id of the Job")
+            )
+            .arg(Arg::new("follow")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("follow")
+                .short('f')
+                .requires("status_address")
+                .help("Follow the log of the running job")
+            )
+            .arg(Arg::new("status_address")
+                .required(false)
+                .long("status-address")
+                .value_name("ADDR")
+                .value_parser(socket_address_validator)
+                .help("The status address of the build that runs the job (see 'build --status-address')")
+            )
+            .arg(arg_pager().conflicts_with("follow"))
+            .arg(arg_raw_log().conflicts_with("follow"))
+            .args(args_log_filter().into_iter().map(|arg| arg.conflicts_with("follow")))
         )
 
         .subcommand(Command::new("metrics")
@@ -1272,7 +2697,30 @@ pub fn cli<'a>() -> Command<'a> {
                     )
                 )
             )
+        );
+
+    #[cfg(feature = "grpc")]
+    let cli = cli.subcommand(Command::new("serve")
+        .version(VERSION)
+        .about("Serve the experimental gRPC control API")
+        .long_about(indoc::indoc!(r#"
+            Serve the experimental gRPC control API (see `proto/butido.proto`).
+
+            The API can start and cancel submits (which are run like `butido build`), stream the events, the
+            status and the logs of the jobs of these submits and query the artifacts of packages.
+            The API is not stable yet and might change with every release. It has no authentication, so only
+            listen on trusted networks.
+        "#))
+        .arg(Arg::new("listen")
+            .required(false)
+            .long("listen")
+            .value_name("ADDRESS")
+            .default_value("127.0.0.1:50051")
+            .help("The address to listen on")
         )
+    );
+
+    cli
 }
 
 fn script_arg_line_numbers<'a>() -> clap::Arg<'a> {
@@ -1340,6 +2788,21 @@ fn env_pass_validator(s: &str) -> Result<String, String> {
     }
 }
 
+fn package_priority_validator(s: &str) -> Result<String, String> {
+    match s.split_once('=') {
+        Some((name, priority)) if !name.is_empty() => i32::from_str(priority)
+            .map_err(|e| format!("Invalid priority '{priority}': {e}"))
+            .map(|_| s.to_owned()),
+        _ => Err(format!("Expected NAME=PRIORITY, got: {s}")),
+    }
+}
+
+fn socket_address_validator(s: &str) -> Result<String, String> {
+    SocketAddr::from_str(s)
+        .map_err(|e| format!("Invalid address '{s}': {e}"))
+        .map(|_| s.to_owned())
+}
+
 fn dir_exists_validator(s: &str) -> Result<String, String> {
     if PathBuf::from(&s).is_dir() {
         Ok(s.to_owned())
@@ -1348,10 +2811,49 @@ fn dir_exists_validator(s: &str) -> Result<String, String> {
     }
 }
 
+fn file_exists_validator(s: &str) -> Result<String, String> {
+    if PathBuf::from(&s).is_file() {
+        Ok(s.to_owned())
+    } else {
+        Err(format!("File does not exist: {s}"))
+    }
+}
+
+fn arg_build_args<'a>() -> Arg<'a> {
+    Arg::new("build_args")
+        .required(true)
+        .multiple_values(true)
+        .allow_hyphen_values(true)
+        .index(1)
+        .value_name("BUILD_ARGS")
+        .help("The arguments of 'butido build', e.g. 'mypkg 1.0 --image debian:bullseye'")
+}
+
+fn arg_poll_interval<'a>() -> Arg<'a> {
+    Arg::new("poll_interval")
+        .required(false)
+        .long("poll-interval")
+        .value_name("SECONDS")
+        .default_value("10")
+        .value_parser(parse_u64)
+        .help("How long to wait before looking for submits again if the queue is empty")
+}
+
+fn arg_stale_after<'a>() -> Arg<'a> {
+    Arg::new("stale_after")
+        .required(false)
+        .long("stale-after")
+        .value_name("SECONDS")
+        .default_value("300")
+        .value_parser(parse_u64)
+        .help("Claim submits of other workers that did not send a heartbeat for this long")
+}
+
 fn arg_older_than_date(about: &str) -> Arg<'_> {
     Arg::new("older_than")
         .required(false)
         .long("older-than")
+        .visible_alias("until")
         .takes_value(true)
         .value_name("DATE")
         .help(about)
@@ -1381,6 +2883,7 @@ fn arg_newer_than_date(about: &str) -> Arg<'_> {
     Arg::new("newer_than")
         .required(false)
         .long("newer-than")
+        .visible_alias("since")
         .takes_value(true)
         .value_name("DATE")
         .help(about)
@@ -1406,6 +2909,98 @@ fn arg_newer_than_date(about: &str) -> Arg<'_> {
         .value_parser(parse_date_from_string)
 }
 
+fn arg_pager<'a>() -> Arg<'a> {
+    Arg::new("pager")
+        .action(ArgAction::SetTrue)
+        .required(false)
+        .long("pager")
+        .short('P')
+        .help("Pipe the output through $PAGER (or 'less'), if stdout is a terminal")
+}
+
+fn arg_raw_log<'a>() -> Arg<'a> {
+    Arg::new("raw")
+        .action(ArgAction::SetTrue)
+        .required(false)
+        .long("raw")
+        .help("Print the log as it is stored, without parsing it")
+}
+
+/// The arguments for showing only parts of a stored log
+fn args_log_filter<'a>() -> Vec<Arg<'a>> {
+    vec![
+        Arg::new("log_phase")
+            .required(false)
+            .action(ArgAction::Append)
+            .takes_value(true)
+            .long("phase")
+            .value_name("PHASE")
+            .help("Only show the log of PHASE (can be given multiple times)"),
+
+        Arg::new("errors_only")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .long("errors-only")
+            .help("Only show the error states and the lines around them"),
+
+        Arg::new("error_context")
+            .required(false)
+            .long("error-context")
+            .takes_value(true)
+            .value_name("LINES")
+            .default_value("20")
+            .value_parser(parse_usize)
+            .help("Number of lines to show around an error state with --errors-only"),
+
+        Arg::new("markers_only")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .long("markers-only")
+            .help("Only show the butido markers (phases, progress, metrics, states...), not the output of the script"),
+    ]
+}
+
+fn arg_output_format<'a>() -> Arg<'a> {
+    Arg::new("output")
+        .required(false)
+        .long("output")
+        .takes_value(true)
+        .value_name("FORMAT")
+        .value_parser(["table", "csv", "json"])
+        .conflicts_with("csv")
+        .help("The output format (default: table)")
+}
+
+fn arg_list_limit(about: &str) -> Arg<'_> {
+    Arg::new("limit")
+        .required(false)
+        .long("limit")
+        .takes_value(true)
+        .value_name("LIMIT")
+        .default_value("100")
+        .value_parser(parse_usize)
+        .help(about)
+}
+
+fn arg_list_offset(about: &str) -> Arg<'_> {
+    Arg::new("offset")
+        .required(false)
+        .long("offset")
+        .takes_value(true)
+        .value_name("OFFSET")
+        .value_parser(parse_usize)
+        .help(about)
+}
+
+fn arg_list_all(about: &str) -> Arg<'_> {
+    Arg::new("all")
+        .action(ArgAction::SetTrue)
+        .required(false)
+        .long("all")
+        .conflicts_with("limit")
+        .help(about)
+}
+
 fn parse_date_from_string(s: &str) -> std::result::Result<String, String> {
     humantime::parse_duration(s)
         .map_err(|e| e.to_string())
@@ -1434,8 +3029,20 @@ fn parse_u64(s: &str) -> std::result::Result<String, String> {
 
 #[cfg(test)]
 mod tests {
+    use super::cli;
     use super::env_pass_validator;
 
+    #[test]
+    fn test_quiet_and_no_progress() {
+        let matches = cli().try_get_matches_from(["butido", "-q", "--no-progress", "find-pkg", "foo"]).unwrap();
+        assert!(matches.get_flag("quiet"));
+        assert!(matches.get_flag("hide_bars"));
+
+        let matches = cli().try_get_matches_from(["butido", "find-pkg", "foo"]).unwrap();
+        assert!(!matches.get_flag("quiet"));
+        assert!(!matches.get_flag("hide_bars"));
+    }
+
     #[test]
     fn test_env_pass_validator_1() {
         assert!(env_pass_validator("foo=\"bar\"").is_ok());
@@ -0,0 +1,304 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'audit' subcommand
+//!
+//! Queries the OSV database (https://osv.dev) for known vulnerabilities of the packages in a
+//! dependency tree.
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use serde::Serialize;
+use tokio_stream::StreamExt;
+use tracing::debug;
+use tracing::trace;
+use tracing::warn;
+
+use crate::config::Configuration;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PackageVersionConstraint;
+use crate::repository::Repository;
+use crate::util::docker::ImageName;
+
+const NUMBER_OF_MAX_CONCURRENT_OSV_QUERIES: usize = 10;
+
+/// A known vulnerability of a package
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct Vulnerability {
+    id: String,
+    aliases: Vec<String>,
+    summary: Option<String>,
+}
+
+/// The result of the audit of a package in the tree
+#[derive(Debug, Serialize)]
+struct AuditedPackage {
+    name: PackageName,
+    version: PackageVersion,
+    ecosystem: Option<String>,
+    osv_name: Option<String>,
+    vulnerabilities: Vec<Vulnerability>,
+}
+
+/// Implementation of the "audit" subcommand
+pub async fn audit(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let json = matches.get_flag("json");
+    let pname = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from)
+        .unwrap(); // safe by clap
+    let pvers = matches
+        .get_one::<String>("package_version")
+        .map(|s| s.to_owned())
+        .map(PackageVersionConstraint::try_from)
+        .transpose()?;
+    let image_name = matches
+        .get_one::<String>("image")
+        .map(|s| s.to_owned())
+        .map(ImageName::from);
+    let timeout = matches.get_one::<String>("timeout")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .context("Parsing timeout argument to integer")?;
+
+    let condition_data = ConditionData {
+        image_name: image_name.as_ref(),
+        env: &[],
+    };
+
+    let roots = repo.find_by_name(&pname)
+        .into_iter()
+        .filter(|p| pvers.as_ref().map(|v| v.matches(p.version())).unwrap_or(true))
+        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        return Err(anyhow!("No package found: {} {}", pname, pvers.map(|v| v.to_string()).unwrap_or_default()))
+    }
+
+    let mut packages = BTreeSet::new();
+    for root in roots {
+        let dag = Dag::for_root_package(root.clone(), &repo, None, &condition_data)?;
+        for package in dag.all_packages() {
+            packages.insert((package.name().clone(), package.version().clone()));
+        }
+    }
+    debug!("Auditing {} packages", packages.len());
+
+    let client = {
+        let builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::limited(10));
+
+        if let Some(to) = timeout {
+            builder.timeout(std::time::Duration::from_secs(to))
+        } else {
+            builder
+        }
+    }
+    .build()
+    .context("Building HTTP client failed")?;
+
+    let sema = Arc::new(tokio::sync::Semaphore::new(NUMBER_OF_MAX_CONCURRENT_OSV_QUERIES));
+    let mut audited = packages
+        .into_iter()
+        .map(|(name, version)| {
+            let client = client.clone();
+            let sema = sema.clone();
+            async move {
+                let osv_package = config.audit().osv_package(&name);
+                let vulnerabilities = match osv_package.as_ref() {
+                    Some((ecosystem, osv_name)) => {
+                        let _permit = sema.acquire_owned().await?;
+                        query_vulnerabilities(&client, config.audit().osv_url(), ecosystem, osv_name, &version)
+                            .await
+                            .with_context(|| anyhow!("Querying vulnerabilities of {} {}", name, version))?
+                    },
+                    None => Vec::new(),
+                };
+
+                let (ecosystem, osv_name) = match osv_package {
+                    Some((ecosystem, osv_name)) => (Some(ecosystem), Some(osv_name)),
+                    None => (None, None),
+                };
+                Ok(AuditedPackage { name, version, ecosystem, osv_name, vulnerabilities }) as Result<_>
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Result<Vec<AuditedPackage>>>()
+        .await?;
+    audited.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    let unchecked = audited.iter().filter(|p| p.ecosystem.is_none()).count();
+    let n_vulnerable = audited.iter().filter(|p| !p.vulnerabilities.is_empty()).count();
+    let n_vulnerabilities = audited.iter().map(|p| p.vulnerabilities.len()).sum::<usize>();
+
+    if json {
+        writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&audited)?)?;
+    } else {
+        let header = crate::commands::util::mk_header(["Name", "Version", "Vulnerability", "Aliases", "Summary"].to_vec());
+        let data = audited
+            .iter()
+            .flat_map(|p| {
+                p.vulnerabilities.iter().map(move |v| {
+                    vec![
+                        p.name.to_string(),
+                        p.version.to_string(),
+                        v.id.clone(),
+                        v.aliases.join(", "),
+                        v.summary.clone().unwrap_or_default(),
+                    ]
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if data.is_empty() {
+            writeln!(std::io::stdout(), "No known vulnerabilities")?;
+        } else {
+            crate::commands::util::display_data(header, data, csv)?;
+        }
+    }
+
+    if unchecked > 0 {
+        warn!("{} packages were not checked, they have no ecosystem in the audit configuration", unchecked);
+    }
+    if n_vulnerabilities > 0 {
+        Err(anyhow!("Found {} known vulnerabilities in {} packages", n_vulnerabilities, n_vulnerable))
+    } else {
+        Ok(())
+    }
+}
+
+/// Query the vulnerabilities of a package version from the OSV API, following the pagination
+async fn query_vulnerabilities(
+    client: &reqwest::Client,
+    url: &str,
+    ecosystem: &str,
+    name: &str,
+    version: &PackageVersion,
+) -> Result<Vec<Vulnerability>> {
+    let mut vulnerabilities = Vec::new();
+    let mut page_token = None;
+
+    loop {
+        let mut query = serde_json::json!({
+            "package": { "ecosystem": ecosystem, "name": name },
+            "version": version.as_str(),
+        });
+        if let Some(token) = page_token.take() {
+            query["page_token"] = serde_json::Value::String(token);
+        }
+
+        trace!("Querying {} with {}", url, query);
+        let body = client.post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(query.to_string())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Querying '{}'", url))?
+            .text()
+            .await
+            .with_context(|| anyhow!("Reading response from '{}'", url))?;
+
+        let (page, next_page_token) = parse_response(&body)?;
+        vulnerabilities.extend(page);
+        match next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+    }
+
+    Ok(vulnerabilities)
+}
+
+/// The vulnerabilities and the token of the next page from a response of the OSV query API
+fn parse_response(body: &str) -> Result<(Vec<Vulnerability>, Option<String>)> {
+    let json = serde_json::from_str::<serde_json::Value>(body).context("Parsing OSV response")?;
+
+    let vulnerabilities = json.get("vulns")
+        .and_then(serde_json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|vuln| {
+            let id = vuln.get("id")
+                .and_then(serde_json::Value::as_str)
+                .ok_or_else(|| anyhow!("Vulnerability without id in OSV response"))?
+                .to_string();
+            let aliases = vuln.get("aliases")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(serde_json::Value::as_str)
+                .map(String::from)
+                .collect();
+            let summary = vuln.get("summary")
+                .and_then(serde_json::Value::as_str)
+                .map(String::from);
+            Ok(Vulnerability { id, aliases, summary })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let next_page_token = json.get("next_page_token")
+        .and_then(serde_json::Value::as_str)
+        .filter(|token| !token.is_empty())
+        .map(String::from);
+
+    Ok((vulnerabilities, next_page_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let body = r#"{
+            "vulns": [
+                {
+                    "id": "DSA-5532-1",
+                    "summary": "openssl - security update",
+                    "aliases": ["CVE-2023-5363", "CVE-2023-5678"],
+                    "modified": "2023-10-30T00:00:00Z"
+                },
+                { "id": "GHSA-xxxx-yyyy-zzzz" }
+            ],
+            "next_page_token": "abc"
+        }"#;
+
+        let (vulnerabilities, next) = parse_response(body).unwrap();
+        assert_eq!(vulnerabilities, vec![
+            Vulnerability {
+                id: String::from("DSA-5532-1"),
+                aliases: vec![String::from("CVE-2023-5363"), String::from("CVE-2023-5678")],
+                summary: Some(String::from("openssl - security update")),
+            },
+            Vulnerability {
+                id: String::from("GHSA-xxxx-yyyy-zzzz"),
+                aliases: vec![],
+                summary: None,
+            },
+        ]);
+        assert_eq!(next.as_deref(), Some("abc"));
+
+        assert_eq!(parse_response("{}").unwrap(), (vec![], None));
+        assert!(parse_response(r#"{ "vulns": [{ "summary": "no id" }] }"#).is_err());
+        assert!(parse_response("not json").is_err());
+    }
+}
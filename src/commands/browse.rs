@@ -0,0 +1,499 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'browse' subcommand
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use colored::Colorize;
+use console::Key;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use itertools::Itertools;
+
+use crate::config::Configuration;
+use crate::db::models;
+use crate::db::DbConnectionConfig;
+use crate::log::JobResult;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::Package;
+use crate::repository::Repository;
+use crate::schema;
+use crate::ui::*;
+
+/// How many builds of a package are shown in the builds view
+const RECENT_BUILDS: i64 = 20;
+
+/// The maximum width of the list of packages
+const LIST_WIDTH: usize = 40;
+
+const HELP: &str = "Type to search  Up/Down select  Tab switch view  PgUp/PgDn scroll  Esc quit";
+
+/// Implementation of the "browse" subcommand
+///
+/// Shows the search, the matching packages and the definition, dependency tree or recent builds
+/// of the selected package on one screen, until Esc (or Ctrl-C) is pressed.
+pub async fn browse(
+    config: &Configuration,
+    repo: Repository,
+    db_connection_config: DbConnectionConfig<'_>,
+) -> Result<()> {
+    if !crate::ui::is_interactive() || !atty::is(atty::Stream::Stdout) {
+        return Err(anyhow!("The browser can only be used in an interactive terminal"));
+    }
+
+    let term = console::Term::stdout();
+    let mut browser = Browser::new(&repo);
+
+    // The database is only connected to when builds are shown, so browsing packages works
+    // without a database
+    let mut conn: Option<PgConnection> = None;
+
+    // The details are cached, so moving through the list does not load them again
+    let mut details: HashMap<(String, String, View), Vec<String>> = HashMap::new();
+
+    term.hide_cursor()?;
+    let r = loop {
+        let (height, width) = term.size();
+        let (height, width) = (height as usize, width as usize);
+
+        let detail = match browser.selected() {
+            Some(package) => details
+                .entry((package.name().to_string(), package.version().to_string(), browser.view))
+                .or_insert_with(|| {
+                    load_detail(config, &repo, &db_connection_config, &mut conn, package, browser.view)
+                        .unwrap_or_else(|e| vec![format!("{}: {:#}", "Error".red(), e)])
+                })
+                .as_slice(),
+            None => &[],
+        };
+
+        term.clear_screen()?;
+        term.write_str(&browser.render(detail, width, height).join("\n"))?;
+
+        match term.read_key() {
+            Ok(key) => if !browser.handle_key(key, Browser::body_height(height)) {
+                break Ok(())
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => break Ok(()),
+            Err(e) => break Err(Error::from(e)),
+        }
+    };
+
+    term.clear_screen()?;
+    term.show_cursor()?;
+    r
+}
+
+/// What the detail pane shows about the selected package
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum View {
+    Definition,
+    Tree,
+    Builds,
+}
+
+impl View {
+    const ALL: [View; 3] = [View::Definition, View::Tree, View::Builds];
+
+    fn title(self) -> &'static str {
+        match self {
+            View::Definition => "Definition",
+            View::Tree => "Dependency tree",
+            View::Builds => "Recent builds",
+        }
+    }
+
+    fn next(self) -> View {
+        match self {
+            View::Definition => View::Tree,
+            View::Tree => View::Builds,
+            View::Builds => View::Definition,
+        }
+    }
+
+    fn previous(self) -> View {
+        self.next().next()
+    }
+}
+
+/// The state of the browser: the search, the matching packages, the selected one and the view
+/// on it
+struct Browser<'a> {
+    repo: &'a Repository,
+    search: String,
+    matches: Vec<&'a Package>,
+
+    /// The error of an invalid search regex
+    error: Option<String>,
+    selected: usize,
+    view: View,
+
+    /// The first shown line of the detail pane
+    scroll: usize,
+}
+
+impl<'a> Browser<'a> {
+    /// The browser with an empty search, which matches all packages
+    fn new(repo: &'a Repository) -> Self {
+        let mut browser = Browser {
+            repo,
+            search: String::new(),
+            matches: Vec::new(),
+            error: None,
+            selected: 0,
+            view: View::Definition,
+            scroll: 0,
+        };
+        browser.update_matches();
+        browser
+    }
+
+    fn selected(&self) -> Option<&'a Package> {
+        self.matches.get(self.selected).copied()
+    }
+
+    /// The number of lines of the list and the detail pane on a terminal of `height` lines
+    fn body_height(height: usize) -> usize {
+        height.saturating_sub(3)
+    }
+
+    fn update_matches(&mut self) {
+        match search_packages(self.repo, &self.search) {
+            Ok(matches) => {
+                self.matches = matches;
+                self.error = None;
+            },
+
+            // Keep the matches of the last valid search while the regex is being typed
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// Handle a key press, `page` is the number of lines the detail pane scrolls per page
+    ///
+    /// Returns false if the browser should quit.
+    fn handle_key(&mut self, key: Key, page: usize) -> bool {
+        match key {
+            Key::Escape => return false,
+            Key::Char(c) if !c.is_control() => {
+                self.search.push(c);
+                self.update_matches();
+            },
+            Key::Backspace if self.search.pop().is_some() => self.update_matches(),
+            Key::ArrowUp if self.selected > 0 => {
+                self.selected -= 1;
+                self.scroll = 0;
+            },
+            Key::ArrowDown if self.selected + 1 < self.matches.len() => {
+                self.selected += 1;
+                self.scroll = 0;
+            },
+            Key::Tab => {
+                self.view = self.view.next();
+                self.scroll = 0;
+            },
+            Key::BackTab => {
+                self.view = self.view.previous();
+                self.scroll = 0;
+            },
+            Key::PageDown => self.scroll += page.max(1),
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(page.max(1)),
+            _ => {},
+        }
+        true
+    }
+
+    /// Render the browser into `height` lines of at most `width` characters, with the `detail`
+    /// lines of the selected package in the detail pane
+    fn render(&self, detail: &[String], width: usize, height: usize) -> Vec<String> {
+        let status = match self.error.as_ref() {
+            Some(e) => format!("invalid search: {}", e),
+            None => format!("{} packages", self.matches.len()),
+        };
+        let views = View::ALL
+            .iter()
+            .map(|v| if *v == self.view { format!("[{}]", v.title()) } else { v.title().to_string() })
+            .join("  ");
+
+        let body_height = Browser::body_height(height);
+        let list_width = (width / 3).min(LIST_WIDTH);
+        let detail_width = width.saturating_sub(list_width + 3);
+
+        // Keep the selected package in the visible part of the list
+        let list_start = (self.selected + 1).saturating_sub(body_height);
+        let list = self.matches
+            .iter()
+            .enumerate()
+            .skip(list_start)
+            .map(|(i, p)| format!("{} {} {}", if i == self.selected { ">" } else { " " }, p.name(), p.version()));
+
+        // The detail pane cannot be scrolled past its last line
+        let scroll = self.scroll.min(detail.len().saturating_sub(1));
+        let detail_lines = std::iter::once(views).chain(detail.iter().skip(scroll).cloned());
+
+        let body = list
+            .map(Some)
+            .chain(std::iter::repeat(None))
+            .zip(detail_lines.map(Some).chain(std::iter::repeat(None)))
+            .take(body_height)
+            .map(|(left, right)| {
+                let left = console::pad_str(left.as_deref().unwrap_or(""), list_width, console::Alignment::Left, Some(""));
+                let right = right.unwrap_or_default().replace('\t', "    ");
+                format!("{} | {}", left, console::truncate_str(&right, detail_width, ""))
+            });
+
+        std::iter::once(format!("Search: {}", self.search))
+            .chain(std::iter::once(status))
+            .chain(body)
+            .chain(std::iter::once(HELP.to_string()))
+            .take(height)
+            .map(|line| console::truncate_str(&line, width, "").trim_end().to_string())
+            .collect()
+    }
+}
+
+/// The packages whose name matches the `search` regex, sorted by name and version
+fn search_packages<'a>(repo: &'a Repository, search: &str) -> Result<Vec<&'a Package>> {
+    let regex = crate::commands::util::mk_package_name_regex(search)?;
+
+    Ok(repo
+        .packages()
+        .filter(|p| regex.captures(p.name()).is_some())
+        .sorted_by(|a, b| a.name().cmp(b.name()).then_with(|| a.version().cmp(b.version())))
+        .collect())
+}
+
+/// Load the lines of the `view` on the `package`
+fn load_detail(
+    config: &Configuration,
+    repo: &Repository,
+    db_connection_config: &DbConnectionConfig<'_>,
+    conn: &mut Option<PgConnection>,
+    package: &Package,
+    view: View,
+) -> Result<Vec<String>> {
+    let text = match view {
+        View::Definition => definition(config, package)?,
+        View::Tree => tree(repo, package)?,
+        View::Builds => {
+            if conn.is_none() {
+                *conn = Some(db_connection_config.clone().establish_connection()?);
+            }
+
+            // unwrap is safe because the connection was set above
+            recent_builds(conn.as_ref().unwrap(), package)?
+        },
+    };
+
+    Ok(text.lines().map(String::from).collect())
+}
+
+fn definition(config: &Configuration, package: &Package) -> Result<String> {
+    let flags = PackagePrintFlags {
+        print_all: true,
+        print_runtime_deps: true,
+        print_build_deps: true,
+        print_sources: true,
+        print_dependencies: true,
+        print_patches: true,
+        print_env: true,
+        print_flags: true,
+        print_allowed_images: true,
+        print_denied_images: true,
+        print_phases: true,
+        print_script: true,
+        script_line_numbers: true,
+        script_highlighting: true,
+    };
+
+    let hb = handlebars_for_package_printing(config.package_print_format())?;
+    let printable = package.prepare_print(config, &flags, &hb, 0).into_displayable()?;
+    Ok(printable.to_string())
+}
+
+fn tree(repo: &Repository, package: &Package) -> Result<String> {
+    let condition_data = ConditionData {
+        image_name: None,
+        env: &[],
+    };
+
+    let tree = Dag::for_root_package(package.clone(), repo, None, &condition_data)
+        .with_context(|| anyhow!("Building the dependency tree of {} {}", package.name(), package.version()))?;
+
+    let mut out = Vec::new();
+    ptree::write_tree(&tree.display(), &mut out)?;
+    String::from_utf8(out).map_err(Error::from)
+}
+
+fn recent_builds(conn: &PgConnection, package: &Package) -> Result<String> {
+    let jobs = schema::jobs::table
+        .inner_join(schema::submits::table)
+        .inner_join(schema::packages::table)
+        .inner_join(schema::images::table)
+        .filter(schema::packages::name.eq(package.name().as_ref() as &str))
+        .filter(schema::packages::version.eq(package.version().as_ref() as &str))
+        .order_by(schema::jobs::id.desc())
+        .limit(RECENT_BUILDS)
+        .select((schema::jobs::all_columns, schema::submits::all_columns, schema::images::all_columns))
+        .load::<(models::Job, models::Submit, models::Image)>(conn)
+        .with_context(|| anyhow!("Loading the builds of {} {}", package.name(), package.version()))?;
+
+    if jobs.is_empty() {
+        return Ok(format!("No builds of {} {} found", package.name(), package.version()))
+    }
+
+    jobs.into_iter()
+        .map(|(job, submit, image)| {
+            let result = match crate::log::ParsedLog::from_str(&job.log_text)?.is_successfull() {
+                JobResult::Success => "Success".green(),
+                JobResult::Errored => "Error".red(),
+                JobResult::Unknown => "Unknown".yellow(),
+            };
+
+            Ok(format!("{} {} {} {} {}", submit.submit_time, result, image.name, submit.uuid, job.uuid))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|lines| lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    fn repo(packages: &[(&str, &str)]) -> Repository {
+        let btree = packages
+            .iter()
+            .map(|(name, vers)| ((pname(name), pversion(vers)), package(name, vers, "https://rust-lang.org", "123")))
+            .collect::<BTreeMap<_, _>>();
+        Repository::from(btree)
+    }
+
+    fn found(packages: Vec<&Package>) -> Vec<(String, String)> {
+        packages
+            .into_iter()
+            .map(|p| (p.name().to_string(), p.version().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_packages() {
+        let repo = repo(&[("foo", "2"), ("foo", "1"), ("foobar", "1"), ("bar", "1")]);
+
+        let expected = vec![
+            (String::from("foo"), String::from("1")),
+            (String::from("foo"), String::from("2")),
+            (String::from("foobar"), String::from("1")),
+        ];
+        assert_eq!(found(search_packages(&repo, "foo").unwrap()), expected);
+        assert_eq!(found(search_packages(&repo, "^bar$").unwrap()), vec![(String::from("bar"), String::from("1"))]);
+        assert!(search_packages(&repo, "baz").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_packages_invalid_regex() {
+        let repo = repo(&[("foo", "1")]);
+        assert!(search_packages(&repo, "foo(").is_err());
+    }
+
+    #[test]
+    fn test_browser_keys() {
+        let repo = repo(&[("foo", "1"), ("foobar", "1"), ("bar", "1")]);
+        let mut browser = Browser::new(&repo);
+        let selected = |b: &Browser<'_>| b.selected().map(|p| p.name().to_string());
+
+        // the empty search matches all packages
+        assert_eq!(browser.matches.len(), 3);
+        assert_eq!(selected(&browser).as_deref(), Some("bar"));
+
+        assert!(browser.handle_key(Key::Char('f'), 10));
+        assert_eq!(browser.matches.len(), 2);
+        assert_eq!(selected(&browser).as_deref(), Some("foo"));
+
+        // the selection stays in the matches
+        assert!(browser.handle_key(Key::ArrowDown, 10));
+        assert!(browser.handle_key(Key::ArrowDown, 10));
+        assert_eq!(selected(&browser).as_deref(), Some("foobar"));
+        assert!(browser.handle_key(Key::ArrowUp, 10));
+        assert!(browser.handle_key(Key::ArrowUp, 10));
+        assert_eq!(selected(&browser).as_deref(), Some("foo"));
+
+        // an invalid regex keeps the last matches
+        assert!(browser.handle_key(Key::Char('('), 10));
+        assert!(browser.error.is_some());
+        assert_eq!(browser.matches.len(), 2);
+        assert!(browser.handle_key(Key::Backspace, 10));
+        assert!(browser.error.is_none());
+
+        assert!(browser.handle_key(Key::Tab, 10));
+        assert_eq!(browser.view, View::Tree);
+        assert!(browser.handle_key(Key::BackTab, 10));
+        assert!(browser.handle_key(Key::BackTab, 10));
+        assert_eq!(browser.view, View::Builds);
+
+        assert!(browser.handle_key(Key::PageDown, 10));
+        assert_eq!(browser.scroll, 10);
+        assert!(browser.handle_key(Key::PageUp, 10));
+        assert!(browser.handle_key(Key::PageUp, 10));
+        assert_eq!(browser.scroll, 0);
+
+        // a search without matches has no selection
+        assert!(browser.handle_key(Key::Char('x'), 10));
+        assert_eq!(selected(&browser), None);
+
+        assert!(!browser.handle_key(Key::Escape, 10));
+    }
+
+    #[test]
+    fn test_browser_render() {
+        let repo = repo(&[("foo", "1"), ("foo", "2"), ("bar", "1")]);
+        let mut browser = Browser::new(&repo);
+        browser.handle_key(Key::Char('f'), 10);
+        browser.handle_key(Key::ArrowDown, 10);
+        let detail = vec![String::from("name: foo"), String::from("version:\t2")];
+
+        let lines = browser.render(&detail, 60, 8);
+        assert_eq!(lines, vec![
+            "Search: f",
+            "2 packages",
+            "  foo 1              | [Definition]  Dependency tree  Recent",
+            "> foo 2              | name: foo",
+            "                     | version:    2",
+            "                     |",
+            "                     |",
+            "Type to search  Up/Down select  Tab switch view  PgUp/PgDn s",
+        ]);
+
+        // the detail pane is scrolled, but not past its last line
+        browser.handle_key(Key::Tab, 10);
+        browser.scroll = 5;
+        let lines = browser.render(&detail, 60, 8);
+        assert_eq!(lines[2], "  foo 1              | Definition  [Dependency tree]  Recent");
+        assert_eq!(lines[3], "> foo 2              | version:    2");
+        assert_eq!(lines[4], "                     |");
+
+        // the selected package is kept visible in a small terminal
+        let lines = browser.render(&detail, 30, 4);
+        assert_eq!(lines, vec!["Search: f", "2 packages", "> foo 2    | Definition  [Depe", "Type to search  Up/Down select"]);
+    }
+}
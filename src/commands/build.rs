@@ -10,7 +10,9 @@
 
 //! Implementation of the 'build' subcommand
 
+use std::collections::HashMap;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -23,7 +25,7 @@ use anyhow::Result;
 use clap::ArgMatches;
 use colored::Colorize;
 use diesel::ExpressionMethods;
-use diesel::PgConnection;
+use diesel::OptionalExtension;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use itertools::Itertools;
@@ -32,12 +34,21 @@ use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
+use crate::commands::build_manifest::BuildManifest;
 use crate::config::*;
+use crate::db::DbConnectionConfig;
+use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::filestore::path::StoreRoot;
 use crate::job::JobResource;
+use crate::log::LogFiles;
+use crate::log::LogForwarder;
+use crate::log::LogHighlighter;
 use crate::log::LogItem;
+use crate::notification::Notification;
+use crate::notification::Notifier;
+use crate::orchestrator::ContinuousRelease;
 use crate::orchestrator::OrchestratorSetup;
 use crate::package::Dag;
 use crate::package::PackageName;
@@ -57,7 +68,7 @@ pub async fn build(
     repo_root: &Path,
     matches: &ArgMatches,
     progressbars: ProgressBars,
-    database_connection: PgConnection,
+    db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
     repo: Repository,
     repo_path: &Path,
@@ -69,19 +80,110 @@ pub async fn build(
 
     crate::ui::package_repo_cleanness_check(&git_repo)?;
     let now = chrono::offset::Local::now().naive_local();
+    let json_lines = matches.get_one::<String>("output").map(|o| o == "json-lines").unwrap_or(false);
+
+    let (manifest, manifest_content) = match matches.get_one::<String>("manifest") {
+        Some(path) => {
+            let (manifest, content) = BuildManifest::load(Path::new(path))?;
+            (Some(manifest), Some(content))
+        },
+        None => (None, None),
+    };
 
     let shebang = Shebang::from({
         matches
             .get_one::<String>("shebang")
             .map(|s| s.to_owned())
+            .or_else(|| manifest.as_ref().and_then(|m| m.shebang().clone()))
             .unwrap_or_else(|| config.shebang().clone())
     });
 
-    let image_name = matches
-        .get_one::<String>("image")
-        .map(|s| s.to_owned())
-        .map(ImageName::from)
-        .unwrap(); // safe by clap
+    debug!("Getting repository HEAD");
+    let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
+    trace!("Repository HEAD = {}", hash_str);
+    let phases = config.available_phases();
+
+    // The package from the CLI replaces the package from the manifest, including the version
+    let (pname, pvers) = match matches.get_one::<String>("package_name") {
+        Some(name) => {
+            let pvers = matches
+                .get_one::<String>("package_version")
+                .map(|s| s.to_owned())
+                .map(PackageVersion::from);
+
+            (PackageName::from(name.to_owned()), pvers)
+        },
+        None => {
+            let package = manifest.as_ref()
+                .and_then(|m| m.package().as_ref())
+                .ok_or_else(|| anyhow!("No package passed and no package in the manifest"))?;
+
+            (package.name().clone(), package.version().clone())
+        },
+    };
+    info!("We want {} ({:?})", pname, pvers);
+
+    let flavor = matches.get_one::<String>("flavor")
+        .or_else(|| manifest.as_ref().and_then(|m| m.flavor().as_ref()))
+        .map(|s| s.as_str());
+    let repo = if let Some(flavor) = flavor {
+        info!("Building flavor {}", flavor);
+        repo.with_flavor(flavor)
+    } else {
+        repo
+    };
+
+    // The features from the CLI replace the features from the manifest
+    let features = match matches.get_many::<String>("with") {
+        Some(features) => features.cloned().collect::<Vec<String>>(),
+        None => manifest.as_ref().map(|m| m.features().clone()).unwrap_or_default(),
+    };
+    let repo = if !features.is_empty() {
+        info!("Building with features {}", features.join(", "));
+        repo.with_features(&features)
+    } else {
+        repo
+    };
+
+    let packages = if let Some(pvers) = pvers {
+        debug!("Searching for package with version: '{}' '{}'", pname, pvers);
+        repo.find(&pname, &pvers)
+    } else {
+        debug!("Searching for package by name: '{}'", pname);
+        repo.find_by_name(&pname)
+    };
+    debug!("Found {} relevant packages", packages.len());
+
+    // We only support building one package per call.
+    // Everything else is invalid
+    if packages.len() > 1 {
+        return Err(anyhow!(
+            "Found multiple packages ({}). Cannot decide which one to build",
+            packages.len()
+        ));
+    }
+    let package = *packages
+        .get(0)
+        .ok_or_else(|| anyhow!("Found no package."))?;
+
+    if let Some(flavor) = flavor {
+        if !package.flavors().contains_key(flavor) {
+            return Err(anyhow!(
+                "Package {} {} has no flavor '{}'",
+                package.name(),
+                package.version(),
+                flavor
+            ));
+        }
+    }
+
+    let image_name = match matches.get_one::<String>("image").or_else(|| manifest.as_ref().and_then(|m| m.image().as_ref())) {
+        Some(image) => ImageName::from(image.to_owned()),
+        None => find_default_image(config, package)?
+            .ok_or_else(|| anyhow!("No image passed and no default image configured for {} {}", package.name(), package.version()))?,
+    };
+    info!("Building on image {}", image_name);
+
     if config.docker().verify_images_present()
         && !config
             .docker()
@@ -97,11 +199,6 @@ pub async fn build(
         .map_err(Error::from);
     }
 
-    debug!("Getting repository HEAD");
-    let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
-    trace!("Repository HEAD = {}", hash_str);
-    let phases = config.available_phases();
-
     let mut endpoint_configurations = config
         .docker()
         .endpoints()
@@ -125,44 +222,103 @@ pub async fn build(
     }
     info!("Endpoint config build");
 
-    let pname = matches
-        .get_one::<String>("package_name")
-        .map(|s| s.to_owned())
-        .map(PackageName::from)
-        .unwrap(); // safe by clap
+    let additional_env = {
+        let cli_env = matches
+            .get_many::<String>("env")
+            .unwrap_or_default()
+            .map(|s| crate::util::env::parse_to_env(s.as_ref()))
+            .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
 
-    let pvers = matches
-        .get_one::<String>("package_version")
-        .map(|s| s.to_owned())
-        .map(PackageVersion::from);
-    info!("We want {} ({:?})", pname, pvers);
+        // Variables from the CLI override the variables from the manifest
+        manifest.as_ref()
+            .map(|m| m.env().iter())
+            .into_iter()
+            .flatten()
+            .filter(|(k, _)| !cli_env.iter().any(|(cli_k, _)| cli_k == *k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .chain(cli_env.iter().cloned())
+            .collect::<Vec<(EnvironmentVariableName, String)>>()
+    };
 
-    let additional_env = matches
-        .get_many::<String>("env")
-        .unwrap_or_default()
-        .map(|s| crate::util::env::parse_to_env(s.as_ref()))
-        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+    let dag = {
+        let bar_tree_building = progressbars.bar()?;
+        let condition_data = ConditionData {
+            image_name: Some(&image_name),
+            env: &additional_env,
+        };
 
-    let packages = if let Some(pvers) = pvers {
-        debug!("Searching for package with version: '{}' '{}'", pname, pvers);
-        repo.find(&pname, &pvers)
-    } else {
-        debug!("Searching for package by name: '{}'", pname);
-        repo.find_by_name(&pname)
+        let dag = Dag::for_root_package(package.clone(), &repo, Some(&bar_tree_building), &condition_data)?;
+        bar_tree_building.finish_with_message("Finished loading Dag");
+        dag
     };
-    debug!("Found {} relevant packages", packages.len());
 
-    // We only support building one package per call.
-    // Everything else is invalid
-    if packages.len() > 1 {
+    let deny_deprecated = matches.get_flag("deny_deprecated") || manifest.as_ref().map(|m| *m.deny_deprecated()).unwrap_or(false);
+    dag.check_deprecated(deny_deprecated)?;
+
+    if let Some(feature) = features.iter().find(|f| !dag.all_packages().iter().any(|p| p.features().contains_key(*f))) {
         return Err(anyhow!(
-            "Found multiple packages ({}). Cannot decide which one to build",
-            packages.len()
+            "No package in the tree of {} {} has the feature '{}'",
+            package.name(),
+            package.version(),
+            feature
         ));
     }
-    let package = *packages
-        .get(0)
-        .ok_or_else(|| anyhow!("Found no package."))?;
+
+    dag.all_packages()
+        .into_iter()
+        .map(|pkg| {
+            if let Some(allowlist) = pkg.allowed_images() {
+                if !allowlist.contains(&image_name) {
+                    return Err(anyhow!(
+                        "Package {} {} is only allowed on: {}",
+                        pkg.name(),
+                        pkg.version(),
+                        allowlist.iter().join(", ")
+                    ));
+                }
+            }
+
+            if let Some(deniedlist) = pkg.denied_images() {
+                if deniedlist.contains(&image_name) {
+                    return Err(anyhow!(
+                        "Package {} {} is not allowed to be built on {}",
+                        pkg.name(),
+                        pkg.version(),
+                        image_name
+                    ));
+                }
+            }
+
+            Ok(())
+        })
+        .collect::<Result<Vec<()>>>()?;
+
+    if matches.get_flag("dry_run") {
+        let resources: Vec<JobResource> = additional_env.into_iter().map(JobResource::from).collect();
+        let jobdag = crate::job::Dag::from_package_dag(dag, shebang, image_name, phases.clone(), resources);
+
+        // The checks that are done before a job is run, the plan of a build that would fail is not printed
+        let containers = config.containers();
+        let git_env = containers.git_author().iter().chain(containers.git_commit_hash().iter()).collect::<Vec<_>>();
+        jobdag.iter().try_for_each(|definition| crate::job::check_env(definition.job, containers, git_env.iter().copied()))?;
+
+        return print_build_plan(&jobdag, &endpoint_configurations)
+    }
+
+    let database_connection = db_connection_config.establish_connection()?;
+
+    let continuous_release = matches.get_one::<String>("continuous_release")
+        .map(|store_name| {
+            if !config.release_stores().contains(store_name) {
+                return Err(anyhow!("Not a configured release store: {}", store_name))
+            }
+
+            Ok(ContinuousRelease {
+                directory: config.releases_directory().join(store_name),
+                store: crate::db::models::ReleaseStore::create(&database_connection, store_name)?,
+            })
+        })
+        .transpose()?;
 
     let release_stores = config
         .release_stores()
@@ -225,33 +381,23 @@ pub async fn build(
         r.map(RwLock::new).map(Arc::new).map(|store| (store, p, submit_id))?
     };
 
-    let dag = {
-        let bar_tree_building = progressbars.bar()?;
-        let condition_data = ConditionData {
-            image_name: Some(&image_name),
-            env: &additional_env,
-        };
-
-        let dag = Dag::for_root_package(package.clone(), &repo, Some(&bar_tree_building), &condition_data)?;
-        bar_tree_building.finish_with_message("Finished loading Dag");
-        dag
-    };
-
     let source_cache = SourceCache::new(config.source_cache_root().clone());
 
-    if matches.get_flag("no_verification") {
+    if matches.get_flag("no_verification") || manifest.as_ref().map(|m| *m.no_verification()).unwrap_or(false) {
         warn!("No hash verification will be performed");
     } else {
         crate::commands::source::verify_impl(
             dag.all_packages().into_iter(),
             &source_cache,
             &progressbars,
+            false,
+            crate::commands::source::default_verification_jobs(),
         )
         .await?;
     }
 
     // linting the package scripts
-    if matches.get_flag("no_lint") {
+    if matches.get_flag("no_lint") || manifest.as_ref().map(|m| *m.no_lint()).unwrap_or(false) {
         warn!("No script linting will be performed!");
     } else if let Some(linter) = crate::ui::find_linter_command(repo_root, config)? {
         let all_packages = dag.all_packages();
@@ -265,34 +411,14 @@ pub async fn build(
         warn!("No linter set in configuration, no script linting will be performed!");
     } // linting
 
-    dag.all_packages()
-        .into_iter()
-        .map(|pkg| {
-            if let Some(allowlist) = pkg.allowed_images() {
-                if !allowlist.contains(&image_name) {
-                    return Err(anyhow!(
-                        "Package {} {} is only allowed on: {}",
-                        pkg.name(),
-                        pkg.version(),
-                        allowlist.iter().join(", ")
-                    ));
-                }
-            }
-
-            if let Some(deniedlist) = pkg.denied_images() {
-                if deniedlist.iter().any(|denied| image_name == *denied) {
-                    return Err(anyhow!(
-                        "Package {} {} is not allowed to be built on {}",
-                        pkg.name(),
-                        pkg.version(),
-                        image_name
-                    ));
-                }
-            }
-
-            Ok(())
-        })
-        .collect::<Result<Vec<()>>>()?;
+    crate::hooks::run_required_hooks(config.hooks(), HookEvent::PreSubmit, &[
+        ("SUBMIT_UUID", submit_id.to_string()),
+        ("PACKAGE_NAME", package.name().to_string()),
+        ("PACKAGE_VERSION", package.version().to_string()),
+        ("IMAGE", image_name.to_string()),
+        ("GIT_HASH", hash_str.clone()),
+    ])
+    .await?;
 
     trace!("Setting up database jobs for Package, GitHash, Image");
     let db_package = async { Package::create_or_fetch(&database_connection, package) };
@@ -319,6 +445,17 @@ pub async fn build(
     let (db_package, db_githash, db_image, _) = (db_package?, db_githash?, db_image?, db_envs?);
 
     trace!("Database jobs for Package, GitHash, Image finished successfully");
+    let notifier = Arc::new(Notifier::new(config.webhooks(), config.notifications())?);
+
+    let (log_forwarder, log_forwarding) = if config.log_sinks().is_empty() {
+        (None, None)
+    } else {
+        let (forwarder, forwarding) = LogForwarder::spawn(config.log_sinks())
+            .await
+            .context("Setting up the forwarding of the logs")?;
+        (Some(forwarder), Some(forwarding))
+    };
+
     trace!("Creating Submit in database");
     let submit = Submit::create(
         &database_connection,
@@ -327,13 +464,29 @@ pub async fn build(
         &db_image,
         &db_package,
         &db_githash,
+        flavor,
+        &features,
+        manifest_content.as_deref(),
+        Some(crate::cli::VERSION),
+        Some(env!("VERGEN_GIT_SHA")),
+        config.fingerprint(),
     )?;
     trace!(
         "Creating Submit in database finished successfully: {:?}",
         submit
     );
 
-    {
+    if json_lines {
+        let event = serde_json::json!({
+            "event": "submit_started",
+            "time": chrono::Utc::now().to_rfc3339(),
+            "submit": submit_id.to_string(),
+            "package": format!("{} {}", db_package.name, db_package.version),
+            "image": db_image.name,
+            "githash": db_githash.hash,
+        });
+        writeln!(std::io::stdout(), "{event}")?;
+    } else {
         let out = std::io::stdout();
         let mut outlock = out.lock();
 
@@ -349,8 +502,21 @@ pub async fn build(
             p = mkgreen(&db_package.name),
             v = mkgreen(&db_package.version))?;
         writeln!(outlock, "On repo hash:    {}", mkgreen(&db_githash.hash))?;
+        if let Some(flavor) = submit.flavor.as_ref() {
+            writeln!(outlock, "Flavor:          {}", mkgreen(flavor))?;
+        }
+        if !submit.features.is_empty() {
+            writeln!(outlock, "Features:        {}", mkgreen(&submit.features.join(", ")))?;
+        }
+        if let Some(manifest) = manifest.as_ref() {
+            for (key, value) in manifest.labels() {
+                writeln!(outlock, "Label:           {}={}", mkgreen(key), mkgreen(value))?;
+            }
+        }
     }
 
+    notifier.notify(&Notification::submit_started(submit_id, package)).await;
+
     trace!("Setting up job sets");
     let resources: Vec<JobResource> = additional_env.into_iter().map(JobResource::from).collect();
     let jobdag = crate::job::Dag::from_package_dag(dag, shebang, image_name, phases.clone(), resources);
@@ -358,16 +524,48 @@ pub async fn build(
 
     trace!("Setting up Orchestrator");
     let database_connection = Arc::new(database_connection);
+    let max_parallel_jobs = max_parallel_jobs(matches.get_one::<String>("jobs"), *config.max_parallel_jobs())?;
+
+    let priorities = matches
+        .get_many::<String>("priority")
+        .unwrap_or_default()
+        .map(|s| {
+            let (name, priority) = s
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected NAME=PRIORITY, got: {}", s))?;
+            Ok((PackageName::from(name.to_string()), i32::from_str(priority)?))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let dashboard = matches.get_flag("dashboard");
+    if dashboard && crate::util::stdout_is_pipe() {
+        warn!("Not showing the dashboard, the output is not a terminal");
+    }
+    let dashboard = dashboard && !crate::util::stdout_is_pipe();
+
+    let status_address = matches
+        .get_one::<String>("status_address")
+        .map(|s| SocketAddr::from_str(s))
+        .transpose()?;
+
     let orch = OrchestratorSetup::builder()
-        .progress_generator(progressbars)
+        .progress_generator(if dashboard || json_lines { progressbars.hidden() } else { progressbars })
         .endpoint_config(endpoint_configurations)
         .staging_store(staging_store)
         .release_stores(release_stores)
         .database(database_connection.clone())
         .source_cache(source_cache)
-        .submit(submit)
-        .log_dir(if matches.get_flag("write-log-file") {
-            Some(config.log_dir().clone())
+        .submit(submit.clone())
+        .continuous_release(continuous_release.clone())
+        .max_parallel_jobs(max_parallel_jobs)
+        .priorities(priorities)
+        .dashboard(dashboard)
+        .status_address(status_address)
+        .json_lines(json_lines)
+        .notifier(notifier.clone())
+        .log_forwarder(log_forwarder)
+        .log_files(if matches.get_flag("write-log-file") || manifest.as_ref().map(|m| *m.write_log()).unwrap_or(false) {
+            Some(LogFiles::new(config.log_dir().clone(), config.log_files().clone()))
         } else {
             None
         })
@@ -380,7 +578,42 @@ pub async fn build(
 
     info!("Running orchestrator...");
     let mut artifacts = vec![];
-    let errors = orch.run(&mut artifacts).await?;
+    let result = orch.run(&mut artifacts).await;
+    if let Some(forwarding) = log_forwarding {
+        forwarding.finish().await;
+    }
+    notifier.notify(&Notification::submit_finished(submit_id, package, &result)).await;
+    run_submit_hooks(config, &submit_id, package, &staging_dir, &artifacts, &result).await;
+    let errors = result?;
+
+    if let Some(continuous_release) = continuous_release.as_ref() {
+        if errors.is_empty() {
+            let n = crate::db::models::Release::finish_partial_releases(database_connection.as_ref(), &submit)?;
+            debug!("Marked {} releases to '{}' as complete", n, continuous_release.store.store_name);
+        }
+    }
+
+    if json_lines {
+        let event = serde_json::json!({
+            "event": "submit_finished",
+            "time": chrono::Utc::now().to_rfc3339(),
+            "submit": submit_id.to_string(),
+            "success": errors.is_empty(),
+            "artifacts": artifacts.iter().map(|a| staging_dir.join(a).display().to_string()).collect::<Vec<_>>(),
+            "failed_jobs": errors
+                .iter()
+                .map(|(uuid, e)| serde_json::json!({ "job": uuid.to_string(), "error": format!("{e:#}") }))
+                .collect::<Vec<_>>(),
+        });
+        writeln!(std::io::stdout(), "{event}")?;
+
+        return if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("One or multiple errors during build"))
+        }
+    }
+
     let out = std::io::stdout();
     let mut outlock = out.lock();
 
@@ -391,6 +624,8 @@ pub async fn build(
         writeln!(outlock, "{}", staging_dir.join(artifact_path).display()).map_err(Error::from)
     })?;
 
+    print_phase_times(&mut outlock, database_connection.as_ref(), config, &submit)?;
+
     let mut had_error = false;
     for (job_uuid, error) in errors {
         had_error = true;
@@ -403,12 +638,31 @@ pub async fn build(
             .inner_join(schema::packages::table)
             .first::<(Job, Package)>(database_connection.as_ref())?;
 
+        // The phase the job failed in is the last one, if it is shown and its lines are known
+        let failed_phase = if *config.build_error_whole_phase() {
+            schema::job_phases::table
+                .filter(schema::job_phases::job_id.eq(data.0.id))
+                .order_by(schema::job_phases::id.desc())
+                .first::<crate::db::models::JobPhase>(database_connection.as_ref())
+                .optional()?
+                .and_then(|phase| phase.lines().map(|lines| (phase.name, lines)))
+        } else {
+            None
+        };
+
         let number_log_lines = *config.build_error_lines();
-        writeln!(
-            outlock,
-            "Last {} lines of Job {}",
-            number_log_lines, job_uuid.to_string().red()
-        )?;
+        match failed_phase.as_ref() {
+            Some((name, _)) => writeln!(
+                outlock,
+                "Phase '{}' of Job {}",
+                name, job_uuid.to_string().red()
+            )?,
+            None => writeln!(
+                outlock,
+                "Last {} lines of Job {}",
+                number_log_lines, job_uuid.to_string().red()
+            )?,
+        }
         writeln!(
             outlock,
             "for package {} {}\n\n",
@@ -416,6 +670,7 @@ pub async fn build(
             data.1.version.to_string().red()
         )?;
 
+        let highlighter = LogHighlighter::new(config.log_highlight())?;
         let mut last_phase = None;
         let mut error_catched = false;
         let lines = crate::log::ParsedLog::from_str(&data.0.log_text)?
@@ -432,20 +687,20 @@ pub async fn build(
                 }
 
 
-                line_item.display().map(|d| d.to_string())
+                line_item.display_highlighted(&highlighter).map(|d| d.to_string())
             })
             .collect::<Result<Vec<_>>>()?;
 
+        let shown = match failed_phase {
+            Some((_, phase_lines)) => phase_lines,
+            None => lines.len().saturating_sub(number_log_lines)..lines.len(),
+        };
+
         lines
             .iter()
             .enumerate()
-            .skip({
-                if lines.len() > number_log_lines {
-                    lines.len() - number_log_lines
-                } else {
-                    lines.len()
-                }
-            })
+            .skip(shown.start)
+            .take(shown.len())
             .try_for_each(|(i, line)| {
                 let lineno = format!("{i:>4} | ").bright_black();
                 writeln!(outlock, "{lineno}{line}").map_err(Error::from)
@@ -472,3 +727,235 @@ pub async fn build(
         Ok(())
     }
 }
+
+/// Print the time each job of the submit spent in its phases
+///
+/// One row per job, one column per phase that was run by any of the jobs.
+fn print_phase_times(
+    out: &mut impl Write,
+    database_connection: &diesel::PgConnection,
+    config: &Configuration,
+    submit: &crate::db::models::Submit,
+) -> Result<()> {
+    use crate::db::models::{JobPhase, Package};
+
+    let phases = schema::job_phases::table
+        .inner_join(schema::jobs::table.inner_join(schema::packages::table))
+        .filter(schema::jobs::submit_id.eq(submit.id))
+        .order_by(schema::job_phases::id)
+        .select((schema::job_phases::all_columns, schema::packages::all_columns))
+        .load::<(JobPhase, Package)>(database_connection)?;
+
+    if phases.is_empty() {
+        return Ok(())
+    }
+
+    // The phases that were run, in the configured order
+    let phase_names = config.available_phases()
+        .iter()
+        .map(|name| name.as_str())
+        .filter(|name| phases.iter().any(|(phase, _)| phase.name == *name))
+        .collect::<Vec<_>>();
+
+    let format_duration = |d: chrono::Duration| {
+        let secs = d.to_std().map(|d| d.as_secs()).unwrap_or(0);
+        humantime::format_duration(std::time::Duration::from_secs(secs)).to_string()
+    };
+
+    let data = phases
+        .iter()
+        .group_by(|(phase, _)| phase.job_id)
+        .into_iter()
+        .map(|(_, job_phases)| {
+            let job_phases = job_phases.collect::<Vec<_>>();
+            let package = &job_phases[0].1;
+            let total = job_phases.iter().fold(chrono::Duration::zero(), |total, (phase, _)| total + phase.duration());
+
+            let mut row = vec![package.name.clone(), package.version.clone()];
+            row.extend(phase_names.iter().map(|name| {
+                job_phases.iter()
+                    .find(|(phase, _)| phase.name == *name)
+                    .map(|(phase, _)| format_duration(phase.duration()))
+                    .unwrap_or_else(|| String::from("-"))
+            }));
+            row.push(format_duration(total));
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let mut hdrs = vec!["Package", "Version"];
+    hdrs.extend(phase_names.iter().copied());
+    hdrs.push("Total");
+
+    writeln!(out, "Phase times:")?;
+    crate::commands::util::display_data(crate::commands::util::mk_header(hdrs), data, false)
+}
+
+/// Run the hooks for the success or failure of a submit
+async fn run_submit_hooks(
+    config: &Configuration,
+    submit_id: &Uuid,
+    package: &crate::package::Package,
+    staging_dir: &Path,
+    artifacts: &[ArtifactPath],
+    result: &Result<HashMap<Uuid, Error>>,
+) {
+    let (event, error) = match result {
+        Ok(errors) if errors.is_empty() => (HookEvent::SubmitSuccess, None),
+        Ok(errors) => (HookEvent::SubmitFailure, Some(format!("{} jobs failed", errors.len()))),
+        Err(e) => (HookEvent::SubmitFailure, Some(format!("{e:#}"))),
+    };
+
+    let mut env = vec![
+        ("SUBMIT_UUID", submit_id.to_string()),
+        ("PACKAGE_NAME", package.name().to_string()),
+        ("PACKAGE_VERSION", package.version().to_string()),
+        ("ARTIFACT_PATHS", artifacts.iter().map(|a| staging_dir.join(a).display().to_string()).join("\n")),
+    ];
+    if let Some(error) = error {
+        env.push(("ERROR", error));
+    }
+
+    crate::hooks::run_hooks(config.hooks(), event, &env).await;
+}
+
+/// Print the jobs of a build without running them
+fn print_build_plan(jobdag: &crate::job::Dag, endpoints: &[crate::endpoint::EndpointConfiguration]) -> Result<()> {
+    let endpoints = endpoints
+        .iter()
+        .map(|ep| ep.endpoint_name().to_string())
+        .sorted()
+        .join(", ");
+
+    let header = crate::commands::util::mk_header(["Package", "Version", "Image", "Endpoints", "Environment", "Dependencies"].to_vec());
+    crate::commands::util::display_data(header, build_plan_rows(jobdag, &endpoints), false)
+}
+
+/// The rows of the build plan, one for each job
+fn build_plan_rows(jobdag: &crate::job::Dag, endpoints: &str) -> Vec<Vec<String>> {
+    let graph = jobdag.dag().graph();
+    jobdag
+        .iter()
+        .map(|definition| {
+            let job = definition.job;
+            let env = job.resources()
+                .iter()
+                .filter_map(JobResource::env)
+                .chain(job.package().environment().iter().flatten())
+                .map(|(k, v)| format!("{k}={v}"))
+                .sorted()
+                .join(" ");
+
+            let dependencies = definition.dependencies
+                .iter()
+                .filter_map(|uuid| graph.raw_nodes().iter().map(|n| &n.weight).find(|j| j.uuid() == uuid))
+                .map(|j| format!("{} {}", j.package().name(), j.package().version()))
+                .sorted()
+                .join(", ");
+
+            vec![
+                job.package().name().to_string(),
+                job.package().version().to_string(),
+                job.image().to_string(),
+                endpoints.to_string(),
+                env,
+                dependencies,
+            ]
+        })
+        .collect()
+}
+
+/// The maximum number of parallel jobs, `jobs` from the command line overrides the `configured`
+/// value
+fn max_parallel_jobs(jobs: Option<&String>, configured: Option<usize>) -> Result<Option<usize>> {
+    let max_parallel_jobs = jobs
+        .map(|s| usize::from_str(s))
+        .transpose()?
+        .or(configured);
+    if max_parallel_jobs == Some(0) {
+        return Err(anyhow!("The maximum number of parallel jobs must be at least 1"))
+    }
+    Ok(max_parallel_jobs)
+}
+
+/// Find the configured default image for a package
+///
+/// The first default image that matches the package and is not excluded by the allowed/denied
+/// images of the package is returned.
+fn find_default_image(config: &Configuration, package: &crate::package::Package) -> Result<Option<ImageName>> {
+    for default_image in config.docker().default_images() {
+        if !default_image.matches(package)? {
+            continue
+        }
+
+        let image = default_image.image();
+        let allowed = package.allowed_images().as_ref().map(|list| list.contains(image)).unwrap_or(true);
+        let denied = package.denied_images().as_ref().map(|list| list.contains(image)).unwrap_or(false);
+        if allowed && !denied {
+            debug!("Using default image {} for {} {}", image, package.name(), package.version());
+            return Ok(Some(image.clone()))
+        }
+
+        trace!("Default image {} matches {} {}, but is not allowed for it", image, package.name(), package.version());
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_max_parallel_jobs() {
+        assert_eq!(max_parallel_jobs(None, None).unwrap(), None);
+        assert_eq!(max_parallel_jobs(None, Some(4)).unwrap(), Some(4));
+        assert_eq!(max_parallel_jobs(Some(&String::from("2")), Some(4)).unwrap(), Some(2));
+        assert!(max_parallel_jobs(Some(&String::from("0")), Some(4)).is_err());
+        assert!(max_parallel_jobs(None, Some(0)).is_err());
+        assert!(max_parallel_jobs(Some(&String::from("x")), None).is_err());
+    }
+
+    #[test]
+    fn test_build_plan_rows() {
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b =2"))));
+        let b = package("b", "2", "https://rust-lang.org", "124");
+
+        let mut btree = BTreeMap::new();
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        btree.insert((pname("b"), pversion("2")), b);
+        let repo = Repository::from(btree);
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+        let dag = Dag::for_root_package(a, &repo, None, &condition_data).unwrap();
+        let resources = vec![JobResource::from((EnvironmentVariableName::from("FOO"), String::from("bar")))];
+        let jobdag = crate::job::Dag::from_package_dag(
+            dag,
+            Shebang::from(String::from("#!/bin/sh")),
+            ImageName::from(String::from("debian:bullseye")),
+            vec![],
+            resources,
+        );
+
+        let rows = build_plan_rows(&jobdag, "ep1, ep2")
+            .into_iter()
+            .sorted()
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, vec![
+            vec!["a", "1", "debian:bullseye", "ep1, ep2", "FOO=bar", "b 2"],
+            vec!["b", "2", "debian:bullseye", "ep1, ep2", "FOO=bar", ""],
+        ]);
+    }
+}
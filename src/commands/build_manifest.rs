@@ -0,0 +1,186 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The manifest file for the "build" subcommand
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::util::EnvironmentVariableName;
+
+/// A build manifest
+///
+/// The manifest declares a submit in a file instead of CLI arguments:
+///
+/// ```toml
+/// image = "debian:bullseye"
+/// flavor = "full"
+/// features = [ "ssl" ]
+/// no_lint = true
+///
+/// [package]
+/// name = "foo"
+/// version = "1.0"
+///
+/// [env]
+/// FOO = "bar"
+///
+/// [labels]
+/// pipeline = "nightly"
+/// ```
+///
+/// Arguments that are passed on the CLI override the settings from the manifest.
+#[derive(Debug, Deserialize, Getters)]
+#[serde(deny_unknown_fields)]
+pub struct BuildManifest {
+    #[getset(get = "pub")]
+    package: Option<ManifestPackage>,
+
+    #[getset(get = "pub")]
+    image: Option<String>,
+
+    #[getset(get = "pub")]
+    flavor: Option<String>,
+
+    /// The optional features of the packages that are enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    features: Vec<String>,
+
+    #[getset(get = "pub")]
+    shebang: Option<String>,
+
+    /// Environment variables that are passed to all build jobs
+    #[getset(get = "pub")]
+    #[serde(default)]
+    env: BTreeMap<EnvironmentVariableName, String>,
+
+    /// Free-form labels, only recorded with the submit
+    #[getset(get = "pub")]
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    no_verification: bool,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    no_lint: bool,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    deny_deprecated: bool,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    write_log: bool,
+}
+
+#[derive(Debug, Deserialize, Getters)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestPackage {
+    #[getset(get = "pub")]
+    name: PackageName,
+
+    #[getset(get = "pub")]
+    version: Option<PackageVersion>,
+}
+
+impl BuildManifest {
+    /// Load a manifest file
+    ///
+    /// Returns the raw content of the file as well, so it can be recorded with the submit.
+    pub fn load(path: &Path) -> Result<(Self, String)> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Reading manifest {}", path.display()))?;
+
+        let mut config = config::Config::default();
+        config.merge(config::File::from_str(&content, config::FileFormat::Toml))
+            .with_context(|| anyhow!("Parsing manifest {}", path.display()))?;
+
+        let manifest = config.try_into::<BuildManifest>()
+            .map_err(Error::from)
+            .with_context(|| anyhow!("Loading manifest {}", path.display()))?;
+
+        Ok((manifest, content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(content: &str) -> Result<BuildManifest> {
+        let path = std::env::temp_dir().join(format!("butido-test-manifest-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        let result = BuildManifest::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result.map(|(manifest, raw)| {
+            assert_eq!(raw, content);
+            manifest
+        })
+    }
+
+    #[test]
+    fn test_load() {
+        let manifest = load_str(indoc::indoc!(r#"
+            image = "debian:bullseye"
+            flavor = "full"
+            features = [ "ssl" ]
+            no_lint = true
+
+            [package]
+            name = "foo"
+            version = "1.0"
+
+            [env]
+            FOO = "bar"
+
+            [labels]
+            pipeline = "nightly"
+        "#)).unwrap();
+
+        let package = manifest.package().as_ref().unwrap();
+        assert_eq!(package.name(), &PackageName::from(String::from("foo")));
+        assert_eq!(package.version(), &Some(PackageVersion::from(String::from("1.0"))));
+        assert_eq!(manifest.image().as_deref(), Some("debian:bullseye"));
+        assert_eq!(manifest.flavor().as_deref(), Some("full"));
+        assert_eq!(manifest.features(), &[String::from("ssl")]);
+        assert_eq!(manifest.env().get(&EnvironmentVariableName::from("FOO")).map(String::as_str), Some("bar"));
+        assert_eq!(manifest.labels().get("pipeline").map(String::as_str), Some("nightly"));
+        assert!(*manifest.no_lint());
+        assert!(!*manifest.no_verification());
+        assert!(manifest.shebang().is_none());
+    }
+
+    #[test]
+    fn test_load_empty() {
+        let manifest = load_str("").unwrap();
+        assert!(manifest.package().is_none());
+        assert!(manifest.features().is_empty());
+        assert!(manifest.env().is_empty());
+    }
+
+    #[test]
+    fn test_load_unknown_field() {
+        assert!(load_str("imgae = \"debian:bullseye\"").is_err());
+        assert!(load_str("[package]\nname = \"foo\"\nvresion = \"1.0\"").is_err());
+    }
+}
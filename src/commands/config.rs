@@ -0,0 +1,225 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'config' subcommand
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::RunQueryDsl;
+use futures::stream::StreamExt;
+
+use crate::commands::util::OutputFormat;
+use crate::config::Configuration;
+use crate::config::NotValidatedConfiguration;
+use crate::db::DbConnectionConfig;
+use crate::util::docker::ImageName;
+
+/// Implementation of the "config" subcommand
+///
+/// This gets the configuration before it is validated, so that problems can be reported instead
+/// of failing at the first one.
+pub async fn config(matches: &ArgMatches, cli: &ArgMatches, config: ::config::Config) -> Result<()> {
+    match matches.subcommand() {
+        Some(("check", matches)) => check(matches, cli, config).await,
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, parse_display::Display)]
+#[display(style = "lowercase")]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem that was found in the configuration
+struct Finding {
+    severity: Severity,
+    setting: String,
+    check: &'static str,
+    message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, setting: impl Into<String>, check: &'static str, message: String) -> Self {
+        Finding {
+            severity,
+            setting: setting.into(),
+            check,
+            message,
+        }
+    }
+}
+
+/// Load and validate the configuration and check the environment it describes
+async fn check(matches: &ArgMatches, cli: &ArgMatches, config: ::config::Config) -> Result<()> {
+    let format = OutputFormat::from_matches(matches)?;
+    let offline = matches.get_flag("offline");
+
+    let mut findings = Vec::new();
+    match NotValidatedConfiguration::unknown_keys(&config) {
+        Ok(keys) => findings.extend({
+            keys.into_iter()
+                .map(|key| Finding::new(Severity::Warning, key, "unknown-key", String::from("Unknown setting, it is ignored")))
+        }),
+        Err(e) => findings.push(Finding::new(Severity::Error, "-", "parse", format!("{e:#}"))),
+    }
+
+    let validated = match config.try_into::<NotValidatedConfiguration>() {
+        Ok(not_validated) => {
+            findings.extend(check_directories(&not_validated));
+            match not_validated.validate() {
+                Ok(validated) => Some(validated),
+                Err(e) => {
+                    findings.push(Finding::new(Severity::Error, "-", "validate", format!("{e:#}")));
+                    None
+                }
+            }
+        },
+        Err(e) => {
+            findings.push(Finding::new(Severity::Error, "-", "parse", format!("{e:#}")));
+            None
+        }
+    };
+
+    match validated {
+        Some(_) if offline => {},
+        Some(validated) => {
+            findings.extend(check_database(&validated, cli));
+            findings.extend(check_endpoints(&validated).await);
+        },
+        None => {
+            let message = String::from("Not checked, because the configuration is invalid");
+            findings.push(Finding::new(Severity::Warning, "database", "database", message.clone()));
+            findings.push(Finding::new(Severity::Warning, "docker.endpoints", "endpoint", message));
+        },
+    }
+
+    findings.sort_by(|a, b| (a.severity, &a.setting).cmp(&(b.severity, &b.setting)));
+    let n_errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let n_warnings = findings.len() - n_errors;
+
+    let header = crate::commands::util::mk_header(["Severity", "Setting", "Check", "Message"].to_vec());
+    let data = findings
+        .into_iter()
+        .map(|f| vec![f.severity.to_string(), f.setting, f.check.to_string(), f.message])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data_as(header, data, format)?;
+
+    if n_errors > 0 {
+        Err(anyhow!("Configuration check found {} errors and {} warnings", n_errors, n_warnings))
+    } else {
+        if n_warnings > 0 {
+            tracing::warn!("Configuration check found {} warnings", n_warnings);
+        }
+        Ok(())
+    }
+}
+
+/// Check that the configured directories exist
+fn check_directories(config: &NotValidatedConfiguration) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut check = |setting: String, path: &Path, severity_if_missing: Severity| {
+        if !path.exists() {
+            findings.push(Finding::new(severity_if_missing, setting, "directory", format!("Does not exist: {}", path.display())));
+        } else if !path.is_dir() {
+            findings.push(Finding::new(Severity::Error, setting, "directory", format!("Not a directory: {}", path.display())));
+        }
+    };
+
+    check(String::from("staging"), config.staging_directory(), Severity::Error);
+    check(String::from("releases_root"), config.releases_directory(), Severity::Error);
+    check(String::from("source_cache"), config.source_cache_root(), Severity::Error);
+
+    // The log directories are created when the first log file is written
+    check(String::from("log_dir"), config.log_dir(), Severity::Warning);
+    if let Some(log_archive) = config.log_archive() {
+        check(String::from("log_archive.directory"), log_archive.directory(), Severity::Warning);
+    }
+
+    for store in config.release_stores() {
+        check(format!("release_stores.{store}"), &config.releases_directory().join(store), Severity::Error);
+    }
+
+    for repository in config.repositories() {
+        check(String::from("repositories"), repository, Severity::Error);
+    }
+
+    findings
+}
+
+/// Check that the database can be connected to
+fn check_database(config: &Configuration, cli: &ArgMatches) -> Option<Finding> {
+    DbConnectionConfig::parse(config, cli)
+        .and_then(DbConnectionConfig::establish_connection)
+        .and_then(|conn| diesel::sql_query("SELECT 1").execute(&conn).map_err(Error::from))
+        .err()
+        .map(|e| Finding::new(Severity::Error, "database", "database", format!("{e:#}")))
+}
+
+/// Check that the endpoints can be connected to and have the configured images
+async fn check_endpoints(config: &Configuration) -> Vec<Finding> {
+    config.docker()
+        .endpoints()
+        .iter()
+        .map(|(ep_name, ep_cfg)| async move {
+            let setting = format!("docker.endpoints.{ep_name}");
+            let epc = crate::endpoint::EndpointConfiguration::builder()
+                .endpoint_name(ep_name.clone())
+                .endpoint(ep_cfg.clone())
+                .required_docker_versions(config.docker().docker_versions().clone())
+                .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .build();
+
+            let endpoint = match crate::endpoint::util::setup_endpoints(vec![epc]).await {
+                Ok(mut endpoints) => match endpoints.pop() {
+                    Some(endpoint) => endpoint,
+                    None => return vec![],
+                },
+                Err(e) => return vec![Finding::new(Severity::Error, setting, "endpoint", format!("{e:#}"))],
+            };
+
+            let timeout = std::time::Duration::from_secs(ep_cfg.timeout().unwrap_or(10));
+            let available = match tokio::time::timeout(timeout, endpoint.images(None)).await {
+                Ok(Ok(images)) => images
+                    .filter_map(|img| img.tags().clone())
+                    .flatten()
+                    .map(ImageName::from)
+                    .collect::<Vec<_>>(),
+                Ok(Err(e)) => return vec![Finding::new(Severity::Error, setting, "endpoint", format!("{e:#}"))],
+                Err(_) => return vec![Finding::new(Severity::Error, setting, "endpoint", String::from("Timeout while listing the images"))],
+            };
+
+            // Missing images are only a problem if butido verifies them before building
+            let severity = if config.docker().verify_images_present() {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+
+            config.docker()
+                .images()
+                .iter()
+                .filter(|img| !available.contains(&img.name))
+                .map(|img| Finding::new(severity, setting.clone(), "image", format!("Image {} is missing", img.name)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Vec<Vec<Finding>>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
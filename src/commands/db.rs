@@ -10,8 +10,13 @@
 
 //! Implementation of the 'db' subcommand
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 use std::str::FromStr;
@@ -25,20 +30,37 @@ use colored::Colorize;
 use diesel::BelongingToDsl;
 use diesel::ExpressionMethods;
 use diesel::JoinOnDsl;
+use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use itertools::Itertools;
 use tracing::{debug, info, trace, warn};
 
 use crate::commands::util::get_date_filter;
+use crate::commands::util::get_limit_and_offset;
+use crate::commands::util::OutputFormat;
 use crate::config::Configuration;
 use crate::db::models;
 use crate::db::DbConnectionConfig;
 use crate::log::JobResult;
+use crate::log::LogFilter;
+use crate::log::LogHighlighter;
 use crate::package::Script;
 use crate::schema;
 
-diesel_migrations::embed_migrations!("migrations");
+// Expands like `diesel_migrations::embed_migrations!("migrations")`, but also gives access to the
+// versions of the embedded migrations
+#[allow(dead_code)]
+mod embedded_migrations {
+    #[derive(EmbedMigrations)]
+    #[embed_migrations_options(migrations_path = "migrations")]
+    struct _Dummy;
+
+    /// The versions of the embedded migrations, in the order they are run
+    pub fn versions() -> impl Iterator<Item = &'static str> {
+        ALL_MIGRATIONS.iter().map(|migration| migration.version())
+    }
+}
 
 /// Implementation of the "db" subcommand
 pub fn db(
@@ -49,34 +71,58 @@ pub fn db(
     match matches.subcommand() {
         Some(("cli", matches)) => cli(db_connection_config, matches),
         Some(("setup", _matches)) => setup(db_connection_config),
+        Some(("test", _matches)) => test(db_connection_config),
         Some(("artifacts", matches)) => artifacts(db_connection_config, matches),
         Some(("envvars", matches)) => envvars(db_connection_config, matches),
         Some(("images", matches)) => images(db_connection_config, matches),
         Some(("submit", matches)) => submit(db_connection_config, matches),
+        Some(("submit-diff", matches)) => submit_diff(db_connection_config, matches),
         Some(("submits", matches)) => submits(db_connection_config, matches),
         Some(("jobs", matches)) => jobs(db_connection_config, config, matches),
         Some(("job", matches)) => job(db_connection_config, config, matches),
         Some(("log-of", matches)) => log_of(db_connection_config, matches),
+        Some(("buildinfo-of", matches)) => buildinfo_of(db_connection_config, matches),
+        Some(("diff-artifacts", matches)) => diff_artifacts(db_connection_config, config, matches),
+        Some(("job-diff", matches)) => job_diff(db_connection_config, matches),
         Some(("releases", matches)) => releases(db_connection_config, config, matches),
+        Some(("export", matches)) => export(db_connection_config, matches),
+        Some(("import", matches)) => import(db_connection_config, matches),
+        Some(("known-failures", matches)) => known_failures(db_connection_config, matches),
+        Some(("stats", matches)) => stats(db_connection_config, matches),
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
 }
 
+/// The environment for the CLI program, which makes the session read-only if requested
+///
+/// libpq (used by psql and pgcli) passes the options from PGOPTIONS to the server, the options
+/// that are already set (`pgoptions`) are kept. Later options override earlier ones, so the
+/// session is read-only even if `pgoptions` turns it off.
+fn pg_env(read_only: bool, pgoptions: Option<&str>) -> Vec<(&'static str, String)> {
+    if read_only {
+        let options = pgoptions.unwrap_or_default();
+        vec![("PGOPTIONS", format!("{options} -c default_transaction_read_only=on").trim().to_string())]
+    } else {
+        vec![]
+    }
+}
+
 /// Implementation of the "db cli" subcommand
 fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     trait PgCliCommand {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()>;
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, read_only: bool) -> Result<()>;
     }
 
     struct Psql(PathBuf);
     impl PgCliCommand for Psql {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()> {
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, read_only: bool) -> Result<()> {
             Command::new(&self.0)
                 .arg(format!("--dbname={}", dbcc.database_name()))
                 .arg(format!("--host={}", dbcc.database_host()))
                 .arg(format!("--port={}", dbcc.database_port()))
                 .arg(format!("--username={}", dbcc.database_user()))
+                .envs(pg_env(read_only, std::env::var("PGOPTIONS").ok().as_deref()))
                 .stdin(std::process::Stdio::inherit())
                 .stdout(std::process::Stdio::inherit())
                 .stderr(std::process::Stdio::inherit())
@@ -88,10 +134,7 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
                         Ok(())
                     } else {
                         Err(anyhow!("psql did not exit successfully"))
-                            .with_context(|| match String::from_utf8(out.stderr) {
-                                Ok(log) => anyhow!("{}", log),
-                                Err(e) => anyhow!("Cannot parse log into valid UTF-8: {}", e),
-                            })
+                            .with_context(|| anyhow!("{}", String::from_utf8_lossy(&out.stderr)))
                             .map_err(Error::from)
                     }
                 })
@@ -100,7 +143,7 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
 
     struct PgCli(PathBuf);
     impl PgCliCommand for PgCli {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()> {
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, read_only: bool) -> Result<()> {
             Command::new(&self.0)
                 .arg("--host")
                 .arg(dbcc.database_host())
@@ -109,6 +152,7 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
                 .arg("--username")
                 .arg(dbcc.database_user())
                 .arg(dbcc.database_name())
+                .envs(pg_env(read_only, std::env::var("PGOPTIONS").ok().as_deref()))
                 .stdin(std::process::Stdio::inherit())
                 .stdout(std::process::Stdio::inherit())
                 .stderr(std::process::Stdio::inherit())
@@ -120,10 +164,7 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
                         Ok(())
                     } else {
                         Err(anyhow!("pgcli did not exit successfully"))
-                            .with_context(|| match String::from_utf8(out.stderr) {
-                                Ok(log) => anyhow!("{}", log),
-                                Err(e) => anyhow!("Cannot parse log into valid UTF-8: {}", e),
-                            })
+                            .with_context(|| anyhow!("{}", String::from_utf8_lossy(&out.stderr)))
                             .map_err(Error::from)
                     }
                 })
@@ -144,7 +185,7 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
         .next()
         .transpose()?
         .ok_or_else(|| anyhow!("No Program found"))?
-        .run_for_uri(db_connection_config)
+        .run_for_uri(db_connection_config, matches.get_flag("read_only"))
 }
 
 fn setup(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
@@ -152,51 +193,166 @@ fn setup(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
     embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).map_err(Error::from)
 }
 
+/// Implementation of the "db test" subcommand
+///
+/// Prints the results of the checks and fails if any check failed.
+fn test(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
+    use diesel::sql_types::Text;
+
+    #[derive(QueryableByName)]
+    struct TextRow {
+        #[sql_type = "Text"]
+        value: String,
+    }
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    let mut failed = false;
+
+    macro_rules! report {
+        ($name:expr, $res:expr) => {
+            match $res {
+                Ok(value) => writeln!(outlock, "{:<20} {}", $name, value)?,
+                Err(e) => {
+                    failed = true;
+                    writeln!(outlock, "{:<20} {}: {:#}", $name, "FAILED".red(), e)?
+                },
+            }
+        };
+    }
+
+    let conn = match conn_cfg.establish_connection() {
+        Ok(conn) => {
+            report!("Connection", Ok::<_, Error>("ok".green()));
+            conn
+        },
+        Err(e) => {
+            writeln!(outlock, "{:<20} {}: {:#}", "Connection", "FAILED".red(), e)?;
+            return Err(anyhow!("Database checks failed"))
+        },
+    };
+
+    let text_query = |query: &str| -> Result<String> {
+        diesel::sql_query(query)
+            .get_result::<TextRow>(&conn)
+            .map(|row| row.value)
+            .map_err(Error::from)
+    };
+
+    report!("Server version", text_query("SELECT version() AS value"));
+    report!("Database size", text_query("SELECT pg_size_pretty(pg_database_size(current_database())) AS value"));
+
+    // The versions of the migrations that were run are recorded by diesel
+    let pending = diesel::sql_query("SELECT version AS value FROM __diesel_schema_migrations")
+        .load::<TextRow>(&conn)
+        .map_err(Error::from)
+        .context("Loading the versions of the migrations that were run")
+        .and_then(|rows| {
+            let applied = rows.into_iter().map(|row| row.value).collect::<HashSet<_>>();
+            let pending = pending_migrations(embedded_migrations::versions(), &applied);
+
+            if pending.is_empty() {
+                Ok(String::from("none"))
+            } else {
+                Err(anyhow!("{} (run 'butido db setup')", pending.join(", ")))
+            }
+        });
+    report!("Pending migrations", pending);
+
+    macro_rules! count {
+        ($table:ident) => {
+            report!(
+                concat!("Rows in ", stringify!($table)),
+                schema::$table::table.count().get_result::<i64>(&conn).map_err(Error::from)
+            )
+        };
+    }
+
+    count!(submits);
+    count!(jobs);
+    count!(artifacts);
+    count!(releases);
+    count!(packages);
+    count!(images);
+    count!(endpoints);
+
+    if failed {
+        Err(anyhow!("Database checks failed"))
+    } else {
+        Ok(())
+    }
+}
+
+/// The versions of the migrations that were not run yet, in the order they have to be run
+fn pending_migrations<'a>(embedded: impl Iterator<Item = &'a str>, applied: &HashSet<String>) -> Vec<&'a str> {
+    embedded.filter(|version| !applied.contains(*version)).collect()
+}
+
 /// Implementation of the "db artifacts" subcommand
 fn artifacts(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     use crate::schema::artifacts::dsl;
 
-    let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec!["Path", "Released", "Job"]);
+    let format = OutputFormat::from_matches(matches)?;
+    let hdrs = crate::commands::util::mk_header(vec!["Path", "Released", "Job", "Verified", "Size"]);
     let conn = conn_cfg.establish_connection()?;
-    let data = matches
-        .get_one::<String>("job_uuid")
-        .map(|s| uuid::Uuid::parse_str(s.as_ref()))
-        .transpose()?
-        .map(|job_uuid| -> Result<_> {
-            dsl::artifacts
-                .inner_join(schema::jobs::table)
-                .left_join(schema::releases::table)
-                .filter(schema::jobs::dsl::uuid.eq(job_uuid))
-                .load::<(models::Artifact, models::Job, Option<models::Release>)>(&conn)
-                .map_err(Error::from)
-        })
-        .unwrap_or_else(|| {
-            dsl::artifacts
-                .inner_join(schema::jobs::table)
-                .left_join(schema::releases::table)
-                .order_by(schema::artifacts::id.asc())
-                .load::<(models::Artifact, models::Job, Option<models::Release>)>(&conn)
-                .map_err(Error::from)
-        })?
+    let (limit, offset) = get_limit_and_offset(matches)?;
+    let older_than_filter = get_date_filter("older_than", matches)?;
+    let newer_than_filter = get_date_filter("newer_than", matches)?;
+
+    let mut sel = dsl::artifacts
+        .inner_join(schema::jobs::table.inner_join(schema::submits::table))
+        .left_join(schema::releases::table)
+        .into_boxed();
+
+    if let Some(job_uuid) = matches.get_one::<String>("job_uuid").map(|s| uuid::Uuid::parse_str(s.as_ref())).transpose()? {
+        sel = sel.filter(schema::jobs::dsl::uuid.eq(job_uuid))
+    }
+
+    if let Some(datetime) = older_than_filter.as_ref() {
+        sel = sel.filter(schema::submits::dsl::submit_time.lt(datetime))
+    }
+
+    if let Some(datetime) = newer_than_filter.as_ref() {
+        sel = sel.filter(schema::submits::dsl::submit_time.gt(datetime))
+    }
+
+    if let Some(limit) = limit {
+        sel = sel.limit(limit)
+    }
+
+    if let Some(offset) = offset {
+        sel = sel.offset(offset)
+    }
+
+    let data = sel
+        .order_by(schema::artifacts::id.desc()) // required for the --limit implementation
+        .load::<(models::Artifact, (models::Job, models::Submit), Option<models::Release>)>(&conn)?
         .into_iter()
-        .map(|(artifact, job, rel)| {
+        .rev() // required for the --limit implementation
+        .map(|(artifact, (job, _), rel)| {
             let rel = rel
                 .map(|r| r.release_date.to_string())
                 .unwrap_or_else(|| String::from("no"));
+            let verified = match artifact.verified {
+                Some(true) => "yes",
+                Some(false) => "MISMATCH",
+                None => "unknown",
+            };
+            let size = fmt_size(artifact.size);
             vec![
                 artifact.path,
                 rel,
                 job.uuid.to_string(),
+                verified.to_string(),
+                size,
             ]
         })
         .collect::<Vec<_>>();
 
     if data.is_empty() {
         info!("No artifacts in database");
-    } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
     }
+    crate::commands::util::display_data_as(hdrs, data, format)?;
 
     Ok(())
 }
@@ -205,7 +361,7 @@ fn artifacts(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<(
 fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     use crate::schema::envvars::dsl;
 
-    let csv = matches.get_flag("csv");
+    let format = OutputFormat::from_matches(matches)?;
     let hdrs = crate::commands::util::mk_header(vec!["Name", "Value"]);
     let conn = conn_cfg.establish_connection()?;
     let data = dsl::envvars
@@ -216,9 +372,8 @@ fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
     if data.is_empty() {
         info!("No environment variables in database");
-    } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
     }
+    crate::commands::util::display_data_as(hdrs, data, format)?;
 
     Ok(())
 }
@@ -227,7 +382,7 @@ fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 fn images(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     use crate::schema::images::dsl;
 
-    let csv = matches.get_flag("csv");
+    let format = OutputFormat::from_matches(matches)?;
     let hdrs = crate::commands::util::mk_header(vec!["Name"]);
     let conn = conn_cfg.establish_connection()?;
     let data = dsl::images
@@ -238,9 +393,8 @@ fn images(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
     if data.is_empty() {
         info!("No images in database");
-    } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
     }
+    crate::commands::util::display_data_as(hdrs, data, format)?;
 
     Ok(())
 }
@@ -267,21 +421,39 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         .load::<models::Job>(&conn)
         .with_context(|| anyhow!("Loading jobs for submit = {}", submit_id))?;
 
+    let known_failures = models::KnownFailure::all(&conn)?;
+    let jobs = jobs.into_iter()
+        .map(|job| {
+            let image = models::Image::fetch_for_job(&conn, &job)?
+                .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
+            let package = models::Package::fetch_for_job(&conn, &job)?
+                .ok_or_else(|| anyhow!("Package for job {} not found", job.uuid))?;
+            let endpoint = models::Endpoint::fetch_for_job(&conn, &job)?
+                .ok_or_else(|| anyhow!("Endpoint for job {} not found", job.uuid))?;
+            let result = crate::log::ParsedLog::from_str(&job.log_text)?.is_successfull();
+            let known_failure = result != JobResult::Success
+                && models::KnownFailure::any_matches(&known_failures, job.id, &package.name, &package.version, &image.name);
+            Ok((job, image, package, endpoint, result, known_failure))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
     let n_jobs = jobs.len();
-    let (jobs_unknown, jobs_success, jobs_err) = {
+    let (jobs_unknown, jobs_success, jobs_err, jobs_known) = {
         let mut unkn = 0;
         let mut succ = 0;
         let mut err = 0;
+        let mut known = 0;
 
-        for j in jobs.iter() {
-            match crate::log::ParsedLog::from_str(&j.log_text)?.is_successfull() {
+        for (_, _, _, _, result, known_failure) in jobs.iter() {
+            match result {
+                _ if *known_failure => known += 1,
                 JobResult::Unknown => unkn += 1,
                 JobResult::Success => succ += 1,
                 JobResult::Errored => err += 1,
             }
         }
 
-        (unkn, succ, err)
+        (unkn, succ, err, known)
     };
 
     let out = std::io::stdout();
@@ -291,53 +463,241 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
             Submit   {submit_id}
             Date:    {submit_dt}
             Commit:  {submit_commit}
+            Flavor:  {submit_flavor}
+            Features: {submit_features}
+            Butido:  {submit_butido}
+            Config:  {submit_config}
             Jobs:    {n_jobs}
             Success: {n_jobs_success}
             Unknown: {n_jobs_unknown}
             Errored: {n_jobs_err}
+            Known failures: {n_jobs_known}
 
         "#,
         submit_id = submit.uuid.to_string().cyan(),
         submit_dt = submit.submit_time.to_string().cyan(),
         submit_commit = githash.hash.cyan(),
+        submit_flavor = submit.flavor.as_deref().unwrap_or("-").cyan(),
+        submit_features = if submit.features.is_empty() { String::from("-") } else { submit.features.join(", ") }.cyan(),
+        submit_butido = match (submit.butido_version.as_deref(), submit.butido_git_hash.as_deref()) {
+            (Some(version), Some(hash)) => format!("{version} ({hash})"),
+            (Some(version), None) => version.to_string(),
+            (None, Some(hash)) => hash.to_string(),
+            (None, None) => String::from("-"),
+        }.cyan(),
+        submit_config = submit.config_fingerprint.as_deref().unwrap_or("-").cyan(),
         n_jobs = n_jobs.to_string().cyan(),
         n_jobs_success = jobs_success.to_string().green(),
         n_jobs_unknown = jobs_unknown.to_string().red(),
         n_jobs_err = jobs_err.to_string().red(),
+        n_jobs_known = jobs_known.to_string().yellow(),
     )?;
 
-    let header = crate::commands::util::mk_header(["Job", "Success", "Package", "Version", "Container", "Endpoint", "Image"].to_vec());
-    let data = jobs.iter()
-        .map(|job| {
-            let image = models::Image::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
-            let package = models::Package::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Package for job {} not found", job.uuid))?;
-            let endpoint = models::Endpoint::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Endpoint for job {} not found", job.uuid))?;
+    if matches.get_flag("show_manifest") {
+        match submit.manifest.as_ref() {
+            Some(manifest) => writeln!(outlock, "Manifest:\n{manifest}\n")?,
+            None => writeln!(outlock, "Manifest: -\n")?,
+        }
+    }
 
-            Ok(vec![
+    let header = crate::commands::util::mk_header(["Job", "Success", "Package", "Version", "Container", "Endpoint", "Image"].to_vec());
+    let data = jobs.into_iter()
+        .map(|(job, image, package, endpoint, result, known_failure)| {
+            vec![
                 job.uuid.to_string().cyan(),
-                match is_job_successfull(job)? {
-                    Some(true) => "Success".green(),
-                    Some(false) => "Error".red(),
-                    None => "Unknown".yellow(),
+                match result {
+                    _ if known_failure => "Known failure".yellow(),
+                    JobResult::Success => "Success".green(),
+                    JobResult::Errored => "Error".red(),
+                    JobResult::Unknown => "Unknown".yellow(),
                 },
                 package.name.cyan(),
                 package.version.cyan(),
                 job.container_hash.normal(),
                 endpoint.name.normal(),
                 image.name.normal(),
-            ])
+            ]
         })
-        .collect::<Result<Vec<Vec<colored::ColoredString>>>>()?;
+        .collect::<Vec<Vec<colored::ColoredString>>>();
     crate::commands::util::display_data(header, data, false)
 }
 
+/// Implementation of the "db submit-diff" subcommand
+fn submit_diff(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    /// The versions, the result and the duration (if known) of the jobs of a package in a submit
+    struct PackageJobs {
+        versions: BTreeSet<String>,
+        result: JobResult,
+        duration: Option<chrono::Duration>,
+    }
+
+    let csv = matches.get_flag("csv");
+    let changed_only = matches.get_flag("changed_only");
+    let conn = conn_cfg.establish_connection()?;
+
+    let load_jobs = |name: &str| -> Result<BTreeMap<String, PackageJobs>> {
+        let submit_id = matches.get_one::<String>(name)
+            .map(|s| uuid::Uuid::from_str(s.as_ref()))
+            .transpose()
+            .context("Parsing submit UUID")?
+            .unwrap(); // safe by clap
+
+        // Make sure the submit exists, so a typo does not look like a submit without jobs
+        models::Submit::with_id(&conn, &submit_id)
+            .with_context(|| anyhow!("Loading submit '{}' from DB", submit_id))?;
+
+        let mut packages: BTreeMap<String, PackageJobs> = BTreeMap::new();
+        for (job, package) in schema::jobs::table
+            .inner_join(schema::submits::table)
+            .inner_join(schema::packages::table)
+            .filter(schema::submits::uuid.eq(&submit_id))
+            .select((schema::jobs::all_columns, schema::packages::all_columns))
+            .load::<(models::Job, models::Package)>(&conn)
+            .with_context(|| anyhow!("Loading jobs for submit = {}", submit_id))?
+        {
+            let result = crate::log::ParsedLog::from_str(&job.log_text)?.is_successfull();
+            let duration = job.started.zip(job.finished).map(|(started, finished)| finished - started);
+
+            match packages.get_mut(&package.name) {
+                Some(entry) => {
+                    entry.versions.insert(package.version);
+                    entry.result = combine_job_results(entry.result, result);
+                    entry.duration = entry.duration.zip(duration).map(|(a, b)| a + b);
+                },
+                None => {
+                    let entry = PackageJobs {
+                        versions: std::iter::once(package.version).collect(),
+                        result,
+                        duration,
+                    };
+                    packages.insert(package.name, entry);
+                },
+            }
+        }
+
+        Ok(packages)
+    };
+
+    let jobs_a = load_jobs("submit_a")?;
+    let jobs_b = load_jobs("submit_b")?;
+
+    let fmt_versions = |jobs: Option<&PackageJobs>| jobs
+        .map(|j| j.versions.iter().join(", "))
+        .unwrap_or_else(|| String::from("-"));
+    let fmt_result = |jobs: Option<&PackageJobs>| match jobs.map(|j| j.result) {
+        None => String::from("-"),
+        Some(JobResult::Success) => String::from("Success"),
+        Some(JobResult::Errored) => String::from("Error"),
+        Some(JobResult::Unknown) => String::from("Unknown"),
+    };
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let data = jobs_a.keys()
+        .chain(jobs_b.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|name| {
+            let a = jobs_a.get(name);
+            let b = jobs_b.get(name);
+            let status = submit_diff_status(a.map(|j| j.result), b.map(|j| j.result));
+            *counts.entry(status).or_default() += 1;
+
+            if changed_only && status == "unchanged" {
+                return None
+            }
+
+            let duration_a = a.and_then(|j| j.duration);
+            let duration_b = b.and_then(|j| j.duration);
+
+            Some(vec![
+                name.clone(),
+                String::from(status),
+                fmt_versions(a),
+                fmt_versions(b),
+                fmt_result(a),
+                fmt_result(b),
+                fmt_duration(duration_a),
+                fmt_duration(duration_b),
+                fmt_duration_delta(duration_a, duration_b),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let hdrs = crate::commands::util::mk_header(vec![
+        "Package",
+        "Status",
+        "Version A",
+        "Version B",
+        "Result A",
+        "Result B",
+        "Duration A",
+        "Duration B",
+        "Delta",
+    ]);
+    crate::commands::util::display_data(hdrs, data, csv)?;
+
+    if !csv {
+        let summary = counts.iter().map(|(status, n)| format!("{n} {status}")).join(", ");
+        writeln!(std::io::stdout(), "\n{summary}")?;
+    }
+
+    Ok(())
+}
+
+/// The result of several jobs of a package, an error wins over an unknown result
+fn combine_job_results(a: JobResult, b: JobResult) -> JobResult {
+    match (a, b) {
+        (JobResult::Errored, _) | (_, JobResult::Errored) => JobResult::Errored,
+        (JobResult::Unknown, _) | (_, JobResult::Unknown) => JobResult::Unknown,
+        (JobResult::Success, JobResult::Success) => JobResult::Success,
+    }
+}
+
+/// How the result of a package differs between two submits, `None` if the submit has no job for
+/// the package
+fn submit_diff_status(a: Option<JobResult>, b: Option<JobResult>) -> &'static str {
+    match (a, b) {
+        (Some(_), None) => "removed",
+        (None, Some(_)) => "added",
+        (Some(JobResult::Success), Some(JobResult::Success)) => "unchanged",
+        (Some(_), Some(JobResult::Success)) => "fixed",
+        (Some(JobResult::Success), Some(_)) => "broken",
+        _ => "unchanged",
+    }
+}
+
+fn fmt_duration(duration: Option<chrono::Duration>) -> String {
+    duration
+        .and_then(|d| d.to_std().ok())
+        .map(|d| humantime::format_duration(std::time::Duration::from_secs(d.as_secs())).to_string())
+        .unwrap_or_else(|| String::from("-"))
+}
+
+/// The time a job ran, if it was started and finished
+fn job_duration(started: Option<chrono::NaiveDateTime>, finished: Option<chrono::NaiveDateTime>) -> Option<chrono::Duration> {
+    started.zip(finished).map(|(started, finished)| finished - started)
+}
+
+/// The size of an artifact, which is not known for old artifacts
+fn fmt_size(size: Option<i64>) -> String {
+    size.map(|s| bytesize::ByteSize::b(s as u64).to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// The difference of the durations `a` and `b`, with sign
+fn fmt_duration_delta(a: Option<chrono::Duration>, b: Option<chrono::Duration>) -> String {
+    match a.zip(b).map(|(a, b)| b - a) {
+        Some(delta) if delta < chrono::Duration::zero() => format!("-{}", fmt_duration(Some(-delta))),
+        Some(delta) => format!("+{}", fmt_duration(Some(delta))),
+        None => String::from("-"),
+    }
+}
+
 /// Implementation of the "db submits" subcommand
 fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
-    let csv = matches.get_flag("csv");
-    let limit = matches.get_one::<String>("limit").map(|s| s.parse::<i64>()).transpose()?;
+    let format = OutputFormat::from_matches(matches)?;
+    let (limit, offset) = get_limit_and_offset(matches)?;
+    let older_than_filter = get_date_filter("older_than", matches)?;
+    let newer_than_filter = get_date_filter("newer_than", matches)?;
     let hdrs = crate::commands::util::mk_header(vec!["Time", "UUID", "For Package", "For Package Version"]);
     let conn = conn_cfg.establish_connection()?;
 
@@ -359,19 +719,32 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         query
     };
 
+    let query = if let Some(datetime) = older_than_filter.as_ref() {
+        query.filter(schema::submits::submit_time.lt(datetime))
+    } else {
+        query
+    };
+
+    let query = if let Some(datetime) = newer_than_filter.as_ref() {
+        query.filter(schema::submits::submit_time.gt(datetime))
+    } else {
+        query
+    };
+
     let submits = if let Some(pkgname) = matches.get_one::<String>("with_pkg") {
-        // In the case of a with_pkg command, we must execute two queries on the database, as the
-        // diesel framework does not yet support aliases for queries (see
-        // https://github.com/diesel-rs/diesel/pull/2254).
-        // This is due to the fact that we need to join the packages table twice, once to filter
-        // out all submits that did not include the "with pkg" and once to join the requested
-        // package for the output.
-
-        // Get all submits which included the package, but were not necessarily made _for_ the package
+        // Get all submits which included the package, but were not necessarily made _for_ the package.
+        // The jobs of the package are selected in a subquery and not joined, so that each submit is
+        // selected only once and the limit and offset count submits, not jobs.
         let query = query
-            .inner_join(schema::jobs::table)
-            .inner_join(schema::packages::table.on(schema::jobs::package_id.eq(schema::packages::id)))
-            .filter(schema::packages::name.eq(&pkgname));
+            .filter(schema::submits::id.eq_any({
+                schema::jobs::table
+                    .inner_join(schema::packages::table)
+                    .filter(schema::packages::name.eq(pkgname))
+                    .select(schema::jobs::submit_id)
+            }))
+            .inner_join({
+                schema::packages::table.on(schema::submits::requested_package_id.eq(schema::packages::id))
+            });
 
         let query = if let Some(limit) = limit {
             query.limit(limit)
@@ -379,17 +752,13 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
             query
         };
 
-        // Only load the IDs of the submits, so we can later use them to filter the submits
-        let submit_ids = query.select(schema::submits::id).load::<i32>(&conn)?;
-
-        schema::submits::table
-            .order_by(schema::submits::id.desc()) // required for the --limit implementation
-            .inner_join({
-                schema::packages::table.on(schema::submits::requested_package_id.eq(schema::packages::id))
-            })
-            .filter(schema::submits::id.eq_any(submit_ids))
-            .select((schema::submits::all_columns, schema::packages::all_columns))
-            .load::<(models::Submit, models::Package)>(&conn)?
+        if let Some(offset) = offset {
+            query.offset(offset)
+        } else {
+            query
+        }
+        .select((schema::submits::all_columns, schema::packages::all_columns))
+        .load::<(models::Submit, models::Package)>(&conn)?
     } else if let Some(pkgname) = matches.get_one::<String>("for_pkg") {
         // Get all submits _for_ the package
         let query = query
@@ -398,27 +767,37 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
             })
             .filter(schema::packages::dsl::name.eq(&pkgname));
 
-        if let Some(limit) = limit {
+        let query = if let Some(limit) = limit {
             query.limit(limit)
         } else {
             query
+        };
+
+        if let Some(offset) = offset {
+            query.offset(offset)
+        } else {
+            query
         }
         .select((schema::submits::all_columns, schema::packages::all_columns))
         .load::<(models::Submit, models::Package)>(&conn)?
-    } else if let Some(limit) = limit {
-        query
-            .inner_join({
-                schema::packages::table.on(schema::submits::requested_package_id.eq(schema::packages::id))
-            })
-            .select((schema::submits::all_columns, schema::packages::all_columns))
-            .limit(limit)
-            .load::<(models::Submit, models::Package)>(&conn)?
     } else {
-        query.inner_join({
-                schema::packages::table.on(schema::submits::requested_package_id.eq(schema::packages::id))
-            })
-            .select((schema::submits::all_columns, schema::packages::all_columns))
-            .load::<(models::Submit, models::Package)>(&conn)?
+        let query = query.inner_join({
+            schema::packages::table.on(schema::submits::requested_package_id.eq(schema::packages::id))
+        });
+
+        let query = if let Some(limit) = limit {
+            query.limit(limit)
+        } else {
+            query
+        };
+
+        if let Some(offset) = offset {
+            query.offset(offset)
+        } else {
+            query
+        }
+        .select((schema::submits::all_columns, schema::packages::all_columns))
+        .load::<(models::Submit, models::Package)>(&conn)?
     };
 
     // Helper to map (Submit, Package) -> Vec<String>
@@ -435,16 +814,15 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
     if data.is_empty() {
         info!("No submits in database");
-    } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
     }
+    crate::commands::util::display_data_as(hdrs, data, format)?;
 
     Ok(())
 }
 
 /// Implementation of the "db jobs" subcommand
 fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
-    let csv = matches.get_flag("csv");
+    let format = OutputFormat::from_matches(matches)?;
     let hdrs = crate::commands::util::mk_header(vec![
         "Submit",
         "Job",
@@ -454,8 +832,10 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         "Package",
         "Version",
         "Distro",
+        "Duration",
     ]);
     let conn = conn_cfg.establish_connection()?;
+    let (limit, offset) = get_limit_and_offset(matches)?;
     let older_than_filter = get_date_filter("older_than", matches)?;
     let newer_than_filter = get_date_filter("newer_than", matches)?;
 
@@ -490,6 +870,37 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         sel = sel.filter(schema::jobs::dsl::id.eq_any(jids));
     }
 
+    // Filter for the markers the scripts reported, the same way as for the environment
+    if let Some(metric) = matches.get_one::<String>("metric") {
+        let mut markers = schema::job_markers::table
+            .filter(schema::job_markers::kind.eq(models::MARKER_KIND_METRIC))
+            .into_boxed();
+
+        match metric.split_once('=') {
+            Some((key, value)) => {
+                debug!("Filtering for metric: {} = {}", key, value);
+                markers = markers
+                    .filter(schema::job_markers::name.eq(key))
+                    .filter(schema::job_markers::value.eq(value));
+            },
+            None => {
+                debug!("Filtering for metric: {}", metric);
+                markers = markers.filter(schema::job_markers::name.eq(metric));
+            },
+        }
+
+        let jids = markers.select(schema::job_markers::job_id).load::<i32>(&conn)?;
+        sel = sel.filter(schema::jobs::dsl::id.eq_any(jids));
+    }
+
+    if matches.get_flag("with_warnings") {
+        let jids = schema::job_markers::table
+            .filter(schema::job_markers::kind.eq(models::MARKER_KIND_WARNING))
+            .select(schema::job_markers::job_id)
+            .load::<i32>(&conn)?;
+        sel = sel.filter(schema::jobs::dsl::id.eq_any(jids));
+    }
+
     if let Some(datetime) = older_than_filter.as_ref() {
         sel = sel.filter(schema::submits::dsl::submit_time.lt(datetime))
     }
@@ -498,10 +909,14 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         sel = sel.filter(schema::submits::dsl::submit_time.gt(datetime))
     }
 
-    if let Some(limit) = matches.get_one::<String>("limit").map(|s| s.parse::<i64>()).transpose()? {
+    if let Some(limit) = limit {
         sel = sel.limit(limit)
     }
 
+    if let Some(offset) = offset {
+        sel = sel.offset(offset)
+    }
+
     if let Some(ep_name) = matches.get_one::<String>("endpoint") {
         sel = sel.filter(schema::endpoints::name.eq(ep_name))
     }
@@ -515,17 +930,24 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         image_short_name_map.insert(image.name.clone(), image.short_name.clone());
     }
 
+    let known_failures = models::KnownFailure::all(&conn)?;
+
     let data = sel
         .order_by(schema::jobs::id.desc()) // required for the --limit implementation
         .load::<(models::Job, models::Submit, models::Endpoint, models::Package, models::Image)>(&conn)?
         .into_iter()
         .rev() // required for the --limit implementation
         .map(|(job, submit, ep, package, image)| {
-            let success = is_job_successfull(&job)?
-                .map(|b| if b { "yes" } else { "no" })
-                .map(String::from)
-                .unwrap_or_else(|| String::from("unknown"));
+            let success = match is_job_successfull(&job)? {
+                Some(true) => String::from("yes"),
+                _ if models::KnownFailure::any_matches(&known_failures, job.id, &package.name, &package.version, &image.name) => {
+                    String::from("known failure")
+                },
+                Some(false) => String::from("no"),
+                None => String::from("unknown"),
+            };
             let image_name = crate::util::docker::ImageName::from(image.name);
+            let duration = fmt_duration(job_duration(job.started, job.finished));
 
             Ok(vec![
                 submit.uuid.to_string(),
@@ -536,15 +958,15 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
                 package.name,
                 package.version,
                 image_short_name_map.get(&image_name).unwrap_or(&image_name).to_string(),
+                duration,
             ])
         })
         .collect::<Result<Vec<_>>>()?;
 
     if data.is_empty() {
         info!("No submits in database");
-    } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
     }
+    crate::commands::util::display_data_as(hdrs, data, format)?;
 
     Ok(())
 }
@@ -556,7 +978,7 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
     let configured_theme = config.script_highlight_theme();
     let show_log = matches.get_flag("show_log");
     let show_script = matches.get_flag("show_script");
-    let csv = matches.get_flag("csv");
+    let format = OutputFormat::from_matches(matches)?;
     let conn = conn_cfg.establish_connection()?;
     let job_uuid = matches
         .get_one::<String>("job_uuid")
@@ -584,7 +1006,86 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
     let success = parsed_log.is_successfull();
     trace!("log successfull = {:?}", success);
 
-    if csv {
+    let markers = models::JobMarker::belonging_to(&data.0)
+        .order_by(schema::job_markers::id)
+        .load::<models::JobMarker>(&conn)?;
+    let metrics = markers.iter()
+        .filter(|m| m.is_metric())
+        .map(|m| (m.name.clone().unwrap_or_default(), m.value.clone()))
+        .collect::<Vec<_>>();
+    let warnings = markers.iter()
+        .filter(|m| m.is_warning())
+        .map(|m| m.value.clone())
+        .collect::<Vec<_>>();
+
+    // The lines of the log that are shown, all lines if no phase is selected
+    let shown_lines = matches
+        .get_one::<String>("show_phase")
+        .map(|phase| lines_of_phase(&conn, &data.0, phase))
+        .transpose()?;
+    let is_shown = |i: usize| {
+        shown_lines
+            .as_ref()
+            .map(|lines| lines.iter().any(|lines| lines.contains(&i)))
+            .unwrap_or(true)
+    };
+    let shown_text = |text: &str| -> String {
+        if shown_lines.is_some() {
+            text.lines()
+                .enumerate()
+                .filter(|(i, _)| is_shown(*i))
+                .map(|(_, line)| line)
+                .join("\n")
+        } else {
+            text.to_string()
+        }
+    };
+
+    if format == OutputFormat::Json {
+        let env = if matches.get_flag("show_env") {
+            Some({
+                data.0.env(&conn)?
+                    .into_iter()
+                    .map(|env| (env.name, serde_json::Value::String(env.value)))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+        } else {
+            None
+        };
+
+        let mut record = serde_json::json!({
+            "uuid": data.0.uuid.to_string(),
+            "submit": data.1.uuid.to_string(),
+            "success": success.to_bool(),
+            "package_name": data.3.name,
+            "package_version": data.3.version,
+            "endpoint": data.2.name,
+            "image": data.4.name,
+            "container": data.0.container_hash,
+            "started": data.0.started.map(|t| t.to_string()),
+            "finished": data.0.finished.map(|t| t.to_string()),
+            "metrics": metrics
+                .into_iter()
+                .map(|(key, value)| (key, serde_json::Value::String(value)))
+                .collect::<serde_json::Map<_, _>>(),
+            "warnings": warnings,
+        });
+        if let Some(env) = env {
+            record["env"] = serde_json::Value::Object(env);
+        }
+        if show_script {
+            record["script"] = serde_json::Value::String(data.0.script_text);
+        }
+        if show_log {
+            record["log"] = serde_json::Value::String(shown_text(&data.0.log_text));
+        }
+
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        serde_json::to_writer_pretty(&mut outlock, &record)?;
+        writeln!(outlock)?;
+        Ok(())
+    } else if format == OutputFormat::Csv {
         let hdrs = crate::commands::util::mk_header(vec![
             "UUID",
             "Success",
@@ -608,7 +1109,7 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             data.4.name.to_string(),
             data.0.container_hash,
         ]];
-        crate::commands::util::display_data(hdrs, data, csv)
+        crate::commands::util::display_data_as(hdrs, data, format)
     } else {
         let env_vars = if matches.get_flag("show_env") {
             Some({
@@ -625,7 +1126,7 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             None
         };
 
-        let mut out = std::io::stdout();
+        let mut out = crate::ui::Pager::start(matches.get_flag("pager"));
         let s = indoc::formatdoc!(
             r#"
                 Job:        {job_uuid}
@@ -662,6 +1163,12 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
         );
         writeln!(out, "{s}")?;
 
+        if !metrics.is_empty() || !warnings.is_empty() {
+            let metrics = metrics.iter().map(|(key, value)| format!("\tMetric:  {key} = {value}"));
+            let warnings = warnings.iter().map(|w| format!("\t{}: {}", "Warning".yellow(), w));
+            writeln!(out, "{}\n", metrics.chain(warnings).join("\n"))?;
+        }
+
         if let Some(envs) = env_vars {
             let s = indoc::formatdoc!(
                 r#"
@@ -700,12 +1207,20 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
         }
 
         if show_log {
-            let log = parsed_log
-                .into_iter()
-                .map(|line_item| line_item.display().map(|d| d.to_string()))
-                .collect::<Result<Vec<_>>>()?
-                .into_iter() // ugly, but hey... not important right now.
-                .join("\n");
+            let log = if matches.get_flag("raw") {
+                shown_text(&data.0.log_text)
+            } else {
+                let show_times = matches.get_flag("show_log_times");
+                let highlighter = LogHighlighter::new(config.log_highlight())?;
+                parsed_log
+                    .into_timed_iter()
+                    .enumerate()
+                    .filter(|(i, _)| is_shown(*i))
+                    .map(|(_, line_item)| line_item.display(show_times, &highlighter).map(|d| d.to_string()))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter() // ugly, but hey... not important right now.
+                    .join("\n")
+            };
 
             let s = indoc::formatdoc!(
                 r#"
@@ -723,34 +1238,732 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
     }
 }
 
+/// The lines of the log of a job that belong to a phase
+///
+/// The lines are recorded with the phases of the job. For jobs that were run before they were
+/// recorded, they are taken from the log itself.
+fn lines_of_phase(conn: &PgConnection, job: &models::Job, phase: &str) -> Result<Vec<Range<usize>>> {
+    let recorded = models::JobPhase::belonging_to(job)
+        .filter(schema::job_phases::name.eq(phase))
+        .order_by(schema::job_phases::id)
+        .load::<models::JobPhase>(conn)?
+        .iter()
+        .filter_map(models::JobPhase::lines)
+        .collect::<Vec<_>>();
+
+    let lines = if recorded.is_empty() {
+        crate::log::phase_lines(crate::log::ParsedLog::from_str(&job.log_text)?.into_iter())
+            .into_iter()
+            .filter(|(name, _)| name == phase)
+            .map(|(_, lines)| lines)
+            .collect::<Vec<_>>()
+    } else {
+        recorded
+    };
+
+    if lines.is_empty() {
+        return Err(anyhow!("The log of job {} has no phase '{}'", job.uuid, phase))
+    }
+    Ok(lines)
+}
+
 /// Implementation of the subcommand "db log-of"
-fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+///
+/// Also used by the "logs" subcommand, if the log is not followed.
+pub(super) fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     let conn   = conn_cfg.establish_connection()?;
     let job_uuid = matches
         .get_one::<String>("job_uuid")
         .map(|s| uuid::Uuid::parse_str(s.as_ref()))
         .transpose()?
         .unwrap();
-    let out = std::io::stdout();
-    let mut lock = out.lock();
-
-    schema::jobs::table
+    let log_text = schema::jobs::table
         .filter(schema::jobs::dsl::uuid.eq(job_uuid))
         .select(schema::jobs::dsl::log_text)
-        .first::<String>(&conn)
-        .map_err(Error::from)
-        .and_then(|s| crate::log::ParsedLog::from_str(&s))?
+        .first::<String>(&conn)?;
+
+    let filter = LogFilter {
+        phases: matches
+            .get_many::<String>("log_phase")
+            .map(|phases| phases.cloned().collect())
+            .unwrap_or_default(),
+        markers_only: matches.get_flag("markers_only"),
+        errors_context: if matches.get_flag("errors_only") {
+            matches
+                .get_one::<String>("error_context")
+                .map(|s| s.parse::<usize>())
+                .transpose()?
+        } else {
+            None
+        },
+    };
+
+    let mut out = crate::ui::Pager::start(matches.get_flag("pager"));
+    write_log(&mut out, &log_text, &filter, matches.get_flag("raw"))
+}
+
+/// Write the items of a log that match the filter, unparsed if `raw`
+fn write_log<W: Write>(out: &mut W, log_text: &str, filter: &LogFilter, raw: bool) -> Result<()> {
+    if raw && filter.is_empty() {
+        return out.write_all(log_text.as_bytes()).map_err(Error::from)
+    }
+
+    let items = filter.apply(crate::log::ParsedLog::from_str(log_text)?.into_timed_iter().collect());
+    if items.is_empty() && !filter.is_empty() {
+        info!("No log lines match the filter");
+    }
+
+    items
         .into_iter()
-        .map(|line| line.display().and_then(|d| writeln!(lock, "{d}").map_err(Error::from)))
+        .map(|item| {
+            if raw {
+                writeln!(out, "{}", item.raw()?).map_err(Error::from)
+            } else {
+                item.item.display().and_then(|d| writeln!(out, "{d}").map_err(Error::from))
+            }
+        })
         .collect::<Result<Vec<()>>>()
         .map(|_| ())
 }
 
+/// Implementation of the subcommand "db buildinfo-of"
+fn buildinfo_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let conn = conn_cfg.establish_connection()?;
+    let job_uuid = matches
+        .get_one::<String>("job_uuid")
+        .map(|s| uuid::Uuid::parse_str(s.as_ref()))
+        .transpose()?
+        .unwrap();
+    let buildinfo = schema::jobs::table
+        .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+        .select(schema::jobs::dsl::buildinfo)
+        .first::<Option<String>>(&conn)
+        .with_context(|| anyhow!("Loading job {}", job_uuid))?
+        .ok_or_else(|| anyhow!("No buildinfo recorded for job {}", job_uuid))?;
+
+    std::io::stdout().write_all(buildinfo.as_bytes()).map_err(Error::from)
+}
+
+/// Implementation of the subcommand "db diff-artifacts"
+fn diff_artifacts(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let compare_contents = matches.get_flag("contents");
+    let conn = conn_cfg.establish_connection()?;
+
+    let load_artifacts = |name: &str| -> Result<BTreeMap<String, (PathBuf, ArtifactInfo)>> {
+        let job_uuid = matches
+            .get_one::<String>(name)
+            .map(|s| uuid::Uuid::parse_str(s.as_ref()))
+            .transpose()?
+            .unwrap(); // safe by clap
+
+        let (job, submit) = schema::jobs::table
+            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+            .inner_join(schema::submits::table)
+            .first::<(models::Job, models::Submit)>(&conn)
+            .with_context(|| anyhow!("Loading job {} from DB", job_uuid))?;
+
+        schema::artifacts::table
+            .left_join(schema::releases::table.inner_join(schema::release_stores::table))
+            .filter(schema::artifacts::job_id.eq(job.id))
+            .load::<(models::Artifact, Option<(models::Release, models::ReleaseStore)>)>(&conn)?
+            .into_iter()
+            .map(|(artifact, release)| {
+                let path = match release {
+                    Some((_, store)) => config.releases_directory().join(store.store_name).join(&artifact.path),
+                    None => config.staging_directory().join(submit.uuid.to_string()).join(&artifact.path),
+                };
+
+                let name = artifact.path_buf()
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| artifact.path.clone());
+
+                let info = if path.is_file() {
+                    Some(artifact_size_and_checksum(&path)?)
+                } else {
+                    warn!("Artifact file not found: {}", path.display());
+                    None
+                };
+
+                Ok((name, (path, info)))
+            })
+            .collect()
+    };
+
+    let arts_a = load_artifacts("job_a")?;
+    let arts_b = load_artifacts("job_b")?;
+
+    let fmt_info = |info: Option<&ArtifactInfo>| match info {
+        None => (String::from("-"), String::from("-")),
+        Some(None) => (String::from("missing"), String::from("missing")),
+        Some(Some((size, hash))) => (size.to_string(), hash.chars().take(16).collect()),
+    };
+
+    let mut changed_tars = vec![];
+    let data = arts_a.keys()
+        .chain(arts_b.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|name| {
+            let a = arts_a.get(name);
+            let b = arts_b.get(name);
+            let status = artifact_diff_status(a.map(|tpl| &tpl.1), b.map(|tpl| &tpl.1));
+            if let (Some((pa, _)), Some((pb, _)), "changed") = (a, b, status) {
+                changed_tars.push((name.clone(), pa.clone(), pb.clone()));
+            }
+
+            let (size_a, hash_a) = fmt_info(a.map(|tpl| &tpl.1));
+            let (size_b, hash_b) = fmt_info(b.map(|tpl| &tpl.1));
+            vec![name.clone(), String::from(status), size_a, size_b, hash_a, hash_b]
+        })
+        .collect::<Vec<_>>();
+
+    let hdrs = crate::commands::util::mk_header(vec!["Artifact", "Status", "Size A", "Size B", "SHA256 A", "SHA256 B"]);
+    crate::commands::util::display_data(hdrs, data, csv)?;
+
+    if compare_contents {
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+
+        for (name, path_a, path_b) in changed_tars {
+            let (contents_a, contents_b) = match (tar_contents(&path_a)?, tar_contents(&path_b)?) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+
+            writeln!(outlock, "\n{}", name.cyan())?;
+            for entry in contents_a.keys().chain(contents_b.keys()).collect::<BTreeSet<_>>() {
+                match (contents_a.get(entry), contents_b.get(entry)) {
+                    (Some(_), None) => writeln!(outlock, "{} {}", "-".red(), entry)?,
+                    (None, Some(_)) => writeln!(outlock, "{} {}", "+".green(), entry)?,
+                    (Some(sa), Some(sb)) if sa != sb => writeln!(outlock, "{} {} ({} -> {} bytes)", "~".yellow(), entry, sa, sb)?,
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Implementation of the subcommand "db job-diff"
+fn job_diff(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let show_log = matches.get_flag("log");
+    let context = matches
+        .get_one::<String>("context")
+        .map(|s| s.parse::<usize>())
+        .transpose()?
+        .unwrap(); // safe by clap
+    let conn = conn_cfg.establish_connection()?;
+
+    let load_job = |name: &str| -> Result<(models::Job, models::Endpoint, models::Package, models::Image, String)> {
+        let job_uuid = matches
+            .get_one::<String>(name)
+            .map(|s| uuid::Uuid::parse_str(s.as_ref()))
+            .transpose()?
+            .unwrap(); // safe by clap
+
+        let (job, endpoint, package, image) = schema::jobs::table
+            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+            .inner_join(schema::endpoints::table)
+            .inner_join(schema::packages::table)
+            .inner_join(schema::images::table)
+            .first::<(models::Job, models::Endpoint, models::Package, models::Image)>(&conn)
+            .with_context(|| anyhow!("Loading job {} from DB", job_uuid))?;
+
+        let env = job.env(&conn)?
+            .into_iter()
+            .map(|var| format!("{}={}", var.name, var.value))
+            .sorted()
+            .join("\n");
+
+        Ok((job, endpoint, package, image, env))
+    };
+
+    let (job_a, endpoint_a, package_a, image_a, env_a) = load_job("job_a")?;
+    let (job_b, endpoint_b, package_b, image_b, env_b) = load_job("job_b")?;
+
+    let mut sections = vec![
+        ("package", format!("{} {}", package_a.name, package_a.version), format!("{} {}", package_b.name, package_b.version)),
+        ("image", image_a.name, image_b.name),
+        ("endpoint", endpoint_a.name, endpoint_b.name),
+        ("environment", env_a, env_b),
+        ("script", job_a.script_text, job_b.script_text),
+    ];
+    if show_log {
+        sections.push(("log", job_a.log_text, job_b.log_text));
+    }
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    let mut differences = false;
+    for (section, a, b) in sections {
+        let name_a = format!("{} ({})", job_a.uuid, section);
+        let name_b = format!("{} ({})", job_b.uuid, section);
+        let diff = match crate::util::diff::unified_diff(&a, &b, &name_a, &name_b, context) {
+            Some(diff) => diff,
+            None => continue,
+        };
+
+        differences = true;
+        for line in diff.lines() {
+            if line.starts_with("---") || line.starts_with("+++") {
+                writeln!(outlock, "{}", line.bold())?;
+            } else if line.starts_with("@@") {
+                writeln!(outlock, "{}", line.cyan())?;
+            } else if line.starts_with('-') {
+                writeln!(outlock, "{}", line.red())?;
+            } else if line.starts_with('+') {
+                writeln!(outlock, "{}", line.green())?;
+            } else {
+                writeln!(outlock, "{line}")?;
+            }
+        }
+    }
+
+    if !differences {
+        writeln!(outlock, "No differences between job {} and job {}", job_a.uuid, job_b.uuid)?;
+    }
+
+    Ok(())
+}
+
+/// Size and checksum of an artifact file, `None` if the file was not found
+type ArtifactInfo = Option<(u64, String)>;
+
+/// How an artifact differs between two jobs, `None` if the job has no such artifact
+fn artifact_diff_status(a: Option<&ArtifactInfo>, b: Option<&ArtifactInfo>) -> &'static str {
+    match (a, b) {
+        (Some(_), None) => "removed",
+        (None, Some(_)) => "added",
+        (Some(Some(ia)), Some(Some(ib))) if ia == ib => "unchanged",
+        (Some(Some(_)), Some(Some(_))) => "changed",
+        _ => "unknown",
+    }
+}
+
+/// Get the size and the (hex encoded) SHA256 checksum of a file
+fn artifact_size_and_checksum(path: &Path) -> Result<(u64, String)> {
+    use sha2::Digest;
+
+    let mut file = std::fs::File::open(path)
+        .with_context(|| anyhow!("Opening {}", path.display()))?;
+    let mut hasher = sha2::Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher)
+        .with_context(|| anyhow!("Hashing {}", path.display()))?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// Get the file list (path -> size) of a tar artifact
+///
+/// Returns `Ok(None)` if the file is not a (gzipped) tar archive according to its name.
+fn tar_contents(path: &Path) -> Result<Option<BTreeMap<String, u64>>> {
+    fn entries<R: std::io::Read>(mut archive: tar::Archive<R>) -> Result<BTreeMap<String, u64>> {
+        archive.entries()?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.path()?.display().to_string(), entry.header().size()?))
+            })
+            .collect()
+    }
+
+    let name = path.to_string_lossy();
+    let file = || std::fs::File::open(path).with_context(|| anyhow!("Opening {}", path.display()));
+    let contents = if name.ends_with(".tar") {
+        entries(tar::Archive::new(file()?)).map(Some)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        entries(tar::Archive::new(flate2::read::GzDecoder::new(file()?))).map(Some)
+    } else {
+        Ok(None)
+    };
+
+    contents.with_context(|| anyhow!("Reading tar archive {}", path.display()))
+}
+
+/// Implementation of the "db export" subcommand
+fn export(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    // "json" is the only format for now
+    debug!("Exporting in format {:?}", matches.get_one::<String>("format"));
+
+    let conn = conn_cfg.establish_connection()?;
+    let export = crate::db::export::export(&conn)?;
+    info!(
+        "Exporting {} submits, {} jobs, {} artifacts and {} releases",
+        export.submits.len(),
+        export.jobs.len(),
+        export.artifacts.len(),
+        export.releases.len()
+    );
+
+    match matches.get_one::<String>("output") {
+        Some(path) => {
+            let file = std::fs::File::create(path).with_context(|| anyhow!("Creating {}", path))?;
+            let mut writer = std::io::BufWriter::new(file);
+            serde_json::to_writer(&mut writer, &export)?;
+            writer.flush().map_err(Error::from)
+        },
+        None => {
+            let out = std::io::stdout();
+            let mut outlock = out.lock();
+            serde_json::to_writer(&mut outlock, &export)?;
+            writeln!(outlock).map_err(Error::from)
+        },
+    }
+}
+
+/// Implementation of the "db import" subcommand
+fn import(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<String>("file").unwrap(); // safe by clap
+    let file = std::fs::File::open(path).with_context(|| anyhow!("Opening {}", path))?;
+    let export = serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| anyhow!("Parsing export {}", path))?;
+
+    let conn = conn_cfg.establish_connection()?;
+    let stats = crate::db::export::import(&conn, export)?;
+    info!(
+        "Imported {} submits ({} existed already), {} jobs, {} artifacts and {} releases",
+        stats.submits,
+        stats.skipped_submits,
+        stats.jobs,
+        stats.artifacts,
+        stats.releases
+    );
+    Ok(())
+}
+
+/// Implementation of the "db known-failures" subcommand
+fn known_failures(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let conn = conn_cfg.establish_connection()?;
+
+    match matches.subcommand() {
+        Some(("list", matches)) => {
+            let format = OutputFormat::from_matches(matches)?;
+            let hdrs = crate::commands::util::mk_header(vec!["ID", "Created", "Job", "Package", "Version", "Image", "Reason"]);
+            let data = schema::known_failures::table
+                .left_join(schema::jobs::table)
+                .order_by(schema::known_failures::id.asc())
+                .load::<(models::KnownFailure, Option<models::Job>)>(&conn)?
+                .into_iter()
+                .map(|(failure, job)| {
+                    vec![
+                        failure.id.to_string(),
+                        failure.created.format("%Y-%m-%d %H:%M:%S").to_string(),
+                        job.map(|j| j.uuid.to_string()).unwrap_or_else(|| String::from("-")),
+                        failure.package_name.unwrap_or_else(|| String::from("-")),
+                        failure.package_version.unwrap_or_else(|| String::from("*")),
+                        failure.image_name.unwrap_or_else(|| String::from("*")),
+                        failure.reason,
+                    ]
+                })
+                .collect::<Vec<_>>();
+
+            if data.is_empty() {
+                info!("No known failures in database");
+            }
+            crate::commands::util::display_data_as(hdrs, data, format)
+        },
+        Some(("add", matches)) => {
+            let reason = matches.get_one::<String>("reason").unwrap(); // safe by clap
+            let failure = if let Some(job_uuid) = matches.get_one::<String>("job") {
+                let job_uuid = uuid::Uuid::parse_str(job_uuid)?;
+                let job = schema::jobs::table
+                    .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+                    .first::<models::Job>(&conn)
+                    .with_context(|| anyhow!("Loading job {} from DB", job_uuid))?;
+                models::KnownFailure::create_for_job(&conn, &job, reason)?
+            } else {
+                let package = matches.get_one::<String>("package").unwrap(); // safe by clap
+                let version = matches.get_one::<String>("package_version").map(String::as_str);
+                let image = matches.get_one::<String>("image").map(String::as_str);
+                models::KnownFailure::create_for_package(&conn, package, version, image, reason)?
+            };
+
+            writeln!(std::io::stdout(), "Added known failure {}", failure.id)?;
+            Ok(())
+        },
+        Some(("remove", matches)) => {
+            let id = matches.get_one::<String>("id").map(|s| s.parse::<i32>()).transpose()?.unwrap(); // safe by clap
+            if models::KnownFailure::delete(&conn, id)? {
+                writeln!(std::io::stdout(), "Removed known failure {id}")?;
+                Ok(())
+            } else {
+                Err(anyhow!("No known failure with ID {}", id))
+            }
+        },
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "db stats" subcommand
+fn stats(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    use diesel::sql_types::BigInt;
+    use diesel::sql_types::Double;
+    use diesel::sql_types::Integer;
+    use diesel::sql_types::Nullable;
+    use diesel::sql_types::Text;
+    use diesel::sql_types::Timestamptz;
+
+    #[derive(QueryableByName)]
+    struct CountRow {
+        #[sql_type = "Text"]
+        name: String,
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct ResultRow {
+        #[sql_type = "Integer"]
+        job_id: i32,
+        #[sql_type = "Text"]
+        package: String,
+        #[sql_type = "Text"]
+        version: String,
+        #[sql_type = "Text"]
+        image: String,
+        #[sql_type = "Text"]
+        log_tail: String,
+    }
+
+    #[derive(QueryableByName)]
+    struct DurationRow {
+        #[sql_type = "Text"]
+        name: String,
+        #[sql_type = "Nullable<Double>"]
+        seconds: Option<f64>,
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct ArtifactSizeRow {
+        #[sql_type = "Text"]
+        name: String,
+        #[sql_type = "Nullable<Double>"]
+        bytes: Option<f64>,
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct SizeRow {
+        #[sql_type = "Text"]
+        name: String,
+        #[sql_type = "Text"]
+        size: String,
+    }
+
+    let newer_than = get_date_filter("newer_than", matches)?;
+    let weeks = matches.get_one::<String>("weeks").map(|s| i64::from_str(s)).transpose()?.unwrap_or(12);
+    let top = matches.get_one::<String>("top").map(|s| i64::from_str(s)).transpose()?.unwrap_or(10);
+    let conn = conn_cfg.establish_connection()?;
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+
+    // The submits are filtered by their time, the jobs by the time of their submit
+    const SUBMIT_FILTER: &str = "($1::timestamptz IS NULL OR submits.submit_time > $1)";
+
+    writeln!(outlock, "{}", "Totals".bold())?;
+    let totals = diesel::sql_query(format!(r#"
+            SELECT 'Submits' AS name, COUNT(*) AS count FROM submits WHERE {SUBMIT_FILTER}
+            UNION ALL
+            SELECT 'Jobs', COUNT(*) FROM jobs JOIN submits ON submits.id = jobs.submit_id WHERE {SUBMIT_FILTER}
+            UNION ALL
+            SELECT 'Artifacts', COUNT(*) FROM artifacts
+                JOIN jobs ON jobs.id = artifacts.job_id
+                JOIN submits ON submits.id = jobs.submit_id
+                WHERE {SUBMIT_FILTER}
+            UNION ALL
+            SELECT 'Releases', COUNT(*) FROM releases
+                JOIN artifacts ON artifacts.id = releases.artifact_id
+                JOIN jobs ON jobs.id = artifacts.job_id
+                JOIN submits ON submits.id = jobs.submit_id
+                WHERE {SUBMIT_FILTER}
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .load::<CountRow>(&conn)?
+        .into_iter()
+        .map(|row| vec![row.name, row.count.to_string()])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["", "Count"]), totals, false)?;
+
+    writeln!(outlock, "\n{}", "Submits per week".bold())?;
+    let per_week = diesel::sql_query(format!(r#"
+            SELECT to_char(date_trunc('week', submit_time), 'YYYY-MM-DD') AS name, COUNT(*) AS count
+            FROM submits
+            WHERE {SUBMIT_FILTER} AND submit_time > date_trunc('week', now()) - make_interval(weeks => $2::int)
+            GROUP BY 1
+            ORDER BY 1
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .bind::<BigInt, _>(weeks - 1)
+        .load::<CountRow>(&conn)?
+        .into_iter()
+        .map(|row| vec![row.name, row.count.to_string()])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["Week", "Submits"]), per_week, false)?;
+
+    // The result of a job is the last state in its log, so only the end of the log is loaded
+    let known_failures = models::KnownFailure::all(&conn)?;
+    let results = diesel::sql_query(format!(r#"
+            SELECT jobs.id AS job_id, packages.name AS package, packages.version AS version, images.name AS image,
+                right(jobs.log_text, 16384) AS log_tail
+            FROM jobs
+            JOIN submits ON submits.id = jobs.submit_id
+            JOIN packages ON packages.id = jobs.package_id
+            JOIN images ON images.id = jobs.image_id
+            WHERE {SUBMIT_FILTER}
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .load::<ResultRow>(&conn)?
+        .into_iter()
+        .map(|row| {
+            let result = crate::log::ParsedLog::from_str(&row.log_tail)?.is_successfull();
+            let known_failure = result != JobResult::Success
+                && models::KnownFailure::any_matches(&known_failures, row.job_id, &row.package, &row.version, &row.image);
+            Ok((row.package, row.image, result, known_failure))
+        })
+        .collect::<Result<Vec<JobStats>>>()?;
+
+    writeln!(outlock, "\n{}", "Success rate per package".bold())?;
+    let data = success_rates(&results, |r| &r.0);
+    let hdrs = crate::commands::util::mk_header(vec!["Package", "Jobs", "Success", "Known failures", "Success w/o known failures"]);
+    crate::commands::util::display_data(hdrs, data, false)?;
+
+    writeln!(outlock, "\n{}", "Success rate per image".bold())?;
+    let data = success_rates(&results, |r| &r.1);
+    let hdrs = crate::commands::util::mk_header(vec!["Image", "Jobs", "Success", "Known failures", "Success w/o known failures"]);
+    crate::commands::util::display_data(hdrs, data, false)?;
+
+    const DURATION_FILTER: &str = "jobs.started IS NOT NULL AND jobs.finished IS NOT NULL";
+
+    writeln!(outlock, "\n{}", "Average job duration".bold())?;
+    let average = diesel::sql_query(format!(r#"
+            SELECT 'all' AS name,
+                EXTRACT(EPOCH FROM AVG(jobs.finished - jobs.started))::float8 AS seconds,
+                COUNT(*) AS count
+            FROM jobs
+            JOIN submits ON submits.id = jobs.submit_id
+            WHERE {SUBMIT_FILTER} AND {DURATION_FILTER}
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .get_result::<DurationRow>(&conn)?;
+    writeln!(outlock, "{} ({} jobs)", fmt_seconds(average.seconds), average.count)?;
+
+    writeln!(outlock, "\n{}", "Slowest packages".bold())?;
+    let data = diesel::sql_query(format!(r#"
+            SELECT packages.name AS name,
+                EXTRACT(EPOCH FROM AVG(jobs.finished - jobs.started))::float8 AS seconds,
+                COUNT(*) AS count
+            FROM jobs
+            JOIN submits ON submits.id = jobs.submit_id
+            JOIN packages ON packages.id = jobs.package_id
+            WHERE {SUBMIT_FILTER} AND {DURATION_FILTER}
+            GROUP BY packages.name
+            ORDER BY seconds DESC
+            LIMIT $2
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .bind::<BigInt, _>(top)
+        .load::<DurationRow>(&conn)?
+        .into_iter()
+        .map(|row| vec![row.name, fmt_seconds(row.seconds), row.count.to_string()])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["Package", "Average duration", "Jobs"]), data, false)?;
+
+    // The size of a job is the sum of the sizes of its artifacts
+    writeln!(outlock, "\n{}", "Largest packages".bold())?;
+    let data = diesel::sql_query(format!(r#"
+            SELECT packages.name AS name, AVG(job_sizes.size)::float8 AS bytes, COUNT(*) AS count
+            FROM (
+                SELECT artifacts.job_id AS job_id, SUM(artifacts.size) AS size
+                FROM artifacts
+                WHERE artifacts.size IS NOT NULL
+                GROUP BY artifacts.job_id
+            ) AS job_sizes
+            JOIN jobs ON jobs.id = job_sizes.job_id
+            JOIN submits ON submits.id = jobs.submit_id
+            JOIN packages ON packages.id = jobs.package_id
+            WHERE {SUBMIT_FILTER}
+            GROUP BY packages.name
+            ORDER BY bytes DESC
+            LIMIT $2
+        "#))
+        .bind::<Nullable<Timestamptz>, _>(newer_than)
+        .bind::<BigInt, _>(top)
+        .load::<ArtifactSizeRow>(&conn)?
+        .into_iter()
+        .map(|row| vec![row.name, fmt_size(row.bytes.map(|b| b.round() as i64)), row.count.to_string()])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["Package", "Average size", "Jobs"]), data, false)?;
+
+    writeln!(outlock, "\n{}", "Database size per table".bold())?;
+    let data = diesel::sql_query(r#"
+            SELECT relname::text AS name, pg_size_pretty(pg_total_relation_size(relid)) AS size
+            FROM pg_catalog.pg_statio_user_tables
+            ORDER BY pg_total_relation_size(relid) DESC
+        "#)
+        .load::<SizeRow>(&conn)?
+        .into_iter()
+        .map(|row| vec![row.name, row.size])
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["Table", "Size"]), data, false)
+}
+
+/// The package, the image and the result of a job, and whether it is a known failure
+type JobStats = (String, String, JobResult, bool);
+
+/// The rows of the success rates of the jobs, grouped by `key`
+///
+/// The success rate without the known failures shows the regressions.
+fn success_rates<'a, F>(results: &'a [JobStats], key: F) -> Vec<Vec<String>>
+where
+    F: Fn(&'a JobStats) -> &'a String,
+{
+    let mut map: BTreeMap<&String, (usize, usize, usize)> = BTreeMap::new();
+    for result in results.iter() {
+        let entry = map.entry(key(result)).or_default();
+        entry.0 += 1;
+        if result.2 == JobResult::Success {
+            entry.1 += 1;
+        }
+        if result.3 {
+            entry.2 += 1;
+        }
+    }
+
+    map.into_iter()
+        .map(|(name, (jobs, succeeded, known))| {
+            vec![
+                name.clone(),
+                jobs.to_string(),
+                format!("{:.1}%", succeeded as f64 * 100.0 / jobs as f64),
+                known.to_string(),
+                if jobs > known {
+                    format!("{:.1}%", succeeded as f64 * 100.0 / (jobs - known) as f64)
+                } else {
+                    String::from("-")
+                },
+            ]
+        })
+        .collect()
+}
+
+/// Format a duration in seconds, e.g. an average
+fn fmt_seconds(seconds: Option<f64>) -> String {
+    seconds
+        .map(|s| humantime::format_duration(std::time::Duration::from_secs(s.round() as u64)).to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
 /// Implementation of the "db releases" subcommand
 fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
-    let csv = matches.get_flag("csv");
+    let format = OutputFormat::from_matches(matches)?;
     let conn   = conn_cfg.establish_connection()?;
-    let header = crate::commands::util::mk_header(["Package", "Version", "Date", "Path"].to_vec());
+    let include_missing = matches.get_flag("include_missing");
+    let header = crate::commands::util::mk_header(["ID", "Package", "Version", "Date", "Partial", "Rollback", "Path", "File"].to_vec());
     let mut query = schema::jobs::table
         .inner_join(schema::packages::table)
         .inner_join(schema::artifacts::table)
@@ -791,22 +2004,42 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         .into_iter()
         .filter_map(|(art, pack, rel, rstore)| {
             let p = config.releases_directory().join(rstore.store_name).join(art.path);
+            let file_state = release_file_state(&p, include_missing);
+            if file_state != Some("present") {
+                warn!("Released file for {} {} not found: {}", pack.name, pack.version, p.display());
+            }
 
-            if p.is_file() {
-                Some(vec![
+            file_state.map(|file_state| {
+                vec![
+                    rel.id.to_string(),
                     pack.name,
                     pack.version,
                     rel.release_date.to_string(),
+                    if rel.partial { "yes" } else { "no" }.to_string(),
+                    match rel.rollback_of {
+                        Some(id) => format!("of {}: {}", id, rel.reason.as_deref().unwrap_or("")),
+                        None => String::from("-"),
+                    },
                     p.display().to_string(),
-                ])
-            } else {
-                warn!("Released file for {} {} not found: {}", pack.name, pack.version, p.display());
-                None
-            }
+                    file_state.to_string(),
+                ]
+            })
         })
         .collect::<Vec<Vec<_>>>();
 
-    crate::commands::util::display_data(header, data, csv)
+    crate::commands::util::display_data_as(header, data, format)
+}
+
+/// Whether the released file at `path` is "present" or "missing", `None` if a missing file is not
+/// listed
+fn release_file_state(path: &Path, include_missing: bool) -> Option<&'static str> {
+    if path.is_file() {
+        Some("present")
+    } else if include_missing {
+        Some("missing")
+    } else {
+        None
+    }
 }
 
 /// Check if a job is successful
@@ -816,3 +2049,249 @@ fn is_job_successfull(job: &models::Job) -> Result<Option<bool>> {
     crate::log::ParsedLog::from_str(&job.log_text).map(|pl| pl.is_successfull().to_bool())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary directory for the files of a test, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("butido-test-db-{}-{}", name, uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_release_file_state() {
+        let dir = TestDir::new("release-file-state");
+        let file = dir.0.join("a-1.tar");
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(release_file_state(&file, false), Some("present"));
+        assert_eq!(release_file_state(&file, true), Some("present"));
+        assert_eq!(release_file_state(&dir.0.join("missing.tar"), false), None);
+        assert_eq!(release_file_state(&dir.0.join("missing.tar"), true), Some("missing"));
+
+        // A directory is not a released file
+        assert_eq!(release_file_state(&dir.0, true), Some("missing"));
+    }
+
+    #[test]
+    fn test_releases_args() {
+        let matches = crate::cli::cli()
+            .try_get_matches_from(["butido", "db", "releases", "--store", "prod", "--include-missing"])
+            .unwrap();
+        let matches = matches.subcommand_matches("db").unwrap().subcommand_matches("releases").unwrap();
+        assert_eq!(matches.get_one::<String>("store").map(String::as_str), Some("prod"));
+        assert!(matches.get_flag("include_missing"));
+    }
+
+    #[test]
+    fn test_combine_job_results() {
+        use JobResult::*;
+        assert_eq!(combine_job_results(Success, Success), Success);
+        assert_eq!(combine_job_results(Success, Unknown), Unknown);
+        assert_eq!(combine_job_results(Unknown, Errored), Errored);
+        assert_eq!(combine_job_results(Errored, Success), Errored);
+    }
+
+    #[test]
+    fn test_submit_diff_status() {
+        use JobResult::*;
+        assert_eq!(submit_diff_status(Some(Success), None), "removed");
+        assert_eq!(submit_diff_status(None, Some(Errored)), "added");
+        assert_eq!(submit_diff_status(Some(Success), Some(Success)), "unchanged");
+        assert_eq!(submit_diff_status(Some(Errored), Some(Success)), "fixed");
+        assert_eq!(submit_diff_status(Some(Unknown), Some(Success)), "fixed");
+        assert_eq!(submit_diff_status(Some(Success), Some(Errored)), "broken");
+        assert_eq!(submit_diff_status(Some(Success), Some(Unknown)), "broken");
+        assert_eq!(submit_diff_status(Some(Errored), Some(Errored)), "unchanged");
+    }
+
+    #[test]
+    fn test_fmt_duration_delta() {
+        let secs = |s| Some(chrono::Duration::seconds(s));
+        assert_eq!(fmt_duration_delta(secs(60), secs(90)), "+30s");
+        assert_eq!(fmt_duration_delta(secs(90), secs(60)), "-30s");
+        assert_eq!(fmt_duration_delta(secs(60), secs(60)), "+0s");
+        assert_eq!(fmt_duration_delta(None, secs(60)), "-");
+        assert_eq!(fmt_duration(secs(3661)), "1h 1m 1s");
+        assert_eq!(fmt_duration(None), "-");
+    }
+
+    #[test]
+    fn test_job_duration_and_size() {
+        let started = chrono::NaiveDateTime::parse_from_str("2022-01-01 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let finished = chrono::NaiveDateTime::parse_from_str("2022-01-01 10:02:05", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(fmt_duration(job_duration(Some(started), Some(finished))), "2m 5s");
+        assert_eq!(fmt_duration(job_duration(Some(started), None)), "-");
+        assert_eq!(fmt_duration(job_duration(None, None)), "-");
+
+        assert_eq!(fmt_size(Some(1_000)), bytesize::ByteSize::b(1_000).to_string());
+        assert_eq!(fmt_size(Some(0)), "0 B");
+        assert_eq!(fmt_size(None), "unknown");
+    }
+
+    #[test]
+    fn test_write_log() {
+        let log_text = "setup\n#BUTIDO:PHASE:build\ncompiling  a\n#BUTIDO:STATE:OK\n";
+        let write = |filter: &LogFilter, raw: bool| {
+            let mut out = Vec::new();
+            write_log(&mut out, log_text, filter, raw).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let no_filter = LogFilter::default();
+        let build_phase = LogFilter { phases: vec![String::from("build")], ..LogFilter::default() };
+
+        // the raw log is written as it is stored
+        assert_eq!(write(&no_filter, true), log_text);
+        assert_eq!(write(&build_phase, true), "#BUTIDO:PHASE:build\ncompiling  a\n#BUTIDO:STATE:OK\n");
+
+        let parsed = write(&build_phase, false);
+        assert_eq!(parsed.lines().count(), 3);
+        assert!(!parsed.contains("setup"));
+        assert!(parsed.contains("compiling  a"));
+
+        let markers = LogFilter { markers_only: true, ..LogFilter::default() };
+        assert_eq!(write(&markers, true), "#BUTIDO:PHASE:build\n#BUTIDO:STATE:OK\n");
+    }
+
+    #[test]
+    fn test_success_rates() {
+        let job = |package: &str, result: JobResult, known_failure: bool| {
+            (String::from(package), String::from("image"), result, known_failure)
+        };
+        let results = vec![
+            job("a", JobResult::Success, false),
+            job("a", JobResult::Errored, true),
+            job("a", JobResult::Errored, false),
+            job("a", JobResult::Success, false),
+            job("b", JobResult::Unknown, true),
+        ];
+
+        assert_eq!(success_rates(&results, |r| &r.0), vec![
+            vec!["a", "4", "50.0%", "1", "66.7%"],
+            vec!["b", "1", "0.0%", "1", "-"],
+        ]);
+        assert_eq!(success_rates(&results, |r| &r.1), vec![
+            vec!["image", "5", "40.0%", "2", "66.7%"],
+        ]);
+        assert!(success_rates(&[], |r| &r.0).is_empty());
+    }
+
+    #[test]
+    fn test_fmt_seconds() {
+        assert_eq!(fmt_seconds(Some(125.4)), "2m 5s");
+        assert_eq!(fmt_seconds(Some(0.6)), "1s");
+        assert_eq!(fmt_seconds(None), "unknown");
+    }
+
+    #[test]
+    fn test_pg_env() {
+        assert!(pg_env(false, None).is_empty());
+        assert!(pg_env(false, Some("-c statement_timeout=5s")).is_empty());
+
+        assert_eq!(pg_env(true, None), vec![("PGOPTIONS", String::from("-c default_transaction_read_only=on"))]);
+        assert_eq!(pg_env(true, Some("")), vec![("PGOPTIONS", String::from("-c default_transaction_read_only=on"))]);
+
+        // existing options are kept, read-only is set last so it is not overridden
+        assert_eq!(
+            pg_env(true, Some("-c statement_timeout=5s")),
+            vec![("PGOPTIONS", String::from("-c statement_timeout=5s -c default_transaction_read_only=on"))]
+        );
+        let (_, options) = &pg_env(true, Some("-c default_transaction_read_only=off"))[0];
+        assert!(options.ends_with("-c default_transaction_read_only=on"), "{options}");
+    }
+
+    #[test]
+    fn test_embedded_migrations() {
+        let versions = embedded_migrations::versions().collect::<Vec<_>>();
+        let mut dirs = std::fs::read_dir("migrations")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with('.'))
+            .collect::<Vec<_>>();
+        dirs.sort();
+
+        assert_eq!(versions.len(), dirs.len());
+        assert!(versions.windows(2).all(|w| w[0] < w[1]), "Not sorted: {:?}", versions);
+        for (version, dir) in versions.iter().zip(dirs.iter()) {
+            assert_eq!(*version, dir.split('_').next().unwrap().replace('-', ""));
+        }
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let embedded = ["20201022", "20201023", "20201024"];
+        let applied = |versions: &[&str]| versions.iter().map(|v| v.to_string()).collect::<HashSet<_>>();
+
+        assert!(pending_migrations(embedded.into_iter(), &applied(&embedded)).is_empty());
+        assert_eq!(pending_migrations(embedded.into_iter(), &applied(&[])), embedded);
+        assert_eq!(pending_migrations(embedded.into_iter(), &applied(&["20201022"])), ["20201023", "20201024"]);
+
+        // Migrations of a newer butido version are not pending
+        assert_eq!(pending_migrations(embedded.into_iter(), &applied(&["20201022", "20201023", "20201030"])), ["20201024"]);
+    }
+
+    #[test]
+    fn test_artifact_diff_status() {
+        let info = |size, hash: &str| Some((size, String::from(hash)));
+
+        assert_eq!(artifact_diff_status(Some(&info(1, "a")), None), "removed");
+        assert_eq!(artifact_diff_status(None, Some(&info(1, "a"))), "added");
+        assert_eq!(artifact_diff_status(Some(&info(1, "a")), Some(&info(1, "a"))), "unchanged");
+        assert_eq!(artifact_diff_status(Some(&info(1, "a")), Some(&info(1, "b"))), "changed");
+        assert_eq!(artifact_diff_status(Some(&info(1, "a")), Some(&info(2, "a"))), "changed");
+        assert_eq!(artifact_diff_status(Some(&None), Some(&info(1, "a"))), "unknown");
+        assert_eq!(artifact_diff_status(Some(&None), Some(&None)), "unknown");
+    }
+
+    #[test]
+    fn test_artifact_size_and_checksum() {
+        let dir = TestDir::new("checksum");
+        let path = dir.0.join("foo");
+        std::fs::write(&path, "foo").unwrap();
+
+        let (size, checksum) = artifact_size_and_checksum(&path).unwrap();
+        assert_eq!(size, 3);
+        assert_eq!(checksum, "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae");
+        assert!(artifact_size_and_checksum(&dir.0.join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_tar_contents() {
+        let dir = TestDir::new("tar");
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "usr/bin/foo", &b"hello"[..]).unwrap();
+        let tar = builder.into_inner().unwrap();
+
+        let tar_path = dir.0.join("foo-1.0.tar");
+        std::fs::write(&tar_path, &tar).unwrap();
+        let contents = tar_contents(&tar_path).unwrap().unwrap();
+        assert_eq!(contents, BTreeMap::from([(String::from("usr/bin/foo"), 5)]));
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut gz, &tar).unwrap();
+        let tgz_path = dir.0.join("foo-1.0.tar.gz");
+        std::fs::write(&tgz_path, gz.finish().unwrap()).unwrap();
+        assert_eq!(tar_contents(&tgz_path).unwrap().unwrap(), contents);
+
+        let other_path = dir.0.join("foo-1.0.rpm");
+        std::fs::write(&other_path, "").unwrap();
+        assert!(tar_contents(&other_path).unwrap().is_none());
+    }
+}
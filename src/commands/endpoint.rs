@@ -257,7 +257,7 @@ async fn containers_prune(endpoint_names: Vec<EndpointName>,
         .await?;
 
     let prompt = format!("Really delete {} Containers?", stats.iter().flatten().count());
-    if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+    if !crate::ui::confirm(prompt)? {
         return Ok(())
     }
 
@@ -400,7 +400,7 @@ async fn containers_stop(endpoint_names: Vec<EndpointName>,
         .await?;
 
     let prompt = format!("Really stop {} Containers?", stats.iter().flatten().count());
-    if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+    if !crate::ui::confirm(prompt)? {
         return Ok(())
     }
 
@@ -59,12 +59,10 @@ pub async fn container(endpoint_names: Vec<EndpointName>,
         .await?
         .ok_or_else(|| anyhow!("Cannot find container {} on {}", container_id, relevant_endpoint.name()))?;
 
-    let confirm = |prompt: String| dialoguer::Confirm::new().with_prompt(prompt).interact();
-
     match matches.subcommand() {
         Some(("top", matches))  => top(matches, container).await,
         Some(("kill", matches)) => {
-            confirm({
+            crate::ui::confirm({
                 if let Some(sig) = matches.get_one::<String>("signal") {
                     format!("Really kill {container_id} with {sig}?")
                 } else {
@@ -75,21 +73,21 @@ pub async fn container(endpoint_names: Vec<EndpointName>,
             kill(matches, container).await
         },
         Some(("delete", _)) => {
-            if confirm(format!("Really delete {container_id}?"))? {
+            if crate::ui::confirm(format!("Really delete {container_id}?"))? {
                 delete(container).await
             } else {
                 Ok(())
             }
         },
         Some(("start", _))      => {
-            if confirm(format!("Really start {container_id}?"))? {
+            if crate::ui::confirm(format!("Really start {container_id}?"))? {
                 start(container).await
             } else {
                 Ok(())
             }
         },
         Some(("stop", matches)) => {
-            if confirm(format!("Really stop {container_id}?"))? {
+            if crate::ui::confirm(format!("Really stop {container_id}?"))? {
                 stop(matches, container).await
             } else {
                 Ok(())
@@ -97,7 +95,7 @@ pub async fn container(endpoint_names: Vec<EndpointName>,
         },
         Some(("exec", matches)) => {
             let commands = matches.get_many::<String>("commands").unwrap().map(AsRef::as_ref).collect::<Vec<&str>>();
-            if confirm(format!("Really run '{}' in {}?", commands.join(" "), container_id))? {
+            if crate::ui::confirm(format!("Really run '{}' in {}?", commands.join(" "), container_id))? {
                 exec(matches, container).await
             } else {
                 Ok(())
@@ -12,6 +12,7 @@
 
 use std::convert::TryFrom;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use clap::ArgMatches;
@@ -20,6 +21,7 @@ use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 
 use crate::config::Configuration;
+use crate::package::Package;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
 use crate::ui::*;
@@ -57,7 +59,16 @@ pub async fn find_pkg(
 
     let out = std::io::stdout();
     let mut outlock = out.lock();
-    if matches.get_flag("terse") {
+    if matches.get_flag("show_effective") {
+        for (i, p) in iter.enumerate() {
+            if i > 0 {
+                writeln!(outlock)?;
+            }
+            writeln!(outlock, "# {} {}", p.name(), p.version())?;
+            write!(outlock, "{}", effective_definition(p)?)?;
+        }
+        Ok(())
+    } else if matches.get_flag("terse") {
         for p in iter {
             writeln!(outlock, "{} {}", p.name(), p.version())?;
         }
@@ -103,3 +114,98 @@ pub async fn find_pkg(
         .await
     }
 }
+
+/// The merged definition of a package as TOML document
+fn effective_definition(package: &Package) -> Result<toml_edit::Document> {
+    match serde_json::to_value(package)? {
+        serde_json::Value::Object(map) => {
+            let mut document = toml_edit::Document::new();
+            *document.as_table_mut() = toml_table(&map);
+            Ok(document)
+        },
+        other => Err(anyhow!("Package serialized to {}, not a table", other)),
+    }
+}
+
+fn toml_table(map: &serde_json::Map<String, serde_json::Value>) -> toml_edit::Table {
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+    for (key, value) in map {
+        if let Some(item) = toml_item(value) {
+            table.insert(key.as_str(), item);
+        }
+    }
+    table
+}
+
+/// Convert a JSON value to a TOML item, `None` for null (unset) values
+fn toml_item(value: &serde_json::Value) -> Option<toml_edit::Item> {
+    match value {
+        serde_json::Value::Object(map) => Some(toml_edit::Item::Table(toml_table(map))),
+        serde_json::Value::Array(values) if !values.is_empty() && values.iter().all(serde_json::Value::is_object) => {
+            let mut tables = toml_edit::ArrayOfTables::new();
+            for map in values.iter().filter_map(serde_json::Value::as_object) {
+                tables.push(toml_table(map));
+            }
+            Some(toml_edit::Item::ArrayOfTables(tables))
+        },
+        other => toml_value(other).map(toml_edit::Item::Value),
+    }
+}
+
+fn toml_value(value: &serde_json::Value) -> Option<toml_edit::Value> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(b) => Some(toml_edit::Value::from(*b)),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Some(toml_edit::Value::from(i)),
+            None => n.as_f64().map(toml_edit::Value::from),
+        },
+        serde_json::Value::String(s) => Some(toml_edit::Value::from(s.as_str())),
+        serde_json::Value::Array(values) => {
+            let mut array = toml_edit::Array::new();
+            for value in values.iter().filter_map(toml_value) {
+                array.push(value);
+            }
+            Some(toml_edit::Value::Array(array))
+        },
+        serde_json::Value::Object(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in map {
+                if let Some(value) = toml_value(value) {
+                    table.insert(key.as_str(), value);
+                }
+            }
+            Some(toml_edit::Value::InlineTable(table))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_item() {
+        let value = serde_json::json!({
+            "name": "foo",
+            "unset": null,
+            "flags": ["a", "b"],
+            "env": { "FOO": "bar" },
+            "patches": [{ "path": "a.patch" }],
+            "nested": [[1, 2], [{ "x": true }]],
+        });
+
+        let mut document = toml_edit::Document::new();
+        *document.as_table_mut() = toml_table(value.as_object().unwrap());
+        let document = document.to_string().parse::<toml_edit::Document>().unwrap();
+
+        assert_eq!(document["name"].as_str(), Some("foo"));
+        assert!(document.get("unset").is_none());
+        assert_eq!(document["flags"].as_array().unwrap().len(), 2);
+        assert_eq!(document["env"]["FOO"].as_str(), Some("bar"));
+        assert_eq!(document["patches"].as_array_of_tables().unwrap().len(), 1);
+        assert_eq!(document["patches"][0]["path"].as_str(), Some("a.patch"));
+        assert_eq!(document["nested"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,384 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'init' subcommand
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use dialoguer::Confirm;
+use dialoguer::Input;
+use itertools::Itertools;
+
+/// The phases of the generated configuration and skeleton repository
+const PHASES: &[&str] = &["unpack", "patch", "configure", "build", "fixup", "pack"];
+
+/// The answers that are used to generate the configuration
+struct InitSettings {
+    database_host: String,
+    database_port: u16,
+    database_user: String,
+    database_password: String,
+    database_name: String,
+    staging: PathBuf,
+    releases_root: PathBuf,
+    release_stores: Vec<String>,
+    source_cache: PathBuf,
+    log_dir: PathBuf,
+
+    /// Container images as (name, short name)
+    images: Vec<(String, String)>,
+    endpoints: Vec<InitEndpoint>,
+}
+
+struct InitEndpoint {
+    name: String,
+    uri: String,
+    endpoint_type: &'static str,
+    maxjobs: usize,
+}
+
+/// Implementation of the "init" subcommand
+///
+/// This runs before the repository and the configuration are loaded, because there is none yet.
+pub async fn init(matches: &ArgMatches) -> Result<()> {
+    if !crate::ui::is_interactive() {
+        return Err(anyhow!("The setup wizard can only be used in an interactive terminal"));
+    }
+
+    let root = std::env::current_dir().context("Getting the current directory")?;
+    let config_path = root.join("config.toml");
+    if config_path.exists() && !matches.get_flag("force") {
+        return Err(anyhow!("{} exists already, use --force to overwrite it", config_path.display()));
+    }
+
+    if git2::Repository::open(&root).is_err() {
+        let prompt = format!("{} is not a git repository, initialize one?", root.display());
+        if Confirm::new().with_prompt(prompt).default(true).interact()? {
+            git2::Repository::init(&root).context("Initializing the git repository")?;
+        } else {
+            println!("Butido must be executed in the top-level of a git repository, remember to create one");
+        }
+    }
+
+    let settings = ask_settings()?;
+    std::fs::write(&config_path, render_config(&settings))
+        .with_context(|| anyhow!("Writing {}", config_path.display()))?;
+    println!("Wrote {}", config_path.display());
+
+    let directories = std::iter::once(&settings.staging)
+        .chain(std::iter::once(&settings.source_cache))
+        .chain(std::iter::once(&settings.log_dir))
+        .cloned()
+        .chain(settings.release_stores.iter().map(|store| settings.releases_root.join(store)))
+        .filter(|dir| !dir.exists())
+        .collect::<Vec<_>>();
+
+    if !directories.is_empty() {
+        let prompt = format!("Create the missing directories {}?", directories.iter().map(|d| d.display()).join(", "));
+        if Confirm::new().with_prompt(prompt).default(true).interact()? {
+            for dir in directories.iter() {
+                std::fs::create_dir_all(dir).with_context(|| anyhow!("Creating {}", dir.display()))?;
+            }
+        }
+    }
+
+    if Confirm::new().with_prompt("Create a skeleton package repository?").default(!root.join("pkg.toml").exists()).interact()? {
+        write_skeleton(&root)?;
+    }
+
+    println!("Run 'butido config check' to verify the configuration and 'butido db setup' to set up the database");
+    Ok(())
+}
+
+fn ask_settings() -> Result<InitSettings> {
+    let data_home = xdg::BaseDirectories::with_prefix("butido")?.get_data_home();
+    let ask_path = |prompt: &str, default: PathBuf| -> Result<PathBuf> {
+        Input::<String>::new()
+            .with_prompt(prompt)
+            .default(default.display().to_string())
+            .interact_text()
+            .map(PathBuf::from)
+            .map_err(anyhow::Error::from)
+    };
+
+    println!("Database");
+    let database_host = Input::new().with_prompt("Host").default(String::from("localhost")).interact_text()?;
+    let database_port = Input::new().with_prompt("Port").default(5432).interact_text()?;
+    let database_user = Input::new().with_prompt("User").default(String::from("butido")).interact_text()?;
+    let database_password = dialoguer::Password::new().with_prompt("Password").allow_empty_password(true).interact()?;
+    let database_name = Input::new().with_prompt("Database name").default(String::from("butido")).interact_text()?;
+
+    println!("Stores");
+    let staging = ask_path("Staging directory", data_home.join("staging"))?;
+    let releases_root = ask_path("Releases directory", data_home.join("releases"))?;
+    let release_stores = Input::<String>::new()
+        .with_prompt("Release stores (comma separated)")
+        .default(String::from("default"))
+        .interact_text()?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let source_cache = ask_path("Source cache directory", data_home.join("sources"))?;
+    let log_dir = ask_path("Log directory", data_home.join("logs"))?;
+
+    println!("Images");
+    let mut images = Vec::new();
+    loop {
+        let name = Input::<String>::new()
+            .with_prompt("Image (empty to finish)")
+            .default(if images.is_empty() { String::from("debian:bullseye") } else { String::new() })
+            .allow_empty(true)
+            .interact_text()?;
+        if name.trim().is_empty() {
+            break
+        }
+
+        let short_name = Input::<String>::new()
+            .with_prompt("Short name")
+            .default(name.trim().replace([':', '/'], "-"))
+            .interact_text()?;
+        images.push((name.trim().to_string(), short_name));
+    }
+
+    println!("Endpoints");
+    let mut endpoints = Vec::new();
+    let mut add_endpoint_default = true;
+    while Confirm::new().with_prompt("Add a docker endpoint?").default(add_endpoint_default).interact()? {
+        let name = Input::new().with_prompt("Name").default(String::from("localhost")).interact_text()?;
+        let endpoint_type = match dialoguer::Select::new().with_prompt("Type").items(&["socket", "http"]).default(0).interact()? {
+            0 => "socket",
+            _ => "http",
+        };
+        let default_uri = if endpoint_type == "socket" { "/var/run/docker.sock" } else { "http://localhost:2375" };
+        let uri = Input::new().with_prompt("URI").default(String::from(default_uri)).interact_text()?;
+        let maxjobs = Input::new().with_prompt("Maximum number of parallel jobs").default(1).interact_text()?;
+
+        endpoints.push(InitEndpoint { name, uri, endpoint_type, maxjobs });
+        add_endpoint_default = false;
+    }
+
+    Ok(InitSettings {
+        database_host,
+        database_port,
+        database_user,
+        database_password,
+        database_name,
+        staging,
+        releases_root,
+        release_stores,
+        source_cache,
+        log_dir,
+        images,
+        endpoints,
+    })
+}
+
+/// Quote a string for TOML
+fn quoted(s: &str) -> String {
+    toml_edit::Value::from(s).to_string()
+}
+
+fn quoted_path(p: &Path) -> String {
+    quoted(&p.display().to_string())
+}
+
+fn render_config(settings: &InitSettings) -> String {
+    let images = settings.images
+        .iter()
+        .map(|(name, short_name)| format!("    {{ name = {}, short_name = {} }},\n", quoted(name), quoted(short_name)))
+        .join("");
+
+    let endpoints = settings.endpoints
+        .iter()
+        .map(|ep| {
+            format!(
+                "[docker.endpoints.{name}]\nuri           = {uri}\nendpoint_type = {ty}\nmaxjobs       = {maxjobs}\n",
+                name = toml_edit::Key::new(ep.name.as_str()),
+                uri = quoted(&ep.uri),
+                ty = quoted(ep.endpoint_type),
+                maxjobs = ep.maxjobs
+            )
+        })
+        .join("\n");
+
+    // The endpoints table is required, even if no endpoint was configured yet
+    let endpoints = if endpoints.is_empty() {
+        String::from("[docker.endpoints]\n")
+    } else {
+        endpoints
+    };
+
+    indoc::formatdoc!(
+        r#"
+            # Configuration file for butido, generated by 'butido init'
+            #
+            # See the config.toml in the butido repository for all settings and their documentation.
+
+            # Configuration and package definition compatibility
+            compatibility = {compatibility}
+
+            # The directory where the builds are stored before they are released
+            staging = {staging}
+
+            # The directory of the release stores and the stores in it
+            releases_root  = {releases_root}
+            release_stores = [ {release_stores} ]
+
+            # The directory where the sources of the packages are cached
+            source_cache = {source_cache}
+
+            # The directory for the log files written with 'butido build --write-log'
+            log_dir = {log_dir}
+
+            database_host     = {database_host}
+            database_port     = {database_port}
+            database_user     = {database_user}
+            database_password = {database_password}
+            database_name     = {database_name}
+
+            # The phases of the packaging script, in the order they are run
+            available_phases = [ {phases} ]
+
+            [docker]
+            # Check whether the images are present on the endpoints before building
+            verify_images_present = true
+
+            images = [
+            {images}]
+
+            {endpoints}
+            [containers]
+            # Only allow the environment variables listed in "allowed_env" to be passed to a build
+            check_env_names = true
+            allowed_env     = [ ]
+        "#,
+        compatibility = quoted(env!("CARGO_PKG_VERSION")),
+        staging = quoted_path(&settings.staging),
+        releases_root = quoted_path(&settings.releases_root),
+        release_stores = settings.release_stores.iter().map(|s| quoted(s)).join(", "),
+        source_cache = quoted_path(&settings.source_cache),
+        log_dir = quoted_path(&settings.log_dir),
+        database_host = quoted(&settings.database_host),
+        database_port = settings.database_port,
+        database_user = quoted(&settings.database_user),
+        database_password = quoted(&settings.database_password),
+        database_name = quoted(&settings.database_name),
+        phases = PHASES.iter().map(|p| quoted(p)).join(", "),
+        images = images,
+        endpoints = endpoints,
+    )
+}
+
+/// Write a top-level pkg.toml with defaults for all packages and an example package
+fn write_skeleton(root: &Path) -> Result<()> {
+    let toplevel = indoc::indoc!(r#"
+        # Settings in this file are inherited by all packages in subdirectories
+
+        version_is_semver = false
+        patches = []
+
+        [dependencies]
+        build = []
+        runtime = []
+
+        [sources.src]
+        hash.type = "sha256"
+        download_manually = false
+
+        [phases]
+        unpack.script = '''
+            tar xf /inputs/src-{{this.sources.src.hash.hash}}.source
+        '''
+
+        build.script = '''
+            mkdir -p /outputs
+            echo "Build {{this.name}} {{this.version}} here"
+            touch /outputs/{{this.name}}-{{this.version}}.pkg
+            {{state "OK"}}
+        '''
+    "#);
+
+    let example = indoc::indoc!(r#"
+        name = "example"
+        version = "1.0.0"
+
+        [sources.src]
+        url = "https://example.com/example-1.0.0.tar.gz"
+        # The hash of the source archive, see 'butido source download' and 'butido source verify'
+        hash.hash = "0000000000000000000000000000000000000000000000000000000000000000"
+    "#);
+
+    let files = [
+        (root.join("pkg.toml"), toplevel),
+        (root.join("example").join("pkg.toml"), example),
+    ];
+
+    for (path, content) in files.iter() {
+        if path.exists() {
+            println!("Not overwriting {}", path.display());
+            continue
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| anyhow!("Creating {}", parent.display()))?;
+        }
+        std::fs::write(path, content).with_context(|| anyhow!("Writing {}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NotValidatedConfiguration;
+
+    #[test]
+    fn test_render_config_is_loadable() {
+        let settings = InitSettings {
+            database_host: String::from("localhost"),
+            database_port: 5432,
+            database_user: String::from("butido"),
+            database_password: String::from("pass\"word"),
+            database_name: String::from("butido"),
+            staging: PathBuf::from("/tmp/staging"),
+            releases_root: PathBuf::from("/tmp/releases"),
+            release_stores: vec![String::from("default"), String::from("stable")],
+            source_cache: PathBuf::from("/tmp/sources"),
+            log_dir: PathBuf::from("/tmp/logs"),
+            images: vec![(String::from("debian:bullseye"), String::from("deb11"))],
+            endpoints: vec![InitEndpoint {
+                name: String::from("buildhost"),
+                uri: String::from("http://build.example.com:2375"),
+                endpoint_type: "http",
+                maxjobs: 2,
+            }],
+        };
+
+        let rendered = render_config(&settings);
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(&rendered, ::config::FileFormat::Toml)).unwrap();
+
+        assert!(NotValidatedConfiguration::unknown_keys(&config).unwrap().is_empty());
+        let config = config.try_into::<NotValidatedConfiguration>().unwrap();
+        assert_eq!(config.database_password().unwrap(), "pass\"word");
+        assert_eq!(config.release_stores(), &[String::from("default"), String::from("stable")]);
+        assert_eq!(config.docker().endpoints().len(), 1);
+        assert_eq!(config.docker().images().len(), 1);
+    }
+}
@@ -0,0 +1,202 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'logs' subcommand
+
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::db::DbConnectionConfig;
+
+/// The time between two requests to the status endpoint
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Implementation of the "logs" subcommand
+pub async fn logs<'a, F>(matches: &ArgMatches, db_connection_config: F) -> Result<()>
+    where F: FnOnce() -> Result<DbConnectionConfig<'a>>
+{
+    if !matches.get_flag("follow") {
+        return crate::commands::db::log_of(db_connection_config()?, matches)
+    }
+
+    let job_uuid = matches
+        .get_one::<String>("job_uuid")
+        .map(|s| Uuid::parse_str(s.as_ref()))
+        .transpose()?
+        .unwrap(); // safe by clap
+    let address = matches
+        .get_one::<String>("status_address")
+        .ok_or_else(|| anyhow!("--follow needs the --status-address of the build that runs the job"))?;
+
+    follow(&format!("http://{address}"), &job_uuid, &mut std::io::stdout()).await
+}
+
+/// Stream the log of the job from the status endpoint at `base` to `out` until the job is finished
+async fn follow<W: Write>(base: &str, job_uuid: &Uuid, out: &mut W) -> Result<()> {
+    let client = reqwest::Client::new();
+
+    // The number of lines of the current run of the job that were printed already
+    let mut printed = 0;
+    let mut connected = false;
+    let mut last_status = String::new();
+
+    loop {
+        let status = match job_status(&client, base, job_uuid).await {
+            Ok(status) => status,
+
+            // The status endpoint stops when the submit is finished
+            Err(e) if connected => {
+                debug!("Status endpoint not reachable anymore: {:?}", e);
+                writeln!(out, "{}", "The submit finished".yellow())?;
+                return Ok(())
+            },
+            Err(e) => return Err(e).with_context(|| anyhow!("Getting the status of job {} from {}", job_uuid, base)),
+        };
+        connected = true;
+
+        if status != last_status {
+            // A retried job starts with an empty log again
+            if status == "retrying" {
+                printed = 0;
+            }
+
+            writeln!(out, "{}", format!("--- Job {job_uuid} is {status} ---").yellow())?;
+            last_status = status.clone();
+        }
+
+        let (first, lines) = get_log(&client, &format!("{base}/logs/{job_uuid}?from={printed}")).await?;
+        if first > printed {
+            writeln!(out, "{}", format!("--- {} lines were dropped ---", first - printed).yellow())?;
+        }
+        printed = first;
+        if !lines.is_empty() {
+            for line in lines.lines() {
+                writeln!(out, "{line}")?;
+                printed += 1;
+            }
+        }
+
+        if matches!(status.as_str(), "built" | "reused" | "failed" | "stopped") {
+            return Ok(())
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Get the status of the job, as listed by the status endpoint
+async fn job_status(client: &reqwest::Client, base: &str, job_uuid: &Uuid) -> Result<String> {
+    let jobs = get(client, &format!("{base}/jobs")).await?;
+    serde_json::from_str::<serde_json::Value>(&jobs)?
+        .as_array()
+        .ok_or_else(|| anyhow!("Unexpected response from the status endpoint: {}", jobs))?
+        .iter()
+        .find(|job| job["uuid"].as_str() == Some(&job_uuid.to_string()))
+        .ok_or_else(|| anyhow!("Job {} is not part of the submit", job_uuid))?["status"]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Unexpected response from the status endpoint: {}", jobs))
+}
+
+/// Get lines of a job log, with the line number of the first line
+async fn get_log(client: &reqwest::Client, url: &str) -> Result<(usize, String)> {
+    let response = client.get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?;
+    let first = first_line(&response)?;
+    Ok((first, response.text().await?))
+}
+
+/// The line number of the first line of a log response of the status endpoint
+pub fn first_line(response: &reqwest::Response) -> Result<usize> {
+    response.headers()
+        .get(crate::orchestrator::FIRST_LINE_HEADER)
+        .ok_or_else(|| anyhow!("Missing {} header in the response of the status endpoint", crate::orchestrator::FIRST_LINE_HEADER))?
+        .to_str()?
+        .parse()
+        .map_err(Error::from)
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<String> {
+    client.get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?
+        .text()
+        .await
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::ui::Dashboard;
+    use crate::ui::JobStatus;
+
+    /// Serve the status of `dashboard` on a free local port and return the base URL
+    fn serve(dashboard: Arc<Dashboard>) -> String {
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        crate::orchestrator::status::serve(address, dashboard).unwrap();
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn test_follow_finished_job() {
+        let dashboard = Arc::new(Dashboard::new());
+        let uuid = Uuid::new_v4();
+        dashboard.add_job(uuid, String::from("a 1"));
+        dashboard.push_log_line(&uuid, String::from("first"));
+        dashboard.push_log_line(&uuid, String::from("second"));
+        dashboard.set_status(&uuid, JobStatus::Built);
+
+        let mut out = Vec::new();
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let base = serve(dashboard);
+            follow(&base, &uuid, &mut out).await
+        }).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].contains(&format!("--- Job {uuid} is built ---")));
+        assert_eq!(&lines[1..], ["first", "second"]);
+    }
+
+    #[test]
+    fn test_job_status() {
+        let dashboard = Arc::new(Dashboard::new());
+        let uuid = Uuid::new_v4();
+        dashboard.add_job(uuid, String::from("a 1"));
+        dashboard.set_status(&uuid, JobStatus::Running);
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let base = serve(dashboard);
+            let client = reqwest::Client::new();
+
+            assert_eq!(job_status(&client, &base, &uuid).await.unwrap(), "running");
+            let err = job_status(&client, &base, &Uuid::nil()).await.unwrap_err();
+            assert!(err.to_string().ends_with("is not part of the submit"));
+
+            let (first, lines) = get_log(&client, &format!("{base}/logs/{uuid}?from=0")).await.unwrap();
+            assert_eq!((first, lines.as_str()), (0, ""));
+        });
+    }
+}
@@ -21,6 +21,7 @@ use diesel::RunQueryDsl;
 use walkdir::WalkDir;
 
 use crate::config::Configuration;
+use crate::repository::PackageFileFormat;
 use crate::repository::Repository;
 
 pub async fn metrics(
@@ -39,7 +40,8 @@ pub async fn metrics(
         .filter(|f| {
             f.path()
                 .file_name()
-                .map(|name| name == "pkg.toml")
+                .and_then(|name| name.to_str())
+                .map(|name| PackageFileFormat::from_file_name(name).is_some())
                 .unwrap_or(false)
         })
         .count();
@@ -8,8 +8,18 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+mod audit;
+pub use audit::audit;
+
+mod browse;
+pub use browse::browse;
+
 mod build;
 pub use build::build;
+mod build_manifest;
+
+mod config;
+pub use config::config;
 
 mod db;
 pub use db::db;
@@ -27,17 +37,43 @@ pub use find_artifact::find_artifact;
 mod find_pkg;
 pub use find_pkg::find_pkg;
 
+mod init;
+pub use init::init;
+
 mod dependencies_of;
 pub use dependencies_of::dependencies_of;
 
 mod lint;
 pub use lint::lint;
 
+mod logs;
+pub use logs::logs;
+
 mod what_depends;
 pub use what_depends::what_depends;
 
+mod queue;
+pub use queue::daemon;
+pub use queue::queue;
+pub use queue::submit;
+
+mod rebuild_dependents;
+pub use rebuild_dependents::rebuild_dependents;
+
 mod release;
 pub use release::release;
+mod release_notes;
+
+mod repo;
+pub use repo::repo;
+
+#[cfg(any(feature = "grpc", test))]
+mod running_submits;
+
+#[cfg(feature = "grpc")]
+mod serve;
+#[cfg(feature = "grpc")]
+pub use serve::serve;
 
 mod source;
 pub use source::source;
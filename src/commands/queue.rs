@@ -0,0 +1,434 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'queue', 'submit' and 'daemon' subcommands
+//!
+//! Several butido instances (on different hosts) can work on one build queue in the database, so
+//! that builds do not depend on a single central instance.
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::PgConnection;
+use tracing::error;
+use tracing::info;
+
+use crate::commands::build_manifest::BuildManifest;
+use crate::config::Configuration;
+use crate::db::DbConnectionConfig;
+use crate::db::models::QueuedBuild;
+use crate::repository::Repository;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "queue" subcommand
+#[allow(clippy::too_many_arguments)]
+pub async fn queue<L>(
+    repo_root: &Path,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: Fn() -> Result<Repository>,
+{
+    match matches.subcommand() {
+        Some(("add", matches)) => add(matches, db_connection_config, repo_path),
+        Some(("list", _)) => list(db_connection_config),
+        Some(("work", matches)) => {
+            let options = WorkerOptions::from_matches(matches)?;
+            work(&options, repo_root, progressbars, db_connection_config, config, load_repo, repo_path).await
+        },
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "submit" subcommand
+pub async fn submit(matches: &ArgMatches, db_connection_config: DbConnectionConfig<'_>, repo_path: &Path) -> Result<()> {
+    match matches.subcommand() {
+        Some(("enqueue", matches)) => add(matches, db_connection_config, repo_path),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "daemon" subcommand
+///
+/// Runs builds from the queue until it is stopped, and enqueues the submit requests from the
+/// requests directory.
+pub async fn daemon<L>(
+    repo_root: &Path,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: Fn() -> Result<Repository>,
+{
+    let options = WorkerOptions::from_matches(matches)?;
+    work(&options, repo_root, progressbars, db_connection_config, config, load_repo, repo_path).await
+}
+
+/// Implementation of the "queue add" and "submit enqueue" subcommands
+fn add(matches: &ArgMatches, db_connection_config: DbConnectionConfig<'_>, repo_path: &Path) -> Result<()> {
+    let args = matches
+        .get_many::<String>("build_args")
+        .unwrap() // safe by clap
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let conn = db_connection_config.establish_connection()?;
+    let queued = enqueue(&conn, &args, repo_path)?;
+    writeln!(std::io::stdout(), "Enqueued build {} of {} {}",
+        queued.id,
+        queued.package_name,
+        queued.package_version.as_deref().unwrap_or(""))?;
+    Ok(())
+}
+
+/// Enqueue a build with the arguments of the "build" subcommand
+///
+/// The manifest of the build (if any) is stored in the queue, so that workers on other hosts can
+/// read it.
+fn enqueue(conn: &PgConnection, args: &[String], repo_path: &Path) -> Result<QueuedBuild> {
+    // Check the arguments right away, so that the workers do not fail on them
+    let build = build_matches(args)?;
+
+    let (manifest, manifest_content) = match build.get_one::<String>("manifest") {
+        Some(path) => {
+            let (manifest, content) = BuildManifest::load(Path::new(path))?;
+            (Some(manifest), Some(content))
+        },
+        None => (None, None),
+    };
+
+    // The package from the CLI replaces the package from the manifest, like in the build
+    let (name, version) = match build.get_one::<String>("package_name") {
+        Some(name) => (name.clone(), build.get_one::<String>("package_version").cloned()),
+        None => {
+            let package = manifest.as_ref()
+                .and_then(|m| m.package().as_ref())
+                .ok_or_else(|| anyhow!("No package passed and no package in the manifest"))?;
+
+            (package.name().to_string(), package.version().as_ref().map(|v| v.to_string()))
+        },
+    };
+
+    let git_repo = git2::Repository::open(repo_path)?;
+    let hash = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
+
+    QueuedBuild::enqueue(conn, &name, version.as_deref(), &strip_manifest_arg(args), manifest_content.as_deref(), &hash)
+}
+
+/// Remove the manifest argument from the arguments of a build
+fn strip_manifest_arg(args: &[String]) -> Vec<String> {
+    let mut stripped = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--manifest" {
+            iter.next(); // the path
+        } else if !arg.starts_with("--manifest=") {
+            stripped.push(arg.clone());
+        }
+    }
+    stripped
+}
+
+/// Enqueue the submit requests (build manifests with the extension "toml") in `dir`
+///
+/// Enqueued requests are removed, requests that cannot be enqueued are renamed to "*.failed".
+fn enqueue_requests(conn: &PgConnection, dir: &Path, repo_path: &Path) -> Result<()> {
+    let mut requests = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    requests.retain(|path| path.is_file() && path.extension().map(|ext| ext == "toml").unwrap_or(false));
+    requests.sort();
+
+    for request in requests {
+        let args = [String::from("--manifest"), request.display().to_string()];
+        match enqueue(conn, &args, repo_path) {
+            Ok(queued) => {
+                info!("Enqueued build {} from {}", queued.id, request.display());
+                std::fs::remove_file(&request)?;
+            },
+            Err(e) => {
+                error!("Failed to enqueue {}: {:?}", request.display(), e);
+                let mut failed = request.clone().into_os_string();
+                failed.push(".failed");
+                std::fs::rename(&request, failed)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Implementation of the "queue list" subcommand
+fn list(db_connection_config: DbConnectionConfig<'_>) -> Result<()> {
+    let conn = db_connection_config.establish_connection()?;
+    let data = QueuedBuild::all(&conn)?
+        .into_iter()
+        .map(|build| {
+            vec![
+                build.id.to_string(),
+                build.created.to_string(),
+                format!("{} {}", build.package_name, build.package_version.unwrap_or_default()),
+                build.repo_hash,
+                build.state,
+                build.claimed_by.unwrap_or_default(),
+                build.heartbeat.map(|t| t.to_string()).unwrap_or_default(),
+                build.finished.map(|t| t.to_string()).unwrap_or_default(),
+                build.error.and_then(|e| e.lines().next().map(String::from)).unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let hdrs = crate::commands::util::mk_header(vec!["Id", "Enqueued", "Package", "Commit", "State", "Worker", "Heartbeat", "Finished", "Error"]);
+    crate::commands::util::display_data(hdrs, data, false)
+}
+
+/// The settings of a worker, from the arguments of "queue work" or "daemon"
+struct WorkerOptions {
+    /// Exit as soon as the queue is empty
+    once: bool,
+    poll_interval: Duration,
+    stale_after: Duration,
+
+    /// The directory with submit requests, if any
+    requests_dir: Option<PathBuf>,
+}
+
+impl WorkerOptions {
+    fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let seconds = |name: &str| -> Result<Duration> {
+            matches
+                .get_one::<String>(name)
+                .map(|s| u64::from_str(s))
+                .transpose()?
+                .map(Duration::from_secs)
+                .ok_or_else(|| anyhow!("Missing argument: {}", name))
+        };
+
+        Ok(WorkerOptions {
+            once: matches.try_get_one::<bool>("once").ok().flatten().copied().unwrap_or(false),
+            poll_interval: seconds("poll_interval")?,
+            stale_after: seconds("stale_after")?,
+            requests_dir: matches.try_get_one::<String>("requests_dir").ok().flatten().map(PathBuf::from),
+        })
+    }
+}
+
+/// Claim builds from the queue and run them
+///
+/// Only builds that were enqueued at the current commit of the repository are claimed, the
+/// package repository is loaded for each build, so that the worker picks up new commits. The
+/// worker stops when the queue is empty (with `once`) or when it is interrupted (Ctrl-C). A build
+/// that is interrupted is put back into the queue.
+async fn work<L>(
+    options: &WorkerOptions,
+    repo_root: &Path,
+    progressbars: ProgressBars,
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: Fn() -> Result<Repository>,
+{
+    let worker = worker_name();
+    let head = || -> Result<String> {
+        let git_repo = git2::Repository::open(repo_path)?;
+        crate::util::git::get_repo_head_commit_hash(&git_repo)
+    };
+    let conn = db_connection_config.clone().establish_connection()?;
+
+    info!("Worker {} is waiting for builds", worker);
+    loop {
+        if let Some(dir) = options.requests_dir.as_ref() {
+            enqueue_requests(&conn, dir, repo_path)?;
+        }
+
+        let build = match QueuedBuild::claim(&conn, &worker, options.stale_after, &head()?)? {
+            Some(build) => build,
+            None if options.once => return Ok(()),
+            None => {
+                tokio::select! {
+                    _ = tokio::time::sleep(options.poll_interval) => continue,
+                    _ = tokio::signal::ctrl_c() => {
+                        info!("Worker {} stopped", worker);
+                        return Ok(())
+                    },
+                }
+            },
+        };
+
+        // The repository could have been changed since the build was claimed
+        let repo = load_repo()?;
+        let loaded_head = head()?;
+        if build.repo_hash != loaded_head {
+            info!("Build {} was enqueued at commit {}, but the repository is at {} now, putting it back into the queue",
+                build.id,
+                build.repo_hash,
+                loaded_head);
+            build.release(&conn, &worker)?;
+            continue
+        }
+
+        info!("Running build {} of {} {}", build.id, build.package_name, build.package_version.as_deref().unwrap_or(""));
+        let run = run_build(
+            &build,
+            &conn,
+            &worker,
+            options.stale_after,
+            repo_root,
+            progressbars.clone(),
+            db_connection_config.clone(),
+            config,
+            repo,
+            repo_path,
+        );
+
+        let result = tokio::select! {
+            result = run => result,
+            _ = tokio::signal::ctrl_c() => {
+                build.release(&conn, &worker)?;
+                info!("Worker {} stopped, build {} was put back into the queue", worker, build.id);
+                return Ok(())
+            },
+        };
+
+        if let Err(e) = result.as_ref() {
+            error!("Build {} failed: {:?}", build.id, e);
+        }
+        build.finish(&conn, &worker, &result)?;
+    }
+}
+
+/// Run a build from the queue, while sending heartbeats for it
+#[allow(clippy::too_many_arguments)]
+async fn run_build(
+    build: &QueuedBuild,
+    conn: &PgConnection,
+    worker: &str,
+    stale_after: Duration,
+    repo_root: &Path,
+    progressbars: ProgressBars,
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    repo: Repository,
+    repo_path: &Path,
+) -> Result<()> {
+    // The manifest is passed to the build as temporary file, which is removed when it is dropped
+    let mut args = build.args.clone();
+    let manifest_file = build.manifest.as_ref().map(|manifest| write_manifest(build.id, manifest)).transpose()?;
+    if let Some(manifest_file) = manifest_file.as_ref() {
+        args.extend([String::from("--manifest"), manifest_file.path().display().to_string()]);
+    }
+
+    let matches = build_matches(&args)?;
+    let run = crate::commands::build(repo_root, &matches, progressbars, db_connection_config, config, repo, repo_path);
+    tokio::pin!(run);
+
+    // Send heartbeats often enough that a short hiccup does not make the build look stale
+    let mut heartbeat = tokio::time::interval((stale_after / 3).max(Duration::from_secs(1)));
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+
+            // If another worker claimed the build, it is aborted here
+            _ = heartbeat.tick() => {
+                if let Err(e) = build.heartbeat(conn, worker) {
+                    break Err(e)
+                }
+            },
+        }
+    };
+
+    result
+}
+
+/// Write the manifest of the queued build `id` to a temporary file
+fn write_manifest(id: i32, manifest: &str) -> Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("butido-queue-{id}-"))
+        .suffix(".toml")
+        .tempfile()?;
+    file.write_all(manifest.as_bytes())?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// Parse the arguments of the "build" subcommand
+fn build_matches(args: &[String]) -> Result<ArgMatches> {
+    let args = [String::from("butido"), String::from("build")]
+        .into_iter()
+        .chain(args.iter().cloned());
+
+    crate::cli::cli()
+        .try_get_matches_from(args)?
+        .subcommand_matches("build")
+        .cloned()
+        .ok_or_else(|| anyhow!("Not a valid build"))
+}
+
+/// The name of this worker in the queue, the host name and the process id
+fn worker_name() -> String {
+    let host = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| String::from("unknown"));
+
+    format!("{}:{}", host, std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_matches() {
+        let args = ["a", "1", "--no-lint"].map(String::from);
+        let matches = build_matches(&args).unwrap();
+        assert_eq!(matches.get_one::<String>("package_name").map(String::as_str), Some("a"));
+        assert_eq!(matches.get_one::<String>("package_version").map(String::as_str), Some("1"));
+        assert!(matches.get_flag("no_lint"));
+
+        assert!(build_matches(&["a".to_string(), "--no-such-flag".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_write_manifest() {
+        let file = write_manifest(1, "[package]\nname = \"a\"\n").unwrap();
+        let path = file.path().to_path_buf();
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("butido-queue-1-"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[package]\nname = \"a\"\n");
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_strip_manifest_arg() {
+        let args = ["--manifest", "a.toml", "--no-lint", "--manifest=b.toml", "-I", "img"].map(String::from);
+        assert_eq!(strip_manifest_arg(&args), ["--no-lint", "-I", "img"]);
+    }
+}
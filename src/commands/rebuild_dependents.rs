@@ -0,0 +1,207 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'rebuild-dependents' subcommand
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use tracing::error;
+use tracing::info;
+
+use crate::commands::what_depends::find_dependents;
+use crate::config::Configuration;
+use crate::db::DbConnectionConfig;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::repository::Repository;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "rebuild-dependents" subcommand
+pub async fn rebuild_dependents(
+    repo_root: &Path,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    repo: Repository,
+    repo_path: &Path,
+) -> Result<()> {
+    let name = matches
+        .get_one::<String>("package_name")
+        .map(|s| PackageName::from(s.to_owned()))
+        .unwrap(); // safe by clap
+    let version = matches
+        .get_one::<String>("package_version")
+        .map(|s| PackageVersion::from(s.to_owned()));
+
+    let exists = match version.as_ref() {
+        Some(version) => !repo.find(&name, version).is_empty(),
+        None => !repo.find_by_name(&name).is_empty(),
+    };
+    if !exists {
+        return Err(anyhow!("Found no package {} {}", name, version.as_ref().map(|v| v.to_string()).unwrap_or_default()));
+    }
+
+    let dependents = find_dependents(&repo, &name, version.as_ref(), true, true, None)?;
+    let roots = find_roots(&repo, &dependents)?;
+
+    {
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        writeln!(outlock, "{} packages depend on {}, rebuilding {} of them:", dependents.len(), name, roots.len())?;
+        for package in roots.iter() {
+            writeln!(outlock, "{} {}", package.name(), package.version())?;
+        }
+    }
+
+    if matches.get_flag("dry_run") {
+        return Ok(())
+    }
+
+    let mut failed = Vec::new();
+    for package in roots.iter() {
+        info!("Rebuilding {} {}", package.name(), package.version());
+        let build_matches = build_matches(matches, package)?;
+        let result = crate::commands::build(
+            repo_root,
+            &build_matches,
+            progressbars.clone(),
+            db_connection_config.clone(),
+            config,
+            repo.clone(),
+            repo_path,
+        )
+        .await;
+
+        if let Err(e) = result {
+            error!("Rebuilding {} {} failed: {:?}", package.name(), package.version(), e);
+            failed.push(format!("{} {}", package.name(), package.version()));
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Rebuilding failed for: {}", failed.join(", ")))
+    }
+}
+
+/// Find the dependents that no other dependent depends on
+///
+/// Building these rebuilds all dependents, because the others are in their trees.
+fn find_roots<'a>(repo: &'a Repository, dependents: &[&'a Package]) -> Result<Vec<&'a Package>> {
+    let keys = dependents
+        .iter()
+        .map(|p| (p.name(), p.version()))
+        .collect::<HashSet<_>>();
+
+    let mut roots = Vec::new();
+    for package in dependents.iter() {
+        let is_root = find_dependents(repo, package.name(), Some(package.version()), true, true, Some(1))?
+            .iter()
+            .all(|p| !keys.contains(&(p.name(), p.version())));
+
+        if is_root {
+            roots.push(*package);
+        }
+    }
+    Ok(roots)
+}
+
+/// Get the arguments of the "build" subcommand for rebuilding `package`
+///
+/// The build is set up exactly like `butido build`, with the settings that were passed to this
+/// command.
+fn build_matches(matches: &ArgMatches, package: &Package) -> Result<ArgMatches> {
+    let mut args = vec![
+        String::from("butido"),
+        String::from("build"),
+        package.name().to_string(),
+        package.version().to_string(),
+    ];
+
+    if let Some(image) = matches.get_one::<String>("image") {
+        args.extend([String::from("--image"), image.clone()]);
+    }
+
+    for env in matches.get_many::<String>("env").unwrap_or_default() {
+        args.extend([String::from("--env"), env.clone()]);
+    }
+
+    for (flag, arg) in [("no_verification", "--no-verify"), ("no_lint", "--no-lint"), ("write-log-file", "--write-log")] {
+        if matches.get_flag(flag) {
+            args.push(String::from(arg));
+        }
+    }
+
+    crate::cli::cli()
+        .try_get_matches_from(args)?
+        .subcommand_matches("build")
+        .cloned()
+        .ok_or_else(|| anyhow!("Failed to set up the build of {} {}", package.name(), package.version()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_find_roots() {
+        // c -> b -> a, d -> a
+        let mut btree = BTreeMap::new();
+        for (name, dependency) in [("a", None), ("b", Some("a =1")), ("c", Some("b =1")), ("d", Some("a =1"))] {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            if let Some(dependency) = dependency {
+                pack.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from(dependency))));
+            }
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+        let repo = Repository::from(btree);
+
+        let dependents = find_dependents(&repo, &pname("a"), None, true, true, None).unwrap();
+        let roots = find_roots(&repo, &dependents)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(roots, ["d", "c"]);
+    }
+
+    #[test]
+    fn test_build_matches() {
+        let matches = crate::cli::cli()
+            .try_get_matches_from(["butido", "rebuild-dependents", "a", "--no-lint", "-E", "A=1", "-I", "img"])
+            .unwrap();
+        let matches = matches.subcommand_matches("rebuild-dependents").unwrap();
+
+        let build = build_matches(matches, &package("b", "2", "https://rust-lang.org", "123")).unwrap();
+        assert_eq!(build.get_one::<String>("package_name").map(String::as_str), Some("b"));
+        assert_eq!(build.get_one::<String>("package_version").map(String::as_str), Some("2"));
+        assert_eq!(build.get_one::<String>("image").map(String::as_str), Some("img"));
+        assert_eq!(build.get_many::<String>("env").unwrap().collect::<Vec<_>>(), ["A=1"]);
+        assert!(build.get_flag("no_lint"));
+        assert!(!build.get_flag("no_verification"));
+    }
+}
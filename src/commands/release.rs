@@ -10,7 +10,10 @@
 
 //! Implementation of the 'release' subcommand
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -24,6 +27,8 @@ use tokio_stream::StreamExt;
 use resiter::AndThen;
 
 use crate::config::Configuration;
+use crate::config::HookEvent;
+use crate::config::OciReleaseTarget;
 use crate::db::models as dbmodels;
 use crate::db::DbConnectionConfig;
 
@@ -36,6 +41,12 @@ pub async fn release(
     match matches.subcommand() {
         Some(("new", matches))  => new_release(db_connection_config, config, matches).await,
         Some(("rm", matches))   => rm_release(db_connection_config, config, matches).await,
+        Some(("rollback", matches)) => rollback_release(db_connection_config, config, matches).await,
+        Some(("propose", matches)) => propose_release(db_connection_config, config, matches).await,
+        Some(("approve", matches)) => approve_release(db_connection_config, config, matches).await,
+        Some(("proposals", matches)) => list_release_proposals(db_connection_config, matches).await,
+        Some(("notes", matches)) => crate::commands::release_notes::release_notes(db_connection_config, matches).await,
+        Some(("sync", matches)) => sync_release_store(db_connection_config, config, matches).await,
         Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("Missing subcommand")),
     }
@@ -47,32 +58,65 @@ async fn new_release(
     config: &Configuration,
     matches: &ArgMatches,
 ) -> Result<()> {
-    let print_released_file_pathes = !matches.get_flag("quiet");
     let release_store_name = matches.get_one::<String>("release_store_name").unwrap(); // safe by clap
-    if !(config.releases_directory().exists() && config.releases_directory().is_dir()) {
+    if config.release_approval_required().contains(release_store_name) {
         return Err(anyhow!(
-            "Release directory does not exist or does not point to directory: {}",
-            config.releases_directory().display()
-        ));
+            "Releases to {} need approval, propose the release with 'butido release propose' instead",
+            release_store_name
+        ))
     }
 
-    let pname = matches.get_one::<String>("package_name");
-
-    let pvers = matches.get_one::<String>("package_version");
-
-    debug!("Release called for: {:?} {:?}", pname, pvers);
-
-    let conn = db_connection_config.establish_connection()?;
     let submit_uuid = matches
         .get_one::<String>("submit_uuid")
         .map(|s| uuid::Uuid::parse_str(s.as_ref()))
         .transpose()?
         .unwrap(); // safe by clap
+
+    let conn = db_connection_config.establish_connection()?;
+    release_artifacts(&conn, config, ReleaseParams {
+        submit_uuid,
+        release_store_name,
+        package_name: matches.get_one::<String>("package_name").map(String::as_str),
+        package_version: matches.get_one::<String>("package_version").map(String::as_str),
+        do_update: matches.get_flag("package_do_update"),
+        skip_released: false,
+        interactive: !matches.get_flag("noninteractive"),
+        print_released_file_pathes: !matches.get_flag("quiet"),
+    }).await
+}
+
+/// What to release, for `release_artifacts()`
+struct ReleaseParams<'a> {
+    submit_uuid: uuid::Uuid,
+    release_store_name: &'a str,
+    package_name: Option<&'a str>,
+    package_version: Option<&'a str>,
+    do_update: bool,
+
+    /// Do not release the artifacts that are released to the store already
+    skip_released: bool,
+    interactive: bool,
+    print_released_file_pathes: bool,
+}
+
+/// Release the (not yet released) artifacts of a submit
+async fn release_artifacts(conn: &PgConnection, config: &Configuration, params: ReleaseParams<'_>) -> Result<()> {
+    let ReleaseParams {
+        submit_uuid,
+        release_store_name,
+        package_name: pname,
+        package_version: pvers,
+        do_update,
+        skip_released,
+        interactive,
+        print_released_file_pathes,
+    } = params;
+    debug!("Release called for: {:?} {:?}", pname, pvers);
     debug!("Release called for submit: {:?}", submit_uuid);
 
     let submit = crate::schema::submits::dsl::submits
         .filter(crate::schema::submits::dsl::uuid.eq(submit_uuid))
-        .first::<dbmodels::Submit>(&conn)?;
+        .first::<dbmodels::Submit>(conn)?;
     debug!("Found Submit: {:?}", submit_uuid);
 
     let arts = {
@@ -91,7 +135,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&conn)?
+                query.load::<dbmodels::Artifact>(conn)?
             }
             (Some(name), None) => {
                 let query = sel.filter(crate::schema::packages::name.eq(name));
@@ -99,7 +143,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&conn)?
+                query.load::<dbmodels::Artifact>(conn)?
             }
             (None, Some(vers)) => {
                 let query = sel.filter(crate::schema::packages::version.like(vers));
@@ -107,19 +151,52 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&conn)?
+                query.load::<dbmodels::Artifact>(conn)?
             }
             (None, None) => {
                 debug!(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&sel)
                 );
-                sel.load::<dbmodels::Artifact>(&conn)?
+                sel.load::<dbmodels::Artifact>(conn)?
             }
         }
     };
     debug!("Artifacts = {:?}", arts);
 
+    let arts = if skip_released {
+        let released = crate::schema::releases::table
+            .inner_join(crate::schema::release_stores::table)
+            .filter(crate::schema::release_stores::store_name.eq(release_store_name))
+            .filter(crate::schema::releases::artifact_id.eq_any(arts.iter().map(|art| art.id).collect::<Vec<_>>()))
+            .select(crate::schema::releases::artifact_id)
+            .load::<i32>(conn)?;
+        debug!("Skipping released artifacts: {:?}", released);
+        arts.into_iter().filter(|art| !released.contains(&art.id)).collect()
+    } else {
+        arts
+    };
+
+    let staging_base: &PathBuf = &config.staging_directory().join(submit.uuid.to_string());
+
+    crate::hooks::run_required_hooks(config.hooks(), HookEvent::PreRelease, &[
+        ("SUBMIT_UUID", submit.uuid.to_string()),
+        ("RELEASE_STORE", release_store_name.to_string()),
+        ("ARTIFACT_PATHS", arts.iter().map(|art| staging_base.join(&art.path).display().to_string()).collect::<Vec<_>>().join("\n")),
+    ])
+    .await?;
+
+    if let Some(target) = config.oci_release_targets().get(release_store_name) {
+        return release_to_registry(conn, target, release_store_name, &submit, staging_base, arts, do_update, interactive, print_released_file_pathes).await
+    }
+
+    if !(config.releases_directory().exists() && config.releases_directory().is_dir()) {
+        return Err(anyhow!(
+            "Release directory does not exist or does not point to directory: {}",
+            config.releases_directory().display()
+        ));
+    }
+
     arts.iter()
         .filter_map(|art| {
             art.path_buf()
@@ -134,11 +211,15 @@ async fn new_release(
         .collect::<Result<()>>()
         .await?;
 
-    let staging_base: &PathBuf = &config.staging_directory().join(submit.uuid.to_string());
-
-    let release_store = crate::db::models::ReleaseStore::create(&conn, release_store_name)?;
-    let do_update = matches.get_flag("package_do_update");
-    let interactive = !matches.get_flag("noninteractive");
+    let release_store = crate::db::models::ReleaseStore::create(conn, release_store_name)?;
+    let store_path = config.releases_directory().join(release_store_name);
+    let signer = config.release_signing()
+        .as_ref()
+        .filter(|signing| signing.release_stores().is_empty() || signing.release_stores().iter().any(|s| s == release_store_name))
+        .map(|signing| signing.signer());
+    let provenance = config.release_provenance()
+        .as_ref()
+        .filter(|provenance| provenance.release_stores().is_empty() || provenance.release_stores().iter().any(|s| s == release_store_name));
 
     let now = chrono::offset::Local::now().naive_local();
     let any_err = arts.into_iter()
@@ -163,7 +244,7 @@ async fn new_release(
                     return Err(anyhow!("Does already exist: {}", dest_path.display()));
                 } else if dest_path.exists() && do_update {
                     writeln!(std::io::stderr(), "Going to update: {}", dest_path.display())?;
-                    if interactive && !dialoguer::Confirm::new().with_prompt("Continue?").interact()? {
+                    if interactive && !crate::ui::confirm("Continue?")? {
                         return Err(anyhow!("Does already exist: {} and update was denied", dest_path.display()));
                     }
                 }
@@ -175,17 +256,70 @@ async fn new_release(
                         .with_context(|| anyhow!("Removing {} before writing new file to this path", dest_path.display()))?;
                 }
 
-                // else !dest_path.exists()
-                tokio::fs::copy(&art_path, &dest_path)
-                    .await
-                    .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display()))
-                    .map_err(Error::from)
-                    .and_then(|_| {
-                        debug!("Updating {:?} to set released = true", art);
-                        let rel = crate::db::models::Release::create(&conn, &art, &now, &release_store)?;
+                // The files that were written for the artifact are removed again if the release
+                // fails, so that it can be retried
+                let mut written = Vec::new();
+                let released = async {
+                    // else !dest_path.exists()
+                    written.push(dest_path.clone());
+                    tokio::fs::copy(&art_path, &dest_path)
+                        .await
+                        .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display()))?;
+
+                    // Jobs from before buildinfo files were written do not have one
+                    let buildinfo_path = crate::buildinfo::buildinfo_path(&art_path);
+                    if buildinfo_path.is_file() {
+                        let dest_buildinfo_path = crate::buildinfo::buildinfo_path(&dest_path);
+                        written.push(dest_buildinfo_path.clone());
+                        tokio::fs::copy(&buildinfo_path, &dest_buildinfo_path)
+                            .await
+                            .with_context(|| anyhow!("Copying {} to {}", buildinfo_path.display(), dest_buildinfo_path.display()))?;
+                    }
+
+                    let signature = match signer {
+                        Some(signer) => {
+                            let signature = crate::release_signing::sign(signer, &dest_path).await?;
+                            written.push(signature.path.clone());
+                            Some(signature)
+                        },
+                        None => None,
+                    };
+
+                    if let Some(provenance) = provenance {
+                        let build = crate::release_provenance::Build::load(conn, &art)?;
+                        let signer = config.release_signing().as_ref().map(|signing| signing.signer());
+                        written.extend(crate::release_provenance::write(provenance, signer, &build, &dest_path).await?);
+                    }
+
+                    // The release is recorded last, so that it is only recorded if all files were written
+                    debug!("Updating {:?} to set released = true", art);
+                    conn.transaction::<_, Error, _>(|| {
+                        let rel = crate::db::models::Release::create(conn, &art, &now, &release_store)?;
+                        match signature.as_ref() {
+                            Some(signature) => {
+                                let path = signature.path.strip_prefix(&store_path)?.display().to_string();
+                                rel.set_signature(conn, &path, signature.fingerprint.as_deref())
+                            },
+                            None => Ok(rel),
+                        }
+                    })
+                }.await;
+
+                match released {
+                    Ok(rel) => {
                         debug!("Release object = {:?}", rel);
                         Ok(dest_path)
-                    })
+                    },
+                    Err(e) => {
+                        for path in written.iter().filter(|path| path.exists()) {
+                            debug!("Removing {} of the failed release", path.display());
+                            if let Err(e) = tokio::fs::remove_file(path).await {
+                                error!("Removing {} failed: {}", path.display(), e);
+                            }
+                        }
+                        Err(e)
+                    },
+                }
             }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
@@ -204,6 +338,253 @@ async fn new_release(
         .last()
         .is_some(); // consume iterator completely, if not empty, there was an error
 
+    update_generated_files(conn, config, release_store_name).await?;
+
+    if any_err {
+        Err(anyhow!("Releasing one or more artifacts failed"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Propose a release, which is done when another user approves it
+async fn propose_release(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let release_store_name = matches.get_one::<String>("release_store_name").unwrap(); // safe by clap
+    if !config.release_stores().contains(release_store_name) && !config.oci_release_targets().contains_key(release_store_name) {
+        return Err(anyhow!("Unknown release store name: {}", release_store_name))
+    }
+
+    let submit_uuid = matches
+        .get_one::<String>("submit_uuid")
+        .map(|s| uuid::Uuid::parse_str(s.as_ref()))
+        .transpose()?
+        .unwrap(); // safe by clap
+    let pname = matches.get_one::<String>("package_name").map(String::as_str);
+    let pvers = matches.get_one::<String>("package_version").map(String::as_str);
+    let conn = db_connection_config.establish_connection()?;
+    let user = current_user(&conn)?;
+    let submit = crate::schema::submits::dsl::submits
+        .filter(crate::schema::submits::dsl::uuid.eq(submit_uuid))
+        .first::<dbmodels::Submit>(&conn)
+        .with_context(|| anyhow!("Loading submit {} from DB", submit_uuid))?;
+
+    let proposal = dbmodels::ReleaseProposal::create(&conn, &submit, release_store_name, pname, pvers, &user)?;
+    info!("Release proposal created in database: {:?}", proposal);
+    writeln!(
+        std::io::stdout(),
+        "Proposed release {} of {} to {}, it has to be approved by another user with 'butido release approve {}'",
+        proposal.id,
+        submit_uuid,
+        release_store_name,
+        proposal.id
+    )?;
+    Ok(())
+}
+
+/// Approve a proposed release and release the artifacts
+async fn approve_release(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let proposal_id = *matches.get_one::<i32>("proposal_id").unwrap(); // safe by clap
+    let conn = db_connection_config.establish_connection()?;
+    let user = current_user(&conn)?;
+
+    // The proposal is approved before the artifacts are released, so that concurrent approvals
+    // cannot release it twice
+    let now = chrono::offset::Local::now().naive_local();
+    let proposal = match dbmodels::ReleaseProposal::approve(&conn, proposal_id, &user, &now)? {
+        Some(proposal) => proposal,
+        None => {
+            let proposal = crate::schema::release_proposals::table
+                .find(proposal_id)
+                .first::<dbmodels::ReleaseProposal>(&conn)
+                .optional()?
+                .ok_or_else(|| anyhow!("No release proposal with ID {}", proposal_id))?;
+
+            return Err(proposal.approval_refused(&user)
+                .unwrap_or_else(|| anyhow!("Release proposal {} could not be approved", proposal.id)))
+        },
+    };
+    info!("Release proposal approved: {:?}", proposal);
+
+    let submit = crate::schema::submits::table
+        .find(proposal.submit_id)
+        .first::<dbmodels::Submit>(&conn)?;
+
+    let released = release_artifacts(&conn, config, ReleaseParams {
+        submit_uuid: submit.uuid,
+        release_store_name: &proposal.release_store_name,
+        package_name: proposal.package_name.as_deref(),
+        package_version: proposal.package_version.as_deref(),
+        do_update: matches.get_flag("package_do_update"),
+        skip_released: true, // by a failed approval before
+        interactive: !matches.get_flag("noninteractive"),
+        print_released_file_pathes: !matches.get_flag("quiet"),
+    })
+    .await;
+
+    // A proposal that could not be released can be approved again, after the problem is fixed
+    if released.is_err() {
+        proposal.revoke_approval(&conn, &user)?;
+    }
+    released
+}
+
+/// List the release proposals
+async fn list_release_proposals(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let format = crate::commands::util::OutputFormat::from_matches(matches)?;
+    let conn = db_connection_config.establish_connection()?;
+
+    let mut query = crate::schema::release_proposals::table
+        .inner_join(crate::schema::submits::table)
+        .order_by(crate::schema::release_proposals::id.asc())
+        .into_boxed();
+    if !matches.get_flag("all") {
+        query = query.filter(crate::schema::release_proposals::approved_by.is_null());
+    }
+
+    let hdrs = crate::commands::util::mk_header(vec!["ID", "Submit", "Store", "Package", "Version", "Proposed by", "Proposed", "Approved by", "Approved"]);
+    let format_date = |date: &chrono::NaiveDateTime| date.format("%Y-%m-%d %H:%M:%S").to_string();
+    let data = query
+        .load::<(dbmodels::ReleaseProposal, dbmodels::Submit)>(&conn)?
+        .into_iter()
+        .map(|(proposal, submit)| {
+            vec![
+                proposal.id.to_string(),
+                submit.uuid.to_string(),
+                proposal.release_store_name,
+                proposal.package_name.unwrap_or_else(|| String::from("*")),
+                proposal.package_version.unwrap_or_else(|| String::from("*")),
+                proposal.proposed_by,
+                format_date(&proposal.proposed),
+                proposal.approved_by.unwrap_or_else(|| String::from("-")),
+                proposal.approved.as_ref().map(format_date).unwrap_or_else(|| String::from("-")),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    if data.is_empty() {
+        info!("No release proposals in database");
+    }
+    crate::commands::util::display_data_as(hdrs, data, format)
+}
+
+/// The database role butido is connected as, for recording who proposed and approved a release
+///
+/// Unlike e.g. the USER environment variable, the role cannot be changed without knowing the
+/// password of another role.
+fn current_user(conn: &PgConnection) -> Result<String> {
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Text>("current_user"))
+        .get_result::<String>(conn)
+        .context("Getting the current database user")
+}
+
+/// Release the artifacts by pushing them to an OCI registry
+///
+/// The artifacts of each package are pushed as one OCI artifact, to the repository of the package
+/// and tagged with the version of the package.
+#[allow(clippy::too_many_arguments)]
+async fn release_to_registry(
+    conn: &PgConnection,
+    target: &OciReleaseTarget,
+    release_store_name: &str,
+    submit: &dbmodels::Submit,
+    staging_base: &Path,
+    arts: Vec<dbmodels::Artifact>,
+    do_update: bool,
+    interactive: bool,
+    print_released_references: bool,
+) -> Result<()> {
+    let art_ids = arts.iter().map(|art| art.id).collect::<Vec<_>>();
+    let packages = crate::schema::artifacts::table
+        .inner_join(crate::schema::jobs::table.inner_join(crate::schema::packages::table))
+        .filter(crate::schema::artifacts::id.eq_any(art_ids))
+        .select((crate::schema::artifacts::all_columns, crate::schema::packages::all_columns))
+        .load::<(dbmodels::Artifact, dbmodels::Package)>(conn)?
+        .into_iter()
+        .fold(BTreeMap::<(String, String), Vec<dbmodels::Artifact>>::new(), |mut packages, (art, package)| {
+            packages.entry((package.name, package.version)).or_default().push(art);
+            packages
+        });
+
+    let release_store = crate::db::models::ReleaseStore::create(conn, release_store_name)?;
+    let client = reqwest::Client::new();
+    let now = chrono::offset::Local::now();
+
+    let mut any_err = false;
+    for ((name, version), arts) in packages {
+        let repository = crate::util::oci::repository_name(target.repository_prefix(), &name);
+        let tag = crate::util::oci::tag_name(&version);
+
+        let result = async {
+            let registry = crate::util::oci::Registry::connect(client.clone(), target, repository).await?;
+            let reference = registry.reference(&tag);
+
+            if registry.tag_exists(&tag).await? {
+                if !do_update {
+                    return Err(anyhow!("Does already exist: {}", reference));
+                }
+                writeln!(std::io::stderr(), "Going to update: {}", reference)?;
+                if interactive && !crate::ui::confirm("Continue?")? {
+                    return Err(anyhow!("Does already exist: {} and update was denied", reference));
+                }
+            }
+
+            let mut files = Vec::with_capacity(arts.len());
+            for art in arts.iter() {
+                let art_path = staging_base.join(&art.path);
+                if !art_path.is_file() {
+                    trace!("Artifact does not exist as file, cannot release it: {:?}", art);
+                    return Err(anyhow!("Not a file: {}", art_path.display()));
+                }
+                debug!("Pushing {} to {}", art_path.display(), reference);
+                files.push(registry.push_file(&art_path).await?);
+            }
+
+            let annotations = HashMap::from([
+                (String::from("org.opencontainers.image.version"), version.clone()),
+                (String::from("org.opencontainers.image.created"), now.to_rfc3339()),
+                (String::from("org.opencontainers.image.title"), name.clone()),
+                (String::from("io.butido.submit"), submit.uuid.to_string()),
+            ]);
+            // The releases are recorded before the manifest is pushed, which creates the tag, and
+            // are removed again if that fails, so that the release can be retried
+            let rels = conn.transaction::<_, Error, _>(|| {
+                arts.iter()
+                    .map(|art| crate::db::models::Release::create(conn, art, &now.naive_local(), &release_store))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            debug!("Release objects = {:?}", rels);
+
+            match registry.push_manifest(&tag, &files, &annotations).await {
+                Ok(digest) => debug!("Pushed manifest {} for {}", digest, reference),
+                Err(e) => {
+                    let rel_ids = rels.iter().map(|rel| rel.id).collect::<Vec<_>>();
+                    diesel::delete(crate::schema::releases::table.filter(crate::schema::releases::id.eq_any(rel_ids)))
+                        .execute(conn)?;
+                    return Err(e)
+                },
+            }
+            Ok::<_, Error>(reference)
+        }.await;
+
+        match result {
+            Ok(reference) => if print_released_references {
+                writeln!(std::io::stdout(), "{}", reference)?;
+            },
+            Err(err) => {
+                error!("Error: {:?}", err);
+                any_err = true;
+            },
+        }
+    }
+
     if any_err {
         Err(anyhow!("Releasing one or more artifacts failed"))
     } else {
@@ -254,16 +635,460 @@ pub async fn rm_release(
 
     writeln!(std::io::stderr(), "Going to delete: {}", artifact_path.display())?;
     writeln!(std::io::stderr(), "Going to remove from database: Release with ID {} from {}", release.id, release.release_date)?;
-    if !dialoguer::Confirm::new().with_prompt("Continue?").interact()? {
+    if !crate::ui::confirm("Continue?")? {
         return Ok(())
     }
 
     tokio::fs::remove_file(&artifact_path).await?;
     info!("File removed");
 
+    if let Some(signature_path) = release.signature_path.as_ref() {
+        let signature_path = config.releases_directory().join(release_store_name).join(signature_path);
+        if signature_path.is_file() {
+            tokio::fs::remove_file(&signature_path).await?;
+            info!("Signature removed");
+        }
+    }
+
+    let buildinfo_path = crate::buildinfo::buildinfo_path(&artifact_path);
+    if buildinfo_path.is_file() {
+        tokio::fs::remove_file(&buildinfo_path).await?;
+        info!("Buildinfo removed");
+    }
+
+    for path in crate::release_provenance::existing_files(&artifact_path)? {
+        tokio::fs::remove_file(&path).await?;
+        info!("Provenance file {} removed", path.display());
+    }
+
     diesel::delete(&release).execute(&conn)?;
     info!("Release deleted from database");
 
+    update_generated_files(&conn, config, release_store_name).await
+}
+
+pub async fn rollback_release(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    use crate::db::models::{Artifact, Package, Release, Submit};
+    use crate::schema;
+
+    let release_store_name = matches.get_one::<String>("release_store_name").unwrap(); // safe by clap
+    if !(config.releases_directory().exists() && config.releases_directory().is_dir()) {
+        return Err(anyhow!(
+            "Release directory does not exist or does not point to directory: {}",
+            config.releases_directory().display()
+        ));
+    }
+    if !config.release_stores().contains(release_store_name) {
+        return Err(anyhow!("Unknown release store name: {}", release_store_name))
+    }
+
+    let pname = matches.get_one::<String>("package_name").unwrap(); // safe by clap
+    let pvers = matches.get_one::<String>("package_version").unwrap(); // safe by clap
+    let reason = matches.get_one::<String>("reason").unwrap(); // safe by clap
+    let to_release_id = matches.get_one::<i32>("to_release_id");
+    debug!("Rollback Release called for: {:?} {:?} (to {:?})", pname, pvers, to_release_id);
+
+    let conn = db_connection_config.establish_connection()?;
+
+    // The releases that were already rolled back
+    let rolled_back_releases = schema::releases::table
+        .filter(schema::releases::rollback_of.is_not_null())
+        .select(schema::releases::rollback_of)
+        .load::<Option<i32>>(&conn)?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<i32>>();
+
+    let (current_release, current_artifact) = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::releases::table
+            .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+        .inner_join(schema::release_stores::table
+            .on(schema::release_stores::id.eq(schema::releases::release_store_id)))
+        .filter(schema::packages::dsl::name.eq(&pname)
+            .and(schema::packages::dsl::version.eq(&pvers)))
+        .filter(schema::release_stores::dsl::store_name.eq(&release_store_name))
+        .order(schema::releases::dsl::release_date.desc())
+        .select((schema::releases::all_columns, schema::artifacts::all_columns))
+        .first::<(Release, Artifact)>(&conn)
+        .optional()?
+        .ok_or_else(|| anyhow!("No release of {} {} in {}", pname, pvers, release_store_name))?;
+
+    if schema::releases::table
+        .filter(schema::releases::rollback_of.eq(current_release.id))
+        .first::<Release>(&conn)
+        .optional()?
+        .is_some()
+    {
+        return Err(anyhow!("Release {} of {} {} was already rolled back", current_release.id, pname, pvers))
+    }
+
+    let target_query = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::submits::table)
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::releases::table
+            .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+        .filter(schema::packages::dsl::name.eq(&pname))
+        .filter(schema::releases::release_store_id.eq(current_release.release_store_id))
+        .filter(schema::artifacts::id.ne(current_artifact.id))
+        .select((
+            schema::releases::all_columns,
+            schema::artifacts::all_columns,
+            schema::packages::all_columns,
+            schema::submits::all_columns,
+        ))
+        .into_boxed();
+
+    let (target_release, target_artifact, target_package, target_submit) = match to_release_id {
+        Some(id) => target_query
+            .filter(schema::releases::id.eq(id))
+            .first::<(Release, Artifact, Package, Submit)>(&conn)
+            .optional()?
+            .ok_or_else(|| {
+                anyhow!("Release {} is not a release of another artifact of {} in {}", id, pname, release_store_name)
+            })?,
+
+        None => target_query
+            .filter(schema::releases::release_date.lt(current_release.release_date))
+            .filter(diesel::dsl::not(schema::releases::id.eq_any(rolled_back_releases)))
+            .order(schema::releases::dsl::release_date.desc())
+            .first::<(Release, Artifact, Package, Submit)>(&conn)
+            .optional()?
+            .ok_or_else(|| {
+                anyhow!("No previous release of {} in {}, pass the release to roll back to explicitly", pname, release_store_name)
+            })?,
+    };
+    debug!("Rolling back to release {:?} of artifact {:?}", target_release, target_artifact);
+
+    let store_path = config.releases_directory().join(release_store_name);
+    let current_path = store_path.join(&current_artifact.path);
+    let target_path = store_path.join(&target_artifact.path);
+
+    let staging_path = config.staging_directory()
+        .join(target_submit.uuid.to_string())
+        .join(&target_artifact.path);
+    let restore_source = rollback_restore_source(&current_path, &target_path, staging_path)?;
+
+    writeln!(std::io::stderr(), "Going to roll back: Release with ID {} from {} ({})", current_release.id, current_release.release_date, current_path.display())?;
+    writeln!(std::io::stderr(), "to: Release with ID {} from {} ({} {})", target_release.id, target_release.release_date, target_package.name, target_package.version)?;
+    if let Some(source) = restore_source.as_ref() {
+        writeln!(std::io::stderr(), "Going to restore: {} from {}", target_path.display(), source.display())?;
+    }
+    if target_path != current_path {
+        writeln!(std::io::stderr(), "Going to delete: {}", current_path.display())?;
+    }
+    if !matches.get_flag("noninteractive") && !crate::ui::confirm("Continue?")? {
+        return Ok(())
+    }
+
+    // Copy the restored file next to its destination first, so that it can be moved into place
+    // atomically
+    let restore_tmp_path = match restore_source {
+        Some(source) => {
+            let tmp_path = target_path.with_file_name({
+                let mut name = target_path.file_name()
+                    .ok_or_else(|| anyhow!("Not a file path: {}", target_path.display()))?
+                    .to_os_string();
+                name.push(".rollback");
+                name
+            });
+
+            if let Some(parent) = tmp_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(&source, &tmp_path)
+                .await
+                .with_context(|| anyhow!("Copying {} to {}", source.display(), tmp_path.display()))?;
+            Some(tmp_path)
+        },
+        None => None,
+    };
+
+    let now = chrono::offset::Local::now().naive_local();
+    let release = conn.transaction::<_, Error, _>(|| {
+        let release = Release::create_rollback(&conn, &target_artifact, &now, &current_release, reason)?;
+
+        if let Some(tmp_path) = restore_tmp_path.as_ref() {
+            std::fs::rename(tmp_path, &target_path)
+                .with_context(|| anyhow!("Moving {} to {}", tmp_path.display(), target_path.display()))?;
+            info!("File restored: {}", target_path.display());
+        }
+
+        if target_path != current_path && current_path.is_file() {
+            std::fs::remove_file(&current_path)
+                .with_context(|| anyhow!("Removing {}", current_path.display()))?;
+            info!("File removed: {}", current_path.display());
+        }
+
+        Ok(release)
+    });
+
+    if release.is_err() {
+        if let Some(tmp_path) = restore_tmp_path.as_ref().filter(|p| p.exists()) {
+            let _ = tokio::fs::remove_file(tmp_path).await;
+        }
+    }
+
+    let release = release?;
+    info!("Release created in database: {:?}", release);
+    writeln!(std::io::stdout(), "{}", target_path.display())?;
+
+    update_generated_files(&conn, config, release_store_name).await
+}
+
+/// Sync the released files of a release store to a remote storage
+async fn sync_release_store(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    use crate::schema;
+
+    let release_store_name = matches.get_one::<String>("release_store_name").unwrap(); // safe by clap
+    if !config.release_stores().contains(release_store_name) {
+        return Err(anyhow!("Unknown release store name: {}", release_store_name))
+    }
+
+    let target_name = matches.get_one::<String>("sync_target").unwrap(); // safe by clap
+    let target = config.release_sync_targets()
+        .get(target_name)
+        .ok_or_else(|| anyhow!("Unknown release sync target: {}", target_name))?;
+
+    let pname = matches.get_one::<String>("package_name");
+    let pvers = matches.get_one::<String>("package_version");
+    let dry_run = matches.get_flag("dry_run");
+    debug!("Sync of {} to {} called for: {:?} {:?}", release_store_name, target_name, pname, pvers);
+
+    let conn = db_connection_config.establish_connection()?;
+
+    let mut query = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::releases::table
+            .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+        .inner_join(schema::release_stores::table
+            .on(schema::release_stores::id.eq(schema::releases::release_store_id)))
+        .filter(schema::release_stores::dsl::store_name.eq(release_store_name))
+        .select((schema::releases::all_columns, schema::artifacts::path))
+        .into_boxed();
+
+    if let Some(name) = pname {
+        query = query.filter(schema::packages::name.eq(name));
+    }
+    if let Some(vers) = pvers {
+        query = query.filter(schema::packages::version.like(vers));
+    }
+    if !matches.get_flag("force") {
+        let synced = schema::release_syncs::table
+            .filter(schema::release_syncs::target.eq(target_name))
+            .select(schema::release_syncs::release_id);
+        query = query.filter(diesel::dsl::not(schema::releases::id.eq_any(synced)));
+    }
+
+    let store_path = config.releases_directory().join(release_store_name);
+
+    let (releases, mut files) = releases_in_store(&store_path, query.load::<(dbmodels::Release, String)>(&conn)?);
+    if files.is_empty() {
+        info!("Nothing to sync to {}", target_name);
+        return Ok(())
+    }
+
+    // The repository metadata changes with every release, so it is synced with every sync
+    files.extend(crate::release_metadata::metadata_files(&store_path)?);
+
+    let synced = sync_files(target, &store_path, release_store_name, &files, dry_run, &mut std::io::stdout())
+        .await
+        .with_context(|| anyhow!("Syncing {} to {}", release_store_name, target_name))?;
+
+    if synced {
+        conn.transaction::<_, Error, _>(|| {
+            releases.iter()
+                .try_for_each(|release| dbmodels::ReleaseSync::create(&conn, release, target_name))
+        })?;
+    }
+    Ok(())
+}
+
+/// The releases whose files are in the release store at `store_path`, and their files
+///
+/// Only the files that are (still) in the release store can be synced, the files of rolled back
+/// or removed releases might be gone.
+fn releases_in_store<R>(store_path: &Path, releases: Vec<(R, String)>) -> (Vec<R>, Vec<PathBuf>) {
+    let (releases, files): (Vec<R>, std::collections::BTreeSet<PathBuf>) = releases
+        .into_iter()
+        .filter(|(_, path)| {
+            let exists = store_path.join(path).is_file();
+            if !exists {
+                debug!("Not syncing {}, it is not in the release store", path);
+            }
+            exists
+        })
+        .map(|(release, path)| (release, PathBuf::from(path)))
+        .unzip();
+
+    (releases, files.into_iter().collect())
+}
+
+/// Sync the `files` of the release store and print them, returns whether they were synced
+///
+/// With `dry_run` the files are only printed.
+async fn sync_files<W: Write>(
+    target: &crate::config::ReleaseSyncTarget,
+    store_path: &Path,
+    release_store_name: &str,
+    files: &[PathBuf],
+    dry_run: bool,
+    out: &mut W,
+) -> Result<bool> {
+    if !dry_run {
+        crate::release_sync::sync(target, store_path, release_store_name, files).await?;
+    }
+
+    for file in files {
+        writeln!(out, "{}", store_path.join(file).display())?;
+    }
+    Ok(!dry_run)
+}
+
+/// Update the files that are generated in a release store when its releases change
+///
+/// These are the "latest" links and the package repository metadata, if they are configured for
+/// the release store.
+async fn update_generated_files(conn: &PgConnection, config: &Configuration, release_store_name: &str) -> Result<()> {
+    let formats = config.release_metadata()
+        .get(release_store_name)
+        .filter(|formats| !formats.is_empty());
+    let links = config.release_latest_links()
+        .as_ref()
+        .filter(|links| links.release_stores().is_empty() || links.release_stores().iter().any(|s| s == release_store_name));
+
+    if formats.is_none() && links.is_none() {
+        return Ok(())
+    }
+
+    let packages: crate::release_metadata::ReleasedPackages = crate::schema::jobs::table
+        .inner_join(crate::schema::packages::table)
+        .inner_join(crate::schema::artifacts::table)
+        .inner_join(crate::schema::releases::table
+            .on(crate::schema::releases::artifact_id.eq(crate::schema::artifacts::id)))
+        .inner_join(crate::schema::release_stores::table
+            .on(crate::schema::release_stores::id.eq(crate::schema::releases::release_store_id)))
+        .filter(crate::schema::release_stores::dsl::store_name.eq(release_store_name))
+        .select((crate::schema::artifacts::path, crate::schema::packages::name, crate::schema::packages::version))
+        .load::<(String, String, String)>(conn)?
+        .into_iter()
+        .map(|(path, name, version)| (PathBuf::from(path), (name, version)))
+        .collect();
+
+    let store_path = config.releases_directory().join(release_store_name);
+    if let Some(links) = links {
+        crate::release_links::update(&store_path, links.per_major_version(), &packages)
+            .with_context(|| anyhow!("Updating the latest links in {}", store_path.display()))?;
+        info!("Latest links updated in {}", store_path.display());
+    }
+
+    if let Some(formats) = formats {
+        crate::release_metadata::generate(&store_path, formats, packages).await?;
+        info!("Repository metadata updated in {}", store_path.display());
+    }
     Ok(())
 }
 
+/// The file that the file of the release that is rolled back to has to be restored from
+///
+/// The file has to be restored from the staging store if it was removed from the release store or
+/// if it is overwritten by the release that is rolled back. Returns `None` if the file does not
+/// need to be restored.
+fn rollback_restore_source(current_path: &Path, target_path: &Path, staging_path: PathBuf) -> Result<Option<PathBuf>> {
+    if target_path.is_file() && target_path != current_path {
+        return Ok(None)
+    }
+
+    if !staging_path.is_file() {
+        return Err(anyhow!(
+            "Cannot restore {}: not in the release store and not in the staging store ({})",
+            target_path.display(),
+            staging_path.display()
+        ))
+    }
+    Ok(Some(staging_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restore_source() {
+        let dir = std::env::temp_dir().join(format!("butido-test-rollback-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let current = dir.join("foo-2.0.tar");
+        let target = dir.join("foo-1.0.tar");
+        let staging = dir.join("staging-foo-1.0.tar");
+        std::fs::write(&current, "2.0").unwrap();
+
+        // The file of the target release was removed from the release store
+        assert!(rollback_restore_source(&current, &target, staging.clone()).is_err());
+        std::fs::write(&staging, "1.0").unwrap();
+        assert_eq!(rollback_restore_source(&current, &target, staging.clone()).unwrap(), Some(staging.clone()));
+
+        // The file of the target release is still in the release store
+        std::fs::write(&target, "1.0").unwrap();
+        assert_eq!(rollback_restore_source(&current, &target, staging.clone()).unwrap(), None);
+
+        // The rebuild of the same version overwrote the file of the target release
+        assert_eq!(rollback_restore_source(&current, &current, staging.clone()).unwrap(), Some(staging));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_releases_in_store() {
+        let dir = std::env::temp_dir().join(format!("butido-test-sync-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("x86_64")).unwrap();
+        std::fs::write(dir.join("x86_64/foo-1.0.rpm"), "").unwrap();
+        std::fs::write(dir.join("bar-1.0.tar"), "").unwrap();
+
+        let releases = vec![
+            (1, String::from("x86_64/foo-1.0.rpm")),
+            (2, String::from("gone-1.0.tar")),
+            (3, String::from("bar-1.0.tar")),
+            (4, String::from("x86_64/foo-1.0.rpm")), // released again
+        ];
+        let (releases, files) = releases_in_store(&dir, releases);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(releases, [1, 3, 4]);
+        assert_eq!(files, [PathBuf::from("bar-1.0.tar"), PathBuf::from("x86_64/foo-1.0.rpm")]);
+    }
+
+    #[test]
+    fn test_sync_files_dry_run() {
+        // neither the file nor the endpoint exists, so actually syncing fails
+        let target: crate::config::ReleaseSyncTarget = toml::from_str(indoc::indoc!(r#"
+            type = "s3"
+            endpoint = "http://127.0.0.1:1"
+            bucket = "releases"
+            access_key = "key"
+            secret_key = "secret"
+        "#)).unwrap();
+        let store_path = PathBuf::from("/srv/releases/default");
+        let files = [PathBuf::from("foo-1.0.tar")];
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut out = Vec::new();
+        let synced = rt.block_on(sync_files(&target, &store_path, "default", &files, true, &mut out)).unwrap();
+        assert!(!synced);
+        assert_eq!(String::from_utf8(out).unwrap(), "/srv/releases/default/foo-1.0.tar\n");
+
+        let mut out = Vec::new();
+        assert!(rt.block_on(sync_files(&target, &store_path, "default", &files, false, &mut out)).is_err());
+        assert!(out.is_empty());
+    }
+}
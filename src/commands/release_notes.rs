@@ -0,0 +1,273 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'release notes' subcommand
+//!
+//! Generates a Markdown changelog of the releases between two points in time.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::Local;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use tracing::debug;
+
+use crate::commands::util::get_date_filter;
+use crate::db::DbConnectionConfig;
+use crate::schema;
+
+/// A release of a package version from a submit
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ReleaseNote {
+    store: String,
+    package: String,
+    version: String,
+    released: NaiveDateTime,
+    rollback: bool,
+    submit: uuid::Uuid,
+    submit_time: NaiveDateTime,
+    git_hash: String,
+}
+
+/// Implementation of the "release notes" subcommand
+pub async fn release_notes(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let conn = db_connection_config.establish_connection()?;
+    let since = bound(&conn, "since", matches)?.ok_or_else(|| anyhow!("Missing start of the release notes"))?; // safe by clap
+    let until = bound(&conn, "until", matches)?;
+    debug!("Release notes called for: {} until {:?}", since, until);
+
+    let mut query = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::submits::table)
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::releases::table
+            .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+        .inner_join(schema::release_stores::table
+            .on(schema::release_stores::id.eq(schema::releases::release_store_id)))
+        .inner_join(schema::githashes::table
+            .on(schema::githashes::id.eq(schema::submits::repo_hash_id)))
+        .filter(schema::releases::release_date.gt(since))
+        .order_by(schema::releases::release_date.asc())
+        .select((
+            schema::release_stores::store_name,
+            schema::packages::name,
+            schema::packages::version,
+            schema::releases::release_date,
+            schema::releases::rollback_of,
+            schema::submits::uuid,
+            schema::submits::submit_time,
+            schema::githashes::hash,
+        ))
+        .into_boxed();
+
+    if let Some(until) = until.as_ref() {
+        query = query.filter(schema::releases::release_date.le(until));
+    }
+    if let Some(store) = matches.get_one::<String>("release_store_name") {
+        query = query.filter(schema::release_stores::store_name.eq(store));
+    }
+
+    let mut notes: Vec<ReleaseNote> = Vec::new();
+    for (store, package, version, released, rollback_of, submit, submit_time, git_hash) in query
+        .load::<(String, String, String, NaiveDateTime, Option<i32>, uuid::Uuid, NaiveDateTime, String)>(&conn)?
+    {
+        let note = ReleaseNote { store, package, version, released, rollback: rollback_of.is_some(), submit, submit_time, git_hash };
+
+        // Several artifacts of a package are released at once, they are one release in the notes
+        let duplicate = notes.iter().any(|n| {
+            n.store == note.store && n.package == note.package && n.version == note.version && n.submit == note.submit && n.rollback == note.rollback
+        });
+        if !duplicate {
+            notes.push(note);
+        }
+    }
+
+    // The versions that were released before the notes start, to show the version changes
+    let mut previous_versions = HashMap::new();
+    for note in notes.iter() {
+        let key = (note.store.clone(), note.package.clone());
+        if previous_versions.contains_key(&key) {
+            continue
+        }
+
+        let previous = schema::jobs::table
+            .inner_join(schema::packages::table)
+            .inner_join(schema::artifacts::table)
+            .inner_join(schema::releases::table
+                .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+            .inner_join(schema::release_stores::table
+                .on(schema::release_stores::id.eq(schema::releases::release_store_id)))
+            .filter(schema::release_stores::store_name.eq(&note.store))
+            .filter(schema::packages::name.eq(&note.package))
+            .filter(schema::releases::release_date.le(since))
+            .order_by(schema::releases::release_date.desc())
+            .select(schema::packages::version)
+            .first::<String>(&conn)
+            .optional()
+            .with_context(|| anyhow!("Loading the previous release of {} in {}", note.package, note.store))?;
+        previous_versions.insert(key, previous);
+    }
+
+    let until = until.unwrap_or_else(Local::now);
+    write!(std::io::stdout(), "{}", render(&since, &until, &notes, &previous_versions)).map_err(anyhow::Error::from)
+}
+
+/// The point in time of a submit UUID or a date (or duration) argument
+fn bound(conn: &PgConnection, name: &str, matches: &ArgMatches) -> Result<Option<DateTime<Local>>> {
+    match matches.get_one::<String>(name).map(|s| uuid::Uuid::parse_str(s)) {
+        Some(Ok(submit_uuid)) => schema::submits::table
+            .filter(schema::submits::uuid.eq(submit_uuid))
+            .select(schema::submits::submit_time)
+            .first::<NaiveDateTime>(conn)
+            .with_context(|| anyhow!("Loading submit {} from DB", submit_uuid))
+            .map(|time| Some(chrono::Utc.from_utc_datetime(&time).with_timezone(&Local))),
+        _ => get_date_filter(name, matches),
+    }
+}
+
+/// Render the release notes as Markdown
+///
+/// The releases are grouped by release store and package, and must be in the order they were done.
+fn render(
+    since: &DateTime<Local>,
+    until: &DateTime<Local>,
+    notes: &[ReleaseNote],
+    previous_versions: &HashMap<(String, String), Option<String>>,
+) -> String {
+    let format_date = |date: &NaiveDateTime| date.format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let mut by_store: BTreeMap<&str, BTreeMap<&str, Vec<&ReleaseNote>>> = BTreeMap::new();
+    for note in notes {
+        by_store.entry(&note.store)
+            .or_default()
+            .entry(&note.package)
+            .or_default()
+            .push(note);
+    }
+
+    let mut md = format!(
+        "# Release notes\n\nReleases from {} until {}\n",
+        since.format("%Y-%m-%d %H:%M:%S"),
+        until.format("%Y-%m-%d %H:%M:%S")
+    );
+
+    if by_store.is_empty() {
+        md.push_str("\nNo releases.\n");
+        return md
+    }
+
+    for (store, packages) in by_store {
+        md.push_str(&format!("\n## Release store {store}\n"));
+
+        for (package, releases) in packages {
+            md.push_str(&format!("\n### {package}\n\n"));
+
+            let mut previous = previous_versions
+                .get(&(store.to_string(), package.to_string()))
+                .cloned()
+                .flatten();
+
+            for note in releases {
+                let change = match previous.as_ref() {
+                    _ if note.rollback => String::from(" (rollback)"),
+                    None => String::from(" (new)"),
+                    Some(p) if *p == note.version => String::new(),
+                    Some(p) => format!(" (previously {p})"),
+                };
+
+                md.push_str(&format!(
+                    "- **{}**{}, released {}, from submit `{}` of {} at `{}`\n",
+                    note.version,
+                    change,
+                    format_date(&note.released),
+                    note.submit,
+                    format_date(&note.submit_time),
+                    note.git_hash
+                ));
+                previous = Some(note.version.clone());
+            }
+        }
+    }
+
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(store: &str, package: &str, version: &str, day: u32, rollback: bool) -> ReleaseNote {
+        let time = chrono::NaiveDate::from_ymd_opt(2023, 1, day).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        ReleaseNote {
+            store: store.to_string(),
+            package: package.to_string(),
+            version: version.to_string(),
+            released: time,
+            rollback,
+            submit: uuid::Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            submit_time: time,
+            git_hash: String::from("abc123"),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let since = Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let until = Local.with_ymd_and_hms(2023, 2, 1, 0, 0, 0).unwrap();
+        let notes = vec![
+            note("default", "foo", "1.1", 2, false),
+            note("default", "bar", "2.0", 3, false),
+            note("default", "foo", "1.0", 4, true),
+            note("stable", "foo", "1.1", 5, false),
+        ];
+        let previous = HashMap::from([
+            ((String::from("default"), String::from("foo")), Some(String::from("1.0"))),
+            ((String::from("default"), String::from("bar")), None),
+            ((String::from("stable"), String::from("foo")), Some(String::from("1.1"))),
+        ]);
+
+        assert_eq!(render(&since, &until, &notes, &previous), indoc::indoc!("
+            # Release notes
+
+            Releases from 2023-01-01 00:00:00 until 2023-02-01 00:00:00
+
+            ## Release store default
+
+            ### bar
+
+            - **2.0** (new), released 2023-01-03 12:00:00, from submit `00000000-0000-0000-0000-000000000001` of 2023-01-03 12:00:00 at `abc123`
+
+            ### foo
+
+            - **1.1** (previously 1.0), released 2023-01-02 12:00:00, from submit `00000000-0000-0000-0000-000000000001` of 2023-01-02 12:00:00 at `abc123`
+            - **1.0** (rollback), released 2023-01-04 12:00:00, from submit `00000000-0000-0000-0000-000000000001` of 2023-01-04 12:00:00 at `abc123`
+
+            ## Release store stable
+
+            ### foo
+
+            - **1.1**, released 2023-01-05 12:00:00, from submit `00000000-0000-0000-0000-000000000001` of 2023-01-05 12:00:00 at `abc123`
+        "));
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let since = Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        assert!(render(&since, &since, &[], &HashMap::new()).ends_with("\nNo releases.\n"));
+    }
+}
@@ -0,0 +1,495 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'repo' subcommand
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use tracing::debug;
+
+use crate::config::Configuration;
+use crate::package::condition::ConditionData;
+use crate::package::schema::CURRENT_SCHEMA_VERSION;
+use crate::package::Dag;
+use crate::package::License;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersionConstraint;
+use crate::package::ParseDependency;
+use crate::repository::PackageFileFormat;
+use crate::repository::Repository;
+use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "repo" subcommand
+pub async fn repo<L>(
+    repo_path: &Path,
+    matches: &ArgMatches,
+    config: &Configuration,
+    progressbars: ProgressBars,
+    load_repo: L,
+) -> Result<()>
+where
+    L: FnOnce() -> Result<Repository>,
+{
+    match matches.subcommand() {
+        Some(("check", matches)) => check(repo_path, matches, config, progressbars),
+        Some(("migrate", matches)) => migrate(repo_path, matches),
+        Some(("licenses", matches)) => licenses(matches, config, load_repo()?),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, parse_display::Display)]
+#[display(style = "lowercase")]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem that was found in a package definition
+struct Finding {
+    severity: Severity,
+    package: Option<(String, String)>,
+    path: PathBuf,
+    check: &'static str,
+    message: String,
+}
+
+impl Finding {
+    fn for_package(severity: Severity, package: &Package, check: &'static str, message: String) -> Self {
+        Finding {
+            severity,
+            package: Some((package.name().to_string(), package.version().to_string())),
+            path: package.path().clone(),
+            check,
+            message,
+        }
+    }
+}
+
+/// Validate all package definitions of the repositories
+fn check(repo_path: &Path, matches: &ArgMatches, config: &Configuration, progressbars: ProgressBars) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let bar = progressbars.bar()?;
+
+    let mut findings = Vec::new();
+    let mut packages = Vec::new();
+    let mut overlay = BTreeMap::new();
+    for root in Repository::roots(repo_path, config.repositories())? {
+        let mut root_packages = Vec::new();
+        for (path, result) in Repository::load_packages(repo_path, &root, config.package_variables(), None, &bar)? {
+            match result {
+                Ok(package) => root_packages.push(package),
+                Err(e) => findings.push(Finding {
+                    severity: Severity::Error,
+                    package: None,
+                    path,
+                    check: "parse",
+                    message: format!("{e:#}"),
+                }),
+            }
+        }
+
+        // Packages may be overridden by later repositories, but not within one repository
+        findings.extend(check_duplicates(&root_packages));
+        overlay.extend(root_packages.iter().map(|p| ((p.name().clone(), p.version().clone()), p.clone())));
+        packages.extend(root_packages);
+    }
+    bar.finish_with_message("Repository loading finished");
+    debug!("Loaded {} packages, {} failed to load", packages.len(), findings.len());
+
+    let repo = Repository::from(overlay).with_preferred_providers(config.preferred_providers().clone());
+
+    for package in packages.iter() {
+        findings.extend(check_dependencies(&repo, package));
+        findings.extend(check_hashes(package));
+        findings.extend(check_phases(config, package));
+        findings.extend(check_images(config, package));
+    }
+
+    findings.sort_by(|a, b| (a.severity, &a.path).cmp(&(b.severity, &b.path)));
+    let n_errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let n_warnings = findings.len() - n_errors;
+
+    let header = crate::commands::util::mk_header(["Severity", "Package", "Version", "Path", "Check", "Message"].to_vec());
+    let data = findings
+        .into_iter()
+        .map(|f| {
+            let (name, version) = f.package.unwrap_or_else(|| (String::from("-"), String::from("-")));
+            vec![
+                f.severity.to_string(),
+                name,
+                version,
+                f.path.display().to_string(),
+                f.check.to_string(),
+                f.message,
+            ]
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(header, data, csv)?;
+
+    if n_errors > 0 {
+        Err(anyhow!("Repository check found {} errors and {} warnings", n_errors, n_warnings))
+    } else {
+        if n_warnings > 0 {
+            tracing::warn!("Repository check found {} warnings", n_warnings);
+        }
+        Ok(())
+    }
+}
+
+/// Find packages with the same name and version
+///
+/// When loading the repository, only one of them is used, the others are silently ignored.
+fn check_duplicates(packages: &[Package]) -> Vec<Finding> {
+    let mut by_name_version: BTreeMap<_, Vec<&Package>> = BTreeMap::new();
+    for package in packages {
+        by_name_version.entry((package.name(), package.version())).or_default().push(package);
+    }
+
+    by_name_version
+        .into_values()
+        .filter(|packages| packages.len() > 1)
+        .flat_map(|packages| {
+            let paths = packages.iter().map(|p| p.path().display().to_string()).collect::<Vec<_>>().join(", ");
+            packages.into_iter().map(move |package| {
+                Finding::for_package(Severity::Error, package, "duplicate", format!("Defined multiple times: {paths}"))
+            })
+        })
+        .collect()
+}
+
+/// Find dependencies (including those of flavors and features) that cannot be satisfied by any
+/// package in the repository
+fn check_dependencies(repo: &Repository, package: &Package) -> Vec<Finding> {
+    let mut dependencies = Vec::new();
+    dependencies.extend(package.dependencies().build().iter().map(|d| ("", d.parse_as_name_and_version())));
+    dependencies.extend(package.dependencies().runtime().iter().map(|d| ("", d.parse_as_name_and_version())));
+    for flavor in package.flavors().values() {
+        dependencies.extend(flavor.build_dependencies().iter().map(|d| ("flavor ", d.parse_as_name_and_version())));
+        dependencies.extend(flavor.runtime_dependencies().iter().map(|d| ("flavor ", d.parse_as_name_and_version())));
+    }
+    for feature in package.features().values() {
+        dependencies.extend(feature.build_dependencies().iter().map(|d| ("feature ", d.parse_as_name_and_version())));
+        dependencies.extend(feature.runtime_dependencies().iter().map(|d| ("feature ", d.parse_as_name_and_version())));
+    }
+
+    dependencies
+        .into_iter()
+        .filter_map(|(origin, dependency)| {
+            let (name, constraint) = match dependency {
+                Ok(d) => d,
+                Err(e) => {
+                    return Some(Finding::for_package(Severity::Error, package, "dependency", format!("Invalid {origin}dependency: {e:#}")))
+                },
+            };
+
+            if !repo.find_with_version(&name, &constraint).is_empty() {
+                return None
+            }
+
+            match repo.find_providers(&name, &constraint) {
+                Ok(providers) if !providers.is_empty() => None,
+                Ok(_) if repo.find_by_name(&name).is_empty() => {
+                    Some(Finding::for_package(Severity::Error, package, "dependency", format!("Dangling {origin}dependency: no package {name}")))
+                },
+                Ok(_) => {
+                    Some(Finding::for_package(Severity::Error, package, "dependency", format!("Dangling {origin}dependency: no version of {name} matches {constraint}")))
+                },
+                Err(e) => Some(Finding::for_package(Severity::Error, package, "dependency", format!("{e:#}"))),
+            }
+        })
+        .collect()
+}
+
+/// Find sources without a hash
+fn check_hashes(package: &Package) -> Vec<Finding> {
+    package.sources()
+        .iter()
+        .filter(|(_, source)| source.hash().value().to_string().trim().is_empty())
+        .map(|(name, _)| Finding::for_package(Severity::Error, package, "hash", format!("Source '{name}' has no hash")))
+        .collect()
+}
+
+/// Find phases (including those of flavors) that are not in the `available_phases` setting
+fn check_phases(config: &Configuration, package: &Package) -> Vec<Finding> {
+    let flavor_phases = package.flavors()
+        .values()
+        .flat_map(|flavor| flavor.phases().keys().chain(flavor.disabled_phases().iter()));
+
+    package.phases()
+        .keys()
+        .chain(flavor_phases)
+        .filter(|phase| !config.available_phases().contains(phase))
+        .map(|phase| {
+            Finding::for_package(Severity::Error, package, "phase", format!("Unknown phase '{}', it is not in available_phases", phase.as_str()))
+        })
+        .collect()
+}
+
+/// Find inconsistent `allowed_images` and `denied_images` settings
+fn check_images(config: &Configuration, package: &Package) -> Vec<Finding> {
+    let is_configured = |image: &ImageName| {
+        config.docker().images().iter().any(|img| img.name == *image || img.short_name == *image)
+    };
+
+    let allowed = package.allowed_images().as_deref().unwrap_or_default();
+    let denied = package.denied_images().as_deref().unwrap_or_default();
+    let mut findings = Vec::new();
+
+    for image in allowed.iter().filter(|image| denied.contains(image)) {
+        findings.push(Finding::for_package(Severity::Error, package, "image", format!("Image {image} is allowed and denied")));
+    }
+
+    for image in allowed.iter().chain(denied.iter()).filter(|image| !is_configured(image)) {
+        findings.push(Finding::for_package(Severity::Warning, package, "image", format!("Image {image} is not in the configured images")));
+    }
+
+    if !allowed.is_empty() && allowed.iter().all(|image| denied.contains(image)) {
+        findings.push(Finding::for_package(Severity::Error, package, "image", String::from("All allowed images are denied, the package cannot be built")));
+    }
+
+    findings
+}
+
+/// The packages of a dependency tree with the same license (or without one)
+#[derive(Debug, Eq, PartialEq)]
+struct LicenseUsage {
+    license: Option<License>,
+    status: LicenseStatus,
+    packages: BTreeSet<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, parse_display::Display)]
+#[display(style = "lowercase")]
+enum LicenseStatus {
+    Ok,
+    Missing,
+    Denied,
+    Invalid,
+}
+
+/// Report the licenses in the dependency trees of the selected packages
+fn licenses(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let pname = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from)
+        .unwrap(); // safe by clap
+    let pvers = matches
+        .get_one::<String>("package_version")
+        .map(|s| s.to_owned())
+        .map(PackageVersionConstraint::try_from)
+        .transpose()?;
+    let image_name = matches
+        .get_one::<String>("image")
+        .map(|s| s.to_owned())
+        .map(ImageName::from);
+
+    let condition_data = ConditionData {
+        image_name: image_name.as_ref(),
+        env: &[],
+    };
+
+    let roots = repo.find_by_name(&pname)
+        .into_iter()
+        .filter(|p| pvers.as_ref().map(|v| v.matches(p.version())).unwrap_or(true))
+        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        return Err(anyhow!("No package found: {} {}", pname, pvers.map(|v| v.to_string()).unwrap_or_default()))
+    }
+
+    let mut packages = BTreeMap::new();
+    for root in roots {
+        let dag = Dag::for_root_package(root.clone(), &repo, None, &condition_data)?;
+        for package in dag.all_packages() {
+            packages.insert(format!("{} {}", package.name(), package.version()), package.license().clone());
+        }
+    }
+    debug!("Found {} packages in the trees", packages.len());
+
+    let usages = license_usages(packages, config.denied_licenses());
+    let count = |status| usages.iter().filter(|u| u.status == status).map(|u| u.packages.len()).sum::<usize>();
+    let (n_missing, n_denied, n_invalid) = (count(LicenseStatus::Missing), count(LicenseStatus::Denied), count(LicenseStatus::Invalid));
+
+    let header = crate::commands::util::mk_header(["License", "Status", "Count", "Packages"].to_vec());
+    let data = usages
+        .into_iter()
+        .map(|u| {
+            vec![
+                u.license.map(|l| l.to_string()).unwrap_or_else(|| String::from("-")),
+                u.status.to_string(),
+                u.packages.len().to_string(),
+                u.packages.into_iter().collect::<Vec<_>>().join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(header, data, csv)?;
+
+    if n_missing > 0 {
+        tracing::warn!("{} packages have no license", n_missing);
+    }
+    if n_invalid > 0 {
+        tracing::warn!("{} packages have an invalid license expression", n_invalid);
+    }
+    if n_denied > 0 {
+        Err(anyhow!("{} packages have a denied license", n_denied))
+    } else {
+        Ok(())
+    }
+}
+
+/// Group the packages ("name version") by their license and check the licenses against `denied`
+///
+/// The denied, invalid and missing licenses come first, then the licenses by number of packages.
+fn license_usages(packages: BTreeMap<String, Option<License>>, denied: &[String]) -> Vec<LicenseUsage> {
+    let mut by_license: BTreeMap<Option<License>, BTreeSet<String>> = BTreeMap::new();
+    for (package, license) in packages {
+        by_license.entry(license).or_default().insert(package);
+    }
+
+    let mut usages = by_license
+        .into_iter()
+        .map(|(license, packages)| {
+            let status = match license.as_ref().map(|l| l.is_denied(denied)) {
+                None => LicenseStatus::Missing,
+                Some(Ok(true)) => LicenseStatus::Denied,
+                Some(Ok(false)) => LicenseStatus::Ok,
+                Some(Err(e)) => {
+                    debug!("Invalid license expression: {:#}", e);
+                    LicenseStatus::Invalid
+                },
+            };
+            LicenseUsage { license, status, packages }
+        })
+        .collect::<Vec<_>>();
+
+    let rank = |status: LicenseStatus| match status {
+        LicenseStatus::Denied => 0,
+        LicenseStatus::Invalid => 1,
+        LicenseStatus::Missing => 2,
+        LicenseStatus::Ok => 3,
+    };
+    usages.sort_by(|a, b| {
+        rank(a.status).cmp(&rank(b.status))
+            .then_with(|| b.packages.len().cmp(&a.packages.len()))
+            .then_with(|| a.license.cmp(&b.license))
+    });
+    usages
+}
+
+/// Migrate all `pkg.toml` files of the repository to the current schema version
+fn migrate(repo_path: &Path, matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.get_flag("dry_run");
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+
+    // Only pkg.toml files can be migrated, the other formats are usually generated
+    let migrated = Repository::package_files(repo_path)?
+        .into_iter()
+        .filter(|path| PackageFileFormat::from_path(path) == Some(PackageFileFormat::Toml))
+        .map(|path| {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| anyhow!("Reading {}", path.display()))?;
+
+            let migrated = crate::package::schema::migrate(&content)
+                .with_context(|| anyhow!("Migrating {}", path.display()))?;
+
+            let migrated = match migrated {
+                Some(migrated) => migrated,
+                None => return Ok(false),
+            };
+
+            if dry_run {
+                writeln!(outlock, "Would migrate {}", path.display())?;
+            } else {
+                std::fs::write(&path, migrated).with_context(|| anyhow!("Writing {}", path.display()))?;
+                writeln!(outlock, "Migrated {}", path.display())?;
+            }
+            Ok(true)
+        })
+        .collect::<Result<Vec<bool>>>()?
+        .into_iter()
+        .filter(|migrated| *migrated)
+        .count();
+
+    if migrated == 0 {
+        writeln!(outlock, "All files use pkg.toml schema version {CURRENT_SCHEMA_VERSION}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+
+    fn messages(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|f| f.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_check_duplicates() {
+        let mut a1 = package("a", "1", "https://rust-lang.org", "123");
+        a1.set_path(PathBuf::from("a/pkg.toml"));
+        let mut a1_again = a1.clone();
+        a1_again.set_path(PathBuf::from("a/again/pkg.toml"));
+        let a2 = package("a", "2", "https://rust-lang.org", "123");
+
+        assert!(check_duplicates(&[a1.clone(), a2.clone()]).is_empty());
+
+        let findings = check_duplicates(&[a1, a2, a1_again]);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.check == "duplicate" && f.severity == Severity::Error));
+        assert_eq!(findings[0].message, "Defined multiple times: a/pkg.toml, a/again/pkg.toml");
+        assert_eq!(findings[1].path, PathBuf::from("a/again/pkg.toml"));
+    }
+
+    #[test]
+    fn test_check_dependencies() {
+        let b1 = package("b", "1", "https://rust-lang.org", "123");
+        let repo = Repository::from(BTreeMap::from([((b1.name().clone(), b1.version().clone()), b1.clone())]));
+
+        let with_dependency = |dependency: &str| {
+            let mut p = package("a", "1", "https://rust-lang.org", "123");
+            p.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from(dependency))));
+            p
+        };
+
+        assert!(check_dependencies(&repo, &b1).is_empty());
+        assert!(check_dependencies(&repo, &with_dependency("b =1")).is_empty());
+        assert_eq!(messages(&check_dependencies(&repo, &with_dependency("b =2"))), ["Dangling dependency: no version of b matches =2"]);
+        assert_eq!(messages(&check_dependencies(&repo, &with_dependency("c =1"))), ["Dangling dependency: no package c"]);
+
+        let findings = check_dependencies(&repo, &with_dependency("b"));
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.starts_with("Invalid dependency"), "{}", findings[0].message);
+    }
+
+    #[test]
+    fn test_check_hashes() {
+        assert!(check_hashes(&package("a", "1", "https://rust-lang.org", "123")).is_empty());
+        assert_eq!(messages(&check_hashes(&package("a", "1", "https://rust-lang.org", " "))), ["Source 'src' has no hash"]);
+    }
+}
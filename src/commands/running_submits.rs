@@ -0,0 +1,455 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The bookkeeping of the submits that were started by the 'serve' subcommand
+//!
+//! This is kept free of the gRPC types, so that it can be tested without the "grpc" feature. The
+//! 'serve' subcommand converts the types of this module to the protobuf messages.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use getset::CopyGetters;
+use getset::Getters;
+use uuid::Uuid;
+
+use crate::log::JobResult;
+
+/// The number of events of a submit that are buffered for slow clients
+const EVENTS_CHANNEL_CAPACITY: usize = 1024;
+
+/// A job event from the output of `butido build --output json-lines`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JobUpdate {
+    pub job_uuid: String,
+    pub package: String,
+    pub event: String,
+    pub time: String,
+    pub endpoint: Option<String>,
+    pub phase: Option<String>,
+    pub artifact: Option<String>,
+    pub status: Option<String>,
+}
+
+impl JobUpdate {
+    /// Parse a job event from a line of the output of `butido build --output json-lines`
+    pub fn parse(line: &str) -> Option<Self> {
+        let event = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        let string = |key: &str| event.get(key).and_then(serde_json::Value::as_str).map(String::from);
+
+        Some(JobUpdate {
+            job_uuid: string("job")?,
+            package: string("package").unwrap_or_default(),
+            event: string("event")?,
+            time: string("time").unwrap_or_default(),
+            endpoint: string("endpoint"),
+            phase: string("phase"),
+            artifact: string("artifact"),
+            status: string("status"),
+        })
+    }
+
+    /// The job that was built or failed with this event, if any
+    ///
+    /// Only these jobs are recorded in the database and can be loaded from there.
+    pub fn recorded_job(&self) -> Option<Uuid> {
+        match self.status.as_deref() {
+            Some("built") | Some("failed") => Uuid::parse_str(&self.job_uuid).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The status of a job of a submit, as far as it is known from the job events
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JobStatus {
+    pub job_uuid: String,
+    pub package: String,
+    pub status: String,
+    pub endpoint: Option<String>,
+    pub phase: Option<String>,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "built" | "reused" | "failed" | "stopped")
+    }
+}
+
+/// A finished job, as loaded from the database
+#[derive(Clone, Debug)]
+pub struct FinishedJob {
+    pub job_uuid: Uuid,
+    pub package_name: String,
+    pub package_version: String,
+    pub endpoint: String,
+    pub container_hash: String,
+    pub state: JobResult,
+    pub log: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SubmitResult {
+    Succeeded,
+    Failed(String),
+    Cancelled,
+}
+
+impl SubmitResult {
+    /// The result of a submit from the exit of its build process
+    pub fn from_exit(cancelled: bool, status: std::io::Result<std::process::ExitStatus>, stderr: &str) -> Self {
+        match status {
+            _ if cancelled => SubmitResult::Cancelled,
+            Ok(status) if status.success() => SubmitResult::Succeeded,
+            Ok(_) => SubmitResult::Failed(stderr.trim().to_string()),
+            Err(e) => SubmitResult::Failed(e.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SubmitEvent {
+    JobUpdate(JobUpdate),
+    JobFinished(FinishedJob),
+    SubmitFinished(SubmitResult),
+}
+
+impl SubmitEvent {
+    pub fn is_submit_finished(&self) -> bool {
+        matches!(self, SubmitEvent::SubmitFinished(_))
+    }
+}
+
+/// A submit that was started by the server
+#[derive(Getters, CopyGetters)]
+pub struct RunningSubmit {
+    /// The events of the submit so far, sent first to new subscribers
+    events: Vec<SubmitEvent>,
+
+    /// Sends the new events of the submit to the subscribers
+    sender: tokio::sync::broadcast::Sender<SubmitEvent>,
+
+    /// The jobs of the submit that were scheduled so far, by job UUID
+    #[getset(get = "pub")]
+    jobs: BTreeMap<String, JobStatus>,
+
+    /// The status endpoint of the build process, which serves the logs of the running jobs
+    #[getset(get_copy = "pub")]
+    status_address: SocketAddr,
+
+    /// Cancels the submit, taken when the submit is cancelled
+    cancel: Option<tokio::sync::oneshot::Sender<()>>,
+
+    /// The result of the submit, once it is finished
+    #[getset(get = "pub")]
+    result: Option<SubmitResult>,
+}
+
+impl RunningSubmit {
+    pub fn new(status_address: SocketAddr, cancel: tokio::sync::oneshot::Sender<()>) -> Self {
+        RunningSubmit {
+            events: Vec::new(),
+            sender: tokio::sync::broadcast::channel(EVENTS_CHANNEL_CAPACITY).0,
+            jobs: BTreeMap::new(),
+            status_address,
+            cancel: Some(cancel),
+            result: None,
+        }
+    }
+
+    /// Record an event of the submit and send it to the subscribers
+    pub fn push(&mut self, event: SubmitEvent) {
+        match &event {
+            SubmitEvent::JobUpdate(update) => self.update_job_status(update),
+            SubmitEvent::JobFinished(_) => {},
+            SubmitEvent::SubmitFinished(result) => {
+                self.cancel = None;
+                self.result = Some(result.clone());
+            },
+        }
+
+        self.events.push(event.clone());
+        let _ = self.sender.send(event); // no subscribers is fine
+    }
+
+    /// The events of the submit so far and a receiver for the following events
+    ///
+    /// Both are taken together, so that no event is missed or received twice.
+    pub fn subscribe(&self) -> (Vec<SubmitEvent>, tokio::sync::broadcast::Receiver<SubmitEvent>) {
+        (self.events.clone(), self.sender.subscribe())
+    }
+
+    /// Take the sender that cancels the submit, `None` if the submit is not running anymore
+    pub fn take_cancel(&mut self) -> Option<tokio::sync::oneshot::Sender<()>> {
+        self.cancel.take()
+    }
+
+    /// Whether a job of the submit is finished, i.e. its log is complete in the database
+    pub fn is_job_finished(&self, job_uuid: &Uuid) -> bool {
+        self.result.is_some() || self.jobs.get(&job_uuid.to_string()).map(JobStatus::is_finished).unwrap_or(false)
+    }
+
+    fn update_job_status(&mut self, update: &JobUpdate) {
+        let job = self.jobs.entry(update.job_uuid.clone()).or_insert_with(|| JobStatus {
+            job_uuid: update.job_uuid.clone(),
+            package: update.package.clone(),
+            status: String::from("scheduling"),
+            endpoint: None,
+            phase: None,
+        });
+
+        match update.event.as_str() {
+            "job_scheduled" => job.status = String::from("scheduling"),
+            "job_retrying" => job.status = String::from("retrying"),
+            "job_started" => {
+                job.status = String::from("running");
+                job.endpoint = update.endpoint.clone();
+                job.phase = None;
+            },
+            "phase_changed" => job.phase = update.phase.clone(),
+            "job_finished" => {
+                if let Some(status) = update.status.as_ref() {
+                    job.status = status.clone();
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Why a submit could not be found
+#[derive(Debug)]
+pub enum SubmitLookupError {
+    InvalidUuid(String),
+    NotFound(Uuid),
+}
+
+impl std::fmt::Display for SubmitLookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubmitLookupError::InvalidUuid(e) => write!(f, "Not a submit UUID: {e}"),
+            SubmitLookupError::NotFound(uuid) => write!(f, "Submit {uuid} was not started by this server"),
+        }
+    }
+}
+
+/// The submits that were started by the server, by submit UUID
+#[derive(Clone, Default)]
+pub struct RunningSubmits(Arc<Mutex<HashMap<Uuid, RunningSubmit>>>);
+
+impl RunningSubmits {
+    pub fn insert(&self, submit_uuid: Uuid, submit: RunningSubmit) {
+        self.0.lock().unwrap().insert(submit_uuid, submit);
+    }
+
+    /// Call `f` with the submit, `None` if there is no such submit
+    pub fn with_submit<T, F>(&self, submit_uuid: &Uuid, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut RunningSubmit) -> T,
+    {
+        self.0.lock().unwrap().get_mut(submit_uuid).map(f)
+    }
+
+    /// Call `f` with the submit of a submit UUID that was passed by a client
+    pub fn lookup<T, F>(&self, submit_uuid: &str, f: F) -> Result<(Uuid, T), SubmitLookupError>
+    where
+        F: FnOnce(&mut RunningSubmit) -> T,
+    {
+        let submit_uuid = Uuid::parse_str(submit_uuid).map_err(|e| SubmitLookupError::InvalidUuid(e.to_string()))?;
+
+        self.with_submit(&submit_uuid, f)
+            .map(|t| (submit_uuid, t))
+            .ok_or(SubmitLookupError::NotFound(submit_uuid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running_submit() -> RunningSubmit {
+        RunningSubmit::new("127.0.0.1:1".parse().unwrap(), tokio::sync::oneshot::channel().0)
+    }
+
+    fn update(line: &str) -> SubmitEvent {
+        SubmitEvent::JobUpdate(JobUpdate::parse(line).unwrap())
+    }
+
+    #[test]
+    fn test_parse_job_update() {
+        let update = JobUpdate::parse(r#"{"event":"job_started","time":"2022-01-01T00:00:00Z","job":"a","package":"foo 1.0","endpoint":"ep"}"#).unwrap();
+        assert_eq!(update.job_uuid, "a");
+        assert_eq!(update.package, "foo 1.0");
+        assert_eq!(update.event, "job_started");
+        assert_eq!(update.endpoint.as_deref(), Some("ep"));
+        assert_eq!(update.status, None);
+        assert_eq!(update.recorded_job(), None);
+
+        assert!(JobUpdate::parse(r#"{"event":"submit_started","time":"2022-01-01T00:00:00Z"}"#).is_none());
+        assert!(JobUpdate::parse("not json").is_none());
+    }
+
+    #[test]
+    fn test_recorded_job() {
+        let job_uuid = Uuid::new_v4();
+        let finished = |status: &str| {
+            JobUpdate::parse(&format!(r#"{{"event":"job_finished","job":"{job_uuid}","status":"{status}"}}"#))
+                .unwrap()
+                .recorded_job()
+        };
+
+        assert_eq!(finished("built"), Some(job_uuid));
+        assert_eq!(finished("failed"), Some(job_uuid));
+        assert_eq!(finished("reused"), None);
+        assert_eq!(finished("stopped"), None);
+    }
+
+    #[test]
+    fn test_push_updates_job_status() {
+        let mut submit = running_submit();
+        let job_uuid = Uuid::new_v4();
+        submit.push(update(&format!(r#"{{"event":"job_scheduled","job":"{job_uuid}","package":"foo 1.0"}}"#)));
+        assert_eq!(submit.jobs().get(&job_uuid.to_string()).unwrap().status, "scheduling");
+        assert!(!submit.is_job_finished(&job_uuid));
+
+        submit.push(update(&format!(r#"{{"event":"job_started","job":"{job_uuid}","package":"foo 1.0","endpoint":"ep"}}"#)));
+        submit.push(update(&format!(r#"{{"event":"phase_changed","job":"{job_uuid}","package":"foo 1.0","phase":"build"}}"#)));
+
+        let job = submit.jobs().get(&job_uuid.to_string()).unwrap();
+        assert_eq!(job.package, "foo 1.0");
+        assert_eq!(job.status, "running");
+        assert_eq!(job.endpoint.as_deref(), Some("ep"));
+        assert_eq!(job.phase.as_deref(), Some("build"));
+        assert!(!submit.is_job_finished(&job_uuid));
+
+        submit.push(update(&format!(r#"{{"event":"job_retrying","job":"{job_uuid}","package":"foo 1.0"}}"#)));
+        assert_eq!(submit.jobs().get(&job_uuid.to_string()).unwrap().status, "retrying");
+
+        submit.push(update(&format!(r#"{{"event":"job_finished","job":"{job_uuid}","package":"foo 1.0","status":"built"}}"#)));
+        assert_eq!(submit.jobs().get(&job_uuid.to_string()).unwrap().status, "built");
+        assert!(submit.is_job_finished(&job_uuid));
+        assert!(!submit.is_job_finished(&Uuid::new_v4()));
+        assert_eq!(submit.jobs().len(), 1);
+    }
+
+    #[test]
+    fn test_push_submit_finished() {
+        let mut submit = running_submit();
+        assert!(submit.result().is_none());
+
+        submit.push(SubmitEvent::SubmitFinished(SubmitResult::Failed(String::from("error"))));
+        assert_eq!(submit.result(), &Some(SubmitResult::Failed(String::from("error"))));
+        assert!(submit.take_cancel().is_none());
+
+        // all jobs are finished with the submit, even if no event was received for them
+        assert!(submit.is_job_finished(&Uuid::new_v4()));
+    }
+
+    #[test]
+    fn test_push_job_finished() {
+        let mut submit = running_submit();
+        let job_uuid = Uuid::new_v4();
+        submit.push(update(&format!(r#"{{"event":"job_finished","job":"{job_uuid}","package":"foo 1.0","status":"failed"}}"#)));
+        submit.push(SubmitEvent::JobFinished(FinishedJob {
+            job_uuid,
+            package_name: String::from("foo"),
+            package_version: String::from("1.0"),
+            endpoint: String::from("ep"),
+            container_hash: String::from("hash"),
+            state: JobResult::Errored,
+            log: String::new(),
+        }));
+
+        // the status of the job comes from the job events only
+        assert_eq!(submit.jobs().get(&job_uuid.to_string()).unwrap().status, "failed");
+        assert!(submit.result().is_none());
+
+        let (events, _) = submit.subscribe();
+        match &events[1] {
+            SubmitEvent::JobFinished(job) => {
+                assert_eq!(job.job_uuid, job_uuid);
+                assert_eq!(job.package_name, "foo");
+                assert_eq!(job.package_version, "1.0");
+                assert_eq!(job.endpoint, "ep");
+                assert_eq!(job.container_hash, "hash");
+                assert_eq!(job.state, JobResult::Errored);
+                assert!(job.log.is_empty());
+            },
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_take_cancel() {
+        let mut submit = running_submit();
+        assert!(submit.take_cancel().is_some());
+        assert!(submit.take_cancel().is_none());
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut submit = running_submit();
+        submit.push(update(r#"{"event":"job_scheduled","job":"a","package":"foo 1.0"}"#));
+
+        let (events, mut receiver) = submit.subscribe();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], SubmitEvent::JobUpdate(update) if update.job_uuid == "a"));
+        assert!(receiver.try_recv().is_err());
+
+        submit.push(SubmitEvent::SubmitFinished(SubmitResult::Succeeded));
+        let event = receiver.try_recv().unwrap();
+        assert!(event.is_submit_finished());
+        assert!(receiver.try_recv().is_err());
+
+        // later subscribers get all events
+        let (events, _) = submit.subscribe();
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].is_submit_finished());
+        assert!(events[1].is_submit_finished());
+    }
+
+    #[test]
+    fn test_submit_result_from_exit() {
+        use std::os::unix::process::ExitStatusExt;
+        let exit = |code: i32| Ok(std::process::ExitStatus::from_raw(code << 8));
+
+        assert_eq!(SubmitResult::from_exit(false, exit(0), ""), SubmitResult::Succeeded);
+        assert_eq!(SubmitResult::from_exit(false, exit(1), "error\n"), SubmitResult::Failed(String::from("error")));
+        assert_eq!(SubmitResult::from_exit(true, exit(1), "killed"), SubmitResult::Cancelled);
+
+        let error = std::io::Error::new(std::io::ErrorKind::Other, "wait failed");
+        assert_eq!(SubmitResult::from_exit(false, Err(error), ""), SubmitResult::Failed(String::from("wait failed")));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let submits = RunningSubmits::default();
+        let submit_uuid = Uuid::new_v4();
+        submits.insert(submit_uuid, running_submit());
+
+        let (uuid, address) = submits.lookup(&submit_uuid.to_string(), |submit| submit.status_address()).unwrap();
+        assert_eq!(uuid, submit_uuid);
+        assert_eq!(address, "127.0.0.1:1".parse().unwrap());
+
+        assert!(matches!(submits.lookup("foo", |_| ()), Err(SubmitLookupError::InvalidUuid(_))));
+
+        let other = Uuid::new_v4();
+        match submits.lookup(&other.to_string(), |_| ()) {
+            Err(e @ SubmitLookupError::NotFound(_)) => {
+                assert_eq!(e.to_string(), format!("Submit {other} was not started by this server"));
+            },
+            _ => panic!("Unknown submit was found"),
+        }
+        assert!(submits.with_submit(&other, |_| ()).is_none());
+    }
+}
@@ -0,0 +1,556 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'serve' subcommand
+//!
+//! The experimental gRPC control API of butido (see `proto/butido.proto`).
+//! Submits are started by running `butido build` as a child process, so they use exactly the same
+//! code paths as submits from the CLI. The job events are read from the JSON lines output of the
+//! build and pushed to the clients, the logs of running jobs come from the status endpoint of the
+//! build. Finished jobs and artifacts are read from the database.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use futures::Stream;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tracing::{debug, error, info, trace, warn};
+use uuid::Uuid;
+
+use crate::commands::running_submits::FinishedJob;
+use crate::commands::running_submits::JobStatus;
+use crate::commands::running_submits::JobUpdate;
+use crate::commands::running_submits::RunningSubmit;
+use crate::commands::running_submits::RunningSubmits;
+use crate::commands::running_submits::SubmitEvent;
+use crate::commands::running_submits::SubmitLookupError;
+use crate::commands::running_submits::SubmitResult;
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::log::JobResult;
+use crate::log::ParsedLog;
+use crate::schema;
+
+pub mod proto {
+    tonic::include_proto!("butido");
+}
+
+use proto::butido_server::Butido;
+use proto::butido_server::ButidoServer;
+
+/// The interval in which the status endpoint of a build is polled for new log lines of a job
+const JOB_LOGS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Implementation of the "serve" subcommand
+pub async fn serve(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let addr = matches.get_one::<String>("listen")
+        .unwrap() // safe by clap
+        .parse::<SocketAddr>()
+        .context("Parsing listen address")?;
+
+    let service = Service {
+        database_uri: db_connection_config.database_uri(),
+        database_env: vec![
+            ("BUTIDO_DATABASE_HOST", db_connection_config.database_host().to_string()),
+            ("BUTIDO_DATABASE_PORT", db_connection_config.database_port().to_string()),
+            ("BUTIDO_DATABASE_USER", db_connection_config.database_user().to_string()),
+            ("BUTIDO_DATABASE_PASSWORD", db_connection_config.database_password().to_string()),
+            ("BUTIDO_DATABASE_NAME", db_connection_config.database_name().to_string()),
+        ],
+        staging_directory: config.staging_directory().clone(),
+        executable: std::env::current_exe().context("Finding the butido executable")?,
+        submits: RunningSubmits::default(),
+    };
+
+    info!("Serving the gRPC API on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(ButidoServer::new(service))
+        .serve(addr)
+        .await
+        .map_err(Error::from)
+}
+
+struct Service {
+    database_uri: String,
+
+    /// The database settings, passed to the `butido build` processes
+    database_env: Vec<(&'static str, String)>,
+    staging_directory: PathBuf,
+    executable: PathBuf,
+    submits: RunningSubmits,
+}
+
+impl From<SubmitLookupError> for Status {
+    fn from(e: SubmitLookupError) -> Self {
+        match e {
+            SubmitLookupError::InvalidUuid(_) => Status::invalid_argument(e.to_string()),
+            SubmitLookupError::NotFound(_) => Status::not_found(e.to_string()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Butido for Service {
+    type SubmitEventsStream = Pin<Box<dyn Stream<Item = std::result::Result<proto::SubmitEvent, Status>> + Send>>;
+    type JobLogsStream = Pin<Box<dyn Stream<Item = std::result::Result<proto::LogLine, Status>> + Send>>;
+
+    async fn submit(&self, request: Request<proto::SubmitRequest>) -> std::result::Result<Response<proto::SubmitResponse>, Status> {
+        let request = request.into_inner();
+        let submit_uuid = Uuid::new_v4();
+        debug!("Starting submit {} for {:?}", submit_uuid, request);
+
+        // The status endpoint of the build is only used by this server, so any free local port is fine
+        let status_address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| Status::internal(format!("Finding a free port for the status endpoint failed: {e}")))?;
+
+        let mut command = tokio::process::Command::new(&self.executable);
+        command.arg("--hide-bars")
+            .arg("build")
+            .arg("--output")
+            .arg("json-lines")
+            .arg("--status-address")
+            .arg(status_address.to_string())
+            .arg("--staging-dir")
+            .arg(self.staging_directory.join(submit_uuid.hyphenated().to_string()))
+            .arg(&request.package_name);
+
+        if let Some(version) = request.package_version.as_ref() {
+            command.arg(version);
+        }
+        if let Some(image) = request.image.as_ref() {
+            command.arg("--image").arg(image);
+        }
+        if let Some(flavor) = request.flavor.as_ref() {
+            command.arg("--flavor").arg(flavor);
+        }
+        for feature in request.features.iter() {
+            command.arg("--with").arg(feature);
+        }
+        for (key, value) in request.env.iter() {
+            command.arg("--env").arg(format!("{key}={value}"));
+        }
+        if request.no_verification {
+            command.arg("--no-verify");
+        }
+        if request.no_lint {
+            command.arg("--no-lint");
+        }
+
+        let mut child = command
+            .envs(self.database_env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Status::internal(format!("Starting butido build failed: {e}")))?;
+
+        let (cancel_sender, mut cancel_receiver) = tokio::sync::oneshot::channel();
+        self.submits.insert(submit_uuid, RunningSubmit::new(status_address, cancel_sender));
+
+        let submits = self.submits.clone();
+        let database_uri = self.database_uri.clone();
+        tokio::spawn(async move {
+            let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines(); // safe, stdout is piped
+            let mut stderr = child.stderr.take().unwrap(); // safe, stderr is piped
+            let stderr = tokio::spawn(async move {
+                let mut output = String::new();
+                let _ = stderr.read_to_string(&mut output).await;
+                output
+            });
+
+            let mut cancelled = false;
+            let mut cancel_handled = false;
+            loop {
+                tokio::select! {
+                    line = stdout.next_line() => match line {
+                        Ok(Some(line)) => handle_build_output(&submits, &submit_uuid, &database_uri, &line).await,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("Reading the output of submit {} failed: {}", submit_uuid, e);
+                            break
+                        },
+                    },
+                    cancel = &mut cancel_receiver, if !cancel_handled => {
+                        cancel_handled = true;
+                        if cancel.is_ok() {
+                            info!("Cancelling submit {}", submit_uuid);
+                            cancelled = true;
+                            if let Err(e) = child.start_kill() {
+                                error!("Killing the build process of submit {} failed: {}", submit_uuid, e);
+                            }
+                        }
+                    },
+                }
+            }
+
+            let status = child.wait().await;
+            let stderr = stderr.await.unwrap_or_default();
+            let result = SubmitResult::from_exit(cancelled, status, &stderr);
+
+            if let SubmitResult::Failed(e) = &result {
+                error!("Submit {} failed: {}", submit_uuid, e);
+            }
+
+            submits.with_submit(&submit_uuid, |submit| submit.push(SubmitEvent::SubmitFinished(result)));
+        });
+
+        Ok(Response::new(proto::SubmitResponse {
+            submit_uuid: submit_uuid.to_string(),
+        }))
+    }
+
+    async fn submit_events(&self, request: Request<proto::SubmitEventsRequest>) -> std::result::Result<Response<Self::SubmitEventsStream>, Status> {
+        let (submit_uuid, (events, mut receiver)) = self.submits.lookup(&request.into_inner().submit_uuid, |submit| submit.subscribe())?;
+
+        let (sender, stream_receiver) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(async move {
+            for event in events {
+                let finished = event.is_submit_finished();
+                if sender.send(Ok(event.into())).await.is_err() || finished {
+                    return // client is gone or submit was finished already
+                }
+            }
+
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Client of submit {} missed {} events", submit_uuid, n);
+                        continue
+                    },
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+                };
+
+                let finished = event.is_submit_finished();
+                if sender.send(Ok(event.into())).await.is_err() || finished {
+                    return
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(stream_receiver))))
+    }
+
+    async fn submit_status(&self, request: Request<proto::SubmitStatusRequest>) -> std::result::Result<Response<proto::SubmitStatusResponse>, Status> {
+        let (_, response) = self.submits.lookup(&request.into_inner().submit_uuid, |submit| {
+            let (state, error) = match submit.result().as_ref() {
+                None => (proto::SubmitState::Running, None),
+                Some(SubmitResult::Succeeded) => (proto::SubmitState::Succeeded, None),
+                Some(SubmitResult::Failed(e)) => (proto::SubmitState::Failed, Some(e.clone())),
+                Some(SubmitResult::Cancelled) => (proto::SubmitState::Cancelled, None),
+            };
+
+            proto::SubmitStatusResponse {
+                state: state as i32,
+                error,
+                jobs: submit.jobs().values().cloned().map(proto::JobStatus::from).collect(),
+            }
+        })?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn job_logs(&self, request: Request<proto::JobLogsRequest>) -> std::result::Result<Response<Self::JobLogsStream>, Status> {
+        let request = request.into_inner();
+        let job_uuid = Uuid::parse_str(&request.job_uuid)
+            .map_err(|e| Status::invalid_argument(format!("Not a job UUID: {e}")))?;
+        let (submit_uuid, status_address) = self.submits.lookup(&request.submit_uuid, |submit| submit.status_address())?;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        let submits = self.submits.clone();
+        let database_uri = self.database_uri.clone();
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            let url = format!("http://{status_address}/logs/{job_uuid}");
+            let mut streamed = 0;
+
+            loop {
+                let job_finished = submits.with_submit(&submit_uuid, |submit| submit.is_job_finished(&job_uuid))
+                    .unwrap_or(true);
+
+                if job_finished {
+                    let log = {
+                        let database_uri = database_uri.clone();
+                        tokio::task::spawn_blocking(move || load_job_log(&database_uri, &job_uuid))
+                            .await
+                            .map_err(Error::from)
+                            .and_then(|r| r)
+                    };
+
+                    let lines = match log {
+                        Ok(Some(log)) => log.lines().skip(streamed).map(String::from).collect::<Vec<_>>(),
+                        Ok(None) => Vec::new(), // not recorded, e.g. because an artifact was reused
+                        Err(e) => {
+                            let _ = sender.send(Err(Status::internal(format!("{e:#}")))).await;
+                            return
+                        },
+                    };
+                    for line in lines {
+                        if sender.send(Ok(proto::LogLine { line })).await.is_err() {
+                            return
+                        }
+                    }
+                    return
+                }
+
+                let lines = client.get(format!("{url}?from={streamed}"))
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status);
+                let lines = match lines {
+                    Ok(response) => {
+                        // lines that were dropped by the status endpoint are skipped
+                        if let Ok(first) = crate::commands::logs::first_line(&response) {
+                            streamed = streamed.max(first);
+                        }
+                        response.text().await.unwrap_or_default()
+                    },
+                    Err(e) => {
+                        trace!("Log of job {} not available yet: {}", job_uuid, e);
+                        String::new()
+                    },
+                };
+
+                for line in lines.lines() {
+                    streamed += 1;
+                    if sender.send(Ok(proto::LogLine { line: line.to_string() })).await.is_err() {
+                        return // client is gone
+                    }
+                }
+
+                tokio::time::sleep(JOB_LOGS_POLL_INTERVAL).await;
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn cancel(&self, request: Request<proto::CancelRequest>) -> std::result::Result<Response<proto::CancelResponse>, Status> {
+        let (submit_uuid, cancel) = self.submits.lookup(&request.into_inner().submit_uuid, RunningSubmit::take_cancel)?;
+
+        match cancel {
+            Some(cancel) => {
+                let _ = cancel.send(());
+                Ok(Response::new(proto::CancelResponse {}))
+            },
+            None => Err(Status::failed_precondition(format!("Submit {submit_uuid} is not running"))),
+        }
+    }
+
+    async fn artifacts(&self, request: Request<proto::ArtifactsRequest>) -> std::result::Result<Response<proto::ArtifactsResponse>, Status> {
+        let request = request.into_inner();
+        let database_uri = self.database_uri.clone();
+
+        tokio::task::spawn_blocking(move || load_artifacts(&database_uri, &request))
+            .await
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .map(|artifacts| Response::new(proto::ArtifactsResponse { artifacts }))
+            .map_err(|e| Status::internal(format!("{e:#}")))
+    }
+}
+
+impl From<SubmitEvent> for proto::SubmitEvent {
+    fn from(event: SubmitEvent) -> Self {
+        let event = match event {
+            SubmitEvent::JobUpdate(update) => proto::submit_event::Event::JobUpdate(update.into()),
+            SubmitEvent::JobFinished(job) => proto::submit_event::Event::JobFinished(job.into()),
+            SubmitEvent::SubmitFinished(result) => proto::submit_event::Event::SubmitFinished(proto::SubmitFinished {
+                success: matches!(result, SubmitResult::Succeeded),
+                cancelled: matches!(result, SubmitResult::Cancelled),
+                error: match result {
+                    SubmitResult::Failed(e) => Some(e),
+                    _ => None,
+                },
+            }),
+        };
+
+        proto::SubmitEvent { event: Some(event) }
+    }
+}
+
+impl From<JobUpdate> for proto::JobUpdate {
+    fn from(update: JobUpdate) -> Self {
+        proto::JobUpdate {
+            job_uuid: update.job_uuid,
+            package: update.package,
+            event: update.event,
+            time: update.time,
+            endpoint: update.endpoint,
+            phase: update.phase,
+            artifact: update.artifact,
+            status: update.status,
+        }
+    }
+}
+
+impl From<JobStatus> for proto::JobStatus {
+    fn from(job: JobStatus) -> Self {
+        proto::JobStatus {
+            job_uuid: job.job_uuid,
+            package: job.package,
+            status: job.status,
+            endpoint: job.endpoint,
+            phase: job.phase,
+        }
+    }
+}
+
+impl From<FinishedJob> for proto::JobFinished {
+    fn from(job: FinishedJob) -> Self {
+        let state = match job.state {
+            JobResult::Success => proto::JobState::Success,
+            JobResult::Errored => proto::JobState::Errored,
+            JobResult::Unknown => proto::JobState::Unknown,
+        };
+
+        proto::JobFinished {
+            job_uuid: job.job_uuid.to_string(),
+            package_name: job.package_name,
+            package_version: job.package_version,
+            endpoint: job.endpoint,
+            container_hash: job.container_hash,
+            state: state as i32,
+            log: job.log,
+        }
+    }
+}
+
+/// Handle a line of the output of `butido build --output json-lines`
+///
+/// Job events are recorded and sent to the subscribers of the submit. When a job finished, the
+/// job is loaded from the database and sent as well.
+async fn handle_build_output(submits: &RunningSubmits, submit_uuid: &Uuid, database_uri: &str, line: &str) {
+    let update = match JobUpdate::parse(line) {
+        Some(update) => update,
+        None => return, // not a job event
+    };
+    trace!("Event of submit {}: {:?}", submit_uuid, update);
+
+    let recorded_job = update.recorded_job();
+    submits.with_submit(submit_uuid, |submit| submit.push(SubmitEvent::JobUpdate(update)));
+
+    if let Some(job_uuid) = recorded_job {
+        let database_uri = database_uri.to_string();
+        let job = tokio::task::spawn_blocking(move || load_finished_job(&database_uri, &job_uuid))
+            .await
+            .map_err(Error::from)
+            .and_then(|r| r);
+
+        match job {
+            Ok(Some(job)) => {
+                submits.with_submit(submit_uuid, |submit| submit.push(SubmitEvent::JobFinished(job)));
+            },
+            Ok(None) => debug!("Finished job {} is not in the database", job_uuid),
+            Err(e) => error!("Loading job {} failed: {:#}", job_uuid, e),
+        }
+    }
+}
+
+/// Load a finished job from the database, if it was recorded
+fn load_finished_job(database_uri: &str, job_uuid: &Uuid) -> Result<Option<FinishedJob>> {
+    let conn = PgConnection::establish(database_uri)?;
+
+    let job = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::endpoints::table)
+        .filter(schema::jobs::uuid.eq(job_uuid))
+        .select((schema::jobs::all_columns, schema::packages::all_columns, schema::endpoints::all_columns))
+        .first::<(dbmodels::Job, dbmodels::Package, dbmodels::Endpoint)>(&conn)
+        .optional()?;
+
+    job.map(|(job, package, endpoint)| {
+            Ok(FinishedJob {
+                job_uuid: job.uuid,
+                package_name: package.name,
+                package_version: package.version,
+                endpoint: endpoint.name,
+                container_hash: job.container_hash,
+                state: ParsedLog::from_str(&job.log_text)?.is_successfull(),
+                log: job.log_text,
+            })
+        })
+        .transpose()
+}
+
+/// Load the log of a job from the database, if the job was recorded
+fn load_job_log(database_uri: &str, job_uuid: &Uuid) -> Result<Option<String>> {
+    let conn = PgConnection::establish(database_uri)?;
+
+    schema::jobs::table
+        .filter(schema::jobs::uuid.eq(job_uuid))
+        .select(schema::jobs::log_text)
+        .first::<String>(&conn)
+        .optional()
+        .map_err(Error::from)
+}
+
+/// Load the artifacts of a package from the database
+fn load_artifacts(database_uri: &str, request: &proto::ArtifactsRequest) -> Result<Vec<proto::Artifact>> {
+    let conn = PgConnection::establish(database_uri)?;
+
+    let mut query = schema::artifacts::table
+        .inner_join({
+            schema::jobs::table
+                .inner_join(schema::packages::table)
+                .inner_join(schema::submits::table)
+        })
+        .filter(schema::packages::name.eq(&request.package_name))
+        .into_boxed();
+
+    if let Some(version) = request.package_version.as_ref() {
+        query = query.filter(schema::packages::version.eq(version));
+    }
+
+    query
+        .select((schema::artifacts::all_columns, schema::packages::all_columns, schema::jobs::uuid, schema::submits::uuid))
+        .load::<(dbmodels::Artifact, dbmodels::Package, Uuid, Uuid)>(&conn)?
+        .into_iter()
+        .map(|(artifact, package, job_uuid, submit_uuid)| {
+            let release_stores = schema::releases::table
+                .inner_join(schema::release_stores::table)
+                .filter(schema::releases::artifact_id.eq(artifact.id))
+                .select(schema::release_stores::store_name)
+                .distinct()
+                .load::<String>(&conn)?;
+
+            Ok(proto::Artifact {
+                path: artifact.path,
+                package_name: package.name,
+                package_version: package.version,
+                job_uuid: job_uuid.to_string(),
+                submit_uuid: submit_uuid.to_string(),
+                release_stores,
+            })
+        })
+        .collect()
+}
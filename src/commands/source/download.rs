@@ -9,6 +9,7 @@
 //
 
 use std::convert::TryFrom;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -103,7 +104,15 @@ impl ProgressWrapper {
     }
 }
 
-async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrapper>>, timeout: Option<u64>) -> Result<()> {
+/// Settings that are the same for all downloads
+#[derive(Clone, Copy)]
+struct DownloadSettings<'a> {
+    timeout: Option<u64>,
+    ssh_key: Option<&'a Path>,
+    forge_tokens: forge::ForgeTokens<'a>,
+}
+
+async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrapper>>, settings: DownloadSettings<'_>) -> Result<()> {
     trace!("Creating: {:?}", source);
     let file = source.create().await.with_context(|| {
         anyhow!(
@@ -112,11 +121,16 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
         )
     })?;
 
+    if matches!(source.url().scheme(), "sftp" | "scp") {
+        drop(file);
+        return perform_ssh_download(source, progress, settings.timeout, settings.ssh_key).await
+    }
+
     let mut file = tokio::io::BufWriter::new(file);
     let client_builder = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::limited(10));
 
-    let client_builder = if let Some(to) = timeout {
+    let client_builder = if let Some(to) = settings.timeout {
         client_builder.timeout(std::time::Duration::from_secs(to))
     } else {
         client_builder
@@ -124,9 +138,21 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
 
     let client = client_builder.build().context("Building HTTP client failed")?;
 
-    let request = client.get(source.url().as_ref())
-        .build()
-        .with_context(|| anyhow!("Building request for {} failed", source.url().as_ref()))?;
+    let (url, auth_header, lock) = if forge::is_forge_url(source.url()) {
+        let (url, auth_header) = resolve_forge_url(&client, source, settings.forge_tokens).await?;
+        let lock = forge::ForgeLock { url: url.clone(), hash: source.hash().value().to_string() };
+        (url, auth_header, Some(lock))
+    } else {
+        (source.url().clone(), None, None)
+    };
+
+    let mut request = client.get(url.as_ref());
+    if let Some((name, value)) = auth_header {
+        request = request.header(name, value);
+    }
+
+    let request = request.build()
+        .with_context(|| anyhow!("Building request for {} failed", url))?;
 
     let response = match client.execute(request).await {
         Ok(resp) => resp,
@@ -155,12 +181,111 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
         )?;
     }
 
-    file.flush()
-        .await
-        .map_err(Error::from)
-        .map(|_| ())
+    file.flush().await?;
+
+    if let Some(lock) = lock {
+        lock.store(&source.lock_path()).await?;
+    }
+
+    Ok(())
 }
 
+/// Resolve the download URL of a forge release asset
+///
+/// The URL recorded in the lock data is used if it was recorded for the expected hash of the
+/// source, so the forge API is only queried if the source changed.
+async fn resolve_forge_url(client: &reqwest::Client, source: &SourceEntry, tokens: forge::ForgeTokens<'_>) -> Result<(url::Url, Option<(&'static str, String)>)> {
+    let expected_hash = source.hash().value().to_string();
+    match forge::ForgeLock::load(&source.lock_path()).await? {
+        Some(lock) if lock.hash == expected_hash => {
+            trace!("Using locked URL for {}: {}", source.url(), lock.url);
+            let auth_header = forge::download_auth_header(source.url(), &lock.url, tokens);
+            Ok((lock.url, auth_header))
+        },
+        _ => {
+            let resolved = forge::resolve(client, source.url(), tokens)
+                .await
+                .with_context(|| anyhow!("Resolving release asset '{}'", source.url()))?;
+            debug!("Resolved {} to {}", source.url(), resolved.url);
+            Ok((resolved.url, resolved.auth_header))
+        },
+    }
+}
+
+/// The "[user@]host:path" argument of `scp` for a `sftp://` or `scp://` URL
+///
+/// The user and path of the URL are percent-encoded, `scp` needs them decoded. A path starting
+/// with "/~/" is relative to the home directory of the user. The path is escaped, because `scp`
+/// passes it to the shell on the remote host (or, with the SFTP protocol, expands globs in it).
+fn scp_remote(url: &url::Url) -> Result<String> {
+    let decode = |s| {
+        percent_encoding::percent_decode_str(s)
+            .decode_utf8()
+            .with_context(|| anyhow!("Decoding '{}' in URL: {}", s, url))
+    };
+
+    let host = url.host_str().ok_or_else(|| anyhow!("No host in URL: {}", url))?;
+    let path = decode(url.path())?;
+    let path = path.strip_prefix("/~/").unwrap_or(&path);
+    let path = path.chars()
+        .fold(String::with_capacity(path.len()), |mut escaped, c| {
+            if !(c.is_ascii_alphanumeric() || "/._-+,:@%=".contains(c)) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        });
+
+    if url.username().is_empty() {
+        Ok(format!("{host}:{path}"))
+    } else {
+        Ok(format!("{}@{}:{}", decode(url.username())?, host, path))
+    }
+}
+
+/// Download a source from a `sftp://` or `scp://` URL using the `scp` program
+///
+/// Only key based authentication is supported, `scp` is never allowed to ask for a password.
+async fn perform_ssh_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrapper>>, timeout: Option<u64>, ssh_key: Option<&Path>) -> Result<()> {
+    let url = source.url();
+    let remote = scp_remote(url)?;
+
+    let mut cmd = tokio::process::Command::new("scp");
+    cmd.arg("-q")
+        .arg("-B") // batch mode, do not ask for passwords
+        .arg("-o")
+        .arg("StrictHostKeyChecking=yes");
+
+    if let Some(port) = url.port() {
+        cmd.arg("-P").arg(port.to_string());
+    }
+
+    if let Some(key) = ssh_key {
+        cmd.arg("-i").arg(key);
+    }
+
+    if let Some(to) = timeout {
+        cmd.arg("-o").arg(format!("ConnectTimeout={to}"));
+    }
+
+    cmd.arg(&remote).arg(source.path());
+    trace!("Executing: {:?}", cmd);
+
+    let output = cmd.output()
+        .await
+        .with_context(|| anyhow!("Executing scp for '{}'", url))?;
+
+    if !output.status.success() {
+        return Err(anyhow!("scp failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()))
+            .with_context(|| anyhow!("Downloading '{}'", url))
+    }
+
+    let len = tokio::fs::metadata(source.path()).await?.len();
+    let mut progress = progress.lock().await;
+    progress.inc_download_bytes(len).await;
+    progress.add_bytes(len as usize).await;
+    Ok(())
+}
 
 // Implementation of the 'source download' subcommand
 pub async fn download(
@@ -174,6 +299,14 @@ pub async fn download(
         .map(|s| s.parse::<u64>())
         .transpose()
         .context("Parsing timeout argument to integer")?;
+    let settings = DownloadSettings {
+        timeout,
+        ssh_key: config.source_ssh_key().as_deref(),
+        forge_tokens: forge::ForgeTokens {
+            github: config.github_token().as_deref(),
+            gitlab: config.gitlab_token().as_deref(),
+        },
+    };
     let cache = PathBuf::from(config.source_cache_root());
     let sc = SourceCache::new(cache);
     let pname = matches
@@ -247,10 +380,15 @@ pub async fn download(
                             source.remove_file().await?;
                         }
 
+                        // Resolve forge release assets again if a download is forced
+                        if force && source.lock_path().exists() {
+                            tokio::fs::remove_file(source.lock_path()).await?;
+                        }
+
                         progressbar.lock().await.inc_download_count().await;
                         {
                             let permit = download_sema.acquire_owned().await?;
-                            perform_download(&source, progressbar.clone(), timeout).await?;
+                            perform_download(&source, progressbar.clone(), settings).await?;
                             drop(permit);
                         }
                         progressbar.lock().await.finish_one_download().await;
@@ -275,3 +413,28 @@ pub async fn download(
     r
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scp_remote() {
+        let remote = |s| scp_remote(&url::Url::parse(s).unwrap()).unwrap();
+
+        assert_eq!(remote("sftp://example.com/srv/foo-1.0.tar.gz"), "example.com:/srv/foo-1.0.tar.gz");
+        assert_eq!(remote("scp://build@example.com:2222/srv/foo.tar.gz"), "build@example.com:/srv/foo.tar.gz");
+        assert_eq!(remote("sftp://example.com/srv/my%20sources/foo%2Bbar.tar.gz"), r"example.com:/srv/my\ sources/foo+bar.tar.gz");
+        assert_eq!(remote("sftp://build%2Bci@example.com/foo.tar.gz"), "build+ci@example.com:/foo.tar.gz");
+
+        // relative to the home directory
+        assert_eq!(remote("sftp://example.com/~/foo.tar.gz"), "example.com:foo.tar.gz");
+        assert_eq!(remote("sftp://build@example.com/~/src/foo.tar.gz"), "build@example.com:src/foo.tar.gz");
+        assert_eq!(remote("sftp://example.com/srv/~/foo.tar.gz"), r"example.com:/srv/\~/foo.tar.gz");
+
+        // the path is not interpreted by the remote shell
+        assert_eq!(remote("sftp://example.com/srv/foo$(id).tar.gz"), r"example.com:/srv/foo\$\(id\).tar.gz");
+        assert_eq!(remote("sftp://example.com/srv/a;b%27c%22*.tar.gz"), r#"example.com:/srv/a\;b\'c\"\*.tar.gz"#);
+
+        assert!(scp_remote(&url::Url::parse("sftp://example.com/%FF").unwrap()).is_err());
+    }
+}
@@ -13,6 +13,7 @@
 use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Context;
 use anyhow::Error;
@@ -72,6 +73,13 @@ pub async fn verify(
         .map(|s| crate::commands::util::mk_package_name_regex(s.as_ref()))
         .transpose()?;
 
+    let only_unverified = matches.get_flag("missing_only");
+    let jobs = matches.get_one::<String>("jobs")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .context("Parsing jobs argument to integer")?
+        .unwrap_or_else(default_verification_jobs);
+
     let packages = repo
         .packages()
         .filter(|p| {
@@ -88,47 +96,82 @@ pub async fn verify(
         })
         .inspect(|p| trace!("Found for verification: {} {}", p.name(), p.version()));
 
-    verify_impl(packages, &sc, &progressbars).await
+    verify_impl(packages, &sc, &progressbars, only_unverified, jobs).await
+}
+
+/// The default number of sources that are verified concurrently
+pub(in crate::commands) fn default_verification_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(std::num::NonZeroUsize::get)
+        .unwrap_or(1)
 }
 
 pub(in crate::commands) async fn verify_impl<'a, I>(
     packages: I,
     sc: &SourceCache,
     progressbars: &ProgressBars,
+    only_unverified: bool,
+    jobs: usize,
 ) -> Result<()>
 where
     I: Iterator<Item = &'a Package> + 'a,
 {
+    let mut state = sc.load_verification_state()?;
     let sources = packages
         .flat_map(|p| sc.sources_for(p).into_iter())
+        .filter(|source| {
+            let skip = only_unverified && state.is_verified(source);
+            if skip {
+                trace!("Already verified, skipping: {}", source.path().display());
+            }
+            !skip
+        })
         .collect::<Vec<_>>();
 
     let bar = progressbars.bar()?;
     bar.set_message("Verifying sources");
     bar.set_length(sources.len() as u64);
 
+    // The verification tasks are spawned so that the hashing can run on multiple threads
+    let sema = Arc::new(tokio::sync::Semaphore::new(jobs.max(1)));
     let results = sources.into_iter()
-        .map(|src| (bar.clone(), src))
-        .map(|(bar, source)| async move {
-            trace!("Verifying: {}", source.path().display());
-            if source.path().exists() {
-                trace!("Exists: {}", source.path().display());
-                source.verify_hash().await.with_context(|| {
-                    anyhow!("Hash verification failed for: {}", source.path().display())
-                })?;
-
-                trace!("Success verifying: {}", source.path().display());
-                bar.inc(1);
-                Ok(())
-            } else {
-                trace!("Failed verifying: {}", source.path().display());
+        .map(|source| {
+            let bar = bar.clone();
+            let sema = sema.clone();
+            tokio::spawn(async move {
+                let _permit = sema.acquire_owned().await;
+                trace!("Verifying: {}", source.path().display());
+                let result = if source.path().exists() {
+                    trace!("Exists: {}", source.path().display());
+                    source.verify_hash().await.with_context(|| {
+                        anyhow!("Hash verification failed for: {}", source.path().display())
+                    })
+                } else {
+                    trace!("Failed verifying: {}", source.path().display());
+                    Err(anyhow!("Source missing: {}", source.path().display()))
+                };
+
+                trace!("Finished verifying: {}", source.path().display());
                 bar.inc(1);
-                Err(anyhow!("Source missing: {}", source.path().display()))
-            }
+                (source, result)
+            })
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Vec<Result<_>>>()
-        .await;
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .map(|r| r.map_err(Error::from))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .map(|(source, result)| {
+            if result.is_ok() {
+                state.mark_verified(&source)?;
+            }
+            Ok(result)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    sc.store_verification_state(&state)?;
 
     info!("Verification processes finished");
 
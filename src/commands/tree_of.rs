@@ -10,25 +10,42 @@
 
 //! Implementation of the 'tree-of' subcommand
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
 
+use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
 use resiter::AndThen;
+use resiter::Map;
+use tracing::warn;
 
+use crate::config::Configuration;
 use crate::package::Dag;
+use crate::package::DependencyCycle;
+use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
 use crate::package::condition::ConditionData;
 use crate::repository::Repository;
 use crate::util::EnvironmentVariableName;
 use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+use crate::util::version::compare_versions;
 
 /// Implementation of the "tree_of" subcommand
 pub async fn tree_of(
     matches: &ArgMatches,
     repo: Repository,
+    repo_path: &Path,
+    config: &Configuration,
+    progressbars: ProgressBars,
 ) -> Result<()> {
     let pname = matches
         .get_one::<String>("package_name")
@@ -57,20 +74,220 @@ pub async fn tree_of(
         env: &additional_env,
     };
 
-    repo.packages()
+    let packages = repo.packages()
         .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
         .filter(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
                 .unwrap_or(true)
-        })
+        });
+
+    if matches.get_flag("check_cycles") {
+        return check_cycles(packages, &repo, &condition_data)
+    }
+
+    if let Some(refname) = matches.get_one::<String>("diff") {
+        let old_repo = load_repo_at(refname, repo_path, config, &progressbars)?;
+        return diff(packages, refname, &old_repo, &repo, &condition_data)
+    }
+
+    let trees = packages
         .map(|package| Dag::for_root_package(package.clone(), &repo, None, &condition_data))
-        .and_then_ok(|tree| {
-            let stdout = std::io::stdout();
-            let mut outlock = stdout.lock();
+        .and_then_ok(|tree| tree.check_deprecated(false).map(|_| tree));
+
+    let stdout = std::io::stdout();
+    let mut outlock = stdout.lock();
+
+    match matches.get_one::<String>("format").map(String::as_str) {
+        None | Some("tree") => trees
+            .and_then_ok(|tree| ptree::write_tree(&tree.display(), &mut outlock).map_err(Error::from))
+            .collect::<Result<()>>(),
+
+        Some("dot") => trees
+            .and_then_ok(|tree| write!(outlock, "{}", tree.to_dot()).map_err(Error::from))
+            .collect::<Result<()>>(),
+
+        Some("json") => {
+            let trees = trees
+                .map_ok(|tree| tree.to_json())
+                .collect::<Result<Vec<_>>>()?;
+            writeln!(outlock, "{}", serde_json::to_string_pretty(&trees)?).map_err(Error::from)
+        },
+
+        Some(other) => Err(anyhow!("Unknown format: {}", other)),
+    }
+}
+
+/// Build the trees of `packages` and print every dependency cycle that is found
+fn check_cycles<'a>(
+    packages: impl Iterator<Item = &'a Package>,
+    repo: &Repository,
+    condition_data: &ConditionData<'_>,
+) -> Result<()> {
+    let cycles = packages
+        .filter_map(|package| match Dag::for_root_package(package.clone(), repo, None, condition_data) {
+            Ok(_) => None,
+            Err(e) => match e.downcast_ref::<DependencyCycle>() {
+                Some(cycle) => Some(cycle.clone()),
+                None => {
+                    warn!("Could not check the tree of {} {} for cycles: {:#}", package.name(), package.version(), e);
+                    None
+                },
+            },
+        })
+        .collect::<BTreeSet<_>>();
+
+    let stdout = std::io::stdout();
+    let mut outlock = stdout.lock();
+    for cycle in cycles.iter() {
+        writeln!(outlock, "{cycle}")?;
+    }
+
+    if cycles.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} dependency cycles", cycles.len()))
+    }
+}
+
+/// Load the repository as it is in the git ref `refname`
+///
+/// The files of the ref are written to a temporary directory, which is removed afterwards.
+fn load_repo_at(refname: &str, repo_path: &Path, config: &Configuration, progressbars: &ProgressBars) -> Result<Repository> {
+    let git_repo = git2::Repository::open(repo_path)?;
+    let dir = std::env::temp_dir().join(format!("butido-tree-of-{}", std::process::id()));
+
+    let repo = crate::util::git::export_tree(&git_repo, refname, &dir).and_then(|_| {
+        let bar = progressbars.bar()?;
+        let roots = Repository::roots(&dir, config.repositories())?;
+        let repo = Repository::load_overlays(&dir, &roots, config.package_variables(), None, &bar)
+            .with_context(|| anyhow!("Loading the repository at '{}'", refname))?
+            .with_preferred_providers(config.preferred_providers().clone());
+        bar.finish_with_message("Repository loading finished");
+        Ok(repo)
+    });
+
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        warn!("Failed to remove {}: {}", dir.display(), e);
+    }
+    repo
+}
+
+/// Print the differences between the trees of `packages` in `old_repo` and `repo`
+fn diff<'a>(
+    packages: impl Iterator<Item = &'a Package>,
+    refname: &str,
+    old_repo: &Repository,
+    repo: &Repository,
+    condition_data: &ConditionData<'_>,
+) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut outlock = stdout.lock();
+
+    for package in packages {
+        let tree = Dag::for_root_package(package.clone(), repo, None, condition_data)?;
+
+        // A package that does not exist in the old repository has an empty tree there
+        let old_tree = old_repo
+            .find(package.name(), package.version())
+            .into_iter()
+            .next()
+            .map(|old| Dag::for_root_package(old.clone(), old_repo, None, condition_data))
+            .transpose()
+            .with_context(|| anyhow!("Building the tree of {} {} at '{}'", package.name(), package.version(), refname))?;
 
-            ptree::write_tree(&tree.display(), &mut outlock).map_err(Error::from)
+        let old_packages = old_tree.as_ref().map(|t| t.all_packages()).unwrap_or_default();
+        let changes = diff_trees(&old_packages, &tree.all_packages());
+
+        if changes.is_empty() {
+            writeln!(outlock, "{} {}: no changes compared to {}", package.name(), package.version(), refname)?;
+        } else {
+            writeln!(outlock, "{} {} (compared to {}):", package.name(), package.version(), refname)?;
+            for change in changes {
+                writeln!(outlock, "    {change}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A difference between the packages in two trees
+#[derive(Debug, Eq, PartialEq)]
+enum TreeChange {
+    Added(PackageName, PackageVersion),
+    Removed(PackageName, PackageVersion),
+    VersionChanged(PackageName, Vec<PackageVersion>, Vec<PackageVersion>),
+}
+
+impl std::fmt::Display for TreeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // not imported at module level, it would make the `resiter` methods ambiguous
+        use itertools::Itertools;
+
+        match self {
+            TreeChange::Added(name, version) => write!(f, "+ {name} {version}"),
+            TreeChange::Removed(name, version) => write!(f, "- {name} {version}"),
+            TreeChange::VersionChanged(name, old, new) => {
+                write!(f, "~ {} {} -> {}", name, old.iter().join(", "), new.iter().join(", "))
+            },
+        }
+    }
+}
+
+/// Compare the packages of two trees by name
+fn diff_trees(old: &[&Package], new: &[&Package]) -> Vec<TreeChange> {
+    let versions_by_name = |packages: &[&Package]| {
+        let mut map: BTreeMap<PackageName, Vec<PackageVersion>> = BTreeMap::new();
+        for p in packages {
+            map.entry(p.name().clone()).or_default().push(p.version().clone());
+        }
+        map.values_mut().for_each(|versions| versions.sort_by(|a, b| compare_versions(a, b)));
+        map
+    };
+
+    let old = versions_by_name(old);
+    let new = versions_by_name(new);
+
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .flat_map(|name| match (old.get(name), new.get(name)) {
+            (Some(old), None) => old.iter().map(|v| TreeChange::Removed(name.clone(), v.clone())).collect(),
+            (None, Some(new)) => new.iter().map(|v| TreeChange::Added(name.clone(), v.clone())).collect(),
+            (Some(old), Some(new)) if old != new => vec![TreeChange::VersionChanged(name.clone(), old.clone(), new.clone())],
+            _ => vec![],
         })
-        .collect::<Result<()>>()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_diff_trees() {
+        let a = package("a", "1", "https://rust-lang.org", "123");
+        let b1 = package("b", "1", "https://rust-lang.org", "124");
+        let b2 = package("b", "2", "https://rust-lang.org", "125");
+        let c = package("c", "1", "https://rust-lang.org", "126");
+        let d = package("d", "1", "https://rust-lang.org", "127");
+
+        let changes = diff_trees(&[&a, &b1, &c], &[&a, &b2, &d]);
+        assert_eq!(changes, [
+            TreeChange::VersionChanged(pname("b"), vec![pversion("1")], vec![pversion("2")]),
+            TreeChange::Removed(pname("c"), pversion("1")),
+            TreeChange::Added(pname("d"), pversion("1")),
+        ]);
+        assert_eq!(changes[0].to_string(), "~ b 1 -> 2");
+        assert_eq!(changes[2].to_string(), "+ d 1");
+
+        assert!(diff_trees(&[&a, &b1], &[&b1, &a]).is_empty());
+    }
 }
@@ -47,6 +47,9 @@ where
     I: Iterator<Item = &'a Package> + 'a,
 {
     let shebang = Shebang::from(config.shebang().clone());
+
+    // Meta packages do not have a script
+    let iter = iter.filter(|pkg| !pkg.is_meta());
     bar.set_length({
         let (lower, upper) = iter.size_hint();
         upper.unwrap_or(lower) as u64
@@ -62,6 +65,7 @@ where
 
                 let cmd = tokio::process::Command::new(linter);
                 let script = ScriptBuilder::new(&shebang)
+                    .apply_patches_before(config.apply_patches_before().as_ref())
                     .build(pkg, config.available_phases(), *config.strict_script_interpolation())?;
 
                 let (status, stdout, stderr) = script.lint(cmd).await?;
@@ -173,6 +177,28 @@ pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
         .collect()
 }
 
+/// The format in which a subcommand prints its data
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+impl OutputFormat {
+    /// Get the output format from the "output" argument, or from the "csv" flag if it is not set
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        match matches.get_one::<String>("output").map(String::as_str) {
+            Some("table") => Ok(OutputFormat::Table),
+            Some("csv") => Ok(OutputFormat::Csv),
+            Some("json") => Ok(OutputFormat::Json),
+            Some(other) => Err(anyhow!("Unknown output format: {}", other)),
+            None if matches.get_flag("csv") => Ok(OutputFormat::Csv),
+            None => Ok(OutputFormat::Table),
+        }
+    }
+}
+
 /// Display the passed data as nice ascii table,
 /// or, if stdout is a pipe, print it nicely parseable
 ///
@@ -182,11 +208,41 @@ pub fn display_data<D: Display>(
     data: Vec<Vec<D>>,
     csv: bool,
 ) -> Result<()> {
+    let format = if csv { OutputFormat::Csv } else { OutputFormat::Table };
+    display_data_as(headers, data, format)
+}
+
+/// Display the passed data in the passed format
+///
+/// In the JSON format, the data is printed as an array of objects, with the headers (in snake
+/// case) as keys. Other than for the other formats, an empty array is printed if there is no data.
+pub fn display_data_as<D: Display>(
+    headers: Vec<ascii_table::Column>,
+    data: Vec<Vec<D>>,
+    format: OutputFormat,
+) -> Result<()> {
+    if format == OutputFormat::Json {
+        let keys = headers.iter().map(|c| json_key(c.header())).collect::<Vec<_>>();
+        let records = data.into_iter()
+            .map(|record| {
+                keys.iter()
+                    .cloned()
+                    .zip(record.into_iter().map(|e| serde_json::Value::String(e.to_string())))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect::<Vec<_>>();
+
+        let out = std::io::stdout();
+        let mut lock = out.lock();
+        serde_json::to_writer_pretty(&mut lock, &records)?;
+        return writeln!(lock).map_err(Error::from)
+    }
+
     if data.is_empty() {
         return Ok(())
     }
 
-    if csv {
+    if format == OutputFormat::Csv {
         use csv::WriterBuilder;
         let mut wtr = WriterBuilder::new().from_writer(vec![]);
         for record in data.into_iter() {
@@ -225,6 +281,28 @@ pub fn display_data<D: Display>(
     }
 }
 
+/// Get the limit and the offset of a listing from the "limit", "offset" and "all" arguments
+///
+/// The limit is `None` if all rows should be listed.
+pub fn get_limit_and_offset(matches: &ArgMatches) -> Result<(Option<i64>, Option<i64>)> {
+    let limit = if matches.get_flag("all") {
+        None
+    } else {
+        matches.get_one::<String>("limit").map(|s| s.parse::<i64>()).transpose()?
+    };
+    let offset = matches.get_one::<String>("offset").map(|s| s.parse::<i64>()).transpose()?;
+    Ok((limit, offset))
+}
+
+/// Convert a table header to a key for the JSON output, e.g. "For Package Version" to "for_package_version"
+fn json_key(header: &str) -> String {
+    header
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_lowercase())
+        .join("_")
+}
+
 pub fn get_date_filter(name: &str, matches: &ArgMatches) -> Result<Option<chrono::DateTime::<chrono::Local>>> {
     matches.get_one::<String>(name)
         .map(|s| {
@@ -258,3 +336,57 @@ pub fn get_date_filter(name: &str, matches: &ArgMatches) -> Result<Option<chrono
         .transpose()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::get_date_filter;
+    use super::get_limit_and_offset;
+    use super::json_key;
+
+    fn db_jobs_matches(args: &[&str]) -> clap::Result<clap::ArgMatches> {
+        let args = ["butido", "db", "jobs"].iter().chain(args.iter());
+        crate::cli::cli()
+            .try_get_matches_from(args)
+            .map(|matches| matches.subcommand_matches("db").unwrap().subcommand_matches("jobs").unwrap().clone())
+    }
+
+    #[test]
+    fn test_json_key() {
+        assert_eq!(json_key("UUID"), "uuid");
+        assert_eq!(json_key("For Package Version"), "for_package_version");
+        assert_eq!(json_key("Ok?"), "ok");
+        assert_eq!(json_key("SHA256 A"), "sha256_a");
+    }
+
+    #[test]
+    fn test_date_filter_since_until() {
+        let matches = db_jobs_matches(&["--since", "2h", "--until", "2020-01-01"]).unwrap();
+
+        let since = get_date_filter("newer_than", &matches).unwrap().unwrap();
+        let expected = chrono::Local::now() - chrono::Duration::hours(2);
+        assert!((since - expected).num_seconds().abs() < 60, "{since} is not about {expected}");
+
+        let until = get_date_filter("older_than", &matches).unwrap().unwrap();
+        let expected = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap();
+        assert!((until - expected.with_timezone(&chrono::Local)).num_seconds().abs() < 5, "{until} is not {expected}");
+
+        // The long names are still available
+        let matches = db_jobs_matches(&["--newer-than", "2020-01-01 12:30:00"]).unwrap();
+        let since = get_date_filter("newer_than", &matches).unwrap().unwrap();
+        let expected = chrono::DateTime::parse_from_rfc3339("2020-01-01T12:30:00Z").unwrap();
+        assert!((since - expected.with_timezone(&chrono::Local)).num_seconds().abs() < 5, "{since} is not {expected}");
+        assert!(get_date_filter("older_than", &matches).unwrap().is_none());
+
+        assert!(db_jobs_matches(&["--since", "yesterday"]).is_err());
+    }
+
+    #[test]
+    fn test_limit_and_offset() {
+        let limit_and_offset = |args: &[&str]| get_limit_and_offset(&db_jobs_matches(args).unwrap()).unwrap();
+        assert_eq!(limit_and_offset(&[]), (Some(100), None));
+        assert_eq!(limit_and_offset(&["--limit", "5", "--offset", "10"]), (Some(5), Some(10)));
+        assert_eq!(limit_and_offset(&["--all", "--offset", "10"]), (None, Some(10)));
+
+        assert!(db_jobs_matches(&["--all", "--limit", "5"]).is_err());
+        assert!(db_jobs_matches(&["--limit", "-1"]).is_err());
+    }
+}
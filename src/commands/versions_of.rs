@@ -10,19 +10,35 @@
 
 //! Implementation of the 'versions_of' subcommand
 
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
-use tracing::trace;
+use tokio_stream::StreamExt;
+use tracing::{debug, trace};
 
+use crate::config::Configuration;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::repository::Repository;
+use crate::util::version::compare_versions;
+
+const NUMBER_OF_MAX_CONCURRENT_UPSTREAM_QUERIES: usize = 10;
 
 /// Implementation of the "versions_of" subcommand
-pub async fn versions_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
+pub async fn versions_of(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
     use filters::filter::Filter;
     use std::io::Write;
 
+    if let Some(("check-upstream", matches)) = matches.subcommand() {
+        return check_upstream(matches, config, repo).await
+    }
+
     let package_filter = {
         let name = matches
             .get_one::<String>("package_name")
@@ -42,3 +58,165 @@ pub async fn versions_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
         .collect::<Result<Vec<_>>>()
         .map(|_| ())
 }
+
+/// Implementation of the "versions_of check-upstream" subcommand
+async fn check_upstream(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let print_all = matches.get_flag("all");
+    let pname = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from);
+    let matching_regexp = matches.get_one::<String>("matching")
+        .map(|s| crate::commands::util::mk_package_name_regex(s.as_ref()))
+        .transpose()?;
+    let timeout = matches.get_one::<String>("timeout")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .context("Parsing timeout argument to integer")?;
+
+    // Only the newest packaged version of each package is compared to upstream
+    let newest_packages = repo.packages()
+        .filter(|p| match (pname.as_ref(), matching_regexp.as_ref()) {
+            (Some(pname), _) => p.name() == pname,
+            (None, Some(regex)) => regex.is_match(p.name()),
+            (None, None) => true,
+        })
+        .fold(BTreeMap::<&PackageName, &Package>::new(), |mut acc, p| {
+            match acc.get(p.name()) {
+                Some(other) if compare_versions(other.version(), p.version()) != Ordering::Less => {},
+                _ => {
+                    acc.insert(p.name(), p);
+                },
+            }
+            acc
+        });
+
+    if newest_packages.is_empty() {
+        return Err(anyhow!("No packages found"))
+    }
+
+    let client = {
+        let builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::limited(10));
+
+        if let Some(to) = timeout {
+            builder.timeout(std::time::Duration::from_secs(to))
+        } else {
+            builder
+        }
+    }
+    .build()
+    .context("Building HTTP client failed")?;
+
+    let mut hb = handlebars::Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+
+    let sema = Arc::new(tokio::sync::Semaphore::new(NUMBER_OF_MAX_CONCURRENT_UPSTREAM_QUERIES));
+    let mut data = newest_packages
+        .into_values()
+        .map(|package| {
+            let client = client.clone();
+            let sema = sema.clone();
+            let hb = &hb;
+            async move {
+                let template = package.upstream_version_url()
+                    .as_ref()
+                    .unwrap_or_else(|| config.upstream_version_url());
+                let url = hb.render_template(template, &serde_json::json!({
+                        "name": package.name(),
+                        "version": package.version(),
+                    }))
+                    .with_context(|| anyhow!("Rendering upstream version URL for {} {}", package.name(), package.version()))?;
+
+                let _permit = sema.acquire_owned().await?;
+                trace!("Querying upstream version of {} from {}", package.name(), url);
+                let body = client.get(&url)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .with_context(|| anyhow!("Querying '{}'", url))?
+                    .text()
+                    .await
+                    .with_context(|| anyhow!("Reading response from '{}'", url))?;
+
+                let upstream = parse_upstream_version(&body);
+                debug!("Upstream version of {}: {:?}", package.name(), upstream);
+                Ok((package, upstream)) as Result<_>
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Result<Vec<_>>>()
+        .await?
+        .into_iter()
+        .filter_map(|(package, upstream): (&Package, Option<String>)| {
+            let status = match upstream.as_ref() {
+                None => "unknown",
+                Some(upstream) => match compare_versions(package.version(), upstream) {
+                    Ordering::Less => "outdated",
+                    Ordering::Equal => "up to date",
+                    Ordering::Greater => "newer than upstream",
+                },
+            };
+
+            if print_all || status == "outdated" {
+                Some(vec![
+                    package.name().to_string(),
+                    package.version().to_string(),
+                    upstream.unwrap_or_else(|| String::from("-")),
+                    String::from(status),
+                ])
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    data.sort();
+
+    if data.is_empty() {
+        println!("All packages are up to date");
+        return Ok(())
+    }
+
+    let hdrs = crate::commands::util::mk_header(vec!["Name", "Version", "Upstream", "Status"]);
+    crate::commands::util::display_data(hdrs, data, csv)
+}
+
+/// Extract the version from the response of an upstream version query
+///
+/// For JSON responses, the version is taken from the first project in the result list
+/// (release-monitoring.org) or from a toplevel "version" field. Any other response is
+/// interpreted as plain text and the first line is used.
+fn parse_upstream_version(body: &str) -> Option<String> {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => json.pointer("/items/0/version")
+            .or_else(|| json.get("version"))
+            .and_then(serde_json::Value::as_str)
+            .map(String::from),
+
+        Err(_) => body.lines()
+            .next()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_upstream_version_anitya() {
+        let body = r#"{"items": [{"name": "curl", "version": "8.4.0"}], "total_items": 1}"#;
+        assert_eq!(parse_upstream_version(body), Some(String::from("8.4.0")));
+    }
+
+    #[test]
+    fn test_parse_upstream_version_plain() {
+        assert_eq!(parse_upstream_version("1.2.3\n"), Some(String::from("1.2.3")));
+        assert_eq!(parse_upstream_version(""), None);
+    }
+}
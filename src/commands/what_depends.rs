@@ -10,19 +10,22 @@
 
 //! Implementation of the 'what_depends' subcommand
 
+use std::collections::HashSet;
 use std::io::Write;
+use std::str::FromStr;
 
 use anyhow::Result;
 use clap::ArgMatches;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use tracing::trace;
-use resiter::Filter;
-use resiter::Map;
 
 use crate::commands::util::getbool;
 use crate::config::*;
+use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::ParseDependency;
 use crate::repository::Repository;
 use crate::ui::*;
 
@@ -32,8 +35,6 @@ pub async fn what_depends(
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
-    use filters::failable::filter::FailableFilter;
-
     let print_runtime_deps = getbool(
         matches,
         "dependency_type",
@@ -45,20 +46,20 @@ pub async fn what_depends(
         crate::cli::IDENT_DEPENDENCY_TYPE_BUILD,
     );
 
-    let package_filter = {
-        let name = matches
-            .get_one::<String>("package_name")
-            .map(|s| s.to_owned())
-            .map(PackageName::from)
-            .unwrap();
-
-        crate::util::filters::build_package_filter_by_dependency_name(
-            &name,
-            print_build_deps,
-            print_runtime_deps,
-        )
+    let name = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from)
+        .unwrap();
+
+    let depth = if matches.get_flag("transitive") {
+        matches.get_one::<String>("depth").map(|s| usize::from_str(s)).transpose()?
+    } else {
+        Some(1)
     };
 
+    let dependents = find_dependents(&repo, &name, None, print_build_deps, print_runtime_deps, depth)?;
+
     let hb = crate::ui::handlebars_for_package_printing(config.package_print_format())?;
     let stdout = std::io::stdout();
     let mut outlock = stdout.lock();
@@ -80,23 +81,146 @@ pub async fn what_depends(
         script_highlighting: false,
     };
 
-    let mut i = 0;
-    let iter = repo
-        .packages()
-        .map(|package| package_filter.filter(package).map(|b| (b, package)))
-        .filter_ok(|(b, _)| *b)
-        .map_ok(|tpl| tpl.1)
-        .inspect(|pkg| trace!("Found package: {:?}", pkg))
-        .map_ok(|p| { // poor mans enumerate_ok()
-            i += 1;
-            p.prepare_print(config, &flags, &hb, i)
-        });
+    let iter = dependents
+        .into_iter()
+        .enumerate()
+        .map(|(i, p)| p.prepare_print(config, &flags, &hb, i + 1));
 
     tokio_stream::iter(iter)
-        .map(|pp| pp.and_then(|p| p.into_displayable()))
+        .map(|p| p.into_displayable())
         .try_for_each(|p| {
             let r = writeln!(&mut outlock, "{p}").map_err(anyhow::Error::from);
             futures::future::ready(r)
         })
         .await
 }
+
+/// Find the packages that depend on the package `name`
+///
+/// If a `version` is passed, only packages with a dependency on `name` that matches the version
+/// are direct dependents. The packages that depend on the dependents are found as well, up to
+/// `depth` levels of dependencies (unlimited if `None`). The direct dependents come first.
+pub(super) fn find_dependents<'a>(
+    repo: &'a Repository,
+    name: &PackageName,
+    version: Option<&PackageVersion>,
+    check_build_deps: bool,
+    check_runtime_deps: bool,
+    depth: Option<usize>,
+) -> Result<Vec<&'a Package>> {
+    use filters::failable::filter::FailableFilter;
+
+    let mut seen_names = HashSet::from([name.clone()]);
+    let mut seen_packages = HashSet::new();
+    let mut names = vec![name.clone()];
+    let mut dependents = Vec::new();
+    let mut level = 0;
+
+    while !names.is_empty() && depth.map(|depth| level < depth).unwrap_or(true) {
+        level += 1;
+        let mut next_names = Vec::new();
+
+        for name in names.iter() {
+            let filter = crate::util::filters::build_package_filter_by_dependency_name(
+                name,
+                check_build_deps,
+                check_runtime_deps,
+            );
+
+            for package in repo.packages() {
+                if !filter.filter(package)? {
+                    continue
+                }
+
+                if let Some(version) = version.filter(|_| level == 1) {
+                    if !depends_on_version(package, name, version, check_build_deps, check_runtime_deps)? {
+                        continue
+                    }
+                }
+
+                if !seen_packages.insert((package.name(), package.version())) {
+                    continue
+                }
+
+                trace!("Found package depending on {}: {:?}", name, package);
+                dependents.push(package);
+                if seen_names.insert(package.name().clone()) {
+                    next_names.push(package.name().clone());
+                }
+            }
+        }
+
+        names = next_names;
+    }
+
+    Ok(dependents)
+}
+
+/// Check whether a dependency of `package` on `name` matches `version`
+fn depends_on_version(
+    package: &Package,
+    name: &PackageName,
+    version: &PackageVersion,
+    check_build_deps: bool,
+    check_runtime_deps: bool,
+) -> Result<bool> {
+    let build_deps = package.dependencies()
+        .build()
+        .iter()
+        .filter(|_| check_build_deps)
+        .map(|d| d.parse_as_name_and_version());
+
+    let runtime_deps = package.dependencies()
+        .runtime()
+        .iter()
+        .filter(|_| check_runtime_deps)
+        .map(|d| d.parse_as_name_and_version());
+
+    build_deps
+        .chain(runtime_deps)
+        .map(|dependency| dependency.map(|(n, constraint)| n == *name && constraint.matches(version)))
+        .collect::<Result<Vec<bool>>>()
+        .map(|matches| matches.into_iter().any(|b| b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_find_dependents() {
+        // c -> b -> a, d -> a
+        let mut btree = BTreeMap::new();
+        for (name, dependency) in [("a", None), ("b", Some("a =1")), ("c", Some("b =1")), ("d", Some("a =1"))] {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            if let Some(dependency) = dependency {
+                pack.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from(dependency))));
+            }
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+        let repo = Repository::from(btree);
+
+        let names = |depth| {
+            find_dependents(&repo, &pname("a"), None, true, true, depth)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(Some(1)), ["b", "d"]);
+        assert_eq!(names(Some(2)), ["b", "d", "c"]);
+        assert_eq!(names(None), ["b", "d", "c"]);
+        assert!(find_dependents(&repo, &pname("a"), None, true, false, None).unwrap().is_empty());
+        assert!(find_dependents(&repo, &pname("a"), Some(&pversion("2")), true, true, None).unwrap().is_empty());
+        assert_eq!(find_dependents(&repo, &pname("a"), Some(&pversion("1")), true, true, None).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,107 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::package::PackageName;
+
+/// The configuration for `butido audit`, which queries the OSV database for known vulnerabilities
+///
+/// OSV identifies packages by ecosystem (e.g. "Debian", "PyPI") and name, so the packages of the
+/// repository are mapped to OSV packages. Packages without an ecosystem are not checked.
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct AuditConfig {
+    /// The URL of the OSV query API
+    #[getset(get = "pub")]
+    #[serde(default = "default_osv_url")]
+    osv_url: String,
+
+    /// The ecosystem of the packages without an entry in `packages`
+    #[serde(default)]
+    ecosystem: Option<String>,
+
+    /// The OSV packages of the packages of the repository, by package name
+    #[serde(default)]
+    packages: HashMap<PackageName, OsvPackage>,
+}
+
+/// The OSV package of a package of the repository
+#[derive(Clone, Debug, Deserialize)]
+pub struct OsvPackage {
+    /// The ecosystem, defaults to the `ecosystem` of the audit configuration
+    #[serde(default)]
+    ecosystem: Option<String>,
+
+    /// The name in the ecosystem, defaults to the name of the package
+    #[serde(default)]
+    name: Option<String>,
+
+    /// Do not check this package
+    #[serde(default)]
+    skip: bool,
+}
+
+fn default_osv_url() -> String {
+    String::from("https://api.osv.dev/v1/query")
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            osv_url: default_osv_url(),
+            ecosystem: None,
+            packages: HashMap::new(),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// The ecosystem and name of a package in the OSV database, if it is checked
+    pub fn osv_package(&self, name: &PackageName) -> Option<(String, String)> {
+        match self.packages.get(name) {
+            Some(package) if package.skip => None,
+            Some(package) => package.ecosystem
+                .as_ref()
+                .or(self.ecosystem.as_ref())
+                .map(|ecosystem| (ecosystem.clone(), package.name.clone().unwrap_or_else(|| name.to_string()))),
+            None => self.ecosystem.as_ref().map(|ecosystem| (ecosystem.clone(), name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osv_package() {
+        let config: AuditConfig = toml::from_str(indoc::indoc!(r#"
+            ecosystem = "Debian:12"
+
+            [packages]
+            openssl = { name = "openssl3" }
+            requests = { ecosystem = "PyPI", name = "requests" }
+            internal = { skip = true }
+        "#)).unwrap();
+
+        let osv_package = |name: &str| config.osv_package(&PackageName::from(name.to_string()));
+        assert_eq!(osv_package("curl"), Some((String::from("Debian:12"), String::from("curl"))));
+        assert_eq!(osv_package("openssl"), Some((String::from("Debian:12"), String::from("openssl3"))));
+        assert_eq!(osv_package("requests"), Some((String::from("PyPI"), String::from("requests"))));
+        assert_eq!(osv_package("internal"), None);
+
+        let config = AuditConfig::default();
+        assert_eq!(config.osv_package(&PackageName::from(String::from("curl"))), None);
+        assert_eq!(config.osv_url(), "https://api.osv.dev/v1/query");
+    }
+}
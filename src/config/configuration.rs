@@ -10,12 +10,66 @@
 
 use std::ops::Deref;
 
+use anyhow::Result;
+
 use crate::config::NotValidatedConfiguration;
 
 /// A valid configuration (validated via NotValidatedConfiguration::validate())
 #[derive(Debug)]
 pub struct Configuration {
     pub(in crate::config) inner: NotValidatedConfiguration,
+
+    /// The SHA-256 hash of the effective configuration, see `Configuration::compute_fingerprint()`
+    pub(in crate::config) fingerprint: Option<String>,
+}
+
+impl Configuration {
+    /// Compute the fingerprint of the effective (merged) configuration
+    ///
+    /// The fingerprint is the SHA-256 hash of the configuration as JSON, with sorted keys, so it
+    /// does not depend on the order of the settings or on which file they come from.
+    pub fn compute_fingerprint(config: &::config::Config) -> Result<String> {
+        use sha2::Digest;
+
+        let mut value = config.clone().try_into::<serde_json::Value>()?;
+
+        // The profiles are already merged into the configuration, if one was selected
+        if let Some(map) = value.as_object_mut() {
+            map.remove(crate::config::PROFILES_KEY);
+        }
+
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(canonical_json(&value).as_bytes());
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: String) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+}
+
+/// Serialize a JSON value with the keys of all objects sorted
+fn canonical_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Object(map) => {
+            let fields = map.iter()
+                .collect::<std::collections::BTreeMap<_, _>>()
+                .into_iter()
+                .map(|(key, value)| format!("{}:{}", serde_json::Value::String(key.clone()), canonical_json(value)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(","))
+        },
+        serde_json::Value::Array(values) => {
+            let values = values.iter().map(canonical_json).collect::<Vec<_>>();
+            format!("[{}]", values.join(","))
+        },
+        other => other.to_string(),
+    }
 }
 
 impl Deref for Configuration {
@@ -25,3 +79,17 @@ impl Deref for Configuration {
         &self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_json() {
+        let value = serde_json::json!({
+            "b": [1, {"d": "x", "c": null}],
+            "a": true,
+        });
+        assert_eq!(canonical_json(&value), r#"{"a":true,"b":[1,{"c":null,"d":"x"}]}"#);
+    }
+}
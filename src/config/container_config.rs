@@ -25,6 +25,11 @@ pub struct ContainerConfig {
     #[getset(get = "pub")]
     allowed_env: Vec<EnvironmentVariableName>,
 
+    /// What to do if a variable is passed to a package that is not in its `allowed_env` list
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    undeclared_package_env: UndeclaredEnvAction,
+
     /// Pass the current git author to the container
     /// This can be used to the the "packager" name in a package, for example
     #[getset(get = "pub")]
@@ -34,3 +39,15 @@ pub struct ContainerConfig {
     #[getset(get = "pub")]
     git_commit_hash: Option<EnvironmentVariableName>,
 }
+
+/// What to do if an environment variable is passed to a package that does not declare it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UndeclaredEnvAction {
+    /// Fail the build
+    #[default]
+    Error,
+
+    /// Only print a warning
+    Warn,
+}
@@ -9,13 +9,19 @@
 //
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
 
 use crate::config::Endpoint;
 use crate::config::EndpointName;
+use crate::package::Package;
 use crate::util::docker::ContainerImage;
+use crate::util::docker::ImageName;
 
 /// Configuration of the docker daemon interfacing functionality
 #[derive(Debug, Getters, CopyGetters, Deserialize)]
@@ -50,6 +56,62 @@ pub struct DockerConfig {
     #[getset(get = "pub")]
     images: Vec<ContainerImage>,
 
+    /// Images that are used if no image is passed when building a package
+    ///
+    /// The first entry matching the package (and being allowed for it) is used.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    default_images: Vec<DefaultImage>,
+
     #[getset(get = "pub")]
     endpoints: HashMap<EndpointName, Endpoint>,
 }
+
+/// A mapping of packages to the image that should be used for them by default
+#[derive(Debug, Getters, Deserialize)]
+pub struct DefaultImage {
+    /// The image to use
+    #[getset(get = "pub")]
+    image: ImageName,
+
+    /// A glob for the package name ("*" and "?" are supported)
+    #[getset(get = "pub")]
+    package: Option<String>,
+
+    /// A prefix of the path of the package definition inside the repository
+    #[getset(get = "pub")]
+    path: Option<PathBuf>,
+}
+
+impl DockerConfig {
+    /// Interpolate environment variables in the URIs of the endpoints
+    pub(in crate::config) fn interpolate_env(&mut self) -> Result<()> {
+        self.endpoints
+            .iter_mut()
+            .try_for_each(|(name, endpoint)| endpoint.interpolate_env(name))
+    }
+}
+
+impl DefaultImage {
+    /// Check whether this default applies to a package
+    ///
+    /// If both `package` and `path` are set, both have to match.
+    pub fn matches(&self, package: &Package) -> Result<bool> {
+        let name_matches = self.package
+            .as_ref()
+            .map(|glob| {
+                crate::util::glob::glob_to_regex(glob)
+                    .map(|re| re.is_match(package.name()))
+                    .with_context(|| anyhow!("Building regex for package glob '{}'", glob))
+            })
+            .transpose()?
+            .unwrap_or(true);
+
+        let path_matches = self.path
+            .as_ref()
+            .map(|prefix| package.path().starts_with(prefix))
+            .unwrap_or(true);
+
+        Ok(name_matches && path_matches)
+    }
+}
@@ -8,9 +8,12 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use anyhow::Result;
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
 
+use crate::config::interpolation::interpolate_env;
+
 #[derive(Debug, Clone, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[serde(transparent)]
 pub struct EndpointName(String);
@@ -46,6 +49,7 @@ pub struct Endpoint {
 
     /// Maximum number of jobs which are allowed on this endpoint
     #[getset(get_copy = "pub")]
+    #[serde(alias = "max_parallel_jobs")]
     maxjobs: usize,
 
     #[getset(get = "pub")]
@@ -56,6 +60,14 @@ pub struct Endpoint {
     timeout: Option<u64>,
 }
 
+impl Endpoint {
+    /// Interpolate environment variables in the URI, see `crate::config::interpolation`
+    pub(in crate::config) fn interpolate_env(&mut self, name: &EndpointName) -> Result<()> {
+        self.uri = interpolate_env(&self.uri, &format!("docker.endpoints.{name}.uri"))?;
+        Ok(())
+    }
+}
+
 /// The type of an endpoint
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub enum EndpointType {
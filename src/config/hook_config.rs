@@ -0,0 +1,53 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// When a hook is run
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, parse_display::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// After a submit finished successfully
+    #[display("submit_success")]
+    SubmitSuccess,
+
+    /// After a submit failed
+    #[display("submit_failure")]
+    SubmitFailure,
+
+    /// For each artifact that a job produced
+    #[display("artifact")]
+    Artifact,
+
+    /// Before a submit is started, a failing hook stops the submit
+    #[display("pre_submit")]
+    PreSubmit,
+
+    /// After a job finished, successfully or not
+    #[display("post_job")]
+    PostJob,
+
+    /// Before artifacts are released, a failing hook stops the release
+    #[display("pre_release")]
+    PreRelease,
+}
+
+/// An external command that is run on an event of a build or release
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct HookConfig {
+    #[getset(get_copy = "pub")]
+    event: HookEvent,
+
+    /// The program and its arguments
+    #[getset(get = "pub")]
+    command: Vec<String>,
+}
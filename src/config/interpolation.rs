@@ -0,0 +1,106 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Interpolation of environment variables in configuration values
+//!
+//! `${VAR}` in a value is replaced with the value of the environment variable `VAR`, `$${` is
+//! replaced with a literal `${`. A `$` that is not followed by `{` is kept as it is.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Replace `${VAR}` in the value of the setting `setting` with the environment variable `VAR`
+pub(in crate::config) fn interpolate_env(value: &str, setting: &str) -> Result<String> {
+    interpolate(value, setting, |name| std::env::var(name).ok())
+}
+
+/// Like `interpolate_env()`, for paths
+///
+/// Paths that are not valid UTF-8 are returned unchanged.
+pub(in crate::config) fn interpolate_env_path(path: &Path, setting: &str) -> Result<PathBuf> {
+    match path.to_str() {
+        Some(s) => interpolate_env(s, setting).map(PathBuf::from),
+        None => Ok(path.to_path_buf()),
+    }
+}
+
+fn interpolate<F>(value: &str, setting: &str, lookup: F) -> Result<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("Missing '}}' after '${{' in setting '{}'", setting))?;
+            let name = &after[..end];
+            if name.is_empty() {
+                return Err(anyhow!("Empty variable name '${{}}' in setting '{}'", setting))
+            }
+
+            let var = lookup(name)
+                .ok_or_else(|| anyhow!("Environment variable '{}' is not set, but used in setting '{}'", name, setting))?;
+            result.push_str(&var);
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some(String::from("/home/butido")),
+            "PASSWORD" => Some(String::from("secret")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(interpolate("${HOME}/staging", "staging", lookup).unwrap(), "/home/butido/staging");
+        assert_eq!(interpolate("pre-${PASSWORD}-${PASSWORD}", "p", lookup).unwrap(), "pre-secret-secret");
+        assert_eq!(interpolate("no variables", "p", lookup).unwrap(), "no variables");
+    }
+
+    #[test]
+    fn test_interpolate_keeps_dollars() {
+        assert_eq!(interpolate("pa$$word$", "p", lookup).unwrap(), "pa$$word$");
+        assert_eq!(interpolate("$${HOME}", "p", lookup).unwrap(), "${HOME}");
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        let e = interpolate("${UNSET}/x", "staging", lookup).unwrap_err();
+        assert_eq!(e.to_string(), "Environment variable 'UNSET' is not set, but used in setting 'staging'");
+
+        assert!(interpolate("${HOME", "p", lookup).is_err());
+        assert!(interpolate("${}", "p", lookup).is_err());
+    }
+}
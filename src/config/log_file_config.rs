@@ -0,0 +1,162 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::config::interpolation::interpolate_env_path;
+
+/// The placeholders that can be used in the path of the log files
+const PLACEHOLDERS: &[&str] = &["submit", "package", "version", "job", "image"];
+
+/// The configuration of the log files that are written with `build --write-log`
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct LogFileConfig {
+    /// The path of the log file of a job, relative to the log directory
+    ///
+    /// The placeholders `{submit}`, `{package}`, `{version}`, `{job}` and `{image}` are replaced
+    /// with the values of the job.
+    #[getset(get = "pub")]
+    #[serde(default = "default_path")]
+    path: String,
+
+    /// How many previous log files are kept if a log file is written again (e.g. for a retried
+    /// job), 0 overwrites the previous log file
+    #[getset(get_copy = "pub")]
+    #[serde(default = "default_keep")]
+    keep: usize,
+
+    /// Whether the log files are compressed with gzip after the job finished
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    compress: bool,
+}
+
+fn default_path() -> String {
+    String::from("{submit}/{package}-{version}-{job}.log")
+}
+
+fn default_keep() -> usize {
+    3
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        LogFileConfig {
+            path: default_path(),
+            keep: default_keep(),
+            compress: false,
+        }
+    }
+}
+
+impl LogFileConfig {
+    /// Check that the path only uses known placeholders and stays within the log directory
+    pub fn validate(&self) -> Result<()> {
+        let mut rest = self.path.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed placeholder in log file path: {}", self.path))?;
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(anyhow!(
+                    "Unknown placeholder '{{{}}}' in log file path, possible values: {}",
+                    name,
+                    PLACEHOLDERS.iter().map(|p| format!("{{{p}}}")).collect::<Vec<_>>().join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+
+        let path = Path::new(&self.path);
+        if self.path.is_empty() || path.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(anyhow!("Log file path must be a relative path within the log directory: {}", self.path));
+        }
+
+        Ok(())
+    }
+
+    /// The path of the log file for a job, relative to the log directory
+    ///
+    /// Slashes in the values are replaced, so that a value cannot add directories to the path.
+    pub fn render(&self, submit: &str, package: &str, version: &str, job: &str, image: &str) -> PathBuf {
+        let sanitize = |value: &str| value.replace('/', "_");
+        let path = self.path
+            .replace("{submit}", &sanitize(submit))
+            .replace("{package}", &sanitize(package))
+            .replace("{version}", &sanitize(version))
+            .replace("{job}", &sanitize(job))
+            .replace("{image}", &sanitize(image));
+
+        PathBuf::from(path)
+    }
+}
+
+/// The archive that the raw logs of all jobs are written to, independent of `build --write-log`
+///
+/// The archive is a backup of the logs in the database, the log of a job is written to
+/// `<directory>/<submit>/<package>-<version>-<job>.log` before the job is recorded in the database.
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct LogArchiveConfig {
+    #[getset(get = "pub")]
+    directory: PathBuf,
+
+    /// Whether the logs are compressed with gzip
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    compress: bool,
+}
+
+impl LogArchiveConfig {
+    pub(in crate::config) fn interpolate_env(&mut self) -> Result<()> {
+        self.directory = interpolate_env_path(&self.directory, "log_archive.directory")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let config: LogFileConfig = toml::from_str("").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.keep(), 3);
+        assert!(!config.compress());
+        assert_eq!(
+            config.render("s", "foo", "1.0", "j", "registry/debian:11"),
+            PathBuf::from("s/foo-1.0-j.log")
+        );
+
+        let config: LogFileConfig = toml::from_str(r#"path = "{image}/{package}.log""#).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.render("s", "foo", "1.0", "j", "registry/debian:11"),
+            PathBuf::from("registry_debian:11/foo.log")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        for path in ["{package}-{foo}.log", "{package.log", "../{job}.log", "/tmp/{job}.log", ""] {
+            let config = LogFileConfig { path: path.to_string(), ..LogFileConfig::default() };
+            assert!(config.validate().is_err(), "{}", path);
+        }
+    }
+}
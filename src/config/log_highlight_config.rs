@@ -0,0 +1,50 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// A rule for highlighting parts of the log lines when a log is shown
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct LogHighlightRule {
+    /// The regex that is matched against each line
+    #[getset(get = "pub")]
+    pattern: String,
+
+    /// The color of the matches, one of the colors of the terminal (e.g. "red", "bright yellow")
+    #[getset(get = "pub")]
+    #[serde(default = "default_color")]
+    color: String,
+
+    /// Whether the whole line is highlighted instead of only the matches
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    whole_line: bool,
+}
+
+fn default_color() -> String {
+    String::from("red")
+}
+
+impl LogHighlightRule {
+    /// Check that the pattern is a valid regex and the color is known
+    pub fn validate(&self) -> Result<()> {
+        regex::Regex::new(&self.pattern)
+            .with_context(|| anyhow!("Invalid pattern in log highlight rule: {}", self.pattern))?;
+        self.color
+            .parse::<colored::Color>()
+            .map_err(|_| anyhow!("Unknown color in log highlight rule: {}", self.color))?;
+        Ok(())
+    }
+}
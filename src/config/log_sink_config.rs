@@ -0,0 +1,125 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use url::Url;
+
+/// The configuration of an external log collector the job logs are forwarded to
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LogSinkConfig {
+    Journald(JournaldConfig),
+    Syslog(SyslogConfig),
+    Loki(LokiConfig),
+}
+
+/// Forward the log lines to the systemd journal, via its native protocol
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct JournaldConfig {
+    /// The socket of the journal
+    #[getset(get = "pub")]
+    #[serde(default = "default_journald_socket")]
+    socket: PathBuf,
+
+    /// The SYSLOG_IDENTIFIER of the entries
+    #[getset(get = "pub")]
+    #[serde(default = "default_identifier")]
+    identifier: String,
+}
+
+fn default_journald_socket() -> PathBuf {
+    PathBuf::from("/run/systemd/journal/socket")
+}
+
+fn default_identifier() -> String {
+    String::from("butido")
+}
+
+/// The syslog facilities that can be used for the forwarded log lines
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    #[default]
+    User,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    /// The numerical code of the facility
+    pub fn code(self) -> u8 {
+        match self {
+            SyslogFacility::User => 1,
+            SyslogFacility::Daemon => 3,
+            SyslogFacility::Local0 => 16,
+            SyslogFacility::Local1 => 17,
+            SyslogFacility::Local2 => 18,
+            SyslogFacility::Local3 => 19,
+            SyslogFacility::Local4 => 20,
+            SyslogFacility::Local5 => 21,
+            SyslogFacility::Local6 => 22,
+            SyslogFacility::Local7 => 23,
+        }
+    }
+}
+
+/// Forward the log lines as RFC 5424 syslog messages
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct SyslogConfig {
+    /// A path of a unix socket (e.g. "/dev/log") or "host:port" of a syslog server (UDP)
+    #[getset(get = "pub")]
+    #[serde(default = "default_syslog_address")]
+    address: String,
+
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    facility: SyslogFacility,
+
+    /// The APP-NAME of the messages
+    #[getset(get = "pub")]
+    #[serde(default = "default_identifier")]
+    identifier: String,
+}
+
+fn default_syslog_address() -> String {
+    String::from("/dev/log")
+}
+
+/// Push the log lines to the push API of Grafana Loki
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct LokiConfig {
+    /// The URL of the push API, e.g. "http://loki.example.com:3100/loki/api/v1/push"
+    #[getset(get = "pub")]
+    url: Url,
+
+    /// Additional labels of the log streams
+    ///
+    /// The streams are always labeled with "submit", "job", "package" and "version".
+    #[getset(get = "pub")]
+    #[serde(default)]
+    labels: HashMap<String, String>,
+
+    /// The tenant (sent as "X-Scope-OrgID" header), if Loki runs in multi-tenant mode
+    #[getset(get = "pub")]
+    #[serde(default)]
+    tenant: Option<String>,
+}
@@ -20,6 +20,9 @@
 //! that is not possible to do with TOML itself.
 //!
 
+mod audit_config;
+pub use audit_config::*;
+
 mod configuration;
 pub use configuration::*;
 
@@ -32,7 +35,51 @@ pub use docker_config::*;
 mod endpoint_config;
 pub use endpoint_config::*;
 
+mod hook_config;
+pub use hook_config::*;
+
+mod interpolation;
+
+mod log_file_config;
+pub use log_file_config::*;
+
+mod log_highlight_config;
+pub use log_highlight_config::*;
+
+mod log_sink_config;
+pub use log_sink_config::*;
+
 mod not_validated;
 pub use not_validated::*;
 
+mod notification_config;
+pub use notification_config::*;
+
+mod oci_config;
+pub use oci_config::*;
+
+mod profile;
+pub use profile::*;
+
+mod release_links_config;
+pub use release_links_config::*;
+
+mod release_metadata_config;
+pub use release_metadata_config::*;
+
+mod release_provenance_config;
+pub use release_provenance_config::*;
+
+mod release_signing_config;
+pub use release_signing_config::*;
+
+mod release_sync_config;
+pub use release_sync_config::*;
+
+mod retry_config;
+pub use retry_config::*;
+
 mod util;
+
+mod webhook_config;
+pub use webhook_config::*;
@@ -11,15 +11,43 @@
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use bytesize::ByteSize;
 use getset::Getters;
+use serde::de::Visitor;
 use serde::Deserialize;
+use serde::Deserializer;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::config::interpolation::interpolate_env;
+use crate::config::interpolation::interpolate_env_path;
 use crate::config::util::*;
+use crate::config::AuditConfig;
 use crate::config::Configuration;
 use crate::config::ContainerConfig;
+use crate::config::DefaultImage;
 use crate::config::DockerConfig;
+use crate::config::Endpoint;
+use crate::config::HookConfig;
+use crate::config::LatestLinksConfig;
+use crate::config::OsvPackage;
+use crate::config::LogArchiveConfig;
+use crate::config::LogFileConfig;
+use crate::config::LogHighlightRule;
+use crate::config::LogSinkConfig;
+use crate::config::NotificationBackendConfig;
+use crate::config::OciReleaseTarget;
+use crate::config::PROFILES_KEY;
+use crate::config::ReleaseSigner;
+use crate::config::ReleaseProvenanceConfig;
+use crate::config::ReleaseSigningConfig;
+use crate::config::ReleaseSyncTarget;
+use crate::config::RepositoryMetadataFormat;
+use crate::config::RetryConfig;
+use crate::config::WebhookConfig;
+use crate::package::PackageName;
 use crate::package::PhaseName;
+use crate::util::docker::ContainerImage;
 
 /// The configuration that is loaded from the filesystem
 #[derive(Debug, Getters, Deserialize)]
@@ -37,6 +65,29 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     log_dir: PathBuf,
 
+    /// The naming, rotation and compression of the log files in `log_dir`
+    #[getset(get = "pub")]
+    #[serde(default)]
+    log_files: LogFileConfig,
+
+    /// The archive the raw log of every job is written to, if set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    log_archive: Option<LogArchiveConfig>,
+
+    /// The maximum size of the log of a job that is stored (in the database and in log files)
+    ///
+    /// Longer logs are truncated in the middle.
+    #[getset(get = "pub")]
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    max_log_size: Option<ByteSize>,
+
+    /// Whether ANSI escape sequences (colors, ...) are removed from the output of the jobs before
+    /// it is stored (in the database and in log files)
+    #[getset(get = "pub")]
+    #[serde(default)]
+    strip_ansi_escapes: bool,
+
     /// Whether the script interpolation feature should be struct, i.e. missing variables result in
     /// a failing interpolation. This should be `true` for most users.
     #[serde(default = "default_strict_script_interpolation")]
@@ -61,11 +112,63 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     package_print_format: String,
 
+    /// Variables that are available (as `vars`) in the templates of the package definitions and
+    /// in the packaging scripts
+    #[serde(default)]
+    #[getset(get = "pub")]
+    package_variables: HashMap<String, String>,
+
+    /// The package repositories, in the order they are loaded
+    ///
+    /// Packages in later repositories override packages with the same name and version in
+    /// earlier ones. Relative paths are relative to the repository butido is run in, if empty,
+    /// only that repository is used.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    repositories: Vec<PathBuf>,
+
+    /// Whether the loaded package definitions are cached, so that unchanged packages do not have
+    /// to be loaded again on the next run
+    #[serde(default = "default_repository_cache")]
+    #[getset(get = "pub")]
+    repository_cache: bool,
+
+    /// Licenses that must not be used in a dependency tree, reported by `butido repo licenses`
+    #[serde(default)]
+    #[getset(get = "pub")]
+    denied_licenses: Vec<String>,
+
+    /// The preferred provider of a virtual package, if several packages provide it
+    #[serde(default)]
+    #[getset(get = "pub")]
+    preferred_providers: HashMap<PackageName, PackageName>,
+
+    /// The URL that is queried to find the latest upstream version of a package
+    ///
+    /// This is handlebars syntax, `name` and `version` of the package are available.
+    /// Can be overridden per package with the `upstream_version_url` setting in the package
+    /// definition.
+    #[serde(default = "default_upstream_version_url")]
+    #[getset(get = "pub")]
+    upstream_version_url: String,
+
     /// How many lines should be printed from the log if a build fails
     #[serde(default = "default_build_error_lines")]
     #[getset(get = "pub")]
     build_error_lines: usize,
 
+    /// Whether the whole failing phase is printed if a build fails, instead of the last
+    /// `build_error_lines` lines of the log
+    #[serde(default)]
+    #[getset(get = "pub")]
+    build_error_whole_phase: bool,
+
+    /// The rules for highlighting log lines in `db job --show-log` and the log of failed jobs
+    /// after a build
+    #[getset(get = "pub")]
+    #[serde(default)]
+    log_highlight: Vec<LogHighlightRule>,
+
     /// The theme used to highlight scripts when printing them to the CLI
     #[getset(get = "pub")]
     script_highlight_theme: Option<String>,
@@ -89,6 +192,45 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     release_stores: Vec<String>,
 
+    /// The release stores (or OCI release targets) that can only be released to by approving a
+    /// release that was proposed by another user
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_approval_required: Vec<String>,
+
+    /// The OCI registries that can be released to, by name
+    ///
+    /// The names are used like the names of the release stores.
+    #[getset(get = "pub")]
+    #[serde(default)]
+    oci_release_targets: HashMap<String, OciReleaseTarget>,
+
+    /// The "latest" links that are maintained in the release stores, none if not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_latest_links: Option<LatestLinksConfig>,
+
+    /// The formats of the package repository metadata that is generated after releasing, by
+    /// release store name
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_metadata: HashMap<String, Vec<RepositoryMetadataFormat>>,
+
+    /// The provenance documents that are written next to the released artifacts, none if not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_provenance: Option<ReleaseProvenanceConfig>,
+
+    /// How the released artifacts are signed, not at all if not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_signing: Option<ReleaseSigningConfig>,
+
+    /// The remote storages the release stores can be synced to, by name
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_sync_targets: HashMap<String, ReleaseSyncTarget>,
+
     /// The directory where intermediate ("staging") artifacts are stored.
     /// This is used as a root directory, a UUID-named directory will be added below this, using
     /// the UUID of the submit
@@ -101,6 +243,22 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     source_cache_root: PathBuf,
 
+    /// The SSH private key used to download sources from `sftp://` and `scp://` URLs
+    ///
+    /// If not set, the default identities of the ssh client (and the ssh-agent) are used.
+    #[getset(get = "pub")]
+    source_ssh_key: Option<PathBuf>,
+
+    /// The API token used to resolve `github://` release asset sources
+    ///
+    /// Not required for public repositories, but unauthenticated requests are heavily rate-limited.
+    #[getset(get = "pub")]
+    github_token: Option<String>,
+
+    /// The API token used to resolve and download `gitlab://` release asset sources
+    #[getset(get = "pub")]
+    gitlab_token: Option<String>,
+
     /// The hostname used to connect to the database
     #[getset(get = "pub")]
     #[serde(rename = "database_host")]
@@ -117,9 +275,23 @@ pub struct NotValidatedConfiguration {
     database_user: String,
 
     /// The password used to connect to the database
-    #[getset(get = "pub")]
-    #[serde(rename = "database_password")]
-    database_password: String,
+    ///
+    /// Exactly one of this, `database_password_file`, `database_password_env` and
+    /// `database_password_command` has to be set, see `database_password()`.
+    #[serde(rename = "database_password", default)]
+    database_password: Option<String>,
+
+    /// A file that contains the password used to connect to the database
+    #[serde(default)]
+    database_password_file: Option<PathBuf>,
+
+    /// An environment variable that contains the password used to connect to the database
+    #[serde(default)]
+    database_password_env: Option<String>,
+
+    /// A command that prints the password used to connect to the database, run with `sh -c`
+    #[serde(default)]
+    database_password_command: Option<String>,
 
     /// The name of the database
     #[getset(get = "pub")]
@@ -138,9 +310,62 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     containers: ContainerConfig,
 
+    /// The maximum number of jobs that run in parallel, on all endpoints together
+    ///
+    /// The number of jobs per endpoint is limited by the configuration of the endpoint.
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_parallel_jobs: Option<usize>,
+
+    /// The configuration for `butido audit`
+    #[getset(get = "pub")]
+    #[serde(default)]
+    audit: AuditConfig,
+
+    /// The configuration for retrying jobs that failed because of the endpoint or the container
+    #[getset(get = "pub")]
+    #[serde(default)]
+    job_retry: RetryConfig,
+
+    /// The webhooks that are notified about submit and job events
+    #[getset(get = "pub")]
+    #[serde(default)]
+    webhooks: Vec<WebhookConfig>,
+
+    /// The builtin notification backends (Matrix, Slack, email)
+    #[getset(get = "pub")]
+    #[serde(default)]
+    notifications: Vec<NotificationBackendConfig>,
+
+    /// The external log collectors the logs of the jobs are forwarded to while they run
+    #[getset(get = "pub")]
+    #[serde(default)]
+    log_sinks: Vec<LogSinkConfig>,
+
+    /// External commands that are run after builds
+    #[getset(get = "pub")]
+    #[serde(default)]
+    hooks: Vec<HookConfig>,
+
     /// The names of the phases which should be compiled into the packaging script
     #[getset(get = "pub")]
     available_phases: Vec<PhaseName>,
+
+    /// The phase before which the patches of a package are applied automatically
+    ///
+    /// If not set, patches are only copied to the container and the package scripts have to apply
+    /// them.
+    #[getset(get = "pub")]
+    apply_patches_before: Option<PhaseName>,
+}
+
+fn deserialize_byte_size<'de, D>(deserializer: D) -> std::result::Result<Option<ByteSize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|size| size.parse::<ByteSize>().map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 impl NotValidatedConfiguration {
@@ -149,7 +374,10 @@ impl NotValidatedConfiguration {
     ///
     /// This function does sanity-checking on the configuration values.
     /// It fails with the appropriate error message if a setting is bogus.
-    pub fn validate(self) -> Result<Configuration> {
+    pub fn validate(mut self) -> Result<Configuration> {
+        self.interpolate_env()
+            .context("Interpolating environment variables in the configuration")?;
+
         let crate_version = semver::Version::parse(env!("CARGO_PKG_VERSION"))
             .context("Parsing version of crate (CARGO_PKG_VERSION) into semver::Version object")?;
 
@@ -176,10 +404,63 @@ impl NotValidatedConfiguration {
             ));
         }
 
+        self.check_database_password_sources()?;
+
         if self.release_stores.is_empty() {
             return Err(anyhow!("You need at least one release store in 'release_stores'"))
         }
 
+        if let Some(name) = self.oci_release_targets.keys().find(|name| self.release_stores.contains(name)) {
+            return Err(anyhow!("'{}' is both a release store and an OCI release target", name))
+        }
+
+        if let Some(name) = self.release_approval_required
+            .iter()
+            .find(|name| !self.release_stores.contains(name) && !self.oci_release_targets.contains_key(*name))
+        {
+            return Err(anyhow!("Release approval required for unknown release store '{}'", name))
+        }
+
+        if let Some(links) = self.release_latest_links.as_ref() {
+            if let Some(name) = links.release_stores().iter().find(|name| !self.release_stores.contains(name)) {
+                return Err(anyhow!("Latest links configured for unknown release store '{}'", name))
+            }
+        }
+
+        if let Some(provenance) = self.release_provenance.as_ref() {
+            if let Some(name) = provenance.release_stores().iter().find(|name| !self.release_stores.contains(name)) {
+                return Err(anyhow!("Release provenance configured for unknown release store '{}'", name))
+            }
+            if provenance.sign() && self.release_signing.is_none() {
+                return Err(anyhow!("Signing the release provenance requires the release_signing setting"))
+            }
+        }
+
+        if let Some(signing) = self.release_signing.as_ref() {
+            if let Some(name) = signing.release_stores().iter().find(|name| !self.release_stores.contains(name)) {
+                return Err(anyhow!("Release signing configured for unknown release store '{}'", name))
+            }
+            if let ReleaseSigner::Command(signer) = signing.signer() {
+                if signer.command().is_empty() {
+                    return Err(anyhow!("The command for signing releases must not be empty"))
+                }
+            }
+        }
+
+        if let Some(name) = self.release_metadata.keys().find(|name| !self.release_stores.contains(name)) {
+            return Err(anyhow!("Repository metadata configured for unknown release store '{}'", name))
+        }
+
+        if self.hooks.iter().any(|hook| hook.command().is_empty()) {
+            return Err(anyhow!("The command of a hook must not be empty"))
+        }
+
+        self.log_files.validate().context("Checking the configuration of the log files")?;
+        self.log_highlight
+            .iter()
+            .try_for_each(LogHighlightRule::validate)
+            .context("Checking the log highlight rules")?;
+
         // Error if source_cache_root is not a directory
         if !self.source_cache_root.is_dir() {
             return Err(anyhow!(
@@ -193,6 +474,34 @@ impl NotValidatedConfiguration {
             return Err(anyhow!("No phases configured"));
         }
 
+        // Error if the phase to apply patches before is not a configured phase
+        if let Some(phase) = self.apply_patches_before.as_ref() {
+            if !self.available_phases.contains(phase) {
+                return Err(anyhow!(
+                    "apply_patches_before = '{}' is not in available_phases",
+                    phase.as_str()
+                ));
+            }
+        }
+
+        // Error if a default image does not restrict the packages it is used for, or is not
+        // a configured image
+        for default_image in self.docker.default_images() {
+            if default_image.package().is_none() && default_image.path().is_none() {
+                return Err(anyhow!(
+                    "Default image {} needs at least one of 'package' or 'path'",
+                    default_image.image()
+                ));
+            }
+
+            if !self.docker.images().iter().any(|img| img.name == *default_image.image()) {
+                return Err(anyhow!(
+                    "Default image {} is not in the configured images",
+                    default_image.image()
+                ));
+            }
+        }
+
         // Error if script highlighting theme is not valid
         if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
             let allowed_theme_present = [
@@ -213,6 +522,367 @@ impl NotValidatedConfiguration {
             }
         }
 
-        Ok(Configuration { inner: self })
+        Ok(Configuration { inner: self, fingerprint: None })
+    }
+}
+
+impl NotValidatedConfiguration {
+    /// Get the password used to connect to the database
+    ///
+    /// The password is either set in the configuration or read from a file, an environment
+    /// variable or the output of a command. It is only read when it is needed, so that e.g. a
+    /// password manager is not asked for commands that do not use the database.
+    pub fn database_password(&self) -> Result<String> {
+        if let Some(password) = self.database_password.as_ref() {
+            Ok(password.clone())
+        } else if let Some(path) = self.database_password_file.as_ref() {
+            std::fs::read_to_string(path)
+                .map(|content| content.trim_end_matches(['\n', '\r']).to_string())
+                .with_context(|| anyhow!("Reading the database password from {}", path.display()))
+        } else if let Some(var) = self.database_password_env.as_ref() {
+            std::env::var(var)
+                .with_context(|| anyhow!("Reading the database password from the environment variable {}", var))
+        } else if let Some(command) = self.database_password_command.as_ref() {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdin(std::process::Stdio::inherit())
+                .stderr(std::process::Stdio::inherit())
+                .output()
+                .with_context(|| anyhow!("Running the database password command '{}'", command))?;
+
+            if !output.status.success() {
+                return Err(anyhow!("The database password command '{}' failed: {}", command, output.status))
+            }
+
+            String::from_utf8(output.stdout)
+                .map(|out| out.lines().next().unwrap_or_default().to_string())
+                .with_context(|| anyhow!("The output of the database password command '{}' is not UTF-8", command))
+        } else {
+            Err(anyhow!("No database password configured"))
+        }
+    }
+
+    /// Error if not exactly one source of the database password is configured
+    fn check_database_password_sources(&self) -> Result<()> {
+        let n_password_sources = [
+            self.database_password.is_some(),
+            self.database_password_file.is_some(),
+            self.database_password_env.is_some(),
+            self.database_password_command.is_some(),
+        ]
+        .iter()
+        .filter(|is_set| **is_set)
+        .count();
+
+        if n_password_sources != 1 {
+            return Err(anyhow!(
+                "Exactly one of 'database_password', 'database_password_file', 'database_password_env' \
+                and 'database_password_command' has to be set"
+            ))
+        }
+        Ok(())
+    }
+
+    /// Replace `${VAR}` with the environment variable `VAR` in the paths, the database settings,
+    /// the tokens and the URIs of the endpoints
+    fn interpolate_env(&mut self) -> Result<()> {
+        let paths = [
+            ("log_dir", &mut self.log_dir),
+            ("releases_root", &mut self.releases_directory),
+            ("staging", &mut self.staging_directory),
+            ("source_cache", &mut self.source_cache_root),
+        ];
+        for (setting, path) in paths {
+            *path = interpolate_env_path(path, setting)?;
+        }
+
+        for repository in self.repositories.iter_mut() {
+            *repository = interpolate_env_path(repository, "repositories")?;
+        }
+
+        if let Some(log_archive) = self.log_archive.as_mut() {
+            log_archive.interpolate_env()?;
+        }
+
+        let optional_paths = [
+            ("source_ssh_key", self.source_ssh_key.as_mut()),
+            ("script_linter", self.script_linter.as_mut()),
+            ("database_password_file", self.database_password_file.as_mut()),
+        ];
+        for (setting, path) in optional_paths {
+            if let Some(path) = path {
+                *path = interpolate_env_path(path, setting)?;
+            }
+        }
+
+        let strings = [
+            ("database_host", &mut self.database_host),
+            ("database_user", &mut self.database_user),
+            ("database_name", &mut self.database_name),
+        ];
+        for (setting, value) in strings {
+            *value = interpolate_env(value, setting)?;
+        }
+
+        let optional_strings = [
+            ("github_token", self.github_token.as_mut()),
+            ("gitlab_token", self.gitlab_token.as_mut()),
+            ("database_password", self.database_password.as_mut()),
+            ("database_password_command", self.database_password_command.as_mut()),
+        ];
+        for (setting, value) in optional_strings {
+            if let Some(value) = value {
+                *value = interpolate_env(value, setting)?;
+            }
+        }
+
+        for (name, target) in self.oci_release_targets.iter_mut() {
+            target.interpolate_env(name)?;
+        }
+
+        for (name, target) in self.release_sync_targets.iter_mut() {
+            target.interpolate_env(name)?;
+        }
+
+        self.docker.interpolate_env()
+    }
+
+    /// Find the keys in the (merged) configuration that are not known to butido
+    ///
+    /// Unknown keys are ignored when loading the configuration, so a typo in the name of an
+    /// optional setting silently results in its default value being used.
+    pub fn unknown_keys(config: &::config::Config) -> Result<Vec<String>> {
+        use serde_json::Value;
+
+        let value = config.clone().try_into::<Value>()?;
+        let mut unknown = Vec::new();
+        find_unknown_settings(&value, "", &mut unknown);
+
+        for (name, profile) in value.get(PROFILES_KEY).and_then(Value::as_object).into_iter().flatten() {
+            find_unknown_settings(profile, &format!("{PROFILES_KEY}.{name}"), &mut unknown);
+        }
+
+        // The profiles are not a setting of their own
+        unknown.retain(|key| key != PROFILES_KEY);
+        unknown.sort();
+        Ok(unknown)
+    }
+}
+
+/// Find the unknown keys in the settings `value`, which are either the top-level settings or the
+/// settings of a profile
+fn find_unknown_settings(value: &serde_json::Value, prefix: &str, unknown: &mut Vec<String>) {
+    use serde_json::Value;
+
+    let path = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{prefix}.{key}") };
+    find_unknown_keys(value, prefix, field_names::<NotValidatedConfiguration>(), unknown);
+
+    if let Some(docker) = value.get("docker") {
+        find_unknown_keys(docker, &path("docker"), field_names::<DockerConfig>(), unknown);
+
+        for (name, endpoint) in docker.get("endpoints").and_then(Value::as_object).into_iter().flatten() {
+            let prefix = path(&format!("docker.endpoints.{name}"));
+            find_unknown_keys(endpoint, &prefix, field_names::<Endpoint>(), unknown);
+        }
+
+        for (i, image) in docker.get("images").and_then(Value::as_array).into_iter().flatten().enumerate() {
+            let prefix = path(&format!("docker.images[{i}]"));
+            find_unknown_keys(image, &prefix, field_names::<ContainerImage>(), unknown);
+        }
+
+        for (i, image) in docker.get("default_images").and_then(Value::as_array).into_iter().flatten().enumerate() {
+            let prefix = path(&format!("docker.default_images[{i}]"));
+            find_unknown_keys(image, &prefix, field_names::<DefaultImage>(), unknown);
+        }
+    }
+
+    if let Some(containers) = value.get("containers") {
+        find_unknown_keys(containers, &path("containers"), field_names::<ContainerConfig>(), unknown);
+    }
+    if let Some(log_files) = value.get("log_files") {
+        find_unknown_keys(log_files, &path("log_files"), field_names::<LogFileConfig>(), unknown);
+    }
+    if let Some(log_archive) = value.get("log_archive") {
+        find_unknown_keys(log_archive, &path("log_archive"), field_names::<LogArchiveConfig>(), unknown);
+    }
+    if let Some(audit) = value.get("audit") {
+        find_unknown_keys(audit, &path("audit"), field_names::<AuditConfig>(), unknown);
+        for (name, package) in audit.get("packages").and_then(Value::as_object).into_iter().flatten() {
+            let prefix = path(&format!("audit.packages.{name}"));
+            find_unknown_keys(package, &prefix, field_names::<OsvPackage>(), unknown);
+        }
+    }
+    if let Some(job_retry) = value.get("job_retry") {
+        find_unknown_keys(job_retry, &path("job_retry"), field_names::<RetryConfig>(), unknown);
+    }
+    if let Some(provenance) = value.get("release_provenance") {
+        find_unknown_keys(provenance, &path("release_provenance"), field_names::<ReleaseProvenanceConfig>(), unknown);
+    }
+    if let Some(links) = value.get("release_latest_links") {
+        find_unknown_keys(links, &path("release_latest_links"), field_names::<LatestLinksConfig>(), unknown);
+    }
+    for (name, target) in value.get("oci_release_targets").and_then(Value::as_object).into_iter().flatten() {
+        let prefix = path(&format!("oci_release_targets.{name}"));
+        find_unknown_keys(target, &prefix, field_names::<OciReleaseTarget>(), unknown);
+    }
+}
+
+fn find_unknown_keys(value: &serde_json::Value, prefix: &str, known: &[&str], unknown: &mut Vec<String>) {
+    if let Some(map) = value.as_object() {
+        let keys = map.keys()
+            .filter(|key| !known.contains(&key.as_str()))
+            .map(|key| if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") });
+
+        unknown.extend(keys);
+    }
+}
+
+/// Get the names (including aliases) of the fields of a struct that derives `Deserialize`
+///
+/// The derived implementation passes the names of the fields to `Deserializer::deserialize_struct()`,
+/// so they are taken from there instead of maintaining a second list of the known keys.
+fn field_names<T: serde::de::DeserializeOwned>() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> std::result::Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("only collecting the field names"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_names() {
+        assert_eq!(field_names::<LogFileConfig>(), &["path", "keep", "compress"]);
+        assert!(field_names::<Endpoint>().contains(&"max_parallel_jobs"));
+    }
+
+    /// A configuration with the database password settings in `password`
+    fn config_with_password(password: &str) -> NotValidatedConfiguration {
+        let toml = indoc::formatdoc!(r#"
+            compatibility = "*"
+            staging = "/tmp/staging"
+            releases_root = "/tmp/releases"
+            release_stores = [ "default" ]
+            source_cache = "/tmp/sources"
+            log_dir = "/tmp/logs"
+            database_host = "localhost"
+            database_port = 5432
+            database_user = "butido"
+            database_name = "butido"
+            available_phases = [ "build" ]
+            {password}
+
+            [docker]
+            verify_images_present = false
+            images = []
+            endpoints = {{}}
+
+            [containers]
+            check_env_names = false
+            allowed_env = []
+        "#);
+
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(&toml, ::config::FileFormat::Toml)).unwrap();
+        config.try_into::<NotValidatedConfiguration>().unwrap()
+    }
+
+    #[test]
+    fn test_database_password_sources() {
+        assert!(config_with_password(r#"database_password = "secret""#).check_database_password_sources().is_ok());
+        assert!(config_with_password(r#"database_password_env = "PGPASSWORD""#).check_database_password_sources().is_ok());
+
+        let err = config_with_password("").check_database_password_sources().unwrap_err();
+        assert!(err.to_string().starts_with("Exactly one of"));
+
+        let both = indoc::indoc!(r#"
+            database_password = "secret"
+            database_password_command = "echo secret"
+        "#);
+        assert!(config_with_password(both).check_database_password_sources().is_err());
+    }
+
+    #[test]
+    fn test_database_password() {
+        let config = config_with_password(r#"database_password = "secret""#);
+        assert_eq!(config.database_password().unwrap(), "secret");
+
+        let dir = std::env::temp_dir().join(format!("butido-test-password-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("password");
+        std::fs::write(&file, "from file\n").unwrap();
+        let config = config_with_password(&format!("database_password_file = {:?}", file.display().to_string()));
+        let password = config.database_password();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(password.unwrap(), "from file");
+
+        let config = config_with_password(r#"database_password_command = "printf 'from command\nsecond line'""#);
+        assert_eq!(config.database_password().unwrap(), "from command");
+
+        let config = config_with_password(r#"database_password_command = "exit 1""#);
+        assert!(config.database_password().is_err());
+
+        let config = config_with_password(r#"database_password_env = "BUTIDO_TEST_UNSET_PASSWORD_VARIABLE""#);
+        assert!(config.database_password().is_err());
+
+        assert!(config_with_password("").database_password().is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let toml = indoc::indoc!(r#"
+            compatibility = "*"
+            staging = "/tmp/staging"
+            databse_host = "localhost"
+
+            [docker]
+            images = [ { name = "debian:bullseye", short_name = "deb11", tag = "x" } ]
+
+            [docker.endpoints.testhost]
+            uri = "http://0.0.0.0:8095"
+            endpoint_type = "http"
+            maxjobs = 1
+            timout = 10
+
+            [log_files]
+            keep = 2
+        "#);
+
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(toml, ::config::FileFormat::Toml)).unwrap();
+
+        assert_eq!(NotValidatedConfiguration::unknown_keys(&config).unwrap(), vec![
+            String::from("databse_host"),
+            String::from("docker.endpoints.testhost.timout"),
+            String::from("docker.images[0].tag"),
+        ]);
     }
 }
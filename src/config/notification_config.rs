@@ -0,0 +1,129 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use url::Url;
+
+use crate::notification::NotificationEvent;
+
+/// The configuration of a builtin notification backend
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotificationBackendConfig {
+    Matrix(MatrixConfig),
+    Slack(SlackConfig),
+    Email(EmailConfig),
+}
+
+impl NotificationBackendConfig {
+    /// The events the backend is notified about
+    pub fn events(&self) -> &[NotificationEvent] {
+        match self {
+            NotificationBackendConfig::Matrix(c) => c.events(),
+            NotificationBackendConfig::Slack(c) => c.events(),
+            NotificationBackendConfig::Email(c) => c.events(),
+        }
+    }
+}
+
+/// Send notifications as messages to a Matrix room
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct MatrixConfig {
+    /// The URL of the homeserver, e.g. "https://matrix.example.com"
+    #[getset(get = "pub")]
+    homeserver: Url,
+
+    /// The ID of the room, e.g. "!abcdefg:example.com"
+    #[getset(get = "pub")]
+    room: String,
+
+    /// The access token of the user that sends the messages
+    #[getset(get = "pub")]
+    access_token: String,
+
+    #[getset(get = "pub")]
+    #[serde(default = "NotificationEvent::all")]
+    events: Vec<NotificationEvent>,
+}
+
+/// Send notifications to a Slack-compatible incoming webhook
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct SlackConfig {
+    #[getset(get = "pub")]
+    url: Url,
+
+    #[getset(get = "pub")]
+    #[serde(default = "NotificationEvent::all")]
+    events: Vec<NotificationEvent>,
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection, not recommended
+    None,
+
+    /// Plain connection that is upgraded with STARTTLS
+    #[default]
+    StartTls,
+
+    /// TLS connection
+    Tls,
+}
+
+/// Send notifications as email via SMTP
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct EmailConfig {
+    /// The host name of the SMTP server
+    #[getset(get = "pub")]
+    server: String,
+
+    /// The port of the SMTP server, the default port for `tls` if not set
+    #[serde(default)]
+    port: Option<u16>,
+
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    tls: SmtpTls,
+
+    /// The user name for authenticating at the SMTP server, if any (requires TLS)
+    #[getset(get = "pub")]
+    #[serde(default)]
+    username: Option<String>,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    password: Option<String>,
+
+    /// The sender address
+    #[getset(get = "pub")]
+    from: String,
+
+    /// The recipient addresses
+    #[getset(get = "pub")]
+    to: Vec<String>,
+
+    #[getset(get = "pub")]
+    #[serde(default = "NotificationEvent::all")]
+    events: Vec<NotificationEvent>,
+}
+
+impl EmailConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            SmtpTls::None => 25,
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+        })
+    }
+}
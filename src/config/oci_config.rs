@@ -0,0 +1,64 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::config::interpolation::interpolate_env;
+
+/// An OCI registry that artifacts can be released to with `release new --to <name>`
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct OciReleaseTarget {
+    /// The host (and port) of the registry, e.g. "registry.example.com:5000"
+    #[getset(get = "pub")]
+    registry: String,
+
+    /// The repository the packages are pushed to, below this prefix, e.g. "butido/packages"
+    #[getset(get = "pub")]
+    #[serde(default)]
+    repository_prefix: String,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    username: Option<String>,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    password: Option<String>,
+
+    /// Connect via HTTP instead of HTTPS
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    insecure: bool,
+
+    /// The artifact type of the pushed manifests
+    #[getset(get = "pub")]
+    #[serde(default = "default_artifact_type")]
+    artifact_type: String,
+}
+
+fn default_artifact_type() -> String {
+    String::from("application/vnd.butido.package.v1")
+}
+
+impl OciReleaseTarget {
+    pub(in crate::config) fn interpolate_env(&mut self, name: &str) -> Result<()> {
+        let setting = format!("oci_release_targets.{name}");
+        if let Some(username) = self.username.as_mut() {
+            *username = interpolate_env(username, &format!("{setting}.username"))?;
+        }
+        if let Some(password) = self.password.as_mut() {
+            *password = interpolate_env(password, &format!("{setting}.password"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Configuration profiles
+//!
+//! A profile is a table `[profiles.<name>]` in the configuration, which contains settings that
+//! override the top-level settings if the profile is selected (via `--profile` or
+//! `BUTIDO_PROFILE`). This way one configuration file can be used for several build farms, e.g.
+//! for staging and production.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use itertools::Itertools;
+
+/// The key of the table with the profiles
+pub const PROFILES_KEY: &str = "profiles";
+
+/// Merge the settings of the profile `name` into the configuration
+///
+/// Tables are merged, so a profile only has to contain the settings it changes.
+pub fn apply_profile(config: &mut ::config::Config, name: &str) -> Result<()> {
+    let profiles = config.get_table(PROFILES_KEY).unwrap_or_default();
+    let profile = profiles
+        // The keys of the configuration are case insensitive
+        .get(&name.to_lowercase())
+        .ok_or_else(|| {
+            let available = profiles.keys().sorted().join(", ");
+            anyhow!("Profile '{}' not found, available profiles: [{}]", name, available)
+        })?
+        .clone()
+        .into_table()
+        .with_context(|| anyhow!("Profile '{}' is not a table", name))?;
+
+    let mut overrides = ::config::Config::default();
+    for (key, value) in profile {
+        overrides.set(&key, value)?;
+    }
+
+    config.merge(overrides)
+        .with_context(|| anyhow!("Applying profile '{}'", name))?;
+    Ok(())
+}
+
+/// The environment variables with the prefix "BUTIDO_" as source for the configuration
+///
+/// `BUTIDO_CONFIG` and `BUTIDO_PROFILE` select the configuration file and the profile, they are
+/// not settings and are therefore left out.
+#[derive(Clone, Debug)]
+pub struct EnvironmentSource(::config::Environment);
+
+impl Default for EnvironmentSource {
+    fn default() -> Self {
+        EnvironmentSource(::config::Environment::with_prefix("BUTIDO"))
+    }
+}
+
+impl ::config::Source for EnvironmentSource {
+    fn clone_into_box(&self) -> Box<dyn ::config::Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> std::result::Result<HashMap<String, ::config::Value>, ::config::ConfigError> {
+        let mut settings = self.0.collect()?;
+        settings.remove("config");
+        settings.remove("profile");
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from(toml: &str) -> ::config::Config {
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(toml, ::config::FileFormat::Toml)).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let mut config = config_from(indoc::indoc!(r#"
+            staging = "/tmp/staging"
+            database_host = "localhost"
+
+            [docker.endpoints.a]
+            uri = "http://a:8095"
+            maxjobs = 1
+
+            [profiles.production]
+            database_host = "db.example.com"
+
+            [profiles.production.docker.endpoints.a]
+            maxjobs = 4
+        "#));
+
+        apply_profile(&mut config, "production").unwrap();
+        assert_eq!(config.get_str("database_host").unwrap(), "db.example.com");
+        assert_eq!(config.get_str("staging").unwrap(), "/tmp/staging");
+        assert_eq!(config.get_int("docker.endpoints.a.maxjobs").unwrap(), 4);
+        assert_eq!(config.get_str("docker.endpoints.a.uri").unwrap(), "http://a:8095");
+    }
+
+    #[test]
+    fn test_apply_missing_profile() {
+        let mut config = config_from(indoc::indoc!(r#"
+            [profiles.staging]
+            database_host = "db.example.com"
+        "#));
+
+        let e = apply_profile(&mut config, "production").unwrap_err();
+        assert!(e.to_string().contains("available profiles: [staging]"));
+    }
+}
@@ -0,0 +1,27 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The "latest" links that are maintained in the release stores
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct LatestLinksConfig {
+    /// Only maintain the links in these release stores, in all release stores if empty
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_stores: Vec<String>,
+
+    /// Also maintain links to the newest version of each major version of a package
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    per_major_version: bool,
+}
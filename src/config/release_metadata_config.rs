@@ -0,0 +1,29 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use serde::Deserialize;
+
+/// The formats of package repository metadata that can be generated in a release store
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, parse_display::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum RepositoryMetadataFormat {
+    /// "repodata/" for RPMs, generated with `createrepo_c`
+    #[display("rpm")]
+    Rpm,
+
+    /// "Packages", "Packages.gz" and "Release" for debs, the packages are scanned with
+    /// `dpkg-scanpackages`
+    #[display("deb")]
+    Deb,
+
+    /// "index.json" with all files of the release store
+    #[display("json")]
+    Json,
+}
@@ -0,0 +1,42 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The provenance documents that are written next to released artifacts
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct ReleaseProvenanceConfig {
+    /// Only write provenance documents in these release stores, in all release stores if empty
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_stores: Vec<String>,
+
+    /// The ID of the builder in the provenance documents
+    #[getset(get = "pub")]
+    #[serde(default = "default_builder_id")]
+    builder_id: String,
+
+    /// The URI of the package repository (e.g. "git+https://git.example.com/packages.git"), it is
+    /// recorded with the commit the artifacts were built from
+    #[getset(get = "pub")]
+    #[serde(default)]
+    repository_uri: Option<String>,
+
+    /// Sign the provenance documents with the signer of `release_signing`
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    sign: bool,
+}
+
+fn default_builder_id() -> String {
+    String::from("https://github.com/science-computing/butido")
+}
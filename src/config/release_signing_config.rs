@@ -0,0 +1,72 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::PathBuf;
+
+use getset::Getters;
+use serde::Deserialize;
+
+/// How released artifacts are signed
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct ReleaseSigningConfig {
+    #[serde(flatten)]
+    #[getset(get = "pub")]
+    signer: ReleaseSigner,
+
+    /// Only sign the artifacts released to these release stores, all release stores if empty
+    #[getset(get = "pub")]
+    #[serde(default)]
+    release_stores: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ReleaseSigner {
+    Gpg(GpgSigner),
+    Command(CommandSigner),
+}
+
+/// Sign with a detached, ASCII armored GPG signature ("<file>.asc")
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct GpgSigner {
+    /// The key to sign with (a key ID, fingerprint or user ID)
+    #[getset(get = "pub")]
+    key: String,
+
+    /// The GnuPG home directory, the default of gpg if not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    homedir: Option<PathBuf>,
+}
+
+/// Sign by running an external command
+///
+/// The command gets the path of the artifact in BUTIDO_ARTIFACT_PATH and has to write the
+/// signature to BUTIDO_SIGNATURE_PATH.
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct CommandSigner {
+    /// The program and its arguments
+    #[getset(get = "pub")]
+    command: Vec<String>,
+
+    /// The extension of the signature files
+    #[getset(get = "pub")]
+    #[serde(default = "default_signature_extension")]
+    signature_extension: String,
+
+    /// The fingerprint of the signing key, recorded with the releases
+    #[getset(get = "pub")]
+    #[serde(default)]
+    fingerprint: Option<String>,
+}
+
+fn default_signature_extension() -> String {
+    String::from("sig")
+}
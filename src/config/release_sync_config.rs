@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use url::Url;
+
+use crate::config::interpolation::interpolate_env;
+
+/// A remote storage that release stores can be synced to with `release sync --to <name>`
+///
+/// The files of a release store are synced to "<destination>/<release store name>/".
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ReleaseSyncTarget {
+    Rsync(RsyncTarget),
+    S3(S3Target),
+}
+
+impl ReleaseSyncTarget {
+    pub(in crate::config) fn interpolate_env(&mut self, name: &str) -> Result<()> {
+        match self {
+            ReleaseSyncTarget::Rsync(_) => Ok(()),
+            ReleaseSyncTarget::S3(s3) => {
+                let setting = format!("release_sync_targets.{name}");
+                s3.access_key = interpolate_env(&s3.access_key, &format!("{setting}.access_key"))?;
+                s3.secret_key = interpolate_env(&s3.secret_key, &format!("{setting}.secret_key"))?;
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Sync with `rsync` over SSH
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct RsyncTarget {
+    /// The remote directory, e.g. "butido@mirror.example.com:/srv/releases"
+    #[getset(get = "pub")]
+    destination: String,
+
+    /// The SSH port, if not the default
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    port: Option<u16>,
+
+    /// The SSH private key, the default identities of the ssh client are used if not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    ssh_key: Option<PathBuf>,
+}
+
+/// Upload to an S3 compatible object storage
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct S3Target {
+    /// The URL of the storage, e.g. "https://s3.eu-central-1.amazonaws.com"
+    #[getset(get = "pub")]
+    endpoint: Url,
+
+    #[getset(get = "pub")]
+    bucket: String,
+
+    /// The prefix of the object keys, e.g. "butido/releases"
+    #[getset(get = "pub")]
+    #[serde(default)]
+    prefix: String,
+
+    #[getset(get = "pub")]
+    #[serde(default = "default_region")]
+    region: String,
+
+    #[getset(get = "pub")]
+    access_key: String,
+
+    #[getset(get = "pub")]
+    secret_key: String,
+}
+
+fn default_region() -> String {
+    String::from("us-east-1")
+}
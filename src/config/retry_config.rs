@@ -0,0 +1,70 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::time::Duration;
+
+use getset::CopyGetters;
+use serde::Deserialize;
+
+/// The configuration for retrying jobs
+///
+/// Only jobs that failed because of the endpoint or the container are retried, a failing script
+/// fails the build right away.
+#[derive(Clone, Debug, CopyGetters, Deserialize)]
+pub struct RetryConfig {
+    /// How often a failed job is retried, 0 disables retrying
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    max_retries: usize,
+
+    /// The number of seconds to wait before the first retry, doubled for each further retry
+    #[getset(get_copy = "pub")]
+    #[serde(default = "default_backoff")]
+    backoff: u64,
+}
+
+fn default_backoff() -> u64 {
+    30
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 0,
+            backoff: default_backoff(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The time to wait before the retry number `retry` (starting at 1)
+    pub fn delay(&self, retry: usize) -> Duration {
+        let factor = 2u64.saturating_pow(u32::try_from(retry.saturating_sub(1)).unwrap_or(u32::MAX));
+        Duration::from_secs(self.backoff.saturating_mul(factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let config: RetryConfig = toml::from_str("max_retries = 3\nbackoff = 10").unwrap();
+        assert_eq!(config.max_retries(), 3);
+        assert_eq!(config.delay(1), Duration::from_secs(10));
+        assert_eq!(config.delay(2), Duration::from_secs(20));
+        assert_eq!(config.delay(3), Duration::from_secs(40));
+
+        let config: RetryConfig = toml::from_str("").unwrap();
+        assert_eq!(config.max_retries(), 0);
+        assert_eq!(config.delay(1), Duration::from_secs(30));
+    }
+}
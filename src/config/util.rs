@@ -106,6 +106,11 @@ pub fn default_strict_script_interpolation() -> bool {
     true
 }
 
+/// The default value for whether the loaded package definitions are cached
+pub fn default_repository_cache() -> bool {
+    true
+}
+
 /// The default value for the shebang
 pub fn default_script_shebang() -> String {
     String::from("#!/bin/bash")
@@ -115,3 +120,8 @@ pub fn default_script_shebang() -> String {
 pub fn default_build_error_lines() -> usize {
     10
 }
+
+/// The default URL for querying the latest upstream version of a package (release-monitoring.org)
+pub fn default_upstream_version_url() -> String {
+    String::from("https://release-monitoring.org/api/v2/projects/?name={{name}}")
+}
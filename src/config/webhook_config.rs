@@ -0,0 +1,35 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::Getters;
+use serde::Deserialize;
+use url::Url;
+
+use crate::notification::NotificationEvent;
+
+/// The configuration of a webhook that is notified about submit and job events
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct WebhookConfig {
+    /// The URL the notifications are POSTed to
+    #[getset(get = "pub")]
+    url: Url,
+
+    /// The events the webhook is notified about, all events if not set
+    #[getset(get = "pub")]
+    #[serde(default = "NotificationEvent::all")]
+    events: Vec<NotificationEvent>,
+
+    /// The payload that is sent, the notification as JSON if not set
+    ///
+    /// This is handlebars syntax, the fields of the notification are available.
+    #[getset(get = "pub")]
+    #[serde(default)]
+    payload: Option<String>,
+}
@@ -18,7 +18,7 @@ use tracing::debug;
 
 use crate::config::Configuration;
 
-#[derive(Getters)]
+#[derive(Clone, Getters)]
 pub struct DbConnectionConfig<'a> {
     #[getset(get = "pub")]
     database_host: &'a str,
@@ -30,7 +30,7 @@ pub struct DbConnectionConfig<'a> {
     database_user: &'a str,
 
     #[getset(get = "pub")]
-    database_password: &'a str,
+    database_password: String,
 
     #[getset(get = "pub")]
     database_name: &'a str,
@@ -62,7 +62,10 @@ impl<'a> DbConnectionConfig<'a> {
                     .unwrap_or_else(|| *config.database_port())
             },
             database_user: cli.get_one::<String>("database_user").unwrap_or_else(|| config.database_user()),
-            database_password: cli.get_one::<String>("database_password").unwrap_or_else(|| config.database_password()),
+            database_password: match cli.get_one::<String>("database_password") {
+                Some(password) => password.clone(),
+                None => config.database_password()?,
+            },
             database_name: cli.get_one::<String>("database_name").unwrap_or_else(|| config.database_name()),
             database_connection_timeout: {
                 cli.get_one::<String>("database_connection_timeout")
@@ -78,7 +81,12 @@ impl<'a> DbConnectionConfig<'a> {
 
     pub fn establish_connection(self) -> Result<PgConnection> {
         debug!("Trying to connect to database: {:?}", self);
-        let database_uri: String = format!(
+        PgConnection::establish(&self.database_uri()).map_err(Error::from)
+    }
+
+    /// Get the URI for connecting to the database, including the password
+    pub fn database_uri(&self) -> String {
+        format!(
             "postgres://{user}:{password}@{host}:{port}/{name}?connect_timeout={timeout}",
             host = self.database_host,
             port = self.database_port,
@@ -86,9 +94,7 @@ impl<'a> DbConnectionConfig<'a> {
             password = self.database_password,
             name = self.database_name,
             timeout = self.database_connection_timeout,
-        );
-        PgConnection::establish(&database_uri).map_err(Error::from)
+        )
     }
-
 }
 
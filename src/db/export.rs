@@ -0,0 +1,474 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Export and import of the build history (submits, jobs, artifacts and releases)
+//!
+//! Submits and jobs are referenced by their UUIDs, packages, images, endpoints and release stores
+//! by their names, so that an export can be imported into another database. Artifacts and
+//! releases do not have such a key, they are referenced by IDs that are only valid within the
+//! export.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::EndpointName;
+use crate::db::models;
+use crate::filestore::ArtifactPath;
+use crate::package::Script;
+use crate::schema;
+use crate::util::EnvironmentVariableName;
+use crate::util::docker::ContainerHash;
+use crate::util::docker::ImageName;
+
+/// The version of the export format, increased on incompatible changes
+pub const EXPORT_VERSION: u32 = 1;
+
+/// The format of the timestamps in the export
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Export {
+    pub version: u32,
+    pub submits: Vec<SubmitRecord>,
+    pub jobs: Vec<JobRecord>,
+    pub artifacts: Vec<ArtifactRecord>,
+    pub releases: Vec<ReleaseRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackageRecord {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvRecord {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmitRecord {
+    pub uuid: Uuid,
+    pub time: String,
+    pub image: String,
+    pub package: PackageRecord,
+    pub repo_hash: String,
+    pub flavor: Option<String>,
+    pub features: Vec<String>,
+    pub manifest: Option<String>,
+    pub env: Vec<EnvRecord>,
+    #[serde(default)]
+    pub butido_version: Option<String>,
+    #[serde(default)]
+    pub butido_git_hash: Option<String>,
+    #[serde(default)]
+    pub config_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub uuid: Uuid,
+    pub submit: Uuid,
+    pub endpoint: String,
+    pub image: String,
+    pub package: PackageRecord,
+    pub container_hash: String,
+    pub script: String,
+    pub log: String,
+    pub env: Vec<EnvRecord>,
+    #[serde(default)]
+    pub started: Option<String>,
+    #[serde(default)]
+    pub finished: Option<String>,
+    #[serde(default)]
+    pub image_digest: Option<String>,
+    #[serde(default)]
+    pub buildinfo: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArtifactRecord {
+    /// The ID of the artifact within the export
+    pub id: i32,
+    pub job: Uuid,
+    pub path: String,
+    pub checksum: Option<String>,
+    pub verified: Option<bool>,
+    #[serde(default)]
+    pub size: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReleaseRecord {
+    /// The ID of the release within the export
+    pub id: i32,
+    /// The ID of the released artifact within the export
+    pub artifact: i32,
+    pub store: String,
+    pub date: String,
+    pub partial: bool,
+    /// The ID of the rolled back release within the export
+    pub rollback_of: Option<i32>,
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub signature_path: Option<String>,
+    #[serde(default)]
+    pub signature_fingerprint: Option<String>,
+}
+
+/// The number of rows that were imported
+#[derive(Debug, Default)]
+pub struct ImportStats {
+    pub submits: usize,
+    pub skipped_submits: usize,
+    pub jobs: usize,
+    pub artifacts: usize,
+    pub releases: usize,
+}
+
+/// Export the build history from the database
+pub fn export(database_connection: &PgConnection) -> Result<Export> {
+    let mut submit_envs = env_by_id(
+        schema::submit_envs::table
+            .inner_join(schema::envvars::table)
+            .select((schema::submit_envs::submit_id, schema::envvars::name, schema::envvars::value))
+            .load::<(i32, String, String)>(database_connection)?,
+    );
+
+    let submits = schema::submits::table
+        .inner_join(schema::images::table)
+        .inner_join(schema::packages::table)
+        .inner_join(schema::githashes::table)
+        .order_by(schema::submits::id.asc())
+        .load::<(models::Submit, models::Image, models::Package, models::GitHash)>(database_connection)
+        .context("Loading submits")?
+        .into_iter()
+        .map(|(submit, image, package, githash)| SubmitRecord {
+            env: submit_envs.remove(&submit.id).unwrap_or_default(),
+            uuid: submit.uuid,
+            time: submit.submit_time.format(TIME_FORMAT).to_string(),
+            image: image.name,
+            package: PackageRecord { name: package.name, version: package.version },
+            repo_hash: githash.hash,
+            flavor: submit.flavor,
+            features: submit.features,
+            manifest: submit.manifest,
+            butido_version: submit.butido_version,
+            butido_git_hash: submit.butido_git_hash,
+            config_fingerprint: submit.config_fingerprint,
+        })
+        .collect();
+
+    let mut job_envs = env_by_id(
+        schema::job_envs::table
+            .inner_join(schema::envvars::table)
+            .select((schema::job_envs::job_id, schema::envvars::name, schema::envvars::value))
+            .load::<(i32, String, String)>(database_connection)?,
+    );
+
+    let jobs = schema::jobs::table
+        .inner_join(schema::submits::table)
+        .inner_join(schema::endpoints::table)
+        .inner_join(schema::images::table)
+        .inner_join(schema::packages::table)
+        .order_by(schema::jobs::id.asc())
+        .load::<(models::Job, models::Submit, models::Endpoint, models::Image, models::Package)>(database_connection)
+        .context("Loading jobs")?
+        .into_iter()
+        .map(|(job, submit, endpoint, image, package)| JobRecord {
+            env: job_envs.remove(&job.id).unwrap_or_default(),
+            uuid: job.uuid,
+            submit: submit.uuid,
+            endpoint: endpoint.name,
+            image: image.name,
+            package: PackageRecord { name: package.name, version: package.version },
+            container_hash: job.container_hash,
+            script: job.script_text,
+            log: job.log_text,
+            started: job.started.map(|t| t.format(TIME_FORMAT).to_string()),
+            finished: job.finished.map(|t| t.format(TIME_FORMAT).to_string()),
+            image_digest: job.image_digest,
+            buildinfo: job.buildinfo,
+        })
+        .collect();
+
+    let artifacts = schema::artifacts::table
+        .inner_join(schema::jobs::table)
+        .select((schema::artifacts::all_columns, schema::jobs::uuid))
+        .order_by(schema::artifacts::id.asc())
+        .load::<(models::Artifact, Uuid)>(database_connection)
+        .context("Loading artifacts")?
+        .into_iter()
+        .map(|(artifact, job)| ArtifactRecord {
+            id: artifact.id,
+            job,
+            path: artifact.path,
+            checksum: artifact.checksum,
+            verified: artifact.verified,
+            size: artifact.size,
+        })
+        .collect();
+
+    let releases = schema::releases::table
+        .inner_join(schema::release_stores::table)
+        .order_by(schema::releases::id.asc())
+        .load::<(models::Release, models::ReleaseStore)>(database_connection)
+        .context("Loading releases")?
+        .into_iter()
+        .map(|(release, store)| ReleaseRecord {
+            id: release.id,
+            artifact: release.artifact_id,
+            store: store.store_name,
+            date: release.release_date.format(TIME_FORMAT).to_string(),
+            partial: release.partial,
+            rollback_of: release.rollback_of,
+            reason: release.reason,
+            signature_path: release.signature_path,
+            signature_fingerprint: release.signature_fingerprint,
+        })
+        .collect();
+
+    Ok(Export {
+        version: EXPORT_VERSION,
+        submits,
+        jobs,
+        artifacts,
+        releases,
+    })
+}
+
+/// Import an export into the database
+///
+/// Submits that already exist in the database are skipped, together with their jobs, artifacts
+/// and releases. The import is done in one transaction.
+pub fn import(database_connection: &PgConnection, export: Export) -> Result<ImportStats> {
+    if export.version != EXPORT_VERSION {
+        return Err(anyhow!(
+            "Unsupported export version {}, expected {}",
+            export.version,
+            EXPORT_VERSION
+        ));
+    }
+
+    database_connection.transaction::<_, Error, _>(|| {
+        let mut stats = ImportStats::default();
+
+        let mut submits = HashMap::new();
+        for record in export.submits {
+            let exists = schema::submits::table
+                .filter(schema::submits::uuid.eq(record.uuid))
+                .first::<models::Submit>(database_connection)
+                .optional()?
+                .is_some();
+            if exists {
+                debug!("Submit {} exists already, skipping it", record.uuid);
+                stats.skipped_submits += 1;
+                continue
+            }
+
+            let image = models::Image::create_or_fetch(database_connection, &ImageName::from(record.image))?;
+            let package = models::Package::create_or_fetch_by_name(database_connection, &record.package.name, &record.package.version)?;
+            let githash = models::GitHash::create_or_fetch(database_connection, &record.repo_hash)?;
+            let submit = models::Submit::create(
+                database_connection,
+                &parse_time(&record.time)?,
+                &record.uuid,
+                &image,
+                &package,
+                &githash,
+                record.flavor.as_deref(),
+                &record.features,
+                record.manifest.as_deref(),
+                record.butido_version.as_deref(),
+                record.butido_git_hash.as_deref(),
+                record.config_fingerprint.as_deref(),
+            )
+            .with_context(|| anyhow!("Importing submit {}", record.uuid))?;
+
+            for env in record.env {
+                let envvar = models::EnvVar::create_or_fetch(database_connection, &EnvironmentVariableName::from(env.name.as_str()), &env.value)?;
+                diesel::insert_into(schema::submit_envs::table)
+                    .values((
+                        schema::submit_envs::submit_id.eq(submit.id),
+                        schema::submit_envs::env_id.eq(envvar.id),
+                    ))
+                    .execute(database_connection)?;
+            }
+
+            stats.submits += 1;
+            submits.insert(record.uuid, submit);
+        }
+
+        let mut jobs = HashMap::new();
+        for record in export.jobs {
+            let submit = match submits.get(&record.submit) {
+                Some(submit) => submit,
+                None => continue, // the submit was skipped
+            };
+
+            let endpoint = models::Endpoint::create_or_fetch(database_connection, &EndpointName::from(record.endpoint))?;
+            let image = models::Image::create_or_fetch(database_connection, &ImageName::from(record.image))?;
+            let package = models::Package::create_or_fetch_by_name(database_connection, &record.package.name, &record.package.version)?;
+            let started = record.started.as_deref().map(parse_time).transpose()?;
+            let finished = record.finished.as_deref().map(parse_time).transpose()?;
+            let job = models::Job::create(
+                database_connection,
+                &record.uuid,
+                submit,
+                &endpoint,
+                &package,
+                &image,
+                &ContainerHash::from(record.container_hash),
+                &Script::from(record.script),
+                &record.log,
+                started.as_ref(),
+                finished.as_ref(),
+            )
+            .with_context(|| anyhow!("Importing job {}", record.uuid))?;
+            let job = match record.image_digest.as_deref() {
+                Some(digest) => job.set_image_digest(database_connection, digest)?,
+                None => job,
+            };
+            let job = match record.buildinfo.as_deref() {
+                Some(buildinfo) => job.set_buildinfo(database_connection, buildinfo)?,
+                None => job,
+            };
+
+            for env in record.env {
+                let envvar = models::EnvVar::create_or_fetch(database_connection, &EnvironmentVariableName::from(env.name.as_str()), &env.value)?;
+                models::JobEnv::create(database_connection, &job, &envvar)?;
+            }
+
+            stats.jobs += 1;
+            jobs.insert(record.uuid, job);
+        }
+
+        let mut artifacts = HashMap::new();
+        for record in export.artifacts {
+            let job = match jobs.get(&record.job) {
+                Some(job) => job,
+                None => continue, // the submit of the job was skipped
+            };
+
+            let path = ArtifactPath::new(PathBuf::from(&record.path))?;
+            let artifact = models::Artifact::create(database_connection, &path, job, record.checksum.as_deref(), record.verified, record.size)
+                .with_context(|| anyhow!("Importing artifact {}", record.path))?;
+
+            stats.artifacts += 1;
+            artifacts.insert(record.id, artifact.id);
+        }
+
+        // Releases are imported in the order of the export, so that a rolled back release is
+        // imported before the release that rolled it back
+        let mut releases = HashMap::new();
+        for record in export.releases {
+            let artifact_id = match artifacts.get(&record.artifact) {
+                Some(id) => *id,
+                None => continue, // the submit of the artifact was skipped
+            };
+
+            let rollback_of = match record.rollback_of {
+                Some(id) => {
+                    let rolled_back = releases.get(&id).copied();
+                    if rolled_back.is_none() {
+                        warn!("Release {} rolled back a release that was not imported, dropping the reference", record.id);
+                    }
+                    rolled_back
+                },
+                None => None,
+            };
+
+            let store = models::ReleaseStore::create(database_connection, &record.store)?;
+            let release_id = diesel::insert_into(schema::releases::table)
+                .values((
+                    schema::releases::artifact_id.eq(artifact_id),
+                    schema::releases::release_date.eq(parse_time(&record.date)?),
+                    schema::releases::release_store_id.eq(store.id),
+                    schema::releases::partial.eq(record.partial),
+                    schema::releases::rollback_of.eq(rollback_of),
+                    schema::releases::reason.eq(record.reason),
+                    schema::releases::signature_path.eq(record.signature_path),
+                    schema::releases::signature_fingerprint.eq(record.signature_fingerprint),
+                ))
+                .returning(schema::releases::id)
+                .get_result::<i32>(database_connection)
+                .with_context(|| anyhow!("Importing release {}", record.id))?;
+
+            stats.releases += 1;
+            releases.insert(record.id, release_id);
+        }
+
+        Ok(stats)
+    })
+}
+
+/// Group the environment variables of submits or jobs by the ID of the submit or job
+fn env_by_id(rows: Vec<(i32, String, String)>) -> HashMap<i32, Vec<EnvRecord>> {
+    let mut map: HashMap<i32, Vec<EnvRecord>> = HashMap::new();
+    for (id, name, value) in rows {
+        map.entry(id).or_default().push(EnvRecord { name, value });
+    }
+    map
+}
+
+fn parse_time(s: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, TIME_FORMAT).with_context(|| anyhow!("Parsing timestamp {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_roundtrip() {
+        let time = NaiveDateTime::parse_from_str("2026-10-15 12:34:56.789", "%Y-%m-%d %H:%M:%S%.f").unwrap();
+        let s = time.format(TIME_FORMAT).to_string();
+        assert_eq!(s, "2026-10-15T12:34:56.789");
+        assert_eq!(parse_time(&s).unwrap(), time);
+        assert_eq!(parse_time("2026-10-15T12:34:56").unwrap().format("%S").to_string(), "56");
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let export = serde_json::from_str::<Export>(indoc::indoc!(r#"
+            {
+                "version": 1,
+                "submits": [],
+                "jobs": [],
+                "artifacts": [
+                    { "id": 3, "job": "8c3f9f6c-6e8a-4c8f-9b35-8b3f9c3f1e01", "path": "a-1.tar", "checksum": null, "verified": null }
+                ],
+                "releases": [
+                    { "id": 7, "artifact": 3, "store": "default", "date": "2026-10-15T12:00:00", "partial": false, "rollback_of": null, "reason": null }
+                ]
+            }
+        "#)).unwrap();
+
+        assert_eq!(export.version, EXPORT_VERSION);
+        assert_eq!(export.artifacts[0].path, "a-1.tar");
+        assert_eq!(export.releases[0].artifact, export.artifacts[0].id);
+        assert!(parse_time(&export.releases[0].date).is_ok());
+    }
+}
@@ -83,11 +83,13 @@ impl<'a> FindArtifacts<'a> {
     pub fn run(self) -> Result<Vec<(FullArtifactPath<'a>, Option<NaiveDateTime>)>> {
         let shebang = Shebang::from(self.config.shebang().clone());
         let script = if self.script_filter {
-            let script = ScriptBuilder::new(&shebang).build(
-                self.package,
-                self.config.available_phases(),
-                *self.config.strict_script_interpolation(),
-            )?;
+            let script = ScriptBuilder::new(&shebang)
+                .apply_patches_before(self.config.apply_patches_before().as_ref())
+                .build(
+                    self.package,
+                    self.config.available_phases(),
+                    *self.config.strict_script_interpolation(),
+                )?;
             Some(script)
         } else {
             None
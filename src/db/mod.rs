@@ -11,6 +11,8 @@
 mod connection;
 pub use connection::*;
 
+pub mod export;
+
 mod find_artifacts;
 pub use find_artifacts::FindArtifacts;
 
@@ -30,6 +30,17 @@ pub struct Artifact {
     pub id: i32,
     pub path: String,
     pub job_id: i32,
+
+    /// The SHA-256 checksum of the artifact, computed after copying it from the container
+    pub checksum: Option<String>,
+
+    /// Whether the checksum matched the checksum computed in the container
+    ///
+    /// `None` if the container did not report a checksum for the artifact.
+    pub verified: Option<bool>,
+
+    /// The size of the artifact in bytes, not known for old artifacts
+    pub size: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -37,6 +48,9 @@ pub struct Artifact {
 struct NewArtifact<'a> {
     pub path: &'a str,
     pub job_id: i32,
+    pub checksum: Option<&'a str>,
+    pub verified: Option<bool>,
+    pub size: Option<i64>,
 }
 
 impl Artifact {
@@ -70,6 +84,9 @@ impl Artifact {
         database_connection: &PgConnection,
         art_path: &ArtifactPath,
         job: &Job,
+        art_checksum: Option<&str>,
+        art_verified: Option<bool>,
+        art_size: Option<i64>,
     ) -> Result<Artifact> {
         let path_str = art_path
             .to_str()
@@ -78,6 +95,9 @@ impl Artifact {
         let new_art = NewArtifact {
             path: path_str,
             job_id: job.id,
+            checksum: art_checksum,
+            verified: art_verified,
+            size: art_size,
         };
 
         database_connection.transaction::<_, Error, _>(|| {
@@ -0,0 +1,187 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The build queue that is shared by all butido instances
+//!
+//! Builds are enqueued with their arguments and claimed by the workers with
+//! `SELECT ... FOR UPDATE SKIP LOCKED`, so that each build is run by exactly one worker. Workers
+//! update the heartbeat of the builds they run, builds whose heartbeat is too old (because the
+//! worker died) are claimed again by other workers. Workers only claim the builds that were
+//! enqueued at the commit their repository is at.
+
+use std::time::Duration;
+
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::schema::build_queue;
+use crate::schema::build_queue::*;
+
+/// The build waits for a worker
+pub const QUEUE_STATE_QUEUED: &str = "queued";
+
+/// The build is run by a worker
+pub const QUEUE_STATE_RUNNING: &str = "running";
+
+/// The build succeeded
+pub const QUEUE_STATE_DONE: &str = "done";
+
+/// The build failed
+pub const QUEUE_STATE_FAILED: &str = "failed";
+
+#[derive(Clone, Debug, Eq, PartialEq, Identifiable, Queryable)]
+#[table_name = "build_queue"]
+pub struct QueuedBuild {
+    pub id: i32,
+    pub created: NaiveDateTime,
+    pub package_name: String,
+    pub package_version: Option<String>,
+
+    /// The arguments of the "build" subcommand
+    pub args: Vec<String>,
+
+    /// The commit of the repository the build was enqueued at
+    pub repo_hash: String,
+    pub state: String,
+
+    /// The worker that runs (or ran) the build
+    pub claimed_by: Option<String>,
+    pub heartbeat: Option<NaiveDateTime>,
+    pub finished: Option<NaiveDateTime>,
+    pub error: Option<String>,
+
+    /// The content of the manifest of the build, if any
+    pub manifest: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "build_queue"]
+struct NewQueuedBuild<'a> {
+    pub package_name: &'a str,
+    pub package_version: Option<&'a str>,
+    pub args: &'a [String],
+    pub repo_hash: &'a str,
+    pub manifest: Option<&'a str>,
+}
+
+impl QueuedBuild {
+    pub fn enqueue(
+        database_connection: &PgConnection,
+        name: &str,
+        version: Option<&str>,
+        build_args: &[String],
+        manifest_content: Option<&str>,
+        hash: &str,
+    ) -> Result<QueuedBuild> {
+        let new_build = NewQueuedBuild {
+            package_name: name,
+            package_version: version,
+            args: build_args,
+            repo_hash: hash,
+            manifest: manifest_content,
+        };
+
+        diesel::insert_into(build_queue::table)
+            .values(&new_build)
+            .get_result::<QueuedBuild>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Claim the oldest build that was enqueued at the commit `head_hash` and waits for a worker,
+    /// or whose worker did not send a heartbeat for `stale_after`
+    ///
+    /// Builds that are locked by another worker that claims them at the same time are skipped.
+    pub fn claim(database_connection: &PgConnection, worker: &str, stale_after: Duration, head_hash: &str) -> Result<Option<QueuedBuild>> {
+        let stale = diesel::dsl::sql::<diesel::sql_types::Bool>(&format!(
+            "heartbeat < NOW() - INTERVAL '{} seconds'",
+            stale_after.as_secs()
+        ));
+
+        database_connection.transaction::<_, Error, _>(|| {
+            let candidate = dsl::build_queue
+                .filter(state.eq(QUEUE_STATE_QUEUED).or(state.eq(QUEUE_STATE_RUNNING).and(stale)))
+                .filter(repo_hash.eq(head_hash))
+                .order_by(created.asc())
+                .for_update()
+                .skip_locked()
+                .first::<QueuedBuild>(database_connection)
+                .optional()?;
+
+            let candidate = match candidate {
+                Some(candidate) => candidate,
+                None => return Ok(None),
+            };
+
+            diesel::update(&candidate)
+                .set((
+                    state.eq(QUEUE_STATE_RUNNING),
+                    claimed_by.eq(worker),
+                    heartbeat.eq(diesel::dsl::now),
+                ))
+                .get_result::<QueuedBuild>(database_connection)
+                .map(Some)
+                .map_err(Error::from)
+        })
+    }
+
+    /// Tell the other workers that the build is still running
+    ///
+    /// Fails if the build was claimed by another worker in the meantime.
+    pub fn heartbeat(&self, database_connection: &PgConnection, worker: &str) -> Result<()> {
+        let n = diesel::update(self)
+            .filter(claimed_by.eq(worker))
+            .set(heartbeat.eq(diesel::dsl::now))
+            .execute(database_connection)?;
+
+        if n == 0 {
+            Err(anyhow::anyhow!("Build {} was claimed by another worker", self.id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Put the build back into the queue, for another worker
+    pub fn release(&self, database_connection: &PgConnection, worker: &str) -> Result<()> {
+        diesel::update(self)
+            .filter(claimed_by.eq(worker))
+            .set((state.eq(QUEUE_STATE_QUEUED), claimed_by.eq(None::<&str>), heartbeat.eq(None::<NaiveDateTime>)))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Record the result of the build
+    ///
+    /// Nothing is recorded if the build was claimed by another worker in the meantime.
+    pub fn finish(&self, database_connection: &PgConnection, worker: &str, result: &Result<()>) -> Result<()> {
+        let (new_state, new_error) = match result {
+            Ok(()) => (QUEUE_STATE_DONE, None),
+            Err(e) => (QUEUE_STATE_FAILED, Some(format!("{e:#}"))),
+        };
+
+        diesel::update(self)
+            .filter(claimed_by.eq(worker))
+            .set((state.eq(new_state), finished.eq(diesel::dsl::now), error.eq(new_error)))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// All builds in the queue, oldest first
+    pub fn all(database_connection: &PgConnection) -> Result<Vec<QueuedBuild>> {
+        dsl::build_queue
+            .order_by(created.asc())
+            .load::<QueuedBuild>(database_connection)
+            .map_err(Error::from)
+    }
+}
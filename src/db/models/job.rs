@@ -11,6 +11,7 @@
 use anyhow::Error;
 use anyhow::Context;
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::PgConnection;
 use tracing::trace;
@@ -37,6 +38,18 @@ pub struct Job {
     pub script_text: String,
     pub log_text: String,
     pub uuid: ::uuid::Uuid,
+
+    /// When the container of the job was started, not known for old jobs
+    pub started: Option<NaiveDateTime>,
+
+    /// When the container of the job finished, not known for old jobs
+    pub finished: Option<NaiveDateTime>,
+
+    /// The digest of the image the job ran on, not known for old jobs
+    pub image_digest: Option<String>,
+
+    /// The buildinfo of the job (see `crate::buildinfo`), not known for old and failed jobs
+    pub buildinfo: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -50,6 +63,8 @@ struct NewJob<'a> {
     pub script_text: String,
     pub log_text: String,
     pub uuid: &'a ::uuid::Uuid,
+    pub started: Option<&'a NaiveDateTime>,
+    pub finished: Option<&'a NaiveDateTime>,
 }
 
 impl Job {
@@ -64,6 +79,8 @@ impl Job {
         container: &ContainerHash,
         script: &Script,
         log: &str,
+        job_started: Option<&NaiveDateTime>,
+        job_finished: Option<&NaiveDateTime>,
     ) -> Result<Job> {
         let new_job = NewJob {
             uuid: job_uuid,
@@ -74,6 +91,8 @@ impl Job {
             container_hash: container.as_ref(),
             script_text: script.as_ref().replace('\0', ""),
             log_text: log.replace('\0', ""),
+            started: job_started,
+            finished: job_finished,
         };
 
         trace!("Creating Job in database: {:?}", new_job);
@@ -96,6 +115,20 @@ impl Job {
         })
     }
 
+    pub fn set_image_digest(&self, database_connection: &PgConnection, digest: &str) -> Result<Job> {
+        diesel::update(self)
+            .set(image_digest.eq(digest))
+            .get_result::<Job>(database_connection)
+            .map_err(Error::from)
+    }
+
+    pub fn set_buildinfo(&self, database_connection: &PgConnection, text: &str) -> Result<Job> {
+        diesel::update(self)
+            .set(buildinfo.eq(text))
+            .get_result::<Job>(database_connection)
+            .map_err(Error::from)
+    }
+
     pub fn env(&self, database_connection: &PgConnection) -> Result<Vec<crate::db::models::EnvVar>> {
         use crate::schema;
 
@@ -0,0 +1,86 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Markers from the log of a job, i.e. the metrics and warnings reported by the script
+
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Job;
+use crate::log::LogItem;
+use crate::schema::job_markers;
+
+/// The kind of a metric marker
+pub const MARKER_KIND_METRIC: &str = "metric";
+
+/// The kind of a warning marker
+pub const MARKER_KIND_WARNING: &str = "warning";
+
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Job)]
+#[table_name = "job_markers"]
+pub struct JobMarker {
+    pub id: i32,
+    pub job_id: i32,
+    pub kind: String,
+
+    /// The key of a metric, `None` for warnings
+    pub name: Option<String>,
+    pub value: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "job_markers"]
+struct NewJobMarker<'a> {
+    pub job_id: i32,
+    pub kind: &'a str,
+    pub name: Option<&'a str>,
+    pub value: &'a str,
+}
+
+impl JobMarker {
+    /// Record the markers of a job
+    ///
+    /// Log items that are not markers are ignored.
+    pub fn create_all(database_connection: &PgConnection, job: &Job, items: &[LogItem]) -> Result<()> {
+        let new_markers = items
+            .iter()
+            .filter_map(|item| match item {
+                LogItem::Metric(key, value) => Some((MARKER_KIND_METRIC, Some(key.as_str()), value.as_str())),
+                LogItem::Warning(message) => Some((MARKER_KIND_WARNING, None, message.as_str())),
+                _ => None,
+            })
+            .map(|(kind, name, value)| NewJobMarker {
+                job_id: job.id,
+                kind,
+                name,
+                value,
+            })
+            .collect::<Vec<_>>();
+
+        if new_markers.is_empty() {
+            return Ok(())
+        }
+
+        diesel::insert_into(job_markers::table)
+            .values(&new_markers)
+            .execute(database_connection)?;
+        Ok(())
+    }
+
+    pub fn is_metric(&self) -> bool {
+        self.kind == MARKER_KIND_METRIC
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.kind == MARKER_KIND_WARNING
+    }
+}
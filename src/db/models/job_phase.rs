@@ -0,0 +1,85 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::ops::Range;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Job;
+use crate::log::PhaseTime;
+use crate::schema::job_phases;
+
+/// The time span of a phase of a job
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Job)]
+#[table_name = "job_phases"]
+pub struct JobPhase {
+    pub id: i32,
+    pub job_id: i32,
+    pub name: String,
+    pub started: NaiveDateTime,
+    pub finished: NaiveDateTime,
+    pub first_line: Option<i32>,
+    pub line_count: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "job_phases"]
+struct NewJobPhase<'a> {
+    pub job_id: i32,
+    pub name: &'a str,
+    pub started: NaiveDateTime,
+    pub finished: NaiveDateTime,
+    pub first_line: Option<i32>,
+    pub line_count: Option<i32>,
+}
+
+impl JobPhase {
+    pub fn create_all(database_connection: &PgConnection, job: &Job, phases: &[PhaseTime]) -> Result<()> {
+        if phases.is_empty() {
+            return Ok(())
+        }
+
+        let new_phases = phases
+            .iter()
+            .map(|phase| NewJobPhase {
+                job_id: job.id,
+                name: &phase.name,
+                started: phase.started.naive_utc(),
+                finished: phase.finished.naive_utc(),
+                first_line: phase.lines.as_ref().map(|lines| lines.start as i32),
+                line_count: phase.lines.as_ref().map(|lines| lines.len() as i32),
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(job_phases::table)
+            .values(&new_phases)
+            .execute(database_connection)?;
+        Ok(())
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.finished - self.started
+    }
+
+    /// The lines of the phase in the log of the job, as indices of the lines of the log text
+    ///
+    /// `None` for phases that were cut out of a truncated log and phases of jobs that were run
+    /// before the lines of the phases were recorded.
+    pub fn lines(&self) -> Option<Range<usize>> {
+        match (self.first_line, self.line_count) {
+            (Some(first), Some(count)) => Some(first as usize..(first + count) as usize),
+            _ => None,
+        }
+    }
+}
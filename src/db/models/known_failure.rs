@@ -0,0 +1,163 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Known failures, i.e. failed jobs that are expected to fail
+//!
+//! A known failure either flags one specific job, or all jobs of a package, optionally only of a
+//! specific version and/or image. Failed jobs that are flagged as known failures are shown as such
+//! in the summaries and statistics, so that they can be told apart from regressions.
+
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::schema::known_failures;
+use crate::schema::known_failures::*;
+
+#[derive(Clone, Debug, Eq, PartialEq, Identifiable, Queryable)]
+#[table_name = "known_failures"]
+pub struct KnownFailure {
+    pub id: i32,
+    pub created: NaiveDateTime,
+
+    /// The job that is a known failure, if a specific job is flagged
+    pub job_id: Option<i32>,
+
+    /// The package whose jobs are known failures, if no specific job is flagged
+    pub package_name: Option<String>,
+
+    /// Only flag jobs of this version of the package, all versions if `None`
+    pub package_version: Option<String>,
+
+    /// Only flag jobs on this image, all images if `None`
+    pub image_name: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "known_failures"]
+struct NewKnownFailure<'a> {
+    pub job_id: Option<i32>,
+    pub package_name: Option<&'a str>,
+    pub package_version: Option<&'a str>,
+    pub image_name: Option<&'a str>,
+    pub reason: &'a str,
+}
+
+impl KnownFailure {
+    /// Flag a specific job as known failure
+    pub fn create_for_job(database_connection: &PgConnection, job: &crate::db::models::Job, why: &str) -> Result<KnownFailure> {
+        let new_failure = NewKnownFailure {
+            job_id: Some(job.id),
+            package_name: None,
+            package_version: None,
+            image_name: None,
+            reason: why,
+        };
+
+        diesel::insert_into(known_failures::table)
+            .values(&new_failure)
+            .get_result::<KnownFailure>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Flag the jobs of a package (optionally only of a version and/or an image) as known failures
+    pub fn create_for_package(
+        database_connection: &PgConnection,
+        name: &str,
+        version: Option<&str>,
+        image: Option<&str>,
+        why: &str,
+    ) -> Result<KnownFailure> {
+        let new_failure = NewKnownFailure {
+            job_id: None,
+            package_name: Some(name),
+            package_version: version,
+            image_name: image,
+            reason: why,
+        };
+
+        diesel::insert_into(known_failures::table)
+            .values(&new_failure)
+            .get_result::<KnownFailure>(database_connection)
+            .map_err(Error::from)
+    }
+
+    pub fn all(database_connection: &PgConnection) -> Result<Vec<KnownFailure>> {
+        known_failures::table
+            .order_by(id.asc())
+            .load::<KnownFailure>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Remove a known failure, returns whether it existed
+    pub fn delete(database_connection: &PgConnection, failure_id: i32) -> Result<bool> {
+        diesel::delete(known_failures::table.filter(id.eq(failure_id)))
+            .execute(database_connection)
+            .map(|n| n > 0)
+            .map_err(Error::from)
+    }
+
+    /// Check whether a job (given by its ID, package and image) is flagged by this known failure
+    pub fn matches(&self, job: i32, package: &str, version: &str, image: &str) -> bool {
+        match self.job_id {
+            Some(flagged_job) => flagged_job == job,
+            None => {
+                self.package_name.as_deref() == Some(package)
+                    && self.package_version.as_deref().map(|v| v == version).unwrap_or(true)
+                    && self.image_name.as_deref().map(|i| i == image).unwrap_or(true)
+            }
+        }
+    }
+
+    /// Check whether a job is flagged by any of the known failures
+    pub fn any_matches(failures: &[KnownFailure], job: i32, package: &str, version: &str, image: &str) -> bool {
+        failures.iter().any(|f| f.matches(job, package, version, image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(job: Option<i32>, package: Option<&str>, version: Option<&str>, image: Option<&str>) -> KnownFailure {
+        KnownFailure {
+            id: 1,
+            created: NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+            job_id: job,
+            package_name: package.map(String::from),
+            package_version: version.map(String::from),
+            image_name: image.map(String::from),
+            reason: String::from("test"),
+        }
+    }
+
+    #[test]
+    fn test_matches_job() {
+        let f = failure(Some(5), None, None, None);
+        assert!(f.matches(5, "a", "1", "debian:bullseye"));
+        assert!(!f.matches(6, "a", "1", "debian:bullseye"));
+    }
+
+    #[test]
+    fn test_matches_package() {
+        let f = failure(None, Some("a"), None, None);
+        assert!(f.matches(5, "a", "1", "debian:bullseye"));
+        assert!(f.matches(6, "a", "2", "debian:buster"));
+        assert!(!f.matches(5, "b", "1", "debian:bullseye"));
+
+        let f = failure(None, Some("a"), Some("1"), Some("debian:bullseye"));
+        assert!(f.matches(5, "a", "1", "debian:bullseye"));
+        assert!(!f.matches(5, "a", "2", "debian:bullseye"));
+        assert!(!f.matches(5, "a", "1", "debian:buster"));
+    }
+}
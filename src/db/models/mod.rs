@@ -11,6 +11,9 @@
 mod artifact;
 pub use artifact::*;
 
+mod build_queue;
+pub use build_queue::*;
+
 mod endpoint;
 pub use endpoint::*;
 
@@ -26,17 +29,32 @@ pub use job::*;
 mod job_env;
 pub use job_env::*;
 
+mod job_marker;
+pub use job_marker::*;
+
+mod job_phase;
+pub use job_phase::*;
+
 mod githash;
 pub use githash::*;
 
+mod known_failure;
+pub use known_failure::*;
+
 mod package;
 pub use package::*;
 
 mod releases;
 pub use releases::*;
 
+mod release_proposal;
+pub use release_proposal::*;
+
 mod release_store;
 pub use release_store::*;
 
+mod release_sync;
+pub use release_sync::*;
+
 mod submit;
 pub use submit::*;
@@ -36,10 +36,18 @@ impl Package {
     pub fn create_or_fetch(
         database_connection: &PgConnection,
         p: &crate::package::Package,
+    ) -> Result<Package> {
+        Self::create_or_fetch_by_name(database_connection, p.name().deref(), p.version().deref())
+    }
+
+    pub fn create_or_fetch_by_name(
+        database_connection: &PgConnection,
+        p_name: &str,
+        p_vers: &str,
     ) -> Result<Package> {
         let new_package = NewPackage {
-            name: p.name().deref(),
-            version: p.version().deref(),
+            name: p_name,
+            version: p_vers,
         };
 
         database_connection.transaction::<_, Error, _>(|| {
@@ -49,12 +57,7 @@ impl Package {
                 .execute(database_connection)?;
 
             dsl::packages
-                .filter({
-                    let p_name = p.name().deref();
-                    let p_vers = p.version().deref();
-
-                    name.eq(p_name).and(version.eq(p_vers))
-                })
+                .filter(name.eq(p_name).and(version.eq(p_vers)))
                 .first::<Package>(database_connection)
                 .map_err(Error::from)
         })
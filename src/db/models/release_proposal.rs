@@ -0,0 +1,179 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Proposed releases, which are released when another user approves them
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
+use diesel::query_dsl::LoadQuery;
+use diesel::PgConnection;
+
+use crate::db::models::Submit;
+use crate::schema::release_proposals;
+use crate::schema::release_proposals::*;
+
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Submit)]
+pub struct ReleaseProposal {
+    pub id: i32,
+    pub submit_id: i32,
+    pub release_store_name: String,
+
+    /// Only release the artifacts of this package, all packages if `None`
+    pub package_name: Option<String>,
+
+    /// Only release the artifacts of the versions that match this pattern (SQL LIKE)
+    pub package_version: Option<String>,
+    pub proposed_by: String,
+    pub proposed: NaiveDateTime,
+    pub approved_by: Option<String>,
+    pub approved: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "release_proposals"]
+struct NewReleaseProposal<'a> {
+    pub submit_id: i32,
+    pub release_store_name: &'a str,
+    pub package_name: Option<&'a str>,
+    pub package_version: Option<&'a str>,
+    pub proposed_by: &'a str,
+}
+
+impl ReleaseProposal {
+    pub fn create(
+        database_connection: &PgConnection,
+        submit: &Submit,
+        store: &str,
+        pname: Option<&str>,
+        pvers: Option<&str>,
+        user: &str,
+    ) -> Result<ReleaseProposal> {
+        let new_proposal = NewReleaseProposal {
+            submit_id: submit.id,
+            release_store_name: store,
+            package_name: pname,
+            package_version: pvers,
+            proposed_by: user,
+        };
+
+        diesel::insert_into(release_proposals::table)
+            .values(&new_proposal)
+            .get_result::<ReleaseProposal>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Record the approval of the proposal `proposal_id` by `user`
+    ///
+    /// The proposal is only approved if it is not approved yet and was not proposed by `user`.
+    /// Checking and approving is one statement, so that a proposal can only be approved once, even
+    /// if it is approved by several users at the same time. Returns `None` if the proposal was not
+    /// approved.
+    pub fn approve(
+        database_connection: &PgConnection,
+        proposal_id: i32,
+        user: &str,
+        date: &NaiveDateTime,
+    ) -> Result<Option<ReleaseProposal>> {
+        Self::approve_statement(proposal_id, user, date)
+            .get_result::<ReleaseProposal>(database_connection)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    fn approve_statement<'a>(
+        proposal_id: i32,
+        user: &'a str,
+        date: &'a NaiveDateTime,
+    ) -> impl LoadQuery<PgConnection, ReleaseProposal> + QueryFragment<Pg> + 'a {
+        diesel::update(release_proposals::table)
+            .filter(id.eq(proposal_id))
+            .filter(approved_by.is_null())
+            .filter(proposed_by.ne(user))
+            .set((approved_by.eq(user), approved.eq(date)))
+    }
+
+    /// Why `user` cannot approve this proposal, `None` if they can
+    pub fn approval_refused(&self, user: &str) -> Option<Error> {
+        if let Some(approver) = self.approved_by.as_ref() {
+            Some(anyhow!("Release proposal {} was already approved by {}", self.id, approver))
+        } else if self.proposed_by == user {
+            Some(anyhow!(
+                "Release proposal {} was proposed by {}, it has to be approved by another database user (every user needs an own database user)",
+                self.id,
+                user
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Undo the approval by `user`, so that the proposal can be approved again
+    pub fn revoke_approval(&self, database_connection: &PgConnection, user: &str) -> Result<()> {
+        diesel::update(self)
+            .filter(approved_by.eq(user))
+            .set((approved_by.eq(None::<String>), approved.eq(None::<NaiveDateTime>)))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap().and_hms_opt(3, 4, 5).unwrap()
+    }
+
+    fn proposal(proposer: &str, approver: Option<&str>) -> ReleaseProposal {
+        ReleaseProposal {
+            id: 1,
+            submit_id: 1,
+            release_store_name: String::from("stable"),
+            package_name: None,
+            package_version: None,
+            proposed_by: String::from(proposer),
+            proposed: date(),
+            approved_by: approver.map(String::from),
+            approved: approver.map(|_| date()),
+        }
+    }
+
+    #[test]
+    fn test_approve_statement() {
+        let date = date();
+        let statement = ReleaseProposal::approve_statement(1, "alice", &date);
+        let sql = diesel::debug_query::<Pg, _>(&statement).to_string();
+
+        // the proposal is only approved if it is not approved yet and by another user
+        assert!(sql.contains(r#""release_proposals"."approved_by" IS NULL"#), "{sql}");
+        assert!(sql.contains(r#""release_proposals"."proposed_by" != $"#), "{sql}");
+        assert!(sql.ends_with(r#"binds: ["alice", 2023-01-02T03:04:05, 1, "alice"]"#), "{sql}");
+    }
+
+    #[test]
+    fn test_approval_refused() {
+        assert!(proposal("alice", None).approval_refused("bob").is_none());
+
+        let refused = proposal("alice", None).approval_refused("alice").unwrap();
+        assert!(refused.to_string().contains("approved by another database user"), "{refused}");
+
+        let refused = proposal("alice", Some("carol")).approval_refused("bob").unwrap();
+        assert_eq!(refused.to_string(), "Release proposal 1 was already approved by carol");
+        assert!(proposal("alice", Some("bob")).approval_refused("alice").is_some());
+    }
+}
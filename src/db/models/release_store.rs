@@ -19,7 +19,7 @@ use diesel::RunQueryDsl;
 use crate::schema::release_stores;
 use crate::schema;
 
-#[derive(Debug, Identifiable, Queryable)]
+#[derive(Clone, Debug, Identifiable, Queryable)]
 #[table_name = "release_stores"]
 pub struct ReleaseStore {
     pub id: i32,
@@ -0,0 +1,54 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Release;
+use crate::schema::release_syncs;
+
+/// A release that was synced to a remote storage (a "release sync target" of the configuration)
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Release)]
+pub struct ReleaseSync {
+    pub id: i32,
+    pub release_id: i32,
+
+    /// The name of the sync target
+    pub target: String,
+    pub synced: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "release_syncs"]
+struct NewReleaseSync<'a> {
+    pub release_id: i32,
+    pub target: &'a str,
+}
+
+impl ReleaseSync {
+    /// Record that a release was synced to a target, if it is not recorded already
+    pub fn create(database_connection: &PgConnection, release: &Release, target: &str) -> Result<()> {
+        let new_sync = NewReleaseSync {
+            release_id: release.id,
+            target,
+        };
+
+        diesel::insert_into(release_syncs::table)
+            .values(&new_sync)
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
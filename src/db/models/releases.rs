@@ -16,6 +16,8 @@ use diesel::PgConnection;
 
 use crate::db::models::Artifact;
 use crate::db::models::ReleaseStore;
+use crate::db::models::Submit;
+use crate::schema;
 use crate::schema::releases;
 use crate::schema::releases::*;
 
@@ -27,6 +29,21 @@ pub struct Release {
     pub artifact_id: i32,
     pub release_date: NaiveDateTime,
     pub release_store_id: i32,
+
+    /// Whether the artifact was released before its submit finished
+    pub partial: bool,
+
+    /// The release that was rolled back by this release, if any
+    pub rollback_of: Option<i32>,
+
+    /// The reason for the rollback
+    pub reason: Option<String>,
+
+    /// The path of the detached signature of the released file, relative to the release store
+    pub signature_path: Option<String>,
+
+    /// The fingerprint of the key the released file was signed with, if known
+    pub signature_fingerprint: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -35,6 +52,9 @@ struct NewRelease<'a> {
     pub artifact_id: i32,
     pub release_date: &'a NaiveDateTime,
     pub release_store_id: i32,
+    pub partial: bool,
+    pub rollback_of: Option<i32>,
+    pub reason: Option<&'a str>,
 }
 
 impl Release {
@@ -44,12 +64,82 @@ impl Release {
         date: &'a NaiveDateTime,
         store: &'a ReleaseStore,
     ) -> Result<Release> {
-        let new_rel = NewRelease {
+        Self::insert(database_connection, art, NewRelease {
+            artifact_id: art.id,
+            release_date: date,
+            release_store_id: store.id,
+            partial: false,
+            rollback_of: None,
+            reason: None,
+        })
+    }
+
+    /// Create a release of an artifact whose submit did not finish yet
+    pub fn create_partial<'a>(
+        database_connection: &PgConnection,
+        art: &Artifact,
+        date: &'a NaiveDateTime,
+        store: &'a ReleaseStore,
+    ) -> Result<Release> {
+        Self::insert(database_connection, art, NewRelease {
             artifact_id: art.id,
             release_date: date,
             release_store_id: store.id,
-        };
+            partial: true,
+            rollback_of: None,
+            reason: None,
+        })
+    }
 
+    /// Create a release of an artifact that replaces the release `rolled_back`
+    pub fn create_rollback<'a>(
+        database_connection: &PgConnection,
+        art: &Artifact,
+        date: &'a NaiveDateTime,
+        rolled_back: &Release,
+        rollback_reason: &'a str,
+    ) -> Result<Release> {
+        Self::insert(database_connection, art, NewRelease {
+            artifact_id: art.id,
+            release_date: date,
+            release_store_id: rolled_back.release_store_id,
+            partial: false,
+            rollback_of: Some(rolled_back.id),
+            reason: Some(rollback_reason),
+        })
+    }
+
+    /// Mark the partial releases of the artifacts of a submit as complete
+    ///
+    /// Returns the number of releases that were updated.
+    pub fn finish_partial_releases(database_connection: &PgConnection, submit: &Submit) -> Result<usize> {
+        let submit_artifacts = schema::artifacts::table
+            .inner_join(schema::jobs::table)
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .select(schema::artifacts::id);
+
+        diesel::update(releases::table)
+            .filter(partial.eq(true))
+            .filter(artifact_id.eq_any(submit_artifacts))
+            .set(partial.eq(false))
+            .execute(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Record the signature of the released file
+    pub fn set_signature(&self, database_connection: &PgConnection, path: &str, fingerprint: Option<&str>) -> Result<Release> {
+        diesel::update(self)
+            .set((signature_path.eq(path), signature_fingerprint.eq(fingerprint)))
+            .get_result::<Release>(database_connection)
+            .map_err(Error::from)
+    }
+
+    fn insert(
+        database_connection: &PgConnection,
+        art: &Artifact,
+        new_rel: NewRelease<'_>,
+    ) -> Result<Release> {
+        let date = new_rel.release_date;
         database_connection.transaction::<_, Error, _>(|| {
             diesel::insert_into(releases::table)
                 .values(&new_rel)
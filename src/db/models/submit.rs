@@ -32,6 +32,18 @@ pub struct Submit {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub flavor: Option<String>,
+    pub manifest: Option<String>,
+    pub features: Vec<String>,
+
+    /// The version of butido that ran the submit, not known for old submits
+    pub butido_version: Option<String>,
+
+    /// The commit of butido itself that ran the submit, not known for old submits
+    pub butido_git_hash: Option<String>,
+
+    /// The SHA-256 hash of the effective configuration of the submit, not known for old submits
+    pub config_fingerprint: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -42,9 +54,16 @@ struct NewSubmit<'a> {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub flavor: Option<&'a str>,
+    pub manifest: Option<&'a str>,
+    pub features: &'a [String],
+    pub butido_version: Option<&'a str>,
+    pub butido_git_hash: Option<&'a str>,
+    pub config_fingerprint: Option<&'a str>,
 }
 
 impl Submit {
+    #[allow(clippy::too_many_arguments)]
     pub fn create(
         database_connection: &PgConnection,
         submit_datetime: &NaiveDateTime,
@@ -52,6 +71,12 @@ impl Submit {
         requested_image: &Image,
         requested_package: &Package,
         repo_hash: &GitHash,
+        submit_flavor: Option<&str>,
+        submit_features: &[String],
+        submit_manifest: Option<&str>,
+        version: Option<&str>,
+        git_hash: Option<&str>,
+        fingerprint: Option<&str>,
     ) -> Result<Submit> {
         let new_submit = NewSubmit {
             uuid: submit_id,
@@ -59,6 +84,12 @@ impl Submit {
             requested_image_id: requested_image.id,
             requested_package_id: requested_package.id,
             repo_hash_id: repo_hash.id,
+            flavor: submit_flavor,
+            manifest: submit_manifest,
+            features: submit_features,
+            butido_version: version,
+            butido_git_hash: git_hash,
+            config_fingerprint: fingerprint,
         };
 
         database_connection.transaction::<_, Error, _>(|| {
@@ -8,7 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -37,7 +39,9 @@ use crate::filestore::path::ArtifactPath;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
 use crate::log::LogItem;
+use crate::log::TimedLogItem;
 use crate::log::buffer_stream_to_line_stream;
+use crate::package::ImageRequirement;
 use crate::package::Script;
 use crate::util::docker::ContainerHash;
 use crate::util::docker::ImageName;
@@ -228,6 +232,39 @@ impl Endpoint {
             .map(|_| ())
     }
 
+    /// Check whether the image on this endpoint satisfies the image requirement of a package
+    pub async fn check_image_requirement(&self, image: &ImageName, requirement: &ImageRequirement) -> Result<()> {
+        let details = self.docker
+            .images()
+            .get(image.as_ref())
+            .inspect()
+            .await
+            .with_context(|| anyhow!("Inspecting image '{}' on endpoint '{}'", image.as_ref(), self.name))?;
+
+        let repo_digests = details.repo_digests.unwrap_or_default();
+        let labels = details.config.labels.unwrap_or_default();
+        requirement.check(&details.id, &repo_digests, &labels)
+    }
+
+    /// The digest of an image on this endpoint
+    ///
+    /// This is the digest of the image in its registry, if it was pulled from one, and the ID of
+    /// the image otherwise.
+    pub async fn image_digest(&self, image: &ImageName) -> Result<String> {
+        let details = self.docker
+            .images()
+            .get(image.as_ref())
+            .inspect()
+            .await
+            .with_context(|| anyhow!("Inspecting image '{}' on endpoint '{}'", image.as_ref(), self.name))?;
+
+        let repo_digest = details.repo_digests
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|rd| rd.rsplit_once('@').map(|(_, digest)| digest.to_string()));
+        Ok(repo_digest.unwrap_or(details.id))
+    }
+
     pub async fn prepare_container(
         &self,
         job: &RunnableJob,
@@ -597,6 +634,7 @@ impl<'a> PreparedContainer<'a> {
             .patches()
             .iter()
             .map(|patch| async move {
+                let patch = patch.path();
                 let destination = PathBuf::from(crate::consts::PATCH_DIR_PATH).join(patch);
                 trace!("Copying patch {} to container at {}", patch.display(), destination.display());
 
@@ -772,7 +810,7 @@ pub struct StartedContainer<'a> {
 impl<'a> StartedContainer<'a> {
     pub async fn execute_script(
         self,
-        logsink: UnboundedSender<LogItem>,
+        logsink: UnboundedSender<TimedLogItem>,
     ) -> Result<ExecutedContainer<'a>> {
         let exec_opts = ExecContainerOptions::builder()
             .cmd(vec!["/bin/bash", "/script"])
@@ -781,6 +819,9 @@ impl<'a> StartedContainer<'a> {
             .build();
         trace!("Exec options = {:?}", exec_opts);
 
+        // The checksums of the artifacts, as computed in the container
+        let mut checksums = HashMap::new();
+
         trace!("Moving logs to log sink for container {}", self.create_info.id);
         let stream = self.endpoint
             .docker
@@ -795,7 +836,7 @@ impl<'a> StartedContainer<'a> {
                         "['{}':{}] Found log line: {:?}",
                         self.endpoint.name,
                         self.create_info.id,
-                        line
+                        line.as_ref().map(|l| crate::log::lossy_utf8(l))
                     );
                     line.with_context(|| {
                         anyhow!(
@@ -806,13 +847,13 @@ impl<'a> StartedContainer<'a> {
                     })
                     .and_then(|l| {
                         crate::log::parser()
-                            .parse(l.as_bytes())
+                            .parse(&l)
                             .with_context(|| {
                                 anyhow!(
                                     "Parsing log from {}:{}: {:?}",
                                     self.endpoint.name,
                                     self.create_info.id,
-                                    l
+                                    crate::log::lossy_utf8(&l)
                                 )
                             })
                     })
@@ -823,9 +864,13 @@ impl<'a> StartedContainer<'a> {
                             _ => None, // Nothing
                         };
 
+                        if let LogItem::Checksum(ref checksum, ref path) = item {
+                            checksums.insert(PathBuf::from(path), checksum.clone());
+                        }
+
                         trace!("Log item: {}", item.display()?);
                         logsink
-                            .send(item)
+                            .send(TimedLogItem::now(item))
                             .with_context(|| anyhow!("Sending log to log sink"))
                             .map(|_| exited_successfully)
                     })
@@ -863,6 +908,7 @@ impl<'a> StartedContainer<'a> {
                 create_info: self.create_info,
                 script: self.script,
                 exit_info: exited_successfully,
+                checksums,
             }
         })
     }
@@ -873,6 +919,7 @@ pub struct ExecutedContainer<'a> {
     create_info: shiplift::rep::ContainerCreateInfo,
     script: Script,
     exit_info: Option<(bool, Option<String>)>,
+    checksums: HashMap<PathBuf, String>,
 }
 
 impl<'a> ExecutedContainer<'a> {
@@ -910,10 +957,28 @@ impl<'a> ExecutedContainer<'a> {
                     });
 
                 let mut writelock = staging_store.write().await;
-                let artifacts = writelock
+                let paths = writelock
                     .write_files_from_tar_stream(tar_stream)
                     .await
                     .with_context(|| anyhow!("Copying the TAR stream to the staging store"))?;
+                let root = writelock.root_path().clone();
+                drop(writelock);
+
+                // Verify the artifacts against the checksums computed in the container, to detect
+                // artifacts that were corrupted while copying them
+                let mut artifacts = Vec::with_capacity(paths.len());
+                for path in paths {
+                    let full_path = root.join(&path)?
+                        .ok_or_else(|| anyhow!("Artifact not in staging store: {}", path.display()))?;
+                    let checksum = full_path.sha256().await?;
+                    let size = full_path.size().await?;
+                    let verified = self.checksums
+                        .get(AsRef::<Path>::as_ref(&path))
+                        .map(|expected| *expected == checksum);
+                    trace!("Checksum of {}: {} (verified: {:?})", path.display(), checksum, verified);
+                    artifacts.push(ExportedArtifact { path, checksum, verified, size });
+                }
+
                 container
                     .stop(Some(std::time::Duration::new(1, 0)))
                     .await
@@ -931,14 +996,31 @@ impl<'a> ExecutedContainer<'a> {
     }
 }
 
+/// An artifact that was copied from a container to the staging store
+#[derive(Debug)]
+pub struct ExportedArtifact {
+    pub path: ArtifactPath,
+
+    /// The SHA-256 checksum of the artifact in the staging store
+    pub checksum: String,
+
+    /// Whether the checksum matches the checksum computed in the container
+    ///
+    /// `None` if the container did not report a checksum for the artifact.
+    pub verified: Option<bool>,
+
+    /// The size of the artifact in bytes
+    pub size: u64,
+}
+
 #[derive(Debug)]
 pub struct FinalizedContainer {
-    artifacts: Vec<ArtifactPath>,
+    artifacts: Vec<ExportedArtifact>,
     exit_info: Result<()>,
 }
 
 impl FinalizedContainer {
-    pub fn unpack(self) -> (Vec<ArtifactPath>, Result<()>) {
+    pub fn unpack(self) -> (Vec<ExportedArtifact>, Result<()>) {
         (self.artifacts, self.exit_info)
     }
 }
@@ -8,8 +8,10 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
-use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -19,12 +21,17 @@ use colored::Colorize;
 use diesel::PgConnection;
 use indicatif::ProgressBar;
 use itertools::Itertools;
-use tracing::trace;
-use tokio::io::AsyncWriteExt;
+use tracing::{debug, trace, warn};
+use tokio::sync::OwnedSemaphorePermit;
 use tokio::sync::RwLock;
+use tokio::sync::Semaphore;
+use tokio::sync::TryAcquireError;
 use tokio::sync::mpsc::UnboundedReceiver;
 use uuid::Uuid;
 
+use crate::buildinfo::BuildInfo;
+use crate::buildinfo::BuildResult;
+use crate::config::EndpointName;
 use crate::db::models as dbmodels;
 use crate::endpoint::Endpoint;
 use crate::endpoint::EndpointHandle;
@@ -34,12 +41,46 @@ use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
+use crate::log::LogArchive;
+use crate::log::LogBuffer;
+use crate::log::LogFile;
+use crate::log::ForwardedJob;
+use crate::log::ForwardedLine;
+use crate::log::LogFiles;
+use crate::log::LogForwarder;
 use crate::log::LogItem;
+use crate::log::LogOptions;
+use crate::log::PhaseTime;
+use crate::log::PhaseTimes;
+use crate::log::TimedLogItem;
+use crate::package::PhaseLog;
+use crate::util::progress::PhaseMarkers;
+use crate::ui::Dashboard;
 
 pub struct EndpointScheduler {
-    log_dir: Option<PathBuf>,
+    log_files: Option<LogFiles>,
+
+    /// Forwards the logs to the configured external log collectors, if any
+    log_forwarder: Option<LogForwarder>,
+
+    /// The archive the raw logs are written to before the jobs are recorded in the database
+    log_archive: Option<LogArchive>,
+
+    /// How the logs of the jobs are stored
+    log_options: LogOptions,
+
     endpoints: Vec<Arc<Endpoint>>,
 
+    /// Limits the number of jobs that run in parallel on all endpoints together, if set
+    job_slots: Option<Arc<Semaphore>>,
+
+    /// The jobs that wait for a free endpoint
+    waiting: Mutex<Vec<WaitingJob>>,
+    next_waiting_id: AtomicU64,
+
+    /// The dashboard that shows the status of the jobs, if enabled
+    dashboard: Option<Arc<Dashboard>>,
+
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     db: Arc<PgConnection>,
@@ -47,19 +88,33 @@ pub struct EndpointScheduler {
 }
 
 impl EndpointScheduler {
+    #[allow(clippy::too_many_arguments)]
     pub async fn setup(
         endpoints: Vec<EndpointConfiguration>,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
         db: Arc<PgConnection>,
         submit: crate::db::models::Submit,
-        log_dir: Option<PathBuf>,
+        log_files: Option<LogFiles>,
+        log_forwarder: Option<LogForwarder>,
+        log_archive: Option<LogArchive>,
+        log_options: LogOptions,
+        max_parallel_jobs: Option<usize>,
+        dashboard: Option<Arc<Dashboard>>,
     ) -> Result<Self> {
         let endpoints = crate::endpoint::util::setup_endpoints(endpoints).await?;
+        let job_slots = max_parallel_jobs.map(|n| Arc::new(Semaphore::new(n)));
 
         Ok(EndpointScheduler {
-            log_dir,
+            log_files,
+            log_forwarder,
+            log_archive,
+            log_options,
             endpoints,
+            job_slots,
+            waiting: Mutex::new(Vec::new()),
+            next_waiting_id: AtomicU64::new(0),
+            dashboard,
             staging_store,
             release_stores,
             db,
@@ -69,16 +124,25 @@ impl EndpointScheduler {
 
     /// Schedule a Job
     ///
+    /// Jobs with a higher `priority` get a free endpoint first.
+    ///
     /// # Warning
     ///
-    /// This function blocks as long as there is no free endpoint available!
-    pub async fn schedule_job(&self, job: RunnableJob, bar: indicatif::ProgressBar) -> Result<JobHandle> {
-        let endpoint = self.select_free_endpoint().await?;
+    /// This function blocks as long as there is no free endpoint available (or the maximum number
+    /// of parallel jobs is reached)!
+    pub async fn schedule_job(&self, job: RunnableJob, bar: indicatif::ProgressBar, priority: i32) -> Result<JobHandle> {
+        let endpoints = self.endpoints_for_job(&job).await?;
+        let (endpoint, job_slot) = self.select_free_endpoint(&endpoints, priority).await?;
 
         Ok(JobHandle {
-            log_dir: self.log_dir.clone(),
+            log_files: self.log_files.clone(),
+            log_forwarder: self.log_forwarder.clone(),
+            log_archive: self.log_archive.clone(),
+            log_options: self.log_options,
             bar,
             endpoint,
+            _job_slot: job_slot,
+            dashboard: self.dashboard.clone(),
             job,
             staging_store: self.staging_store.clone(),
             release_stores: self.release_stores.clone(),
@@ -87,34 +151,158 @@ impl EndpointScheduler {
         })
     }
 
-    async fn select_free_endpoint(&self) -> Result<EndpointHandle> {
+    pub fn endpoints(&self) -> &[Arc<Endpoint>] {
+        &self.endpoints
+    }
+
+    pub fn dashboard(&self) -> Option<&Arc<Dashboard>> {
+        self.dashboard.as_ref()
+    }
+
+    /// Find the endpoints whose image satisfies the image requirement of the package of a job
+    async fn endpoints_for_job(&self, job: &RunnableJob) -> Result<Vec<Arc<Endpoint>>> {
+        let requirement = match job.package().image_requirement() {
+            Some(requirement) => requirement,
+            None => return Ok(self.endpoints.clone()),
+        };
+
+        let mut endpoints = Vec::with_capacity(self.endpoints.len());
+        let mut errors = Vec::new();
+        for ep in self.endpoints.iter() {
+            match ep.check_image_requirement(job.image(), requirement).await {
+                Ok(()) => endpoints.push(ep.clone()),
+                Err(e) => {
+                    debug!("Endpoint {} not considered for job {}: {:#}", ep.name(), job.uuid(), e);
+                    errors.push(format!("{}: {:#}", ep.name(), e));
+                },
+            }
+        }
+
+        if endpoints.is_empty() {
+            return Err(anyhow!(
+                "No endpoint has an image '{}' that satisfies the image requirement of {} {}:\n{}\nUpdate the image on the endpoints or change the image_requirement of the package",
+                job.image().as_ref(),
+                job.package().name(),
+                job.package().version(),
+                errors.join("\n")
+            ))
+        }
+
+        Ok(endpoints)
+    }
+
+    /// Wait for a free endpoint (and a free slot, if the number of parallel jobs is limited)
+    ///
+    /// An endpoint is left to the waiting jobs with a higher priority that can run on it.
+    async fn select_free_endpoint(&self, endpoints: &[Arc<Endpoint>], priority: i32)
+        -> Result<(EndpointHandle, Option<OwnedSemaphorePermit>)>
+    {
+        let waiting = WaitingJob {
+            id: self.next_waiting_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            priority,
+            endpoints: endpoints.iter().map(|ep| ep.name().clone()).collect(),
+        };
+        let _waiting = WaitingGuard::new(&self.waiting, waiting.clone());
+
         loop {
-            let ep = self
-                .endpoints
+            let ep = endpoints
                 .iter()
                 .filter(|ep| { // filter out all running containers where the number of max jobs is reached
                     let r = ep.running_jobs() < ep.num_max_jobs();
                     trace!("Endpoint {} considered for scheduling job: {}", ep.name(), r);
                     r
                 })
+                .filter(|ep| {
+                    let r = !self.waiting.lock().unwrap().iter().any(|other| other.has_precedence_over(&waiting, ep.name()));
+                    trace!("Endpoint {} not needed by jobs with higher priority: {}", ep.name(), r);
+                    r
+                })
                 .sorted_by(|ep1, ep2| {
                     ep1.utilization().partial_cmp(&ep2.utilization()).unwrap_or(std::cmp::Ordering::Equal)
                 })
                 .next();
 
             if let Some(endpoint) = ep {
-                return Ok(EndpointHandle::new(endpoint.clone()));
+                let job_slot = match self.job_slots.as_ref() {
+                    None => Ok(None),
+                    Some(slots) => slots.clone().try_acquire_owned().map(Some),
+                };
+
+                match job_slot {
+                    Ok(job_slot) => return Ok((EndpointHandle::new(endpoint.clone()), job_slot)),
+                    Err(TryAcquireError::Closed) => return Err(anyhow!("Job slots closed")),
+                    Err(TryAcquireError::NoPermits) => trace!("Maximum number of parallel jobs reached, retry..."),
+                }
             } else {
                 trace!("No free endpoint found, retry...");
-                tokio::task::yield_now().await
             }
+            tokio::task::yield_now().await
         }
     }
 }
 
+/// A job that waits for a free endpoint
+#[derive(Clone, Debug)]
+struct WaitingJob {
+    id: u64,
+    priority: i32,
+
+    /// The endpoints the job can run on
+    endpoints: Vec<EndpointName>,
+}
+
+impl WaitingJob {
+    /// Whether this job gets the endpoint `ep` before `other`
+    fn has_precedence_over(&self, other: &WaitingJob, ep: &EndpointName) -> bool {
+        self.id != other.id && self.priority > other.priority && self.endpoints.contains(ep)
+    }
+}
+
+/// Removes a job from the list of waiting jobs when it got an endpoint (or stopped waiting)
+struct WaitingGuard<'a> {
+    waiting: &'a Mutex<Vec<WaitingJob>>,
+    id: u64,
+}
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a Mutex<Vec<WaitingJob>>, job: WaitingJob) -> Self {
+        let id = job.id;
+        waiting.lock().unwrap().push(job);
+        WaitingGuard { waiting, id }
+    }
+}
+
+impl<'a> Drop for WaitingGuard<'a> {
+    fn drop(&mut self) {
+        self.waiting.lock().unwrap().retain(|job| job.id != self.id);
+    }
+}
+
+/// Marks an error of a job run that was caused by the endpoint or the container rather than by
+/// the script of the job
+///
+/// These errors happen before the job is recorded in the database, so running the job again
+/// might succeed.
+#[derive(Debug)]
+pub struct EndpointFailure(String);
+
+impl std::fmt::Display for EndpointFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Running the job on endpoint '{}' failed", self.0)
+    }
+}
+
 pub struct JobHandle {
-    log_dir: Option<PathBuf>,
+    log_files: Option<LogFiles>,
+    log_forwarder: Option<LogForwarder>,
+    log_archive: Option<LogArchive>,
+    log_options: LogOptions,
     endpoint: EndpointHandle,
+
+    /// The slot of the job in the global limit of parallel jobs, released when the job finished
+    _job_slot: Option<OwnedSemaphorePermit>,
+
+    dashboard: Option<Arc<Dashboard>>,
     job: RunnableJob,
     bar: ProgressBar,
     db: Arc<PgConnection>,
@@ -131,7 +319,7 @@ impl std::fmt::Debug for JobHandle {
 
 impl JobHandle {
     pub async fn run(self) -> Result<Result<Vec<ArtifactPath>>> {
-        let (log_sender, log_receiver) = tokio::sync::mpsc::unbounded_channel::<LogItem>();
+        let (log_sender, log_receiver) = tokio::sync::mpsc::unbounded_channel::<TimedLogItem>();
         let endpoint_uri = self.endpoint.uri().clone();
         let endpoint_name = self.endpoint.name().clone();
         let endpoint = dbmodels::Endpoint::create_or_fetch(&self.db, self.endpoint.name())?;
@@ -139,10 +327,24 @@ impl JobHandle {
         let image = dbmodels::Image::create_or_fetch(&self.db, self.job.image())?;
         let envs = self.create_env_in_db()?;
         let job_id = *self.job.uuid();
+        let buildinfo = BuildInfo::new(&self.submit.uuid, &self.job);
+        let started = chrono::offset::Local::now().naive_local();
         trace!("Running on Job {} on Endpoint {}", job_id, self.endpoint.name());
+
+        // The digest is only recorded for the provenance of the artifacts, not knowing it does
+        // not fail the job
+        let image_digest = match self.endpoint.image_digest(self.job.image()).await {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                warn!("Could not get the digest of the image of job {}: {:#}", job_id, e);
+                None
+            },
+        };
+
         let prepared_container = self.endpoint
             .prepare_container(&self.job, self.staging_store.clone(), self.release_stores.clone())
-            .await?;
+            .await
+            .with_context(|| EndpointFailure(endpoint_name.to_string()))?;
         let container_id = prepared_container.create_info().id.clone();
         let running_container = prepared_container
             .start()
@@ -155,24 +357,35 @@ impl JobHandle {
                     &endpoint_uri,
                     &container_id,
                 )
-            })?
+            })
+            .with_context(|| EndpointFailure(endpoint_name.to_string()))?
             .execute_script(log_sender);
 
+        if let Some(dashboard) = self.dashboard.as_ref() {
+            dashboard.set_running(&job_id, endpoint_name.to_string());
+        }
+
         let logres = LogReceiver {
             endpoint_name: endpoint_name.as_ref(),
             container_id_chrs: container_id.chars().take(7).collect(),
             package_name: &package.name,
             package_version: &package.version,
-            log_dir: self.log_dir.as_ref(),
+            log_files: self.log_files.as_ref(),
+            log_forwarder: self.log_forwarder.as_ref(),
+            log_options: self.log_options,
+            submit_uuid: self.submit.uuid,
             job: self.job,
             log_receiver,
             bar: self.bar.clone(),
+            dashboard: self.dashboard.as_deref(),
         }
         .join();
         drop(self.bar);
 
         let (run_container, logres) = tokio::join!(running_container, logres);
-        let log = logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
+        let log = logres
+            .with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))
+            .with_context(|| EndpointFailure(endpoint_name.to_string()))?;
         let run_container = run_container
             .with_context(|| anyhow!("Running container {} failed", container_id))
             .with_context(|| {
@@ -183,7 +396,16 @@ impl JobHandle {
                     &endpoint_uri,
                     &container_id,
                 )
-            })?;
+            })
+            .with_context(|| EndpointFailure(endpoint_name.to_string()))?;
+
+        // The archive is a backup of the database, a failure to write it does not fail the job
+        if let Some(archive) = self.log_archive.as_ref() {
+            match archive.store(&self.submit.uuid, &job_id, &package.name, &package.version, &log.text).await {
+                Ok(path) => trace!("Archived log of job {} to {}", job_id, path.display()),
+                Err(e) => warn!("Archiving the log of job {} failed: {:#}", job_id, e),
+            }
+        }
 
         let job = dbmodels::Job::create(
             &self.db,
@@ -194,11 +416,22 @@ impl JobHandle {
             &image,
             &run_container.container_hash(),
             run_container.script(),
-            &log,
+            &log.text,
+            Some(&started),
+            Some(&chrono::offset::Local::now().naive_local()),
         )
         .context("Recording job that is ready in database")?;
 
         trace!("DB: Job entry for job {} created: {}", job.uuid, job.id);
+        let job = match image_digest.as_deref() {
+            Some(digest) => job.set_image_digest(&self.db, digest)
+                .with_context(|| format!("Recording the image digest of job {}", job.uuid))?,
+            None => job,
+        };
+        dbmodels::JobPhase::create_all(&self.db, &job, &log.phase_times)
+            .with_context(|| format!("Recording the phase times of job {}", job.uuid))?;
+        dbmodels::JobMarker::create_all(&self.db, &job, &log.markers)
+            .with_context(|| format!("Recording the metrics and warnings of job {}", job.uuid))?;
         for env in envs {
             dbmodels::JobEnv::create(&self.db, &job, &env)
                 .with_context(|| format!("Creating Environment Variable mapping for Job: {}", job.uuid))?;
@@ -219,7 +452,7 @@ impl JobHandle {
             })?;
 
         trace!("Found result for job {}: {:?}", job_id, res);
-        let (paths, res) = res.unpack();
+        let (artifacts, res) = res.unpack();
         let res = res
             .with_context(|| anyhow!("Error during running job on '{}'", endpoint_name))
             .with_context(|| {
@@ -243,9 +476,16 @@ impl JobHandle {
         // Have to do it the ugly way here because of borrowing semantics
         let mut r = vec![];
         let staging_read = self.staging_store.read().await;
-        for p in paths.iter() {
+        for artifact in artifacts.iter() {
+            let p = &artifact.path;
+            match artifact.verified {
+                Some(true) => {},
+                Some(false) => warn!("Checksum of artifact {} does not match the checksum in the container", p.display()),
+                None => debug!("No checksum reported for artifact {}, cannot verify it", p.display()),
+            }
+
             trace!("DB: Creating artifact entry for path: {}", p.display());
-            let _ = dbmodels::Artifact::create(&self.db, p, &job)?;
+            let _ = dbmodels::Artifact::create(&self.db, p, &job, Some(&artifact.checksum), artifact.verified, i64::try_from(artifact.size).ok())?;
             r.push({
                 staging_read
                     .get(p)
@@ -253,6 +493,43 @@ impl JobHandle {
                     .clone()
             });
         }
+
+        let corrupted = artifacts.iter()
+            .filter(|artifact| artifact.verified == Some(false))
+            .map(|artifact| artifact.path.display().to_string())
+            .collect::<Vec<_>>();
+        if !corrupted.is_empty() {
+            return Ok(Err({
+                anyhow!("Checksum mismatch after copying artifacts from the container: {}", corrupted.join(", "))
+                    .context(Self::create_job_run_error(
+                        &job.uuid,
+                        &package.name,
+                        &package.version,
+                        &endpoint_uri,
+                        &container_id,
+                    ))
+            }))
+        }
+
+        let buildinfo = buildinfo.render(&BuildResult {
+            endpoint: endpoint_name.as_ref(),
+            image_digest: job.image_digest.as_deref(),
+            container_hash: &job.container_hash,
+            script: &job.script_text,
+            finished: job.finished,
+            artifacts: &artifacts,
+        });
+        job.set_buildinfo(&self.db, &buildinfo)
+            .with_context(|| format!("Recording the buildinfo of job {}", job.uuid))?;
+
+        let artifact_files = r.iter()
+            .map(|artifact| staging_read.root_path().join(artifact).map(|path| path.map(|p| p.joined())))
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>>>()?;
+        crate::buildinfo::write(&buildinfo, &artifact_files)
+            .await
+            .with_context(|| format!("Writing the buildinfo of job {}", job.uuid))?;
+
         Ok(Ok(r))
     }
 
@@ -314,24 +591,68 @@ impl JobHandle {
     }
 }
 
+/// The log of a job, as received by the `LogReceiver`
+struct ReceivedLog {
+    /// The log as it is stored in the database
+    text: String,
+    phase_times: Vec<PhaseTime>,
+
+    /// The metrics and warnings reported by the script, these are kept even if the log is
+    /// truncated
+    markers: Vec<LogItem>,
+}
+
+/// The maximum number of characters of a log line that is shown in the progress bar
+const LIVE_LOG_LINE_MAX_CHARS: usize = 80;
+
+/// The phase markers followed by the name of the phase, for the progress bar message
+fn phase_status(markers: &PhaseMarkers, phase: &str) -> String {
+    let markers = markers.to_string();
+    if markers.is_empty() {
+        phase.to_string()
+    } else {
+        format!("{markers} {phase}")
+    }
+}
+
 struct LogReceiver<'a> {
     endpoint_name: &'a str,
     container_id_chrs: String,
     package_name: &'a str,
     package_version: &'a str,
-    log_dir: Option<&'a PathBuf>,
+    log_files: Option<&'a LogFiles>,
+    log_forwarder: Option<&'a LogForwarder>,
+    log_options: LogOptions,
+    submit_uuid: Uuid,
     job: RunnableJob,
-    log_receiver: UnboundedReceiver<LogItem>,
+    log_receiver: UnboundedReceiver<TimedLogItem>,
     bar: ProgressBar,
+    dashboard: Option<&'a Dashboard>,
 }
 
 impl<'a> LogReceiver<'a> {
-    async fn join(mut self) -> Result<String> {
+    /// Receive the log of the job
+    async fn join(mut self) -> Result<ReceivedLog> {
         let mut success = None;
-        let mut accu = vec![];
+        let mut log = LogBuffer::with_options(self.log_options);
+        let mut phase_times = PhaseTimes::default();
+        let mut reported = Vec::new();
+        let mut current_phase: Option<String> = None;
+        let mut log_mode = PhaseLog::Full;
+        let mut markers = PhaseMarkers::new(self.job.phases().iter().map(|p| p.as_str().to_string()).collect());
+        let forwarding = self.log_forwarder.map(|forwarder| {
+            let job = Arc::new(ForwardedJob {
+                submit: self.submit_uuid,
+                job: *self.job.uuid(),
+                package: self.package_name.to_string(),
+                version: self.package_version.to_string(),
+            });
+            (forwarder, job)
+        });
 
-        // Reserve a reasonable amount of elements.
-        accu.reserve(4096);
+        // Once the script reports its progress itself, the phases are not used for the position
+        // of the bar anymore
+        let mut progress_reported = false;
 
         let mut logfile = self.get_logfile()
             .await
@@ -361,26 +682,78 @@ impl<'a> LogReceiver<'a> {
                 Ok(Some(logitem)) => logitem,
             };
 
-            if let Some(lf) = logfile.as_mut() {
-                lf.write_all(logitem.display()?.to_string().as_bytes())
-                    .await?;
-                lf.write_all(b"\n").await?;
-            }
-
-            match logitem {
-                LogItem::Line(_) => {
-                    // ignore
+            match logitem.item {
+                LogItem::Line(ref bytes) => {
+                    // All lines are forwarded, independent of the log mode of the phase
+                    if let Some((forwarder, job)) = forwarding.as_ref() {
+                        let line = if self.log_options.strip_ansi_escapes {
+                            crate::log::strip_ansi_escapes(bytes)
+                        } else {
+                            bytes.clone()
+                        };
+
+                        forwarder.forward(ForwardedLine {
+                            job: job.clone(),
+                            time: logitem.time.unwrap_or_else(chrono::Utc::now),
+                            phase: current_phase.clone(),
+                            line: crate::log::lossy_utf8(&line).into_owned(),
+                        });
+                    }
+
+                    // Only phases with full log show their output live, the output of phases with
+                    // summary log is only written to the log file and the database
+                    if log_mode == PhaseLog::Full {
+                        let line = crate::log::lossy_utf8(bytes);
+                        let line = line.trim();
+                        if !line.is_empty() {
+                            if let Some(dashboard) = self.dashboard {
+                                dashboard.push_log_line(self.job.uuid(), line.to_string());
+                            }
+
+                            self.bar.set_message(format!(
+                                "[{}/{} {} {} {}]: Phase: {}: {}",
+                                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                                phase_status(&markers, current_phase.as_deref().unwrap_or("-")),
+                                line.chars().take(LIVE_LOG_LINE_MAX_CHARS).collect::<String>()
+                            ));
+                        }
+                    }
+                }
+                LogItem::Checksum(ref checksum, ref path) => {
+                    trace!("Checksum of {} in container: {}", path, checksum);
+                }
+                LogItem::Metric(ref key, ref value) => {
+                    trace!("Metric reported: {} = {}", key, value);
+                    reported.push(LogItem::Metric(key.clone(), value.clone()));
+                }
+                LogItem::Warning(ref message) => {
+                    trace!("Warning reported: {}", message);
+                    reported.push(LogItem::Warning(message.clone()));
+                }
+                LogItem::LogMode(mode) => {
+                    trace!("Setting log mode to {}", mode.as_str());
+                    log_mode = mode;
                 }
                 LogItem::Progress(u) => {
                     trace!("Setting bar to {}", u as u64);
                     self.bar.set_position(u as u64);
+                    progress_reported = true;
                 }
                 LogItem::CurrentPhase(ref phasename) => {
                     trace!("Setting bar phase to {}", phasename);
+                    markers.set_current(phasename);
+                    if !progress_reported {
+                        self.bar.set_position(markers.percent());
+                    }
                     self.bar.set_message(format!(
                         "[{}/{} {} {} {}]: Phase: {}",
-                        self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version, phasename
+                        self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                        phase_status(&markers, phasename)
                     ));
+                    if let Some(dashboard) = self.dashboard {
+                        dashboard.set_phase(self.job.uuid(), phasename.clone());
+                    }
+                    current_phase = Some(phasename.clone());
                 }
                 LogItem::State(Ok(())) => {
                     trace!("Setting bar state to Ok");
@@ -399,18 +772,32 @@ impl<'a> LogReceiver<'a> {
                     success = Some(false);
                 }
             }
-            accu.push(logitem);
+
+            phase_times.push(&logitem);
+
+            // The head of the log is written to the log file right away, the (possibly truncated)
+            // tail when the job finished
+            if let Some(item) = log.push(logitem) {
+                if let Some(lf) = logfile.as_mut() {
+                    lf.write_item(&item.item).await?;
+                }
+            }
         }
 
         trace!("Finishing bar = {:?}", success);
+        if success == Some(true) {
+            markers.set_finished();
+        }
         let finish_msg = match success {
             Some(true) => format!(
-                "[{}/{} {} {} {}]: finished successfully",
-                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version
+                "[{}/{} {} {} {}]: {}finished successfully",
+                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                phase_status(&markers, "")
             ),
             Some(false) => format!(
-                "[{}/{} {} {} {}]: finished with error",
-                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version
+                "[{}/{} {} {} {}]: {}finished with error",
+                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                phase_status(&markers, "")
             ),
             None => format!(
                 "[{}/{} {} {} {}]: finished",
@@ -420,33 +807,41 @@ impl<'a> LogReceiver<'a> {
         self.bar.finish_with_message(finish_msg);
 
         if let Some(mut lf) = logfile {
-            lf.flush().await?;
+            if let Some(marker) = log.truncation_marker() {
+                lf.write_line(marker.as_bytes()).await?;
+            }
+            for item in log.tail() {
+                lf.write_item(&item.item).await?;
+            }
+            lf.finish().await?;
+        }
+
+        if let Some(marker) = log.truncation_marker() {
+            warn!("Log of job {} was truncated: {}", self.job.uuid(), marker);
         }
 
-        Ok({
-            accu.iter()
-                .map(crate::log::LogItem::raw)
-                .collect::<Result<Vec<String>>>()?
-                .join("\n")
+        let text = log.to_text()?;
+        let mut phase_times = phase_times.finish();
+        crate::log::assign_phase_lines(&mut phase_times, crate::log::ParsedLog::from_str(&text)?.into_iter());
+
+        Ok(ReceivedLog {
+            text,
+            phase_times,
+            markers: reported,
         })
     }
 
-    async fn get_logfile(&self) -> Option<Result<tokio::io::BufWriter<tokio::fs::File>>> {
-        if let Some(log_dir) = self.log_dir.as_ref() {
+    async fn get_logfile(&self) -> Option<Result<LogFile>> {
+        if let Some(log_files) = self.log_files {
             Some({
-                let path = log_dir.join(format!(
-                    "{}-{}-{}-{}.log",
-                    self.package_name, self.package_version, self.job.image(), self.job.uuid()
-                ));
-                tokio::fs::OpenOptions::new()
-                    .create(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(&path)
-                    .await
-                    .map(tokio::io::BufWriter::new)
-                    .with_context(|| anyhow!("Opening {}", path.display()))
-                    .map_err(Error::from)
+                log_files.open(
+                    &self.submit_uuid.to_string(),
+                    self.package_name,
+                    self.package_version,
+                    &self.job.uuid().to_string(),
+                    self.job.image().as_ref(),
+                )
+                .await
             })
         } else {
             None
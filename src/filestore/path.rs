@@ -198,6 +198,40 @@ impl<'a> FullArtifactPath<'a> {
             .with_context(|| anyhow!("Reading artifact from path {}", self.0.display()))
             .map_err(Error::from)
     }
+
+    /// Get the size of the artifact in bytes
+    pub async fn size(&self) -> Result<u64> {
+        let path = self.joined();
+        tokio::fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.len())
+            .with_context(|| anyhow!("Getting the size of artifact {}", path.display()))
+    }
+
+    /// Compute the SHA-256 checksum of the artifact, as lowercase hex string
+    pub async fn sha256(&self) -> Result<String> {
+        use sha2::Digest;
+        use tokio::io::AsyncReadExt;
+
+        let path = self.joined();
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .with_context(|| anyhow!("Opening artifact {}", path.display()))?;
+
+        let mut hasher = sha2::Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer)
+                .await
+                .with_context(|| anyhow!("Reading artifact {}", path.display()))?;
+            if n == 0 {
+                break
+            }
+            hasher.update(&buffer[..n]);
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,146 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Running the configured hooks
+//!
+//! The hooks get the details of the event as environment variables (prefixed with "BUTIDO_") and
+//! as JSON object on stdin (with the lowercase names as keys).
+//! Failing hooks are logged, but do not fail the build, except for the hooks that are run before
+//! a submit or release, which can stop it.
+
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+use tracing::warn;
+
+use crate::config::HookConfig;
+use crate::config::HookEvent;
+
+/// Run the hooks for `event`, one after another
+///
+/// `env` are the names (without the "BUTIDO_" prefix) and values of the variables that describe
+/// the event.
+pub async fn run_hooks(hooks: &[HookConfig], event: HookEvent, env: &[(&str, String)]) {
+    for hook in hooks.iter().filter(|hook| hook.event() == event) {
+        if let Err(e) = run_hook(hook, env).await {
+            warn!("Hook {:?} for {} failed: {:?}", hook.command(), event, e);
+        }
+    }
+}
+
+/// Run the hooks for `event`, one after another, and fail with the first failing hook
+///
+/// Used for the events that happen before something is done, so that a hook can prevent it.
+pub async fn run_required_hooks(hooks: &[HookConfig], event: HookEvent, env: &[(&str, String)]) -> Result<()> {
+    for hook in hooks.iter().filter(|hook| hook.event() == event) {
+        run_hook(hook, env)
+            .await
+            .with_context(|| anyhow!("Hook {:?} for {} failed", hook.command(), event))?;
+    }
+    Ok(())
+}
+
+/// The JSON object that is passed to a hook on stdin
+fn hook_input(event: HookEvent, env: &[(&str, String)]) -> serde_json::Value {
+    let mut input = serde_json::Map::new();
+    input.insert(String::from("event"), serde_json::Value::from(event.to_string()));
+    for (name, value) in env {
+        input.insert(name.to_lowercase(), serde_json::Value::from(value.as_str()));
+    }
+    serde_json::Value::Object(input)
+}
+
+async fn run_hook(hook: &HookConfig, env: &[(&str, String)]) -> Result<()> {
+    let (program, args) = hook.command()
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty hook command"))?;
+
+    debug!("Running hook {:?} for {}", hook.command(), hook.event());
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("BUTIDO_EVENT", hook.event().to_string())
+        .envs(env.iter().map(|(name, value)| (format!("BUTIDO_{name}"), value)))
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| anyhow!("Starting {}", program))?;
+
+    let input = hook_input(hook.event(), env).to_string();
+    let mut stdin = child.stdin.take().unwrap(); // safe, stdin is piped
+    if let Err(e) = stdin.write_all(input.as_bytes()).await {
+        // Hooks that only use the environment variables do not have to read stdin
+        debug!("Writing the input of hook {:?} failed: {}", hook.command(), e);
+    }
+    drop(stdin); // close stdin, so that the hook sees the end of the input
+
+    let status = child.wait()
+        .await
+        .with_context(|| anyhow!("Waiting for {}", program))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} exited with {}", program, status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Hooks {
+        hooks: Vec<HookConfig>,
+    }
+
+    #[test]
+    fn test_run_hook() {
+        let hooks = toml::from_str::<Hooks>(indoc::indoc!(r#"
+            [[hooks]]
+            event = "artifact"
+            command = ["sh", "-c", "test \"$BUTIDO_EVENT $BUTIDO_ARTIFACT_PATH\" = \"artifact /a.tar\""]
+
+            [[hooks]]
+            event = "submit_failure"
+            command = ["false"]
+
+            [[hooks]]
+            event = "pre_release"
+            command = ["sh", "-c", "test \"$(cat)\" = '{\"artifact_paths\":\"/a.tar\",\"event\":\"pre_release\"}'"]
+        "#))
+        .unwrap()
+        .hooks;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let env = [("ARTIFACT_PATH", String::from("/a.tar"))];
+        runtime.block_on(async {
+            assert!(run_hook(&hooks[0], &env).await.is_ok());
+            assert!(run_hook(&hooks[0], &[("ARTIFACT_PATH", String::from("/b.tar"))]).await.is_err());
+            assert!(run_hook(&hooks[1], &[]).await.is_err());
+
+            assert!(run_required_hooks(&hooks, HookEvent::PreRelease, &[("ARTIFACT_PATHS", String::from("/a.tar"))]).await.is_ok());
+            assert!(run_required_hooks(&hooks, HookEvent::PreRelease, &[("ARTIFACT_PATHS", String::from("/b.tar"))]).await.is_err());
+            assert!(run_required_hooks(&hooks, HookEvent::PreSubmit, &[]).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_hook_input() {
+        let input = hook_input(HookEvent::PostJob, &[("JOB_UUID", String::from("a")), ("STATUS", String::from("built"))]);
+        assert_eq!(input, serde_json::json!({
+            "event": "post_job",
+            "job_uuid": "a",
+            "status": "built",
+        }));
+    }
+}
@@ -8,6 +8,8 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
+
 use daggy::Dag as DaggyDag;
 use daggy::Walker;
 use getset::Getters;
@@ -16,6 +18,7 @@ use uuid::Uuid;
 use crate::job::Job;
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PackageName;
 use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::util::docker::ImageName;
@@ -71,6 +74,38 @@ impl Dag {
             })
     }
 
+    /// Get the scheduling priority of each job
+    ///
+    /// The priority of a job is the priority of its package (from `overrides` or the package
+    /// definition, 0 if neither is set), raised to the highest priority of the jobs that depend
+    /// on it, because these cannot start before it finished.
+    pub fn priorities(&self, overrides: &HashMap<PackageName, i32>) -> HashMap<Uuid, i32> {
+        let graph = self.dag.graph();
+        let mut priorities = graph
+            .node_indices()
+            .map(|idx| {
+                let package = graph[idx].package();
+                let priority = overrides.get(package.name()).copied().or(*package.priority()).unwrap_or(0);
+                (idx, priority)
+            })
+            .collect::<HashMap<_, _>>();
+
+        // In a topological order, the jobs that depend on a job come before it
+        let order = daggy::petgraph::algo::toposort(graph, None).expect("A DAG has no cycles");
+        for idx in order {
+            let priority = priorities[&idx];
+            for (_, dependency) in self.dag.children(idx).iter(&self.dag) {
+                let dependency_priority = priorities.entry(dependency).or_default();
+                *dependency_priority = (*dependency_priority).max(priority);
+            }
+        }
+
+        priorities
+            .into_iter()
+            .map(|(idx, priority)| (*graph[idx].uuid(), priority))
+            .collect()
+    }
+
 }
 
 #[derive(Debug)]
@@ -79,3 +114,59 @@ pub struct JobDefinition<'a> {
     pub dependencies: Vec<Uuid>,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+    use crate::package::condition::ConditionData;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::repository::Repository;
+
+    #[test]
+    fn test_priorities() {
+        // a -> b -> c, a -> d
+        let mut btree = BTreeMap::new();
+        let mut root = None;
+        for (name, dependencies, priority) in [("a", vec!["b =1", "d =1"], Some(1)), ("b", vec!["c =1"], Some(5)), ("c", vec![], None), ("d", vec![], Some(2))] {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            pack.set_dependencies(Dependencies::with_runtime_dependencies(dependencies.into_iter().map(|d| Dependency::from(d.to_string())).collect()));
+            pack.set_priority(priority);
+            if name == "a" {
+                root = Some(pack.clone());
+            }
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+        let package_dag = crate::package::Dag::for_root_package(root.unwrap(), &repo, None, &condition_data).unwrap();
+        let dag = Dag::from_package_dag(package_dag, Shebang::from(String::from("#!/bin/bash")), ImageName::from(String::from("img")), vec![], vec![]);
+
+        let priority_of = |priorities: &HashMap<Uuid, i32>, name: &str| {
+            dag.iter()
+                .find(|jobdef| *jobdef.job.package().name() == pname(name))
+                .map(|jobdef| priorities[jobdef.job.uuid()])
+                .unwrap()
+        };
+
+        let priorities = dag.priorities(&HashMap::new());
+        assert_eq!(priority_of(&priorities, "a"), 1);
+        assert_eq!(priority_of(&priorities, "b"), 5);
+        assert_eq!(priority_of(&priorities, "c"), 5); // b depends on c
+        assert_eq!(priority_of(&priorities, "d"), 2);
+
+        let priorities = dag.priorities(&HashMap::from([(pname("a"), 10), (pname("b"), 0)]));
+        assert_eq!(priority_of(&priorities, "a"), 10);
+        assert_eq!(priority_of(&priorities, "b"), 10);
+        assert_eq!(priority_of(&priorities, "c"), 10);
+        assert_eq!(priority_of(&priorities, "d"), 10);
+    }
+}
@@ -12,14 +12,17 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use getset::Getters;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 use uuid::Uuid;
 
 use crate::config::Configuration;
+use crate::config::ContainerConfig;
+use crate::config::UndeclaredEnvAction;
 use crate::filestore::ArtifactPath;
 use crate::job::Job;
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PhaseName;
 use crate::package::Script;
 use crate::package::ScriptBuilder;
 use crate::source::SourceCache;
@@ -47,6 +50,10 @@ pub struct RunnableJob {
 
     #[getset(get = "pub")]
     resources: Vec<JobResource>,
+
+    /// The phases of the package that are run, in the order they are run in
+    #[getset(get = "pub")]
+    phases: Vec<PhaseName>,
 }
 
 impl RunnableJob {
@@ -58,41 +65,11 @@ impl RunnableJob {
         git_commit_env: Option<&(EnvironmentVariableName, String)>,
         dependencies: Vec<ArtifactPath>,
     ) -> Result<Self> {
-        if config.containers().check_env_names() {
-            debug!("Checking environment if all variables are allowed!");
-            job.resources()
-                .iter()
-                .filter_map(|r| r.env())
-                .chain({
-                    job.package()
-                        .environment()
-                        .as_ref()
-                        .map(|hm| hm.iter())
-                        .into_iter()
-                        .flatten()
-                })
-                .chain(git_author_env.as_ref().into_iter().map(|(k, v)| (k, v)))
-                .chain(git_commit_env.as_ref().into_iter().map(|(k, v)| (k, v)))
-                .inspect(|(name, _)| debug!("Checking: {}", name))
-                .try_for_each(|(name, _)| {
-                    trace!("{:?} contains? {:?}", config.containers().allowed_env(), name);
-                    if !config.containers().allowed_env().contains(name) {
-                        Err(anyhow!("Environment variable name not allowed: {}", name))
-                    } else {
-                        Ok(())
-                    }
-                })
-                .with_context(|| {
-                    anyhow!(
-                        "Checking allowed variables for package {} {}",
-                        job.package().name(),
-                        job.package().version()
-                    )
-                })
-                .context("Checking allowed variable names")?;
-        } else {
-            debug!("Environment checking disabled");
-        }
+        check_env(
+            job,
+            config.containers(),
+            git_author_env.into_iter().chain(git_commit_env).map(|(name, _)| name),
+        )?;
 
         let resources = dependencies
             .into_iter()
@@ -107,12 +84,20 @@ impl RunnableJob {
             .chain(git_commit_env.into_iter().cloned().map(JobResource::from))
             .collect();
 
+        let phases = job.script_phases()
+            .iter()
+            .filter(|phase| job.package().phases().contains_key(phase))
+            .cloned()
+            .collect();
+
         debug!("Building script now");
-        let script = ScriptBuilder::new(job.script_shebang()).build(
-            job.package(),
-            job.script_phases(),
-            *config.strict_script_interpolation(),
-        )?;
+        let script = ScriptBuilder::new(job.script_shebang())
+            .apply_patches_before(config.apply_patches_before().as_ref())
+            .build(
+                job.package(),
+                job.script_phases(),
+                *config.strict_script_interpolation(),
+            )?;
 
         Ok(RunnableJob {
             uuid: *job.uuid(),
@@ -122,6 +107,7 @@ impl RunnableJob {
             source_cache: source_cache.clone(),
 
             script,
+            phases,
         })
     }
 
@@ -144,3 +130,151 @@ impl RunnableJob {
     }
 
 }
+
+/// Check that the environment variables of a job are allowed
+///
+/// The variables of the job, its package and `git_env` have to be allowed by the configuration
+/// (if `check_env_names` is set) and the variables of the job by its package.
+pub fn check_env<'a>(
+    job: &'a Job,
+    containers: &'a ContainerConfig,
+    git_env: impl Iterator<Item = &'a EnvironmentVariableName>,
+) -> Result<()> {
+    if containers.check_env_names() {
+        debug!("Checking environment if all variables are allowed!");
+        job.resources()
+            .iter()
+            .filter_map(|r| r.env())
+            .map(|(name, _)| name)
+            .chain({
+                job.package()
+                    .environment()
+                    .as_ref()
+                    .map(|hm| hm.keys())
+                    .into_iter()
+                    .flatten()
+            })
+            .chain(git_env)
+            .inspect(|name| debug!("Checking: {}", name))
+            .try_for_each(|name| {
+                trace!("{:?} contains? {:?}", containers.allowed_env(), name);
+                if !containers.allowed_env().contains(name) {
+                    Err(anyhow!("Environment variable name not allowed: {}", name))
+                } else {
+                    Ok(())
+                }
+            })
+            .with_context(|| {
+                anyhow!(
+                    "Checking allowed variables for package {} {}",
+                    job.package().name(),
+                    job.package().version()
+                )
+            })
+            .context("Checking allowed variable names")?;
+    } else {
+        debug!("Environment checking disabled");
+    }
+
+    check_package_env(
+        job.package(),
+        job.resources().iter().filter_map(|r| r.env()).map(|(name, _)| name),
+        containers.undeclared_package_env(),
+    )
+}
+
+/// Check that only the environment variables that the package allows are passed to its build
+///
+/// Depending on `action`, passing other variables is an error or only a warning.
+fn check_package_env<'a>(
+    package: &Package,
+    env: impl Iterator<Item = &'a EnvironmentVariableName>,
+    action: UndeclaredEnvAction,
+) -> Result<()> {
+    let allowed_env = match package.allowed_env() {
+        Some(allowed_env) => allowed_env,
+        None => return Ok(()),
+    };
+
+    let undeclared = env
+        .filter(|name| !allowed_env.contains(name))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    if !undeclared.is_empty() {
+        let msg = format!("Environment variables not allowed for package {} {}: {}",
+            package.name(),
+            package.version(),
+            undeclared.join(", "));
+
+        match action {
+            UndeclaredEnvAction::Error => return Err(anyhow!(msg)),
+            UndeclaredEnvAction::Warn => warn!("{}", msg),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    fn containers(check_env_names: bool) -> ContainerConfig {
+        toml::from_str(&format!(r#"
+            check_env_names = {check_env_names}
+            allowed_env = [ "FOO", "GIT_AUTHOR" ]
+        "#)).unwrap()
+    }
+
+    fn job(env: &[(&str, &str)], allowed_env: Option<Vec<EnvironmentVariableName>>) -> Job {
+        let resources = env.iter()
+            .map(|(k, v)| JobResource::from((EnvironmentVariableName::from(*k), v.to_string())))
+            .collect();
+        let mut package = package("a", "1", "https://rust-lang.org", "123");
+        package.set_allowed_env(allowed_env);
+        Job::new(package, crate::package::Shebang::from(String::from("#!/bin/bash")), ImageName::from("image"), vec![], resources)
+    }
+
+    #[test]
+    fn test_check_env() {
+        let git_author = EnvironmentVariableName::from("GIT_AUTHOR");
+        let git_commit = EnvironmentVariableName::from("GIT_COMMIT");
+
+        assert!(check_env(&job(&[("FOO", "1")], None), &containers(true), [&git_author].into_iter()).is_ok());
+
+        let e = check_env(&job(&[("FOO", "1"), ("BAR", "2")], None), &containers(true), std::iter::empty()).unwrap_err();
+        assert_eq!(format!("{e:#}"), "Checking allowed variable names: Checking allowed variables for package a 1: Environment variable name not allowed: BAR");
+
+        // the variables of the git integration are checked as well
+        assert!(check_env(&job(&[], None), &containers(true), [&git_commit].into_iter()).is_err());
+
+        // the configured allowlist is not checked if check_env_names is false
+        assert!(check_env(&job(&[("BAR", "2")], None), &containers(false), [&git_commit].into_iter()).is_ok());
+
+        // but the allowlist of the package is
+        assert!(check_env(&job(&[("BAR", "2")], Some(vec![])), &containers(false), std::iter::empty()).is_err());
+    }
+
+    #[test]
+    fn test_check_package_env() {
+        let foo = EnvironmentVariableName::from("FOO");
+        let bar = EnvironmentVariableName::from("BAR");
+
+        // Without an allowlist, all variables can be passed
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+        assert!(check_package_env(&p, [&foo, &bar].into_iter(), UndeclaredEnvAction::Error).is_ok());
+
+        p.set_allowed_env(Some(vec![foo.clone()]));
+        assert!(check_package_env(&p, [&foo].into_iter(), UndeclaredEnvAction::Error).is_ok());
+        assert!(check_package_env(&p, std::iter::empty(), UndeclaredEnvAction::Error).is_ok());
+
+        let e = check_package_env(&p, [&foo, &bar].into_iter(), UndeclaredEnvAction::Error).unwrap_err();
+        assert_eq!(e.to_string(), "Environment variables not allowed for package a 1: BAR");
+        assert!(check_package_env(&p, [&foo, &bar].into_iter(), UndeclaredEnvAction::Warn).is_ok());
+
+        p.set_allowed_env(Some(vec![]));
+        assert!(check_package_env(&p, [&foo].into_iter(), UndeclaredEnvAction::Error).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The archive of the raw logs of all jobs
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::trace;
+use uuid::Uuid;
+
+use crate::config::LogArchiveConfig;
+
+/// Writes the raw log of every job to the configured archive directory
+#[derive(Clone, Debug)]
+pub struct LogArchive {
+    config: LogArchiveConfig,
+}
+
+impl LogArchive {
+    pub fn new(config: LogArchiveConfig) -> Self {
+        LogArchive { config }
+    }
+
+    /// Write the raw log of a job to the archive and return the path of the archived log
+    ///
+    /// The log is written to a temporary file first, so the archive never contains a partial log.
+    pub async fn store(&self, submit: &Uuid, job: &Uuid, package: &str, version: &str, log: &str) -> Result<PathBuf> {
+        let path = self.config.directory().join(archive_path(submit, job, package, version, self.config.compress()));
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| anyhow!("Creating directory {}", parent.display()))?;
+        }
+
+        let compress = self.config.compress();
+        let log = log.to_string();
+        tokio::task::spawn_blocking(move || -> Result<PathBuf> {
+            let mut tmp = path.as_os_str().to_owned();
+            tmp.push(".tmp");
+            let tmp = PathBuf::from(tmp);
+
+            trace!("Archiving log to {}", path.display());
+            let file = std::fs::File::create(&tmp).with_context(|| anyhow!("Creating {}", tmp.display()))?;
+            if compress {
+                let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                encoder.write_all(log.as_bytes())?;
+                encoder.finish()?.sync_all()?;
+            } else {
+                let mut file = file;
+                file.write_all(log.as_bytes())?;
+                file.sync_all()?;
+            }
+
+            std::fs::rename(&tmp, &path)
+                .with_context(|| anyhow!("Renaming {} to {}", tmp.display(), path.display()))?;
+            Ok(path)
+        })
+        .await?
+    }
+}
+
+/// The path of the archived log of a job, relative to the archive directory
+///
+/// Slashes in the package name and version are replaced, so that they cannot add directories.
+fn archive_path(submit: &Uuid, job: &Uuid, package: &str, version: &str, compress: bool) -> PathBuf {
+    let sanitize = |value: &str| value.replace('/', "_");
+    let extension = if compress { "log.gz" } else { "log" };
+    PathBuf::from(submit.to_string())
+        .join(format!("{}-{}-{}.{}", sanitize(package), sanitize(version), job, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_path() {
+        let submit = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+        let job = Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap();
+
+        assert_eq!(
+            archive_path(&submit, &job, "foo", "1.0", false),
+            PathBuf::from("00000000-0000-0000-0000-000000000001/foo-1.0-00000000-0000-0000-0000-000000000002.log")
+        );
+        assert_eq!(
+            archive_path(&submit, &job, "foo", "1.0/rc1", true),
+            PathBuf::from("00000000-0000-0000-0000-000000000001/foo-1.0_rc1-00000000-0000-0000-0000-000000000002.log.gz")
+        );
+    }
+}
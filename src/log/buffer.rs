@@ -0,0 +1,220 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use bytesize::ByteSize;
+
+use crate::log::LogItem;
+use crate::log::TimedLogItem;
+
+/// How the log of a job is stored
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogOptions {
+    /// The maximum size of the log, in bytes
+    pub max_size: Option<u64>,
+
+    /// Whether ANSI escape sequences are removed from the lines of the log
+    pub strip_ansi_escapes: bool,
+}
+
+/// The log of a job, limited in size
+///
+/// If the log exceeds the limit, the first and the last half of the limit are kept and the lines
+/// in between are replaced by a truncation marker.
+#[derive(Debug)]
+pub struct LogBuffer {
+    limit: Option<u64>,
+    strip_ansi_escapes: bool,
+
+    head: Vec<TimedLogItem>,
+    head_size: u64,
+
+    tail: VecDeque<TimedLogItem>,
+    tail_size: u64,
+
+    omitted_lines: u64,
+    omitted_size: u64,
+}
+
+impl LogBuffer {
+    pub fn new(limit: Option<u64>) -> Self {
+        LogBuffer {
+            limit,
+            strip_ansi_escapes: false,
+            head: Vec::with_capacity(4096),
+            head_size: 0,
+            tail: VecDeque::new(),
+            tail_size: 0,
+            omitted_lines: 0,
+            omitted_size: 0,
+        }
+    }
+
+    pub fn with_options(options: LogOptions) -> Self {
+        LogBuffer {
+            strip_ansi_escapes: options.strip_ansi_escapes,
+            ..LogBuffer::new(options.max_size)
+        }
+    }
+
+    /// Add an item to the log
+    ///
+    /// Returns the item if it is part of the head of the log. Items that are not part of the head
+    /// might still be omitted, they are only known to be in the log after all items were added,
+    /// via `tail()`.
+    pub fn push(&mut self, mut item: TimedLogItem) -> Option<&TimedLogItem> {
+        if self.strip_ansi_escapes {
+            if let LogItem::Line(ref mut bytes) = item.item {
+                *bytes = crate::log::strip_ansi_escapes(bytes);
+            }
+        }
+
+        let size = item_size(&item);
+        let limit = match self.limit {
+            None => {
+                self.head.push(item);
+                return self.head.last()
+            },
+            Some(limit) => limit,
+        };
+
+        let head_limit = limit / 2;
+        if self.tail.is_empty() && self.head_size + size <= head_limit {
+            self.head_size += size;
+            self.head.push(item);
+            return self.head.last()
+        }
+
+        self.tail_size += size;
+        self.tail.push_back(item);
+        while self.tail_size > limit - head_limit {
+            match self.tail.pop_front() {
+                Some(omitted) => {
+                    let size = item_size(&omitted);
+                    self.tail_size -= size;
+                    self.omitted_lines += 1;
+                    self.omitted_size += size;
+                },
+                None => break,
+            }
+        }
+
+        None
+    }
+
+    /// The line that replaces the omitted lines, if the log was truncated
+    pub fn truncation_marker(&self) -> Option<String> {
+        if self.omitted_lines == 0 {
+            return None
+        }
+
+        Some(format!(
+            "[... butido: {} lines ({}) omitted, the log exceeded the limit of {} ...]",
+            self.omitted_lines,
+            ByteSize::b(self.omitted_size),
+            ByteSize::b(self.limit.unwrap_or_default()),
+        ))
+    }
+
+    /// The items after the head of the log
+    pub fn tail(&self) -> impl Iterator<Item = &TimedLogItem> {
+        self.tail.iter()
+    }
+
+    /// The log as text, as it is stored in the database
+    pub fn to_text(&self) -> Result<String> {
+        let head = self.head.iter().map(TimedLogItem::raw);
+        let marker = self.truncation_marker().map(Ok);
+        let tail = self.tail.iter().map(TimedLogItem::raw);
+
+        head.chain(marker)
+            .chain(tail)
+            .collect::<Result<Vec<String>>>()
+            .map(|lines| lines.join("\n"))
+    }
+}
+
+/// The size of the item in the log, including the line break
+fn item_size(item: &TimedLogItem) -> u64 {
+    let size = match &item.item {
+        LogItem::Line(bytes) => bytes.len(),
+        other => other.raw().map(|s| s.len()).unwrap_or(0),
+    };
+
+    (item.time_len() + size) as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(s: &str) -> TimedLogItem {
+        untimed(LogItem::Line(s.as_bytes().to_vec()))
+    }
+
+    fn untimed(item: LogItem) -> TimedLogItem {
+        TimedLogItem { time: None, item }
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut log = LogBuffer::new(None);
+        assert!(log.push(line("a")).is_some());
+        assert!(log.push(untimed(LogItem::State(Ok(())))).is_some());
+        assert_eq!(log.truncation_marker(), None);
+        assert_eq!(log.to_text().unwrap(), "a\n#BUTIDO:STATE:OK");
+    }
+
+    #[test]
+    fn test_truncated() {
+        // Every line has a size of 2 bytes, so head and tail keep two lines each
+        let mut log = LogBuffer::new(Some(8));
+        let in_head = ["1", "2", "3", "4", "5", "6", "7"].iter()
+            .map(|s| log.push(line(s)).is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(in_head, [true, true, false, false, false, false, false]);
+
+        assert_eq!(log.tail().count(), 2);
+        assert_eq!(
+            log.to_text().unwrap(),
+            "1\n2\n[... butido: 3 lines (6 B) omitted, the log exceeded the limit of 8 B ...]\n6\n7"
+        );
+    }
+
+    #[test]
+    fn test_not_truncated() {
+        let mut log = LogBuffer::new(Some(8));
+        for s in ["1", "2", "3"] {
+            log.push(line(s));
+        }
+        assert_eq!(log.truncation_marker(), None);
+        assert_eq!(log.to_text().unwrap(), "1\n2\n3");
+    }
+
+    #[test]
+    fn test_strip_ansi_escapes() {
+        let mut log = LogBuffer::with_options(LogOptions { max_size: Some(8), strip_ansi_escapes: true });
+        log.push(line("\x1B[32m1\x1B[0m"));
+        log.push(line("\x1B[32m2\x1B[0m"));
+        assert_eq!(log.truncation_marker(), None);
+        assert_eq!(log.to_text().unwrap(), "1\n2");
+    }
+
+    #[test]
+    fn test_times() {
+        let time = chrono::DateTime::parse_from_rfc3339("2022-01-12T03:04:05.678Z").unwrap().with_timezone(&chrono::Utc);
+        let mut log = LogBuffer::new(None);
+        log.push(TimedLogItem { time: Some(time), item: LogItem::Line(b"a".to_vec()) });
+        log.push(line("b"));
+        assert_eq!(log.to_text().unwrap(), "2022-01-12T03:04:05.678Z a\nb");
+    }
+}
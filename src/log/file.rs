@@ -0,0 +1,177 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Writing the logs of the jobs to files
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+use tracing::trace;
+
+use crate::config::LogFileConfig;
+use crate::log::LogItem;
+
+/// The log files of the jobs, in the log directory
+#[derive(Clone, Debug)]
+pub struct LogFiles {
+    dir: PathBuf,
+    config: LogFileConfig,
+}
+
+impl LogFiles {
+    pub fn new(dir: PathBuf, config: LogFileConfig) -> Self {
+        LogFiles { dir, config }
+    }
+
+    /// Open the log file for a job
+    ///
+    /// A previous log file with the same path (e.g. of a retried job) is rotated.
+    pub async fn open(&self, submit: &str, package: &str, version: &str, job: &str, image: &str) -> Result<LogFile> {
+        let path = self.dir.join(self.config.render(submit, package, version, job, image));
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| anyhow!("Creating directory {}", parent.display()))?;
+        }
+
+        rotate(&path, self.config.keep(), self.config.compress()).await?;
+
+        trace!("Writing log to {}", path.display());
+        let file = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| anyhow!("Opening {}", path.display()))?;
+
+        Ok(LogFile {
+            path,
+            compress: self.config.compress(),
+            writer: tokio::io::BufWriter::new(file),
+        })
+    }
+}
+
+/// The log file of a running job
+pub struct LogFile {
+    path: PathBuf,
+    compress: bool,
+    writer: tokio::io::BufWriter<tokio::fs::File>,
+}
+
+impl LogFile {
+    pub async fn write_item(&mut self, item: &LogItem) -> Result<()> {
+        // Lines are written to the log file unmodified, even if they are not valid UTF-8
+        if let LogItem::Line(bytes) = item {
+            self.write_line(bytes).await
+        } else {
+            self.write_line(item.display()?.to_string().as_bytes()).await
+        }
+    }
+
+    pub async fn write_line(&mut self, line: &[u8]) -> Result<()> {
+        self.writer.write_all(line).await?;
+        self.writer.write_all(b"\n").await.map_err(anyhow::Error::from)
+    }
+
+    /// Flush the log file and compress it, if configured
+    pub async fn finish(mut self) -> Result<()> {
+        self.writer.flush().await?;
+        drop(self.writer);
+
+        if self.compress {
+            let path = self.path;
+            tokio::task::spawn_blocking(move || -> Result<()> {
+                let compressed = rotated(&path, 0, true);
+                trace!("Compressing {} to {}", path.display(), compressed.display());
+
+                let mut input = std::fs::File::open(&path)?;
+                let output = std::fs::File::create(&compressed)
+                    .with_context(|| anyhow!("Creating {}", compressed.display()))?;
+                let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?;
+                std::fs::remove_file(&path).with_context(|| anyhow!("Removing {}", path.display()))
+            })
+            .await??;
+        }
+
+        Ok(())
+    }
+}
+
+/// The path of the `n`th previous log file for `path` (0 is the current one)
+fn rotated(path: &Path, n: usize, compress: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    if n > 0 {
+        name.push(format!(".{n}"));
+    }
+    if compress {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Move the existing log files for `path` one number up, keeping at most `keep` previous files
+async fn rotate(path: &Path, keep: usize, compress: bool) -> Result<()> {
+    let oldest = rotated(path, keep, compress);
+    if tokio::fs::metadata(&oldest).await.is_ok() {
+        tokio::fs::remove_file(&oldest)
+            .await
+            .with_context(|| anyhow!("Removing {}", oldest.display()))?;
+    }
+
+    for n in (0..keep).rev() {
+        let from = rotated(path, n, compress);
+        if tokio::fs::metadata(&from).await.is_ok() {
+            let to = rotated(path, n + 1, compress);
+            trace!("Rotating {} to {}", from.display(), to.display());
+            tokio::fs::rename(&from, &to)
+                .await
+                .with_context(|| anyhow!("Renaming {} to {}", from.display(), to.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotated() {
+        let path = Path::new("logs/foo.log");
+        assert_eq!(rotated(path, 0, false), PathBuf::from("logs/foo.log"));
+        assert_eq!(rotated(path, 2, false), PathBuf::from("logs/foo.log.2"));
+        assert_eq!(rotated(path, 0, true), PathBuf::from("logs/foo.log.gz"));
+        assert_eq!(rotated(path, 1, true), PathBuf::from("logs/foo.log.1.gz"));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("butido-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("foo.log");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        for content in ["1", "2", "3"] {
+            runtime.block_on(rotate(&path, 1, false)).unwrap();
+            std::fs::write(&path, content).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "3");
+        assert_eq!(std::fs::read_to_string(rotated(&path, 1, false)).unwrap(), "2");
+        assert!(!rotated(&path, 2, false).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Filtering of parsed logs, for showing only parts of a stored log
+
+use crate::log::LogItem;
+use crate::log::TimedLogItem;
+
+/// Selects the items of a log that are shown
+///
+/// The filters are applied in order: first the phases, then the markers, then the errors.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    /// Only show the items of these phases, all phases if empty
+    pub phases: Vec<String>,
+
+    /// Only show the butido markers (`#BUTIDO:...`), not the output of the script
+    pub markers_only: bool,
+
+    /// Only show the error states and this number of items before and after them
+    pub errors_context: Option<usize>,
+}
+
+impl LogFilter {
+    /// Whether the filter shows all items
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty() && !self.markers_only && self.errors_context.is_none()
+    }
+
+    pub fn apply(&self, items: Vec<TimedLogItem>) -> Vec<TimedLogItem> {
+        let mut current_phase: Option<String> = None;
+        let items = items
+            .into_iter()
+            .filter(|item| {
+                if let LogItem::CurrentPhase(ref phase) = item.item {
+                    current_phase = Some(phase.clone());
+                }
+
+                self.phases.is_empty() || current_phase.as_ref().map(|p| self.phases.contains(p)).unwrap_or(false)
+            })
+            .filter(|item| !self.markers_only || !matches!(item.item, LogItem::Line(_)))
+            .collect::<Vec<_>>();
+
+        match self.errors_context {
+            None => items,
+            Some(context) => {
+                let errors = items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, item)| matches!(item.item, LogItem::State(Err(_))))
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+
+                items
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| errors.iter().any(|e| e.saturating_sub(context) <= *i && *i <= e + context))
+                    .map(|(_, item)| item)
+                    .collect()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(item: LogItem) -> TimedLogItem {
+        TimedLogItem { time: None, item }
+    }
+
+    fn line(s: &str) -> TimedLogItem {
+        item(LogItem::Line(s.as_bytes().to_vec()))
+    }
+
+    fn phase(s: &str) -> TimedLogItem {
+        item(LogItem::CurrentPhase(s.to_string()))
+    }
+
+    fn log() -> Vec<TimedLogItem> {
+        vec![
+            line("setup"),
+            phase("unpack"),
+            line("unpacking"),
+            phase("build"),
+            line("compiling a"),
+            item(LogItem::Warning(String::from("deprecated flag"))),
+            line("compiling b"),
+            line("error: b.c"),
+            item(LogItem::State(Err(String::from("make failed")))),
+        ]
+    }
+
+    #[test]
+    fn test_no_filter() {
+        let filter = LogFilter::default();
+        assert!(filter.is_empty());
+        assert_eq!(filter.apply(log()), log());
+    }
+
+    #[test]
+    fn test_filter_phases() {
+        let filter = LogFilter { phases: vec![String::from("unpack")], ..LogFilter::default() };
+        assert_eq!(filter.apply(log()), vec![phase("unpack"), line("unpacking")]);
+    }
+
+    #[test]
+    fn test_filter_markers() {
+        let filter = LogFilter { markers_only: true, ..LogFilter::default() };
+        assert_eq!(filter.apply(log()), vec![
+            phase("unpack"),
+            phase("build"),
+            item(LogItem::Warning(String::from("deprecated flag"))),
+            item(LogItem::State(Err(String::from("make failed")))),
+        ]);
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        let filter = LogFilter { errors_context: Some(2), ..LogFilter::default() };
+        assert_eq!(filter.apply(log()), vec![
+            line("compiling b"),
+            line("error: b.c"),
+            item(LogItem::State(Err(String::from("make failed")))),
+        ]);
+
+        let ok = vec![line("a"), item(LogItem::State(Ok(())))];
+        assert!(filter.apply(ok).is_empty());
+    }
+}
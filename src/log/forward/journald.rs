@@ -0,0 +1,108 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Forwarding to the systemd journal, via the native journal protocol
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tokio::net::UnixDatagram;
+
+use crate::config::JournaldConfig;
+use crate::log::forward::ForwardedLine;
+
+/// The priority of the entries ("info")
+const PRIORITY: &str = "6";
+
+pub struct Journald {
+    socket: UnixDatagram,
+    path: PathBuf,
+    identifier: String,
+}
+
+impl Journald {
+    pub fn new(config: &JournaldConfig) -> Result<Self> {
+        Ok(Journald {
+            socket: UnixDatagram::unbound().context("Creating socket for the journal")?,
+            path: config.socket().clone(),
+            identifier: config.identifier().clone(),
+        })
+    }
+
+    pub async fn send(&self, lines: &[Arc<ForwardedLine>]) -> Result<()> {
+        for line in lines {
+            self.socket
+                .send_to(&entry(&self.identifier, line), &self.path)
+                .await
+                .with_context(|| anyhow!("Sending log entry to journal at {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The journal entry of a line, one datagram
+fn entry(identifier: &str, line: &ForwardedLine) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(line.line.len() + 256);
+    push_field(&mut entry, "MESSAGE", &line.line);
+    push_field(&mut entry, "PRIORITY", PRIORITY);
+    push_field(&mut entry, "SYSLOG_IDENTIFIER", identifier);
+    push_field(&mut entry, "BUTIDO_SUBMIT", &line.job.submit.to_string());
+    push_field(&mut entry, "BUTIDO_JOB", &line.job.job.to_string());
+    push_field(&mut entry, "BUTIDO_PACKAGE", &line.job.package);
+    push_field(&mut entry, "BUTIDO_VERSION", &line.job.version);
+    if let Some(phase) = line.phase.as_ref() {
+        push_field(&mut entry, "BUTIDO_PHASE", phase);
+    }
+    entry
+}
+
+/// Append a field to the entry
+///
+/// Values that contain a newline have to be written with their length instead of "=".
+fn push_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let line = crate::log::forward::test_line(Some("build"), "make all");
+        let expected = "MESSAGE=make all\n\
+                        PRIORITY=6\n\
+                        SYSLOG_IDENTIFIER=butido\n\
+                        BUTIDO_SUBMIT=00000000-0000-0000-0000-000000000001\n\
+                        BUTIDO_JOB=00000000-0000-0000-0000-000000000002\n\
+                        BUTIDO_PACKAGE=foo\n\
+                        BUTIDO_VERSION=1.0\n\
+                        BUTIDO_PHASE=build\n";
+        assert_eq!(String::from_utf8(entry("butido", &line)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_field_with_newline() {
+        let mut entry = Vec::new();
+        push_field(&mut entry, "MESSAGE", "a\nb");
+        assert_eq!(entry, b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n");
+    }
+}
@@ -0,0 +1,116 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Forwarding to the push API of Grafana Loki
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::config::LokiConfig;
+use crate::log::forward::ForwardedLine;
+
+pub struct Loki {
+    client: reqwest::Client,
+    config: LokiConfig,
+}
+
+impl Loki {
+    pub fn new(client: reqwest::Client, config: LokiConfig) -> Self {
+        Loki { client, config }
+    }
+
+    pub async fn send(&self, lines: &[Arc<ForwardedLine>]) -> Result<()> {
+        let mut request = self.client
+            .post(self.config.url().clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload(self.config.labels(), lines).to_string());
+
+        if let Some(tenant) = self.config.tenant() {
+            request = request.header("X-Scope-OrgID", tenant);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Pushing log lines to Loki at {}", self.config.url()))?;
+        Ok(())
+    }
+}
+
+/// A stream of the request body: the job and phase, the labels and the values
+type Stream<'a> = ((Uuid, Option<&'a str>), serde_json::Value, Vec<serde_json::Value>);
+
+/// The request body for the lines, with one stream per job and phase
+fn payload(labels: &HashMap<String, String>, lines: &[Arc<ForwardedLine>]) -> serde_json::Value {
+    let mut streams: Vec<Stream<'_>> = Vec::new();
+
+    for line in lines {
+        let key = (line.job.job, line.phase.as_deref());
+        let value = serde_json::json!([line.time.timestamp_nanos().to_string(), line.line]);
+
+        match streams.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, values)) => values.push(value),
+            None => {
+                let mut stream = labels.clone();
+                stream.insert(String::from("submit"), line.job.submit.to_string());
+                stream.insert(String::from("job"), line.job.job.to_string());
+                stream.insert(String::from("package"), line.job.package.clone());
+                stream.insert(String::from("version"), line.job.version.clone());
+                if let Some(phase) = line.phase.as_ref() {
+                    stream.insert(String::from("phase"), phase.clone());
+                }
+                streams.push((key, serde_json::json!(stream), vec![value]));
+            },
+        }
+    }
+
+    serde_json::json!({
+        "streams": streams
+            .into_iter()
+            .map(|(_, stream, values)| serde_json::json!({ "stream": stream, "values": values }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let labels = HashMap::from([(String::from("farm"), String::from("staging"))]);
+        let lines = vec![
+            Arc::new(crate::log::forward::test_line(Some("build"), "make all")),
+            Arc::new(crate::log::forward::test_line(Some("install"), "make install")),
+            Arc::new(crate::log::forward::test_line(Some("build"), "done")),
+        ];
+
+        let payload = payload(&labels, &lines);
+        let streams = payload["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+
+        assert_eq!(streams[0]["stream"]["farm"], "staging");
+        assert_eq!(streams[0]["stream"]["job"], "00000000-0000-0000-0000-000000000002");
+        assert_eq!(streams[0]["stream"]["package"], "foo");
+        assert_eq!(streams[0]["stream"]["phase"], "build");
+        assert_eq!(streams[0]["values"], serde_json::json!([
+            ["1641956645678000000", "make all"],
+            ["1641956645678000000", "done"],
+        ]));
+
+        assert_eq!(streams[1]["stream"]["phase"], "install");
+        assert_eq!(streams[1]["values"][0][1], "make install");
+    }
+}
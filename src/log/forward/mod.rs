@@ -0,0 +1,229 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Forwarding the logs of the jobs to external log collectors
+//!
+//! Each configured sink is fed by its own task, which sends the lines in small batches while the
+//! jobs run. This way a slow or unreachable collector does not hold up the build. Lines that
+//! cannot be forwarded are reported as warnings, they do not fail the job. If a sink falls behind
+//! by more than `QUEUE_SIZE` lines, further lines are dropped for it and the number of dropped
+//! lines is reported.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::Utc;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tracing::debug;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::LogSinkConfig;
+
+mod journald;
+mod loki;
+mod syslog;
+
+/// The maximum time a line waits before it is sent
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of lines that are sent at once
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// The maximum number of lines that wait to be sent to a sink
+const QUEUE_SIZE: usize = 10 * MAX_BATCH_SIZE;
+
+/// The time the remaining lines have to be sent in, after the submit finished
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The job a forwarded log line belongs to
+#[derive(Debug)]
+pub struct ForwardedJob {
+    pub submit: Uuid,
+    pub job: Uuid,
+    pub package: String,
+    pub version: String,
+}
+
+/// A log line of a job
+#[derive(Debug)]
+pub struct ForwardedLine {
+    pub job: Arc<ForwardedJob>,
+    pub time: DateTime<Utc>,
+    pub phase: Option<String>,
+    pub line: String,
+}
+
+/// Forwards the log lines to the tasks of the configured sinks
+#[derive(Clone, Debug)]
+pub struct LogForwarder {
+    senders: Vec<SinkSender>,
+}
+
+/// The queue of a sink, with the number of lines that were dropped because it was full
+#[derive(Clone, Debug)]
+struct SinkSender {
+    sender: Sender<Arc<ForwardedLine>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+/// The tasks that send the forwarded log lines
+#[derive(Debug)]
+pub struct LogForwarding {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl LogForwarder {
+    /// Connect to the configured sinks and start the tasks that feed them
+    pub async fn spawn(configs: &[LogSinkConfig]) -> Result<(LogForwarder, LogForwarding)> {
+        let client = reqwest::Client::new();
+        let mut senders = Vec::with_capacity(configs.len());
+        let mut tasks = Vec::with_capacity(configs.len());
+
+        for config in configs {
+            let sink = match config {
+                LogSinkConfig::Journald(c) => Sink::Journald(journald::Journald::new(c)?),
+                LogSinkConfig::Syslog(c) => Sink::Syslog(syslog::Syslog::new(c).await?),
+                LogSinkConfig::Loki(c) => Sink::Loki(loki::Loki::new(client.clone(), c.clone())),
+            };
+
+            let (sender, receiver) = tokio::sync::mpsc::channel(QUEUE_SIZE);
+            let dropped = Arc::new(AtomicUsize::new(0));
+            senders.push(SinkSender { sender, dropped: dropped.clone() });
+            tasks.push(tokio::task::spawn(run(sink, receiver, dropped)));
+        }
+
+        Ok((LogForwarder { senders }, LogForwarding { tasks }))
+    }
+
+    pub fn forward(&self, line: ForwardedLine) {
+        let line = Arc::new(line);
+        for sink in self.senders.iter() {
+            // Sending also fails if the task ended, which it does not before all senders are
+            // dropped
+            if let Err(tokio::sync::mpsc::error::TrySendError::Full(_)) = sink.sender.try_send(line.clone()) {
+                sink.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl LogForwarding {
+    /// Wait until the remaining lines are sent
+    ///
+    /// The `LogForwarder`s have to be dropped before, otherwise this waits for `FINISH_TIMEOUT`.
+    pub async fn finish(self) {
+        let tasks = futures::future::join_all(self.tasks);
+        if tokio::time::timeout(FINISH_TIMEOUT, tasks).await.is_err() {
+            warn!("Not all log lines were forwarded within {} seconds", FINISH_TIMEOUT.as_secs());
+        }
+    }
+}
+
+enum Sink {
+    Journald(journald::Journald),
+    Syslog(syslog::Syslog),
+    Loki(loki::Loki),
+}
+
+impl Sink {
+    async fn send(&self, lines: &[Arc<ForwardedLine>]) -> Result<()> {
+        match self {
+            Sink::Journald(j) => j.send(lines).await,
+            Sink::Syslog(s) => s.send(lines).await,
+            Sink::Loki(l) => l.send(lines).await,
+        }
+    }
+}
+
+async fn run(sink: Sink, mut receiver: Receiver<Arc<ForwardedLine>>, dropped: Arc<AtomicUsize>) {
+    let mut failing = false;
+
+    while let Some(line) = receiver.recv().await {
+        let mut batch = vec![line];
+        let deadline = tokio::time::Instant::now() + FLUSH_INTERVAL;
+        while batch.len() < MAX_BATCH_SIZE {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(line)) => batch.push(line),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        match sink.send(&batch).await {
+            Ok(()) => failing = false,
+
+            // Only the first of several failures in a row is a warning, so that an unreachable
+            // collector does not flood the output
+            Err(e) if !failing => {
+                warn!("Forwarding {} log lines failed: {:#}", batch.len(), e);
+                failing = true;
+            },
+            Err(e) => debug!("Forwarding {} log lines failed: {:#}", batch.len(), e),
+        }
+
+        warn_dropped(&dropped);
+    }
+
+    warn_dropped(&dropped);
+}
+
+/// Warn about the lines that were dropped since the last call
+fn warn_dropped(dropped: &AtomicUsize) {
+    let n = dropped.swap(0, Ordering::Relaxed);
+    if n > 0 {
+        warn!("Dropped {} log lines because the log collector is too slow", n);
+    }
+}
+
+#[cfg(test)]
+fn test_line(phase: Option<&str>, line: &str) -> ForwardedLine {
+    ForwardedLine {
+        job: Arc::new(ForwardedJob {
+            submit: Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            job: Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap(),
+            package: String::from("foo"),
+            version: String::from("1.0"),
+        }),
+        time: DateTime::parse_from_rfc3339("2022-01-12T03:04:05.678Z").unwrap().with_timezone(&Utc),
+        phase: phase.map(String::from),
+        line: String::from(line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_drops_lines_if_queue_is_full() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(2);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let forwarder = LogForwarder {
+            senders: vec![SinkSender { sender, dropped: dropped.clone() }],
+        };
+
+        for i in 0..5 {
+            forwarder.forward(test_line(None, &format!("line {i}")));
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(receiver.try_recv().unwrap().line, "line 0");
+        assert_eq!(receiver.try_recv().unwrap().line, "line 1");
+        assert!(receiver.try_recv().is_err());
+
+        warn_dropped(&dropped);
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,145 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Forwarding to syslog, as RFC 5424 messages via a unix socket or UDP
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::SecondsFormat;
+use tokio::net::UdpSocket;
+use tokio::net::UnixDatagram;
+
+use crate::config::SyslogConfig;
+use crate::log::forward::ForwardedLine;
+
+/// The severity of the messages ("informational")
+const SEVERITY: u8 = 6;
+
+enum Transport {
+    Unix(UnixDatagram, PathBuf),
+    Udp(UdpSocket),
+}
+
+pub struct Syslog {
+    transport: Transport,
+    address: String,
+    header: Header,
+}
+
+/// The parts of the message header that are the same for all messages
+struct Header {
+    priority: u8,
+    hostname: String,
+    identifier: String,
+    pid: u32,
+}
+
+impl Syslog {
+    pub async fn new(config: &SyslogConfig) -> Result<Self> {
+        let address = config.address().clone();
+        let transport = if address.starts_with('/') {
+            let socket = UnixDatagram::unbound().context("Creating socket for syslog")?;
+            Transport::Unix(socket, PathBuf::from(&address))
+        } else {
+            let remote = tokio::net::lookup_host(&address)
+                .await
+                .with_context(|| anyhow!("Resolving syslog address {}", address))?
+                .next()
+                .ok_or_else(|| anyhow!("Syslog address {} did not resolve to any address", address))?;
+            let local = if remote.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+            let socket = UdpSocket::bind(local).await.context("Creating socket for syslog")?;
+            socket.connect(remote)
+                .await
+                .with_context(|| anyhow!("Connecting to syslog at {}", address))?;
+            Transport::Udp(socket)
+        };
+
+        let header = Header {
+            priority: config.facility().code() * 8 + SEVERITY,
+            hostname: hostname(),
+            identifier: config.identifier().clone(),
+            pid: std::process::id(),
+        };
+
+        Ok(Syslog { transport, address, header })
+    }
+
+    pub async fn send(&self, lines: &[Arc<ForwardedLine>]) -> Result<()> {
+        for line in lines {
+            let message = message(&self.header, line);
+            let sent = match &self.transport {
+                Transport::Unix(socket, path) => socket.send_to(message.as_bytes(), path).await,
+                Transport::Udp(socket) => socket.send(message.as_bytes()).await,
+            };
+            sent.with_context(|| anyhow!("Sending log message to syslog at {}", self.address))?;
+        }
+        Ok(())
+    }
+}
+
+/// The host name for the messages, the nil value "-" if it is not known
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("-"))
+}
+
+/// The syslog message of a line
+///
+/// The job is prefixed to the line in the message, because not all syslog servers support
+/// structured data.
+fn message(header: &Header, line: &ForwardedLine) -> String {
+    format!(
+        "<{}>1 {} {} {} {} - - {} {} {}{}: {}",
+        header.priority,
+        line.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        header.hostname,
+        header.identifier,
+        header.pid,
+        line.job.package,
+        line.job.version,
+        line.job.job,
+        line.phase.as_ref().map(|phase| format!(" {phase}")).unwrap_or_default(),
+        line.line,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let header = Header {
+            priority: 16 * 8 + SEVERITY,
+            hostname: String::from("buildhost"),
+            identifier: String::from("butido"),
+            pid: 42,
+        };
+
+        let line = crate::log::forward::test_line(Some("build"), "make all");
+        assert_eq!(
+            message(&header, &line),
+            "<134>1 2022-01-12T03:04:05.678Z buildhost butido 42 - - foo 1.0 00000000-0000-0000-0000-000000000002 build: make all"
+        );
+
+        let line = crate::log::forward::test_line(None, "make all");
+        assert_eq!(
+            message(&header, &line),
+            "<134>1 2022-01-12T03:04:05.678Z buildhost butido 42 - - foo 1.0 00000000-0000-0000-0000-000000000002: make all"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Highlighting of log lines with the configured rules
+
+use std::ops::Range;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use colored::Color;
+use colored::Colorize;
+use regex::Regex;
+
+use crate::config::LogHighlightRule;
+
+struct Rule {
+    regex: Regex,
+    color: Color,
+    whole_line: bool,
+}
+
+/// Highlights the parts of log lines that match the configured rules
+#[derive(Default)]
+pub struct LogHighlighter {
+    rules: Vec<Rule>,
+}
+
+impl LogHighlighter {
+    pub fn new(rules: &[LogHighlightRule]) -> Result<Self> {
+        rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    regex: Regex::new(rule.pattern())
+                        .with_context(|| anyhow!("Building regex for log highlight rule: {}", rule.pattern()))?,
+                    color: rule.color()
+                        .parse()
+                        .map_err(|_| anyhow!("Unknown color in log highlight rule: {}", rule.color()))?,
+                    whole_line: rule.whole_line(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|rules| LogHighlighter { rules })
+    }
+
+    /// Highlight a line
+    ///
+    /// The first rule that matches the whole line wins. Otherwise the matches of all rules are
+    /// highlighted, where a match of an earlier rule wins over an overlapping match of a later one.
+    pub fn highlight(&self, line: &str) -> String {
+        if let Some(rule) = self.rules.iter().find(|r| r.whole_line && r.regex.is_match(line)) {
+            return line.color(rule.color).bold().to_string()
+        }
+
+        let mut matches: Vec<(Range<usize>, Color)> = Vec::new();
+        for rule in self.rules.iter().filter(|r| !r.whole_line) {
+            for m in rule.regex.find_iter(line).filter(|m| !m.range().is_empty()) {
+                let overlaps = matches.iter().any(|(r, _)| r.start < m.end() && m.start() < r.end);
+                if !overlaps {
+                    matches.push((m.range(), rule.color));
+                }
+            }
+        }
+
+        if matches.is_empty() {
+            return line.to_string()
+        }
+
+        matches.sort_by_key(|(r, _)| r.start);
+        let mut highlighted = String::with_capacity(line.len() + matches.len() * 16);
+        let mut pos = 0;
+        for (range, color) in matches {
+            highlighted.push_str(&line[pos..range.start]);
+            highlighted.push_str(&line[range.clone()].color(color).bold().to_string());
+            pos = range.end;
+        }
+        highlighted.push_str(&line[pos..]);
+        highlighted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Rules {
+        rule: Vec<LogHighlightRule>,
+    }
+
+    fn highlighter(rules: &str) -> Result<LogHighlighter> {
+        let rules: Rules = toml::from_str(rules).unwrap();
+        LogHighlighter::new(&rules.rule)
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        colored::control::set_override(true);
+        let h = highlighter(indoc::indoc!(r#"
+            [[rule]]
+            pattern = "error:"
+
+            [[rule]]
+            pattern = "undefined reference"
+            color = "yellow"
+        "#)).unwrap();
+
+        assert_eq!(h.highlight("all fine"), "all fine");
+        assert_eq!(
+            h.highlight("main.c: error: undefined reference to foo"),
+            format!("main.c: {} {} to foo", "error:".red().bold(), "undefined reference".yellow().bold())
+        );
+    }
+
+    #[test]
+    fn test_highlight_overlapping_matches() {
+        colored::control::set_override(true);
+        let h = highlighter(indoc::indoc!(r#"
+            [[rule]]
+            pattern = "fatal error"
+
+            [[rule]]
+            pattern = "error"
+            color = "yellow"
+        "#)).unwrap();
+
+        assert_eq!(
+            h.highlight("fatal error, error"),
+            format!("{}, {}", "fatal error".red().bold(), "error".yellow().bold())
+        );
+    }
+
+    #[test]
+    fn test_highlight_whole_line() {
+        colored::control::set_override(true);
+        let h = highlighter(indoc::indoc!(r#"
+            [[rule]]
+            pattern = "error"
+
+            [[rule]]
+            pattern = "^make: \\*\\*\\*"
+            color = "magenta"
+            whole_line = true
+        "#)).unwrap();
+
+        assert_eq!(h.highlight("make: *** error 1"), "make: *** error 1".magenta().bold().to_string());
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(highlighter("[[rule]]\npattern = \"(\"").is_err());
+        assert!(highlighter("[[rule]]\npattern = \"x\"\ncolor = \"reddish\"").is_err());
+    }
+}
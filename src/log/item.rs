@@ -8,13 +8,21 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
-use anyhow::Error;
 use anyhow::Result;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
 use colored::Colorize;
 
+use crate::log::LogHighlighter;
+use crate::log::util::lossy_utf8;
+use crate::package::PhaseLog;
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum LogItem {
     /// A line from the log, unmodified
+    ///
+    /// The line is not necessarily valid UTF-8, invalid sequences are escaped when displaying it.
     Line(Vec<u8>),
 
     /// A progress report
@@ -23,6 +31,23 @@ pub enum LogItem {
     /// The name of the current phase the process is in
     CurrentPhase(String),
 
+    /// The log verbosity for the following output, until the next switch
+    LogMode(PhaseLog),
+
+    /// The SHA-256 checksum of an artifact, computed in the container
+    ///
+    /// The first element is the checksum, the second one the path of the artifact relative to the
+    /// outputs directory.
+    Checksum(String, String),
+
+    /// A metric reported by the script, as key and value
+    ///
+    /// Metrics are recorded in the database, so that jobs can be compared and filtered by them.
+    Metric(String, String),
+
+    /// A warning reported by the script
+    Warning(String),
+
     /// The end-state of the process
     /// Either Ok or Error
     State(Result<(), String>),
@@ -31,19 +56,35 @@ pub enum LogItem {
 impl LogItem {
     pub fn display(&self) -> Result<Display> {
         match self {
-            LogItem::Line(s) => Ok(Display(String::from_utf8(s.to_vec())?.normal())),
+            LogItem::Line(s) => Ok(Display(lossy_utf8(s).normal())),
             LogItem::Progress(u) => Ok(Display(format!("#BUTIDO:PROGRESS:{u}").cyan())),
             LogItem::CurrentPhase(p) => Ok(Display(format!("#BUTIDO:PHASE:{p}").cyan())),
+            LogItem::LogMode(m) => Ok(Display(format!("#BUTIDO:LOG:{}", m.as_str()).cyan())),
+            LogItem::Checksum(c, p) => Ok(Display(format!("#BUTIDO:CHECKSUM:{c}:{p}").cyan())),
+            LogItem::Metric(k, v) => Ok(Display(format!("#BUTIDO:METRIC:{k}={v}").cyan())),
+            LogItem::Warning(w) => Ok(Display(format!("#BUTIDO:WARN:{w}").yellow())),
             LogItem::State(Ok(())) => Ok(Display("#BUTIDO:STATE:OK".to_string().green())),
             LogItem::State(Err(s)) => Ok(Display(format!("#BUTIDO:STATE:ERR:{s}").red())),
         }
     }
 
+    /// Like `display()`, with the lines highlighted by the `highlighter`
+    pub fn display_highlighted(&self, highlighter: &LogHighlighter) -> Result<Display> {
+        match self {
+            LogItem::Line(s) => Ok(Display(highlighter.highlight(&lossy_utf8(s)).normal())),
+            other => other.display(),
+        }
+    }
+
     pub fn raw(&self) -> Result<String> {
         match self {
-            LogItem::Line(s) => String::from_utf8(s.to_vec()).map_err(Error::from),
+            LogItem::Line(s) => Ok(lossy_utf8(s).into_owned()),
             LogItem::Progress(u) => Ok(format!("#BUTIDO:PROGRESS:{u}")),
             LogItem::CurrentPhase(p) => Ok(format!("#BUTIDO:PHASE:{p}")),
+            LogItem::LogMode(m) => Ok(format!("#BUTIDO:LOG:{}", m.as_str())),
+            LogItem::Checksum(c, p) => Ok(format!("#BUTIDO:CHECKSUM:{c}:{p}")),
+            LogItem::Metric(k, v) => Ok(format!("#BUTIDO:METRIC:{k}={v}")),
+            LogItem::Warning(w) => Ok(format!("#BUTIDO:WARN:{w}")),
             LogItem::State(Ok(())) => Ok("#BUTIDO:STATE:OK".to_string()),
             LogItem::State(Err(s)) => Ok(format!("#BUTIDO:STATE:ERR:{s}")),
         }
@@ -53,3 +94,92 @@ impl LogItem {
 #[derive(parse_display::Display)]
 #[display("{0}")]
 pub struct Display(colored::ColoredString);
+
+/// A log item with the time its line was received from the container
+///
+/// In the stored log, the time is a prefix of the line (`2022-01-12T03:04:05.678Z <line>`).
+/// Logs stored before the times were recorded have no prefix.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TimedLogItem {
+    pub time: Option<DateTime<Utc>>,
+    pub item: LogItem,
+}
+
+impl TimedLogItem {
+    /// The item, received right now
+    pub fn now(item: LogItem) -> Self {
+        TimedLogItem {
+            time: Some(Utc::now()),
+            item,
+        }
+    }
+
+    /// Display the highlighted item, with its time if `with_time` is set
+    pub fn display(&self, with_time: bool, highlighter: &LogHighlighter) -> Result<Display> {
+        let item = self.item.display_highlighted(highlighter)?;
+        match self.time.as_ref().filter(|_| with_time) {
+            Some(time) => Ok(Display(format!("{} {}", format_time(time).dimmed(), item).normal())),
+            None => Ok(item),
+        }
+    }
+
+    /// The line as it is stored in the database
+    pub fn raw(&self) -> Result<String> {
+        let item = self.item.raw()?;
+        match self.time.as_ref() {
+            Some(time) => Ok(format!("{} {}", format_time(time), item)),
+            None => Ok(item),
+        }
+    }
+
+    /// The number of bytes the time adds to the stored line
+    pub(in crate::log) fn time_len(&self) -> usize {
+        self.time.map(|_| TIME_LEN + 1).unwrap_or(0)
+    }
+}
+
+/// The length of a time as it is formatted by `format_time()`
+const TIME_LEN: usize = "2022-01-12T03:04:05.678Z".len();
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Split a stored log line into the time it was received at and the line itself
+pub(in crate::log) fn split_time(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    let time = line.get(..TIME_LEN)
+        .filter(|_| line[TIME_LEN..].starts_with(' '))
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok());
+
+    match time {
+        Some(time) => (Some(time.with_timezone(&Utc)), &line[TIME_LEN + 1..]),
+        None => (None, line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let time = DateTime::parse_from_rfc3339("2022-01-12T03:04:05.678Z").unwrap().with_timezone(&Utc);
+        let item = TimedLogItem {
+            time: Some(time),
+            item: LogItem::CurrentPhase(String::from("build")),
+        };
+
+        let raw = item.raw().unwrap();
+        assert_eq!(raw, "2022-01-12T03:04:05.678Z #BUTIDO:PHASE:build");
+        assert_eq!(raw.len(), item.item.raw().unwrap().len() + item.time_len());
+        assert_eq!(split_time(&raw), (Some(time), "#BUTIDO:PHASE:build"));
+    }
+
+    #[test]
+    fn test_split_time_without_time() {
+        assert_eq!(split_time("make all"), (None, "make all"));
+        assert_eq!(split_time(""), (None, ""));
+        assert_eq!(split_time("2022-01-12T03:04:05.678Z"), (None, "2022-01-12T03:04:05.678Z"));
+        assert_eq!(split_time("2022-01-12T03:04:05.678Zfoo"), (None, "2022-01-12T03:04:05.678Zfoo"));
+    }
+}
@@ -14,7 +14,32 @@ pub use parser::*;
 mod item;
 pub use item::*;
 
+mod buffer;
+pub use buffer::*;
+
+mod archive;
+pub use archive::*;
+
+mod file;
+pub use file::*;
+
+mod filter;
+pub use filter::*;
+
+mod highlight;
+pub use highlight::*;
+
 mod sink;
 pub use sink::*;
 
+mod forward;
+pub use forward::ForwardedJob;
+pub use forward::ForwardedLine;
+pub use forward::LogForwarder;
+
+mod phase_times;
+pub use phase_times::*;
+
 mod util;
+pub use util::lossy_utf8;
+pub use util::strip_ansi_escapes;
@@ -22,33 +22,55 @@ use shiplift::tty::TtyChunk;
 
 use crate::log::util::*;
 use crate::log::LogItem;
+use crate::log::TimedLogItem;
+use crate::package::PhaseLog;
 
 type IoResult<T> = RResult<T, futures::io::Error>;
 
-pub fn buffer_stream_to_line_stream<S>(stream: S) -> impl Stream<Item = IoResult<String>>
+/// Split a stream of output chunks into a stream of lines
+///
+/// The lines are returned as raw bytes without the line ending, because the output of a build is
+/// not necessarily valid UTF-8.
+pub fn buffer_stream_to_line_stream<S>(stream: S) -> impl Stream<Item = IoResult<Vec<u8>>>
 where
     S: Stream<Item = shiplift::Result<TtyChunk>> + std::marker::Unpin,
 {
-    stream
+    let reader = stream
         .map(|r| r.map(TtyChunkBuf::from))
         .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
-        .into_async_read()
-        .lines()
+        .into_async_read();
+
+    futures::stream::try_unfold(reader, |mut reader| async move {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None)
+        }
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        IoResult::Ok(Some((line, reader)))
+    })
 }
 
-pub struct ParsedLog(Vec<LogItem>);
+pub struct ParsedLog(Vec<TimedLogItem>);
 
 impl std::fmt::Debug for ParsedLog {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "ParsedLog [")?;
         for (i, line) in self.0.iter().enumerate() {
-            match line {
-                LogItem::Line(l)         => {
-                    let s = std::str::from_utf8(l).unwrap_or("ERROR UTF8 ENCODING");
-                    writeln!(f, "[{i}] Line('{s}')")?
-                },
+            match &line.item {
+                LogItem::Line(l)         => writeln!(f, "[{i}] Line('{}')", lossy_utf8(l))?,
                 LogItem::Progress(u)     => writeln!(f, "[{i}] Progress({u})")?,
                 LogItem::CurrentPhase(s) => writeln!(f, "[{i}] Phase({s})")?,
+                LogItem::LogMode(m)      => writeln!(f, "[{i}] LogMode({})", m.as_str())?,
+                LogItem::Checksum(c, p)  => writeln!(f, "[{i}] Checksum({c}, {p})")?,
+                LogItem::Metric(k, v)    => writeln!(f, "[{i}] Metric({k}, {v})")?,
+                LogItem::Warning(w)      => writeln!(f, "[{i}] Warning({w})")?,
                 LogItem::State(Ok(_))    => writeln!(f, "[{i}] State::OK")?,
                 LogItem::State(Err(_))   => writeln!(f, "[{i}] State::Err")?,
             }
@@ -64,13 +86,18 @@ impl FromStr for ParsedLog {
     fn from_str(s: &str) -> Result<Self> {
         let p = parser();
         s.lines()
-            .map(|line| p.parse(line.as_bytes()).map_err(Error::from))
+            .map(|line| {
+                let (time, line) = crate::log::item::split_time(line);
+                p.parse(line.as_bytes())
+                    .map(|item| TimedLogItem { time, item })
+                    .map_err(Error::from)
+            })
             .collect::<Result<Vec<_>>>()
             .map(ParsedLog)
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum JobResult {
     Success,
     Errored,
@@ -92,7 +119,7 @@ impl ParsedLog {
         self.0
             .iter()
             .rev()
-            .filter_map(|line| match line {
+            .filter_map(|line| match line.item {
                 LogItem::State(Ok(_))  => Some(JobResult::Success),
                 LogItem::State(Err(_)) => Some(JobResult::Errored),
                 _ => None,
@@ -102,6 +129,11 @@ impl ParsedLog {
     }
 
     pub fn into_iter(self) -> impl Iterator<Item = LogItem> {
+        self.0.into_iter().map(|line| line.item)
+    }
+
+    /// Iterate over the items together with the times they were received at
+    pub fn into_timed_iter(self) -> impl Iterator<Item = TimedLogItem> {
         self.0.into_iter()
     }
 }
@@ -115,6 +147,16 @@ pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
         .convert(|b| String::from_utf8(b.to_vec()))
         .convert(|s| usize::from_str(&s));
 
+    let checksum = one_of(b"0123456789abcdef")
+        .repeat(64..65)
+        .collect()
+        .convert(|b| String::from_utf8(b.to_vec()));
+
+    let metric_key = none_of(b"=\n")
+        .repeat(1..)
+        .collect()
+        .convert(|b| String::from_utf8(b.to_vec()));
+
     fn ignored<'a>() -> PomParser<'a, u8, Vec<u8>> {
         none_of(b"\n").repeat(0..)
     }
@@ -137,6 +179,17 @@ pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
     (seq(b"#BUTIDO:")
         * ((seq(b"PROGRESS:") * number.map(LogItem::Progress))
             | (seq(b"PHASE:") * string().map(LogItem::CurrentPhase))
+            | (seq(b"LOG:")
+                * (seq(b"summary").map(|_| LogItem::LogMode(PhaseLog::Summary))
+                    | seq(b"full").map(|_| LogItem::LogMode(PhaseLog::Full)))
+                - end())
+            | (seq(b"CHECKSUM:")
+                * (checksum - sym(b':') + ignored().convert(String::from_utf8))
+                    .map(|(c, p)| LogItem::Checksum(c, p)))
+            | (seq(b"METRIC:")
+                * (metric_key - sym(b'=') + ignored().convert(String::from_utf8))
+                    .map(|(k, v)| LogItem::Metric(k, v)))
+            | (seq(b"WARN:") * ignored().convert(String::from_utf8).map(LogItem::Warning))
             | ((seq(b"STATE:ERR:") * string().map(|s| LogItem::State(Err(s))))
                 | seq(b"STATE:OK").map(|_| LogItem::State(Ok(()))))))
         | ignored().map(LogItem::Line)
@@ -151,10 +204,7 @@ mod tests {
     // Helper function for showing log item in error message in pretty
     fn prettify_item(e: &LogItem) -> String {
         match e {
-            LogItem::Line(buf) => {
-                let line = String::from_utf8(buf.to_vec()).unwrap();
-                format!("LogItem::Line({line})")
-            }
+            LogItem::Line(buf) => format!("LogItem::Line({})", lossy_utf8(buf)),
             other => format!("{other:?}"),
         }
     }
@@ -237,6 +287,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_log_mode() {
+        let p = parser();
+
+        let r = p.parse(b"#BUTIDO:LOG:summary");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::LogMode(PhaseLog::Summary));
+
+        let r = p.parse(b"#BUTIDO:LOG:full");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::LogMode(PhaseLog::Full));
+
+        let r = p.parse(b"#BUTIDO:LOG:verbose");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::Line("#BUTIDO:LOG:verbose".bytes().collect()));
+    }
+
     #[test]
     fn test_multiline() {
         let buffer: &'static str = indoc::indoc! {"
@@ -464,4 +531,69 @@ mod tests {
         let log = ParsedLog::from_str(buffer).unwrap();
         assert_eq!(log.is_successfull(), JobResult::Errored);
     }
+
+    #[test]
+    fn test_parsed_log_with_times() {
+        let buffer: &'static str = indoc::indoc! {"
+            2022-01-12T03:04:05.678Z #BUTIDO:PHASE:build
+            2022-01-12T03:04:06.000Z make all
+            a line from a log without times
+        "};
+
+        let items = ParsedLog::from_str(buffer).unwrap().into_timed_iter().collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].item, LogItem::CurrentPhase(String::from("build")));
+        assert_eq!(items[0].time.unwrap().to_rfc3339(), "2022-01-12T03:04:05.678+00:00");
+        assert_eq!(items[1].item, LogItem::Line("make all".bytes().collect()));
+        assert!(items[1].time.is_some());
+        assert_eq!(items[2].item, LogItem::Line("a line from a log without times".bytes().collect()));
+        assert!(items[2].time.is_none());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let checksum = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let s = format!("#BUTIDO:CHECKSUM:{checksum}:dist/foo-1.0.tar.gz");
+        let p = parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert_eq!(r, LogItem::Checksum(checksum.to_string(), String::from("dist/foo-1.0.tar.gz")));
+    }
+
+    #[test]
+    fn test_metric() {
+        let p = parser();
+
+        let r = p.parse(b"#BUTIDO:METRIC:tests.passed=1234");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::Metric(String::from("tests.passed"), String::from("1234")));
+
+        let r = p.parse(b"#BUTIDO:METRIC:binary-size=");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::Metric(String::from("binary-size"), String::new()));
+
+        let r = p.parse(b"#BUTIDO:METRIC:=1");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::Line("#BUTIDO:METRIC:=1".bytes().collect()));
+    }
+
+    #[test]
+    fn test_warning() {
+        let r = parser().parse(b"#BUTIDO:WARN:deprecated configure option --foo");
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), LogItem::Warning(String::from("deprecated configure option --foo")));
+    }
+
+    #[test]
+    fn test_checksum_invalid() {
+        let s = "#BUTIDO:CHECKSUM:e3b0c442:foo";
+        let p = parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert_eq!(r, LogItem::Line(s.bytes().collect()));
+    }
 }
@@ -0,0 +1,187 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The time spent in the phases of a job
+//!
+//! A phase starts with its `#BUTIDO:PHASE:` line and ends with the next one, the last phase ends
+//! with the last line of the log.
+
+use std::ops::Range;
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use crate::log::LogItem;
+use crate::log::TimedLogItem;
+
+/// The time span of one phase of a job
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PhaseTime {
+    pub name: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+
+    /// The lines of the phase in the stored log, as indices of the lines of the log text
+    ///
+    /// `None` if the phase was cut out of a truncated log.
+    pub lines: Option<Range<usize>>,
+}
+
+impl PhaseTime {
+    pub fn duration(&self) -> chrono::Duration {
+        self.finished - self.started
+    }
+}
+
+/// Collects the times of the phases from the items of a log
+#[derive(Debug, Default)]
+pub struct PhaseTimes {
+    finished: Vec<PhaseTime>,
+    current: Option<(String, DateTime<Utc>)>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl PhaseTimes {
+    /// Add the next item of the log
+    ///
+    /// Items without time are ignored.
+    pub fn push(&mut self, item: &TimedLogItem) {
+        let time = match item.time {
+            Some(time) => time,
+            None => return,
+        };
+
+        if let LogItem::CurrentPhase(ref name) = item.item {
+            if let Some((previous, started)) = self.current.take() {
+                self.finished.push(PhaseTime { name: previous, started, finished: time, lines: None });
+            }
+            self.current = Some((name.clone(), time));
+        }
+        self.last = Some(time);
+    }
+
+    /// The times of all phases, in the order they were run in
+    pub fn finish(mut self) -> Vec<PhaseTime> {
+        if let (Some((name, started)), Some(finished)) = (self.current.take(), self.last) {
+            self.finished.push(PhaseTime { name, started, finished, lines: None });
+        }
+        self.finished
+    }
+}
+
+/// The lines of the phases in a log, in the order the phases were run in
+pub fn phase_lines(items: impl Iterator<Item = LogItem>) -> Vec<(String, Range<usize>)> {
+    let mut phases: Vec<(String, Range<usize>)> = Vec::new();
+    let mut n_lines = 0;
+    for (i, item) in items.enumerate() {
+        if let LogItem::CurrentPhase(name) = item {
+            if let Some((_, lines)) = phases.last_mut() {
+                lines.end = i;
+            }
+            phases.push((name, i..i));
+        }
+        n_lines = i + 1;
+    }
+
+    if let Some((_, lines)) = phases.last_mut() {
+        lines.end = n_lines;
+    }
+    phases
+}
+
+/// Set the lines of the phases from the items of the stored log
+///
+/// If the log was truncated, phases might be missing in it, so each phase is matched with the next
+/// phase of the same name in the log.
+pub fn assign_phase_lines(phases: &mut [PhaseTime], items: impl Iterator<Item = LogItem>) {
+    let lines = phase_lines(items);
+    let mut next = 0;
+    for phase in phases.iter_mut() {
+        if let Some(pos) = lines[next..].iter().position(|(name, _)| *name == phase.name) {
+            phase.lines = Some(lines[next + pos].1.clone());
+            next += pos + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64, item: LogItem) -> TimedLogItem {
+        let start = DateTime::parse_from_rfc3339("2022-01-12T03:00:00Z").unwrap().with_timezone(&Utc);
+        TimedLogItem {
+            time: Some(start + chrono::Duration::seconds(secs)),
+            item,
+        }
+    }
+
+    fn phase(name: &str) -> LogItem {
+        LogItem::CurrentPhase(String::from(name))
+    }
+
+    #[test]
+    fn test_phase_times() {
+        let mut times = PhaseTimes::default();
+        times.push(&at(0, LogItem::Line(b"before the first phase".to_vec())));
+        times.push(&at(1, phase("unpack")));
+        times.push(&at(3, phase("build")));
+        times.push(&at(5, LogItem::Line(b"make".to_vec())));
+        times.push(&at(10, LogItem::State(Ok(()))));
+
+        let times = times.finish()
+            .into_iter()
+            .map(|t| (t.name.clone(), t.duration().num_seconds()))
+            .collect::<Vec<_>>();
+        assert_eq!(times, [(String::from("unpack"), 2), (String::from("build"), 7)]);
+    }
+
+    #[test]
+    fn test_phase_lines() {
+        let items = vec![
+            LogItem::Line(b"before the first phase".to_vec()),
+            phase("unpack"),
+            LogItem::Line(b"tar xf".to_vec()),
+            phase("build"),
+            LogItem::Line(b"make".to_vec()),
+            LogItem::State(Ok(())),
+        ];
+        assert_eq!(phase_lines(items.into_iter()), [(String::from("unpack"), 1..3), (String::from("build"), 3..6)]);
+    }
+
+    #[test]
+    fn test_assign_phase_lines() {
+        let mut times = PhaseTimes::default();
+        for (secs, name) in [(0, "unpack"), (1, "patch"), (2, "build")] {
+            times.push(&at(secs, phase(name)));
+        }
+        let mut times = times.finish();
+
+        // The "patch" phase was truncated out of the stored log
+        let items = vec![
+            phase("unpack"),
+            LogItem::Line(b"...".to_vec()),
+            LogItem::Line(b"make".to_vec()),
+            phase("build"),
+            LogItem::Line(b"make".to_vec()),
+        ];
+        assign_phase_lines(&mut times, items.into_iter());
+
+        let lines = times.into_iter().map(|t| t.lines).collect::<Vec<_>>();
+        assert_eq!(lines, [Some(0..3), None, Some(3..5)]);
+    }
+
+    #[test]
+    fn test_phase_times_without_times() {
+        let mut times = PhaseTimes::default();
+        times.push(&TimedLogItem { time: None, item: phase("build") });
+        assert!(times.finish().is_empty());
+    }
+}
@@ -8,8 +8,105 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::borrow::Cow;
+
 use shiplift::tty::TtyChunk;
 
+/// Convert a log line to a string, without failing on invalid UTF-8
+///
+/// Invalid byte sequences are replaced by their escaped form (`\xNN` for each byte), so that the
+/// replaced bytes are still visible in the output.
+pub fn lossy_utf8(mut bytes: &[u8]) -> Cow<'_, str> {
+    let mut s = match std::str::from_utf8(bytes) {
+        Ok(s) => return Cow::Borrowed(s),
+        Err(_) => String::with_capacity(bytes.len() + 16),
+    };
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                s.push_str(valid);
+                return Cow::Owned(s)
+            },
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+
+                // safe because from_utf8() told us that this part is valid
+                s.push_str(std::str::from_utf8(valid).unwrap());
+
+                // error_len() is None if the input ends in the middle of a sequence
+                let invalid_len = e.error_len().unwrap_or(rest.len());
+                for b in &rest[..invalid_len] {
+                    s.push_str(&format!("\\x{b:02X}"));
+                }
+                bytes = &rest[invalid_len..];
+            },
+        }
+    }
+}
+
+/// Remove ANSI escape sequences (colors, cursor movements, ...) and other control characters from
+/// a log line
+///
+/// A carriage return moves to the start of the line, so only the text after the last one is kept,
+/// like a terminal would show it. Tabs are kept.
+pub fn strip_ansi_escapes(line: &[u8]) -> Vec<u8> {
+    const ESC: u8 = 0x1B;
+    const BEL: u8 = 0x07;
+
+    let line = match line.iter().rposition(|b| *b == b'\r') {
+        Some(pos) => &line[pos + 1..],
+        None => line,
+    };
+
+    let mut out = Vec::with_capacity(line.len());
+    let mut i = 0;
+    while i < line.len() {
+        match line[i] {
+            ESC => match line.get(i + 1) {
+                // CSI: parameter and intermediate bytes up to a final byte in 0x40..=0x7E
+                Some(b'[') => {
+                    i += 2;
+                    while i < line.len() && !(0x40..=0x7E).contains(&line[i]) {
+                        i += 1;
+                    }
+                    i += 1;
+                },
+
+                // OSC (e.g. window titles, hyperlinks): up to BEL or ESC \
+                Some(b']') => {
+                    i += 2;
+                    while i < line.len() && line[i] != BEL && !(line[i] == ESC && line.get(i + 1) == Some(&b'\\')) {
+                        i += 1;
+                    }
+                    i += if line.get(i) == Some(&ESC) { 2 } else { 1 };
+                },
+
+                // Other sequences: intermediate bytes in 0x20..=0x2F and one final byte
+                Some(_) => {
+                    i += 1;
+                    while i < line.len() && (0x20..=0x2F).contains(&line[i]) {
+                        i += 1;
+                    }
+                    i += 1;
+                },
+                None => i += 1,
+            },
+            b'\t' => {
+                out.push(b'\t');
+                i += 1;
+            },
+            b if b < 0x20 || b == 0x7F => i += 1,
+            b => {
+                out.push(b);
+                i += 1;
+            },
+        }
+    }
+
+    out
+}
+
 #[allow(clippy::enum_variant_names)]
 pub enum TtyChunkBuf {
     StdIn(Vec<u8>),
@@ -36,3 +133,42 @@ impl AsRef<[u8]> for TtyChunkBuf {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossy_utf8_valid() {
+        assert!(matches!(lossy_utf8("foo bär".as_bytes()), Cow::Borrowed("foo bär")));
+    }
+
+    #[test]
+    fn test_lossy_utf8_invalid() {
+        assert_eq!(lossy_utf8(b"foo\xFFbar"), "foo\\xFFbar");
+        assert_eq!(lossy_utf8(b"\xC3\x28"), "\\xC3(");
+    }
+
+    #[test]
+    fn test_strip_ansi_escapes() {
+        assert_eq!(strip_ansi_escapes(b"no escapes\there"), b"no escapes\there");
+        assert_eq!(strip_ansi_escapes(b"\x1B[1;31merror:\x1B[0m failed"), b"error: failed");
+        assert_eq!(strip_ansi_escapes(b"\x1B[2K\x1B[1Gdone"), b"done");
+        assert_eq!(strip_ansi_escapes(b"\x1B]0;title\x07text"), b"text");
+        assert_eq!(strip_ansi_escapes(b"\x1B]8;;http://x\x1B\\link\x1B]8;;\x1B\\"), b"link");
+        assert_eq!(strip_ansi_escapes(b"\x1B(Bplain\x1B="), b"plain");
+        assert_eq!(strip_ansi_escapes("bär\x08".as_bytes()), "bär".as_bytes());
+        assert_eq!(strip_ansi_escapes(b"trailing\x1B"), b"trailing");
+        assert_eq!(strip_ansi_escapes(b"\x1B[31"), b"");
+    }
+
+    #[test]
+    fn test_strip_ansi_escapes_carriage_return() {
+        assert_eq!(strip_ansi_escapes(b" 10%\r 50%\r100% done"), b"100% done");
+    }
+
+    #[test]
+    fn test_lossy_utf8_truncated() {
+        assert_eq!(lossy_utf8(b"foo\xE2\x82"), "foo\\xE2\\x82");
+    }
+}
@@ -50,6 +50,7 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -59,12 +60,14 @@ use anyhow::Result;
 use clap::ArgMatches;
 use logcrate::debug;
 use logcrate::error;
+use logcrate::warn;
 use rand as _; // Required to make lints happy
 use aquamarine as _; // doc-helper crate
 use funty as _; // doc-helper crate
 use zeroize as _; // Required to make lints happy
 use encoding_rs as _; // Required to make lints happy
 
+mod buildinfo;
 mod cli;
 mod commands;
 mod config;
@@ -72,10 +75,18 @@ mod consts;
 mod db;
 mod endpoint;
 mod filestore;
+mod hooks;
 mod job;
 mod log;
+mod notification;
 mod orchestrator;
 mod package;
+mod plugins;
+mod release_links;
+mod release_metadata;
+mod release_provenance;
+mod release_signing;
+mod release_sync;
 mod repository;
 mod schema;
 mod source;
@@ -83,6 +94,7 @@ mod ui;
 mod util;
 
 use crate::config::*;
+use crate::repository::ParseCache;
 use crate::repository::Repository;
 use crate::util::progress::ProgressBars;
 use indoc::concatdoc;
@@ -110,8 +122,8 @@ async fn main() -> Result<()> {
         .init();
     debug!("Debugging enabled");
 
-    let app = cli::cli();
-    let cli = app.get_matches();
+    let mut app = cli::cli();
+    let cli = app.get_matches_mut();
 
     // check if the version flag is set
     if cli.get_flag("version") {
@@ -119,6 +131,29 @@ async fn main() -> Result<()> {
         std::process::exit(0);
     }
 
+    crate::ui::set_interactive(!cli.get_flag("quiet"));
+
+    // There is no repository and no configuration yet when setting them up
+    if let Some(("init", matches)) = cli.subcommand() {
+        return crate::commands::init(matches).await.context("init command failed");
+    }
+
+    // Unknown subcommands are external plugins. They load the configuration themselves (if they
+    // need it), by calling butido
+    if let Some((name, matches)) = cli.subcommand() {
+        if app.find_subcommand(name).is_none() {
+            let plugin = crate::plugins::find(name)
+                .ok_or_else(|| anyhow!("No such subcommand: {}", name))?;
+            let args = matches.get_many::<OsString>("")
+                .map(|args| args.cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            let code = crate::plugins::run(&plugin, &args, cli.get_one::<String>("config"), cli.get_one::<String>("profile"))
+                .await
+                .with_context(|| anyhow!("Plugin {} failed", name))?;
+            std::process::exit(code);
+        }
+    }
+
     let repo = git2::Repository::open(PathBuf::from("."))
         .map_err(|e| match e.code() {
             git2::ErrorCode::NotFound => {
@@ -132,9 +167,22 @@ async fn main() -> Result<()> {
         .workdir()
         .ok_or_else(|| anyhow!("Not a repository with working directory. Cannot do my job!"))?;
 
+    let config_path = cli.get_one::<String>("config")
+        .cloned()
+        .or_else(|| std::env::var("BUTIDO_CONFIG").ok())
+        .map(PathBuf::from);
+    let profile = cli.get_one::<String>("profile")
+        .cloned()
+        .or_else(|| std::env::var("BUTIDO_PROFILE").ok());
+
     let mut config = ::config::Config::default();
-    config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
-        .context("Failed to load config.toml from repository")?;
+    if let Some(config_path) = config_path.as_ref() {
+        config.merge(::config::File::from(config_path.as_path()).required(true))
+            .with_context(|| anyhow!("Failed to load {}", config_path.display()))?;
+    } else {
+        config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
+            .context("Failed to load config.toml from repository")?;
+    }
 
     {
         let xdg = xdg::BaseDirectories::with_prefix("butido")?;
@@ -148,14 +196,33 @@ async fn main() -> Result<()> {
         }
     }
 
-    config.merge(::config::Environment::with_prefix("BUTIDO"))?;
+    if let Some(profile) = profile.as_ref() {
+        debug!("Using configuration profile: {}", profile);
+        crate::config::apply_profile(&mut config, profile)?;
+    }
+
+    config.merge(crate::config::EnvironmentSource::default())?;
+
+    // The configuration check reports problems of the configuration, so it has to run before the
+    // configuration is validated
+    if let Some(("config", matches)) = cli.subcommand() {
+        return crate::commands::config(matches, &cli, config)
+            .await
+            .context("config command failed");
+    }
 
+    let fingerprint = crate::config::Configuration::compute_fingerprint(&config)
+        .context("Failed to compute the fingerprint of the configuration")?;
     let config = config.try_into::<NotValidatedConfiguration>()
         .context("Failed to load Configuration object")?
         .validate()
-        .context("Failed to validate configuration")?;
+        .context("Failed to validate configuration")?
+        .with_fingerprint(fingerprint);
 
-    let hide_bars = cli.get_flag("hide_bars") || crate::util::stdout_is_pipe();
+    let hide_bars = cli.get_flag("hide_bars")
+        || cli.get_flag("quiet")
+        || crate::util::stdout_is_pipe()
+        || crate::util::stderr_is_pipe(); // the progress bars are drawn on stderr
     let progressbars = ProgressBars::setup(
         config.progress_format().clone(),
         hide_bars,
@@ -163,26 +230,39 @@ async fn main() -> Result<()> {
 
     let load_repo = || -> Result<Repository> {
         let bar = progressbars.bar()?;
-        let repo = Repository::load(repo_path, &bar)
-            .context("Loading the repository")?;
+        let roots = Repository::roots(repo_path, config.repositories())?;
+        let cache = if *config.repository_cache() {
+            Some(ParseCache::for_repository(repo_path)?)
+        } else {
+            None
+        };
+        let repo = Repository::load_overlays(repo_path, &roots, config.package_variables(), cache.as_ref(), &bar)
+            .context("Loading the repository")?
+            .with_preferred_providers(config.preferred_providers().clone());
         bar.finish_with_message("Repository loading finished");
+
+        if let Some(cache) = cache {
+            if let Err(e) = cache.save() {
+                warn!("Failed to write the repository cache: {:?}", e);
+            }
+        }
         Ok(repo)
     };
 
-    let db_connection_config = crate::db::DbConnectionConfig::parse(&config, &cli)?;
+    // Parsed only for the commands that need it, because reading the database password can
+    // involve running a command
+    let db_connection_config = || crate::db::DbConnectionConfig::parse(&config, &cli);
     match cli.subcommand() {
         Some(("generate-completions", matches)) => generate_completions(matches),
-        Some(("db", matches)) => crate::commands::db(db_connection_config, &config, matches)?,
+        Some(("db", matches)) => crate::commands::db(db_connection_config()?, &config, matches)?,
         Some(("build", matches)) => {
-            let conn = db_connection_config.establish_connection()?;
-
             let repo = load_repo()?;
 
             crate::commands::build(
                 repo_path,
                 matches,
                 progressbars,
-                conn,
+                db_connection_config()?,
                 &config,
                 repo,
                 repo_path,
@@ -190,6 +270,52 @@ async fn main() -> Result<()> {
             .await
             .context("build command failed")?
         }
+        Some(("rebuild-dependents", matches)) => {
+            let repo = load_repo()?;
+
+            crate::commands::rebuild_dependents(
+                repo_path,
+                matches,
+                progressbars,
+                db_connection_config()?,
+                &config,
+                repo,
+                repo_path,
+            )
+            .await
+            .context("rebuild-dependents command failed")?
+        }
+        Some(("queue", matches)) => {
+            crate::commands::queue(
+                repo_path,
+                matches,
+                progressbars.clone(),
+                db_connection_config()?,
+                &config,
+                load_repo,
+                repo_path,
+            )
+            .await
+            .context("queue command failed")?
+        }
+        Some(("submit", matches)) => {
+            crate::commands::submit(matches, db_connection_config()?, repo_path)
+                .await
+                .context("submit command failed")?
+        }
+        Some(("daemon", matches)) => {
+            crate::commands::daemon(
+                repo_path,
+                matches,
+                progressbars.clone(),
+                db_connection_config()?,
+                &config,
+                load_repo,
+                repo_path,
+            )
+            .await
+            .context("daemon command failed")?
+        }
         Some(("what-depends", matches)) => {
             let repo = load_repo()?;
             crate::commands::what_depends(matches, &config, repo)
@@ -206,7 +332,7 @@ async fn main() -> Result<()> {
 
         Some(("versions-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::versions_of(matches, repo)
+            crate::commands::versions_of(matches, &config, repo)
                 .await
                 .context("versions-of command failed")?
         }
@@ -220,7 +346,7 @@ async fn main() -> Result<()> {
 
         Some(("find-artifact", matches)) => {
             let repo = load_repo()?;
-            let conn = db_connection_config.establish_connection()?;
+            let conn = db_connection_config()?.establish_connection()?;
             crate::commands::find_artifact(matches, &config, progressbars, repo, conn)
                 .await
                 .context("find-artifact command failed")?
@@ -241,7 +367,7 @@ async fn main() -> Result<()> {
         }
 
         Some(("release", matches)) => {
-            crate::commands::release(db_connection_config, &config, matches)
+            crate::commands::release(db_connection_config()?, &config, matches)
                 .await
                 .context("release command failed")?
         }
@@ -253,16 +379,40 @@ async fn main() -> Result<()> {
                 .context("lint command failed")?
         }
 
+        Some(("repo", matches)) => {
+            crate::commands::repo(repo_path, matches, &config, progressbars.clone(), load_repo)
+                .await
+                .context("repo command failed")?
+        }
+
+        Some(("audit", matches)) => {
+            let repo = load_repo()?;
+            crate::commands::audit(matches, &config, repo)
+                .await
+                .context("audit command failed")?
+        }
+
         Some(("tree-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::tree_of(matches, repo)
+            crate::commands::tree_of(matches, repo, repo_path, &config, progressbars)
                 .await
                 .context("tree-of command failed")?
         }
 
+        Some(("logs", matches)) => crate::commands::logs(matches, db_connection_config)
+            .await
+            .context("logs command failed")?,
+
+        Some(("browse", _)) => {
+            let repo = load_repo()?;
+            crate::commands::browse(&config, repo, db_connection_config()?)
+                .await
+                .context("browse command failed")?
+        }
+
         Some(("metrics", _)) => {
             let repo = load_repo()?;
-            let conn = db_connection_config.establish_connection()?;
+            let conn = db_connection_config()?.establish_connection()?;
             crate::commands::metrics(repo_path, &config, repo, conn)
                 .await
                 .context("metrics command failed")?
@@ -273,6 +423,13 @@ async fn main() -> Result<()> {
                 .await
                 .context("endpoint command failed")?
         },
+
+        #[cfg(feature = "grpc")]
+        Some(("serve", matches)) => {
+            crate::commands::serve(db_connection_config()?, &config, matches)
+                .await
+                .context("serve command failed")?
+        },
         Some((other, _)) => {
             error!("Unknown subcommand: {}", other);
             error!("Use --help to find available subcommands");
@@ -0,0 +1,368 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications as email
+//!
+//! This is a minimal SMTP client, which is sufficient for sending plain text mails to a relay.
+//! Non-ASCII subjects are encoded as RFC 2047 encoded words, non-ASCII texts are sent as 8bit if
+//! the server supports 8BITMIME and base64 encoded otherwise.
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use base64::Engine;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufStream;
+use tokio::net::TcpStream;
+use tracing::trace;
+
+use crate::config::EmailConfig;
+use crate::config::SmtpTls;
+use crate::notification::Notification;
+
+/// The maximum length of a line of a base64 encoded body
+const BASE64_LINE_LENGTH: usize = 76;
+
+/// The maximum number of bytes that are encoded in one RFC 2047 encoded word, so that a header
+/// line with an encoded word is not longer than 76 characters
+const ENCODED_WORD_BYTES: usize = 39;
+
+pub async fn send(config: &EmailConfig, notification: &Notification) -> Result<()> {
+    if config.tls() == SmtpTls::None && config.username().is_some() {
+        return Err(anyhow!("Refusing to send the SMTP credentials to {} without TLS", config.server()))
+    }
+
+    let subject = format!("[butido] {} {}: {}",
+        notification.package.name,
+        notification.package.version,
+        notification.event);
+    let message = Message {
+        subject: &subject,
+        text: &notification.to_string(),
+        date: chrono::Utc::now(),
+    };
+
+    let tcp = TcpStream::connect((config.server().as_str(), config.port()))
+        .await
+        .with_context(|| format!("Connecting to SMTP server {}:{}", config.server(), config.port()))?;
+
+    let result = match config.tls() {
+        SmtpTls::None => Smtp::connect(tcp, true).await?.deliver(config, &message).await,
+        SmtpTls::Tls => Smtp::connect(tls(config, tcp).await?, true).await?.deliver(config, &message).await,
+        SmtpTls::StartTls => {
+            let mut smtp = Smtp::connect(tcp, true).await?;
+            smtp.command("STARTTLS", 220).await?;
+            let stream = tls(config, smtp.stream.into_inner()).await?;
+
+            // There is no new greeting after STARTTLS, we start with EHLO right away
+            Smtp::connect(stream, false).await?.deliver(config, &message).await
+        },
+    };
+    result.with_context(|| format!("Sending notification email via {}", config.server()))
+}
+
+async fn tls(config: &EmailConfig, tcp: TcpStream) -> Result<tokio_native_tls::TlsStream<TcpStream>> {
+    let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+    connector
+        .connect(config.server(), tcp)
+        .await
+        .with_context(|| format!("Establishing TLS connection to {}", config.server()))
+}
+
+struct Message<'a> {
+    subject: &'a str,
+    text: &'a str,
+    date: chrono::DateTime<chrono::Utc>,
+}
+
+/// How the text of a mail is encoded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TransferEncoding {
+    SevenBit,
+    EightBit,
+    Base64,
+}
+
+impl TransferEncoding {
+    /// The encoding for `text`, 8bit is only used if the server supports it
+    fn for_text(text: &str, eight_bit_mime: bool) -> Self {
+        if text.is_ascii() {
+            TransferEncoding::SevenBit
+        } else if eight_bit_mime {
+            TransferEncoding::EightBit
+        } else {
+            TransferEncoding::Base64
+        }
+    }
+
+    fn header(&self) -> &'static str {
+        match self {
+            TransferEncoding::SevenBit => "7bit",
+            TransferEncoding::EightBit => "8bit",
+            TransferEncoding::Base64 => "base64",
+        }
+    }
+}
+
+/// Encode a header value as RFC 2047 encoded words if it is not ASCII
+///
+/// The encoded words are folded onto separate lines and never split a character.
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string()
+    }
+
+    let mut words = Vec::new();
+    let mut start = 0;
+    while start < value.len() {
+        let mut end = value.len().min(start + ENCODED_WORD_BYTES);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        words.push(format!("=?utf-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(&value[start..end])));
+        start = end;
+    }
+    words.join("\r\n ")
+}
+
+/// Format the mail with the headers and the text, with CRLF line endings and dot-stuffing
+fn format_message(config: &EmailConfig, message: &Message<'_>, encoding: TransferEncoding) -> String {
+    let headers = [
+        format!("From: {}", config.from()),
+        format!("To: {}", config.to().join(", ")),
+        format!("Subject: {}", encode_header_value(message.subject)),
+        format!("Date: {}", message.date.to_rfc2822()),
+        String::from("MIME-Version: 1.0"),
+        String::from("Content-Type: text/plain; charset=utf-8"),
+        format!("Content-Transfer-Encoding: {}", encoding.header()),
+    ];
+
+    let text = message.text.lines().collect::<Vec<_>>().join("\r\n");
+    let lines = match encoding {
+        TransferEncoding::SevenBit | TransferEncoding::EightBit => text.split("\r\n").map(String::from).collect::<Vec<_>>(),
+        TransferEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .encode(text)
+            .as_bytes()
+            .chunks(BASE64_LINE_LENGTH)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect(),
+    };
+
+    let body = lines.into_iter().map(|line| {
+        if line.starts_with('.') {
+            format!(".{line}")
+        } else {
+            line
+        }
+    });
+
+    headers
+        .into_iter()
+        .chain(std::iter::once(String::new()))
+        .chain(body)
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+struct Smtp<S> {
+    stream: BufStream<S>,
+
+    /// The extensions the server announced in its reply to EHLO
+    extensions: Vec<String>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Smtp<S> {
+    /// Set up the SMTP session, after reading the greeting of the server if `greeting` is set
+    async fn connect(stream: S, greeting: bool) -> Result<Self> {
+        let mut smtp = Smtp { stream: BufStream::new(stream), extensions: Vec::new() };
+        if greeting {
+            smtp.expect(220).await?;
+        }
+        let reply = smtp.command("EHLO localhost", 250).await?;
+        smtp.extensions = ehlo_extensions(&reply);
+        Ok(smtp)
+    }
+
+    /// Read a (possibly multiline) reply and check its code
+    async fn expect(&mut self, code: u16) -> Result<String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(anyhow!("SMTP server closed the connection"))
+            }
+            trace!("SMTP: {}", line.trim_end());
+            reply.push_str(&line);
+
+            // The last line of a reply has a space after the code, the others a dash
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break
+            }
+        }
+
+        match reply.get(..3).and_then(|c| c.parse::<u16>().ok()) {
+            Some(c) if c == code => Ok(reply),
+            _ => Err(anyhow!("Unexpected SMTP reply (expected {}): {}", code, reply.trim_end())),
+        }
+    }
+
+    async fn command(&mut self, command: &str, code: u16) -> Result<String> {
+        self.stream.write_all(command.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+        self.expect(code).await
+    }
+
+    async fn deliver(mut self, config: &EmailConfig, message: &Message<'_>) -> Result<()> {
+        let eight_bit_mime = self.extensions.iter().any(|extension| extension == "8BITMIME");
+        let encoding = TransferEncoding::for_text(message.text, eight_bit_mime);
+        let message = format_message(config, message, encoding);
+
+        if let (Some(username), Some(password)) = (config.username(), config.password()) {
+            let credentials = base64::engine::general_purpose::STANDARD.encode(format!("\0{username}\0{password}"));
+            self.command(&format!("AUTH PLAIN {credentials}"), 235)
+                .await
+                .context("Authenticating at SMTP server")?;
+        }
+
+        if encoding == TransferEncoding::EightBit {
+            self.command(&format!("MAIL FROM:<{}> BODY=8BITMIME", config.from()), 250).await?;
+        } else {
+            self.command(&format!("MAIL FROM:<{}>", config.from()), 250).await?;
+        }
+        for to in config.to() {
+            self.command(&format!("RCPT TO:<{to}>"), 250).await?;
+        }
+        self.command("DATA", 354).await?;
+        self.command(&format!("{message}\r\n."), 250).await?;
+        self.command("QUIT", 221).await.map(|_| ())
+    }
+}
+
+/// The keywords of the extensions in the reply to EHLO, the first line is the greeting
+fn ehlo_extensions(reply: &str) -> Vec<String> {
+    reply.lines()
+        .skip(1)
+        .filter_map(|line| line.get(4..))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_uppercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message() {
+        let config: EmailConfig = toml::from_str(indoc::indoc!(r#"
+            server = "smtp.example.com"
+            from = "butido@example.com"
+            to = ["a@example.com", "b@example.com"]
+        "#))
+        .unwrap();
+        assert_eq!(config.tls(), SmtpTls::StartTls);
+        assert_eq!(config.port(), 587);
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-01-12T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        let message = Message { subject: "Subject", text: "line 1\n.line 2", date };
+        let message = format_message(&config, &message, TransferEncoding::EightBit);
+        assert_eq!(message, [
+            "From: butido@example.com",
+            "To: a@example.com, b@example.com",
+            "Subject: Subject",
+            "Date: Wed, 12 Jan 2022 03:04:05 +0000",
+            "MIME-Version: 1.0",
+            "Content-Type: text/plain; charset=utf-8",
+            "Content-Transfer-Encoding: 8bit",
+            "",
+            "line 1",
+            "..line 2",
+        ].join("\r\n"));
+    }
+
+    #[test]
+    fn test_format_message_base64() {
+        let config: EmailConfig = toml::from_str(indoc::indoc!(r#"
+            server = "smtp.example.com"
+            from = "butido@example.com"
+            to = ["a@example.com"]
+        "#))
+        .unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-01-12T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        let text = format!("Grüße\n{}", "x".repeat(100));
+        let message = Message { subject: "Grüße", text: &text, date };
+        let message = format_message(&config, &message, TransferEncoding::Base64);
+
+        let (headers, body) = message.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("\r\nSubject: =?utf-8?B?R3LDvMOfZQ==?=\r\n"));
+        assert!(headers.ends_with("\r\nContent-Transfer-Encoding: base64"));
+        assert!(body.split("\r\n").all(|line| line.len() <= BASE64_LINE_LENGTH));
+
+        let decoded = base64::engine::general_purpose::STANDARD.decode(body.replace("\r\n", "")).unwrap();
+        assert_eq!(String::from_utf8(decoded).unwrap(), format!("Grüße\r\n{}", "x".repeat(100)));
+    }
+
+    #[test]
+    fn test_transfer_encoding() {
+        assert_eq!(TransferEncoding::for_text("plain", false), TransferEncoding::SevenBit);
+        assert_eq!(TransferEncoding::for_text("plain", true), TransferEncoding::SevenBit);
+        assert_eq!(TransferEncoding::for_text("Grüße", true), TransferEncoding::EightBit);
+        assert_eq!(TransferEncoding::for_text("Grüße", false), TransferEncoding::Base64);
+    }
+
+    #[test]
+    fn test_encode_header_value() {
+        assert_eq!(encode_header_value("[butido] foo 1.0: failed"), "[butido] foo 1.0: failed");
+
+        // Long values are split into several encoded words, without splitting a character
+        let value = "ü".repeat(30);
+        let encoded = encode_header_value(&value);
+        let words = encoded.split("\r\n ").collect::<Vec<_>>();
+        assert_eq!(words.len(), 2);
+        assert!(words.iter().all(|word| word.len() <= 75 - "Subject: ".len()));
+
+        let decoded = words
+            .iter()
+            .map(|word| word.strip_prefix("=?utf-8?B?").and_then(|w| w.strip_suffix("?=")).unwrap())
+            .map(|word| String::from_utf8(base64::engine::general_purpose::STANDARD.decode(word).unwrap()).unwrap())
+            .collect::<String>();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_ehlo_extensions() {
+        let reply = "250-smtp.example.com Hello\r\n250-8bitmime\r\n250-AUTH PLAIN LOGIN\r\n250 STARTTLS\r\n";
+        assert_eq!(ehlo_extensions(reply), ["8BITMIME", "AUTH", "STARTTLS"]);
+        assert!(ehlo_extensions("250 smtp.example.com\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_refuse_auth_without_tls() {
+        let config: EmailConfig = toml::from_str(indoc::indoc!(r#"
+            server = "smtp.example.com"
+            from = "butido@example.com"
+            to = ["a@example.com"]
+            tls = "none"
+            username = "butido"
+            password = "secret"
+        "#))
+        .unwrap();
+
+        let package = crate::package::tests::package("a", "1", "https://rust-lang.org", "123");
+        let notification = Notification::job_failed(uuid::Uuid::new_v4(), &package, uuid::Uuid::new_v4(), &anyhow!("failed"));
+        let err = tokio::runtime::Runtime::new().unwrap().block_on(send(&config, &notification)).unwrap_err();
+        assert!(err.to_string().contains("without TLS"), "{err}");
+    }
+}
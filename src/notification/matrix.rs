@@ -0,0 +1,45 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications as messages to a Matrix room
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::config::MatrixConfig;
+use crate::notification::Notification;
+
+pub async fn send(client: &reqwest::Client, config: &MatrixConfig, notification: &Notification) -> Result<()> {
+    // PUT /_matrix/client/v3/rooms/{roomId}/send/m.room.message/{txnId}
+    let mut url = config.homeserver().clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("Not a valid homeserver URL: {}", config.homeserver()))?
+        .pop_if_empty()
+        .extend(["_matrix", "client", "v3", "rooms", config.room().as_str(), "send", "m.room.message"])
+        .push(&Uuid::new_v4().to_string());
+
+    let payload = serde_json::json!({
+        "msgtype": "m.text",
+        "body": notification.to_string(),
+    });
+
+    client
+        .put(url)
+        .bearer_auth(config.access_token())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("Sending notification to Matrix room {}", config.room()))?;
+    Ok(())
+}
@@ -0,0 +1,322 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications about submits and jobs
+//!
+//! The notifications are sent to the configured webhooks (as JSON) and to the builtin backends
+//! (as text message).
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use handlebars::Handlebars;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::NotificationBackendConfig;
+use crate::config::WebhookConfig;
+use crate::package::Package;
+
+mod email;
+mod matrix;
+mod slack;
+
+/// The time after which sending a notification to a webhook is aborted
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, parse_display::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    #[display("submit_started")]
+    SubmitStarted,
+
+    #[display("submit_finished")]
+    SubmitFinished,
+
+    #[display("job_succeeded")]
+    JobSucceeded,
+
+    #[display("job_failed")]
+    JobFailed,
+}
+
+impl NotificationEvent {
+    pub fn all() -> Vec<NotificationEvent> {
+        vec![
+            NotificationEvent::SubmitStarted,
+            NotificationEvent::SubmitFinished,
+            NotificationEvent::JobSucceeded,
+            NotificationEvent::JobFailed,
+        ]
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct NotificationPackage {
+    name: String,
+    version: String,
+}
+
+/// A notification about a submit or a job
+///
+/// The package is the package of the submit (for submit events) or of the job (for job events).
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    event: NotificationEvent,
+    submit: Uuid,
+    package: NotificationPackage,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<Uuid>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failed_jobs: Option<usize>,
+}
+
+impl Notification {
+    fn new(event: NotificationEvent, submit: Uuid, package: &Package) -> Self {
+        Notification {
+            event,
+            submit,
+            package: NotificationPackage {
+                name: package.name().to_string(),
+                version: package.version().to_string(),
+            },
+            job: None,
+            error: None,
+            failed_jobs: None,
+        }
+    }
+
+    pub fn submit_started(submit: Uuid, package: &Package) -> Self {
+        Self::new(NotificationEvent::SubmitStarted, submit, package)
+    }
+
+    /// The submit finished, with the errors of the failed jobs or the error of the submit itself
+    pub fn submit_finished(submit: Uuid, package: &Package, result: &Result<HashMap<Uuid, anyhow::Error>>) -> Self {
+        Notification {
+            failed_jobs: result.as_ref().ok().map(HashMap::len),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            ..Self::new(NotificationEvent::SubmitFinished, submit, package)
+        }
+    }
+
+    pub fn job_succeeded(submit: Uuid, package: &Package, job: Uuid) -> Self {
+        Notification {
+            job: Some(job),
+            ..Self::new(NotificationEvent::JobSucceeded, submit, package)
+        }
+    }
+
+    pub fn job_failed(submit: Uuid, package: &Package, job: Uuid, error: &anyhow::Error) -> Self {
+        Notification {
+            job: Some(job),
+            error: Some(format!("{error:#}")),
+            ..Self::new(NotificationEvent::JobFailed, submit, package)
+        }
+    }
+}
+
+/// The notification as text message, for the chat and email backends
+impl std::fmt::Display for Notification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let package = format!("{} {}", self.package.name, self.package.version);
+        let job = self.job.map(|uuid| uuid.to_string()).unwrap_or_default();
+
+        match self.event {
+            NotificationEvent::SubmitStarted => write!(f, "Submit {} for {} started", self.submit, package)?,
+            NotificationEvent::SubmitFinished => match (self.failed_jobs, self.error.as_ref()) {
+                (_, Some(_)) => write!(f, "Submit {} for {} failed", self.submit, package)?,
+                (Some(0), None) | (None, None) => write!(f, "Submit {} for {} finished successfully", self.submit, package)?,
+                (Some(n), None) => write!(f, "Submit {} for {} finished, {} jobs failed", self.submit, package, n)?,
+            },
+            NotificationEvent::JobSucceeded => write!(f, "Job {} for {} of submit {} succeeded", job, package, self.submit)?,
+            NotificationEvent::JobFailed => write!(f, "Job {} for {} of submit {} failed", job, package, self.submit)?,
+        }
+
+        if let Some(error) = self.error.as_ref() {
+            write!(f, ": {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends notifications to the configured webhooks and backends
+///
+/// Failing to notify a webhook or backend is logged, but does not fail the build.
+#[derive(Debug)]
+pub struct Notifier {
+    client: reqwest::Client,
+    webhooks: Vec<WebhookConfig>,
+    backends: Vec<NotificationBackendConfig>,
+    handlebars: Handlebars<'static>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Notifier {
+            client: reqwest::Client::new(),
+            webhooks: Vec::new(),
+            backends: Vec::new(),
+            handlebars: Handlebars::new(),
+        }
+    }
+}
+
+impl Notifier {
+    pub fn new(webhooks: &[WebhookConfig], backends: &[NotificationBackendConfig]) -> Result<Self> {
+        let mut handlebars = Handlebars::new();
+
+        // The payloads are JSON in general, so values are escaped for JSON strings
+        handlebars.register_escape_fn(|s| {
+            let quoted = serde_json::Value::from(s).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        });
+
+        for (i, webhook) in webhooks.iter().enumerate() {
+            if let Some(payload) = webhook.payload() {
+                handlebars
+                    .register_template_string(&Self::template_name(i), payload)
+                    .with_context(|| format!("Parsing payload template of webhook {}", webhook.url()))?;
+            }
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .context("Setting up HTTP client for webhooks")?;
+
+        Ok(Notifier {
+            client,
+            webhooks: webhooks.to_vec(),
+            backends: backends.to_vec(),
+            handlebars,
+        })
+    }
+
+    fn template_name(i: usize) -> String {
+        format!("webhook-{i}")
+    }
+
+    /// Render the payload of the notification for the webhook with index `i`
+    fn payload(&self, i: usize, notification: &Notification) -> Result<String> {
+        match self.webhooks[i].payload() {
+            Some(_) => self.handlebars
+                .render(&Self::template_name(i), notification)
+                .context("Rendering webhook payload"),
+            None => serde_json::to_string(notification).context("Serializing notification"),
+        }
+    }
+
+    /// Send the notification to all webhooks and backends that are configured for its event
+    pub async fn notify(&self, notification: &Notification) {
+        let webhook_requests = self.webhooks
+            .iter()
+            .enumerate()
+            .filter(|(_, webhook)| webhook.events().contains(&notification.event))
+            .map(|(i, webhook)| async move {
+                debug!("Notifying {} about {}", webhook.url(), notification.event);
+                let payload = self.payload(i, notification)?;
+                self.client
+                    .post(webhook.url().clone())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(payload)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .with_context(|| format!("Notifying {} about {}", webhook.url(), notification.event))?;
+                Ok::<_, anyhow::Error>(())
+            });
+
+        let backend_requests = self.backends
+            .iter()
+            .filter(|backend| backend.events().contains(&notification.event))
+            .map(|backend| async move {
+                match backend {
+                    NotificationBackendConfig::Matrix(config) => matrix::send(&self.client, config, notification).await,
+                    NotificationBackendConfig::Slack(config) => slack::send(&self.client, config, notification).await,
+                    NotificationBackendConfig::Email(config) => email::send(config, notification).await,
+                }
+            });
+
+        let (webhook_results, backend_results) = futures::join!(
+            futures::future::join_all(webhook_requests),
+            futures::future::join_all(backend_requests),
+        );
+        for result in webhook_results.into_iter().chain(backend_results) {
+            if let Err(e) = result {
+                warn!("Failed to send notification: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_payload() {
+        let webhooks: Vec<WebhookConfig> = serde_json::from_value(serde_json::json!([
+            { "url": "https://example.com/a" },
+            {
+                "url": "https://example.com/b",
+                "events": ["job_failed"],
+                "payload": r#"{ "text": "{{package.name}} {{package.version}}: {{event}} {{error}}" }"#,
+            },
+        ]))
+        .unwrap();
+        assert_eq!(webhooks[0].events(), &NotificationEvent::all());
+
+        let notifier = Notifier::new(&webhooks, &[]).unwrap();
+        let submit = Uuid::new_v4();
+        let job = Uuid::new_v4();
+        let notification = Notification::job_failed(submit, &package("a", "1", "https://rust-lang.org", "123"), job, &anyhow::anyhow!("script \"build\" failed"));
+
+        let payload = notifier.payload(0, &notification).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&payload).unwrap(), serde_json::json!({
+            "event": "job_failed",
+            "submit": submit.to_string(),
+            "package": { "name": "a", "version": "1" },
+            "job": job.to_string(),
+            "error": "script \"build\" failed",
+        }));
+
+        let payload = notifier.payload(1, &notification).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&payload).unwrap(), serde_json::json!({
+            "text": "a 1: job_failed script \"build\" failed",
+        }));
+    }
+
+    #[test]
+    fn test_display() {
+        let submit = Uuid::new_v4();
+        let job = Uuid::new_v4();
+        let pkg = package("a", "1", "https://rust-lang.org", "123");
+
+        assert_eq!(Notification::submit_started(submit, &pkg).to_string(), format!("Submit {submit} for a 1 started"));
+        assert_eq!(Notification::submit_finished(submit, &pkg, &Ok(HashMap::new())).to_string(),
+            format!("Submit {submit} for a 1 finished successfully"));
+        assert_eq!(Notification::submit_finished(submit, &pkg, &Err(anyhow::anyhow!("no endpoint"))).to_string(),
+            format!("Submit {submit} for a 1 failed: no endpoint"));
+        assert_eq!(Notification::job_failed(submit, &pkg, job, &anyhow::anyhow!("script failed")).to_string(),
+            format!("Job {job} for a 1 of submit {submit} failed: script failed"));
+    }
+}
@@ -0,0 +1,31 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications to Slack-compatible incoming webhooks
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config::SlackConfig;
+use crate::notification::Notification;
+
+pub async fn send(client: &reqwest::Client, config: &SlackConfig, notification: &Notification) -> Result<()> {
+    let payload = serde_json::json!({ "text": notification.to_string() });
+
+    client
+        .post(config.url().clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("Sending notification to Slack webhook {}", config.url()))?;
+    Ok(())
+}
@@ -14,3 +14,6 @@ pub use orchestrator::*;
 
 mod util;
 
+pub(crate) mod status;
+pub use status::FIRST_LINE_HEADER;
+
@@ -12,6 +12,8 @@
 
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -23,7 +25,7 @@ use diesel::PgConnection;
 use git2::Repository;
 use indicatif::ProgressBar;
 use itertools::Itertools;
-use tracing::{debug, trace, error};
+use tracing::{debug, trace, error, warn};
 use resiter::FilterMap;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::Receiver;
@@ -33,8 +35,10 @@ use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use crate::config::Configuration;
+use crate::config::HookEvent;
 use crate::db::models as dbmodels;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::EndpointFailure;
 use crate::endpoint::EndpointScheduler;
 use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
@@ -42,8 +46,15 @@ use crate::filestore::StagingStore;
 use crate::job::Dag;
 use crate::job::JobDefinition;
 use crate::job::RunnableJob;
+use crate::log::LogFiles;
+use crate::log::LogForwarder;
+use crate::notification::Notification;
+use crate::notification::Notifier;
+use crate::package::PackageName;
 use crate::orchestrator::util::*;
 use crate::source::SourceCache;
+use crate::ui::Dashboard;
+use crate::ui::JobStatus;
 use crate::util::EnvironmentVariableName;
 use crate::util::progress::ProgressBars;
 
@@ -156,6 +167,9 @@ use crate::util::progress::ProgressBars;
 ///
 pub struct Orchestrator<'a> {
     scheduler: EndpointScheduler,
+
+    /// The scheduling priority of each job
+    priorities: HashMap<Uuid, i32>,
     progress_generator: ProgressBars,
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
@@ -164,6 +178,17 @@ pub struct Orchestrator<'a> {
     config: &'a Configuration,
     repository: Repository,
     database: Arc<PgConnection>,
+    continuous_release: Option<ContinuousRelease>,
+
+    /// The UUID of the submit, for the notifications about its jobs
+    submit_uuid: Uuid,
+    notifier: Arc<Notifier>,
+
+    /// Whether the dashboard is shown while the jobs run
+    show_dashboard: bool,
+
+    /// The server of the status endpoint, if any
+    status_server: Option<tokio::task::JoinHandle<()>>,
 }
 
 #[derive(TypedBuilder)]
@@ -176,9 +201,52 @@ pub struct OrchestratorSetup<'a> {
     jobdag: Dag,
     database: Arc<PgConnection>,
     submit: dbmodels::Submit,
-    log_dir: Option<PathBuf>,
+    log_files: Option<LogFiles>,
     config: &'a Configuration,
+
+    /// Forwards the logs of the jobs to external log collectors
+    #[builder(default)]
+    log_forwarder: Option<LogForwarder>,
     repository: Repository,
+
+    #[builder(default)]
+    continuous_release: Option<ContinuousRelease>,
+
+    /// The maximum number of jobs that run in parallel on all endpoints together
+    #[builder(default)]
+    max_parallel_jobs: Option<usize>,
+
+    /// Overrides of the scheduling priorities of packages
+    #[builder(default)]
+    priorities: HashMap<PackageName, i32>,
+
+    /// Show the dashboard instead of the progress bars of the jobs
+    #[builder(default)]
+    dashboard: bool,
+
+    /// The address to serve the status of the submit on
+    #[builder(default)]
+    status_address: Option<SocketAddr>,
+
+    /// Write the events of the jobs as JSON lines to stdout
+    #[builder(default)]
+    json_lines: bool,
+
+    /// The notifier for the events of the jobs
+    #[builder(default)]
+    notifier: Arc<Notifier>,
+}
+
+/// A release store that the artifacts of a job are released to as soon as the job succeeded
+///
+/// The releases are recorded as "partial" in the database, until the whole submit finished
+/// successfully.
+#[derive(Clone, Debug)]
+pub struct ContinuousRelease {
+    /// The directory of the release store
+    pub directory: PathBuf,
+
+    pub store: dbmodels::ReleaseStore,
 }
 
 impl<'a> OrchestratorSetup<'a> {
@@ -189,12 +257,32 @@ impl<'a> OrchestratorSetup<'a> {
             self.release_stores.clone(),
             self.database.clone(),
             self.submit.clone(),
-            self.log_dir,
+            self.log_files,
+            self.log_forwarder,
+            self.config.log_archive().clone().map(crate::log::LogArchive::new),
+            crate::log::LogOptions {
+                max_size: self.config.max_log_size().map(|size| size.as_u64()),
+                strip_ansi_escapes: *self.config.strip_ansi_escapes(),
+            },
+            self.max_parallel_jobs,
+            match (self.dashboard || self.status_address.is_some(), self.json_lines) {
+                (_, true) => Some(Arc::new(Dashboard::new().with_events(std::io::stdout()))),
+                (true, false) => Some(Arc::new(Dashboard::new())),
+                (false, false) => None,
+            },
         )
         .await?;
 
+        let status_server = match (self.status_address, scheduler.dashboard()) {
+            (Some(address), Some(dashboard)) => Some(crate::orchestrator::status::serve(address, dashboard.clone())?),
+            _ => None,
+        };
+
+        let priorities = self.jobdag.priorities(&self.priorities);
+
         Ok(Orchestrator {
             scheduler,
+            priorities,
             staging_store: self.staging_store.clone(),
             release_stores: self.release_stores.clone(),
             progress_generator: self.progress_generator,
@@ -203,6 +291,11 @@ impl<'a> OrchestratorSetup<'a> {
             config: self.config,
             database: self.database,
             repository: self.repository,
+            continuous_release: self.continuous_release,
+            submit_uuid: self.submit.uuid,
+            notifier: self.notifier,
+            show_dashboard: self.dashboard,
+            status_server,
         })
     }
 }
@@ -257,8 +350,14 @@ impl Borrow<ArtifactPath> for ProducedArtifact {
 }
 
 impl<'a> Orchestrator<'a> {
-    pub async fn run(self, output: &mut Vec<ArtifactPath>) -> Result<HashMap<Uuid, Error>> {
-        let (results, errors) = self.run_tree().await?;
+    pub async fn run(mut self, output: &mut Vec<ArtifactPath>) -> Result<HashMap<Uuid, Error>> {
+        let status_server = self.status_server.take();
+        let result = self.run_tree().await;
+        if let Some(status_server) = status_server {
+            status_server.abort();
+        }
+
+        let (results, errors) = result?;
         output.extend(results.into_iter());
         Ok(errors)
     }
@@ -272,6 +371,9 @@ impl<'a> Orchestrator<'a> {
             mp
         });
 
+        // The bar for all jobs is added first, so it is shown above the bars of the jobs
+        let overall_bar = multibar.add(self.progress_generator.overall_bar(self.jobdag.iter().count() as u64)?);
+
         let git_author_env = {
             self.config
                 .containers()
@@ -320,10 +422,17 @@ impl<'a> Orchestrator<'a> {
                 let bar = self.progress_generator.bar()?;
                 let bar = multibar.add(bar);
                 bar.set_length(100);
+                let priority = self.priorities.get(jobdef.job.uuid()).copied().unwrap_or_default();
+                if let Some(dashboard) = self.scheduler.dashboard() {
+                    dashboard.add_job(*jobdef.job.uuid(), format!("{} {}", jobdef.job.package().name(), jobdef.job.package().version()));
+                }
+
                 let tp = TaskPreparation {
                     jobdef,
+                    priority,
 
                     bar,
+                    overall_bar: overall_bar.clone(),
                     config: self.config,
                     git_author_env: git_author_env.as_ref(),
                     git_commit_env: git_commit_env.as_ref(),
@@ -332,6 +441,9 @@ impl<'a> Orchestrator<'a> {
                     staging_store: self.staging_store.clone(),
                     release_stores: self.release_stores.clone(),
                     database: self.database.clone(),
+                    continuous_release: self.continuous_release.as_ref(),
+                    submit_uuid: self.submit_uuid,
+                    notifier: &self.notifier,
                 };
 
                 Ok((receiver, tp, sender, std::cell::RefCell::new(None as Option<Vec<Sender<JobResult>>>)))
@@ -420,8 +532,12 @@ impl<'a> Orchestrator<'a> {
             .collect::<futures::stream::FuturesUnordered<_>>();
         debug!("Built {} jobs", running_jobs.len());
 
-        running_jobs.collect::<Result<()>>().await?;
+        match self.scheduler.dashboard().filter(|_| self.show_dashboard) {
+            Some(dashboard) => dashboard.show_while(self.scheduler.endpoints(), running_jobs.collect::<Result<()>>()).await??,
+            None => running_jobs.collect::<Result<()>>().await?,
+        }
         trace!("All jobs finished");
+        overall_bar.finish_with_message("All jobs finished");
         match root_receiver.recv().await {
             None                     => Err(anyhow!("No result received...")),
             Some(Ok(results)) => {
@@ -444,8 +560,10 @@ impl<'a> Orchestrator<'a> {
 /// This simply holds data and does not contain any more functionality
 struct TaskPreparation<'a> {
     jobdef: JobDefinition<'a>,
+    priority: i32,
 
     bar: ProgressBar,
+    overall_bar: ProgressBar,
 
     config: &'a Configuration,
     git_author_env: Option<&'a (EnvironmentVariableName, String)>,
@@ -455,6 +573,9 @@ struct TaskPreparation<'a> {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Arc<PgConnection>,
+    continuous_release: Option<&'a ContinuousRelease>,
+    submit_uuid: Uuid,
+    notifier: &'a Notifier,
 }
 
 /// Helper type for executing one job task
@@ -462,8 +583,10 @@ struct TaskPreparation<'a> {
 /// This type represents a task for a job that can immediately be executed (see `JobTask::run()`).
 struct JobTask<'a> {
     jobdef: JobDefinition<'a>,
+    priority: i32,
 
     bar: ProgressBar,
+    overall_bar: ProgressBar,
 
     config: &'a Configuration,
     git_author_env: Option<&'a (EnvironmentVariableName, String)>,
@@ -473,6 +596,9 @@ struct JobTask<'a> {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Arc<PgConnection>,
+    continuous_release: Option<&'a ContinuousRelease>,
+    submit_uuid: Uuid,
+    notifier: &'a Notifier,
 
     /// Channel where the dependencies arrive
     receiver: Receiver<JobResult>,
@@ -499,6 +625,8 @@ struct JobTask<'a> {
 /// runtime stops running it because some other `JobTask` errored.
 ///
 /// In the latter case, we cleanup by telling the progressbar to finish.
+///
+/// Either way the job is done, so it is counted in the bar that shows the progress of all jobs.
 impl<'a> Drop for JobTask<'a> {
     fn drop(&mut self) {
         if !self.bar.is_finished() {
@@ -510,12 +638,15 @@ impl<'a> Drop for JobTask<'a> {
                 "error on other task"
             };
 
+            self.set_dashboard_status(JobStatus::Stopped);
             self.bar.finish_with_message(format!("[{} {} {}] Stopped, {msg}",
                 self.jobdef.job.uuid(),
                 self.jobdef.job.package().name(),
                 self.jobdef.job.package().version(),
                 msg = errmsg));
         }
+
+        self.overall_bar.inc(1);
     }
 }
 
@@ -529,8 +660,10 @@ impl<'a> JobTask<'a> {
         ));
         JobTask {
             jobdef: prep.jobdef,
+            priority: prep.priority,
 
             bar,
+            overall_bar: prep.overall_bar,
 
             config: prep.config,
             git_author_env: prep.git_author_env,
@@ -540,6 +673,9 @@ impl<'a> JobTask<'a> {
             staging_store: prep.staging_store,
             release_stores: prep.release_stores,
             database: prep.database.clone(),
+            continuous_release: prep.continuous_release,
+            submit_uuid: prep.submit_uuid,
+            notifier: prep.notifier,
 
             receiver,
             sender,
@@ -597,6 +733,7 @@ impl<'a> JobTask<'a> {
                 // And we know that we have at least one sender
                 error!("[{}]: Received errors = {}", self.jobdef.job.uuid(), received_errors.display_error_map());
                 self.sender[0].send(Err(received_errors)).await;
+                self.set_dashboard_status(JobStatus::Stopped);
 
                 // ... and stop operation, because the whole tree will fail anyways.
                 self.bar.finish_with_message(format!("[{} {} {}] Stopping, errors from child received",
@@ -611,6 +748,28 @@ impl<'a> JobTask<'a> {
             }
         }
 
+        // A meta package has nothing to build, it only passes the artifacts of its dependencies on
+        if self.jobdef.job.package().is_meta() {
+            received_dependencies.insert(*self.jobdef.job.uuid(), Vec::new());
+            for s in self.sender.iter() {
+                s.send(Ok(received_dependencies.clone()))
+                    .await
+                    .context("Cannot send received dependencies to parent")
+                    .with_context(|| {
+                        format!("Sending-Channel is closed in Task for {}: {} {}",
+                            self.jobdef.job.uuid(),
+                            self.jobdef.job.package().name(),
+                            self.jobdef.job.package().version())
+                    })?;
+            }
+            self.set_dashboard_status(JobStatus::Built);
+            self.bar.finish_with_message(format!("[{} {} {}] Meta package, nothing to build",
+                self.jobdef.job.uuid(),
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()));
+            return Ok(())
+        }
+
         // Check if any of the received dependencies was built (and not reused).
         // If any dependency was built, we need to build as well.
         let any_dependency_was_built = received_dependencies.values()
@@ -711,6 +870,7 @@ impl<'a> JobTask<'a> {
                                 self.jobdef.job.package().version())
                         })?;
                 }
+                self.set_dashboard_status(JobStatus::Reused);
                 self.bar.finish_with_message(format!("[{} {} {}] Reusing artifact",
                     self.jobdef.job.uuid(),
                     self.jobdef.job.package().name(),
@@ -736,26 +896,64 @@ impl<'a> JobTask<'a> {
             self.jobdef.job.package().version()
         ));
 
-        // Create a RunnableJob object
-        let runnable = RunnableJob::build_from_job(
-            self.jobdef.job,
-            self.source_cache,
-            self.config,
-            self.git_author_env,
-            self.git_commit_env,
-            dependency_artifacts)?;
-
-        self.bar.set_message(format!("[{} {} {}]: Scheduling...",
-            self.jobdef.job.uuid(),
-            self.jobdef.job.package().name(),
-            self.jobdef.job.package().version()
-        ));
         let job_uuid = *self.jobdef.job.uuid();
+        let retry = self.config.job_retry();
+        let mut retries = 0;
 
         // Schedule the job on the scheduler
-        match self.scheduler.schedule_job(runnable, self.bar.clone()).await?.run().await? {
+        //
+        // If the job fails because of the endpoint or the container (and not because of the
+        // script), it is retried as configured
+        let result = loop {
+            // Create a RunnableJob object
+            let runnable = RunnableJob::build_from_job(
+                self.jobdef.job,
+                self.source_cache,
+                self.config,
+                self.git_author_env,
+                self.git_commit_env,
+                dependency_artifacts.clone())?;
+
+            self.set_dashboard_status(JobStatus::Scheduling);
+            self.bar.set_message(format!("[{} {} {}]: Scheduling...",
+                self.jobdef.job.uuid(),
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()
+            ));
+
+            match self.scheduler.schedule_job(runnable, self.bar.clone(), self.priority).await?.run().await {
+                Err(e) if retries < retry.max_retries() && e.downcast_ref::<EndpointFailure>().is_some() => {
+                    retries += 1;
+                    let delay = retry.delay(retries);
+                    warn!("[{}]: Job failed, retrying ({}/{}) in {}s: {:?}",
+                        job_uuid,
+                        retries,
+                        retry.max_retries(),
+                        delay.as_secs(),
+                        e);
+
+                    self.set_dashboard_status(JobStatus::Retrying);
+                    self.bar.set_message(format!("[{} {} {}]: Failed, retrying ({}/{})...",
+                        self.jobdef.job.uuid(),
+                        self.jobdef.job.package().name(),
+                        self.jobdef.job.package().version(),
+                        retries,
+                        retry.max_retries()
+                    ));
+                    tokio::time::sleep(delay).await;
+                },
+                result => break result?,
+            }
+        };
+
+        match result {
             Err(e) => {
                 trace!("[{}]: Scheduler returned error = {:?}", self.jobdef.job.uuid(), e);
+                self.set_dashboard_status(JobStatus::Failed);
+                self.notifier
+                    .notify(&Notification::job_failed(self.submit_uuid, self.jobdef.job.package(), job_uuid, &e))
+                    .await;
+                self.run_post_job_hooks(&job_uuid, "failed", Some(format!("{e:#}"))).await;
                 // ... and we send that to our parent
                 //
                 // We only send to one parent, because it doesn't matter anymore
@@ -777,6 +975,54 @@ impl<'a> JobTask<'a> {
             Ok(artifacts) => {
                 trace!("[{}]: Scheduler returned artifacts = {:?}", self.jobdef.job.uuid(), artifacts);
 
+                if let Some(continuous_release) = self.continuous_release {
+                    // Failing to release early must not fail the build, the artifacts can still
+                    // be released when the submit finished
+                    if let Err(e) = self.release_continuously(continuous_release, &artifacts).await {
+                        warn!("[{}]: Releasing artifacts to '{}' failed: {:?}",
+                            self.jobdef.job.uuid(),
+                            continuous_release.store.store_name,
+                            e);
+                    }
+                }
+
+                if self.scheduler.dashboard().is_some() || !self.config.hooks().is_empty() {
+                    let paths = {
+                        let staging_store = self.staging_store.read().await;
+                        artifacts
+                            .iter()
+                            .map(|artifact| {
+                                staging_store
+                                    .root_path()
+                                    .join(artifact)
+                                    .map(|path| path.map(|p| p.joined().display().to_string()))
+                                    .map(|path| path.unwrap_or_else(|| artifact.display().to_string()))
+                            })
+                            .collect::<Result<Vec<_>>>()?
+                    };
+
+                    for path in paths {
+                        if let Some(dashboard) = self.scheduler.dashboard() {
+                            dashboard.add_artifact(self.jobdef.job.uuid(), path.clone());
+                        }
+
+                        crate::hooks::run_hooks(self.config.hooks(), HookEvent::Artifact, &[
+                            ("SUBMIT_UUID", self.submit_uuid.to_string()),
+                            ("JOB_UUID", job_uuid.to_string()),
+                            ("PACKAGE_NAME", self.jobdef.job.package().name().to_string()),
+                            ("PACKAGE_VERSION", self.jobdef.job.package().version().to_string()),
+                            ("ARTIFACT_PATH", path),
+                        ])
+                        .await;
+                    }
+                }
+
+                self.set_dashboard_status(JobStatus::Built);
+                self.notifier
+                    .notify(&Notification::job_succeeded(self.submit_uuid, self.jobdef.job.package(), job_uuid))
+                    .await;
+                self.run_post_job_hooks(&job_uuid, "built", None).await;
+
                 // mark the produced artifacts as "built" (rather than reused)
                 let artifacts = artifacts.into_iter().map(ProducedArtifact::Built).collect();
 
@@ -791,6 +1037,67 @@ impl<'a> JobTask<'a> {
         Ok(())
     }
 
+    /// Run the hooks for the end of this job, `status` is "built" or "failed"
+    async fn run_post_job_hooks(&self, job_uuid: &Uuid, status: &str, error: Option<String>) {
+        let mut env = vec![
+            ("SUBMIT_UUID", self.submit_uuid.to_string()),
+            ("JOB_UUID", job_uuid.to_string()),
+            ("PACKAGE_NAME", self.jobdef.job.package().name().to_string()),
+            ("PACKAGE_VERSION", self.jobdef.job.package().version().to_string()),
+            ("STATUS", status.to_string()),
+        ];
+        if let Some(error) = error {
+            env.push(("ERROR", error));
+        }
+
+        crate::hooks::run_hooks(self.config.hooks(), HookEvent::PostJob, &env).await;
+    }
+
+    /// Update the status of this job on the dashboard, if the status of the jobs is tracked
+    fn set_dashboard_status(&self, status: JobStatus) {
+        if let Some(dashboard) = self.scheduler.dashboard() {
+            dashboard.set_status(self.jobdef.job.uuid(), status);
+        }
+    }
+
+    /// Release the artifacts of this job to the continuous release store
+    ///
+    /// The artifacts are copied from the staging store (replacing older releases with the same
+    /// path) and recorded as partial releases in the database.
+    async fn release_continuously(&self, continuous_release: &ContinuousRelease, artifacts: &[ArtifactPath]) -> Result<()> {
+        use crate::schema;
+        use diesel::prelude::*;
+
+        let staging_store = self.staging_store.read().await;
+        let now = chrono::offset::Local::now().naive_local();
+
+        for artifact in artifacts {
+            let source = staging_store.root_path()
+                .join(artifact)?
+                .ok_or_else(|| anyhow!("Artifact not in staging store: {}", artifact.display()))?
+                .joined();
+            let dest = continuous_release.directory.join(artifact);
+
+            debug!("[{}]: Releasing {} to {}", self.jobdef.job.uuid(), source.display(), dest.display());
+            copy_to_release_store(&source, &dest).await?;
+
+            let path_str = artifact.to_str()
+                .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", artifact.display()))?;
+            let db_artifact = schema::artifacts::table
+                .inner_join(schema::jobs::table)
+                .filter(schema::jobs::uuid.eq(*self.jobdef.job.uuid()))
+                .filter(schema::artifacts::path.eq(path_str))
+                .select(schema::artifacts::all_columns)
+                .first::<dbmodels::Artifact>(self.database.as_ref())
+                .with_context(|| anyhow!("Finding artifact {} in database", artifact.display()))?;
+
+            let release = dbmodels::Release::create_partial(self.database.as_ref(), &db_artifact, &now, &continuous_release.store)?;
+            trace!("[{}]: Release = {:?}", self.jobdef.job.uuid(), release);
+        }
+
+        Ok(())
+    }
+
     /// Performe a recv() call on the receiving side of the channel
     ///
     /// Put the dependencies you received into the `received_dependencies`, the errors in the
@@ -850,3 +1157,43 @@ impl<'a> JobTask<'a> {
 
 }
 
+
+/// Copy an artifact to a release store, replacing an older release with the same path
+async fn copy_to_release_store(source: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| anyhow!("Creating {}", parent.display()))?;
+    }
+
+    tokio::fs::copy(source, dest)
+        .await
+        .with_context(|| anyhow!("Copying {} to {}", source.display(), dest.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_to_release_store() {
+        let dir = std::env::temp_dir().join(format!("butido-test-release-{}", uuid::Uuid::new_v4()));
+        let source = dir.join("staging").join("foo-1.0.tar");
+        let dest = dir.join("release").join("debian").join("foo-1.0.tar");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        std::fs::write(&source, "first").unwrap();
+        rt.block_on(copy_to_release_store(&source, &dest)).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "first");
+
+        // A rebuild of the same package replaces the earlier release
+        std::fs::write(&source, "second").unwrap();
+        rt.block_on(copy_to_release_store(&source, &dest)).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "second");
+
+        assert!(rt.block_on(copy_to_release_store(&dir.join("missing"), &dest)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
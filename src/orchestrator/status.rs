@@ -0,0 +1,184 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A read-only HTTP endpoint for the status of a running submit
+//!
+//! * `GET /status`: A summary of the submit (number of jobs, running, finished, failed jobs)
+//! * `GET /jobs`: The status, endpoint and current phase of each job
+//! * `GET /logs/<uuid>`: The log of the current run of a job, `?from=<n>` skips the first `n` lines
+//!
+//! Only the last lines of the log of a job are kept. The line number of the first returned line is
+//! sent in the `X-First-Line` header, so that clients can continue with
+//! `from=<first line + number of lines>` and notice lines they missed.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::Body;
+use hyper::Method;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use hyper::service::make_service_fn;
+use hyper::service::service_fn;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::ui::Dashboard;
+
+/// The header with the line number of the first line of a log response
+pub const FIRST_LINE_HEADER: &str = "x-first-line";
+
+/// Serve the status of the jobs on `address`
+///
+/// The server is bound right away (so that errors are reported before the submit starts) and runs
+/// in the background until the returned handle is aborted.
+pub fn serve(address: SocketAddr, dashboard: Arc<Dashboard>) -> Result<tokio::task::JoinHandle<()>> {
+    let server = hyper::Server::try_bind(&address)?.serve(make_service_fn(move |_| {
+        let dashboard = dashboard.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = handle(&dashboard, &request);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    }));
+
+    info!("Serving the status of the submit on http://{}", address);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Status endpoint failed: {}", e);
+        }
+    }))
+}
+
+fn handle(dashboard: &Dashboard, request: &Request<Body>) -> Response<Body> {
+    debug!("Status request: {} {}", request.method(), request.uri());
+    if request.method() != Method::GET {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+    }
+
+    let path = request.uri().path().trim_end_matches('/');
+    match path {
+        "/status" => json(&dashboard.summary()),
+        "/jobs" => json(&dashboard.jobs()),
+        _ => match path.strip_prefix("/logs/").map(Uuid::from_str) {
+            Some(Ok(uuid)) => match from_line(request).map(|from| dashboard.log(&uuid, from)) {
+                Ok(Some((first, lines))) => {
+                    let mut response = text(StatusCode::OK, lines.join("\n"));
+                    response.headers_mut().insert(FIRST_LINE_HEADER, first.into());
+                    response
+                },
+                Ok(None) => text(StatusCode::NOT_FOUND, "No such job"),
+                Err(_) => text(StatusCode::BAD_REQUEST, "Not a line number"),
+            },
+            Some(Err(_)) => text(StatusCode::BAD_REQUEST, "Not a job UUID"),
+            None => text(StatusCode::NOT_FOUND, "Not found"),
+        },
+    }
+}
+
+/// The value of the `from` query parameter, 0 if it is not set
+fn from_line(request: &Request<Body>) -> Result<usize, std::num::ParseIntError> {
+    request.uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|param| param.strip_prefix("from="))
+        .map(usize::from_str)
+        .unwrap_or(Ok(0))
+}
+
+fn json(value: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap() // the response is always valid
+}
+
+fn text<B: Into<Body>>(status: StatusCode, body: B) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(body.into())
+        .unwrap() // the response is always valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(dashboard: &Dashboard, uri: &str) -> (StatusCode, String) {
+        let (status, _, body) = get_with_first_line(dashboard, uri);
+        (status, body)
+    }
+
+    fn get_with_first_line(dashboard: &Dashboard, uri: &str) -> (StatusCode, Option<String>, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = handle(dashboard, &request);
+        let status = response.status();
+        let first_line = response.headers()
+            .get(FIRST_LINE_HEADER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = futures::executor::block_on(hyper::body::to_bytes(response.into_body())).unwrap();
+        (status, first_line, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_handle() {
+        let dashboard = Dashboard::new();
+        let uuid = Uuid::new_v4();
+        dashboard.add_job(uuid, String::from("a 1"));
+        dashboard.push_log_line(&uuid, String::from("line 1"));
+        dashboard.push_log_line(&uuid, String::from("line 2"));
+
+        let (status, body) = get(&dashboard, "/status");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["jobs"], 1);
+
+        let (status, body) = get(&dashboard, "/jobs/");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()[0]["status"], "waiting");
+
+        assert_eq!(get(&dashboard, &format!("/logs/{uuid}")), (StatusCode::OK, String::from("line 1\nline 2")));
+        assert_eq!(get(&dashboard, &format!("/logs/{uuid}?from=1")), (StatusCode::OK, String::from("line 2")));
+        assert_eq!(get(&dashboard, &format!("/logs/{uuid}?from=5")), (StatusCode::OK, String::new()));
+        assert_eq!(get(&dashboard, &format!("/logs/{uuid}?from=x")).0, StatusCode::BAD_REQUEST);
+        assert_eq!(get(&dashboard, &format!("/logs/{}", Uuid::new_v4())).0, StatusCode::NOT_FOUND);
+        assert_eq!(get(&dashboard, "/logs/foo").0, StatusCode::BAD_REQUEST);
+        assert_eq!(get(&dashboard, "/foo").0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_logs_from() {
+        let dashboard = Dashboard::new();
+        let uuid = Uuid::new_v4();
+        dashboard.add_job(uuid, String::from("a 1"));
+        for i in 0..10_002 {
+            dashboard.push_log_line(&uuid, format!("line {i}"));
+        }
+
+        // The first two lines were dropped
+        let (status, first_line, body) = get_with_first_line(&dashboard, &format!("/logs/{uuid}?from=1"));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(first_line.as_deref(), Some("2"));
+        assert!(body.starts_with("line 2\n"));
+        assert_eq!(body.lines().count(), 10_000);
+
+        let (_, first_line, body) = get_with_first_line(&dashboard, &format!("/logs/{uuid}?from=10001"));
+        assert_eq!(first_line.as_deref(), Some("10001"));
+        assert_eq!(body, "line 10001");
+    }
+}
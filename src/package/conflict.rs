@@ -0,0 +1,146 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersionConstraint;
+
+/// A package that must not be in the same tree as the package declaring the conflict
+///
+/// In the `pkg.toml` file, this is either the name of the conflicting package or the name and a
+/// version constraint, separated by a space:
+///
+/// ```toml
+/// conflicts = [ "libressl", "openssl <1.1" ]
+/// ```
+///
+/// A conflict also applies to packages that provide a virtual package with that name.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Getters)]
+#[serde(try_from = "String", into = "String")]
+pub struct Conflict {
+    #[getset(get = "pub")]
+    name: PackageName,
+
+    #[getset(get = "pub")]
+    constraint: Option<PackageVersionConstraint>,
+}
+
+impl Conflict {
+    /// Check whether the package `p` is covered by this conflict
+    pub fn matches(&self, p: &Package) -> bool {
+        let version = if p.name() == &self.name {
+            Some(p.version())
+        } else {
+            p.provided_version(&self.name)
+        };
+
+        match (version, self.constraint.as_ref()) {
+            (Some(version), Some(constraint)) => constraint.matches(version),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Conflict {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (name, constraint) = match s.trim().split_once(' ') {
+            Some((name, constraint)) => (name, Some(constraint.trim())),
+            None => (s.trim(), None),
+        };
+
+        if name.is_empty() {
+            return Err(anyhow!("Expected '<name>' or '<name> <version constraint>' as conflicting package, got: '{}'", s))
+        }
+
+        Ok(Conflict {
+            name: PackageName::from(name.to_string()),
+            constraint: constraint.map(PackageVersionConstraint::try_from).transpose()?,
+        })
+    }
+}
+
+impl From<Conflict> for String {
+    fn from(c: Conflict) -> String {
+        match c.constraint {
+            Some(constraint) => format!("{} {}", c.name, constraint),
+            None => c.name.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.constraint.as_ref() {
+            Some(constraint) => write!(f, "{} {}", self.name, constraint),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::package::tests::package;
+    use crate::package::provides::Provide;
+
+    #[derive(Deserialize)]
+    struct TestConflicts {
+        conflicts: Vec<Conflict>,
+    }
+
+    fn conflicts(s: &str) -> Vec<Conflict> {
+        toml::from_str::<TestConflicts>(s).unwrap().conflicts
+    }
+
+    #[test]
+    fn test_deserialize_conflicts() {
+        let c = conflicts(r#"conflicts = [ "libressl", "openssl <1.1" ]"#);
+
+        assert_eq!(c[0].name(), &PackageName::from(String::from("libressl")));
+        assert!(c[0].constraint().is_none());
+        assert_eq!(c[1].name(), &PackageName::from(String::from("openssl")));
+        assert!(c[1].constraint().is_some());
+
+        assert!(toml::from_str::<TestConflicts>(r#"conflicts = [ "" ]"#).is_err());
+        assert!(toml::from_str::<TestConflicts>(r#"conflicts = [ "openssl 1.0" ]"#).is_err());
+    }
+
+    #[test]
+    fn test_conflict_matches() {
+        let c = conflicts(r#"conflicts = [ "openssl", "openssl <1.1", "libssl" ]"#);
+
+        let openssl_1_0 = package("openssl", "1.0", "https://rust-lang.org", "123");
+        let openssl_3_0 = package("openssl", "3.0", "https://rust-lang.org", "123");
+        let mut libressl = package("libressl", "3.6", "https://rust-lang.org", "123");
+        libressl.set_provides(vec![Provide::try_from(String::from("libssl")).unwrap()]);
+
+        assert!(c[0].matches(&openssl_1_0));
+        assert!(c[0].matches(&openssl_3_0));
+        assert!(!c[0].matches(&libressl));
+
+        assert!(c[1].matches(&openssl_1_0));
+        assert!(!c[1].matches(&openssl_3_0));
+
+        assert!(c[2].matches(&libressl));
+        assert!(!c[2].matches(&openssl_1_0));
+    }
+}
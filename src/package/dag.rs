@@ -9,7 +9,9 @@
 //
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::Result as IoResult;
 use std::io::Write;
 
@@ -21,17 +23,22 @@ use getset::Getters;
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use tracing::trace;
+use tracing::warn;
 use ptree::Style;
 use ptree::TreeItem;
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
 use resiter::AndThen;
 
 use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
 use crate::package::condition::ConditionCheckable;
 use crate::package::condition::ConditionData;
 use crate::package::dependency::ParseDependency;
 use crate::repository::Repository;
+use crate::util::version::compare_versions;
 
 
 #[derive(Debug, Getters)]
@@ -102,74 +109,311 @@ impl Dag {
                 .unique_by(|res| res.as_ref().ok().cloned())
         }
 
+        /// Helper fn to add the dependencies of `p` (and their dependencies) to the tree
+        ///
+        /// The tree is built level by level: the dependencies of all packages of a level are
+        /// resolved in parallel, because the repository is only read here. The new packages are
+        /// then added to the tree (and the progress bar) one after another.
         fn add_sub_packages<'a>(
             repo: &'a Repository,
+            selection: &HashMap<PackageName, &'a Package>,
             mappings: &mut HashMap<&'a Package, daggy::NodeIndex>,
             dag: &mut daggy::Dag<&'a Package, i8>,
             p: &'a Package,
             progress: Option<&ProgressBar>,
             conditional_data: &ConditionData<'_>,
         ) -> Result<()> {
-            get_package_dependencies(p, conditional_data)
-                .and_then_ok(|(name, constr)| {
-                    trace!("Dependency for {} {} found: {:?}", p.name(), p.version(), name);
-                    let packs = repo.find_with_version(&name, &constr);
-                    if packs.is_empty() {
-                        return Err(anyhow!("Dependency of {} {} not found: {} {}", p.name(), p.version(), name, constr))
-                    }
-                    trace!("Found in repo: {:?}", packs);
+            let mut level = vec![p];
+            while !level.is_empty() {
+                let dependencies = level
+                    .par_iter()
+                    .map(|&p| {
+                        get_package_dependencies(p, conditional_data)
+                            .and_then_ok(|(name, constr)| {
+                                trace!("Dependency for {} {} found: {:?}", p.name(), p.version(), name);
+                                resolve_selected(repo, selection, &name, &constr)?
+                                    .ok_or_else(|| anyhow!("Dependency of {} {} not found: {} {}", p.name(), p.version(), name, constr))
+                            })
+                            .collect::<Result<Vec<&'a Package>>>()
+                    })
+                    .collect::<Result<Vec<_>>>()?;
 
+                level = Vec::new();
+                for pack in dependencies.into_iter().flatten() {
                     // If we didn't check that dependency already
-                    if !mappings.keys().any(|p| packs.iter().any(|pk| pk.name() == p.name() && pk.version() == p.version())) {
-                        // recurse
-                        packs.into_iter()
-                            .try_for_each(|p| {
-                                let _ = progress.as_ref().map(|p| p.tick());
+                    if !mappings.keys().any(|p| pack.name() == p.name() && pack.version() == p.version()) {
+                        let _ = progress.as_ref().map(|p| p.tick());
 
-                                let idx = dag.add_node(p);
-                                mappings.insert(p, idx);
+                        let idx = dag.add_node(pack);
+                        mappings.insert(pack, idx);
 
-                                trace!("Recursing for: {:?}", p);
-                                add_sub_packages(repo, mappings, dag, p, progress, conditional_data)
-                            })
-                    } else {
-                        Ok(())
+                        trace!("Recursing for: {:?}", pack);
+                        level.push(pack);
                     }
-                })
-                .collect::<Result<()>>()
+                }
+            }
+
+            Ok(())
         }
 
-        fn add_edges(mappings: &HashMap<&Package, daggy::NodeIndex>,
-            dag: &mut daggy::Dag<&Package, i8>,
+        /// Helper fn to add the edges between the packages in the tree
+        ///
+        /// The dependencies are resolved in parallel, the edges are added one after another.
+        fn add_edges<'a>(repo: &'a Repository,
+            selection: &HashMap<PackageName, &'a Package>,
+            mappings: &HashMap<&'a Package, daggy::NodeIndex>,
+            dag: &mut daggy::Dag<&'a Package, i8>,
             conditional_data: &ConditionData<'_>,
         ) -> Result<()>
         {
-            for (package, idx) in mappings {
-                get_package_dependencies(package, conditional_data)
-                    .and_then_ok(|(name, constr)| {
-                        mappings
-                            .iter()
-                            .filter(|(package, _)| *package.name() == name && constr.matches(package.version()))
-                            .try_for_each(|(_, dep_idx)| {
-                                dag.add_edge(*idx, *dep_idx, 0)
-                                    .map(|_| ())
-                                    .map_err(Error::from)
-                            })
-                    })
-                    .collect::<Result<()>>()?
+            let edges = mappings
+                .par_iter()
+                .map(|(package, idx)| {
+                    get_package_dependencies(package, conditional_data)
+                        .and_then_ok(|(name, constr)| {
+                            let dependency_idx = resolve_selected(repo, selection, &name, &constr)?
+                                .and_then(|dependency| mappings.get(dependency));
+                            Ok(dependency_idx.map(|dependency_idx| (*idx, *dependency_idx)))
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            for (idx, dependency_idx) in edges.into_iter().flatten().flatten() {
+                dag.add_edge(idx, dependency_idx, 0)
+                    .map_err(|_| {
+                        // The edge would close a cycle, so there is a path back from the
+                        // dependency to the package
+                        let graph = dag.graph();
+                        let cycle = std::iter::once(idx)
+                            .chain(dependency_chain(dag, dependency_idx, idx).unwrap_or_default())
+                            .map(|i| (graph[i].name().clone(), graph[i].version().clone()))
+                            .collect();
+
+                        Error::from(DependencyCycle::new(cycle))
+                    })?;
             }
 
             Ok(())
         }
 
+        /// Helper fn to select one version of each package in the tree
+        ///
+        /// For each package, the highest version (that is not yanked, if possible) that satisfies
+        /// the version constraints of all packages in the tree that depend on it is selected.
+        /// Whenever the selection changes, the constraints are collected again, because the newly
+        /// selected version can have other dependencies. Dependencies that are satisfied by
+        /// providers of virtual packages are not part of the selection.
+        fn solve<'a>(repo: &'a Repository, root: &'a Package, conditional_data: &ConditionData<'_>)
+            -> Result<HashMap<PackageName, &'a Package>>
+        {
+            let mut selection = HashMap::from([(root.name().clone(), root)]);
+
+            // Bound the number of rounds, in case the selection oscillates
+            for _ in 0..=repo.packages().count() {
+                let mut requirements: BTreeMap<PackageName, Vec<(&'a Package, PackageVersionConstraint)>> = BTreeMap::new();
+                let mut visited = HashSet::new();
+                let mut stack = vec![root];
+
+                while let Some(package) = stack.pop() {
+                    if !visited.insert((package.name(), package.version())) {
+                        continue
+                    }
+
+                    for dependency in get_package_dependencies(package, conditional_data) {
+                        let (name, constr) = dependency?;
+
+                        if name != *root.name() && repo.find_with_version(&name, &constr).is_empty() {
+                            // A virtual package (or a dependency that cannot be satisfied at all,
+                            // which is reported when building the tree)
+                            if let Some(provider) = resolve_dependency(repo, &name, &constr)? {
+                                stack.push(provider);
+                            }
+                            continue
+                        }
+
+                        let constraints = requirements.entry(name.clone()).or_default();
+                        constraints.push((package, constr));
+                        if !selection.contains_key(&name) {
+                            if let Some(best) = best_version(repo, &name, constraints) {
+                                selection.insert(name.clone(), best);
+                            }
+                        }
+
+                        if let Some(selected) = selection.get(&name) {
+                            stack.push(*selected);
+                        }
+                    }
+                }
+
+                let mut changed = false;
+                for (name, constraints) in requirements.iter() {
+                    let is_satisfied = selection.get(name)
+                        .map(|selected| constraints.iter().all(|(_, constr)| constr.matches(selected.version())))
+                        .unwrap_or(false);
+                    if is_satisfied {
+                        continue
+                    }
+
+                    // The root package cannot be replaced by another version
+                    match best_version(repo, name, constraints).filter(|_| name != root.name()) {
+                        Some(best) => {
+                            trace!("Selecting {} {}", best.name(), best.version());
+                            selection.insert(name.clone(), best);
+                            changed = true;
+                        },
+                        None => return Err(version_conflict(repo, name, constraints)),
+                    }
+                }
+
+                if !changed {
+                    return Ok(selection)
+                }
+            }
+
+            Err(anyhow!("Could not select versions of the dependencies of {} {} that satisfy all version constraints",
+                root.name(),
+                root.version()))
+        }
+
+        /// Helper fn to find the best version of the package `name` that satisfies all `constraints`
+        fn best_version<'a>(repo: &'a Repository, name: &PackageName, constraints: &[(&Package, PackageVersionConstraint)])
+            -> Option<&'a Package>
+        {
+            repo.find_by_name(name)
+                .into_iter()
+                .filter(|p| constraints.iter().all(|(_, constr)| constr.matches(p.version())))
+                .max_by(|a, b| {
+                    b.is_yanked().cmp(&a.is_yanked())
+                        .then_with(|| compare_versions(a.version(), b.version()))
+                })
+        }
+
+        /// Helper fn to explain which packages demand which versions of the package `name`
+        fn version_conflict(repo: &Repository, name: &PackageName, constraints: &[(&Package, PackageVersionConstraint)]) -> Error {
+            let demands = constraints.iter()
+                .map(|(p, constr)| format!("\t{} {} requires {} {}", p.name(), p.version(), name, constr))
+                .join("\n");
+
+            let versions = repo.find_by_name(name)
+                .into_iter()
+                .map(|p| p.version())
+                .sorted_by(|a, b| compare_versions(a, b))
+                .join(", ");
+
+            anyhow!("No version of {} satisfies all packages that depend on it:\n{}\nAvailable versions: {}", name, demands, versions)
+        }
+
+        /// Helper fn to find the package that satisfies a dependency, preferring the version that
+        /// was selected by `solve`
+        fn resolve_selected<'a>(
+            repo: &'a Repository,
+            selection: &HashMap<PackageName, &'a Package>,
+            name: &PackageName,
+            constr: &PackageVersionConstraint,
+        ) -> Result<Option<&'a Package>> {
+            match selection.get(name) {
+                Some(selected) if constr.matches(selected.version()) => Ok(Some(*selected)),
+                _ => resolve_dependency(repo, name, constr),
+            }
+        }
+
+        /// Helper fn to find the package that satisfies a dependency
+        ///
+        /// Packages with the name of the dependency are preferred over packages that provide it
+        /// as a virtual package. If several versions match, the highest one that is not yanked is
+        /// used.
+        fn resolve_dependency<'a>(repo: &'a Repository, name: &PackageName, constr: &PackageVersionConstraint)
+            -> Result<Option<&'a Package>>
+        {
+            let packs = repo.find_with_version(name, constr);
+            trace!("Found in repo: {:?}", packs);
+            if !packs.is_empty() {
+                return Ok(packs.into_iter().max_by(|a, b| {
+                    b.is_yanked().cmp(&a.is_yanked())
+                        .then_with(|| compare_versions(a.version(), b.version()))
+                }))
+            }
+
+            let providers = repo.find_providers(name, constr)?;
+            trace!("Found providers in repo: {:?}", providers);
+            Ok(providers.into_iter().max_by(|a, b| {
+                let va = a.provided_version(name).unwrap_or_else(|| a.version());
+                let vb = b.provided_version(name).unwrap_or_else(|| b.version());
+                b.is_yanked().cmp(&a.is_yanked())
+                    .then_with(|| compare_versions(va, vb))
+            }))
+        }
+
+        /// Helper fn to find the chain of dependencies from the node `from` to the node `to`
+        fn dependency_chain(dag: &daggy::Dag<&Package, i8>, from: daggy::NodeIndex, to: daggy::NodeIndex)
+            -> Option<Vec<daggy::NodeIndex>>
+        {
+            if from == to {
+                return Some(vec![to])
+            }
+
+            dag.children(from)
+                .iter(dag)
+                .find_map(|(_, child)| {
+                    dependency_chain(dag, child, to).map(|mut chain| {
+                        chain.insert(0, from);
+                        chain
+                    })
+                })
+        }
+
+        /// Helper fn to check that no package in the tree conflicts with another package in the
+        /// tree
+        fn check_conflicts(dag: &daggy::Dag<&Package, i8>, root_idx: daggy::NodeIndex) -> Result<()> {
+            let graph = dag.graph();
+            let format_chain = |idx| {
+                dependency_chain(dag, root_idx, idx)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|idx| format!("{} {}", graph[idx].name(), graph[idx].version()))
+                    .join(" -> ")
+            };
+
+            let conflicts = graph.node_indices()
+                .flat_map(|idx| graph[idx].conflicts().iter().map(move |conflict| (idx, conflict)))
+                .flat_map(|(idx, conflict)| {
+                    graph.node_indices()
+                        .filter(move |other| *other != idx && conflict.matches(graph[*other]))
+                        .map(move |other| (idx, conflict, other))
+                })
+                .map(|(idx, conflict, other)| {
+                    format!("{} {} conflicts with {} {} (conflicts = \"{}\")\n\t{}\n\t{}",
+                        graph[idx].name(),
+                        graph[idx].version(),
+                        graph[other].name(),
+                        graph[other].version(),
+                        conflict,
+                        format_chain(idx),
+                        format_chain(other))
+                })
+                .collect::<Vec<_>>();
+
+            if conflicts.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow!("Conflicting packages in the tree of {} {}:\n{}",
+                    graph[root_idx].name(),
+                    graph[root_idx].version(),
+                    conflicts.join("\n")))
+            }
+        }
+
         let mut dag: daggy::Dag<&Package, i8> = daggy::Dag::new();
         let mut mappings = HashMap::new();
 
         trace!("Making package Tree for {:?}", p);
+        let selection = solve(repo, &p, conditional_data)?;
         let root_idx = dag.add_node(&p);
         mappings.insert(&p, root_idx);
-        add_sub_packages(repo, &mut mappings, &mut dag, &p, progress, conditional_data)?;
-        add_edges(&mappings, &mut dag, conditional_data)?;
+        add_sub_packages(repo, &selection, &mut mappings, &mut dag, &p, progress, conditional_data)?;
+        add_edges(repo, &selection, &mappings, &mut dag, conditional_data)?;
+        check_conflicts(&dag, root_idx)?;
         trace!("Finished makeing package Tree");
 
         Ok(Dag {
@@ -191,11 +435,122 @@ impl Dag {
             .collect()
     }
 
+    /// Check whether deprecated packages were selected as dependencies
+    ///
+    /// Prints a warning for each deprecated dependency, or fails if `deny` is set.
+    pub fn check_deprecated(&self, deny: bool) -> Result<()> {
+        let graph = self.dag.graph();
+        let deprecated = graph.node_indices()
+            .filter(|idx| *idx != self.root_idx)
+            .filter_map(|idx| graph[idx].deprecated().as_ref().map(|d| (idx, d)))
+            .map(|(idx, deprecation)| {
+                let required_by = self.dag
+                    .parents(idx)
+                    .iter(&self.dag)
+                    .map(|(_, parent)| format!("{} {}", graph[parent].name(), graph[parent].version()))
+                    .join(", ");
+
+                format!("{} {} is {} (required by {})", graph[idx].name(), graph[idx].version(), deprecation, required_by)
+            })
+            .collect::<Vec<_>>();
+
+        if deny && !deprecated.is_empty() {
+            return Err(anyhow!("Deprecated packages in the tree:\n{}", deprecated.join("\n")))
+        }
+
+        deprecated.iter().for_each(|msg| warn!("{}", msg));
+        Ok(())
+    }
+
     pub fn display(&self) -> DagDisplay {
         DagDisplay(self, self.root_idx)
     }
+
+    /// Render the DAG as a Graphviz digraph, with an edge from each package to its dependencies
+    pub fn to_dot(&self) -> String {
+        let graph = self.dag.graph();
+        let node = |idx: daggy::NodeIndex| {
+            let p = &graph[idx];
+            let id = format!("{} {}", p.name(), p.version()).replace('\\', "\\\\").replace('"', "\\\"");
+            format!("\"{id}\"")
+        };
+
+        let mut dot = format!("digraph {} {{\n", node(self.root_idx));
+        for idx in graph.node_indices() {
+            dot.push_str(&format!("    {};\n", node(idx)));
+        }
+        for edge in graph.raw_edges() {
+            dot.push_str(&format!("    {} -> {};\n", node(edge.source()), node(edge.target())));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Get the DAG as JSON
+    ///
+    /// ```json
+    /// {
+    ///   "root": 0,
+    ///   "packages": [ { "id": 0, "name": "a", "version": "1" }, { "id": 1, "name": "b", "version": "2" } ],
+    ///   "dependencies": [ { "from": 0, "to": 1 } ]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> serde_json::Value {
+        let graph = self.dag.graph();
+        let packages = graph.node_indices()
+            .map(|idx| serde_json::json!({
+                "id": idx.index(),
+                "name": graph[idx].name(),
+                "version": graph[idx].version(),
+            }))
+            .collect::<Vec<_>>();
+
+        let dependencies = graph.raw_edges()
+            .iter()
+            .map(|edge| serde_json::json!({
+                "from": edge.source().index(),
+                "to": edge.target().index(),
+            }))
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "root": self.root_idx.index(),
+            "packages": packages,
+            "dependencies": dependencies,
+        })
+    }
+}
+
+/// A cycle in the dependencies of packages
+///
+/// The cycle starts and ends with the same package, e.g. `a 1 -> b 1 -> a 1`.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct DependencyCycle(Vec<(PackageName, PackageVersion)>);
+
+impl DependencyCycle {
+    fn new(mut packages: Vec<(PackageName, PackageVersion)>) -> Self {
+        // Start the cycle at the smallest package, so that the same cycle is always represented
+        // the same way, no matter at which package it was found
+        packages.pop();
+        if let Some(min_pos) = packages.iter().position_min() {
+            packages.rotate_left(min_pos);
+        }
+        if let Some(first) = packages.first().cloned() {
+            packages.push(first);
+        }
+
+        DependencyCycle(packages)
+    }
+}
+
+impl std::fmt::Display for DependencyCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dependency cycle: {}", self.0.iter().map(|(name, version)| format!("{name} {version}")).join(" -> "))
+    }
 }
 
+impl std::error::Error for DependencyCycle {}
+
 #[derive(Clone)]
 pub struct DagDisplay<'a>(&'a Dag, daggy::NodeIndex);
 
@@ -304,6 +659,372 @@ mod tests {
         assert!(ps.iter().any(|p| *p.version() == pversion("2")));
     }
 
+    #[test]
+    fn test_export_dag() {
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = package("a", "1", "https://rust-lang.org", "123");
+        p1.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b =2"))));
+        btree.insert((pname("a"), pversion("1")), p1.clone());
+
+        let p2 = package("b", "2", "https://rust-lang.org", "124");
+        btree.insert((pname("b"), pversion("2")), p2);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+
+        assert_eq!(dag.to_dot(), "digraph \"a 1\" {\n    \"a 1\";\n    \"b 2\";\n    \"a 1\" -> \"b 2\";\n}\n");
+        assert_eq!(dag.to_json(), serde_json::json!({
+            "root": 0,
+            "packages": [
+                { "id": 0, "name": "a", "version": "1" },
+                { "id": 1, "name": "b", "version": "2" },
+            ],
+            "dependencies": [ { "from": 0, "to": 1 } ],
+        }));
+    }
+
+    #[test]
+    fn test_add_dependency_with_version_range() {
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = {
+            let name = "a";
+            let vers = "1";
+            let pack = package(name, vers, "https://rust-lang.org", "123");
+            btree.insert((pname(name), pversion(vers)), pack.clone());
+            pack
+        };
+
+        for vers in ["1.9", "1.10", "2.0"] {
+            let name = "b";
+            let pack = package(name, vers, "https://rust-lang.org", "124");
+            btree.insert((pname(name), pversion(vers)), pack);
+        }
+
+        {
+            let d = Dependency::from(String::from("b >=1.0, <2.0"));
+            let ds = Dependencies::with_runtime_dependency(d);
+            p1.set_dependencies(ds);
+        }
+
+        let repo = Repository::from(btree);
+        let progress = ProgressBar::hidden();
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data).unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps.iter().any(|p| *p.name() == pname("b") && *p.version() == pversion("1.10")));
+    }
+
+    fn runtime_dependencies(ds: &[&str]) -> Dependencies {
+        Dependencies::with_runtime_dependencies(ds.iter().map(|d| Dependency::from(d.to_string())).collect())
+    }
+
+    fn repo_with_shared_dependency(c_dependency: &str) -> (Package, Repository) {
+        // a -> b >=1, a -> c =1, c -> `c_dependency`
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = package("a", "1", "https://rust-lang.org", "123");
+        p1.set_dependencies(runtime_dependencies(&["b >=1", "c =1"]));
+        btree.insert((pname("a"), pversion("1")), p1.clone());
+
+        for vers in ["1", "2"] {
+            btree.insert((pname("b"), pversion(vers)), package("b", vers, "https://rust-lang.org", "124"));
+        }
+
+        let mut c = package("c", "1", "https://rust-lang.org", "125");
+        c.set_dependencies(runtime_dependencies(&[c_dependency]));
+        btree.insert((pname("c"), pversion("1")), c);
+
+        (p1, Repository::from(btree))
+    }
+
+    #[test]
+    fn test_add_shared_dependency_with_version_constraints() {
+        let (p1, repo) = repo_with_shared_dependency("b <2");
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        let ps = dag.all_packages();
+
+        // Only the version of b that satisfies both a and c is in the tree
+        assert_eq!(ps.len(), 3);
+        assert!(ps.iter().any(|p| *p.name() == pname("b") && *p.version() == pversion("1")));
+        assert!(!ps.iter().any(|p| *p.name() == pname("b") && *p.version() == pversion("2")));
+    }
+
+    #[test]
+    fn test_add_conflicting_version_constraints() {
+        let (mut p1, repo) = repo_with_shared_dependency("b >=2");
+        p1.set_dependencies(runtime_dependencies(&["b =1", "c =1"]));
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap_err();
+        assert_eq!(err.to_string(), "No version of b satisfies all packages that depend on it:\n\
+            \ta 1 requires b =1\n\
+            \tc 1 requires b >=2\n\
+            Available versions: 1, 2");
+    }
+
+    fn repo_with_virtual_dependency(providers: &[&str]) -> (Package, BTreeMap<(PackageName, crate::package::PackageVersion), Package>) {
+        use std::convert::TryFrom;
+        use crate::package::provides::Provide;
+
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = {
+            let name = "a";
+            let vers = "1";
+            let pack = package(name, vers, "https://rust-lang.org", "123");
+            btree.insert((pname(name), pversion(vers)), pack.clone());
+            pack
+        };
+
+        for name in providers {
+            let vers = "2";
+            let mut pack = package(name, vers, "https://rust-lang.org", "124");
+            pack.set_provides(vec![Provide::try_from(String::from("libjpeg 8")).unwrap()]);
+            btree.insert((pname(name), pversion(vers)), pack);
+        }
+
+        {
+            let d = Dependency::from(String::from("libjpeg >=8"));
+            let ds = Dependencies::with_runtime_dependency(d);
+            p1.set_dependencies(ds);
+        }
+
+        (p1, btree)
+    }
+
+    #[test]
+    fn test_add_virtual_dependency() {
+        let (p1, btree) = repo_with_virtual_dependency(&["libjpeg-turbo"]);
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps.iter().any(|p| *p.name() == pname("libjpeg-turbo")));
+    }
+
+    #[test]
+    fn test_add_virtual_dependency_with_several_providers() {
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let (p1, btree) = repo_with_virtual_dependency(&["libjpeg-turbo", "libjpeg-original"]);
+        let repo = Repository::from(btree);
+        assert!(Dag::for_root_package(p1, &repo, None, &condition_data).is_err());
+
+        let (p1, btree) = repo_with_virtual_dependency(&["libjpeg-turbo", "libjpeg-original"]);
+        let repo = Repository::from(btree)
+            .with_preferred_providers(std::iter::once((pname("libjpeg"), pname("libjpeg-turbo"))).collect());
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps.iter().any(|p| *p.name() == pname("libjpeg-turbo")));
+    }
+
+    #[test]
+    fn test_add_conflicting_packages() {
+        use std::convert::TryFrom;
+        use crate::package::conflict::Conflict;
+
+        let mut btree = BTreeMap::new();
+        let mut add = |name: &str, deps: &[&str], conflicts: &[&str]| {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            pack.set_dependencies(Dependencies::with_runtime_dependencies({
+                deps.iter().map(|d| Dependency::from(format!("{d} =1"))).collect()
+            }));
+            pack.set_conflicts(conflicts.iter().map(|c| Conflict::try_from(c.to_string()).unwrap()).collect());
+            btree.insert((pname(name), pversion("1")), pack.clone());
+            pack
+        };
+
+        let p1 = add("a", &["b", "c"], &[]);
+        add("b", &[], &["d"]);
+        add("c", &["d"], &[]);
+        add("d", &[], &[]);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap_err().to_string();
+        assert!(err.contains("b 1 conflicts with d 1"), "Unexpected error: {err}");
+        assert!(err.contains("a 1 -> c 1 -> d 1"), "Unexpected error: {err}");
+    }
+
+    #[test]
+    fn test_add_cyclic_dependencies() {
+        let mut btree = BTreeMap::new();
+        let mut add = |name: &str, deps: &[&str]| {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            pack.set_dependencies(Dependencies::with_runtime_dependencies({
+                deps.iter().map(|d| Dependency::from(format!("{d} =1"))).collect()
+            }));
+            btree.insert((pname(name), pversion("1")), pack.clone());
+            pack
+        };
+
+        let p1 = add("a", &["c"]);
+        add("b", &["d"]);
+        add("c", &["b"]);
+        add("d", &["c"]);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap_err();
+        let cycle = err.downcast_ref::<DependencyCycle>().expect("Expected a dependency cycle");
+        assert_eq!(cycle.to_string(), "Dependency cycle: b 1 -> d 1 -> c 1 -> b 1");
+    }
+
+    #[test]
+    fn test_add_shared_dependencies_on_several_levels() {
+        // a -> b, c, d; b -> e; c -> e, f; d -> f; e -> g; f -> g
+        let mut btree = BTreeMap::new();
+        let mut add = |name: &str, deps: &[&str]| {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            pack.set_dependencies(Dependencies::with_runtime_dependencies({
+                deps.iter().map(|d| Dependency::from(format!("{d} =1"))).collect()
+            }));
+            btree.insert((pname(name), pversion("1")), pack.clone());
+            pack
+        };
+
+        let p1 = add("a", &["b", "c", "d"]);
+        add("b", &["e"]);
+        add("c", &["e", "f"]);
+        add("d", &["f"]);
+        add("e", &["g"]);
+        add("f", &["g"]);
+        add("g", &[]);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        let graph = dag.dag().graph();
+
+        // Every package is added once, even if several packages of a level depend on it
+        let names = dag.all_packages().iter().map(|p| p.name().to_string()).sorted().collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "d", "e", "f", "g"]);
+
+        let edges = graph.raw_edges()
+            .iter()
+            .map(|e| format!("{}->{}", graph[e.source()].name(), graph[e.target()].name()))
+            .sorted()
+            .collect::<Vec<_>>();
+        assert_eq!(edges, ["a->b", "a->c", "a->d", "b->e", "c->e", "c->f", "d->f", "e->g", "f->g"]);
+    }
+
+    #[test]
+    fn test_add_package_depending_on_itself() {
+        let mut p1 = package("a", "1", "https://rust-lang.org", "123");
+        p1.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("a =1"))));
+
+        let mut btree = BTreeMap::new();
+        btree.insert((pname("a"), pversion("1")), p1.clone());
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle: a 1 -> a 1");
+    }
+
+    #[test]
+    fn test_deprecated_dependencies() {
+        use crate::package::deprecation::Deprecation;
+
+        let mut btree = BTreeMap::new();
+        let mut p1 = package("a", "1", "https://rust-lang.org", "123");
+        p1.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b >=1"))));
+        p1.set_deprecated(Some(Deprecation::new(None, false)));
+        btree.insert((pname("a"), pversion("1")), p1.clone());
+
+        let mut b1 = package("b", "1", "https://rust-lang.org", "124");
+        b1.set_deprecated(Some(Deprecation::new(Some(String::from("use b 3")), false)));
+        btree.insert((pname("b"), pversion("1")), b1);
+
+        let mut b2 = package("b", "2", "https://rust-lang.org", "125");
+        b2.set_deprecated(Some(Deprecation::new(None, true)));
+        btree.insert((pname("b"), pversion("2")), b2);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+
+        // the yanked version 2 is not selected, although it is the highest version
+        assert!(dag.all_packages().iter().any(|p| *p.name() == pname("b") && *p.version() == pversion("1")));
+
+        // the root package is not reported
+        assert!(dag.check_deprecated(false).is_ok());
+        let err = dag.check_deprecated(true).unwrap_err().to_string();
+        assert!(err.contains("b 1 is deprecated: use b 3 (required by a 1)"), "Unexpected error: {err}");
+        assert!(!err.contains("a 1 is"), "Unexpected error: {err}");
+    }
+
+    #[test]
+    fn test_add_package_conflicting_with_virtual_package() {
+        use std::convert::TryFrom;
+        use crate::package::conflict::Conflict;
+
+        let (mut p1, btree) = repo_with_virtual_dependency(&["libjpeg-turbo"]);
+        p1.set_conflicts(vec![Conflict::try_from(String::from("libjpeg")).unwrap()]);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        assert!(Dag::for_root_package(p1, &repo, None, &condition_data).is_err());
+    }
+
     #[test]
     fn test_add_deep_package_tree() {
         let mut btree = BTreeMap::new();
@@ -653,6 +1374,11 @@ mod tests {
         assert!(ps.iter().any(|p| *p.name() == pname("p2")));
         assert!(ps.iter().any(|p| *p.name() == pname("p3")));
         assert!(ps.iter().any(|p| *p.name() == pname("p4")));
+
+        // "p3" is only once in the DAG (and thus only built once), both "p2" and "p4" depend on it
+        assert_eq!(ps.iter().filter(|p| *p.name() == pname("p3")).count(), 1);
+        assert_eq!(r.dag().node_count(), 4);
+        assert_eq!(r.dag().edge_count(), 4);
     }
 
 
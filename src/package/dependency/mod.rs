@@ -36,7 +36,7 @@ pub trait ParseDependency {
 
 lazy_static! {
     pub(in crate::package::dependency)  static ref DEPENDENCY_PARSING_RE: Regex =
-        Regex::new("^(?P<name>[[:alpha:]]([[[:alnum:]]\\.\\-_])*) (?P<version>([\\*=><])?[[:alnum:]]([[[:alnum:]][[:punct:]]])*)$").unwrap();
+        Regex::new("^(?P<name>[[:alpha:]]([[[:alnum:]]\\.\\-_])*) (?P<version>([\\*=><!])*[[:alnum:]]([[[:alnum:]][[:punct:]] ])*)$").unwrap();
 }
 
 /// Helper function for the actual implementation of the ParseDependency trait.
@@ -132,4 +132,16 @@ mod tests {
             PackageVersionConstraint::from_version(String::from("="), exact("0.123"))
         );
     }
+
+    #[test]
+    fn test_dependency_version_range() {
+        let s = "foo >=1.2, <2.0";
+        let d = Dependency::from(String::from(s));
+
+        let (n, c) = d.parse_as_name_and_version().unwrap();
+
+        assert_eq!(n, name("foo"));
+        assert!(c.matches(&exact("1.5")));
+        assert!(!c.matches(&exact("2.0")));
+    }
 }
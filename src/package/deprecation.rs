@@ -0,0 +1,82 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Marks a version of a package as deprecated
+///
+/// ```toml
+/// [deprecated]
+/// reason = "CVE-2023-1234, use 1.2.4 or newer"
+/// yanked = true # optional
+/// ```
+///
+/// A warning is printed if a deprecated package is selected as a dependency (or the build fails
+/// with `butido build --deny-deprecated`). A yanked version is only selected as a dependency if no
+/// other version satisfies the dependency.
+#[derive(Clone, Debug, Serialize, Deserialize, Getters, CopyGetters)]
+#[serde(deny_unknown_fields)]
+pub struct Deprecation {
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl std::fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", if self.yanked { "yanked" } else { "deprecated" })?;
+        if let Some(reason) = self.reason.as_ref() {
+            write!(f, ": {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Deprecation {
+    pub fn new(reason: Option<String>, yanked: bool) -> Self {
+        Deprecation { reason, yanked }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TestDeprecated {
+        deprecated: Option<Deprecation>,
+    }
+
+    #[test]
+    fn test_deserialize_deprecation() {
+        let t: TestDeprecated = toml::from_str(r#"
+            [deprecated]
+            reason = "use 2.0"
+        "#).unwrap();
+        let d = t.deprecated.unwrap();
+        assert_eq!(d.reason().as_deref(), Some("use 2.0"));
+        assert!(!d.yanked());
+        assert_eq!(d.to_string(), "deprecated: use 2.0");
+
+        let t: TestDeprecated = toml::from_str("deprecated = { yanked = true }").unwrap();
+        assert_eq!(t.deprecated.unwrap().to_string(), "yanked");
+
+        let t: TestDeprecated = toml::from_str("").unwrap();
+        assert!(t.deprecated.is_none());
+    }
+}
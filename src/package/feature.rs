@@ -0,0 +1,59 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::BuildDependency;
+use crate::package::Dependency;
+use crate::util::EnvironmentVariableName;
+
+/// An optional feature of a package
+///
+/// A feature (for example "ssl" or "docs") is a group of optional dependencies that is only added
+/// to the package if the feature is enabled for a build (`butido build --with <name>`):
+///
+/// ```toml
+/// [features.ssl]
+/// build_dependencies = [ "openssl-dev =3.0" ]
+/// runtime_dependencies = [ "openssl =3.0" ]
+/// environment = { WITH_SSL = "1" }
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Getters)]
+#[serde(deny_unknown_fields)]
+pub struct Feature {
+    /// Environment variables that are set (or overwritten) if the feature is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    environment: HashMap<EnvironmentVariableName, String>,
+
+    /// Build dependencies that are added if the feature is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    build_dependencies: Vec<BuildDependency>,
+
+    /// Runtime dependencies that are added if the feature is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    runtime_dependencies: Vec<Dependency>,
+}
+
+#[cfg(test)]
+impl Feature {
+    pub fn with_runtime_dependencies(runtime_dependencies: Vec<Dependency>) -> Self {
+        Feature {
+            runtime_dependencies,
+            ..Feature::default()
+        }
+    }
+}
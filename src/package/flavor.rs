@@ -0,0 +1,69 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::BuildDependency;
+use crate::package::Dependency;
+use crate::package::{Phase, PhaseName};
+use crate::util::EnvironmentVariableName;
+
+/// A flavor of a package
+///
+/// A flavor (for example "minimal", "full" or "debug") modifies the package definition when it
+/// is selected for a build (`butido build --flavor <name>`).
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Getters)]
+pub struct Flavor {
+    /// Environment variables that are set (or overwritten) for this flavor
+    #[getset(get = "pub")]
+    #[serde(default)]
+    environment: HashMap<EnvironmentVariableName, String>,
+
+    /// Phases that are added to (or replaced in) the package for this flavor
+    #[getset(get = "pub")]
+    #[serde(default)]
+    phases: HashMap<PhaseName, Phase>,
+
+    /// Phases of the package that are removed for this flavor
+    #[getset(get = "pub")]
+    #[serde(default)]
+    disabled_phases: Vec<PhaseName>,
+
+    /// Build dependencies that are added for this flavor
+    #[getset(get = "pub")]
+    #[serde(default)]
+    build_dependencies: Vec<BuildDependency>,
+
+    /// Runtime dependencies that are added for this flavor
+    #[getset(get = "pub")]
+    #[serde(default)]
+    runtime_dependencies: Vec<Dependency>,
+}
+
+#[cfg(test)]
+impl Flavor {
+    pub fn with_environment(environment: HashMap<EnvironmentVariableName, String>) -> Self {
+        Flavor {
+            environment,
+            ..Flavor::default()
+        }
+    }
+
+    pub fn with_runtime_dependencies(runtime_dependencies: Vec<Dependency>) -> Self {
+        Flavor {
+            runtime_dependencies,
+            ..Flavor::default()
+        }
+    }
+}
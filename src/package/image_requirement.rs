@@ -0,0 +1,119 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::util::version::compare_versions;
+
+/// Requirements of a package on the image it is built on
+///
+/// ```toml
+/// [image_requirement]
+/// digest = "sha256:4a7c..."
+/// min_revision = "2023.04"
+/// revision_label = "org.opencontainers.image.version" # default
+/// ```
+///
+/// The `digest` must either be the ID of the image or one of its repository digests.
+/// The revision of an image is the value of its `revision_label` label, it is compared
+/// component-wise with `min_revision`.
+#[derive(Clone, Debug, Serialize, Deserialize, Getters)]
+#[serde(deny_unknown_fields)]
+pub struct ImageRequirement {
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_revision: Option<String>,
+
+    #[getset(get = "pub")]
+    #[serde(default = "default_revision_label")]
+    revision_label: String,
+}
+
+fn default_revision_label() -> String {
+    String::from("org.opencontainers.image.version")
+}
+
+impl ImageRequirement {
+    /// Check whether an image with the passed ID, repository digests and labels satisfies the
+    /// requirement
+    pub fn check(&self, id: &str, repo_digests: &[String], labels: &HashMap<String, String>) -> Result<()> {
+        if let Some(digest) = self.digest.as_ref() {
+            let matches = id == digest || repo_digests.iter().any(|rd| {
+                rd == digest || rd.rsplit_once('@').map(|(_, d)| d == digest).unwrap_or(false)
+            });
+
+            if !matches {
+                return Err(anyhow!("Image has ID {} (digests: {}), but digest {} is required",
+                    id,
+                    if repo_digests.is_empty() { String::from("none") } else { repo_digests.join(", ") },
+                    digest))
+            }
+        }
+
+        if let Some(min_revision) = self.min_revision.as_ref() {
+            let revision = labels.get(&self.revision_label)
+                .ok_or_else(|| anyhow!("Image has no label '{}', but revision {} or newer is required", self.revision_label, min_revision))?;
+
+            if compare_versions(revision, min_revision) == Ordering::Less {
+                return Err(anyhow!("Image has revision {} (label '{}'), but revision {} or newer is required",
+                    revision,
+                    self.revision_label,
+                    min_revision))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirement(s: &str) -> ImageRequirement {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_check_digest() {
+        let req = requirement(r#"digest = "sha256:abc""#);
+        let digests = vec![String::from("debian@sha256:abc")];
+
+        assert!(req.check("sha256:abc", &[], &HashMap::new()).is_ok());
+        assert!(req.check("sha256:123", &digests, &HashMap::new()).is_ok());
+        assert!(req.check("sha256:123", &[], &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_check_min_revision() {
+        let req = requirement(r#"min_revision = "2023.4""#);
+        let label = |v: &str| {
+            let mut hm = HashMap::new();
+            hm.insert(default_revision_label(), String::from(v));
+            hm
+        };
+
+        assert!(req.check("id", &[], &label("2023.10")).is_ok());
+        assert!(req.check("id", &[], &label("2023.4")).is_ok());
+        assert!(req.check("id", &[], &label("2022.12")).is_err());
+        assert!(req.check("id", &[], &HashMap::new()).is_err());
+    }
+}
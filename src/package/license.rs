@@ -0,0 +1,130 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::iter::Peekable;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The license of a package, as SPDX license expression
+///
+/// ```toml
+/// license = "MIT OR Apache-2.0"
+/// ```
+#[derive(parse_display::Display, Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(transparent)]
+#[display("{0}")]
+pub struct License(String);
+
+impl From<String> for License {
+    fn from(s: String) -> Self {
+        License(s)
+    }
+}
+
+impl License {
+    /// Whether the license expression cannot be fulfilled without one of the `denied` licenses
+    ///
+    /// For "A OR B", both alternatives have to be denied, for "A AND B" one of them is enough.
+    /// Exceptions ("A WITH exception") and the "+" suffix are ignored, licenses are compared
+    /// case-insensitively.
+    pub fn is_denied(&self, denied: &[String]) -> Result<bool> {
+        let expression = self.0.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = expression.split_whitespace().peekable();
+        let result = is_denied_or(&mut tokens, denied)?;
+        match tokens.next() {
+            None => Ok(result),
+            Some(token) => Err(anyhow!("Unexpected '{}' in license expression '{}'", token, self.0)),
+        }
+    }
+}
+
+type Tokens<'a> = Peekable<std::str::SplitWhitespace<'a>>;
+
+fn is_keyword(token: &str, keyword: &str) -> bool {
+    token.eq_ignore_ascii_case(keyword)
+}
+
+fn is_denied_or(tokens: &mut Tokens<'_>, denied: &[String]) -> Result<bool> {
+    let mut result = is_denied_and(tokens, denied)?;
+    while tokens.next_if(|t| is_keyword(t, "OR")).is_some() {
+        result &= is_denied_and(tokens, denied)?;
+    }
+    Ok(result)
+}
+
+fn is_denied_and(tokens: &mut Tokens<'_>, denied: &[String]) -> Result<bool> {
+    let mut result = is_denied_term(tokens, denied)?;
+    while tokens.next_if(|t| is_keyword(t, "AND")).is_some() {
+        result |= is_denied_term(tokens, denied)?;
+    }
+    Ok(result)
+}
+
+fn is_denied_term(tokens: &mut Tokens<'_>, denied: &[String]) -> Result<bool> {
+    match tokens.next() {
+        Some("(") => {
+            let result = is_denied_or(tokens, denied)?;
+            match tokens.next() {
+                Some(")") => Ok(result),
+                _ => Err(anyhow!("Missing ')' in license expression")),
+            }
+        },
+        Some(token) if token == ")" || is_keyword(token, "AND") || is_keyword(token, "OR") || is_keyword(token, "WITH") => {
+            Err(anyhow!("Expected a license, found '{}'", token))
+        },
+        Some(license) => {
+            if tokens.next_if(|t| is_keyword(t, "WITH")).is_some() && tokens.next().is_none() {
+                return Err(anyhow!("Missing exception after 'WITH' in license expression"))
+            }
+
+            let license = license.trim_end_matches('+');
+            Ok(denied.iter().any(|d| d.trim_end_matches('+').eq_ignore_ascii_case(license)))
+        },
+        None => Err(anyhow!("Unexpected end of license expression")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_denied(expression: &str, denied: &[&str]) -> Result<bool> {
+        let denied = denied.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        License::from(expression.to_string()).is_denied(&denied)
+    }
+
+    #[test]
+    fn test_is_denied() {
+        assert!(is_denied("GPL-3.0-only", &["GPL-3.0-only"]).unwrap());
+        assert!(is_denied("gpl-3.0-only", &["GPL-3.0-only"]).unwrap());
+        assert!(is_denied("GPL-2.0+", &["GPL-2.0"]).unwrap());
+        assert!(!is_denied("MIT", &["GPL-3.0-only"]).unwrap());
+        assert!(!is_denied("MIT", &[]).unwrap());
+
+        assert!(!is_denied("MIT OR GPL-3.0-only", &["GPL-3.0-only"]).unwrap());
+        assert!(is_denied("MIT AND GPL-3.0-only", &["GPL-3.0-only"]).unwrap());
+        assert!(is_denied("(MIT OR GPL-3.0-only) AND AGPL-3.0-only", &["GPL-3.0-only", "AGPL-3.0-only"]).unwrap());
+        assert!(!is_denied("(MIT AND GPL-3.0-only) OR Apache-2.0", &["GPL-3.0-only"]).unwrap());
+        assert!(is_denied("GPL-2.0-or-later WITH Classpath-exception-2.0", &["GPL-2.0-or-later"]).unwrap());
+    }
+
+    #[test]
+    fn test_is_denied_invalid() {
+        assert!(is_denied("", &[]).is_err());
+        assert!(is_denied("MIT OR", &[]).is_err());
+        assert!(is_denied("(MIT", &[]).is_err());
+        assert!(is_denied("MIT)", &[]).is_err());
+        assert!(is_denied("MIT Apache-2.0", &[]).is_err());
+        assert!(is_denied("MIT WITH", &[]).is_err());
+    }
+}
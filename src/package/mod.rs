@@ -10,9 +10,23 @@
 
 //! Module that contains all types and functionality that has to do with a package.
 
+mod conflict;
+
 mod dependency;
 pub use dependency::*;
 
+mod deprecation;
+
+mod feature;
+
+mod flavor;
+
+mod image_requirement;
+pub use image_requirement::*;
+
+mod license;
+pub use license::*;
+
 mod name;
 pub use name::*;
 
@@ -20,9 +34,15 @@ pub use name::*;
 mod package;
 pub use package::*;
 
+mod patch;
+
 mod phase;
 pub use phase::*;
 
+mod provides;
+
+pub mod schema;
+
 mod script;
 pub use script::*;
 
@@ -32,5 +52,8 @@ pub use source::*;
 mod dag;
 pub use dag::*;
 
+mod unpack;
+pub use unpack::*;
+
 mod version;
 pub use version::*;
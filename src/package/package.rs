@@ -15,8 +15,16 @@ use getset::Getters;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::package::conflict::*;
 use crate::package::dependency::*;
+use crate::package::deprecation::*;
+use crate::package::feature::*;
+use crate::package::flavor::*;
+use crate::package::image_requirement::*;
+use crate::package::license::*;
 use crate::package::name::*;
+use crate::package::patch::*;
+use crate::package::provides::*;
 use crate::package::source::*;
 use crate::package::version::*;
 use crate::package::{Phase, PhaseName};
@@ -34,19 +42,55 @@ pub struct Package {
     #[getset(get = "pub")]
     version_is_semver: bool,
 
+    /// The kind of the package, see [`PackageKind`]
     #[getset(get = "pub")]
+    #[serde(default)]
+    kind: PackageKind,
+
+    /// The sources of the package (none for meta packages)
+    #[getset(get = "pub")]
+    #[serde(default)]
     sources: HashMap<String, Source>,
 
     #[getset(get = "pub")]
     dependencies: Dependencies,
 
     #[getset(get = "pub")]
-    patches: Vec<PathBuf>,
+    patches: Vec<Patch>,
+
+    /// The virtual packages this package provides
+    ///
+    /// A dependency on a virtual package is satisfied by a package that provides it.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    provides: Vec<Provide>,
+
+    /// The packages that must not be in the same tree as this package
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conflicts: Vec<Conflict>,
+
+    /// The license of the package, as SPDX license expression (e.g. "MIT OR Apache-2.0")
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<License>,
+
+    /// Marks this version of the package as deprecated, see [`Deprecation`]
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deprecated: Option<Deprecation>,
 
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     environment: Option<HashMap<EnvironmentVariableName, String>>,
 
+    /// The names of the environment variables that can be passed to the build of this package
+    ///
+    /// If set, passing any other variable (for example via `butido build --env`) is an error.
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_env: Option<Vec<EnvironmentVariableName>>,
+
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     allowed_images: Option<Vec<ImageName>>,
@@ -55,7 +99,21 @@ pub struct Package {
     #[serde(skip_serializing_if = "Option::is_none")]
     denied_images: Option<Vec<ImageName>>,
 
+    /// Requirements on the image the package is built on (e.g. a digest or a minimum revision)
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_requirement: Option<ImageRequirement>,
+
+    /// The scheduling priority of the jobs for this package
+    ///
+    /// Jobs with a higher priority (and the jobs they depend on) get a free endpoint first, so
+    /// that long-running builds are started early in a submit. Defaults to 0.
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<i32>,
+
     #[getset(get = "pub")]
+    #[serde(default)]
     phases: HashMap<PhaseName, Phase>,
 
     /// Meta field
@@ -66,6 +124,57 @@ pub struct Package {
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     meta: Option<HashMap<String, String>>,
+
+    /// URL to query for the latest upstream version of the package
+    ///
+    /// This is handlebars syntax and overrides the `upstream_version_url` setting from the
+    /// configuration for this package.
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_version_url: Option<String>,
+
+    /// The flavors of the package, by name
+    ///
+    /// A flavor can be selected for a build and modifies the environment, phases and
+    /// dependencies of the package.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    flavors: HashMap<String, Flavor>,
+
+    /// The optional features of the package, by name
+    ///
+    /// A feature can be enabled for a build and adds dependencies and environment variables to
+    /// the package.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    features: HashMap<String, Feature>,
+
+    /// The variables from the `package_variables` setting of the configuration
+    ///
+    /// This is not part of the package definition, but set when loading the repository, so that
+    /// the variables are available as `vars` in the packaging scripts.
+    #[getset(get = "pub")]
+    #[serde(skip_deserializing, skip_serializing_if = "HashMap::is_empty")]
+    vars: HashMap<String, String>,
+
+    /// The path of the (leaf) pkg.toml file of this package, relative to the repository root
+    ///
+    /// This is not part of the package definition, but set when loading the repository.
+    #[getset(get = "pub")]
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// The kind of a package
+///
+/// A meta package (`kind = "meta"`) has no sources and no script of its own. It only aggregates
+/// its dependencies, so that a curated set of packages can be built in one submit.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageKind {
+    #[default]
+    Regular,
+    Meta,
 }
 
 impl std::hash::Hash for Package {
@@ -88,22 +197,141 @@ impl Package {
             name,
             version,
             version_is_semver,
+            kind: PackageKind::default(),
             sources,
             dependencies,
             patches: vec![],
+            provides: vec![],
+            conflicts: vec![],
+            license: None,
+            deprecated: None,
             environment: None,
+            allowed_env: None,
             allowed_images: None,
             denied_images: None,
+            image_requirement: None,
+            priority: None,
             phases: HashMap::new(),
             meta: None,
+            upstream_version_url: None,
+            flavors: HashMap::new(),
+            features: HashMap::new(),
+            vars: HashMap::new(),
+            path: PathBuf::new(),
         }
     }
 
+    pub(crate) fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    pub(crate) fn set_vars(&mut self, vars: HashMap<String, String>) {
+        self.vars = vars;
+    }
+
+    #[cfg(test)]
+    pub fn set_kind(&mut self, kind: PackageKind) {
+        self.kind = kind;
+    }
+
     #[cfg(test)]
     pub fn set_dependencies(&mut self, dependencies: Dependencies) {
         self.dependencies = dependencies;
     }
 
+    #[cfg(test)]
+    pub fn set_provides(&mut self, provides: Vec<Provide>) {
+        self.provides = provides;
+    }
+
+    #[cfg(test)]
+    pub fn set_features(&mut self, features: HashMap<String, Feature>) {
+        self.features = features;
+    }
+
+    #[cfg(test)]
+    pub fn set_conflicts(&mut self, conflicts: Vec<Conflict>) {
+        self.conflicts = conflicts;
+    }
+
+    #[cfg(test)]
+    pub fn set_priority(&mut self, priority: Option<i32>) {
+        self.priority = priority;
+    }
+
+    #[cfg(test)]
+    pub fn set_deprecated(&mut self, deprecated: Option<Deprecation>) {
+        self.deprecated = deprecated;
+    }
+
+    #[cfg(test)]
+    pub fn set_allowed_env(&mut self, allowed_env: Option<Vec<EnvironmentVariableName>>) {
+        self.allowed_env = allowed_env;
+    }
+
+    #[cfg(test)]
+    pub fn set_flavors(&mut self, flavors: HashMap<String, Flavor>) {
+        self.flavors = flavors;
+    }
+
+    /// Whether the package is a meta package, which only aggregates its dependencies
+    pub fn is_meta(&self) -> bool {
+        self.kind == PackageKind::Meta
+    }
+
+    /// Whether this version of the package is yanked, see [`Deprecation`]
+    pub fn is_yanked(&self) -> bool {
+        self.deprecated.as_ref().map(Deprecation::yanked).unwrap_or(false)
+    }
+
+    /// Get the version of the virtual package `name` that this package provides, if any
+    pub fn provided_version(&self, name: &PackageName) -> Option<&PackageVersion> {
+        self.provides
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.version().as_ref().unwrap_or(&self.version))
+    }
+
+    /// Get a copy of the package with the flavor `name` applied
+    ///
+    /// Returns None if the package does not have a flavor with that name.
+    pub fn with_flavor(&self, name: &str) -> Option<Package> {
+        let flavor = self.flavors.get(name)?;
+        let mut package = self.clone();
+
+        if !flavor.environment().is_empty() {
+            package.environment
+                .get_or_insert_with(HashMap::new)
+                .extend(flavor.environment().clone());
+        }
+
+        package.phases.extend(flavor.phases().clone());
+        package.phases.retain(|phase_name, _| !flavor.disabled_phases().contains(phase_name));
+        package.dependencies.build.extend(flavor.build_dependencies().iter().cloned());
+        package.dependencies.runtime.extend(flavor.runtime_dependencies().iter().cloned());
+        Some(package)
+    }
+
+    /// Get a copy of the package with the features `names` enabled
+    ///
+    /// Features that the package does not have are ignored.
+    pub fn with_features(&self, names: &[String]) -> Package {
+        let mut package = self.clone();
+
+        for feature in names.iter().filter_map(|name| self.features.get(name)) {
+            if !feature.environment().is_empty() {
+                package.environment
+                    .get_or_insert_with(HashMap::new)
+                    .extend(feature.environment().clone());
+            }
+
+            package.dependencies.build.extend(feature.build_dependencies().iter().cloned());
+            package.dependencies.runtime.extend(feature.runtime_dependencies().iter().cloned());
+        }
+
+        package
+    }
+
     /// Get a wrapper object around self which implements a debug interface with all details about
     /// the Package object
     #[cfg(debug_assertions)]
@@ -140,6 +368,8 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
             version = self.0.version,
             semver = if self.0.version_is_semver { "is semver" } else { "not semver" })?;
 
+        writeln!(f, "\tKind = {:?}", self.0.kind)?;
+
         writeln!(f, "\tSources = ")?;
         self.0.sources.iter().try_for_each(|(k, v)| writeln!(f, "\t\t{name} = (Url = {url}, Hash = {hash} ({hasht}), {dl})",
             name = k,
@@ -156,7 +386,15 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
         self.0.dependencies.runtime.iter().try_for_each(|r| writeln!(f, "\t\t{r:?}"))?;
 
         writeln!(f, "\tPatches = ")?;
-        self.0.patches.iter().try_for_each(|p| writeln!(f, "\t\t{}", p.display()))?;
+        self.0.patches.iter().try_for_each(|p| writeln!(f, "\t\t{} (strip = {}, order = {})", p.path().display(), p.strip(), p.order()))?;
+
+        writeln!(f, "\tProvides = ")?;
+        self.0.provides.iter().try_for_each(|p| writeln!(f, "\t\t{p:?}"))?;
+
+        writeln!(f, "\tConflicts = ")?;
+        self.0.conflicts.iter().try_for_each(|c| writeln!(f, "\t\t{c}"))?;
+
+        writeln!(f, "\tDeprecated = {}", self.0.deprecated.as_ref().map(ToString::to_string).unwrap_or_else(|| String::from("no")))?;
 
         writeln!(f, "\tEnvironment = ")?;
         self.0.environment
@@ -164,6 +402,12 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
             .map(|hm| hm.iter().try_for_each(|(k, v)| writeln!(f, "\t\t{k:?} = {v}")))
             .transpose()?;
 
+        writeln!(f, "\tAllowed Environment = ")?;
+        self.0.allowed_env
+            .as_ref()
+            .map(|v| v.iter().try_for_each(|e| writeln!(f, "\t\t{e:?}")))
+            .transpose()?;
+
         writeln!(f, "\tAllowed Images = ")?;
 
         self.0.allowed_images
@@ -177,11 +421,23 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
             .map(|v| v.iter().try_for_each(|i| writeln!(f, "\t\t{i:?}")))
             .transpose()?;
 
+        writeln!(f, "\tImage Requirement = {:?}", self.0.image_requirement)?;
+
         writeln!(f, "\tPhases = ")?;
         self.0.phases
             .iter()
             .try_for_each(|(k, _)| writeln!(f, "\t\t{k:?} = ..."))?;
 
+        writeln!(f, "\tFlavors = ")?;
+        self.0.flavors
+            .keys()
+            .try_for_each(|k| writeln!(f, "\t\t{k}"))?;
+
+        writeln!(f, "\tFeatures = ")?;
+        self.0.features
+            .keys()
+            .try_for_each(|k| writeln!(f, "\t\t{k}"))?;
+
         Ok(())
     }
 }
@@ -274,4 +530,86 @@ pub mod tests {
         let dependencies = Dependencies::empty();
         Package::new(name, version, version_is_semver, sources, dependencies)
     }
+
+    #[test]
+    fn test_with_unknown_flavor() {
+        let p = package("a", "1", "https://rust-lang.org", "123");
+        assert!(p.with_flavor("full").is_none());
+    }
+
+    #[test]
+    fn test_with_flavor() {
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+        p.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b =1"))));
+        p.set_flavors({
+            let mut hm = HashMap::new();
+            hm.insert(String::from("debug"), Flavor::with_environment({
+                let mut env = HashMap::new();
+                env.insert(EnvironmentVariableName::from("DEBUG"), String::from("1"));
+                env
+            }));
+            hm.insert(String::from("full"), Flavor::with_runtime_dependencies(vec![
+                Dependency::from(String::from("c =2")),
+            ]));
+            hm
+        });
+
+        let debug = p.with_flavor("debug").unwrap();
+        let env = debug.environment().as_ref().unwrap();
+        assert_eq!(env.get(&EnvironmentVariableName::from("DEBUG")), Some(&String::from("1")));
+        assert_eq!(debug.dependencies().runtime().len(), 1);
+
+        let full = p.with_flavor("full").unwrap();
+        assert!(full.environment().is_none());
+        assert_eq!(full.dependencies().runtime().len(), 2);
+        assert!(full.dependencies().runtime().contains(&Dependency::from(String::from("c =2"))));
+    }
+
+    #[test]
+    fn test_with_features() {
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+        p.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b =1"))));
+        p.set_features({
+            let mut hm = HashMap::new();
+            hm.insert(String::from("ssl"), Feature::with_runtime_dependencies(vec![
+                Dependency::from(String::from("openssl =3.0")),
+            ]));
+            hm.insert(String::from("docs"), Feature::with_runtime_dependencies(vec![
+                Dependency::from(String::from("doxygen =1.9")),
+            ]));
+            hm
+        });
+
+        assert_eq!(p.with_features(&[]).dependencies().runtime().len(), 1);
+        assert_eq!(p.with_features(&[String::from("unknown")]).dependencies().runtime().len(), 1);
+
+        let ssl = p.with_features(&[String::from("ssl")]);
+        assert_eq!(ssl.dependencies().runtime().len(), 2);
+        assert!(ssl.dependencies().runtime().contains(&Dependency::from(String::from("openssl =3.0"))));
+
+        let all = p.with_features(&[String::from("ssl"), String::from("docs")]);
+        assert_eq!(all.dependencies().runtime().len(), 3);
+    }
+
+    #[test]
+    fn test_deserialize_meta_package() {
+        let p: Package = toml::from_str(r#"
+            name = "desktop-stack"
+            version = "1"
+            version_is_semver = false
+            kind = "meta"
+            patches = []
+
+            [dependencies]
+            build = []
+            runtime = [ "a =1", "b =2" ]
+        "#).unwrap();
+
+        assert!(p.is_meta());
+        assert!(p.sources().is_empty());
+        assert_eq!(p.dependencies().runtime().len(), 2);
+
+        let p = package("a", "1", "https://rust-lang.org", "123");
+        assert!(!p.is_meta());
+    }
 }
@@ -0,0 +1,121 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::PathBuf;
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+/// A patch file of a package
+///
+/// In the `pkg.toml` file, a patch can either be declared as a plain path, or as a table with the
+/// `path` and (optionally) the `strip` level and the `order` in which the patches are applied:
+///
+/// ```toml
+/// patches = [
+///     "fix-build.patch",
+///     { path = "0001-upstream.patch", strip = 0, order = -1 },
+/// ]
+/// ```
+///
+/// Patches are applied in ascending `order` (default 0), patches with the same order are applied
+/// in the order they are declared in.
+///
+/// A patch serializes to its path only, so that package scripts can iterate over the patches
+/// like they are plain strings.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Getters, CopyGetters)]
+#[serde(from = "PatchDeclaration")]
+pub struct Patch {
+    #[getset(get = "pub")]
+    path: PathBuf,
+
+    /// The number of leading path components to strip from the file names in the patch
+    #[getset(get_copy = "pub")]
+    strip: usize,
+
+    #[getset(get_copy = "pub")]
+    order: i64,
+}
+
+impl Patch {
+    #[cfg(test)]
+    pub fn new(path: PathBuf, strip: usize, order: i64) -> Self {
+        Patch { path, strip, order }
+    }
+}
+
+impl Serialize for Patch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.path.serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PatchDeclaration {
+    Path(PathBuf),
+    Table {
+        path: PathBuf,
+
+        #[serde(default = "default_patch_strip")]
+        strip: usize,
+
+        #[serde(default)]
+        order: i64,
+    },
+}
+
+fn default_patch_strip() -> usize {
+    1
+}
+
+impl From<PatchDeclaration> for Patch {
+    fn from(decl: PatchDeclaration) -> Self {
+        match decl {
+            PatchDeclaration::Path(path) => Patch {
+                path,
+                strip: default_patch_strip(),
+                order: 0,
+            },
+            PatchDeclaration::Table { path, strip, order } => Patch { path, strip, order },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TestPatches {
+        patches: Vec<Patch>,
+    }
+
+    #[test]
+    fn test_deserialize_patches() {
+        let s = r#"patches = [ "a.patch", { path = "b.patch", strip = 0, order = -1 }, { path = "c.patch" } ]"#;
+        let t: TestPatches = toml::from_str(s).unwrap();
+
+        assert_eq!(t.patches, vec![
+            Patch::new(PathBuf::from("a.patch"), 1, 0),
+            Patch::new(PathBuf::from("b.patch"), 0, -1),
+            Patch::new(PathBuf::from("c.patch"), 1, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_serialize_patch_as_path() {
+        let p = Patch::new(PathBuf::from("b.patch"), 0, -1);
+        assert_eq!(serde_json::to_string(&p).unwrap(), r#""b.patch""#);
+    }
+}
@@ -8,11 +8,16 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use getset::CopyGetters;
+use getset::Getters;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::util::EnvironmentVariableName;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
 #[serde(transparent)]
 pub struct PhaseName(String);
@@ -30,11 +35,90 @@ impl From<String> for PhaseName {
     }
 }
 
+/// A phase of the packaging script
+///
+/// ```toml
+/// [phases]
+/// build.script = "make V=1"
+/// build.log = "summary"
+/// configure.environment = { CFLAGS = "-O2" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Getters, CopyGetters)]
+pub struct Phase {
+    #[serde(flatten)]
+    #[getset(get = "pub")]
+    source: PhaseSource,
+
+    /// How the output of the phase is shown to the user while the job is running
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    log: PhaseLog,
+
+    /// Environment variables that are only set while the phase is running
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[getset(get = "pub")]
+    environment: BTreeMap<EnvironmentVariableName, String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
-pub enum Phase {
+pub enum PhaseSource {
     #[serde(rename = "path")]
     Path(PathBuf),
 
     #[serde(rename = "script")]
     Text(String),
 }
+
+/// The log verbosity of a phase
+///
+/// The output of a phase always ends up in the log file and the database. With `summary`, the
+/// live progress display only shows phase transitions and errors of the phase, but not its
+/// output.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseLog {
+    #[default]
+    Full,
+    Summary,
+}
+
+impl PhaseLog {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PhaseLog::Full => "full",
+            PhaseLog::Summary => "summary",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TestPhase {
+        phase: Phase,
+    }
+
+    #[test]
+    fn test_deserialize_phase_log() {
+        let t: TestPhase = toml::from_str(r#"phase = { script = "make" }"#).unwrap();
+        assert_eq!(t.phase.source(), &PhaseSource::Text(String::from("make")));
+        assert_eq!(t.phase.log(), PhaseLog::Full);
+
+        let t: TestPhase = toml::from_str(r#"phase = { script = "make V=1", log = "summary" }"#).unwrap();
+        assert_eq!(t.phase.source(), &PhaseSource::Text(String::from("make V=1")));
+        assert_eq!(t.phase.log(), PhaseLog::Summary);
+
+        assert!(toml::from_str::<TestPhase>(r#"phase = { script = "make", log = "none" }"#).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_phase_environment() {
+        let t: TestPhase = toml::from_str(r#"phase = { script = "make" }"#).unwrap();
+        assert!(t.phase.environment().is_empty());
+
+        let t: TestPhase = toml::from_str(r#"phase = { script = "./configure", environment = { CFLAGS = "-O2" } }"#).unwrap();
+        assert_eq!(t.phase.environment().get(&EnvironmentVariableName::from("CFLAGS")).map(String::as_str), Some("-O2"));
+    }
+}
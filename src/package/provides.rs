@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+
+/// A virtual package that is provided by a package
+///
+/// In the `pkg.toml` file, this is either the name of the virtual package or the name and the
+/// provided version, separated by a space:
+///
+/// ```toml
+/// provides = [ "libjpeg", "libjpeg8 8.0" ]
+/// ```
+///
+/// If no version is given, the version of the providing package is used.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Getters)]
+#[serde(try_from = "String", into = "String")]
+pub struct Provide {
+    #[getset(get = "pub")]
+    name: PackageName,
+
+    #[getset(get = "pub")]
+    version: Option<PackageVersion>,
+}
+
+impl TryFrom<String> for Provide {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (name, version) = match s.trim().split_once(' ') {
+            Some((name, version)) => (name, Some(version.trim())),
+            None => (s.trim(), None),
+        };
+
+        if name.is_empty() || version.map(str::is_empty).unwrap_or(false) {
+            return Err(anyhow!("Expected '<name>' or '<name> <version>' as provided package, got: '{}'", s))
+        }
+
+        Ok(Provide {
+            name: PackageName::from(name.to_string()),
+            version: version.map(|v| PackageVersion::from(v.to_string())),
+        })
+    }
+}
+
+impl From<Provide> for String {
+    fn from(p: Provide) -> String {
+        match p.version {
+            Some(v) => format!("{} {}", p.name, v),
+            None => p.name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TestProvides {
+        provides: Vec<Provide>,
+    }
+
+    #[test]
+    fn test_deserialize_provides() {
+        let t: TestProvides = toml::from_str(r#"provides = [ "libjpeg", "libjpeg8 8.0" ]"#).unwrap();
+
+        assert_eq!(t.provides[0].name(), &PackageName::from(String::from("libjpeg")));
+        assert_eq!(t.provides[0].version(), &None);
+        assert_eq!(t.provides[1].name(), &PackageName::from(String::from("libjpeg8")));
+        assert_eq!(t.provides[1].version(), &Some(PackageVersion::from(String::from("8.0"))));
+
+        assert!(toml::from_str::<TestProvides>(r#"provides = [ "" ]"#).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Versioning of the `pkg.toml` format
+//!
+//! Every `pkg.toml` file can declare the version of the format it is written in:
+//!
+//! ```toml
+//! schema_version = 1
+//! ```
+//!
+//! Files without `schema_version` are version 1. When the format changes incompatibly,
+//! [`CURRENT_SCHEMA_VERSION`] is increased and a [`Migration`] from the previous version is added to
+//! `MIGRATIONS`. Outdated `pkg.toml` files are migrated in memory when the repository is loaded
+//! (with a warning) and can be rewritten with `butido repo migrate`. Package definitions in other
+//! formats (`pkg.yaml`, `pkg.json`) are usually generated, they must use the current version.
+
+use std::borrow::Cow;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use toml_edit::Document;
+
+/// The version of the `pkg.toml` format this version of butido understands
+pub const CURRENT_SCHEMA_VERSION: i64 = 1;
+
+/// The key of the schema version in a `pkg.toml` file
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// A migration of a `pkg.toml` file from schema version `from` to `from + 1`
+pub struct Migration {
+    pub from: i64,
+    pub description: &'static str,
+    pub apply: fn(&mut Document) -> Result<()>,
+}
+
+/// All migrations, ordered by `from`
+const MIGRATIONS: &[Migration] = &[];
+
+/// Get the schema version of a `pkg.toml` file
+pub fn schema_version(doc: &Document) -> Result<i64> {
+    match doc.get(SCHEMA_VERSION_KEY) {
+        None => Ok(1),
+        Some(item) => item
+            .as_integer()
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| anyhow!("{} must be a positive integer, got: {}", SCHEMA_VERSION_KEY, item.to_string().trim())),
+    }
+}
+
+/// Bring the contents of a `pkg.toml` file to the current schema version, for loading it
+///
+/// Returns the (possibly migrated) contents and the schema version of the file.
+/// Files that are newer than [`CURRENT_SCHEMA_VERSION`] cannot be loaded.
+pub fn upgrade(content: &str) -> Result<(Cow<'_, str>, i64)> {
+    let mut doc = content.parse::<Document>()?;
+    let version = schema_version(&doc)?;
+
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok((Cow::Borrowed(content), version))
+    }
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(unsupported_version(version))
+    }
+
+    apply_migrations(&mut doc, version, CURRENT_SCHEMA_VERSION, MIGRATIONS)
+        .context("Please migrate the file manually")?;
+    Ok((Cow::Owned(doc.to_string()), version))
+}
+
+/// Check the schema version of a package definition file that is not migrated automatically
+pub fn check_schema_version(version: i64) -> Result<()> {
+    if version > CURRENT_SCHEMA_VERSION {
+        Err(unsupported_version(version))
+    } else if version < CURRENT_SCHEMA_VERSION {
+        Err(anyhow!(
+            "The file uses the outdated schema version {}, only pkg.toml files are migrated automatically. Please write it in schema version {}",
+            version,
+            CURRENT_SCHEMA_VERSION
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn unsupported_version(version: i64) -> anyhow::Error {
+    anyhow!(
+        "The file uses schema version {}, but this version of butido only supports schema versions up to {}. Please update butido",
+        version,
+        CURRENT_SCHEMA_VERSION
+    )
+}
+
+/// Migrate the contents of a `pkg.toml` file to the current schema version
+///
+/// Returns `None` if the file is already at the current schema version.
+/// In contrast to [`upgrade`], the schema version is written to the migrated file.
+pub fn migrate(content: &str) -> Result<Option<String>> {
+    let mut doc = content.parse::<Document>()?;
+    let version = schema_version(&doc)?;
+
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok(None)
+    }
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Cannot migrate schema version {}, this version of butido only supports schema versions up to {}",
+            version,
+            CURRENT_SCHEMA_VERSION
+        ))
+    }
+
+    apply_migrations(&mut doc, version, CURRENT_SCHEMA_VERSION, MIGRATIONS)?;
+    doc[SCHEMA_VERSION_KEY] = toml_edit::value(CURRENT_SCHEMA_VERSION);
+    Ok(Some(doc.to_string()))
+}
+
+fn apply_migrations(doc: &mut Document, from: i64, to: i64, migrations: &[Migration]) -> Result<()> {
+    (from..to).try_for_each(|from| {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == from)
+            .ok_or_else(|| anyhow!("No migration from pkg.toml schema version {}", from))?;
+
+        (migration.apply)(doc)
+            .with_context(|| anyhow!("Migrating from schema version {}: {}", from, migration.description))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(s: &str) -> Document {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_schema_version() {
+        assert_eq!(schema_version(&doc("name = \"a\"")).unwrap(), 1);
+        assert_eq!(schema_version(&doc("schema_version = 1")).unwrap(), 1);
+        assert!(schema_version(&doc("schema_version = 0")).is_err());
+        assert!(schema_version(&doc("schema_version = \"1\"")).is_err());
+    }
+
+    #[test]
+    fn test_upgrade_current_version() {
+        let content = "# comment\nname = \"a\"\n";
+        let (upgraded, version) = upgrade(content).unwrap();
+        assert!(matches!(upgraded, Cow::Borrowed(_)));
+        assert_eq!(version, CURRENT_SCHEMA_VERSION);
+
+        assert!(migrate(content).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let content = format!("schema_version = {}\n", CURRENT_SCHEMA_VERSION + 1);
+        let e = upgrade(&content).unwrap_err().to_string();
+        assert!(e.contains("Please update butido"), "{}", e);
+        assert!(migrate(&content).is_err());
+        assert!(check_schema_version(CURRENT_SCHEMA_VERSION + 1).is_err());
+        assert!(check_schema_version(CURRENT_SCHEMA_VERSION).is_ok());
+    }
+
+    #[test]
+    fn test_apply_migrations() {
+        fn rename_foo(doc: &mut Document) -> Result<()> {
+            let foo = doc.remove("foo").ok_or_else(|| anyhow!("no foo"))?;
+            doc["bar"] = foo;
+            Ok(())
+        }
+
+        let migrations = [Migration { from: 1, description: "rename foo", apply: rename_foo }];
+
+        // The comment belongs to "name", it is kept when "foo" is renamed
+        let mut d = doc("# comment\nname = \"a\"\nfoo = 1\n");
+        apply_migrations(&mut d, 1, 2, &migrations).unwrap();
+        assert!(d.get("foo").is_none());
+        assert_eq!(d["bar"].as_integer(), Some(1));
+        assert!(d.to_string().starts_with("# comment\nname = \"a\"\n"), "{}", d);
+
+        let mut d = doc("");
+        assert!(apply_migrations(&mut d, 1, 2, &migrations).is_err());
+        assert!(apply_migrations(&mut d, 1, 3, &migrations).is_err());
+        assert!(apply_migrations(&mut d, 2, 2, &[]).is_ok());
+    }
+}
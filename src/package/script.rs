@@ -11,11 +11,16 @@
 // TODO: Is this really necessary?
 #![allow(clippy::format_push_string)]
 
+use std::collections::BTreeMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::ExitStatus;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 use anyhow::anyhow;
 use anyhow::Context as AnyhowContext;
-use anyhow::Error;
 use anyhow::Result;
 use handlebars::{
     Context, Handlebars, Helper, HelperDef, HelperResult, JsonRender, Output, PathAndJson,
@@ -31,8 +36,10 @@ use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 use tokio::process::Command;
 
 use crate::package::Package;
-use crate::package::Phase;
+use crate::package::PhaseLog;
+use crate::package::PhaseSource;
 use crate::package::PhaseName;
+use crate::util::EnvironmentVariableName;
 
 #[derive(parse_display::Display, Serialize, Deserialize, Clone, Debug)]
 #[serde(transparent)]
@@ -93,8 +100,8 @@ impl Script {
 
         Ok((
             out.status,
-            String::from_utf8(out.stdout)?,
-            String::from_utf8(out.stderr)?,
+            String::from_utf8_lossy(&out.stdout).into_owned(),
+            String::from_utf8_lossy(&out.stderr).into_owned(),
         ))
     }
 }
@@ -166,11 +173,21 @@ impl AsRef<str> for Script {
 
 pub struct ScriptBuilder<'a> {
     shebang: &'a Shebang,
+    apply_patches_before: Option<&'a PhaseName>,
 }
 
 impl<'a> ScriptBuilder<'a> {
     pub fn new(shebang: &'a Shebang) -> Self {
-        ScriptBuilder { shebang }
+        ScriptBuilder {
+            shebang,
+            apply_patches_before: None,
+        }
+    }
+
+    /// Generate a step that applies the patches of the package right before the phase `phase`
+    pub fn apply_patches_before(mut self, phase: Option<&'a PhaseName>) -> Self {
+        self.apply_patches_before = phase;
+        self
     }
 
     pub fn build(
@@ -180,22 +197,43 @@ impl<'a> ScriptBuilder<'a> {
         strict_mode: bool,
     ) -> Result<Script> {
         let mut script = format!("{shebang}\n", shebang = self.shebang.0);
+        script.push_str(&Self::unpack_sources_step(package)?);
 
         for name in phaseorder {
-            match package.phases().get(name) {
-                Some(Phase::Text(text)) => {
+            if self.apply_patches_before == Some(name) {
+                script.push_str(&Self::apply_patches_step(package));
+            }
+
+            match package.phases().get(name).map(|phase| (phase.source(), phase.log(), phase.environment())) {
+                Some((PhaseSource::Text(text), log, environment)) => {
                     use unindent::Unindent;
 
+                    // Switch the live log display to summary mode for the phase and back afterwards
+                    let (log_begin, log_end) = match log {
+                        PhaseLog::Full => (String::new(), String::new()),
+                        PhaseLog::Summary => (
+                            format!("echo '#BUTIDO:LOG:{}'\n", PhaseLog::Summary.as_str()),
+                            format!("echo '#BUTIDO:LOG:{}'\n", PhaseLog::Full.as_str()),
+                        ),
+                    };
+
+                    let (env_begin, env_end) = Self::phase_environment(environment)
+                        .with_context(|| anyhow!("Environment of phase {} of {} {}", name.as_str(), package.name(), package.version()))?;
+
                     script.push_str(&indoc::formatdoc!(
                         r#"
                         ### phase {}
-                        {}
-                        ### / {} phase
+                        {}{}{}
+                        {}{}### / {} phase
                     "#,
                         name.as_str(),
+                        env_begin,
+                        log_begin,
                         // whack hack: insert empty line on top because unindent ignores the
                         // indentation of the first line, see commit message for more info
                         format!("\n{text}").unindent(),
+                        log_end,
+                        env_end,
                         name.as_str(),
                     ));
 
@@ -205,7 +243,7 @@ impl<'a> ScriptBuilder<'a> {
                 // TODO: Support path embedding
                 // (requires possibility to have stuff in Script type that gets copied to
                 // container)
-                Some(Phase::Path(pb)) => {
+                Some((PhaseSource::Path(pb), _, _)) => {
                     script.push_str(&format!(
                         r#"
                         # Phase (from file {path}): {name}
@@ -231,15 +269,96 @@ impl<'a> ScriptBuilder<'a> {
         Self::interpolate_package(script, package, strict_mode).map(Script)
     }
 
+    /// Generate the lines that export the environment variables of a phase before the phase and
+    /// restore the previous values afterwards, so that they are only visible within the phase
+    fn phase_environment(environment: &BTreeMap<EnvironmentVariableName, String>) -> Result<(String, String)> {
+        let mut begin = String::new();
+        let mut end = String::new();
+        for (i, (name, value)) in environment.iter().enumerate() {
+            let name: &str = name.as_ref();
+            let is_valid_name = name.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_valid_name {
+                return Err(anyhow!("Invalid environment variable name: '{}'", name))
+            }
+
+            let value = value.replace('\'', r"'\''");
+            begin.push_str(&format!(
+                "__BUTIDO_PHASE_ENV_SET_{i}=\"${{{name}+x}}\"; __BUTIDO_PHASE_ENV_{i}=\"${{{name}-}}\"\nexport {name}='{value}'\n"
+            ));
+            end.push_str(&format!(
+                "if [ -n \"$__BUTIDO_PHASE_ENV_SET_{i}\" ]; then export {name}=\"$__BUTIDO_PHASE_ENV_{i}\"; else unset {name}; fi\n"
+            ));
+        }
+        Ok((begin, end))
+    }
+
+    /// Generate the script step that unpacks the sources of the package that have an `unpack`
+    /// setting, ordered by source name
+    fn unpack_sources_step(package: &Package) -> Result<String> {
+        let mut sources = package.sources()
+            .iter()
+            .filter_map(|(name, source)| source.unpack().as_ref().map(|unpack| (name, source, unpack)))
+            .collect::<Vec<_>>();
+        if sources.is_empty() {
+            return Ok(String::new())
+        }
+        sources.sort_by_key(|(name, _, _)| *name);
+
+        let mut step = String::from("### unpack sources\n");
+        for (name, source, unpack) in sources {
+            let file = PathBuf::from(crate::consts::INPUTS_DIR_PATH).join(crate::package::source_file_name(name));
+            let command = unpack.command(&file, source.url())
+                .with_context(|| anyhow!("Unpacking source '{}' of {} {}", name, package.name(), package.version()))?;
+
+            step.push_str(&format!(
+                "{command} || {{ echo '#BUTIDO:STATE:ERR:Unpacking source {name} failed'; exit 1; }}\n"
+            ));
+        }
+        step.push_str("### / unpack sources\n\n");
+        Ok(step)
+    }
+
+    /// Generate the script step that applies the patches of the package, in their order
+    fn apply_patches_step(package: &Package) -> String {
+        let mut patches = package.patches().iter().collect::<Vec<_>>();
+        if patches.is_empty() {
+            return String::new()
+        }
+        patches.sort_by_key(|patch| patch.order()); // stable, keeps the declaration order
+
+        let mut step = String::from("### apply patches\n");
+        for patch in patches {
+            let path = PathBuf::from(crate::consts::PATCH_DIR_PATH).join(patch.path());
+            step.push_str(&format!(
+                "patch -p{strip} --batch --input='{path}' || {{ echo '#BUTIDO:STATE:ERR:Applying patch {name} failed'; exit 1; }}\n",
+                strip = patch.strip(),
+                path = path.display(),
+                name = patch.path().display(),
+            ));
+        }
+        step.push_str("### / apply patches\n\n");
+        step
+    }
+
     fn interpolate_package(script: String, package: &Package, strict_mode: bool) -> Result<String> {
+        let included_files = Arc::new(Mutex::new(Vec::new()));
+        let file_helper = FileHelper {
+            package_dir: package.path().parent().map(Path::to_path_buf).unwrap_or_default(),
+            included_files: included_files.clone(),
+        };
+
         let mut hb = Handlebars::new();
         hb.register_escape_fn(handlebars::no_escape);
         hb.register_template_string("script", script)?;
         hb.register_helper("phase", Box::new(PhaseHelper));
         hb.register_helper("state", Box::new(StateHelper));
         hb.register_helper("progress", Box::new(ProgressHelper));
+        hb.register_helper("metric", Box::new(MetricHelper));
+        hb.register_helper("warn", Box::new(WarnHelper));
         hb.register_helper("join", Box::new(JoinHelper));
         hb.register_helper("joinwith", Box::new(JoinWithHelper));
+        hb.register_helper("file", Box::new(file_helper));
         hb.set_strict_mode(strict_mode);
 
         #[cfg(debug_assertions)]
@@ -247,9 +366,20 @@ impl<'a> ScriptBuilder<'a> {
             trace!("Rendering Package: {:?}", package.debug_details());
         }
 
-        hb.render("script", package)
-            .with_context(|| anyhow!("Rendering script for package {} {} failed", package.name(), package.version()))
-            .map_err(Error::from)
+        let mut script = hb.render("script", package)
+            .with_context(|| anyhow!("Rendering script for package {} {} failed", package.name(), package.version()))?;
+
+        // Record the hashes of the included files in the script, so that they are part of the job
+        // inputs even if the content of a file is not used verbatim
+        let included_files = included_files.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+        if !included_files.is_empty() {
+            script.push_str("\n### included files\n");
+            for (path, hash) in included_files.iter() {
+                script.push_str(&format!("# {} sha256:{}\n", path.display(), hash));
+            }
+        }
+
+        Ok(script)
     }
 }
 
@@ -298,7 +428,13 @@ impl HelperDef for StateHelper {
             .ok_or_else(|| RenderError::new("Required parameter must be a string: state"))
             .and_then(|state| match state {
                 "OK" => {
-                    out.write("echo '#BUTIDO:STATE:OK'")?;
+                    // Report the checksums of the artifacts, so that they can be verified after
+                    // copying them from the container
+                    out.write("{ ( cd ")?;
+                    out.write(crate::consts::OUTPUTS_DIR_PATH)?;
+                    out.write(" 2>/dev/null && find . -type f -exec sha256sum {} + 2>/dev/null")?;
+                    out.write(" | sed -n 's|^\\([0-9a-f]\\{64\\}\\)  \\./|#BUTIDO:CHECKSUM:\\1:|p' ) || true; ")?;
+                    out.write("echo '#BUTIDO:STATE:OK'; }")?;
                     Ok(())
                 }
                 "ERR" => {
@@ -343,6 +479,71 @@ impl HelperDef for ProgressHelper {
     }
 }
 
+/// Report a metric: `{{metric "key" "value"}}`
+///
+/// The value is written as it is, computed values can be reported with
+/// `echo "#BUTIDO:METRIC:key=$value"`.
+#[derive(Clone, Copy)]
+struct MetricHelper;
+
+impl HelperDef for MetricHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let key = h.param(0)
+            .ok_or_else(|| RenderError::new("Required parameter missing: metric name"))?
+            .value()
+            .as_str()
+            .ok_or_else(|| RenderError::new("Required parameter must be a string: metric name"))?;
+        if key.is_empty() || key.contains('=') {
+            return Err(RenderError::new(format!("Metric name must not be empty or contain '=': '{key}'")))
+        }
+
+        let value = h.param(1)
+            .ok_or_else(|| RenderError::new("Required parameter missing: metric value"))?
+            .value()
+            .render();
+
+        out.write("echo '#BUTIDO:METRIC:")?;
+        out.write(key)?;
+        out.write("=")?;
+        out.write(&value)?;
+        out.write("'")?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct WarnHelper;
+
+impl HelperDef for WarnHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        h.param(0)
+            .ok_or_else(|| RenderError::new("Required parameter missing: warning"))?
+            .value()
+            .as_str()
+            .ok_or_else(|| RenderError::new("Required parameter must be a string: warning"))
+            .and_then(|warning| {
+                out.write("echo '#BUTIDO:WARN:")?;
+                out.write(warning)?;
+                out.write("'")?;
+                Ok(())
+            })
+    }
+}
+
 #[derive(Clone, Copy)]
 struct JoinHelper;
 
@@ -382,6 +583,76 @@ impl HelperDef for JoinWithHelper {
     }
 }
 
+/// Helper to inline the content of a file from the directory of the package
+///
+/// The file must be inside the package directory, its hash is recorded in `included_files`.
+struct FileHelper {
+    package_dir: PathBuf,
+    included_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+}
+
+impl HelperDef for FileHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        use sha2::Digest;
+
+        let name = h
+            .param(0)
+            .ok_or_else(|| RenderError::new("Required parameter missing: file name"))?
+            .value()
+            .as_str()
+            .ok_or_else(|| RenderError::new("Required parameter must be a string: file name"))?;
+
+        let path = resolve_package_file(&self.package_dir, name)
+            .map_err(|e| RenderError::new(format!("{e:#}")))?;
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| RenderError::new(format!("Reading {}: {}", path.display(), e)))?;
+
+        let hash = format!("{:x}", sha2::Sha256::digest(content.as_bytes()));
+        self.included_files
+            .lock()
+            .map_err(|_| RenderError::new("Lock poisoned"))?
+            .push((path, hash));
+
+        out.write(&content)?;
+        Ok(())
+    }
+}
+
+/// Resolve the path of a file in the package directory
+///
+/// Fails if the path is not relative or points outside of the package directory.
+fn resolve_package_file(package_dir: &Path, name: &str) -> Result<PathBuf> {
+    let is_inside = Path::new(name)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if name.is_empty() || !is_inside {
+        return Err(anyhow!("Not a relative path inside the package directory: '{}'", name))
+    }
+
+    let path = package_dir.join(name);
+    let dir = if package_dir.as_os_str().is_empty() { Path::new(".") } else { package_dir };
+
+    // Symlinks must not point outside of the package directory either
+    let canonical_dir = dir.canonicalize()
+        .with_context(|| anyhow!("Resolving package directory {}", dir.display()))?;
+    let canonical_path = path.canonicalize()
+        .with_context(|| anyhow!("Resolving {}", path.display()))?;
+
+    if !canonical_path.starts_with(&canonical_dir) {
+        return Err(anyhow!("File is not inside the package directory: {}", path.display()))
+    }
+
+    Ok(path)
+}
+
 fn joinstrs<'reg: 'rc, 'rc, I>(with: &str, params: I, out: &mut dyn Output) -> HelperResult
 where
     I: Iterator<Item = &'rc PathAndJson<'reg, 'rc>>,
@@ -402,3 +673,70 @@ where
     out.write(&s)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_package_file_outside_of_package_dir() {
+        let dir = Path::new("src");
+
+        assert!(resolve_package_file(dir, "").is_err());
+        assert!(resolve_package_file(dir, "../Cargo.toml").is_err());
+        assert!(resolve_package_file(dir, "package/../../Cargo.toml").is_err());
+        assert!(resolve_package_file(dir, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_resolve_package_file() {
+        let p = resolve_package_file(Path::new("src"), "package/script.rs").unwrap();
+        assert_eq!(p, PathBuf::from("src/package/script.rs"));
+
+        assert!(resolve_package_file(Path::new(""), "Cargo.toml").is_ok());
+        assert!(resolve_package_file(Path::new("src"), "does-not-exist").is_err());
+    }
+
+    #[test]
+    fn test_state_helper() {
+        let mut hb = Handlebars::new();
+        hb.register_helper("state", Box::new(StateHelper));
+
+        let ok = hb.render_template(r#"make && {{state "OK"}}"#, &()).unwrap();
+        assert!(ok.starts_with("make && { ( cd /outputs "), "{}", ok);
+        assert!(ok.contains("#BUTIDO:CHECKSUM:"), "{}", ok);
+        assert!(ok.ends_with("echo '#BUTIDO:STATE:OK'; }"), "{}", ok);
+
+        let err = hb.render_template(r#"{{state "ERR" "failed"}}"#, &()).unwrap();
+        assert_eq!(err, "echo '#BUTIDO:STATE:ERR:failed'");
+    }
+
+    #[test]
+    fn test_metric_and_warn_helpers() {
+        let mut hb = Handlebars::new();
+        hb.register_helper("metric", Box::new(MetricHelper));
+        hb.register_helper("warn", Box::new(WarnHelper));
+
+        let metric = hb.render_template(r#"{{metric "tests.passed" 42}}"#, &()).unwrap();
+        assert_eq!(metric, "echo '#BUTIDO:METRIC:tests.passed=42'");
+        assert!(hb.render_template(r#"{{metric "a=b" 1}}"#, &()).is_err());
+
+        let warn = hb.render_template(r#"{{warn "no tests"}}"#, &()).unwrap();
+        assert_eq!(warn, "echo '#BUTIDO:WARN:no tests'");
+    }
+
+    #[test]
+    fn test_phase_environment() {
+        let (begin, end) = ScriptBuilder::phase_environment(&BTreeMap::new()).unwrap();
+        assert!(begin.is_empty());
+        assert!(end.is_empty());
+
+        let environment = BTreeMap::from([(EnvironmentVariableName::from("CFLAGS"), String::from("-O2 -DX='y'"))]);
+        let (begin, end) = ScriptBuilder::phase_environment(&environment).unwrap();
+        assert!(begin.contains("export CFLAGS='-O2 -DX='\\''y'\\'''\n"), "{}", begin);
+        assert!(end.contains("else unset CFLAGS; fi"), "{}", end);
+
+        let environment = BTreeMap::from([(EnvironmentVariableName::from("A;rm"), String::new())]);
+        assert!(ScriptBuilder::phase_environment(&environment).is_err());
+    }
+}
@@ -17,6 +17,8 @@ use serde::Deserialize;
 use serde::Serialize;
 use url::Url;
 
+use crate::package::Unpack;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Getters)]
 pub struct Source {
     #[getset(get = "pub")]
@@ -25,6 +27,11 @@ pub struct Source {
     hash: SourceHash,
     #[getset(get = "pub")]
     download_manually: bool,
+
+    /// If set, the source is unpacked before the phases of the package script are run
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unpack: Option<Unpack>,
 }
 
 impl Source {
@@ -34,8 +41,20 @@ impl Source {
             url,
             hash,
             download_manually: false,
+            unpack: None,
         }
     }
+
+    #[cfg(test)]
+    pub fn set_unpack(&mut self, unpack: Option<Unpack>) {
+        self.unpack = unpack;
+    }
+}
+
+/// The name of the file the source `source_name` of a package is stored as, in the source cache
+/// and in the inputs directory of the container
+pub fn source_file_name(source_name: &str) -> std::path::PathBuf {
+    std::path::Path::new(source_name).with_extension("source")
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Getters)]
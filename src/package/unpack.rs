@@ -0,0 +1,179 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use url::Url;
+
+/// How a source is unpacked before the phases of the package script are run
+///
+/// ```toml
+/// [sources.src]
+/// url = "https://example.com/foo-1.0.tar.xz"
+/// hash = { type = "sha256", hash = "..." }
+/// unpack = { dir = "src", strip_components = 1, format = "tar.xz" }
+/// ```
+///
+/// All settings are optional: `dir` defaults to the working directory of the script, `format` is
+/// guessed from the URL of the source.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Getters, CopyGetters)]
+#[serde(deny_unknown_fields)]
+pub struct Unpack {
+    /// The directory the source is unpacked into, relative to the working directory of the script
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dir: Option<PathBuf>,
+
+    /// The number of leading path components that are removed from the files in the archive
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    strip_components: usize,
+
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<ArchiveFormat>,
+}
+
+/// The format of a source archive
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, parse_display::Display)]
+pub enum ArchiveFormat {
+    #[serde(rename = "tar")]
+    #[display("tar")]
+    Tar,
+
+    #[serde(rename = "tar.gz")]
+    #[display("tar.gz")]
+    TarGz,
+
+    #[serde(rename = "tar.bz2")]
+    #[display("tar.bz2")]
+    TarBz2,
+
+    #[serde(rename = "tar.xz")]
+    #[display("tar.xz")]
+    TarXz,
+
+    #[serde(rename = "tar.zst")]
+    #[display("tar.zst")]
+    TarZst,
+
+    #[serde(rename = "zip")]
+    #[display("zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format of an archive from the file extension in its URL
+    pub fn from_url(url: &Url) -> Option<Self> {
+        let path = url.path();
+        [
+            (".tar", ArchiveFormat::Tar),
+            (".tar.gz", ArchiveFormat::TarGz),
+            (".tgz", ArchiveFormat::TarGz),
+            (".tar.bz2", ArchiveFormat::TarBz2),
+            (".tbz2", ArchiveFormat::TarBz2),
+            (".tar.xz", ArchiveFormat::TarXz),
+            (".txz", ArchiveFormat::TarXz),
+            (".tar.zst", ArchiveFormat::TarZst),
+            (".zip", ArchiveFormat::Zip),
+        ]
+        .into_iter()
+        .find(|(ext, _)| path.ends_with(ext))
+        .map(|(_, format)| format)
+    }
+}
+
+impl Unpack {
+    /// Get the shell command that unpacks the source file `file` (with the URL `url`)
+    pub fn command(&self, file: &Path, url: &Url) -> Result<String> {
+        let format = self.format
+            .or_else(|| ArchiveFormat::from_url(url))
+            .ok_or_else(|| anyhow!("Cannot guess the archive format from the URL {}, please set 'unpack.format'", url))?;
+
+        let dir = self.dir.as_deref().unwrap_or_else(|| Path::new("."));
+        let mkdir = format!("mkdir -p '{}'", dir.display());
+
+        let unpack = match format {
+            ArchiveFormat::Zip if self.strip_components > 0 => {
+                return Err(anyhow!("'unpack.strip_components' is not supported for zip archives"))
+            },
+            ArchiveFormat::Zip => format!("unzip -q -o '{}' -d '{}'", file.display(), dir.display()),
+            tar => {
+                let compression = match tar {
+                    ArchiveFormat::TarGz => " -z",
+                    ArchiveFormat::TarBz2 => " -j",
+                    ArchiveFormat::TarXz => " -J",
+                    ArchiveFormat::TarZst => " --zstd",
+                    _ => "",
+                };
+
+                let strip = if self.strip_components > 0 {
+                    format!(" --strip-components={}", self.strip_components)
+                } else {
+                    String::new()
+                };
+
+                format!("tar -x{} -f '{}' -C '{}'{}", compression, file.display(), dir.display(), strip)
+            },
+        };
+
+        Ok(format!("{mkdir} && {unpack}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(s: &str) -> Unpack {
+        toml::from_str(s).unwrap()
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_format_from_url() {
+        assert_eq!(ArchiveFormat::from_url(&url("https://example.com/foo-1.0.tar.gz")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_url(&url("https://example.com/foo-1.0.tgz?raw=1")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_url(&url("https://example.com/foo-1.0.tar")), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_url(&url("https://example.com/foo-1.0.zip")), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_url(&url("https://example.com/foo")), None);
+    }
+
+    #[test]
+    fn test_command() {
+        let file = Path::new("/inputs/src.source");
+
+        let u = unpack(r#"dir = "src"
+            strip_components = 1"#);
+        assert_eq!(
+            u.command(file, &url("https://example.com/foo-1.0.tar.xz")).unwrap(),
+            "mkdir -p 'src' && tar -x -J -f '/inputs/src.source' -C 'src' --strip-components=1"
+        );
+
+        let u = unpack(r#"format = "zip""#);
+        assert_eq!(
+            u.command(file, &url("https://example.com/download")).unwrap(),
+            "mkdir -p '.' && unzip -q -o '/inputs/src.source' -d '.'"
+        );
+
+        assert!(unpack("").command(file, &url("https://example.com/download")).is_err());
+        assert!(unpack("strip_components = 1").command(file, &url("https://example.com/foo.zip")).is_err());
+    }
+}
@@ -8,44 +8,92 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::cmp::Ordering;
 use std::ops::Deref;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use pom::parser::Parser as PomParser;
+use pom::parser::{end, list, seq, sym};
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::util::parser::*;
+use crate::util::version::compare_versions;
 
+/// A version constraint of a dependency
+///
+/// The constraint is a comma-separated list of comparisons which all must match, for example
+/// `=1.0` or `>=1.2, <2.0`. Supported comparators are `=`, `!=`, `>`, `>=`, `<` and `<=`.
+///
+/// Versions are compared component-wise, numeric components are compared numerically.
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PackageVersionConstraint {
+    comparisons: Vec<VersionComparison>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct VersionComparison {
     constraint: String,
     version: PackageVersion,
 }
 
-impl PackageVersionConstraint {
+impl VersionComparison {
     fn parser<'a>() -> PomParser<'a, u8, Self> {
-        (pom::parser::sym(b'=') + PackageVersion::parser())
+        let comparator = seq(b">=") | seq(b"<=") | seq(b"!=") | seq(b"=") | seq(b">") | seq(b"<");
+
+        (comparator + PackageVersion::parser())
             .convert(|(constraint, version)| {
-                String::from_utf8(vec![constraint]).map(|c| (c, version))
+                String::from_utf8(constraint.to_vec()).map(|c| (c, version))
             })
-            .map(|(constraint, version)| PackageVersionConstraint {
+            .map(|(constraint, version)| VersionComparison {
                 constraint,
                 version,
             })
     }
 
+    fn matches(&self, v: &PackageVersion) -> bool {
+        let ord = compare_versions(v, &self.version);
+
+        match self.constraint.as_str() {
+            "=" => *v == self.version,
+            "!=" => *v != self.version,
+            ">" => ord == Ordering::Greater,
+            ">=" => ord != Ordering::Less,
+            "<" => ord == Ordering::Less,
+            "<=" => ord != Ordering::Greater,
+            _ => false, // not possible by parser
+        }
+    }
+}
+
+impl PackageVersionConstraint {
+    fn parser<'a>() -> PomParser<'a, u8, Self> {
+        let separator = sym(b' ').repeat(0..) * sym(b',') * sym(b' ').repeat(0..);
+
+        (list(VersionComparison::parser(), separator) - end())
+            .convert(|comparisons| {
+                if comparisons.is_empty() {
+                    Err(anyhow!("Empty version constraint"))
+                } else {
+                    Ok(PackageVersionConstraint { comparisons })
+                }
+            })
+    }
+
     pub fn matches(&self, v: &PackageVersion) -> bool {
-        self.version == *v
+        self.comparisons.iter().all(|c| c.matches(v))
     }
 
     #[cfg(test)]
     pub fn from_version(constraint: String, version: PackageVersion) -> Self {
         PackageVersionConstraint {
-            constraint,
-            version,
+            comparisons: vec![VersionComparison {
+                constraint,
+                version,
+            }],
         }
     }
 }
@@ -65,7 +113,7 @@ impl std::convert::TryFrom<&str> for PackageVersionConstraint {
         PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .context("Failed to parse package version constraint")
-            .context("A package version constraint must have a comparator and a version string, like so: =0.1.0")
+            .context("A package version constraint must have a comparator and a version string, like so: =0.1.0 or >=0.1, <0.2")
             .map_err(Error::from)
 
     }
@@ -73,7 +121,15 @@ impl std::convert::TryFrom<&str> for PackageVersionConstraint {
 
 impl std::fmt::Display for PackageVersionConstraint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.constraint, self.version)
+        let mut first = true;
+        for c in self.comparisons.iter() {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}{}", c.constraint, c.version)?;
+            first = false;
+        }
+        Ok(())
     }
 }
 
@@ -147,10 +203,10 @@ mod tests {
             .parse(b"*1")
             .is_err());
         assert!(PackageVersionConstraint::parser()
-            .parse(b">1")
+            .parse(b">1,")
             .is_err());
         assert!(PackageVersionConstraint::parser()
-            .parse(b"<1")
+            .parse(b"=>1")
             .is_err());
         assert!(PackageVersionConstraint::parser()
             .parse(b"=a")
@@ -178,7 +234,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1")));
+        assert_eq!(c.comparisons[0].version, PackageVersion::from(String::from("1")));
     }
 
     #[test]
@@ -187,7 +243,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1.0.17")));
+        assert_eq!(c.comparisons[0].version, PackageVersion::from(String::from("1.0.17")));
     }
 
     #[test]
@@ -196,7 +252,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1.0.17asejg")));
+        assert_eq!(c.comparisons[0].version, PackageVersion::from(String::from("1.0.17asejg")));
     }
 
     #[test]
@@ -206,8 +262,35 @@ mod tests {
             .parse(s.as_bytes())
             .unwrap();
         assert_eq!(
-            c.version,
+            c.comparisons[0].version,
             PackageVersion::from(String::from("1-0B17-beta1247_commit_12653hasd"))
         );
     }
+
+    #[test]
+    fn test_parse_version_range() {
+        let c = PackageVersionConstraint::try_from(">=1.2, <2.0").unwrap();
+        assert_eq!(c.comparisons.len(), 2);
+        assert_eq!(c.to_string(), ">=1.2, <2.0");
+
+        let c = PackageVersionConstraint::try_from(">1,<=3,!=2.5").unwrap();
+        assert_eq!(c.comparisons.len(), 3);
+    }
+
+    #[test]
+    fn test_version_range_matches() {
+        let v = |s: &str| PackageVersion::from(String::from(s));
+        let c = PackageVersionConstraint::try_from(">=1.2, <2.0").unwrap();
+
+        assert!(c.matches(&v("1.2")));
+        assert!(c.matches(&v("1.10")));
+        assert!(c.matches(&v("1.99.1")));
+        assert!(!c.matches(&v("1.1")));
+        assert!(!c.matches(&v("2.0")));
+        assert!(!c.matches(&v("10.0")));
+
+        let c = PackageVersionConstraint::try_from("=1.2").unwrap();
+        assert!(c.matches(&v("1.2")));
+        assert!(!c.matches(&v("1.2.0")));
+    }
 }
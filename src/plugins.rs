@@ -0,0 +1,130 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! External plugins
+//!
+//! Executables named "butido-<name>" on the PATH are available as "butido <name>", like the
+//! external subcommands of git or cargo. The PATH is only searched for subcommands that are not
+//! builtin, so plugins cannot replace the builtin subcommands.
+
+use std::ffi::OsString;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::debug;
+
+const PLUGIN_PREFIX: &str = "butido-";
+
+/// An executable that is available as subcommand
+#[derive(Debug)]
+pub struct Plugin {
+    path: PathBuf,
+}
+
+/// Find the plugin for the subcommand `name` on the PATH
+///
+/// If there are several executables with the same name, the first one on the PATH is used.
+pub fn find(name: &str) -> Option<Plugin> {
+    std::env::var_os("PATH").and_then(|path| find_in(name, std::env::split_paths(&path)))
+}
+
+fn find_in(name: &str, dirs: impl Iterator<Item = PathBuf>) -> Option<Plugin> {
+    // the name is used as file name, it must not point somewhere else
+    if name.is_empty() || name.contains('/') {
+        return None
+    }
+
+    let file_name = format!("{PLUGIN_PREFIX}{name}");
+    dirs.map(|dir| dir.join(&file_name))
+        .find(|path| is_executable(path))
+        .map(|path| {
+            debug!("Found plugin {} at {}", name, path.display());
+            Plugin { path }
+        })
+}
+
+fn is_executable(path: &Path) -> bool {
+    // follows symlinks, so that linked executables are found as well
+    std::fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Run a plugin with `args` and return its exit code
+///
+/// The plugin inherits stdin, stdout and stderr. It gets the path of the butido executable in
+/// BUTIDO_EXECUTABLE, and the configuration file and profile that were passed on the command line
+/// in BUTIDO_CONFIG and BUTIDO_PROFILE, so that it can call butido with the same settings.
+pub async fn run(plugin: &Plugin, args: &[OsString], config: Option<&String>, profile: Option<&String>) -> Result<i32> {
+    let mut command = tokio::process::Command::new(&plugin.path);
+    command.args(args);
+
+    if let Ok(executable) = std::env::current_exe() {
+        command.env("BUTIDO_EXECUTABLE", executable);
+    }
+    if let Some(config) = config {
+        command.env("BUTIDO_CONFIG", config);
+    }
+    if let Some(profile) = profile {
+        command.env("BUTIDO_PROFILE", profile);
+    }
+
+    debug!("Running plugin {} with {:?}", plugin.path.display(), args);
+    let status = command
+        .status()
+        .await
+        .with_context(|| anyhow!("Starting plugin {}", plugin.path.display()))?;
+
+    // a plugin that was killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &Path, mode: u32) {
+        std::fs::write(path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_find_in() {
+        let dir = std::env::temp_dir().join(format!("butido-test-plugins-{}", uuid::Uuid::new_v4()));
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+
+        write_file(&first.join("butido-foo"), 0o755);
+        write_file(&first.join("butido-noexec"), 0o644);
+        write_file(&second.join("butido-foo"), 0o755);
+        write_file(&second.join("butido-bar"), 0o755);
+
+        let dirs = vec![first.clone(), dir.join("missing"), second.clone()];
+        let found = ["foo", "bar", "noexec", "baz", "", "../first/butido-foo"]
+            .iter()
+            .map(|name| find_in(name, dirs.clone().into_iter()).map(|p| p.path))
+            .collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(found, vec![
+            Some(first.join("butido-foo")),
+            Some(second.join("butido-bar")),
+            None,
+            None,
+            None,
+            None,
+        ]);
+    }
+}
@@ -0,0 +1,153 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The "latest" links in a release store
+//!
+//! "latest/<package>/" contains links to the files of the newest released version of a package,
+//! and "latest/<package>/<major version>/" links to the files of the newest version with that
+//! major version. In the names of the links, the version is replaced with "latest", so that the
+//! paths stay the same when a newer version is released, e.g. "latest/foo/foo-latest.tar.gz"
+//! links to "foo-1.2.tar.gz".
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::release_metadata::ReleasedPackages;
+use crate::util::version::compare_versions;
+
+const LATEST_DIR: &str = "latest";
+const LATEST_DIR_NEW: &str = ".latest.new";
+
+/// Recreate the links in the release store at `store` for the released files that are in the store
+///
+/// The links are created in a new directory that replaces the old one, so that links to removed
+/// or rolled back releases do not stay around.
+pub fn update(store: &Path, per_major_version: bool, packages: &ReleasedPackages) -> Result<()> {
+    let links = latest_links(packages, per_major_version, |path| store.join(path).is_file());
+
+    let new_dir = store.join(LATEST_DIR_NEW);
+    if new_dir.exists() {
+        std::fs::remove_dir_all(&new_dir).with_context(|| anyhow!("Removing {}", new_dir.display()))?;
+    }
+    std::fs::create_dir_all(&new_dir).with_context(|| anyhow!("Creating {}", new_dir.display()))?;
+
+    for (link, target) in links {
+        let link = new_dir.join(link);
+        if let Some(parent) = link.parent() {
+            std::fs::create_dir_all(parent).with_context(|| anyhow!("Creating {}", parent.display()))?;
+        }
+        std::os::unix::fs::symlink(&target, &link)
+            .with_context(|| anyhow!("Linking {} to {}", link.display(), target.display()))?;
+    }
+
+    let dir = store.join(LATEST_DIR);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).with_context(|| anyhow!("Removing {}", dir.display()))?;
+    }
+    std::fs::rename(&new_dir, &dir).with_context(|| anyhow!("Renaming {} to {}", new_dir.display(), dir.display()))
+}
+
+/// The links (relative to the "latest" directory) and their (relative) targets
+fn latest_links(packages: &ReleasedPackages, per_major_version: bool, exists: impl Fn(&Path) -> bool) -> Vec<(PathBuf, PathBuf)> {
+    let released = packages.iter()
+        .filter(|(path, _)| exists(path.as_path()))
+        .collect::<Vec<_>>();
+
+    // The directories of the links and the newest version that is linked in each
+    let link_dirs = |name: &str, version: &str| {
+        let mut dirs = vec![PathBuf::from(name)];
+        if per_major_version {
+            if let Some(major) = major_version(version) {
+                dirs.push(PathBuf::from(name).join(major));
+            }
+        }
+        dirs
+    };
+
+    let mut newest: BTreeMap<PathBuf, &str> = BTreeMap::new();
+    for (_, (name, version)) in released.iter() {
+        for dir in link_dirs(name.as_str(), version.as_str()) {
+            let entry = newest.entry(dir).or_insert(version.as_str());
+            if compare_versions(version, entry) == Ordering::Greater {
+                *entry = version.as_str();
+            }
+        }
+    }
+
+    let mut links = BTreeMap::new();
+    for (path, (name, version)) in released {
+        let file_name = match path.file_name().and_then(|f| f.to_str()) {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        for dir in link_dirs(name.as_str(), version.as_str()).into_iter().filter(|dir| newest.get(dir) == Some(&version.as_str())) {
+            // "latest/" and the directories of the link
+            let up = std::iter::repeat("..")
+                .take(dir.components().count() + 1)
+                .collect::<PathBuf>();
+            links.entry(dir.join(file_name.replacen(version.as_str(), "latest", 1)))
+                .or_insert_with(|| up.join(path));
+        }
+    }
+
+    links.into_iter().collect()
+}
+
+/// The first component of a version, e.g. "1" of "1.2.3"
+fn major_version(version: &str) -> Option<&str> {
+    version.split(|c: char| !c.is_ascii_alphanumeric()).find(|c| !c.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages(files: &[(&str, &str, &str)]) -> ReleasedPackages {
+        files.iter()
+            .map(|(path, name, version)| (PathBuf::from(path), (name.to_string(), version.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_major_version() {
+        assert_eq!(major_version("1.2.3"), Some("1"));
+        assert_eq!(major_version("2023-01"), Some("2023"));
+        assert_eq!(major_version(""), None);
+    }
+
+    #[test]
+    fn test_latest_links() {
+        let packages = packages(&[
+            ("foo-1.2.tar.gz", "foo", "1.2"),
+            ("foo-1.10.tar.gz", "foo", "1.10"),
+            ("foo-2.0.tar.gz", "foo", "2.0"),
+            ("x86_64/bar-0.1-1.x86_64.rpm", "bar", "0.1"),
+        ]);
+
+        assert_eq!(latest_links(&packages, false, |_| true), vec![
+            (PathBuf::from("bar/bar-latest-1.x86_64.rpm"), PathBuf::from("../../x86_64/bar-0.1-1.x86_64.rpm")),
+            (PathBuf::from("foo/foo-latest.tar.gz"), PathBuf::from("../../foo-2.0.tar.gz")),
+        ]);
+
+        assert_eq!(latest_links(&packages, true, |p| p != Path::new("foo-2.0.tar.gz")), vec![
+            (PathBuf::from("bar/0/bar-latest-1.x86_64.rpm"), PathBuf::from("../../../x86_64/bar-0.1-1.x86_64.rpm")),
+            (PathBuf::from("bar/bar-latest-1.x86_64.rpm"), PathBuf::from("../../x86_64/bar-0.1-1.x86_64.rpm")),
+            (PathBuf::from("foo/1/foo-latest.tar.gz"), PathBuf::from("../../../foo-1.10.tar.gz")),
+            (PathBuf::from("foo/foo-latest.tar.gz"), PathBuf::from("../../foo-1.10.tar.gz")),
+        ]);
+    }
+}
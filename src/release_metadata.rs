@@ -0,0 +1,214 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Generating package repository metadata in a release store
+//!
+//! With the metadata, a release store can be served directly as a package repository, e.g. as
+//! `deb [trusted=yes] https://example.com/releases/default ./` or as `baseurl` of a yum repository.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::Utc;
+use tracing::debug;
+use tracing::trace;
+
+use crate::config::RepositoryMetadataFormat;
+
+const JSON_INDEX: &str = "index.json";
+const DEB_PACKAGES: &str = "Packages";
+const DEB_PACKAGES_GZ: &str = "Packages.gz";
+const DEB_RELEASE: &str = "Release";
+const RPM_REPODATA: &str = "repodata";
+
+/// The package and version of a released file, by its path relative to the release store
+pub type ReleasedPackages = HashMap<PathBuf, (String, String)>;
+
+/// Generate the metadata in the given formats for the release store at `store`
+pub async fn generate(store: &Path, formats: &[RepositoryMetadataFormat], packages: ReleasedPackages) -> Result<()> {
+    for format in formats {
+        debug!("Generating {} repository metadata in {}", format, store.display());
+        let generated = match format {
+            RepositoryMetadataFormat::Rpm => generate_rpm(store).await,
+            RepositoryMetadataFormat::Deb => generate_deb(store).await,
+            RepositoryMetadataFormat::Json => {
+                let store = store.to_path_buf();
+                let packages = packages.clone();
+                tokio::task::spawn_blocking(move || generate_json(&store, &packages)).await?
+            },
+        };
+        generated.with_context(|| anyhow!("Generating {} repository metadata in {}", format, store.display()))?;
+    }
+
+    Ok(())
+}
+
+async fn run(cmd: &mut tokio::process::Command) -> Result<Vec<u8>> {
+    trace!("Executing: {:?}", cmd);
+    let output = cmd.output()
+        .await
+        .with_context(|| anyhow!("Executing {:?}", cmd))?;
+
+    if !output.status.success() {
+        return Err(anyhow!("{:?} failed ({}): {}", cmd, output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+    Ok(output.stdout)
+}
+
+async fn generate_rpm(store: &Path) -> Result<()> {
+    run(tokio::process::Command::new("createrepo_c")
+        .arg("--quiet")
+        .arg("--update")
+        .arg(store))
+        .await
+        .map(|_| ())
+}
+
+async fn generate_deb(store: &Path) -> Result<()> {
+    let packages = run(tokio::process::Command::new("dpkg-scanpackages")
+        .arg("--multiversion")
+        .arg(".")
+        .current_dir(store))
+        .await?;
+
+    let store = store.to_path_buf();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut packages_gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        packages_gz.write_all(&packages)?;
+        let packages_gz = packages_gz.finish()?;
+
+        let release = release_file(Utc::now(), &[
+            (DEB_PACKAGES, packages.as_slice()),
+            (DEB_PACKAGES_GZ, packages_gz.as_slice()),
+        ]);
+
+        write_atomically(&store.join(DEB_PACKAGES), &packages)?;
+        write_atomically(&store.join(DEB_PACKAGES_GZ), &packages_gz)?;
+        write_atomically(&store.join(DEB_RELEASE), release.as_bytes())
+    })
+    .await?
+}
+
+/// The "Release" file of a flat debian repository with the checksums of the index files
+fn release_file(date: DateTime<Utc>, files: &[(&str, &[u8])]) -> String {
+    use sha2::Digest;
+
+    let mut release = format!("Origin: butido\nLabel: butido\nDate: {}\nSHA256:\n", date.to_rfc2822());
+    for (name, content) in files {
+        release.push_str(&format!(" {:x} {} {}\n", sha2::Sha256::digest(content), content.len(), name));
+    }
+    release
+}
+
+fn generate_json(store: &Path, packages: &ReleasedPackages) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(store).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path().strip_prefix(store)?.to_path_buf();
+        if !entry.file_type().is_file() || is_metadata(&path) {
+            continue
+        }
+
+        let mut hasher = <sha2::Sha256 as sha2::Digest>::new();
+        let mut file = std::fs::File::open(entry.path())
+            .with_context(|| anyhow!("Opening {}", entry.path().display()))?;
+        let size = std::io::copy(&mut file, &mut hasher)
+            .with_context(|| anyhow!("Reading {}", entry.path().display()))?;
+        let sha256 = format!("{:x}", sha2::Digest::finalize(hasher));
+
+        let package = packages.get(&path);
+        files.push(serde_json::json!({
+            "path": path.display().to_string(),
+            "size": size,
+            "sha256": sha256,
+            "package": package.map(|(name, _)| name),
+            "version": package.map(|(_, version)| version),
+        }));
+    }
+
+    let index = serde_json::json!({
+        "generated": Utc::now().to_rfc3339(),
+        "files": files,
+    });
+    write_atomically(&store.join(JSON_INDEX), serde_json::to_string_pretty(&index)?.as_bytes())
+}
+
+/// The generated metadata files in the release store at `store`, relative to the store
+pub fn metadata_files(store: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = [JSON_INDEX, DEB_PACKAGES, DEB_PACKAGES_GZ, DEB_RELEASE]
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|file| store.join(file).is_file())
+        .collect::<Vec<_>>();
+
+    if store.join(RPM_REPODATA).is_dir() {
+        for entry in walkdir::WalkDir::new(store.join(RPM_REPODATA)).sort_by_file_name() {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                files.push(entry.path().strip_prefix(store)?.to_path_buf());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Whether a path (relative to the release store) is generated metadata, which is not indexed
+fn is_metadata(path: &Path) -> bool {
+    path.starts_with(RPM_REPODATA)
+        || path == Path::new(JSON_INDEX)
+        || path == Path::new(DEB_PACKAGES)
+        || path == Path::new(DEB_PACKAGES_GZ)
+        || path == Path::new(DEB_RELEASE)
+        || path.extension().map(|ext| ext == "tmp").unwrap_or(false)
+}
+
+/// Write a file via a temporary file, so that clients never see a partial file
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    std::fs::write(&tmp, content).with_context(|| anyhow!("Writing {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| anyhow!("Renaming {} to {}", tmp.display(), path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_release_file() {
+        let date = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        assert_eq!(release_file(date, &[("Packages", &b""[..])]), indoc::indoc!("
+            Origin: butido
+            Label: butido
+            Date: Mon, 02 Jan 2023 03:04:05 +0000
+            SHA256:
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 0 Packages
+        "));
+    }
+
+    #[test]
+    fn test_is_metadata() {
+        assert!(is_metadata(Path::new("repodata/repomd.xml")));
+        assert!(is_metadata(Path::new("Packages.gz")));
+        assert!(is_metadata(Path::new("index.json")));
+        assert!(!is_metadata(Path::new("foo/index.json")));
+        assert!(!is_metadata(Path::new("foo-1.0.x86_64.rpm")));
+    }
+}
@@ -0,0 +1,269 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! SLSA provenance documents of released artifacts
+//!
+//! The provenance of an artifact is written as in-toto statement with a SLSA provenance v1
+//! predicate (https://slsa.dev/provenance/v1) to "<file>.intoto.json", next to the artifact.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use tracing::debug;
+
+use crate::config::ReleaseProvenanceConfig;
+use crate::config::ReleaseSigner;
+use crate::db::models as dbmodels;
+use crate::schema;
+
+const BUILD_TYPE: &str = "https://github.com/science-computing/butido/build/v1";
+
+/// How an artifact was built, as recorded in the database
+#[derive(Debug)]
+pub struct Build {
+    pub package_name: String,
+    pub package_version: String,
+    pub submit: uuid::Uuid,
+    pub job: uuid::Uuid,
+    pub git_hash: String,
+    pub endpoint: String,
+    pub image: String,
+    pub image_digest: Option<String>,
+    pub flavor: Option<String>,
+    pub features: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub phases: Vec<String>,
+    pub butido_version: Option<String>,
+    pub started: Option<NaiveDateTime>,
+    pub finished: Option<NaiveDateTime>,
+}
+
+impl Build {
+    /// Load the build of an artifact from the database
+    pub fn load(conn: &PgConnection, artifact: &dbmodels::Artifact) -> Result<Build> {
+        let (job, submit, endpoint, image, package, git_hash) = schema::jobs::table
+            .inner_join(schema::submits::table)
+            .inner_join(schema::endpoints::table)
+            .inner_join(schema::images::table)
+            .inner_join(schema::packages::table)
+            .inner_join(schema::githashes::table
+                .on(schema::githashes::id.eq(schema::submits::repo_hash_id)))
+            .filter(schema::jobs::id.eq(artifact.job_id))
+            .select((
+                schema::jobs::all_columns,
+                schema::submits::all_columns,
+                schema::endpoints::all_columns,
+                schema::images::all_columns,
+                schema::packages::all_columns,
+                schema::githashes::all_columns,
+            ))
+            .first::<(dbmodels::Job, dbmodels::Submit, dbmodels::Endpoint, dbmodels::Image, dbmodels::Package, dbmodels::GitHash)>(conn)
+            .with_context(|| anyhow!("Loading the job of artifact {}", artifact.path))?;
+
+        let env = job.env(conn)?
+            .into_iter()
+            .map(|env| (env.name, env.value))
+            .collect();
+
+        let phases = schema::job_phases::table
+            .filter(schema::job_phases::job_id.eq(job.id))
+            .order_by(schema::job_phases::started.asc())
+            .select(schema::job_phases::name)
+            .load::<String>(conn)
+            .with_context(|| anyhow!("Loading the phases of job {}", job.uuid))?;
+
+        Ok(Build {
+            package_name: package.name,
+            package_version: package.version,
+            submit: submit.uuid,
+            job: job.uuid,
+            git_hash: git_hash.hash,
+            endpoint: endpoint.name,
+            image: image.name,
+            image_digest: job.image_digest,
+            flavor: submit.flavor,
+            features: submit.features,
+            env,
+            phases,
+            butido_version: submit.butido_version,
+            started: job.started,
+            finished: job.finished,
+        })
+    }
+}
+
+/// Write the provenance document of the released artifact at `file` and sign it, if configured
+///
+/// Returns the paths of the document and its signature.
+pub async fn write(
+    config: &ReleaseProvenanceConfig,
+    signer: Option<&ReleaseSigner>,
+    build: &Build,
+    file: &Path,
+) -> Result<Vec<PathBuf>> {
+    let name = file.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Not a file name: {}", file.display()))?;
+    let (digest, _) = crate::util::sha256_of_file(file).await?;
+    let sha256 = digest.trim_start_matches("sha256:");
+
+    let statement = statement(config, build, name, sha256);
+    let path = provenance_path(file);
+    debug!("Writing provenance of {} to {}", file.display(), path.display());
+    tokio::fs::write(&path, serde_json::to_string_pretty(&statement)?)
+        .await
+        .with_context(|| anyhow!("Writing {}", path.display()))?;
+
+    let mut paths = vec![path];
+    if config.sign() {
+        if let Some(signer) = signer {
+            let signature = crate::release_signing::sign(signer, &paths[0]).await?;
+            paths.push(signature.path);
+        }
+    }
+    Ok(paths)
+}
+
+/// The path of the provenance document of a file, "<file>.intoto.json"
+pub fn provenance_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".intoto.json");
+    PathBuf::from(path)
+}
+
+/// The provenance document of a file and its signature, if they exist
+pub fn existing_files(file: &Path) -> Result<Vec<PathBuf>> {
+    let path = provenance_path(file);
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| anyhow!("Reading {}", dir.display()))? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(name.as_ref()) && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// The in-toto statement of an artifact with the file name `name`
+fn statement(config: &ReleaseProvenanceConfig, build: &Build, name: &str, sha256: &str) -> serde_json::Value {
+    let format_time = |time: &NaiveDateTime| format!("{}Z", time.format("%Y-%m-%dT%H:%M:%S"));
+
+    let mut repository = serde_json::json!({
+        "name": "repository",
+        "digest": { "gitCommit": build.git_hash },
+    });
+    if let Some(uri) = config.repository_uri() {
+        repository["uri"] = serde_json::Value::String(uri.clone());
+    }
+
+    let mut image = serde_json::json!({
+        "name": "image",
+        "uri": format!("docker-image://{}", build.image),
+    });
+    if let Some((algorithm, digest)) = build.image_digest.as_deref().and_then(|d| d.split_once(':')) {
+        image["digest"] = serde_json::json!({ algorithm: digest });
+    }
+
+    serde_json::json!({
+        "_type": "https://in-toto.io/Statement/v1",
+        "subject": [
+            { "name": name, "digest": { "sha256": sha256 } }
+        ],
+        "predicateType": "https://slsa.dev/provenance/v1",
+        "predicate": {
+            "buildDefinition": {
+                "buildType": BUILD_TYPE,
+                "externalParameters": {
+                    "package": { "name": build.package_name, "version": build.package_version },
+                    "image": build.image,
+                    "flavor": build.flavor,
+                    "features": build.features,
+                    "environment": build.env
+                        .iter()
+                        .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                        .collect::<serde_json::Map<_, _>>(),
+                },
+                "internalParameters": {
+                    "endpoint": build.endpoint,
+                    "phases": build.phases,
+                },
+                "resolvedDependencies": [repository, image],
+            },
+            "runDetails": {
+                "builder": {
+                    "id": config.builder_id(),
+                    "version": { "butido": build.butido_version },
+                },
+                "metadata": {
+                    "invocationId": format!("{}/{}", build.submit, build.job),
+                    "startedOn": build.started.as_ref().map(format_time),
+                    "finishedOn": build.finished.as_ref().map(format_time),
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_path() {
+        assert_eq!(provenance_path(Path::new("/releases/foo-1.0.rpm")), PathBuf::from("/releases/foo-1.0.rpm.intoto.json"));
+    }
+
+    #[test]
+    fn test_statement() {
+        let config: ReleaseProvenanceConfig = toml::from_str(r#"repository_uri = "git+https://git.example.com/packages.git""#).unwrap();
+        let time = chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap().and_hms_opt(3, 4, 5).unwrap();
+        let build = Build {
+            package_name: String::from("foo"),
+            package_version: String::from("1.0"),
+            submit: uuid::Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            job: uuid::Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap(),
+            git_hash: String::from("abc123"),
+            endpoint: String::from("builder1"),
+            image: String::from("debian:bullseye"),
+            image_digest: Some(String::from("sha256:0123")),
+            flavor: None,
+            features: vec![String::from("ssl")],
+            env: vec![(String::from("FOO"), String::from("bar"))],
+            phases: vec![String::from("build"), String::from("package")],
+            butido_version: Some(String::from("0.4.0")),
+            started: Some(time),
+            finished: None,
+        };
+
+        let statement = statement(&config, &build, "foo-1.0.tar.gz", "e3b0");
+        assert_eq!(statement["subject"], serde_json::json!([{ "name": "foo-1.0.tar.gz", "digest": { "sha256": "e3b0" } }]));
+
+        let predicate = &statement["predicate"];
+        assert_eq!(predicate["buildDefinition"]["externalParameters"]["environment"], serde_json::json!({ "FOO": "bar" }));
+        assert_eq!(predicate["buildDefinition"]["resolvedDependencies"], serde_json::json!([
+            { "name": "repository", "uri": "git+https://git.example.com/packages.git", "digest": { "gitCommit": "abc123" } },
+            { "name": "image", "uri": "docker-image://debian:bullseye", "digest": { "sha256": "0123" } },
+        ]));
+        assert_eq!(predicate["runDetails"]["builder"]["id"], "https://github.com/science-computing/butido");
+        assert_eq!(predicate["runDetails"]["metadata"]["invocationId"], "00000000-0000-0000-0000-000000000001/00000000-0000-0000-0000-000000000002");
+        assert_eq!(predicate["runDetails"]["metadata"]["startedOn"], "2023-01-02T03:04:05Z");
+        assert_eq!(predicate["runDetails"]["metadata"]["finishedOn"], serde_json::Value::Null);
+    }
+}
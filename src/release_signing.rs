@@ -0,0 +1,141 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Signing of released artifacts
+//!
+//! The signatures are detached signatures next to the released files.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::debug;
+use tracing::trace;
+
+use crate::config::CommandSigner;
+use crate::config::GpgSigner;
+use crate::config::ReleaseSigner;
+
+/// A created signature
+#[derive(Debug)]
+pub struct Signature {
+    pub path: PathBuf,
+
+    /// The fingerprint of the key the file was signed with, if known
+    pub fingerprint: Option<String>,
+}
+
+/// Sign a file, the signature is written next to it
+pub async fn sign(signer: &ReleaseSigner, file: &Path) -> Result<Signature> {
+    debug!("Signing {}", file.display());
+    let signature = match signer {
+        ReleaseSigner::Gpg(gpg) => sign_gpg(gpg, file).await,
+        ReleaseSigner::Command(command) => sign_command(command, file).await,
+    };
+    signature.with_context(|| anyhow!("Signing {}", file.display()))
+}
+
+async fn sign_gpg(signer: &GpgSigner, file: &Path) -> Result<Signature> {
+    let path = signature_path(file, "asc");
+
+    let mut cmd = tokio::process::Command::new("gpg");
+    if let Some(homedir) = signer.homedir() {
+        cmd.arg("--homedir").arg(homedir);
+    }
+    cmd.arg("--batch")
+        .arg("--yes")
+        .arg("--status-fd")
+        .arg("1")
+        .arg("--local-user")
+        .arg(signer.key())
+        .arg("--armor")
+        .arg("--detach-sign")
+        .arg("--output")
+        .arg(&path)
+        .arg(file);
+    trace!("Executing: {:?}", cmd);
+
+    let output = cmd.output().await.context("Executing gpg")?;
+    if !output.status.success() {
+        return Err(anyhow!("gpg failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+
+    Ok(Signature {
+        path,
+        fingerprint: created_signature_fingerprint(&String::from_utf8_lossy(&output.stdout)),
+    })
+}
+
+async fn sign_command(signer: &CommandSigner, file: &Path) -> Result<Signature> {
+    let path = signature_path(file, signer.signature_extension());
+    let (program, args) = signer.command()
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty signing command"))?;
+
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .env("BUTIDO_ARTIFACT_PATH", file)
+        .env("BUTIDO_SIGNATURE_PATH", &path)
+        .status()
+        .await
+        .with_context(|| anyhow!("Starting {}", program))?;
+
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status))
+    }
+    if !path.is_file() {
+        return Err(anyhow!("{} did not write the signature to {}", program, path.display()))
+    }
+
+    Ok(Signature {
+        path,
+        fingerprint: signer.fingerprint().clone(),
+    })
+}
+
+/// The path of the signature of a file, "<file>.<extension>"
+fn signature_path(file: &Path, extension: &str) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// The fingerprint from the SIG_CREATED line of the status output of gpg
+fn created_signature_fingerprint(status: &str) -> Option<String> {
+    status.lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] SIG_CREATED "))
+        .filter_map(|fields| fields.split_whitespace().nth(5))
+        .map(String::from)
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_path() {
+        assert_eq!(signature_path(Path::new("/releases/foo-1.0.rpm"), "asc"), PathBuf::from("/releases/foo-1.0.rpm.asc"));
+    }
+
+    #[test]
+    fn test_created_signature_fingerprint() {
+        let status = indoc::indoc!("
+            [GNUPG:] KEY_CONSIDERED 0123456789ABCDEF0123456789ABCDEF01234567 2
+            [GNUPG:] BEGIN_SIGNING H8
+            [GNUPG:] SIG_CREATED D 1 8 00 1672531200 0123456789ABCDEF0123456789ABCDEF01234567
+        ");
+        assert_eq!(created_signature_fingerprint(status).as_deref(), Some("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert_eq!(created_signature_fingerprint("[GNUPG:] BEGIN_SIGNING H8"), None);
+    }
+}
@@ -0,0 +1,29 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Syncing the files of a release store to a remote storage
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::config::ReleaseSyncTarget;
+
+mod rsync;
+mod s3;
+
+/// Copy the `files` (relative to the release store at `store`) to "<target>/<store_name>/"
+pub async fn sync(target: &ReleaseSyncTarget, store: &Path, store_name: &str, files: &[PathBuf]) -> Result<()> {
+    match target {
+        ReleaseSyncTarget::Rsync(rsync) => rsync::sync(rsync, store, store_name, files).await,
+        ReleaseSyncTarget::S3(s3) => s3::sync(s3, store, store_name, files).await,
+    }
+}
@@ -0,0 +1,124 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Syncing with `rsync` over SSH
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+use tracing::trace;
+
+use crate::config::RsyncTarget;
+
+pub async fn sync(target: &RsyncTarget, store: &Path, store_name: &str, files: &[PathBuf]) -> Result<()> {
+    let mut cmd = command(target, store, store_name);
+    trace!("Executing: {:?}", cmd);
+
+    let mut child = cmd.spawn().context("Executing rsync")?;
+    {
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin for rsync"))?;
+        stdin.write_all(&files_from(files)).await?;
+    } // close stdin, so rsync starts syncing
+
+    let output = child.wait_with_output().await.context("Waiting for rsync")?;
+    if !output.status.success() {
+        return Err(anyhow!("rsync failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+    Ok(())
+}
+
+/// The rsync command, which reads the files to sync from stdin
+fn command(target: &RsyncTarget, store: &Path, store_name: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("rsync");
+    cmd.arg("--archive")
+        .arg("--from0")
+        .arg("--files-from=-") // implies --relative, so the directories of the files are kept
+        .arg("-e")
+        .arg(ssh_command(target))
+        .arg(format!("{}/", store.display()))
+        .arg(destination(target, store_name))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    cmd
+}
+
+/// The list of files for `--files-from`, separated by NUL bytes as set with `--from0`
+fn files_from(files: &[PathBuf]) -> Vec<u8> {
+    files.iter()
+        .flat_map(|file| file.as_os_str().as_bytes().iter().copied().chain(std::iter::once(b'\0')))
+        .collect()
+}
+
+/// The remote shell for rsync
+///
+/// SSH must never ask for a password, as the output of rsync is captured.
+fn ssh_command(target: &RsyncTarget) -> String {
+    let mut ssh = String::from("ssh -o BatchMode=yes");
+    if let Some(port) = target.port() {
+        ssh.push_str(&format!(" -p {port}"));
+    }
+    if let Some(key) = target.ssh_key() {
+        ssh.push_str(&format!(" -i '{}'", key.display()));
+    }
+    ssh
+}
+
+fn destination(target: &RsyncTarget, store_name: &str) -> String {
+    format!("{}/{}/", target.destination().trim_end_matches('/'), store_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rsync_arguments() {
+        let target: RsyncTarget = toml::from_str(indoc::indoc!(r#"
+            destination = "butido@mirror.example.com:/srv/releases/"
+            port = 2222
+            ssh_key = "/home/butido/.ssh/id_mirror"
+        "#)).unwrap();
+
+        assert_eq!(ssh_command(&target), "ssh -o BatchMode=yes -p 2222 -i '/home/butido/.ssh/id_mirror'");
+        assert_eq!(destination(&target, "default"), "butido@mirror.example.com:/srv/releases/default/");
+
+        let cmd = command(&target, Path::new("/srv/butido/releases/default"), "default");
+        assert_eq!(cmd.as_std().get_args().collect::<Vec<_>>(), [
+            "--archive",
+            "--from0",
+            "--files-from=-",
+            "-e",
+            "ssh -o BatchMode=yes -p 2222 -i '/home/butido/.ssh/id_mirror'",
+            "/srv/butido/releases/default/",
+            "butido@mirror.example.com:/srv/releases/default/",
+        ]);
+    }
+
+    #[test]
+    fn test_ssh_command_defaults() {
+        let target: RsyncTarget = toml::from_str(r#"destination = "mirror:/srv/releases""#).unwrap();
+        assert_eq!(ssh_command(&target), "ssh -o BatchMode=yes");
+        assert_eq!(destination(&target, "stable"), "mirror:/srv/releases/stable/");
+    }
+
+    #[test]
+    fn test_files_from() {
+        let files = [PathBuf::from("x86_64/foo 1.0.rpm"), PathBuf::from("repodata/repomd.xml")];
+        assert_eq!(files_from(&files), b"x86_64/foo 1.0.rpm\0repodata/repomd.xml\0");
+        assert!(files_from(&[]).is_empty());
+    }
+}
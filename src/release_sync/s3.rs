@@ -0,0 +1,491 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Uploading to an S3 compatible object storage
+//!
+//! The requests are signed with AWS signature version 4. The payload is not signed, so that the
+//! files can be streamed. Files larger than `MULTIPART_THRESHOLD` are uploaded in parts, as S3
+//! does not accept objects larger than 5 GiB in a single upload.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::Utc;
+use reqwest::Method;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use tracing::debug;
+use tracing::warn;
+
+use crate::config::S3Target;
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Files larger than this are uploaded in parts
+const MULTIPART_THRESHOLD: u64 = 1024 * 1024 * 1024;
+
+/// The size of the parts of a multipart upload, if the file has less than `MAX_PARTS` parts
+const PART_SIZE: u64 = 128 * 1024 * 1024;
+
+/// The maximum number of parts of a multipart upload
+const MAX_PARTS: u64 = 10_000;
+
+pub async fn sync(target: &S3Target, store: &Path, store_name: &str, files: &[PathBuf]) -> Result<()> {
+    let client = reqwest::Client::new();
+
+    for file in files {
+        let path = store.join(file);
+        let url = object_url(target, &object_key(target.prefix(), store_name, file))?;
+        let size = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| anyhow!("Reading metadata of {}", path.display()))?
+            .len();
+
+        let result = if size > MULTIPART_THRESHOLD {
+            debug!("Uploading {} to {} in parts", path.display(), url);
+            upload_multipart(&client, target, &path, &url, size, PART_SIZE).await
+        } else {
+            debug!("Uploading {} to {}", path.display(), url);
+            send(&client, target, Method::PUT, &url, Some(file_part(&path, 0, size).await?)).await.map(|_| ())
+        };
+        result.with_context(|| anyhow!("Uploading {} to {}", path.display(), url))?;
+    }
+
+    Ok(())
+}
+
+/// Upload the file at `path` in parts of at least `part_size` bytes
+///
+/// The upload is aborted if one of the parts cannot be uploaded, so that the storage does not keep
+/// the parts that were uploaded already.
+async fn upload_multipart(client: &reqwest::Client, target: &S3Target, path: &Path, url: &url::Url, size: u64, part_size: u64) -> Result<()> {
+    let mut create_url = url.clone();
+    create_url.set_query(Some("uploads"));
+    let response = send(client, target, Method::POST, &create_url, None).await?.text().await?;
+    let upload_id = xml_value(&response, "UploadId")
+        .ok_or_else(|| anyhow!("No upload ID in the response: {}", response))?
+        .to_string();
+
+    let mut upload_url = url.clone();
+    upload_url.query_pairs_mut().append_pair("uploadId", &upload_id);
+
+    let result = async {
+        let mut etags = Vec::new();
+        for (i, (offset, len)) in parts(size, part_size).into_iter().enumerate() {
+            let mut part_url = url.clone();
+            part_url.query_pairs_mut()
+                .append_pair("partNumber", &(i + 1).to_string())
+                .append_pair("uploadId", &upload_id);
+
+            let response = send(client, target, Method::PUT, &part_url, Some(file_part(path, offset, len).await?)).await?;
+            let etag = response.headers()
+                .get(reqwest::header::ETAG)
+                .ok_or_else(|| anyhow!("No ETag in the response for part {}", i + 1))?
+                .to_str()?
+                .to_string();
+            etags.push(etag);
+        }
+
+        let body = complete_body(&etags);
+        let len = body.len() as u64;
+        let body = (reqwest::Body::from(body), len);
+        let response = send(client, target, Method::POST, &upload_url, Some(body)).await?.text().await?;
+
+        // Completing the upload can fail after the response was started, with an error in the body
+        if response.contains("<Error>") {
+            return Err(anyhow!("Completing the upload failed: {}", response.trim()))
+        }
+        Ok(())
+    }.await;
+
+    if result.is_err() {
+        if let Err(e) = send(client, target, Method::DELETE, &upload_url, None).await {
+            warn!("Aborting the upload of {} failed: {:#}", path.display(), e);
+        }
+    }
+    result
+}
+
+/// The offset and length of the parts of a file of `size` bytes
+fn parts(size: u64, part_size: u64) -> Vec<(u64, u64)> {
+    let part_size = std::cmp::max(part_size, (size + MAX_PARTS - 1) / MAX_PARTS);
+    (0..size)
+        .step_by(part_size as usize)
+        .map(|offset| (offset, std::cmp::min(part_size, size - offset)))
+        .collect()
+}
+
+/// A body that streams `len` bytes of the file at `path`, starting at `offset`
+async fn file_part(path: &Path, offset: u64, len: u64) -> Result<(reqwest::Body, u64)> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| anyhow!("Opening {}", path.display()))?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let stream = crate::util::file_stream(file.take(len));
+    Ok((reqwest::Body::wrap_stream(stream), len))
+}
+
+/// Send a signed request with an optional body and its length, errors if it is not successful
+async fn send(client: &reqwest::Client, target: &S3Target, method: Method, url: &url::Url, body: Option<(reqwest::Body, u64)>) -> Result<reqwest::Response> {
+    let mut request = client.request(method.clone(), url.clone());
+    for (name, value) in signed_headers(target, method.as_str(), url, Utc::now()) {
+        request = request.header(name, value);
+    }
+    if let Some((body, len)) = body {
+        request = request.header(reqwest::header::CONTENT_LENGTH, len).body(body);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("{} {} failed ({}): {}", method, url, status, text.trim()))
+    }
+    Ok(response)
+}
+
+/// The text of the first `element` in an XML response
+fn xml_value<'a>(xml: &'a str, element: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{element}>"))? + element.len() + 2;
+    let len = xml[start..].find(&format!("</{element}>"))?;
+    Some(&xml[start..start + len])
+}
+
+/// The request body that completes a multipart upload with the ETags of the parts
+fn complete_body(etags: &[String]) -> String {
+    let parts = etags.iter()
+        .enumerate()
+        .map(|(i, etag)| {
+            let etag = etag.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;");
+            format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i + 1, etag)
+        })
+        .collect::<String>();
+    format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>")
+}
+
+/// The key of the object for a file of a release store
+fn object_key(prefix: &str, store_name: &str, file: &Path) -> String {
+    let prefix = prefix.trim_matches('/');
+    let file = file.display().to_string();
+    if prefix.is_empty() {
+        format!("{store_name}/{file}")
+    } else {
+        format!("{prefix}/{store_name}/{file}")
+    }
+}
+
+/// The path-style URL of an object
+fn object_url(target: &S3Target, key: &str) -> Result<url::Url> {
+    let path = std::iter::once(target.bucket().as_str())
+        .chain(key.split('/'))
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let endpoint = target.endpoint().as_str().trim_end_matches('/');
+    url::Url::parse(&format!("{endpoint}/{path}")).map_err(anyhow::Error::from)
+}
+
+/// Percent-encode everything but the unreserved characters, as required for the signature
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The query of a request as it is signed: encoded and sorted by parameter name
+fn canonical_query(url: &url::Url) -> String {
+    let mut params = url.query_pairs()
+        .map(|(name, value)| format!("{}={}", uri_encode(&name), uri_encode(&value)))
+        .collect::<Vec<_>>();
+    params.sort();
+    params.join("&")
+}
+
+/// The headers that authenticate a request with AWS signature version 4
+fn signed_headers(target: &S3Target, method: &str, url: &url::Url, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::new(),
+    };
+
+    let canonical_request = format!(
+        "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{UNSIGNED_PAYLOAD}\nx-amz-date:{amz_date}\n\n{SIGNED_HEADERS}\n{UNSIGNED_PAYLOAD}",
+        path = url.path(),
+        query = canonical_query(url),
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, target.region());
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{:x}", amz_date, scope, Sha256::digest(canonical_request.as_bytes()));
+    let signature = hex(&hmac_sha256(
+        &signing_key(target.secret_key(), &date, target.region(), "s3"),
+        string_to_sign.as_bytes(),
+    ));
+
+    vec![
+        ("x-amz-date", amz_date),
+        ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+        (
+            "authorization",
+            format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", target.access_key(), scope, SIGNED_HEADERS, signature),
+        ),
+    ]
+}
+
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret_key}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// HMAC (RFC 2104) with SHA-256
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let ipad = block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>();
+    let opad = block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>();
+    let inner = Sha256::new().chain_update(&ipad).chain_update(data).finalize();
+    Sha256::new().chain_update(&opad).chain_update(inner).finalize().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test cases 1, 2, 3, 6 and 7
+        assert_eq!(
+            hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(&[0xaa; 20], &[0xdd; 50])),
+            "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"
+        );
+
+        // keys longer than the block size are hashed first
+        assert_eq!(
+            hex(&hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than block-size data. \
+                The key needs to be hashed before being used by the HMAC algorithm."
+            )),
+            "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2"
+        );
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // From the AWS documentation of signature version 4
+        assert_eq!(
+            hex(&signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam")),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    fn target(endpoint: &str) -> S3Target {
+        toml::from_str(&indoc::formatdoc!(r#"
+            endpoint = "{endpoint}"
+            bucket = "releases"
+            prefix = "/butido/"
+            access_key = "key"
+            secret_key = "secret"
+        "#)).unwrap()
+    }
+
+    #[test]
+    fn test_object_url() {
+        let target = target("https://s3.example.com/");
+
+        let key = object_key(target.prefix(), "default", Path::new("x86_64/libstdc++-1.0.rpm"));
+        assert_eq!(key, "butido/default/x86_64/libstdc++-1.0.rpm");
+        assert_eq!(
+            object_url(&target, &key).unwrap().as_str(),
+            "https://s3.example.com/releases/butido/default/x86_64/libstdc%2B%2B-1.0.rpm"
+        );
+    }
+
+    #[test]
+    fn test_canonical_query() {
+        let url = url::Url::parse("https://s3.example.com/releases/foo?uploadId=a%2Fb+c&partNumber=2").unwrap();
+        assert_eq!(canonical_query(&url), "partNumber=2&uploadId=a%2Fb%20c");
+
+        let url = url::Url::parse("https://s3.example.com/releases/foo?uploads").unwrap();
+        assert_eq!(canonical_query(&url), "uploads=");
+
+        let url = url::Url::parse("https://s3.example.com/releases/foo").unwrap();
+        assert_eq!(canonical_query(&url), "");
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(parts(10, 4), vec![(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(parts(8, 4), vec![(0, 4), (4, 4)]);
+        assert_eq!(parts(0, 4), vec![]);
+
+        // the parts are made larger if there would be too many
+        let size = 5 * 1024 * PART_SIZE;
+        let parts = parts(size, PART_SIZE);
+        assert!(parts.len() as u64 <= MAX_PARTS);
+        assert_eq!(parts.iter().map(|(_, len)| len).sum::<u64>(), size);
+    }
+
+    #[test]
+    fn test_xml() {
+        let response = "<?xml version=\"1.0\"?><InitiateMultipartUploadResult><Bucket>releases</Bucket>\
+            <UploadId>abc-123</UploadId></InitiateMultipartUploadResult>";
+        assert_eq!(xml_value(response, "UploadId"), Some("abc-123"));
+        assert_eq!(xml_value(response, "Key"), None);
+
+        assert_eq!(
+            complete_body(&[String::from("\"a1\""), String::from("\"b2\"")]),
+            "<CompleteMultipartUpload>\
+            <Part><PartNumber>1</PartNumber><ETag>&quot;a1&quot;</ETag></Part>\
+            <Part><PartNumber>2</PartNumber><ETag>&quot;b2&quot;</ETag></Part>\
+            </CompleteMultipartUpload>"
+        );
+    }
+
+    /// A request to the fake storage: the method, the query and the body
+    type FakeRequest = (String, String, Vec<u8>);
+
+    /// Serve a fake S3 storage that accepts multipart uploads on a free local port
+    ///
+    /// Uploading the part `fail_part` fails.
+    fn fake_storage(requests: std::sync::Arc<std::sync::Mutex<Vec<FakeRequest>>>, fail_part: Option<&'static str>) -> String {
+        use hyper::service::make_service_fn;
+        use hyper::service::service_fn;
+
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let server = hyper::Server::bind(&address).serve(make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(service_fn(move |request: hyper::Request<hyper::Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let method = request.method().to_string();
+                        let query = request.uri().query().unwrap_or_default().to_string();
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap().to_vec();
+                        requests.lock().unwrap().push((method.clone(), query.clone(), body.clone()));
+
+                        let response = hyper::Response::builder();
+                        let response = match method.as_str() {
+                            "POST" if query == "uploads" => response
+                                .body(hyper::Body::from("<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>")),
+                            "PUT" if fail_part.map(|part| query.starts_with(part)).unwrap_or(false) => response
+                                .status(500)
+                                .body(hyper::Body::from("<Error><Code>InternalError</Code></Error>")),
+                            "PUT" => response
+                                .header("ETag", format!("\"{}\"", String::from_utf8_lossy(&body)))
+                                .body(hyper::Body::empty()),
+                            _ => response.body(hyper::Body::empty()),
+                        };
+                        Ok::<_, std::convert::Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        }));
+        tokio::spawn(server);
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn test_upload_multipart() {
+        let dir = std::env::temp_dir().join(format!("butido-test-s3-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("foo-1.0.tar");
+        std::fs::write(&path, "aaaabbbbcc").unwrap();
+
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let result = tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let target = target(&fake_storage(requests.clone(), None));
+            let url = object_url(&target, "butido/default/foo-1.0.tar").unwrap();
+            upload_multipart(&reqwest::Client::new(), &target, &path, &url, 10, 4).await
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let requests = requests.iter()
+            .map(|(method, query, body)| (method.as_str(), query.as_str(), String::from_utf8_lossy(body).to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(requests, vec![
+            ("POST", "uploads", String::new()),
+            ("PUT", "partNumber=1&uploadId=u1", String::from("aaaa")),
+            ("PUT", "partNumber=2&uploadId=u1", String::from("bbbb")),
+            ("PUT", "partNumber=3&uploadId=u1", String::from("cc")),
+            ("POST", "uploadId=u1", complete_body(&[
+                String::from("\"aaaa\""),
+                String::from("\"bbbb\""),
+                String::from("\"cc\""),
+            ])),
+        ]);
+    }
+
+    #[test]
+    fn test_upload_multipart_aborts() {
+        let dir = std::env::temp_dir().join(format!("butido-test-s3-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("foo-1.0.tar");
+        std::fs::write(&path, "aaaabbbbcc").unwrap();
+
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let result = tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let target = target(&fake_storage(requests.clone(), Some("partNumber=2")));
+            let url = object_url(&target, "butido/default/foo-1.0.tar").unwrap();
+            upload_multipart(&reqwest::Client::new(), &target, &path, &url, 10, 4).await
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+
+        let requests = requests.lock().unwrap();
+        let requests = requests.iter().map(|(method, query, _)| (method.as_str(), query.as_str())).collect::<Vec<_>>();
+        assert_eq!(requests, vec![
+            ("POST", "uploads"),
+            ("PUT", "partNumber=1&uploadId=u1"),
+            ("PUT", "partNumber=2&uploadId=u1"),
+            ("DELETE", "uploadId=u1"),
+        ]);
+    }
+}
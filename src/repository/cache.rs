@@ -0,0 +1,195 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A persistent cache of loaded package definitions
+//!
+//! Loading a package (merging its layers, rendering the templates) is the expensive part of
+//! loading a repository. The cache stores the rendered definition of each package, keyed by a
+//! hash of everything that goes into loading it (the paths and contents of all its layers, the
+//! package variables and the build of butido), so that only changed packages are loaded again.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::trace;
+
+/// The version of the cache, caches written by other builds of butido are ignored
+const CACHE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "-", env!("VERGEN_GIT_SHA"));
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: String,
+    entries: HashMap<String, serde_json::Value>,
+}
+
+/// The cache of the loaded package definitions of a repository
+pub struct ParseCache {
+    path: PathBuf,
+
+    /// The entries read from the cache file
+    entries: HashMap<String, serde_json::Value>,
+
+    /// The entries of the packages that were loaded, these are written back to the cache file
+    used: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+impl ParseCache {
+    /// Load the cache of the repository at `repo_path` from the XDG cache directory
+    pub fn for_repository(repo_path: &Path) -> Result<Self> {
+        use sha2::Digest;
+
+        let repo_path = repo_path.canonicalize()
+            .with_context(|| anyhow!("Resolving {}", repo_path.display()))?;
+        let hash = format!("{:x}", sha2::Sha256::digest(repo_path.display().to_string().as_bytes()));
+        let path = xdg::BaseDirectories::with_prefix("butido")?
+            .place_cache_file(format!("repository-{}.json", &hash[..16]))
+            .context("Creating the cache directory")?;
+
+        Ok(Self::load(path))
+    }
+
+    /// Load the cache from the file at `path`
+    ///
+    /// A missing or unreadable cache file results in an empty cache.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| serde_json::from_slice::<CacheFile>(&content).map_err(anyhow::Error::from))
+            .map(|file| if file.version == CACHE_VERSION {
+                file.entries
+            } else {
+                debug!("Ignoring cache {} of version {}", path.display(), file.version);
+                HashMap::new()
+            })
+            .unwrap_or_else(|e| {
+                debug!("Not using cache {}: {}", path.display(), e);
+                HashMap::new()
+            });
+
+        debug!("Loaded {} entries from cache {}", entries.len(), path.display());
+        ParseCache { path, entries, used: Mutex::new(HashMap::new()) }
+    }
+
+    /// Compute the cache key of a package
+    ///
+    /// `prefix` is the prefix of the package paths, `layers` are the paths and contents of the
+    /// package definition files of the package.
+    pub fn key(prefix: &Path, variables: &HashMap<String, String>, layers: &[(PathBuf, &String)]) -> String {
+        use sha2::Digest;
+
+        let mut m = sha2::Sha256::new();
+        let mut update = |s: &str| {
+            m.update(s.len().to_le_bytes());
+            m.update(s.as_bytes());
+        };
+
+        update(CACHE_VERSION);
+        update(&prefix.display().to_string());
+
+        let mut variables = variables.iter().collect::<Vec<_>>();
+        variables.sort();
+        for (key, value) in variables {
+            update(key);
+            update(value);
+        }
+
+        for (path, content) in layers {
+            update(&path.display().to_string());
+            update(content);
+        }
+
+        format!("{:x}", m.finalize())
+    }
+
+    /// Get the cached definition for `key`
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let value = self.entries.get(key)?.clone();
+        trace!("Cache hit: {}", key);
+        self.used.lock().unwrap().insert(key.to_string(), value.clone());
+        Some(value)
+    }
+
+    /// Insert the definition for `key`
+    pub fn insert(&self, key: String, value: serde_json::Value) {
+        trace!("Caching: {}", key);
+        self.used.lock().unwrap().insert(key, value);
+    }
+
+    /// Write the entries of the packages that were loaded back to the cache file
+    ///
+    /// Entries of packages that were not loaded (because they changed or were removed) are
+    /// dropped. The file is only written if the entries changed.
+    pub fn save(self) -> Result<()> {
+        let used = self.used.into_inner().unwrap();
+        if used.len() == self.entries.len() && used.keys().all(|key| self.entries.contains_key(key)) {
+            trace!("Cache {} is up to date", self.path.display());
+            return Ok(())
+        }
+
+        let file = CacheFile { version: String::from(CACHE_VERSION), entries: used };
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&file)?)
+            .with_context(|| anyhow!("Writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| anyhow!("Moving {} to {}", tmp.display(), self.path.display()))?;
+
+        debug!("Wrote {} entries to cache {}", file.entries.len(), self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let content = String::from("name = \"a\"");
+        let other_content = String::from("name = \"b\"");
+        let vars = HashMap::from([(String::from("x"), String::from("1"))]);
+        let layers = [(PathBuf::from("a/pkg.toml"), &content)];
+
+        let key = ParseCache::key(Path::new(""), &vars, &layers);
+        assert_eq!(key, ParseCache::key(Path::new(""), &vars, &layers));
+        assert_ne!(key, ParseCache::key(Path::new("../other"), &vars, &layers));
+        assert_ne!(key, ParseCache::key(Path::new(""), &HashMap::new(), &layers));
+        assert_ne!(key, ParseCache::key(Path::new(""), &vars, &[(PathBuf::from("a/pkg.toml"), &other_content)]));
+        assert_ne!(key, ParseCache::key(Path::new(""), &vars, &[(PathBuf::from("b/pkg.toml"), &content)]));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("butido-test-cache-{}.json", std::process::id()));
+
+        let cache = ParseCache::load(path.clone());
+        assert!(cache.get("a").is_none());
+        cache.insert(String::from("a"), serde_json::json!({ "name": "a" }));
+        cache.save().unwrap();
+
+        let cache = ParseCache::load(path.clone());
+        assert_eq!(cache.get("a"), Some(serde_json::json!({ "name": "a" })));
+        assert!(cache.get("b").is_none());
+        cache.save().unwrap();
+
+        // Entries that are not used are dropped
+        let cache = ParseCache::load(path.clone());
+        cache.save().unwrap();
+        assert!(ParseCache::load(path.clone()).get("a").is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}
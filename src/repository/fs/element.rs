@@ -10,14 +10,16 @@
 
 use std::collections::HashMap;
 
+use crate::repository::fs::path::PackageFileFormat;
 use crate::repository::fs::path::PathComponent;
 
 /// One element in the tree inside FileSystemRepresentation
 ///
-/// This is either a File, or a Directory that contains more (Files or Directories).
+/// This is either a File (with its format and content), or a Directory that contains more (Files or
+/// Directories).
 #[derive(Debug)]
 pub enum Element {
-    File(String),
+    File(PackageFileFormat, String),
     Dir(HashMap<PathComponent, Element>)
 }
 
@@ -25,7 +27,7 @@ impl Element {
     /// Helper fn to get the directory contents of the element, if the element is an Element::Dir
     pub fn get_map_mut(&mut self) -> Option<&mut HashMap<PathComponent, Element>> {
         match self {
-            Element::File(_) => None,
+            Element::File(..) => None,
             Element::Dir(ref mut hm) => Some(hm),
         }
     }
@@ -13,4 +13,5 @@ pub use representation::FileSystemRepresentation;
 
 mod element;
 mod path;
+pub use path::PackageFileFormat;
 
@@ -16,8 +16,9 @@ use anyhow::Result;
 
 /// Helper type for filtering for pathes we need or dont need
 ///
-/// We either have a directory, which has a name, or we have a pkg.toml file, which is of interest.
-/// All other files can be ignored and thus are not represented by this type.
+/// We either have a directory, which has a name, or we have a package definition file (see
+/// [`PackageFileFormat`]), which is of interest. All other files can be ignored and thus are not
+/// represented by this type.
 ///
 /// The PathComponent::DirName(_) represents a _part_ of a Path. Something like
 ///
@@ -28,7 +29,7 @@ use anyhow::Result;
 ///
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PathComponent {
-    PkgToml,
+    PkgFile,
     DirName(String),
 }
 
@@ -43,8 +44,8 @@ impl TryFrom<&std::path::Component<'_>> for PathComponent {
             Component::ParentDir => anyhow::bail!("Unexpected path component: ParentDir"),
             Component::Normal(filename) => {
                 let filename = filename.to_str().ok_or_else(|| anyhow!("UTF8-error"))?;
-                if filename == "pkg.toml" {
-                    Ok(PathComponent::PkgToml)
+                if PackageFileFormat::from_file_name(filename).is_some() {
+                    Ok(PathComponent::PkgFile)
                 } else {
                     Ok(PathComponent::DirName(filename.to_string()))
                 }
@@ -54,18 +55,65 @@ impl TryFrom<&std::path::Component<'_>> for PathComponent {
 }
 
 impl PathComponent {
-    /// Helper fn whether this PathComponent is a PathComponent::PkgToml
-    pub fn is_pkg_toml(&self) -> bool {
-        std::matches!(self, PathComponent::PkgToml)
+    /// Helper fn whether this PathComponent is a PathComponent::PkgFile
+    pub fn is_pkg_file(&self) -> bool {
+        std::matches!(self, PathComponent::PkgFile)
     }
 
     /// Helper fn to get the directory name of this PathComponent if it is a PathComponent::DirName
     /// or None if it is not.
     pub fn dir_name(&self) -> Option<&str> {
         match self {
-            PathComponent::PkgToml => None,
+            PathComponent::PkgFile => None,
             PathComponent::DirName(dn) => Some(dn)
         }
     }
 }
 
+
+/// The formats a package definition file can be written in
+///
+/// A directory contains at most one package definition file, either a `pkg.toml`, a `pkg.yaml` or
+/// a `pkg.json` file. All formats describe the same package structure and can be mixed in one
+/// hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageFileFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl PackageFileFormat {
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        match name {
+            "pkg.toml" => Some(PackageFileFormat::Toml),
+            "pkg.yaml" => Some(PackageFileFormat::Yaml),
+            "pkg.json" => Some(PackageFileFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Get the format of the package definition file at `path`, if it is one
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(PackageFileFormat::from_file_name)
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            PackageFileFormat::Toml => "pkg.toml",
+            PackageFileFormat::Yaml => "pkg.yaml",
+            PackageFileFormat::Json => "pkg.json",
+        }
+    }
+
+    /// The format for loading a file of this format with the `config` crate
+    pub fn config_format(&self) -> config::FileFormat {
+        match self {
+            PackageFileFormat::Toml => config::FileFormat::Toml,
+            PackageFileFormat::Yaml => config::FileFormat::Yaml,
+            PackageFileFormat::Json => config::FileFormat::Json,
+        }
+    }
+}
@@ -26,12 +26,14 @@ use walkdir::WalkDir;
 use tracing::trace;
 
 use crate::repository::fs::element::Element;
+use crate::repository::fs::path::PackageFileFormat;
 use crate::repository::fs::path::PathComponent;
 
 /// A type representing the filesystem
 ///
-/// This type can be used to load pkg.toml files from the filesystem. As soon as this object is
-/// loaded, all filesystem access is done and postprocessing of the loaded data can happen
+/// This type can be used to load package definition files (pkg.toml, pkg.yaml or pkg.json) from the
+/// filesystem. As soon as this object is loaded, all filesystem access is done and postprocessing
+/// of the loaded data can happen
 #[derive(Debug, getset::Getters)]
 pub struct FileSystemRepresentation {
     #[getset(get = "pub")]
@@ -70,8 +72,8 @@ impl FileSystemRepresentation {
             .max_open(max_files_open)
             .same_file_system(true)
             .into_iter()
-            .filter_entry(|e| !is_hidden(e) && (is_pkgtoml(e) || is_dir(e)))
-            .filter_ok(is_pkgtoml)
+            .filter_entry(|e| !is_hidden(e) && (is_package_file(e) || is_dir(e)))
+            .filter_ok(is_package_file)
             .inspect(|el| trace!("Loading: {:?}", el))
             .map_err(Error::from)
             .and_then_ok(|de| {
@@ -82,9 +84,17 @@ impl FileSystemRepresentation {
                 // traverse the HashMap tree
                 for cmp in de_path.components() {
                     match PathComponent::try_from(&cmp)? {
-                        PathComponent::PkgToml => {
-                            curr_hm.entry(PathComponent::PkgToml)
-                                .or_insert(Element::File(load_file(de_path)?));
+                        PathComponent::PkgFile => {
+                            let format = PackageFileFormat::from_path(de_path)
+                                .ok_or_else(|| anyhow!("Not a package definition file: {}", de_path.display()))?;
+
+                            if let Some(Element::File(other, _)) = curr_hm.get(&PathComponent::PkgFile) {
+                                anyhow::bail!("{} conflicts with {} in the same directory, only one package definition file is allowed",
+                                    de_path.display(),
+                                    other.file_name());
+                            }
+
+                            curr_hm.insert(PathComponent::PkgFile, Element::File(format, load_file(&fsr.root.join(de_path))?));
                         },
                         dir @ PathComponent::DirName(_) => {
                             curr_hm.entry(dir.clone())
@@ -123,13 +133,13 @@ impl FileSystemRepresentation {
 
         // Helper to check whether a tree contains pkg.toml files, recursively
         fn toml_files_in_tree(hm: &HashMap<PathComponent, Element>) -> bool {
-            if let Some(Element::File(_)) = hm.get(&PathComponent::PkgToml) {
+            if let Some(Element::File(..)) = hm.get(&PathComponent::PkgFile) {
                 return true
             }
 
             for value in hm.values() {
                 match value {
-                    Element::File(_) => return true,
+                    Element::File(..) => return true,
                     Element::Dir(hm) => if toml_files_in_tree(hm) {
                         return true
                     },
@@ -142,7 +152,7 @@ impl FileSystemRepresentation {
             let elem = PathComponent::try_from(&elem)?;
 
             match curr_hm.get(&elem) {
-                Some(Element::File(_)) => {
+                Some(Element::File(..)) => {
                     // if I have a file now, and the current hashmap only holds either
                     // * No directory
                     // * or a directory where all subdirs do not contain a pkg.toml
@@ -175,14 +185,14 @@ impl FileSystemRepresentation {
         for elem in path.components() {
             let elem = PathComponent::try_from(&elem)?;
 
-            if !elem.is_pkg_toml() {
-                if let Some(Element::File(intermediate)) = curr_hm.get(&PathComponent::PkgToml) {
-                    res.push((curr_path.join("pkg.toml"), intermediate));
+            if !elem.is_pkg_file() {
+                if let Some(Element::File(format, intermediate)) = curr_hm.get(&PathComponent::PkgFile) {
+                    res.push((curr_path.join(format.file_name()), intermediate));
                 }
             }
 
             match curr_hm.get(&elem) {
-                Some(Element::File(cont)) => res.push((curr_path.join("pkg.toml"), cont)),
+                Some(Element::File(format, cont)) => res.push((curr_path.join(format.file_name()), cont)),
                 Some(Element::Dir(hm)) => {
                     curr_path = curr_path.join(elem.dir_name().unwrap()); // unwrap safe by above match
                     curr_hm = hm;
@@ -207,10 +217,10 @@ fn is_dir(entry: &DirEntry) -> bool {
     entry.file_type().is_dir()
 }
 
-/// Helper to check whether a DirEntry points to a pkg.toml file
-fn is_pkgtoml(entry: &DirEntry) -> bool {
-    trace!("Check {:?} == 'pkg.toml'", entry);
-    entry.file_name().to_str().map(|s| s == "pkg.toml").unwrap_or(false)
+/// Helper to check whether a DirEntry points to a package definition file
+fn is_package_file(entry: &DirEntry) -> bool {
+    trace!("Check {:?} is a package definition file", entry);
+    PackageFileFormat::from_path(entry.path()).is_some()
 }
 
 /// Helper fn to load a Path into memory as String
@@ -230,7 +240,11 @@ mod tests {
     }
 
     fn pkgtoml(content: &str) -> (PathComponent, Element) {
-        (PathComponent::PkgToml, Element::File(content.to_string()))
+        (PathComponent::PkgFile, Element::File(PackageFileFormat::Toml, content.to_string()))
+    }
+
+    fn pkgjson(content: &str) -> (PathComponent, Element) {
+        (PathComponent::PkgFile, Element::File(PackageFileFormat::Json, content.to_string()))
     }
 
     fn pb(s: &str) -> PathBuf {
@@ -429,4 +443,40 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_hierarchy_with_mixed_formats() {
+        let fsr = FileSystemRepresentation {
+            root: PathBuf::from("/"),
+
+            // Representing
+            //  /
+            //  /foo
+            //  /foo/pkg.toml
+            //  /foo/bar
+            //  /foo/bar/pkg.json
+            elements: vec![
+                dir("foo", vec![
+                    pkgtoml("content1"),
+                    dir("bar", vec![
+                        pkgjson("content2"),
+                    ])
+                ])
+            ].into_iter().collect(),
+
+            files: vec![
+                PathBuf::from("foo/pkg.toml"),
+                PathBuf::from("foo/bar/pkg.json")
+            ],
+        };
+
+        let path = "foo/pkg.toml".as_ref();
+        assert!(!fsr.is_leaf_file(path).unwrap());
+
+        let path = "foo/bar/pkg.json".as_ref();
+        assert!(fsr.is_leaf_file(path).unwrap());
+        assert_eq!(fsr.get_files_for(path).unwrap(), vec![
+            (pb("foo/pkg.toml"),     &s("content1")),
+            (pb("foo/bar/pkg.json"), &s("content2")),
+        ]);
+    }
 }
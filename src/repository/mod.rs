@@ -12,5 +12,9 @@
 mod repository;
 pub use repository::*;
 
+mod cache;
+pub use cache::ParseCache;
+
 mod fs;
+pub use fs::PackageFileFormat;
 
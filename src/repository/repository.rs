@@ -9,6 +9,7 @@
 //
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -16,56 +17,189 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use config::Config;
+use tracing::debug;
 use tracing::trace;
+use tracing::warn;
 use resiter::AndThen;
 use resiter::FilterMap;
-use resiter::Map;
 
 use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
+use crate::repository::ParseCache;
 
 /// A repository represents a collection of packages
+#[derive(Clone)]
 pub struct Repository {
     inner: BTreeMap<(PackageName, PackageVersion), Package>,
+
+    /// The preferred provider for a virtual package, by name of the virtual package
+    preferred_providers: HashMap<PackageName, PackageName>,
 }
 
-#[cfg(test)]
 impl From<BTreeMap<(PackageName, PackageVersion), Package>> for Repository {
     fn from(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+        Repository::new(inner)
     }
 }
 
 impl Repository {
     fn new(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+        Repository { inner, preferred_providers: HashMap::new() }
+    }
+
+    /// Load the repository at `path`
+    ///
+    /// The string values of the package definitions (except for the phases, which are rendered
+    /// when the packaging script is built) are handlebars templates, `name`, `version` and the
+    /// `variables` (as `vars`) are available in them.
+    ///
+    /// If a `cache` is passed, unchanged packages are taken from the cache and the loaded packages
+    /// are added to it. The paths of the packages are relative to `repo_path` if `path` is inside
+    /// of it (see [`Repository::load_packages`]).
+    pub fn load(repo_path: &Path, path: &Path, variables: &HashMap<String, String>, cache: Option<&ParseCache>, progress: &indicatif::ProgressBar) -> Result<Self> {
+        Self::load_packages(repo_path, path, variables, cache, progress)?
+            .into_iter()
+            .map(|(_, pkg)| pkg.map(|pkg| ((pkg.name().clone(), pkg.version().clone()), pkg)))
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(Repository::new)
+    }
+
+    /// Load the repositories at `paths`, in this order
+    ///
+    /// Packages in later repositories override packages with the same name and version in
+    /// earlier repositories.
+    pub fn load_overlays(repo_path: &Path, paths: &[PathBuf], variables: &HashMap<String, String>, cache: Option<&ParseCache>, progress: &indicatif::ProgressBar) -> Result<Self> {
+        paths.iter()
+            .try_fold(BTreeMap::new(), |mut packages, path| {
+                let repo = Self::load(repo_path, path, variables, cache, progress)
+                    .with_context(|| anyhow!("Loading the repository at {}", path.display()))?;
+
+                for (key, package) in repo.inner {
+                    if let Some(overridden) = packages.insert(key, package) {
+                        debug!("{} {} from {} is overridden by the repository at {}",
+                            overridden.name(),
+                            overridden.version(),
+                            overridden.path().display(),
+                            path.display());
+                    }
+                }
+
+                Ok(packages)
+            })
+            .map(Repository::new)
     }
 
-    pub fn load(path: &Path, progress: &indicatif::ProgressBar) -> Result<Self> {
+    /// Get the roots of the package repositories, in the order they are loaded
+    ///
+    /// `repositories` are the configured repositories (relative to `repo_path`), if empty, only
+    /// the repository at `repo_path` is used. Fails if one of the repositories is not a directory.
+    pub fn roots(repo_path: &Path, repositories: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        if repositories.is_empty() {
+            return Ok(vec![repo_path.to_path_buf()])
+        }
+
+        repositories.iter()
+            .map(|r| {
+                let root = repo_path.join(r);
+                if root.is_dir() {
+                    Ok(root)
+                } else {
+                    Err(anyhow!("Not a directory: repositories = {} ({})", r.display(), root.display()))
+                }
+            })
+            .collect()
+    }
+
+    /// Load all packages of the repository, without failing on the first package that cannot be
+    /// loaded
+    ///
+    /// Returns the path of the leaf `pkg.toml` file and the loading result for each package.
+    pub fn load_packages(repo_path: &Path, path: &Path, variables: &HashMap<String, String>, cache: Option<&ParseCache>, progress: &indicatif::ProgressBar)
+        -> Result<Vec<(PathBuf, Result<Package>)>>
+    {
         use crate::repository::fs::FileSystemRepresentation;
-        use config::Config;
+        use crate::repository::fs::PackageFileFormat;
         use rayon::iter::IntoParallelRefIterator;
         use rayon::iter::ParallelIterator;
 
         trace!("Loading files from filesystem");
         let fsr = FileSystemRepresentation::load(path.to_path_buf())?;
 
-        fn get_patches(config: &Config) -> Result<Vec<PathBuf>> {
+        // The paths of the files are relative to `path`. The packages of repositories inside of
+        // `repo_path` get paths relative to `repo_path`, the paths of other repositories are
+        // prefixed with their root
+        let prefix = package_path_prefix(repo_path, path);
+
+        /// A patch from the "patches" array, either declared as a plain path or as a table with
+        /// a "path" key and additional settings
+        struct PatchEntry {
+            path: PathBuf,
+            settings: Option<HashMap<String, config::Value>>,
+        }
+
+        impl PatchEntry {
+            fn into_value(self) -> config::Value {
+                let path = config::Value::from(self.path.display().to_string());
+                match self.settings {
+                    None => path,
+                    Some(mut settings) => {
+                        settings.insert(String::from("path"), path);
+                        config::Value::from(settings)
+                    },
+                }
+            }
+        }
+
+        fn get_patches(config: &Config) -> Result<Vec<PatchEntry>> {
             match config.get_array("patches") {
                 Ok(v)  => v.into_iter()
-                    .map(config::Value::into_str)
-                    .map_err(Error::from)
-                    .map_err(|e| e.context("patches must be strings"))
-                    .map_err(Error::from)
-                    .map_ok(PathBuf::from)
+                    .map(|value| match value.clone().into_table() {
+                        Ok(mut settings) => {
+                            let path = settings.remove("path")
+                                .ok_or_else(|| anyhow!("patch tables must have a 'path'"))?
+                                .into_str()
+                                .map_err(Error::from)
+                                .context("patch paths must be strings")?;
+
+                            Ok(PatchEntry { path: PathBuf::from(path), settings: Some(settings) })
+                        },
+                        Err(_) => value.into_str()
+                            .map(|path| PatchEntry { path: PathBuf::from(path), settings: None })
+                            .map_err(Error::from)
+                            .map_err(|e| e.context("patches must be strings or tables")),
+                    })
                     .collect(),
                 Err(config::ConfigError::NotFound(_)) => Ok(Vec::with_capacity(0)),
                 Err(e) => Err(e).map_err(Error::from),
             }
         }
 
+        fn get_schema_version(content: &str, format: PackageFileFormat) -> Result<i64> {
+            use crate::package::schema::SCHEMA_VERSION_KEY;
+
+            let mut config = Config::default();
+            config.merge(config::File::from_str(content, format.config_format()))?;
+            match config.get_int(SCHEMA_VERSION_KEY) {
+                Ok(v) if v >= 1 => Ok(v),
+                Ok(v) => Err(anyhow!("{} must be a positive integer, got: {}", SCHEMA_VERSION_KEY, v)),
+                Err(config::ConfigError::NotFound(_)) => Ok(1),
+                Err(e) => Err(Error::from(e)),
+            }
+        }
+
+        /// Get a package from the cache, if it is cached and the cached definition is still valid
+        fn load_cached(cache: &ParseCache, key: &str) -> Option<Package> {
+            let pkg = serde_json::from_value::<Package>(cache.get(key)?)
+                .map_err(|e| debug!("Invalid cache entry {}: {}", key, e))
+                .ok()?;
+
+            // The patch files are checked when a package is loaded, so they are checked here, too
+            pkg.patches().iter().all(|patch| patch.path().exists()).then_some(pkg)
+        }
+
         fsr.files()
             .par_iter()
             .inspect(|path| trace!("Checking for leaf file: {}", path.display()))
@@ -80,68 +214,130 @@ impl Repository {
             .map(|path| {
                 progress.tick();
                 let path = path?;
-                fsr.get_files_for(path)?
-                    .iter()
-                    .inspect(|(path, _)| trace!("Loading layer at {}", path.display()))
-                    .fold(Ok(Config::default()) as Result<_>, |config, (path, content)| {
-                        let mut config = config?;
-                        let patches_before_merge = get_patches(&config)?;
-
-                        config.merge(config::File::from_str(content, config::FileFormat::Toml))
-                            .with_context(|| anyhow!("Loading contents of {}", path.display()))?;
-
-                        // get the patches that are in the `config` object after the merge
-                        let patches = get_patches(&config)?
-                            .into_iter()
-                            .map(|p| if let Some(current_dir) = path.parent() {
-                                Ok(current_dir.join(p))
-                            } else {
-                                Err(anyhow!("Path should point to path with parent, but doesn't: {}", path.display()))
+                let pkg = fsr.get_files_for(path)
+                    .and_then(|files| {
+                        let key = cache.map(|_| ParseCache::key(&prefix, variables, &files));
+                        if let Some(pkg) = cache.zip(key.as_deref()).and_then(|(cache, key)| load_cached(cache, key)) {
+                            return Ok(pkg)
+                        }
+
+                        files
+                            .iter()
+                            .inspect(|(path, _)| trace!("Loading layer at {}", path.display()))
+                            .fold(Ok(Config::default()) as Result<_>, |config, (path, content)| {
+                                let path = prefix.join(path);
+                                let path = path.as_path();
+                                let mut config = config?;
+                                let patches_before_merge = get_patches(&config)?;
+
+                                let format = PackageFileFormat::from_path(path)
+                                    .ok_or_else(|| anyhow!("Not a package definition file: {}", path.display()))?;
+
+                                let content = match format {
+                                    PackageFileFormat::Toml => {
+                                        let (content, schema_version) = crate::package::schema::upgrade(content)
+                                            .with_context(|| anyhow!("Checking the schema version of {}", path.display()))?;
+                                        if schema_version < crate::package::schema::CURRENT_SCHEMA_VERSION {
+                                            warn!("{} uses the outdated pkg.toml schema version {}, please migrate it with 'butido repo migrate'",
+                                                path.display(),
+                                                schema_version);
+                                        }
+                                        content
+                                    },
+                                    PackageFileFormat::Yaml | PackageFileFormat::Json => {
+                                        get_schema_version(content, format)
+                                            .and_then(crate::package::schema::check_schema_version)
+                                            .with_context(|| anyhow!("Checking the schema version of {}", path.display()))?;
+                                        std::borrow::Cow::Borrowed(content.as_str())
+                                    },
+                                };
+
+                                config.merge(config::File::from_str(&content, format.config_format()))
+                                    .with_context(|| anyhow!("Loading contents of {}", path.display()))?;
+
+                                // get the patches that are in the `config` object after the merge
+                                let patches = get_patches(&config)?
+                                    .into_iter()
+                                    .map(|p| if let Some(current_dir) = path.parent() {
+                                        Ok(PatchEntry { path: current_dir.join(p.path), settings: p.settings })
+                                    } else {
+                                        Err(anyhow!("Path should point to path with parent, but doesn't: {}", path.display()))
+                                    })
+                                    .inspect(|patch| trace!("Patch: {:?}", patch.as_ref().map(|p| &p.path)))
+
+                                    // if the patch file exists, use it (as config::Value).
+                                    //
+                                    // Otherwise we have an error here, because we're refering to a non-existing file.
+                                    .and_then_ok(|patch| if patch.path.exists() {
+                                        trace!("Path to patch exists: {}", patch.path.display());
+                                        Ok(Some(patch))
+                                    } else if patches_before_merge.iter().any(|pb| pb.path.file_name() == patch.path.file_name()) {
+                                        // We have a patch already in the array that is named equal to the patch
+                                        // we have in the fold iteration.
+                                        // It seems like this patch was already in the list and we re-found it
+                                        // because we loaded a "deeper" pkg.toml file.
+                                        Ok(None)
+                                    } else {
+                                        trace!("Path to patch does not exist: {}", patch.path.display());
+                                        Err(anyhow!("Patch does not exist: {}", patch.path.display()))
+                                    })
+                                    .filter_map_ok(|o| o)
+                                    .collect::<Result<Vec<_>>>()?;
+
+                                // If we found any patches, use them. Otherwise use the array from before the merge
+                                // (which already has the correct pathes from the previous recursion).
+                                let patches = if !patches.is_empty() {
+                                    patches
+                                } else {
+                                    patches_before_merge
+                                };
+
+                                trace!("Patches after postprocessing merge: {:?}", patches.iter().map(|p| &p.path).collect::<Vec<_>>());
+                                let patches = patches
+                                    .into_iter()
+                                    .map(PatchEntry::into_value)
+                                    .collect::<Vec<_>>();
+                                config.set_once("patches", config::Value::from(patches))?;
+                                Ok(config)
                             })
-                            .inspect(|patch| trace!("Patch: {:?}", patch))
-
-                            // if the patch file exists, use it (as config::Value).
-                            //
-                            // Otherwise we have an error here, because we're refering to a non-existing file.
-                            .and_then_ok(|patch| if patch.exists() {
-                                trace!("Path to patch exists: {}", patch.display());
-                                Ok(Some(patch))
-                            } else if patches_before_merge.iter().any(|pb| pb.file_name() == patch.file_name()) {
-                                // We have a patch already in the array that is named equal to the patch
-                                // we have in the fold iteration.
-                                // It seems like this patch was already in the list and we re-found it
-                                // because we loaded a "deeper" pkg.toml file.
-                                Ok(None)
-                            } else {
-                                trace!("Path to patch does not exist: {}", patch.display());
-                                Err(anyhow!("Patch does not exist: {}", patch.display()))
+                            .and_then(|mut c| {
+                                render_package_templates(&mut c, variables)
+                                    .with_context(|| anyhow!("Rendering templates in package configuration: {}", path.display()))?;
+
+                                if let Some((cache, key)) = cache.zip(key) {
+                                    match c.clone().try_into::<serde_json::Value>() {
+                                        Ok(value) => cache.insert(key, value),
+                                        Err(e) => debug!("Not caching {}: {}", path.display(), e),
+                                    }
+                                }
+
+                                c.try_into::<Package>().map_err(Error::from)
+                                    .with_context(|| anyhow!("Could not load package configuration: {}", path.display()))
                             })
-                            .filter_map_ok(|o| o)
-                            .collect::<Result<Vec<_>>>()?;
-
-                        // If we found any patches, use them. Otherwise use the array from before the merge
-                        // (which already has the correct pathes from the previous recursion).
-                        let patches = if !patches.is_empty() {
-                            patches
-                        } else {
-                            patches_before_merge
-                        };
-
-                        trace!("Patches after postprocessing merge: {:?}", patches);
-                        let patches = patches
-                            .into_iter()
-                            .map(|p| p.display().to_string())
-                            .map(config::Value::from)
-                            .collect::<Vec<_>>();
-                        config.set_once("patches", config::Value::from(patches))?;
-                        Ok(config)
                     })
-                    .and_then(|c| c.try_into::<Package>().map_err(Error::from)
-                        .with_context(|| anyhow!("Could not load package configuration: {}", path.display())))
-                    .map(|pkg| ((pkg.name().clone(), pkg.version().clone()), pkg))
+                    .and_then(|pkg| {
+                        if pkg.is_meta() && !pkg.sources().is_empty() {
+                            return Err(anyhow!("Meta package {} {} must not have sources: {}", pkg.name(), pkg.version(), path.display()))
+                        }
+                        Ok(pkg)
+                    })
+                    .map(|mut pkg| {
+                        pkg.set_path(prefix.join(path));
+                        pkg.set_vars(variables.clone());
+                        pkg
+                    });
+
+                Ok((prefix.join(path), pkg))
             })
-            .collect::<Result<BTreeMap<_, _>>>()
-            .map(Repository::new)
+            .collect()
+    }
+
+    /// Get the paths of all package definition files of the repository at `path`
+    pub fn package_files(path: &Path) -> Result<Vec<PathBuf>> {
+        use crate::repository::fs::FileSystemRepresentation;
+
+        FileSystemRepresentation::load(path.to_path_buf())
+            .map(|fsr| fsr.files().iter().map(|file| path.join(file)).collect())
     }
 
     pub fn find_by_name<'a>(&'a self, name: &PackageName) -> Vec<&'a Package> {
@@ -176,9 +372,150 @@ impl Repository {
             .collect()
     }
 
+    /// Find the packages that provide the virtual package `name` in a version matching `vc`
+    ///
+    /// If packages with different names provide the virtual package, only the preferred provider
+    /// is returned. It is an error if there is no preferred provider in this case.
+    pub fn find_providers<'a>(
+        &'a self,
+        name: &PackageName,
+        vc: &PackageVersionConstraint,
+    ) -> Result<Vec<&'a Package>> {
+        // not imported at module level, it would make the `resiter` methods ambiguous
+        use itertools::Itertools;
+
+        let providers = self.inner
+            .values()
+            .filter(|p| p.provided_version(name).map(|v| vc.matches(v)).unwrap_or(false))
+            .collect::<Vec<_>>();
+
+        if let Some(preferred) = self.preferred_providers.get(name) {
+            trace!("Preferred provider for {}: {}", name, preferred);
+            let preferred_providers = providers.iter()
+                .filter(|p| p.name() == preferred)
+                .copied()
+                .collect::<Vec<_>>();
+
+            if preferred_providers.is_empty() && !providers.is_empty() {
+                return Err(anyhow!("Preferred provider {} does not provide {} {}", preferred, name, vc))
+            }
+
+            return Ok(preferred_providers)
+        }
+
+        let provider_names = providers.iter().map(|p| p.name()).unique().collect::<Vec<_>>();
+        if provider_names.len() > 1 {
+            return Err(anyhow!(
+                "{} {} is provided by several packages: {}. Set the preferred provider in the configuration (preferred_providers)",
+                name,
+                vc,
+                provider_names.iter().join(", ")
+            ))
+        }
+
+        Ok(providers)
+    }
+
+    /// Set the preferred providers of virtual packages, by name of the virtual package
+    pub fn with_preferred_providers(mut self, preferred_providers: HashMap<PackageName, PackageName>) -> Repository {
+        self.preferred_providers = preferred_providers;
+        self
+    }
+
     pub fn packages(&self) -> impl Iterator<Item = &Package> {
         self.inner.values()
     }
+
+    /// Apply the flavor `name` to all packages that have a flavor with that name
+    ///
+    /// Packages that do not know the flavor are left untouched.
+    pub fn with_flavor(self, name: &str) -> Repository {
+        let inner = self.inner
+            .into_iter()
+            .map(|(key, package)| {
+                let package = package.with_flavor(name).unwrap_or(package);
+                (key, package)
+            })
+            .collect();
+
+        Repository {
+            inner,
+            preferred_providers: self.preferred_providers,
+        }
+    }
+
+    /// Enable the features `names` for all packages that have features with these names
+    ///
+    /// Features that a package does not have are ignored for that package.
+    pub fn with_features(self, names: &[String]) -> Repository {
+        let inner = self.inner
+            .into_iter()
+            .map(|(key, package)| (key, package.with_features(names)))
+            .collect();
+
+        Repository {
+            inner,
+            preferred_providers: self.preferred_providers,
+        }
+    }
+}
+
+/// The prefix of the paths of the packages in the repository at `path`
+///
+/// Empty for the repository at `repo_path`, the path relative to `repo_path` for repositories
+/// inside of it and `path` itself for all other repositories.
+fn package_path_prefix(repo_path: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(repo_path)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Render the handlebars templates in the string values of a (merged) package configuration
+///
+/// The phases are skipped, because they are rendered with the package itself when building the
+/// packaging script.
+fn render_package_templates(config: &mut Config, variables: &HashMap<String, String>) -> Result<()> {
+    fn collect_templates(value: &serde_json::Value, path: String, templates: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::String(s) if s.contains("{{") => templates.push((path, s.clone())),
+            serde_json::Value::Array(values) => values.iter()
+                .enumerate()
+                .for_each(|(i, v)| collect_templates(v, format!("{path}[{i}]"), templates)),
+            serde_json::Value::Object(map) => map.iter()
+                .filter(|(key, _)| *key != "phases")
+                .for_each(|(key, v)| {
+                    let path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+                    collect_templates(v, path, templates)
+                }),
+            _ => {},
+        }
+    }
+
+    let mut templates = Vec::new();
+    let tree = config.clone().try_into::<serde_json::Value>()?;
+    collect_templates(&tree, String::new(), &mut templates);
+    if templates.is_empty() {
+        return Ok(())
+    }
+
+    let data = serde_json::json!({
+        "name": config.get_str("name")?,
+        "version": config.get_str("version")?,
+        "vars": variables,
+    });
+
+    let mut hb = handlebars::Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+
+    for (path, template) in templates {
+        trace!("Rendering template at '{}': {}", path, template);
+        let rendered = hb.render_template(&template, &data)
+            .with_context(|| anyhow!("Rendering '{}'", path))?;
+        config.set(&path, rendered)?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -188,6 +525,65 @@ pub mod tests {
     use crate::package::tests::pname;
     use crate::package::tests::pversion;
 
+    #[test]
+    fn test_render_package_templates() {
+        let mut config = Config::default();
+        config.merge(config::File::from_str(r#"
+            name = "foo"
+            version = "1.2"
+            sources.src.url = "https://example.com/{{name}}-{{version}}.tar.gz"
+            environment.MIRROR = "{{vars.mirror}}"
+            phases.build.script = "{{phase \"build\"}}"
+        "#, config::FileFormat::Toml)).unwrap();
+
+        let mut vars = HashMap::new();
+        vars.insert(String::from("mirror"), String::from("https://mirror.example.com"));
+        render_package_templates(&mut config, &vars).unwrap();
+
+        assert_eq!(config.get_str("sources.src.url").unwrap(), "https://example.com/foo-1.2.tar.gz");
+        assert_eq!(config.get_str("environment.MIRROR").unwrap(), "https://mirror.example.com");
+        assert_eq!(config.get_str("phases.build.script").unwrap(), "{{phase \"build\"}}");
+    }
+
+    #[test]
+    fn test_render_package_templates_unknown_variable() {
+        let mut config = Config::default();
+        config.merge(config::File::from_str(r#"
+            name = "foo"
+            version = "1.2"
+            environment.MIRROR = "{{vars.mirror}}"
+        "#, config::FileFormat::Toml)).unwrap();
+
+        assert!(render_package_templates(&mut config, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_roots() {
+        let repo_path = std::env::temp_dir().join(format!("butido-test-roots-{}", uuid::Uuid::new_v4()));
+        let upstream = repo_path.join("upstream");
+        std::fs::create_dir_all(&upstream).unwrap();
+
+        let empty = Repository::roots(&repo_path, &[]).unwrap();
+        let relative = Repository::roots(&repo_path, &[PathBuf::from("upstream"), PathBuf::from(".")]).unwrap();
+        let absolute = Repository::roots(&repo_path, std::slice::from_ref(&upstream)).unwrap();
+        let missing = Repository::roots(&repo_path, &[PathBuf::from("missing")]);
+        std::fs::remove_dir_all(&repo_path).unwrap();
+
+        assert_eq!(empty, vec![repo_path.clone()]);
+        assert_eq!(relative, vec![upstream.clone(), repo_path.join(".")]);
+        assert_eq!(absolute, vec![upstream]);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_package_path_prefix() {
+        let repo_path = Path::new("/repo");
+        assert_eq!(package_path_prefix(repo_path, Path::new("/repo")), PathBuf::new());
+        assert_eq!(package_path_prefix(repo_path, Path::new("/repo/.")), PathBuf::new());
+        assert_eq!(package_path_prefix(repo_path, Path::new("/repo/upstream")), PathBuf::from("upstream"));
+        assert_eq!(package_path_prefix(repo_path, Path::new("/upstream")), PathBuf::from("/upstream"));
+    }
+
     #[test]
     fn test_finding_by_name() {
         let mut btree = BTreeMap::new();
@@ -3,6 +3,26 @@ table! {
         id -> Int4,
         path -> Varchar,
         job_id -> Int4,
+        checksum -> Nullable<Varchar>,
+        verified -> Nullable<Bool>,
+        size -> Nullable<Int8>,
+    }
+}
+
+table! {
+    build_queue (id) {
+        id -> Int4,
+        created -> Timestamptz,
+        package_name -> Varchar,
+        package_version -> Nullable<Varchar>,
+        args -> Array<Text>,
+        repo_hash -> Varchar,
+        state -> Varchar,
+        claimed_by -> Nullable<Varchar>,
+        heartbeat -> Nullable<Timestamptz>,
+        finished -> Nullable<Timestamptz>,
+        error -> Nullable<Text>,
+        manifest -> Nullable<Text>,
     }
 }
 
@@ -43,6 +63,28 @@ table! {
     }
 }
 
+table! {
+    job_markers (id) {
+        id -> Int4,
+        job_id -> Int4,
+        kind -> Varchar,
+        name -> Nullable<Varchar>,
+        value -> Text,
+    }
+}
+
+table! {
+    job_phases (id) {
+        id -> Int4,
+        job_id -> Int4,
+        name -> Varchar,
+        started -> Timestamptz,
+        finished -> Timestamptz,
+        first_line -> Nullable<Int4>,
+        line_count -> Nullable<Int4>,
+    }
+}
+
 table! {
     jobs (id) {
         id -> Int4,
@@ -54,6 +96,22 @@ table! {
         script_text -> Text,
         log_text -> Text,
         uuid -> Uuid,
+        started -> Nullable<Timestamptz>,
+        finished -> Nullable<Timestamptz>,
+        image_digest -> Nullable<Varchar>,
+        buildinfo -> Nullable<Text>,
+    }
+}
+
+table! {
+    known_failures (id) {
+        id -> Int4,
+        created -> Timestamptz,
+        job_id -> Nullable<Int4>,
+        package_name -> Nullable<Varchar>,
+        package_version -> Nullable<Varchar>,
+        image_name -> Nullable<Varchar>,
+        reason -> Text,
     }
 }
 
@@ -65,6 +123,20 @@ table! {
     }
 }
 
+table! {
+    release_proposals (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        release_store_name -> Varchar,
+        package_name -> Nullable<Varchar>,
+        package_version -> Nullable<Varchar>,
+        proposed_by -> Varchar,
+        proposed -> Timestamptz,
+        approved_by -> Nullable<Varchar>,
+        approved -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     release_stores (id) {
         id -> Int4,
@@ -72,12 +144,26 @@ table! {
     }
 }
 
+table! {
+    release_syncs (id) {
+        id -> Int4,
+        release_id -> Int4,
+        target -> Varchar,
+        synced -> Timestamptz,
+    }
+}
+
 table! {
     releases (id) {
         id -> Int4,
         artifact_id -> Int4,
         release_date -> Timestamptz,
         release_store_id -> Int4,
+        partial -> Bool,
+        rollback_of -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        signature_path -> Nullable<Varchar>,
+        signature_fingerprint -> Nullable<Varchar>,
     }
 }
 
@@ -97,16 +183,27 @@ table! {
         requested_image_id -> Int4,
         requested_package_id -> Int4,
         repo_hash_id -> Int4,
+        flavor -> Nullable<Varchar>,
+        manifest -> Nullable<Text>,
+        features -> Array<Text>,
+        butido_version -> Nullable<Varchar>,
+        butido_git_hash -> Nullable<Varchar>,
+        config_fingerprint -> Nullable<Varchar>,
     }
 }
 
 joinable!(artifacts -> jobs (job_id));
 joinable!(job_envs -> envvars (env_id));
 joinable!(job_envs -> jobs (job_id));
+joinable!(job_markers -> jobs (job_id));
+joinable!(job_phases -> jobs (job_id));
 joinable!(jobs -> endpoints (endpoint_id));
 joinable!(jobs -> images (image_id));
 joinable!(jobs -> packages (package_id));
 joinable!(jobs -> submits (submit_id));
+joinable!(known_failures -> jobs (job_id));
+joinable!(release_proposals -> submits (submit_id));
+joinable!(release_syncs -> releases (release_id));
 joinable!(releases -> artifacts (artifact_id));
 joinable!(releases -> release_stores (release_store_id));
 joinable!(submit_envs -> envvars (env_id));
@@ -117,14 +214,20 @@ joinable!(submits -> packages (requested_package_id));
 
 allow_tables_to_appear_in_same_query!(
     artifacts,
+    build_queue,
     endpoints,
     envvars,
     githashes,
     images,
     job_envs,
+    job_markers,
+    job_phases,
     jobs,
+    known_failures,
     packages,
+    release_proposals,
     release_stores,
+    release_syncs,
     releases,
     submit_envs,
     submits,
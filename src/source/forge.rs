@@ -0,0 +1,391 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Resolving release assets of software forges (GitHub, GitLab) to download URLs
+//!
+//! Sources can refer to release assets with the following URL schemes:
+//!
+//! * `github://<owner>/<repo>/<tag>/<asset glob>`
+//! * `gitlab://<host>/<project path>/<tag>/<asset glob>`
+//!
+//! The asset glob supports "*" and "?" wildcards and must match exactly one asset of the release.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::{debug, trace, warn};
+use url::Url;
+
+/// How often a request is retried if it hit an API rate limit
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+/// The maximum time to wait for a rate limit to reset
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// API tokens for the supported forges
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ForgeTokens<'a> {
+    pub github: Option<&'a str>,
+    pub gitlab: Option<&'a str>,
+}
+
+/// A release asset that was resolved to a concrete download URL
+#[derive(Clone, Debug)]
+pub struct ResolvedAsset {
+    pub url: Url,
+
+    /// The HTTP header (name, value) to authenticate the download with, if required
+    pub auth_header: Option<(&'static str, String)>,
+}
+
+/// The lock data of a resolved release asset
+///
+/// This is stored next to the cached source, so that the forge API does not have to be queried
+/// again as long as the expected hash of the source does not change.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ForgeLock {
+    pub url: Url,
+    pub hash: String,
+}
+
+impl ForgeLock {
+    pub async fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None)
+        }
+
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| anyhow!("Reading {}", path.display()))?;
+
+        serde_json::from_str(&content)
+            .map(Some)
+            .with_context(|| anyhow!("Parsing {}", path.display()))
+    }
+
+    pub async fn store(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| anyhow!("Writing {}", path.display()))
+    }
+}
+
+/// Check whether the URL refers to a forge release asset
+pub fn is_forge_url(url: &Url) -> bool {
+    matches!(url.scheme(), "github" | "gitlab")
+}
+
+/// The release of a forge URL and the asset in it
+#[derive(Debug, Eq, PartialEq)]
+struct ForgeRelease {
+    /// The API URL of the release
+    api_url: String,
+
+    /// The glob for the name of the asset
+    asset: String,
+}
+
+/// Parse a `github://` or `gitlab://` URL
+fn parse_forge_url(url: &Url) -> Result<ForgeRelease> {
+    let host = url.host_str().ok_or_else(|| anyhow!("No host in URL: {}", url))?;
+    let segments = url.path_segments()
+        .map(|segs| segs.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let (project, tag, asset) = match segments.as_slice() {
+        [project @ .., tag, asset] if !project.is_empty() => (project.join("/"), *tag, *asset),
+        _ => return Err(anyhow!("Expected '<project>/<tag>/<asset>' in URL: {}", url)),
+    };
+
+    let api_url = match url.scheme() {
+        "github" => format!("https://api.github.com/repos/{host}/{project}/releases/tags/{tag}"),
+        "gitlab" => format!(
+            "https://{host}/api/v4/projects/{project}/releases/{tag}",
+            project = url::form_urlencoded::byte_serialize(project.as_bytes()).collect::<String>(),
+            tag = url::form_urlencoded::byte_serialize(tag.as_bytes()).collect::<String>(),
+        ),
+        other => return Err(anyhow!("Not a forge URL scheme: {}", other)),
+    };
+
+    Ok(ForgeRelease { api_url, asset: asset.to_string() })
+}
+
+/// Resolve a `github://` or `gitlab://` URL to the download URL of the release asset
+pub async fn resolve(client: &reqwest::Client, url: &Url, tokens: ForgeTokens<'_>) -> Result<ResolvedAsset> {
+    let ForgeRelease { api_url, asset } = parse_forge_url(url)?;
+    let asset_re = crate::util::glob::glob_to_regex(&asset)
+        .with_context(|| anyhow!("Building regex for asset glob '{}'", asset))?;
+
+    match url.scheme() {
+        "github" => {
+            #[derive(Deserialize)]
+            struct Release {
+                assets: Vec<Asset>,
+            }
+
+            #[derive(Deserialize)]
+            struct Asset {
+                name: String,
+                browser_download_url: Url,
+            }
+
+            let auth = tokens.github.map(|t| ("Authorization", format!("Bearer {t}")));
+            let release: Release = get_json(client, &api_url, auth.as_ref()).await?;
+            let url = find_single_asset(release.assets.into_iter().map(|a| (a.name, a.browser_download_url)), &asset_re, url)?;
+
+            Ok(ResolvedAsset { url, auth_header: None })
+        },
+
+        "gitlab" => {
+            #[derive(Deserialize)]
+            struct Release {
+                assets: Assets,
+            }
+
+            #[derive(Deserialize)]
+            struct Assets {
+                links: Vec<Link>,
+            }
+
+            #[derive(Deserialize)]
+            struct Link {
+                name: String,
+                url: Url,
+                direct_asset_url: Option<Url>,
+            }
+
+            let auth = tokens.gitlab.map(|t| ("PRIVATE-TOKEN", t.to_string()));
+            let release: Release = get_json(client, &api_url, auth.as_ref()).await?;
+            let links = release.assets.links.into_iter().map(|l| (l.name, l.direct_asset_url.unwrap_or(l.url)));
+            let asset_url = find_single_asset(links, &asset_re, url)?;
+
+            let auth_header = download_auth_header(url, &asset_url, tokens);
+            Ok(ResolvedAsset { url: asset_url, auth_header })
+        },
+
+        other => Err(anyhow!("Not a forge URL scheme: {}", other)),
+    }
+}
+
+/// Get the HTTP header needed to download the asset of a forge URL
+///
+/// The GitLab token is only sent to the GitLab instance itself (via HTTPS, like its API), never
+/// to other hosts. GitHub release assets are public and never need authentication.
+pub fn download_auth_header(url: &Url, asset_url: &Url, tokens: ForgeTokens<'_>) -> Option<(&'static str, String)> {
+    let gitlab_origin = url.host_str()
+        .and_then(|host| Url::parse(&format!("https://{host}")).ok())
+        .map(|gitlab| gitlab.origin());
+
+    match url.scheme() {
+        "gitlab" if gitlab_origin == Some(asset_url.origin()) => {
+            tokens.gitlab.map(|t| ("PRIVATE-TOKEN", t.to_string()))
+        },
+        _ => None,
+    }
+}
+
+fn find_single_asset<I>(assets: I, asset_re: &regex::Regex, url: &Url) -> Result<Url>
+where
+    I: Iterator<Item = (String, Url)>,
+{
+    let mut matching = assets
+        .inspect(|(name, _)| trace!("Release asset: {}", name))
+        .filter(|(name, _)| asset_re.is_match(name))
+        .collect::<Vec<_>>();
+
+    match matching.len() {
+        0 => Err(anyhow!("No release asset matching '{}'", url)),
+        1 => Ok(matching.remove(0).1),
+        n => Err(anyhow!("{} release assets matching '{}': {}", n, url, matching.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", "))),
+    }
+}
+
+/// GET a JSON document from a forge API, waiting for the rate limit to reset if necessary
+async fn get_json<T>(client: &reqwest::Client, url: &str, auth: Option<&(&'static str, String)>) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut attempt = 0;
+    loop {
+        let mut request = client.get(url)
+            .header(reqwest::header::USER_AGENT, concat!("butido/", env!("CARGO_PKG_VERSION")))
+            .header(reqwest::header::ACCEPT, "application/json");
+
+        if let Some((name, value)) = auth {
+            request = request.header(*name, value);
+        }
+
+        trace!("Querying {}", url);
+        let response = request.send()
+            .await
+            .with_context(|| anyhow!("Querying '{}'", url))?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+        if let Some(wait) = rate_limit_wait(response.status(), response.headers(), now) {
+            if attempt < MAX_RATE_LIMIT_RETRIES {
+                attempt += 1;
+                warn!("Rate limit hit for '{}', waiting {}s (attempt {}/{})", url, wait.as_secs(), attempt, MAX_RATE_LIMIT_RETRIES);
+                tokio::time::sleep(wait).await;
+                continue
+            }
+
+            return Err(anyhow!("Rate limit exceeded for '{}'", url))
+        }
+
+        let text = response.error_for_status()
+            .with_context(|| anyhow!("Querying '{}'", url))?
+            .text()
+            .await?;
+        debug!("Response from {}: {} bytes", url, text.len());
+        return serde_json::from_str(&text)
+            .map_err(Error::from)
+            .with_context(|| anyhow!("Parsing response from '{}'", url))
+    }
+}
+
+/// Find out how long to wait if the response indicates that the API rate limit was hit
+///
+/// `now` is the current time in seconds since the UNIX epoch.
+fn rate_limit_wait(status: reqwest::StatusCode, headers: &reqwest::header::HeaderMap, now: u64) -> Option<Duration> {
+    if status != reqwest::StatusCode::FORBIDDEN && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u64>().ok())
+    };
+
+    if let Some(secs) = header("retry-after") {
+        return Some(Duration::from_secs(secs).min(MAX_RATE_LIMIT_WAIT))
+    }
+
+    // GitHub uses the "x-" prefixed headers, GitLab the unprefixed ones
+    let remaining = header("x-ratelimit-remaining").or_else(|| header("ratelimit-remaining"));
+    if remaining != Some(0) {
+        return None
+    }
+
+    let reset = header("x-ratelimit-reset").or_else(|| header("ratelimit-reset"))?;
+    Some(Duration::from_secs(reset.saturating_sub(now) + 1).min(MAX_RATE_LIMIT_WAIT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn assets<'a>(names: &'a [&'a str]) -> impl Iterator<Item = (String, Url)> + 'a {
+        names.iter().map(|name| (name.to_string(), url(&format!("https://example.com/download/{name}"))))
+    }
+
+    #[test]
+    fn test_parse_forge_url() {
+        let release = parse_forge_url(&url("github://science-computing/butido/v0.3.0/butido-*.tar.gz")).unwrap();
+        assert_eq!(release, ForgeRelease {
+            api_url: String::from("https://api.github.com/repos/science-computing/butido/releases/tags/v0.3.0"),
+            asset: String::from("butido-*.tar.gz"),
+        });
+
+        // the project path of GitLab can have several segments and is encoded for the API
+        let release = parse_forge_url(&url("gitlab://gitlab.example.com/group/sub/project/v1.0+1/foo.tar.gz")).unwrap();
+        assert_eq!(release, ForgeRelease {
+            api_url: String::from("https://gitlab.example.com/api/v4/projects/group%2Fsub%2Fproject/releases/v1.0%2B1"),
+            asset: String::from("foo.tar.gz"),
+        });
+
+        assert!(parse_forge_url(&url("github://science-computing/v0.3.0/butido.tar.gz")).is_err());
+        assert!(parse_forge_url(&url("gitlab://gitlab.example.com/foo.tar.gz")).is_err());
+        assert!(parse_forge_url(&url("https://example.com/group/project/v1.0/foo.tar.gz")).is_err());
+    }
+
+    #[test]
+    fn test_find_single_asset() {
+        let forge_url = url("github://owner/repo/v1.0/foo-*.tar.gz");
+        let asset_re = crate::util::glob::glob_to_regex("foo-*.tar.gz").unwrap();
+
+        let found = find_single_asset(assets(&["foo-1.0.tar.gz", "foo-1.0.zip", "bar-1.0.tar.gz"]), &asset_re, &forge_url).unwrap();
+        assert_eq!(found, url("https://example.com/download/foo-1.0.tar.gz"));
+
+        let e = find_single_asset(assets(&["foo-1.0.zip"]), &asset_re, &forge_url).unwrap_err();
+        assert_eq!(e.to_string(), "No release asset matching 'github://owner/repo/v1.0/foo-*.tar.gz'");
+        assert!(find_single_asset(assets(&[]), &asset_re, &forge_url).is_err());
+
+        let e = find_single_asset(assets(&["foo-1.0.tar.gz", "foo-1.0-doc.tar.gz"]), &asset_re, &forge_url).unwrap_err();
+        assert_eq!(e.to_string(), "2 release assets matching 'github://owner/repo/v1.0/foo-*.tar.gz': foo-1.0.tar.gz, foo-1.0-doc.tar.gz");
+    }
+
+    #[test]
+    fn test_rate_limit_wait() {
+        let headers = |headers: &[(&'static str, &str)]| {
+            headers.iter()
+                .map(|(name, value)| (reqwest::header::HeaderName::from_static(name), value.parse().unwrap()))
+                .collect::<reqwest::header::HeaderMap>()
+        };
+        let now = 1_000_000;
+
+        // not rate limited
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::OK, &headers(&[("retry-after", "10")]), now), None);
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::FORBIDDEN, &headers(&[]), now), None);
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::FORBIDDEN, &headers(&[("x-ratelimit-remaining", "5"), ("x-ratelimit-reset", "1000060")]), now), None);
+
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::TOO_MANY_REQUESTS, &headers(&[("retry-after", "10")]), now), Some(Duration::from_secs(10)));
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::FORBIDDEN, &headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1000060")]), now), Some(Duration::from_secs(61)));
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::TOO_MANY_REQUESTS, &headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", "1000009")]), now), Some(Duration::from_secs(10)));
+
+        // a reset in the past is waited for a second
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::FORBIDDEN, &headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "999000")]), now), Some(Duration::from_secs(1)));
+
+        // the wait is limited
+        assert_eq!(rate_limit_wait(reqwest::StatusCode::TOO_MANY_REQUESTS, &headers(&[("retry-after", "86400")]), now), Some(MAX_RATE_LIMIT_WAIT));
+    }
+
+    #[test]
+    fn test_download_auth_header() {
+        let tokens = ForgeTokens { github: Some("github-token"), gitlab: Some("gitlab-token") };
+        let gitlab_url = url("gitlab://gitlab.example.com/group/project/v1.0/foo.tar.gz");
+        let header = |forge_url: &Url, asset_url: &str| download_auth_header(forge_url, &url(asset_url), tokens);
+
+        assert_eq!(
+            header(&gitlab_url, "https://gitlab.example.com/group/project/-/releases/v1.0/downloads/foo.tar.gz"),
+            Some(("PRIVATE-TOKEN", String::from("gitlab-token")))
+        );
+
+        // the token is never sent to other hosts, or without HTTPS
+        assert_eq!(header(&gitlab_url, "https://downloads.example.com/foo.tar.gz"), None);
+        assert_eq!(header(&gitlab_url, "https://gitlab.example.com.evil.org/foo.tar.gz"), None);
+        assert_eq!(header(&gitlab_url, "https://gitlab.example.com:8443/foo.tar.gz"), None);
+        assert_eq!(header(&gitlab_url, "http://gitlab.example.com/foo.tar.gz"), None);
+
+        // without a token, no header is sent
+        let no_tokens = ForgeTokens::default();
+        assert_eq!(download_auth_header(&gitlab_url, &url("https://gitlab.example.com/foo.tar.gz"), no_tokens), None);
+
+        // GitHub assets are downloaded without authentication
+        let github_url = url("github://owner/repo/v1.0/foo.tar.gz");
+        assert_eq!(header(&github_url, "https://github.com/owner/repo/releases/download/v1.0/foo.tar.gz"), None);
+        assert_eq!(header(&github_url, "https://objects.githubusercontent.com/foo.tar.gz"), None);
+    }
+}
@@ -8,12 +8,15 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
 use tracing::trace;
 use url::Url;
 
@@ -21,6 +24,9 @@ use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::Source;
+use crate::package::SourceHash;
+
+pub mod forge;
 
 #[derive(Clone, Debug)]
 pub struct SourceCache {
@@ -35,6 +41,70 @@ impl SourceCache {
     pub fn sources_for(&self, p: &Package) -> Vec<SourceEntry> {
         SourceEntry::for_package(self.root.clone(), p)
     }
+
+    fn verification_state_path(&self) -> PathBuf {
+        self.root.join(".verified.json")
+    }
+
+    /// Load the record of successfully verified sources
+    ///
+    /// If there is no record yet, an empty one is returned.
+    pub fn load_verification_state(&self) -> Result<VerificationState> {
+        let path = self.verification_state_path();
+        if !path.exists() {
+            trace!("No verification state found at {}", path.display());
+            return Ok(VerificationState::default())
+        }
+
+        std::fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|s| serde_json::from_str(&s).map_err(Error::from))
+            .with_context(|| anyhow!("Loading verification state from {}", path.display()))
+    }
+
+    /// Store the record of successfully verified sources
+    pub fn store_verification_state(&self, state: &VerificationState) -> Result<()> {
+        let path = self.verification_state_path();
+        let tmp_path = path.with_extension("json.tmp");
+        serde_json::to_string(state)
+            .map_err(Error::from)
+            .and_then(|s| std::fs::write(&tmp_path, s).map_err(Error::from))
+            .and_then(|_| std::fs::rename(&tmp_path, &path).map_err(Error::from))
+            .with_context(|| anyhow!("Storing verification state to {}", path.display()))
+    }
+}
+
+/// Record of source cache entries that were successfully verified
+///
+/// An entry is considered verified as long as the expected hash as well as the size and the
+/// modification time of the file did not change since it was verified.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VerificationState {
+    entries: HashMap<PathBuf, VerifiedEntry>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct VerifiedEntry {
+    hash: String,
+    size: u64,
+    mtime: u64,
+}
+
+impl VerificationState {
+    /// Check whether a source was already verified in its current state
+    pub fn is_verified(&self, source: &SourceEntry) -> bool {
+        match (self.entries.get(&source.path()), source.verification_entry()) {
+            (Some(recorded), Ok(current)) => *recorded == current,
+            _ => false,
+        }
+    }
+
+    /// Record that a source was successfully verified
+    pub fn mark_verified(&mut self, source: &SourceEntry) -> Result<()> {
+        let entry = source.verification_entry()?;
+        self.entries.insert(source.path(), entry);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -68,19 +138,41 @@ impl SourceEntry {
     }
 
     pub fn path(&self) -> PathBuf {
-        self.source_file_directory().join({
-            (self.package_source_name.as_ref() as &std::path::Path).with_extension("source")
-        })
+        self.source_file_directory().join(crate::package::source_file_name(&self.package_source_name))
+    }
+
+    /// The path of the file the resolved URL of a forge release asset is recorded in
+    pub fn lock_path(&self) -> PathBuf {
+        self.path().with_extension("lock")
     }
 
     pub fn url(&self) -> &Url {
         self.package_source.url()
     }
 
+    pub fn hash(&self) -> &SourceHash {
+        self.package_source.hash()
+    }
+
     pub fn download_manually(&self) -> bool {
         *self.package_source.download_manually()
     }
 
+    fn verification_entry(&self) -> Result<VerifiedEntry> {
+        let path = self.path();
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| anyhow!("Getting metadata of {}", path.display()))?;
+        let mtime = metadata.modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        Ok(VerifiedEntry {
+            hash: self.package_source.hash().value().to_string(),
+            size: metadata.len(),
+            mtime,
+        })
+    }
+
     pub async fn remove_file(&self) -> Result<()> {
         let p = self.path();
         tokio::fs::remove_file(&p).await?;
@@ -147,3 +239,48 @@ impl SourceEntry {
             .map_err(Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_verification_state() {
+        let root = std::env::temp_dir().join(format!("butido-test-sources-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let sc = SourceCache::new(root.clone());
+
+        let p = package("a", "1", "https://example.com/a-1.tar.gz", "123");
+        let source = sc.sources_for(&p).pop().unwrap();
+        let other = sc.sources_for(&package("b", "1", "https://example.com/b-1.tar.gz", "456")).pop().unwrap();
+        std::fs::create_dir_all(source.path().parent().unwrap()).unwrap();
+        std::fs::write(source.path(), "content").unwrap();
+
+        // Nothing is verified before the state was stored
+        let mut state = sc.load_verification_state().unwrap();
+        assert!(!state.is_verified(&source));
+
+        // Missing files cannot be marked as verified
+        assert!(state.mark_verified(&other).is_err());
+
+        state.mark_verified(&source).unwrap();
+        sc.store_verification_state(&state).unwrap();
+
+        let state = sc.load_verification_state().unwrap();
+        assert!(state.is_verified(&source));
+        assert!(!state.is_verified(&other));
+
+        // A changed file has to be verified again
+        std::fs::write(source.path(), "changed content").unwrap();
+        assert!(!state.is_verified(&source));
+
+        // As well as a changed expected hash
+        std::fs::write(source.path(), "content").unwrap();
+        let state = sc.load_verification_state().unwrap();
+        let changed_hash = sc.sources_for(&package("a", "1", "https://example.com/a-1.tar.gz", "789")).pop().unwrap();
+        assert!(!state.is_verified(&changed_hash));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,555 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A live dashboard for running submits
+//!
+//! Instead of one progress bar per job, the dashboard shows the status and current phase of all
+//! jobs, the last log lines of the running jobs and the utilization of the endpoints. It is redrawn
+//! periodically while the jobs run (see `Dashboard::show_while`).
+//!
+//! The state of the jobs is also served by the status endpoint of the orchestrator, and changes of
+//! the state can be written as JSON lines (see `Dashboard::with_events`).
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::endpoint::Endpoint;
+
+/// The number of log lines that are shown for each running job
+const LOG_TAIL_LINES: usize = 3;
+
+/// The number of log lines that are kept for each job, older lines are dropped
+const LOG_BUFFER_LINES: usize = 10_000;
+
+/// The time between two redraws of the dashboard
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The status of a job on the dashboard
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, parse_display::Display)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    #[display("waiting")]
+    Waiting,
+
+    #[display("scheduling")]
+    Scheduling,
+
+    #[display("running")]
+    Running,
+
+    #[display("retrying")]
+    Retrying,
+
+    #[display("built")]
+    Built,
+
+    #[display("reused")]
+    Reused,
+
+    #[display("failed")]
+    Failed,
+
+    #[display("stopped")]
+    Stopped,
+}
+
+impl JobStatus {
+    fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Built | JobStatus::Reused | JobStatus::Failed | JobStatus::Stopped)
+    }
+
+    /// The position of jobs with this status on the dashboard, running jobs are shown first
+    fn order(&self) -> u8 {
+        match self {
+            JobStatus::Running => 0,
+            JobStatus::Scheduling | JobStatus::Retrying => 1,
+            JobStatus::Failed => 2,
+            JobStatus::Waiting => 3,
+            JobStatus::Built | JobStatus::Reused | JobStatus::Stopped => 4,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JobState {
+    uuid: Uuid,
+    package: String,
+    status: JobStatus,
+    endpoint: Option<String>,
+    phase: Option<String>,
+
+    /// The log of the current run of the job (only lines of phases with full log)
+    #[serde(skip)]
+    log: LogBuffer,
+}
+
+/// The last lines of a log
+#[derive(Debug)]
+struct LogBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+
+    /// The number of lines that were dropped from the front of the buffer, the line number of
+    /// the first line in the buffer
+    offset: usize,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        LogBuffer {
+            lines: VecDeque::new(),
+            capacity,
+            offset: 0,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+            self.offset += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.offset = 0;
+    }
+
+    /// The last `n` lines
+    fn tail(&self, n: usize) -> impl Iterator<Item = &String> {
+        self.lines.iter().skip(self.lines.len().saturating_sub(n))
+    }
+
+    /// The lines from line number `from` on, with the line number of the first returned line
+    ///
+    /// If lines from `from` on were already dropped, the lines start at the first line that is
+    /// still in the buffer.
+    fn lines_from(&self, from: usize) -> (usize, Vec<String>) {
+        let first = from.max(self.offset);
+        let lines = self.lines.iter().skip(first - self.offset).cloned().collect();
+        (first, lines)
+    }
+}
+
+/// The state of all jobs of a submit
+pub struct Dashboard {
+    jobs: Mutex<Vec<JobState>>,
+    started: Instant,
+
+    /// The output the events of the jobs are written to as JSON lines, if enabled
+    events: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl std::fmt::Debug for Dashboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "Dashboard ( {} jobs )", self.jobs.lock().unwrap().len())
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Dashboard {
+            jobs: Mutex::new(Vec::new()),
+            started: Instant::now(),
+            events: None,
+        }
+    }
+
+    /// Write the events of the jobs (scheduled, started, phase changed, finished, artifact
+    /// produced) to `output`, one JSON object per line
+    pub fn with_events<W: Write + Send + 'static>(mut self, output: W) -> Self {
+        self.events = Some(Mutex::new(Box::new(output)));
+        self
+    }
+
+    fn emit(&self, event: &str, job: &JobState, mut data: serde_json::Value) {
+        let events = match self.events.as_ref() {
+            Some(events) => events,
+            None => return,
+        };
+
+        if let Some(data) = data.as_object_mut() {
+            data.insert(String::from("event"), serde_json::Value::from(event));
+            data.insert(String::from("time"), serde_json::Value::from(chrono::Utc::now().to_rfc3339()));
+            data.insert(String::from("job"), serde_json::Value::from(job.uuid.to_string()));
+            data.insert(String::from("package"), serde_json::Value::from(job.package.clone()));
+        }
+
+        let mut output = events.lock().unwrap();
+        if let Err(e) = writeln!(output, "{data}").and_then(|_| output.flush()) {
+            tracing::warn!("Failed to write event: {}", e);
+        }
+    }
+
+    pub fn add_job(&self, uuid: Uuid, package: String) {
+        self.jobs.lock().unwrap().push(JobState {
+            uuid,
+            package,
+            status: JobStatus::Waiting,
+            endpoint: None,
+            phase: None,
+            log: LogBuffer::new(LOG_BUFFER_LINES),
+        });
+    }
+
+    fn update<F: FnOnce(&mut JobState)>(&self, uuid: &Uuid, f: F) {
+        if let Some(job) = self.jobs.lock().unwrap().iter_mut().find(|job| job.uuid == *uuid) {
+            f(job)
+        }
+    }
+
+    pub fn set_status(&self, uuid: &Uuid, status: JobStatus) {
+        self.update(uuid, |job| {
+            if job.status == status {
+                return
+            }
+
+            job.status = status;
+            match job.status {
+                JobStatus::Scheduling => self.emit("job_scheduled", job, serde_json::json!({})),
+                JobStatus::Retrying => self.emit("job_retrying", job, serde_json::json!({})),
+                ref status if status.is_finished() => {
+                    self.emit("job_finished", job, serde_json::json!({ "status": status }))
+                },
+                _ => {},
+            }
+        })
+    }
+
+    /// Mark the job as running on the endpoint `endpoint`
+    pub fn set_running(&self, uuid: &Uuid, endpoint: String) {
+        self.update(uuid, |job| {
+            job.status = JobStatus::Running;
+            job.endpoint = Some(endpoint);
+            job.phase = None;
+            job.log.clear();
+            self.emit("job_started", job, serde_json::json!({ "endpoint": job.endpoint }));
+        })
+    }
+
+    pub fn set_phase(&self, uuid: &Uuid, phase: String) {
+        self.update(uuid, |job| {
+            self.emit("phase_changed", job, serde_json::json!({ "phase": phase }));
+            job.phase = Some(phase);
+        })
+    }
+
+    /// Record that the job produced the artifact at `path`
+    pub fn add_artifact(&self, uuid: &Uuid, path: String) {
+        self.update(uuid, |job| self.emit("artifact_produced", job, serde_json::json!({ "artifact": path })))
+    }
+
+    pub fn push_log_line(&self, uuid: &Uuid, line: String) {
+        self.update(uuid, |job| job.log.push(line))
+    }
+
+    /// Get a summary of the submit as JSON
+    pub fn summary(&self) -> serde_json::Value {
+        let jobs = self.jobs.lock().unwrap();
+        let count = |f: fn(&JobStatus) -> bool| jobs.iter().filter(|job| f(&job.status)).count();
+
+        serde_json::json!({
+            "jobs": jobs.len(),
+            "running": count(|s| *s == JobStatus::Running),
+            "finished": count(JobStatus::is_finished),
+            "failed": count(|s| *s == JobStatus::Failed),
+            "elapsed_seconds": self.started.elapsed().as_secs(),
+        })
+    }
+
+    /// Get the state of all jobs as JSON
+    pub fn jobs(&self) -> serde_json::Value {
+        serde_json::json!(*self.jobs.lock().unwrap())
+    }
+
+    /// Get the log of the current run of the job `uuid` from line number `from` on
+    ///
+    /// Only the last lines of the log are kept, the line number of the first returned line is
+    /// returned with the lines.
+    pub fn log(&self, uuid: &Uuid, from: usize) -> Option<(usize, Vec<String>)> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|job| job.uuid == *uuid)
+            .map(|job| job.log.lines_from(from))
+    }
+
+    /// Render the dashboard into lines of at most `width` characters
+    ///
+    /// If there are more than `height` lines, the job table is cut off.
+    pub fn render(&self, endpoints: &[Arc<Endpoint>], width: usize, height: usize) -> Vec<String> {
+        let jobs = self.jobs.lock().unwrap();
+        let count = |f: fn(&JobStatus) -> bool| jobs.iter().filter(|job| f(&job.status)).count();
+
+        let mut header = vec![
+            format!("{} jobs: {} running, {} finished ({} failed), {}s elapsed",
+                jobs.len(),
+                count(|s| *s == JobStatus::Running),
+                count(JobStatus::is_finished),
+                count(|s| *s == JobStatus::Failed),
+                self.started.elapsed().as_secs()),
+            endpoints
+                .iter()
+                .map(|ep| format!("{}: {}/{}", ep.name(), ep.running_jobs(), ep.num_max_jobs()))
+                .collect::<Vec<_>>()
+                .join("  "),
+            String::new(),
+        ];
+
+        let mut sorted = jobs.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|job| job.status.order());
+
+        let mut log_tail = Vec::new();
+        for job in sorted.iter().filter(|job| job.status == JobStatus::Running) {
+            log_tail.push(String::new());
+            log_tail.push(format!("-- {} ({}) --", job.package, job.phase.as_deref().unwrap_or("-")));
+            log_tail.extend(job.log.tail(LOG_TAIL_LINES).map(|line| format!("   {line}")));
+        }
+
+        let table_height = height.saturating_sub(header.len() + log_tail.len()).max(1);
+        let table = sorted
+            .iter()
+            .map(|job| {
+                format!("{:<10} {:<40} {:<20} {}",
+                    job.status.to_string(),
+                    job.package,
+                    job.endpoint.as_deref().unwrap_or(""),
+                    job.phase.as_deref().unwrap_or(""))
+            })
+            .take(table_height);
+
+        header.extend(table);
+        header.extend(log_tail);
+        header
+            .into_iter()
+            .take(height)
+            .map(|line| line.chars().take(width).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    /// Redraw the dashboard on the terminal until `fut` finished
+    pub async fn show_while<F: Future>(&self, endpoints: &[Arc<Endpoint>], fut: F) -> Result<F::Output> {
+        let term = console::Term::stdout();
+        let mut interval = tokio::time::interval(REDRAW_INTERVAL);
+        tokio::pin!(fut);
+
+        loop {
+            tokio::select! {
+                output = &mut fut => {
+                    self.draw(&term, endpoints)?;
+                    return Ok(output)
+                },
+                _ = interval.tick() => self.draw(&term, endpoints)?,
+            }
+        }
+    }
+
+    fn draw(&self, term: &console::Term, endpoints: &[Arc<Endpoint>]) -> Result<()> {
+        let (height, width) = term.size();
+        let lines = self.render(endpoints, width as usize, height as usize);
+        term.clear_screen()?;
+        term.write_str(&lines.join("\n"))?;
+        term.write_line("")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let dashboard = Dashboard::new();
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        dashboard.add_job(a, String::from("a 1"));
+        dashboard.add_job(b, String::from("b 2"));
+
+        dashboard.set_running(&b, String::from("ep1"));
+        dashboard.set_phase(&b, String::from("build"));
+        for i in 0..5 {
+            dashboard.push_log_line(&b, format!("line {i}"));
+        }
+
+        let lines = dashboard.render(&[], 80, 50);
+        assert!(lines[0].starts_with("2 jobs: 1 running, 0 finished (0 failed)"));
+        assert!(lines[3].starts_with("running    b 2"));
+        assert!(lines[4].starts_with("waiting    a 1"));
+        assert_eq!(&lines[6..], ["-- b 2 (build) --", "   line 2", "   line 3", "   line 4"]);
+
+        // The job table is cut off if the terminal is too small
+        let lines = dashboard.render(&[], 20, 8);
+        assert_eq!(lines.len(), 8);
+        assert!(lines.iter().all(|l| l.chars().count() <= 20));
+        assert!(!lines.iter().any(|l| l.starts_with("waiting")));
+    }
+
+    #[test]
+    fn test_render_sort_order() {
+        let dashboard = Dashboard::new();
+        let statuses = [
+            ("built", JobStatus::Built),
+            ("waiting", JobStatus::Waiting),
+            ("failed", JobStatus::Failed),
+            ("retrying", JobStatus::Retrying),
+            ("stopped", JobStatus::Stopped),
+            ("scheduling", JobStatus::Scheduling),
+            ("reused", JobStatus::Reused),
+        ];
+        for (name, status) in statuses {
+            let uuid = Uuid::new_v4();
+            dashboard.add_job(uuid, String::from(name));
+            dashboard.set_status(&uuid, status);
+        }
+        let running = Uuid::new_v4();
+        dashboard.add_job(running, String::from("running"));
+        dashboard.set_running(&running, String::from("ep1"));
+
+        let lines = dashboard.render(&[], 80, 50);
+        let packages = lines[3..11]
+            .iter()
+            .map(|l| l.split_whitespace().nth(1).unwrap())
+            .collect::<Vec<_>>();
+
+        // Running jobs first, then the jobs that are about to run, the failed ones and the
+        // waiting ones, the other finished jobs last. Jobs with the same order keep the order in
+        // which they were added.
+        assert_eq!(packages, ["running", "retrying", "scheduling", "failed", "waiting", "built", "stopped", "reused"]);
+    }
+
+    #[test]
+    fn test_render_truncation() {
+        let dashboard = Dashboard::new();
+        let running = Uuid::new_v4();
+        dashboard.add_job(running, "x".repeat(100));
+        dashboard.set_running(&running, String::from("ep1"));
+        dashboard.push_log_line(&running, String::from("only line"));
+        for i in 0..10 {
+            dashboard.add_job(Uuid::new_v4(), format!("waiting{i}"));
+        }
+
+        // Long lines are cut at the width, trailing whitespace is removed
+        let lines = dashboard.render(&[], 30, 50);
+        assert_eq!(lines.len(), 3 + 11 + 3);
+        assert_eq!(lines[3], format!("running    {}", "x".repeat(19)));
+        assert_eq!(lines[2], "");
+
+        // A job with fewer log lines than the tail shows all of them
+        assert_eq!(lines[lines.len() - 1], "   only line");
+
+        // The job table shrinks so that the log tail fits
+        let lines = dashboard.render(&[], 80, 10);
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[3..7].iter().filter(|l| l.starts_with("waiting")).count(), 3);
+        assert_eq!(lines[9], "   only line");
+
+        // At least one line of the table is shown, the log tail is cut off instead
+        let lines = dashboard.render(&[], 80, 4);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[3].starts_with("running"));
+    }
+
+    #[test]
+    fn test_json() {
+        let dashboard = Dashboard::new();
+        let a = Uuid::new_v4();
+        dashboard.add_job(a, String::from("a 1"));
+        dashboard.set_running(&a, String::from("ep1"));
+        dashboard.push_log_line(&a, String::from("line"));
+
+        assert_eq!(dashboard.summary()["running"], 1);
+        assert_eq!(dashboard.jobs(), serde_json::json!([{
+            "uuid": a.to_string(),
+            "package": "a 1",
+            "status": "running",
+            "endpoint": "ep1",
+            "phase": null,
+        }]));
+        assert_eq!(dashboard.log(&a, 0), Some((0, vec![String::from("line")])));
+        assert_eq!(dashboard.log(&Uuid::new_v4(), 0), None);
+    }
+
+    #[test]
+    fn test_log_buffer() {
+        let mut log = LogBuffer::new(3);
+        for i in 0..5 {
+            log.push(format!("line {i}"));
+        }
+
+        // The oldest lines are dropped, the line numbers are kept
+        assert_eq!(log.lines_from(0), (2, vec![String::from("line 2"), String::from("line 3"), String::from("line 4")]));
+        assert_eq!(log.lines_from(4), (4, vec![String::from("line 4")]));
+        assert_eq!(log.lines_from(7), (7, vec![]));
+        assert_eq!(log.tail(2).collect::<Vec<_>>(), ["line 3", "line 4"]);
+        assert_eq!(log.tail(5).count(), 3);
+
+        log.clear();
+        log.push(String::from("new"));
+        assert_eq!(log.lines_from(0), (0, vec![String::from("new")]));
+    }
+
+    #[test]
+    fn test_events() {
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Output::default();
+        let dashboard = Dashboard::new().with_events(output.clone());
+        let a = Uuid::new_v4();
+        dashboard.add_job(a, String::from("a 1"));
+        dashboard.set_status(&a, JobStatus::Scheduling);
+        dashboard.set_running(&a, String::from("ep1"));
+        dashboard.set_phase(&a, String::from("build"));
+        dashboard.add_artifact(&a, String::from("/staging/a-1.tar"));
+        dashboard.set_status(&a, JobStatus::Built);
+
+        let events = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let events = events
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let names = events.iter().map(|e| e["event"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, ["job_scheduled", "job_started", "phase_changed", "artifact_produced", "job_finished"]);
+        assert!(events.iter().all(|e| e["job"] == a.to_string() && e["package"] == "a 1"));
+        assert_eq!(events[1]["endpoint"], "ep1");
+        assert_eq!(events[2]["phase"], "build");
+        assert_eq!(events[3]["artifact"], "/staging/a-1.tar");
+        assert_eq!(events[4]["status"], "built");
+    }
+}
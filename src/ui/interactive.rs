@@ -0,0 +1,60 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+
+/// Whether butido may ask the user questions, disabled with `--quiet`
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+/// Whether butido may ask the user questions
+///
+/// This is not the case with `--quiet` or if stdin is not a terminal.
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed) && atty::is(atty::Stream::Stdin)
+}
+
+/// Ask the user for confirmation
+///
+/// Fails if butido must not ask questions, instead of blocking or silently assuming an answer.
+pub fn confirm<S: Into<String>>(prompt: S) -> Result<bool> {
+    let prompt = prompt.into();
+    if !is_interactive() {
+        return Err(anyhow!("Cannot ask '{}' in non-interactive mode", prompt))
+    }
+
+    dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .interact()
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirm_non_interactive() {
+        set_interactive(false);
+        assert!(!is_interactive());
+
+        let err = confirm("Really?").unwrap_err();
+        assert_eq!(err.to_string(), "Cannot ask 'Really?' in non-interactive mode");
+        set_interactive(true);
+    }
+}
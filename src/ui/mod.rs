@@ -24,6 +24,15 @@ use crate::package::Script;
 mod package;
 pub use crate::ui::package::*;
 
+mod dashboard;
+pub use crate::ui::dashboard::*;
+
+mod interactive;
+pub use crate::ui::interactive::*;
+
+mod pager;
+pub use crate::ui::pager::*;
+
 pub fn package_repo_cleanness_check(repo: &git2::Repository) -> Result<()> {
     if !crate::util::git::repo_is_clean(repo)? {
         error!(
@@ -98,11 +98,14 @@ pub fn handlebars_for_package_printing(format: &str) -> Result<Handlebars> {
 
 impl<'a, P: Borrow<Package>> PreparePrintPackage<'a, P> {
     pub fn into_displayable(self) -> Result<PrintablePackage> {
-        let script = ScriptBuilder::new(&Shebang::from(self.config.shebang().clone())).build(
-            self.package.borrow(),
-            self.config.available_phases(),
-            *self.config.strict_script_interpolation(),
-        ).context("Rendering script for printing it failed")?;
+        let script = ScriptBuilder::new(&Shebang::from(self.config.shebang().clone()))
+            .apply_patches_before(self.config.apply_patches_before().as_ref())
+            .build(
+                self.package.borrow(),
+                self.config.available_phases(),
+                *self.config.strict_script_interpolation(),
+            )
+            .context("Rendering script for printing it failed")?;
 
         let script = crate::ui::script_to_printable(
             &script,
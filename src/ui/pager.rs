@@ -0,0 +1,168 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::io::Write;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+use tracing::warn;
+
+/// Output that is piped through a pager (`$PAGER` or `less`), like git does
+///
+/// If no pager is used, the output is written to stdout. When the `Pager` is dropped, its input
+/// is closed and it waits for the pager to exit.
+pub struct Pager {
+    child: Option<Child>,
+    input: Box<dyn Write>,
+}
+
+impl Pager {
+    /// Start the pager if `enabled` and stdout is a terminal, otherwise write to stdout
+    pub fn start(enabled: bool) -> Pager {
+        let pager_env = std::env::var("PAGER").ok();
+        match pager(enabled, atty::is(atty::Stream::Stdout), pager_env.as_deref()) {
+            Some(pager) => Pager::spawn(&pager, std::env::var_os("LESS").is_some()),
+            None => Pager::stdout(),
+        }
+    }
+
+    fn spawn(pager: &str, less_set: bool) -> Pager {
+        match pager_command(pager, less_set).spawn() {
+            Ok(mut child) => match child.stdin.take() {
+                Some(stdin) => Pager {
+                    child: Some(child),
+                    input: Box::new(PagerInput(stdin)),
+                },
+                None => Pager::stdout(),
+            },
+            Err(e) => {
+                warn!("Failed to start pager '{}', writing to stdout: {}", pager, e);
+                Pager::stdout()
+            },
+        }
+    }
+
+    fn stdout() -> Pager {
+        Pager {
+            child: None,
+            input: Box::new(std::io::stdout()),
+        }
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.input.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.input.flush()
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.input.flush();
+        if let Some(child) = self.child.as_mut() {
+            // Close the input of the pager, so it knows that the output is complete
+            self.input = Box::new(std::io::sink());
+            if let Err(e) = child.wait() {
+                warn!("Failed to wait for the pager: {}", e);
+            }
+        }
+    }
+}
+
+/// The pager to pipe the output through, `None` if the output is not paged
+///
+/// The output is only paged if paging is `enabled` and the output goes to a terminal. The pager
+/// is `$PAGER` (`pager_env`) if it is set and not empty, `less` otherwise.
+fn pager(enabled: bool, is_terminal: bool, pager_env: Option<&str>) -> Option<String> {
+    if !enabled || !is_terminal {
+        return None
+    }
+
+    let pager = pager_env
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or("less");
+    Some(pager.to_string())
+}
+
+/// The command that runs the pager
+///
+/// The pager is run via the shell, so $PAGER can contain arguments. If $LESS is not set
+/// (`less_set`), `less` quits if the output fits on one screen, shows colors and does not clear
+/// the screen.
+fn pager_command(pager: &str, less_set: bool) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    if !less_set {
+        command.env("LESS", "FRX");
+    }
+    command
+}
+
+/// The input of the pager, which ignores that the user quit the pager before all output was
+/// written
+struct PagerInput(std::process::ChildStdin);
+
+impl Write for PagerInput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.write(buf) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            other => other,
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.0.flush() {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_pager() {
+        assert_eq!(pager(false, true, Some("more")), None);
+        assert_eq!(pager(true, false, Some("more")), None);
+
+        assert_eq!(pager(true, true, Some("more -R")).as_deref(), Some("more -R"));
+        assert_eq!(pager(true, true, None).as_deref(), Some("less"));
+        assert_eq!(pager(true, true, Some(" ")).as_deref(), Some("less"));
+    }
+
+    #[test]
+    fn test_pager_command() {
+        let command = pager_command("more -R", false);
+        assert_eq!(command.get_program(), "sh");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), vec![OsStr::new("-c"), OsStr::new("more -R")]);
+        assert_eq!(command.get_envs().collect::<Vec<_>>(), vec![(OsStr::new("LESS"), Some(OsStr::new("FRX")))]);
+
+        // the LESS of the user is kept
+        assert_eq!(pager_command("less", true).get_envs().count(), 0);
+    }
+
+    #[test]
+    fn test_pager_quit_early() {
+        // The pager exits without reading its input, like when the user quits it
+        let mut pager = Pager::spawn("true", true);
+        assert!(pager.child.is_some());
+        let output = vec![b'x'; 1024 * 1024];
+        assert!(pager.write_all(&output).is_ok());
+        assert!(pager.flush().is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A line based diff in the unified format
+
+/// The maximum size of the table for the longest common subsequence of the changed lines
+///
+/// If the changed parts of the texts are larger, all old lines are shown as removed and all new
+/// lines as added, instead of using a lot of memory.
+const MAX_LCS_CELLS: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Create a unified diff of two texts, with `context` unchanged lines around the changes
+///
+/// Returns `None` if the texts have the same lines.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> Option<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let lines = diff_lines(&old, &new);
+
+    let changes = lines.iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return None
+    }
+
+    // Group the changes into hunks, changes that are close to each other share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    for (start, end) in hunks {
+        let old_before = lines[..start].iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_before = lines[..start].iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+        let old_count = lines[start..end].iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_count = lines[start..end].iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+        let old_start = if old_count == 0 { old_before } else { old_before + 1 };
+        let new_start = if new_count == 0 { new_before } else { new_before + 1 };
+
+        out.push_str(&format!("@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"));
+        for line in &lines[start..end] {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            out.push('\n');
+        }
+    }
+
+    Some(out)
+}
+
+/// Compute the lines of the diff, via the longest common subsequence of the lines
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines = old[..prefix].iter().map(|l| Line::Same(l)).collect::<Vec<_>>();
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_LCS_CELLS {
+        lines.extend(old_middle.iter().map(|l| Line::Removed(l)));
+        lines.extend(new_middle.iter().map(|l| Line::Added(l)));
+    } else {
+        // lcs[i * (m + 1) + j] is the length of the longest common subsequence of
+        // old_middle[i..] and new_middle[j..]
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if old_middle[i] == new_middle[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                lines.push(Line::Same(old_middle[i]));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                // removed lines are shown before the added lines that replace them
+                lines.push(Line::Removed(old_middle[i]));
+                i += 1;
+            } else {
+                lines.push(Line::Added(new_middle[j]));
+                j += 1;
+            }
+        }
+    }
+
+    lines.extend(old[old.len() - suffix..].iter().map(|l| Line::Same(l)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_changes() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb", "old", "new", 3), None);
+        assert_eq!(unified_diff("", "", "old", "new", 3), None);
+    }
+
+    #[test]
+    fn test_changed_line() {
+        let diff = unified_diff("a\nb\nc\nd\ne\nf\n", "a\nb\nC\nd\ne\nf\n", "old", "new", 1).unwrap();
+        assert_eq!(diff, indoc::indoc!("
+            --- old
+            +++ new
+            @@ -2,3 +2,3 @@
+             b
+            -c
+            +C
+             d
+        "));
+    }
+
+    #[test]
+    fn test_added_and_removed_lines() {
+        let diff = unified_diff("", "a\nb\n", "old", "new", 3).unwrap();
+        assert_eq!(diff, "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+a\n+b\n");
+
+        let diff = unified_diff("a\nb\nc\n", "a\nc\n", "old", "new", 0).unwrap();
+        assert_eq!(diff, "--- old\n+++ new\n@@ -2,1 +1,0 @@\n-b\n");
+    }
+
+    #[test]
+    fn test_replaced_lines() {
+        let diff = unified_diff("a\nb\nc\nd\n", "a\nB\nC\nd\n", "old", "new", 0).unwrap();
+        assert_eq!(diff, "--- old\n+++ new\n@@ -2,2 +2,2 @@\n-b\n-c\n+B\n+C\n");
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\nx\n3\n4\n5\n6\n7\ny\n9\n";
+
+        // Far apart changes get their own hunks
+        let diff = unified_diff(old, new, "old", "new", 1).unwrap();
+        assert_eq!(diff.matches("@@ -").count(), 2);
+
+        // Close changes share a hunk
+        let diff = unified_diff(old, new, "old", "new", 3).unwrap();
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("@@ -1,9 +1,9 @@"), "{}", diff);
+    }
+}
@@ -38,3 +38,24 @@ pub fn get_repo_head_commit_hash(r: &Repository) -> Result<String> {
     trace!("Found git commit hash = {}", s);
     Ok(s)
 }
+
+/// Write the files of the tree of `refname` (e.g., a branch, tag or commit hash) to `target`
+///
+/// Neither the index nor the working directory of the repository are touched.
+pub fn export_tree(r: &Repository, refname: &str, target: &std::path::Path) -> Result<()> {
+    let tree = r
+        .revparse_single(refname)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| anyhow!("Resolving '{}' to a tree", refname))?;
+
+    std::fs::create_dir_all(target)
+        .with_context(|| anyhow!("Creating {}", target.display()))?;
+
+    let mut checkout = git2::build::CheckoutBuilder::new();
+    checkout.target_dir(target).update_index(false).force();
+    r.checkout_tree(tree.as_object(), Some(&mut checkout))
+        .with_context(|| anyhow!("Writing the tree of '{}' to {}", refname, target.display()))?;
+
+    trace!("Exported tree of '{}' to {}", refname, target.display());
+    Ok(())
+}
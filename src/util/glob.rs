@@ -0,0 +1,43 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Error;
+use anyhow::Result;
+
+/// Convert a simple glob ("*" and "?" wildcards) to an anchored regex
+pub fn glob_to_regex(glob: &str) -> Result<regex::Regex> {
+    let re = glob.chars()
+        .map(|c| match c {
+            '*' => String::from(".*"),
+            '?' => String::from("."),
+            other => regex::escape(&other.to_string()),
+        })
+        .collect::<String>();
+
+    regex::Regex::new(&format!("^{re}$")).map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_to_regex;
+
+    #[test]
+    fn test_glob_to_regex() {
+        let re = glob_to_regex("python-*").unwrap();
+        assert!(re.is_match("python-requests"));
+        assert!(re.is_match("python-"));
+        assert!(!re.is_match("xpython-requests"));
+
+        let re = glob_to_regex("lib?.so").unwrap();
+        assert!(re.is_match("libc.so"));
+        assert!(!re.is_match("libcc.so"));
+        assert!(!re.is_match("libcXso"));
+    }
+}
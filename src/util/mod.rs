@@ -40,13 +40,65 @@ impl AsRef<str> for EnvironmentVariableName {
 }
 
 
+/// The contents of a file as a stream of chunks, for uploading it without reading it into memory
+pub fn file_stream<R>(file: R) -> impl futures::Stream<Item = std::io::Result<Vec<u8>>>
+    where R: tokio::io::AsyncRead + Unpin
+{
+    futures::stream::try_unfold(file, |mut file| async move {
+        use tokio::io::AsyncReadExt;
+
+        let mut buffer = vec![0; 64 * 1024];
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(None)
+        }
+        buffer.truncate(n);
+        Ok(Some((buffer, file)))
+    })
+}
+
+/// The SHA-256 digest ("sha256:<hex>") and the size of a file
+pub async fn sha256_of_file(path: &std::path::Path) -> anyhow::Result<(String, u64)> {
+    use anyhow::Context;
+    use sha2::Digest;
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| anyhow::anyhow!("Opening {}", path.display()))?;
+
+    let mut hasher = sha2::Sha256::new();
+    let mut size = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)
+            .await
+            .with_context(|| anyhow::anyhow!("Reading {}", path.display()))?;
+        if n == 0 {
+            break
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+pub mod diff;
 pub mod docker;
 pub mod env;
 pub mod filters;
 pub mod git;
+pub mod glob;
+pub mod oci;
 pub mod parser;
 pub mod progress;
+pub mod version;
 
 pub fn stdout_is_pipe() -> bool {
     !atty::is(atty::Stream::Stdout)
 }
+
+pub fn stderr_is_pipe() -> bool {
+    !atty::is(atty::Stream::Stderr)
+}
@@ -0,0 +1,382 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Pushing files to an OCI registry as OCI artifacts
+//!
+//! The files are pushed as the layers of an image manifest with an empty config, like ORAS does,
+//! so they can be pulled with `oras pull` or other registry tooling.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::header::HeaderValue;
+use reqwest::RequestBuilder;
+use reqwest::StatusCode;
+use tracing::debug;
+use tracing::trace;
+
+use crate::config::OciReleaseTarget;
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const EMPTY_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+const EMPTY_CONFIG: &[u8] = b"{}";
+
+/// The media type of the pushed files
+const FILE_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// The annotation that ORAS uses for the file name of a layer
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// A blob in the registry
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+
+    /// The file name of the blob, for files
+    pub title: Option<String>,
+}
+
+impl Descriptor {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "mediaType": self.media_type,
+            "digest": self.digest,
+            "size": self.size,
+        });
+        if let Some(title) = self.title.as_ref() {
+            json["annotations"] = serde_json::json!({ TITLE_ANNOTATION: title });
+        }
+        json
+    }
+}
+
+/// The authorization for the requests to a repository
+enum Auth {
+    None,
+    Basic(String, Option<String>),
+    Bearer(String),
+}
+
+/// A connection to an OCI registry, for pushing to one repository
+pub struct Registry<'a> {
+    client: reqwest::Client,
+    target: &'a OciReleaseTarget,
+    repository: String,
+    auth: Auth,
+}
+
+impl<'a> Registry<'a> {
+    /// Connect to the registry and authenticate for pushing to `repository`
+    pub async fn connect(client: reqwest::Client, target: &'a OciReleaseTarget, repository: String) -> Result<Registry<'a>> {
+        let mut registry = Registry { client, target, repository, auth: Auth::None };
+
+        let response = registry.client
+            .get(registry.url("/v2/"))
+            .send()
+            .await
+            .with_context(|| anyhow!("Connecting to registry {}", target.registry()))?;
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response.headers()
+                .get(reqwest::header::WWW_AUTHENTICATE)
+                .and_then(|h| h.to_str().ok())
+                .and_then(parse_challenge)
+                .ok_or_else(|| anyhow!("Registry {} requires authentication, but sent no usable challenge", target.registry()))?;
+
+            registry.auth = registry.authenticate(challenge).await?;
+        }
+
+        Ok(registry)
+    }
+
+    /// The reference of a tag in the repository, e.g. "registry.example.com/foo:1.0"
+    pub fn reference(&self, tag: &str) -> String {
+        format!("{}/{}:{}", self.target.registry(), self.repository, tag)
+    }
+
+    fn url(&self, path: &str) -> String {
+        let scheme = if self.target.insecure() { "http" } else { "https" };
+        format!("{}://{}{}", scheme, self.target.registry(), path)
+    }
+
+    async fn authenticate(&self, (scheme, params): (String, HashMap<String, String>)) -> Result<Auth> {
+        let username = self.target
+            .username()
+            .clone()
+            .ok_or_else(|| anyhow!("Registry {} requires authentication, but no username is configured", self.target.registry()))?;
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            return Ok(Auth::Basic(username, self.target.password().clone()))
+        }
+
+        let realm = params.get("realm")
+            .ok_or_else(|| anyhow!("Authentication challenge of registry {} has no realm", self.target.registry()))?;
+        let mut query = vec![(String::from("scope"), format!("repository:{}:pull,push", self.repository))];
+        if let Some(service) = params.get("service") {
+            query.push((String::from("service"), service.clone()));
+        }
+
+        debug!("Requesting token for {} from {}", self.repository, realm);
+        let response = self.client
+            .get(realm)
+            .query(&query)
+            .basic_auth(username, self.target.password().as_ref())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Requesting token from {}", realm))?
+            .text()
+            .await?;
+
+        let response: serde_json::Value = serde_json::from_str(&response)
+            .with_context(|| anyhow!("Parsing token response of {}", realm))?;
+        response.get("token")
+            .or_else(|| response.get("access_token"))
+            .and_then(serde_json::Value::as_str)
+            .map(|token| Auth::Bearer(token.to_string()))
+            .ok_or_else(|| anyhow!("Token response of {} contains no token", realm))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Auth::None => request,
+            Auth::Basic(username, password) => request.basic_auth(username, password.as_ref()),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+
+    async fn blob_exists(&self, digest: &str) -> Result<bool> {
+        let response = self.authorized(self.client.head(self.url(&format!("/v2/{}/blobs/{}", self.repository, digest))))
+            .send()
+            .await?;
+        Ok(response.status().is_success())
+    }
+
+    /// Upload a blob, unless the registry already has it
+    async fn push_blob(&self, digest: &str, size: u64, body: reqwest::Body) -> Result<()> {
+        if self.blob_exists(digest).await? {
+            trace!("Blob {} already exists in {}", digest, self.repository);
+            return Ok(())
+        }
+
+        let response = self.authorized(self.client.post(self.url(&format!("/v2/{}/blobs/uploads/", self.repository))))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Starting upload of {} to {}", digest, self.repository))?;
+
+        let location = response.headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| anyhow!("Registry sent no upload location for {}", digest))?;
+
+        // The location might be relative to the registry and might already have a query
+        let mut upload_url = if location.starts_with('/') {
+            self.url(location)
+        } else {
+            location.to_string()
+        };
+        upload_url.push(if upload_url.contains('?') { '&' } else { '?' });
+        upload_url.push_str(&format!("digest={digest}"));
+
+        self.authorized(self.client.put(upload_url))
+            .header(reqwest::header::CONTENT_TYPE, FILE_MEDIA_TYPE)
+            .header(reqwest::header::CONTENT_LENGTH, size)
+            .body(body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Uploading {} to {}", digest, self.repository))?;
+        Ok(())
+    }
+
+    /// Push a file as blob
+    pub async fn push_file(&self, path: &Path) -> Result<Descriptor> {
+        let (digest, size) = crate::util::sha256_of_file(path).await?;
+        let title = path.file_name()
+            .and_then(|name| name.to_str())
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Not a valid file name: {}", path.display()))?;
+
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| anyhow!("Opening {}", path.display()))?;
+        self.push_blob(&digest, size, reqwest::Body::wrap_stream(crate::util::file_stream(file))).await?;
+
+        Ok(Descriptor {
+            media_type: FILE_MEDIA_TYPE.to_string(),
+            digest,
+            size,
+            title: Some(title),
+        })
+    }
+
+    /// Whether the tag exists in the repository
+    pub async fn tag_exists(&self, tag: &str) -> Result<bool> {
+        let response = self.authorized(self.client.head(self.url(&format!("/v2/{}/manifests/{}", self.repository, tag))))
+            .header(reqwest::header::ACCEPT, MANIFEST_MEDIA_TYPE)
+            .send()
+            .await?;
+        Ok(response.status().is_success())
+    }
+
+    /// Push a manifest with the files as layers and tag it, returns the digest of the manifest
+    pub async fn push_manifest(&self, tag: &str, files: &[Descriptor], annotations: &HashMap<String, String>) -> Result<String> {
+        let config = Descriptor {
+            media_type: EMPTY_CONFIG_MEDIA_TYPE.to_string(),
+            digest: sha256_digest(EMPTY_CONFIG),
+            size: EMPTY_CONFIG.len() as u64,
+            title: None,
+        };
+        self.push_blob(&config.digest, config.size, reqwest::Body::from(EMPTY_CONFIG)).await?;
+
+        let manifest = manifest(self.target.artifact_type(), &config, files, annotations).to_string();
+        let digest = sha256_digest(manifest.as_bytes());
+        self.authorized(self.client.put(self.url(&format!("/v2/{}/manifests/{}", self.repository, tag))))
+            .header(reqwest::header::CONTENT_TYPE, HeaderValue::from_static(MANIFEST_MEDIA_TYPE))
+            .body(manifest)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Pushing manifest for {}", self.reference(tag)))?;
+        Ok(digest)
+    }
+}
+
+fn manifest(artifact_type: &str, config: &Descriptor, files: &[Descriptor], annotations: &HashMap<String, String>) -> serde_json::Value {
+    serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "artifactType": artifact_type,
+        "config": config.to_json(),
+        "layers": files.iter().map(Descriptor::to_json).collect::<Vec<_>>(),
+        "annotations": annotations,
+    })
+}
+
+/// Parse a `WWW-Authenticate` header into the scheme and its parameters
+fn parse_challenge(header: &str) -> Option<(String, HashMap<String, String>)> {
+    let (scheme, rest) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if scheme.is_empty() {
+        return None
+    }
+
+    let mut params = HashMap::new();
+    let mut rest = rest.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        params.insert(key.trim().to_lowercase(), value.to_string());
+        rest = after.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+
+    Some((scheme.to_string(), params))
+}
+
+/// The name of a repository below `prefix` for a package
+///
+/// Repository names may only contain lowercase letters, digits and separators.
+pub fn repository_name(prefix: &str, package: &str) -> String {
+    let name = package
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect::<String>();
+
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// The tag for a version, characters that are not allowed in tags are replaced with "_"
+pub fn tag_name(version: &str) -> String {
+    version
+        .chars()
+        .take(128)
+        .enumerate()
+        .map(|(i, c)| {
+            if c.is_ascii_alphanumeric() || c == '_' || (i > 0 && (c == '.' || c == '-')) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn sha256_digest(bytes: &[u8]) -> String {
+    use sha2::Digest;
+    format!("sha256:{:x}", sha2::Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_challenge() {
+        let (scheme, params) = parse_challenge(r#"Bearer realm="https://auth.example.com/token",service="registry.example.com",scope="repository:foo:pull""#).unwrap();
+        assert_eq!(scheme, "Bearer");
+        assert_eq!(params["realm"], "https://auth.example.com/token");
+        assert_eq!(params["service"], "registry.example.com");
+        assert_eq!(params["scope"], "repository:foo:pull");
+
+        let (scheme, params) = parse_challenge(r#"Basic realm="Registry""#).unwrap();
+        assert_eq!(scheme, "Basic");
+        assert_eq!(params["realm"], "Registry");
+
+        assert!(parse_challenge("").is_none());
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(repository_name("butido/packages/", "libFoo++"), "butido/packages/libfoo__");
+        assert_eq!(repository_name("", "foo"), "foo");
+        assert_eq!(tag_name("1.0.2-rc1+build.3"), "1.0.2-rc1_build.3");
+        assert_eq!(tag_name(".1"), "_1");
+    }
+
+    #[test]
+    fn test_manifest() {
+        let config = Descriptor {
+            media_type: EMPTY_CONFIG_MEDIA_TYPE.to_string(),
+            digest: sha256_digest(EMPTY_CONFIG),
+            size: 2,
+            title: None,
+        };
+        let file = Descriptor {
+            media_type: FILE_MEDIA_TYPE.to_string(),
+            digest: String::from("sha256:abc"),
+            size: 42,
+            title: Some(String::from("foo-1.0.rpm")),
+        };
+
+        let manifest = manifest("application/vnd.butido.package.v1", &config, &[file], &HashMap::new());
+        assert_eq!(manifest["config"]["digest"], "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a");
+        assert_eq!(manifest["artifactType"], "application/vnd.butido.package.v1");
+        assert_eq!(manifest["layers"][0]["size"], 42);
+        assert_eq!(manifest["layers"][0]["annotations"][TITLE_ANNOTATION], "foo-1.0.rpm");
+        assert!(manifest["config"].get("annotations").is_none());
+    }
+}
@@ -11,6 +11,9 @@
 use indicatif::*;
 use getset::CopyGetters;
 
+/// The template of the bar that shows the progress of all jobs
+const OVERALL_BAR_TEMPLATE: &str = "[{elapsed_precise}] {bar:40.green/blue} {pos}/{len} jobs finished, ETA: {eta} | {msg}";
+
 #[derive(Clone, Debug, CopyGetters)]
 pub struct ProgressBars {
     bar_template: String,
@@ -27,6 +30,14 @@ impl ProgressBars {
         }
     }
 
+    /// Get a ProgressBars object that only creates hidden progress bars
+    pub fn hidden(self) -> Self {
+        ProgressBars {
+            hide: true,
+            ..self
+        }
+    }
+
     pub fn bar(&self) -> anyhow::Result<ProgressBar> {
         if self.hide {
             Ok(ProgressBar::hidden())
@@ -36,4 +47,128 @@ impl ProgressBars {
             Ok(b)
         }
     }
+
+    /// Get the bar that shows how many of `len` jobs are finished
+    ///
+    /// This bar uses a fixed template, the configured template is made for the job bars.
+    pub fn overall_bar(&self, len: u64) -> anyhow::Result<ProgressBar> {
+        if self.hide {
+            Ok(ProgressBar::hidden())
+        } else {
+            let b = ProgressBar::new(len);
+            b.set_style(ProgressStyle::default_bar().template(OVERALL_BAR_TEMPLATE)?);
+            Ok(b)
+        }
+    }
+}
+
+/// The phases of a job, for showing the progress of the job in its progress bar
+///
+/// Displays as `[==>..] 3/6`: one marker per phase, `=` for finished phases and `>` for the
+/// current one.
+#[derive(Clone, Debug)]
+pub struct PhaseMarkers {
+    phases: Vec<String>,
+    current: Option<usize>,
+    finished: bool,
+}
+
+impl PhaseMarkers {
+    pub fn new(phases: Vec<String>) -> Self {
+        PhaseMarkers {
+            phases,
+            current: None,
+            finished: false,
+        }
+    }
+
+    /// Set the current phase
+    ///
+    /// Phases that are not known are ignored, the markers stay on the last known phase.
+    pub fn set_current(&mut self, phase: &str) {
+        if let Some(idx) = self.phases.iter().position(|p| p == phase) {
+            self.current = Some(idx);
+        }
+    }
+
+    /// Mark all phases as finished
+    pub fn set_finished(&mut self) {
+        self.finished = true;
+    }
+
+    /// The percentage of the phases that are finished
+    pub fn percent(&self) -> u64 {
+        if self.phases.is_empty() {
+            return 0
+        }
+
+        (self.finished_phases() * 100 / self.phases.len()) as u64
+    }
+
+    fn finished_phases(&self) -> usize {
+        if self.finished {
+            self.phases.len()
+        } else {
+            self.current.unwrap_or(0)
+        }
+    }
+}
+
+impl std::fmt::Display for PhaseMarkers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.phases.is_empty() {
+            return Ok(())
+        }
+
+        let finished = self.finished_phases();
+        let current = if self.finished { None } else { self.current };
+
+        write!(f, "[")?;
+        for idx in 0..self.phases.len() {
+            let marker = if Some(idx) == current {
+                '>'
+            } else if idx < finished {
+                '='
+            } else {
+                '.'
+            };
+            write!(f, "{marker}")?;
+        }
+        write!(f, "] {}/{}", current.map(|c| c + 1).unwrap_or(finished), self.phases.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers() -> PhaseMarkers {
+        PhaseMarkers::new(["unpack", "configure", "build", "install"].iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn test_phase_markers() {
+        let mut m = markers();
+        assert_eq!(m.to_string(), "[....] 0/4");
+        assert_eq!(m.percent(), 0);
+
+        m.set_current("configure");
+        assert_eq!(m.to_string(), "[=>..] 2/4");
+        assert_eq!(m.percent(), 25);
+
+        m.set_current("not-a-phase");
+        assert_eq!(m.to_string(), "[=>..] 2/4");
+
+        m.set_finished();
+        assert_eq!(m.to_string(), "[====] 4/4");
+        assert_eq!(m.percent(), 100);
+    }
+
+    #[test]
+    fn test_phase_markers_without_phases() {
+        let mut m = PhaseMarkers::new(vec![]);
+        m.set_current("build");
+        assert_eq!(m.to_string(), "");
+        assert_eq!(m.percent(), 0);
+    }
 }